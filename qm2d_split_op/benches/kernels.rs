@@ -0,0 +1,14 @@
+use qm2d_split_op::bench::*;
+
+/* cargo bench [filter] runs the benchmarks whose names contain filter,
+or all of them, and fails if the fft has become less accurate. The
+--bench argument cargo adds is skipped. */
+fn main() {
+    let filter: Option<String> = std::env::args().skip(1)
+        .find(|arg| !arg.starts_with("--"));
+    let accuracies: Vec<FftAccuracy> = run_fft_accuracy(filter.as_deref());
+    print!("{}", report(&run_benchmarks(1.0, filter.as_deref()), &accuracies));
+    if !accuracies.iter().all(FftAccuracy::within_limit) {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,122 @@
+/* Records the git commit the program is built from as QM2D_GIT_HASH,
+for the run manifest (src/manifest.rs), and writes the C header of the
+functions in src/capi.rs to include/qm2d.h. */
+fn git(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    return Some(String::from_utf8_lossy(&output.stdout).trim().to_string());
+}
+
+/* The C type of a type in the signature of a function of src/capi.rs. */
+fn c_type(rust: &str) -> &'static str {
+    return match rust.trim() {
+        "" => "void",
+        "usize" => "size_t",
+        "i32" => "int",
+        "f32" => "float",
+        "f64" => "double",
+        "*mut Qm2d" => "qm2d *",
+        "*mut Complex<f32>" => "qm2d_complex *",
+        "*const Complex<f32>" => "const qm2d_complex *",
+        "*const std::ffi::c_char" => "const char *",
+        other => panic!("src/capi.rs: no C type for {}", other),
+    };
+}
+
+/* The declaration of a function from its Rust signature, from "fn" to
+the opening brace. */
+fn c_declaration(signature: &str) -> String {
+    let signature: &str = signature.trim_end_matches('{').trim();
+    let (name, rest) = signature.trim_start_matches("fn ")
+        .split_once('(').expect("a parameter list");
+    let (parameters, result) = rest.rsplit_once(')').expect("a ')'");
+    let parameters: Vec<String> = parameters.split(',')
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| {
+            let (name, rust) = p.split_once(':').expect("a parameter type");
+            let c: &str = c_type(rust);
+            let space: &str = if c.ends_with('*') {""} else {" "};
+            format!("{}{}{}", c, space, name.trim())
+        }).collect();
+    let c: &str = c_type(result.trim().trim_start_matches("->"));
+    let space: &str = if c.ends_with('*') {""} else {" "};
+    return format!("{}{}{}({});", c, space, name.trim(),
+                   if parameters.is_empty() {"void".to_string()}
+                   else {parameters.join(", ")});
+}
+
+/* The header for src/capi.rs, with each function declared after its
+comment. */
+fn c_header(source: &str) -> String {
+    let mut header: String = String::from(
+        "/* The C interface of qm2d_split_op, written by build.rs from\n\
+         src/capi.rs, which describes it. Link against the library built\n\
+         by cargo build --release --lib. */\n\
+         #ifndef QM2D_H\n#define QM2D_H\n\n\
+         #include <stddef.h>\n\n\
+         #ifdef __cplusplus\nextern \"C\" {\n#endif\n\n\
+         typedef struct qm2d qm2d;\n\n\
+         /* A complex value, laid out as float complex. */\n\
+         typedef struct {\n    float real;\n    float imag;\n} qm2d_complex;\n\n");
+    let mut comment: Option<String> = None;
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        if line.starts_with("/*") {
+            let mut text: String = line.to_string();
+            let mut last: &str = line;
+            while !last.ends_with("*/") {
+                last = lines.next().expect("a closed comment");
+                text += "\n";
+                text += last;
+            }
+            comment = Some(text);
+        } else if line.starts_with("pub") && line.contains("extern \"C\" fn") {
+            let mut signature: String = line[line.find("fn ").unwrap()..]
+                .to_string();
+            while !signature.ends_with('{') {
+                signature += " ";
+                signature += lines.next().expect("a function body").trim();
+            }
+            if let Some(comment) = comment.take() {
+                header += &comment;
+                header += "\n";
+            }
+            header += &c_declaration(&signature);
+            header += "\n\n";
+        } else if !line.is_empty() && !line.starts_with("#[")
+            && !line.starts_with(' ') {
+            comment = None;
+        }
+    }
+    header += "#ifdef __cplusplus\n}\n#endif\n\n#endif\n";
+    return header;
+}
+
+fn main() {
+    if let Some(hash) = git(&["rev-parse", "HEAD"]) {
+        let dirty: bool = git(&["status", "--porcelain", "--untracked-files=no"])
+            .is_some_and(|s| !s.is_empty());
+        println!("cargo:rustc-env=QM2D_GIT_HASH={}{}", hash,
+                 if dirty {"-dirty"} else {""});
+    }
+    if let Some(dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", dir);
+        println!("cargo:rerun-if-changed={}/index", dir);
+        if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", dir, branch);
+        }
+    }
+    // The header is only written when it changes, and not at all where
+    // the sources are read only, as when built as a dependency.
+    if let Ok(source) = std::fs::read_to_string("src/capi.rs") {
+        let header: String = c_header(&source);
+        if std::fs::read_to_string("include/qm2d.h").ok() != Some(header.clone()) {
+            let _ = std::fs::create_dir_all("include")
+                .and_then(|_| std::fs::write("include/qm2d.h", &header));
+        }
+    }
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,91 @@
+/* The C interface of qm2d_split_op, written by build.rs from
+src/capi.rs, which describes it. Link against the library built
+by cargo build --release --lib. */
+#ifndef QM2D_H
+#define QM2D_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct qm2d qm2d;
+
+/* A complex value, laid out as float complex. */
+typedef struct {
+    float real;
+    float imag;
+} qm2d_complex;
+
+/* The side length of the grid. */
+size_t qm2d_size(void);
+
+/* A new simulation with psi and the potential zero, stepped in double
+precision if double_precision is not 0 (see StepPrecision). Free it
+with qm2d_destroy. */
+qm2d *qm2d_create(int double_precision);
+
+/* Free a simulation from qm2d_create. The arrays from qm2d_psi and
+qm2d_potential are freed with it. */
+void qm2d_destroy(qm2d *simulation);
+
+/* Apply settings given as the lines of a configuration file, as a
+NUL-terminated UTF-8 string. The keys are V, as in the configuration,
+precision (single or double), dt and dt_imag for the time step, and
+packet_x0, packet_y0, packet_sigma, packet_nx and packet_ny, any of
+which start psi again from a wave packet as qm2d_set_wave_packet, with
+the others taking the values of the default wave packet. Nothing is
+changed if a value is not valid or a key is not known. */
+int qm2d_configure(qm2d *s, const char *text);
+
+/* Why the last call that returned 1 failed, as a NUL-terminated
+string owned by the simulation, valid until the next failure. */
+const char *qm2d_error(qm2d *s);
+
+/* The address of psi, N*N complex values with rows of constant y. */
+qm2d_complex *qm2d_psi(qm2d *s);
+
+/* Copy psi into values, which has room for N*N complex values. */
+void qm2d_get_psi(qm2d *s, qm2d_complex *values);
+
+/* Set psi to the N*N complex values in values. */
+void qm2d_set_psi(qm2d *s, const qm2d_complex *values);
+
+/* The address of the potential, of which only the real parts are used.
+Call qm2d_potential_changed after writing to it. */
+qm2d_complex *qm2d_potential(qm2d *s);
+
+/* Tell the simulation the potential was written to, which also drops
+an expression given by qm2d_set_potential. */
+void qm2d_potential_changed(qm2d *s);
+
+/* Set the potential to an expression of x, y and t, as for the V key of
+the configuration, given as a NUL-terminated UTF-8 string. The
+potential is left as it was if it does not parse. */
+int qm2d_set_potential(qm2d *s, const char *expression);
+
+/* Set psi to a Gaussian wave packet centred at (x0, y0), with standard
+deviation sigma and wavenumbers nx and ny, as qm2d_init of the
+WebAssembly module, and the time back to 0. */
+void qm2d_set_wave_packet(qm2d *s, float x0, float y0, float sigma, float nx, float ny);
+
+/* Set the time step, complex for imaginary time propagation. */
+void qm2d_set_time_step(qm2d *s, float real, float imag);
+
+/* The time the simulation has been stepped to. */
+float qm2d_time(qm2d *s);
+
+/* Advance psi by the given number of steps, evaluating a time
+dependent potential expression again before each, and return the norm
+of psi after the last. */
+double qm2d_step(qm2d *s, size_t steps);
+
+/* The norm of psi, the sum of its squared magnitudes. */
+double qm2d_norm(qm2d *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,89 @@
+/* The interface of qm2d_split_op plugins, shared libraries loaded at
+startup from the plugin directory (plugin_dir in the configuration,
+default plugins/), described in src/plugin.rs. A plugin defines
+qm2d_plugin, returning a description that lives as long as the
+program, for example
+
+    #include "qm2d_plugin.h"
+    #include <math.h>
+
+    static void ring(size_t n, float t, qm2d_complex *potential) {
+        for (size_t i = 0; i < n; i++)
+            for (size_t j = 0; j < n; j++) {
+                float x = (float)j/n - 0.5f, y = (float)i/n - 0.5f;
+                potential[i*n + j].real += 0.5f*(fabsf(hypotf(x, y) - 0.3f) < 0.01f);
+            }
+    }
+
+    static const char *names[] = {"inside"};
+
+    static void inside(size_t n, const qm2d_complex *psi,
+                       const qm2d_complex *potential, double *values) {
+        double sum = 0.0;
+        for (size_t i = 0; i < n; i++)
+            for (size_t j = 0; j < n; j++) {
+                float x = (float)j/n - 0.5f, y = (float)i/n - 0.5f;
+                qm2d_complex p = psi[i*n + j];
+                if (hypotf(x, y) < 0.3f)
+                    sum += p.real*p.real + p.imag*p.imag;
+            }
+        values[0] = sum;
+    }
+
+    static const qm2d_plugin_description description = {
+        QM2D_PLUGIN_ABI, "ring", ring, 0, 1, names, inside, NULL};
+
+    const qm2d_plugin_description *qm2d_plugin(void) { return &description; }
+
+built with cc -shared -fPIC -O2 ring.c -o plugins/libring.so. */
+#ifndef QM2D_PLUGIN_H
+#define QM2D_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define QM2D_PLUGIN_ABI 1
+
+#ifndef QM2D_H
+/* A complex value, laid out as float complex. */
+typedef struct {
+    float real;
+    float imag;
+} qm2d_complex;
+#endif
+
+/* What a plugin adds. psi and the potential are n*n values in rows of
+constant y, with x = j/n and y = i/n at index i*n + j. Any of the
+functions may be NULL. */
+typedef struct {
+    /* QM2D_PLUGIN_ABI, for the layout the plugin was built with. */
+    uint32_t abi_version;
+    /* For messages, and the prefix of the observable names. */
+    const char *name;
+    /* Add a term to the real parts of the potential at time t. */
+    void (*potential)(size_t n, float t, qm2d_complex *potential);
+    /* Not 0 if the potential changes with time, for it to be added again
+    before every step. */
+    int time_dependent;
+    /* The number of observables, and their names. */
+    size_t observable_count;
+    const char *const *observable_names;
+    /* Write observable_count values to values. */
+    void (*observables)(size_t n, const qm2d_complex *psi,
+                        const qm2d_complex *potential, double *values);
+    /* Change psi after a step of dt_real + i dt_imag ending at time t. */
+    void (*step)(size_t n, qm2d_complex *psi, const qm2d_complex *potential,
+                 float dt_real, float dt_imag, float t);
+} qm2d_plugin_description;
+
+const qm2d_plugin_description *qm2d_plugin(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
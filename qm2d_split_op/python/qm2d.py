@@ -0,0 +1,141 @@
+"""The qm2d_split_op solver from Python, through the C functions of
+src/capi.rs loaded with ctypes. Build the library first with
+
+    cargo build --release --lib
+
+and put this directory on the Python path, or set QM2D_LIBRARY to the
+path of the library if it is somewhere else. psi and the potential are
+numpy arrays that share memory with the solver, so reading and writing
+them copies nothing; they stay valid until the simulation is closed.
+
+    import qm2d
+    sim = (qm2d.Simulation(precision="double")
+           .wave_packet(x0=0.5, y0=0.2, sigma=0.07, ny=60.0)
+           .potential("0.5*exp(-((x-0.5)^2 + (y-0.6)^2)/0.002)")
+           .time_step(0.5))
+    for _ in range(100):
+        norm = sim.step(10)
+    density = abs(sim.psi)**2
+"""
+
+import ctypes
+import os
+import sys
+
+import numpy
+
+
+def _library_path():
+    if "QM2D_LIBRARY" in os.environ:
+        return os.environ["QM2D_LIBRARY"]
+    name = {"win32": "qm2d_split_op.dll",
+            "darwin": "libqm2d_split_op.dylib"}.get(
+                sys.platform, "libqm2d_split_op.so")
+    here = os.path.dirname(os.path.abspath(__file__))
+    return os.path.join(here, "..", "target", "release", name)
+
+
+_lib = ctypes.CDLL(_library_path())
+_handle = ctypes.c_void_p
+_values = ctypes.POINTER(ctypes.c_float)
+for _name, _result, _arguments in [
+        ("qm2d_size", ctypes.c_size_t, []),
+        ("qm2d_create", _handle, [ctypes.c_int32]),
+        ("qm2d_destroy", None, [_handle]),
+        ("qm2d_configure", ctypes.c_int32, [_handle, ctypes.c_char_p]),
+        ("qm2d_error", ctypes.c_char_p, [_handle]),
+        ("qm2d_psi", _values, [_handle]),
+        ("qm2d_potential", _values, [_handle]),
+        ("qm2d_potential_changed", None, [_handle]),
+        ("qm2d_set_potential", ctypes.c_int32, [_handle, ctypes.c_char_p]),
+        ("qm2d_set_wave_packet", None, [_handle] + [ctypes.c_float]*5),
+        ("qm2d_set_time_step", None, [_handle, ctypes.c_float,
+                                      ctypes.c_float]),
+        ("qm2d_time", ctypes.c_float, [_handle]),
+        ("qm2d_step", ctypes.c_double, [_handle, ctypes.c_size_t]),
+        ("qm2d_norm", ctypes.c_double, [_handle])]:
+    _function = getattr(_lib, _name)
+    _function.restype = _result
+    _function.argtypes = _arguments
+
+N = _lib.qm2d_size()
+
+
+def _view(values):
+    """The N by N complex64 array at the address values."""
+    return numpy.ctypeslib.as_array(values, shape=(N, 2*N)).view(
+        numpy.complex64)
+
+
+class Simulation:
+    """A simulation on the N by N grid, with x and y in [0, 1] along the
+    columns and rows. The methods that set it up return the simulation,
+    so that they can be chained."""
+
+    def __init__(self, precision="single"):
+        if precision not in ("single", "double"):
+            raise ValueError("unknown precision '%s', expected single or "
+                             "double" % precision)
+        self._handle = _lib.qm2d_create(int(precision == "double"))
+        self.psi = _view(_lib.qm2d_psi(self._handle))
+        self._potential = _view(_lib.qm2d_potential(self._handle))
+
+    def close(self):
+        """Free the simulation, after which psi must not be used."""
+        if self._handle is not None:
+            _lib.qm2d_destroy(self._handle)
+            self._handle = None
+
+    def __del__(self):
+        self.close()
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exception):
+        self.close()
+
+    def configure(self, text):
+        """Apply settings given as lines of key = value, with the keys
+        of qm2d_configure in src/capi.rs."""
+        if _lib.qm2d_configure(self._handle, text.encode()) != 0:
+            raise ValueError(_lib.qm2d_error(self._handle).decode())
+        return self
+
+    def wave_packet(self, x0=0.5, y0=0.2, sigma=0.07, nx=0.0, ny=60.0):
+        """Start from a Gaussian wave packet centred at (x0, y0) with
+        wavenumbers nx and ny, at time 0."""
+        _lib.qm2d_set_wave_packet(self._handle, x0, y0, sigma, nx, ny)
+        return self
+
+    def potential(self, V):
+        """Set the potential to an expression of x, y and t, as for the V
+        key of the configuration, or to an N by N array."""
+        if isinstance(V, str):
+            if _lib.qm2d_set_potential(self._handle, V.encode()) != 0:
+                raise ValueError(_lib.qm2d_error(self._handle).decode())
+        else:
+            self._potential[:] = numpy.asarray(V, dtype=numpy.float32)
+            _lib.qm2d_potential_changed(self._handle)
+        return self
+
+    def time_step(self, real, imag=0.0):
+        """Set the time step, complex for imaginary time."""
+        _lib.qm2d_set_time_step(self._handle, real, imag)
+        return self
+
+    def step(self, steps=1):
+        """Advance by the given number of steps, returning the norm."""
+        return _lib.qm2d_step(self._handle, steps)
+
+    @property
+    def V(self):
+        """The potential, as a copy."""
+        return self._potential.real.copy()
+
+    @property
+    def time(self):
+        return _lib.qm2d_time(self._handle)
+
+    def norm(self):
+        return _lib.qm2d_norm(self._handle)
@@ -0,0 +1,179 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::eigenstates::*;
+use crate::lobpcg::*;
+use crate::norm::*;
+use crate::observables::*;
+use crate::split_op::*;
+use crate::stencil::*;
+
+/* How the weight lambda of the target potential goes from 0 to 1 as
+the ramp goes from s = 0 to s = 1. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RampShape {
+    Linear, // lambda = s
+    Smooth, // 3 s^2 - 2 s^3, with no slope at either end
+    Cosine, // (1 - cos(pi s))/2, likewise
+}
+
+impl RampShape {
+    pub fn parse(s: &str) -> Result<RampShape, String> {
+        return match s {
+            "linear" => Ok(RampShape::Linear),
+            "smooth" => Ok(RampShape::Smooth),
+            "cosine" => Ok(RampShape::Cosine),
+            _ => Err(format!("unknown ramp '{}', expected linear, smooth or \
+                              cosine", s)),
+        };
+    }
+
+    pub fn lambda(&self, s: f64) -> f64 {
+        let s: f64 = s.clamp(0.0, 1.0);
+        return match self {
+            RampShape::Linear => s,
+            RampShape::Smooth => s*s*(3.0 - 2.0*s),
+            RampShape::Cosine => 0.5*(1.0 - (std::f64::consts::PI*s).cos()),
+        };
+    }
+}
+
+pub struct AdiabaticSettings {
+    pub steps: usize, // of the ramp, each dt long
+    pub shape: RampShape,
+    pub check_every: usize, // steps between checks, 0 for the ends only
+    // How the ground and first excited states are found at each check:
+    // in imaginary time with these settings, starting from those at the
+    // check before, unless a coarse grid is given, on which they are
+    // found by LOBPCG as by stencil_eigenstates.
+    pub imaginary_time: ImaginaryTimeSettings,
+    pub grid: Option<usize>,
+    pub order: DifferenceOrder,
+    pub boundary: StencilBoundary,
+    pub eigen: LobpcgSettings,
+}
+
+impl Default for AdiabaticSettings {
+    fn default() -> AdiabaticSettings {
+        return AdiabaticSettings {
+            steps: 2000, shape: RampShape::Smooth, check_every: 100,
+            imaginary_time: ImaginaryTimeSettings::default(), grid: None,
+            order: DifferenceOrder::Fourth,
+            boundary: StencilBoundary::Dirichlet,
+            eigen: LobpcgSettings {count: 2, ..Default::default()}};
+    }
+}
+
+/* psi at a check during the ramp, against the ground state phi_0 of
+the Hamiltonian at that point. */
+pub struct AdiabaticCheck {
+    pub step: usize,
+    pub lambda: f64,
+    pub energy: f64, // <psi|H|psi>
+    pub ground_energy: f64, // of phi_0
+    pub gap: f64, // between phi_0 and the first excited state
+    pub overlap: f64, // |<phi_0|psi>|^2/<psi|psi>
+}
+
+/* Set the real part of out to (1 - lambda) v0 + lambda v1, and the
+imaginary (absorbing) part to that of v1. */
+pub fn mix_potentials(v0: &[Complex<f32>], v1: &[Complex<f32>], lambda: f64,
+                      out: &mut [Complex<f32>]) {
+    let lambda: f32 = lambda as f32;
+    for ((u, a), b) in out.iter_mut().zip(v0.iter()).zip(v1.iter()) {
+        *u = Complex {real: (1.0 - lambda)*a.real + lambda*b.real,
+                      imag: b.imag};
+    }
+}
+
+/* Turn the potential slowly from v0 into v1 while psi, which should
+start as the ground state of v0, is stepped in real time, so that psi
+ends close to the ground state of v1 by the adiabatic theorem. States
+that imaginary time propagation finds poorly, or that should carry the
+history of a preparation, such as the ground state of a double well
+split from that of a single one, or a state in a lattice grown out of a
+trap, are prepared this way as initial states for the run.
+
+The Hamiltonian at step k is H(lambda) = T + (1 - lambda) v0 + lambda v1
+at lambda of s = (k + 1/2)/steps, with the given ramp shape. psi
+follows the ground state phi_0 as long as the ramp is slow against the
+gap Delta between it and the first excited state, roughly while
+
+    |<phi_1|dH/ds|phi_0>| << T Delta^2
+
+for a ramp of duration T = steps dt; ramps with no slope at the ends
+leave less excitation behind than a linear one of the same duration.
+At every check_every-th step, and at the start and the end, the energy
+of psi, phi_0 and Delta of the Hamiltonian at that point are found, and
+the overlap of psi with phi_0 is recorded. Where the overlap falls, the
+ramp is too fast for the gap there. The states are followed from check
+to check in imaginary time, as berry_phase follows them around its
+loop, which needs few steps once the first check is done; LOBPCG on a
+coarse grid is faster, but only for states spread over many of its
+points.
+
+Reference:
+
+T. Albash, D. A. Lidar. Adiabatic quantum computation. Reviews of
+Modern Physics 90, 015002 (2018).
+*/
+pub fn adiabatic_ramp(psi: &mut [Complex<f32>], v0: &[Complex<f32>],
+                      v1: &[Complex<f32>], p_squared: &[f32],
+                      dt: Complex<f32>, settings: &AdiabaticSettings
+                      ) -> Result<Vec<AdiabaticCheck>, String> {
+    if dt.imag != 0.0 {
+        return Err(String::from("an adiabatic ramp needs a real time \
+                                 step"));
+    }
+    if settings.steps == 0 {
+        return Err(String::from("an adiabatic ramp needs at least one \
+                                 step"));
+    }
+    let eigen = LobpcgSettings {count: usize::max(2, settings.eigen.count),
+                                ..settings.eigen};
+    let mut mixed: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                            N*N];
+    let mut scratch: Vec<Complex<f32>> = mixed.clone();
+    let mut checks: Vec<AdiabaticCheck> = Vec::new();
+    let mut guesses: Vec<Vec<Complex<f32>>> = default_guesses(2);
+    let mut check = |step: usize, psi: &[Complex<f32>]
+                     | -> Result<AdiabaticCheck, String> {
+        let lambda: f64 = settings.shape.lambda(
+            (step as f64)/(settings.steps as f64));
+        let mut potential: Vec<Complex<f32>> = vec![
+            Complex {real: 0.0, imag: 0.0}; N*N];
+        mix_potentials(v0, v1, lambda, &mut potential);
+        let states: Vec<Eigenstate> = match settings.grid {
+            Some(size) => stencil_eigenstates(
+                &potential, size, settings.order, settings.boundary,
+                &eigen)?,
+            None => {
+                let found: Vec<Eigenstate> = imaginary_time_eigenstates(
+                    std::mem::take(&mut guesses), &potential, p_squared,
+                    &settings.imaginary_time);
+                guesses = found.iter().map(|e| e.psi.clone()).collect();
+                found
+            },
+        };
+        let energy: f64 = compute_expectations(psi, &potential, 0.0,
+                                               &mut scratch).total;
+        return Ok(AdiabaticCheck {
+            step, lambda, energy,
+            ground_energy: states[0].energy,
+            gap: states[1].energy - states[0].energy,
+            overlap: inner_product(&states[0].psi, psi).length_squared()
+                /norm_squared(psi)});
+    };
+    checks.push(check(0, psi)?);
+    let nonlinear = Nonlinear {square: 0.0};
+    for k in 0..settings.steps {
+        let s: f64 = ((k as f64) + 0.5)/(settings.steps as f64);
+        mix_potentials(v0, v1, settings.shape.lambda(s), &mut mixed);
+        step(psi, &mixed, p_squared, nonlinear, dt, true);
+        let done: usize = k + 1;
+        if done == settings.steps
+            || done.is_multiple_of(settings.check_every) {
+            checks.push(check(done, psi)?);
+        }
+    }
+    return Ok(checks);
+}
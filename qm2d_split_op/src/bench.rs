@@ -0,0 +1,283 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::field::*;
+use crate::norm::*;
+use crate::precision::*;
+use crate::random::*;
+use crate::split_op::*;
+
+/* Timings of the parts of a step, for `cargo bench` and the bench
+subcommand, with a description of the machine so that a report can be
+pasted into an issue as it is. Each kernel is run once to warm up and
+then repeatedly for at least the given time, and the median and fastest
+of these runs are reported, the median being less sensitive to other
+programs on the machine than the mean. The fft kernels are timed as a
+forward and an inverse transform together, which leaves the data as it
+was, so that it does not overflow over many runs. */
+pub struct Measurement {
+    pub name: String,
+    pub points: usize, // complex values processed by each run
+    pub runs: usize,
+    pub median: f64, // seconds
+    pub fastest: f64,
+}
+
+impl Measurement {
+    /* Time f, which processes points values at each call. */
+    pub fn new(name: &str, points: usize, min_time: f64,
+               mut f: impl FnMut()) -> Measurement {
+        f();
+        let mut times: Vec<f64> = Vec::new();
+        let start = std::time::Instant::now();
+        while times.len() < 3 || start.elapsed().as_secs_f64() < min_time {
+            let run = std::time::Instant::now();
+            f();
+            times.push(run.elapsed().as_secs_f64());
+        }
+        times.sort_by(f64::total_cmp);
+        return Measurement {name: name.to_string(), points, runs: times.len(),
+                            median: times[times.len()/2], fastest: times[0]};
+    }
+}
+
+/* Run the benchmarks whose names contain filter, or all of them, for
+at least min_time seconds each. */
+pub fn run_benchmarks(min_time: f64, filter: Option<&str>) -> Vec<Measurement> {
+    let selected = |name: &str| filter.is_none_or(|f| name.contains(f));
+    let mut measurements: Vec<Measurement> = Vec::new();
+    let mut psi: Vec<Complex<f32>> = (0..N*N).map(|k| {
+        let phase: f32 = 0.001*(k as f32);
+        Complex {real: f32::cos(phase), imag: f32::sin(phase)}
+    }).collect();
+    let potential: Vec<Complex<f32>> = (0..N*N).map(
+        |k| Complex {real: 0.1*((k % N) as f32)/(N as f32), imag: 0.0})
+        .collect();
+    let mut p_squared: Vec<f32> = vec![0.0; N*N];
+    init_momentum_squared(&mut p_squared);
+    let dt: Complex<f32> = Complex {real: 0.5, imag: 0.0};
+    let mut size: usize = 64;
+    while size <= 16*N {
+        let name: String = format!("fft {}", size);
+        if selected(&name) {
+            let mut row: Vec<Complex<f32>> = psi[..size].to_vec();
+            measurements.push(Measurement::new(
+                &name, 2*size, min_time, || {
+                    base_f32_fft_in_place(&mut row, size, false);
+                    base_f32_fft_in_place(&mut row, size, true);
+                }));
+        }
+        size *= 4;
+    }
+    for (name, use_mt) in [("fft2 serial", false), ("fft2 threaded", true)] {
+        if selected(name) {
+            measurements.push(Measurement::new(
+                name, 2*N*N, min_time, || {
+                    fft2_in_place(&mut psi, use_mt);
+                    ifft2_in_place(&mut psi, use_mt);
+                }));
+        }
+    }
+    let mut propagators = Propagators::default();
+    propagators.update(&potential, &p_squared, dt, true);
+    if selected("propagators") {
+        measurements.push(Measurement::new(
+            "propagators", N*N, min_time,
+            || propagators.update(&potential, &p_squared, dt, true)));
+    }
+    if selected("multiply") {
+        measurements.push(Measurement::new(
+            "multiply", N*N, min_time,
+            || multiply_in_place(&mut psi, &propagators.spatial)));
+    }
+    if selected("multiply planes") {
+        let mut planes: ComplexPlanes = ComplexPlanes::from_slice(&psi, N, N);
+        let factors: ComplexPlanes
+            = ComplexPlanes::from_slice(&propagators.spatial, N, N);
+        measurements.push(Measurement::new(
+            "multiply planes", N*N, min_time,
+            || planes.multiply(&factors)));
+    }
+    if selected("norm") {
+        measurements.push(Measurement::new(
+            "norm", N*N, min_time,
+            || {std::hint::black_box(norm_squared(&psi));}));
+    }
+    if selected("dampen") {
+        measurements.push(Measurement::new(
+            "dampen", N*N, min_time, || dampen(&mut psi, dt.real)));
+    }
+    for (name, precision) in [("step single", StepPrecision::Single),
+                              ("step double", StepPrecision::Double)] {
+        if selected(name) {
+            let mut stepper: Stepper = Stepper::new(precision);
+            measurements.push(Measurement::new(
+                name, N*N, min_time, || {
+                    stepper.step(&mut psi, &potential, &p_squared, dt,
+                                 false, true);
+                }));
+        }
+    }
+    return measurements;
+}
+
+/* The error of the fft of size points of random data against a direct
+DFT in doubles with twiddle factors computed exactly (up to rounding of
+the doubles), for the forward transform and for a forward and inverse
+transform in turn, which should give the data back. Errors are the
+largest difference of a real or imaginary part from the reference, in
+units in the last place of the largest part of the reference, the
+resolution of f32 at the scale of the result. A comparison against
+another FFT library such as rustfft would need it as a dependency, which
+this crate has none of; the direct DFT is slower but exact. */
+pub struct FftAccuracy {
+    pub size: usize,
+    pub forward_ulps: f64,
+    pub round_trip_ulps: f64,
+}
+
+impl FftAccuracy {
+    pub fn new(size: usize, seed: u64) -> FftAccuracy {
+        let mut random: Random = Random::new(seed);
+        let input: Vec<Complex<f32>> = (0..size).map(
+            |_| Complex {real: random.normal() as f32,
+                         imag: random.normal() as f32}).collect();
+        let mut output: Vec<Complex<f32>> = input.clone();
+        base_f32_fft_in_place(&mut output, size, false);
+        let forward_ulps: f64 = ulps_error(&output, &direct_dft(&input));
+        base_f32_fft_in_place(&mut output, size, true);
+        let exact: Vec<Complex<f64>> = input.iter().map(
+            |&z| -> Complex<f64> {z.into()}).collect();
+        return FftAccuracy {size, forward_ulps,
+                            round_trip_ulps: ulps_error(&output, &exact)};
+    }
+
+    /* Whether both errors are within log2(size) ulps, which a radix-2
+    transform, with as many passes over the data, stays within. */
+    pub fn within_limit(&self) -> bool {
+        let limit: f64 = (self.size as f64).log2();
+        return self.forward_ulps <= limit && self.round_trip_ulps <= limit;
+    }
+}
+
+/* The DFT with the sign convention of the forward transform,
+sum_j input[j]*exp(2*pi*i*j*k/n). */
+fn direct_dft(input: &[Complex<f32>]) -> Vec<Complex<f64>> {
+    let n: usize = input.len();
+    let twiddles: Vec<Complex<f64>> = (0..n).map(|m| {
+        let angle: f64 = 2.0*std::f64::consts::PI*(m as f64)/(n as f64);
+        Complex {real: f64::cos(angle), imag: f64::sin(angle)}
+    }).collect();
+    return (0..n).map(|k| {
+        let mut sum: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+        for (j, &z) in input.iter().enumerate() {
+            let z: Complex<f64> = z.into();
+            sum = sum + z*twiddles[(j*k) % n];
+        }
+        sum
+    }).collect();
+}
+
+fn ulps_error(values: &[Complex<f32>], reference: &[Complex<f64>]) -> f64 {
+    let mut largest: f64 = 0.0;
+    let mut error: f64 = 0.0;
+    for (z, r) in values.iter().zip(reference.iter()) {
+        largest = largest.max(r.real.abs()).max(r.imag.abs());
+        error = error.max((z.real as f64 - r.real).abs())
+            .max((z.imag as f64 - r.imag).abs());
+    }
+    let scale: f32 = largest as f32;
+    let ulp: f64 = (f32::from_bits(scale.to_bits() + 1) - scale) as f64;
+    return error/ulp;
+}
+
+/* The accuracy of the fft for sizes from 64 to 4096, if filter is None
+or is contained in "accuracy". */
+pub fn run_fft_accuracy(filter: Option<&str>) -> Vec<FftAccuracy> {
+    if filter.is_some_and(|f| !"accuracy".contains(f)) {
+        return Vec::new();
+    }
+    let mut accuracies: Vec<FftAccuracy> = Vec::new();
+    let mut size: usize = 64;
+    while size <= 4096 {
+        accuracies.push(FftAccuracy::new(size, size as u64));
+        size *= 4;
+    }
+    return accuracies;
+}
+
+/* The processor model, as given by /proc/cpuinfo, or "unknown". */
+fn cpu_model() -> String {
+    if let Ok(info) = std::fs::read_to_string("/proc/cpuinfo") {
+        for line in info.lines() {
+            if let Some((key, value)) = line.split_once(':') {
+                if key.trim() == "model name" {
+                    return value.trim().to_string();
+                }
+            }
+        }
+    }
+    return "unknown".to_string();
+}
+
+/* The SIMD instruction sets the kernels could use on this processor. */
+fn simd_features() -> String {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let mut features: Vec<&str> = Vec::new();
+        if is_x86_feature_detected!("avx") {
+            features.push("avx");
+        }
+        if is_x86_feature_detected!("avx2") {
+            features.push("avx2");
+        }
+        if is_x86_feature_detected!("fma") {
+            features.push("fma");
+        }
+        if !features.is_empty() {
+            return features.join(" ");
+        }
+    }
+    return "none".to_string();
+}
+
+/* A plain text report of the measurements, the accuracy of the fft
+and the machine. */
+pub fn report(measurements: &[Measurement], accuracies: &[FftAccuracy]
+              ) -> String {
+    let mut out: String = String::new();
+    out += &format!("qm2d_split_op {} ({})\n", env!("CARGO_PKG_VERSION"),
+                    option_env!("QM2D_GIT_HASH").unwrap_or("unknown"));
+    out += &format!("cpu: {}\n", cpu_model());
+    out += &format!("threads available: {}\n",
+                    std::thread::available_parallelism()
+                    .map(|n| n.get()).unwrap_or(1));
+    out += &format!("simd: {}\n", simd_features());
+    out += &format!("os: {} {}\n", std::env::consts::OS,
+                    std::env::consts::ARCH);
+    out += &format!("features: {}\n", if cfg!(feature = "threads")
+                    {"threads"} else {"none"});
+    out += &format!("N = {}, TH_COUNT = {}, {}\n\n", N, TH_COUNT,
+                    if cfg!(debug_assertions) {"debug build"}
+                    else {"release build"});
+    if !measurements.is_empty() {
+        out += &format!("{:<16} {:>8} {:>12} {:>12} {:>12}\n", "kernel",
+                        "runs", "median ms", "fastest ms", "Mpoints/s");
+    }
+    for m in measurements.iter() {
+        out += &format!("{:<16} {:>8} {:>12.4} {:>12.4} {:>12.1}\n",
+                        m.name, m.runs, 1e3*m.median, 1e3*m.fastest,
+                        (m.points as f64)/m.median/1e6);
+    }
+    if !accuracies.is_empty() {
+        out += &format!("\n{:<16} {:>16} {:>16}\n",
+                        "fft accuracy", "forward ulps", "round trip ulps");
+        for a in accuracies.iter() {
+            out += &format!("{:<16} {:>16.2} {:>16.2}{}\n",
+                            a.size, a.forward_ulps, a.round_trip_ulps,
+                            if a.within_limit() {""}
+                            else {"  above log2(size) ulps"});
+        }
+    }
+    return out;
+}
@@ -0,0 +1,68 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::potentials::*;
+use crate::norm::*;
+use crate::eigenstates::*;
+
+/* Eigenstates along a closed loop of potentials and the geometric
+phase accumulated around it. */
+pub struct BerryLoop {
+    pub energies: Vec<f64>, // energy of the state at each point
+    pub overlaps: Vec<Complex<f64>>, // <phi_k|phi_k+1>, the last closing the loop
+    pub phase: f64, // Berry phase, in (-pi, pi]
+}
+
+/* Berry phase of the level-th eigenstate (0 for the ground state)
+taken around a loop in parameter space, where potential gives V at the
+loop parameter s in [0, 1), as its time argument, so that the potential
+at s = 1 is the one at s = 0. The lowest level + 1 states are found at
+points evenly spaced values of s by imaginary time propagation, each
+starting from the states at the point before, which follows them
+adiabatically around the loop and needs few steps after the first
+point. The phase is then
+
+    gamma = -arg(<phi_0|phi_1><phi_1|phi_2>...<phi_n-1|phi_0>),
+
+which does not depend on the arbitrary phases of the individual
+states and converges to the Berry phase as the points get closer.
+For a real Hamiltonian, such as one with a scalar potential alone, the
+phase is 0 or pi, the latter when the loop encircles a degeneracy of
+the state with its neighbour. The ground state of such a Hamiltonian
+has no degeneracies, so it always gives 0.
+
+References:
+
+Wikipedia - Geometric phase
+https://en.wikipedia.org/wiki/Geometric_phase
+
+R. Resta.
+Manifestations of Berry's phase in molecules and condensed matter.
+Journal of Physics: Condensed Matter 12, R107 (2000).
+*/
+pub fn berry_phase(potential: &dyn TimeDependentPotential, level: usize,
+                   points: usize, p_squared: &[f32],
+                   settings: &ImaginaryTimeSettings) -> BerryLoop {
+    let points: usize = usize::max(points, 2);
+    let mut grid = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+    let mut states = std::vec::Vec::<Vec<Complex<f32>>>::new();
+    let mut energies = std::vec::Vec::<f64>::new();
+    let mut guesses: Vec<Vec<Complex<f32>>> = default_guesses(level + 1);
+    for k in 0..points {
+        potential.fill_at(grid.as_mut_slice(), (k as f32)/(points as f32));
+        let found: Vec<Eigenstate> = imaginary_time_eigenstates(
+            guesses, grid.as_slice(), p_squared, settings);
+        energies.push(found[level].energy);
+        states.push(found[level].psi.clone());
+        guesses = found.into_iter().map(|e| e.psi).collect();
+    }
+    let mut overlaps = std::vec::Vec::<Complex<f64>>::new();
+    let mut product: Complex<f64> = Complex {real: 1.0, imag: 0.0};
+    for k in 0..points {
+        let c: Complex<f64> = inner_product(&states[k],
+                                            &states[(k + 1) % points]);
+        overlaps.push(c);
+        product = product*c;
+    }
+    return BerryLoop {energies, overlaps,
+                      phase: -f64::atan2(product.imag, product.real)};
+}
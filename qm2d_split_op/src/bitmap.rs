@@ -1,23 +1,23 @@
 pub fn copy_i32(bytes: &mut [u8], val: i32, offset: &mut usize) {
     let val_arr: [u8; 4] = val.to_ne_bytes();
-    for i in 0..4 {
-        bytes[*offset] = val_arr[i];
+    for byte in val_arr {
+        bytes[*offset] = byte;
         *offset += 1;
     }
 }
 
 pub fn copy_u32(bytes: &mut [u8], val: u32, offset: &mut usize) {
     let val_arr: [u8; 4] = val.to_ne_bytes();
-    for i in 0..4 {
-        bytes[*offset] = val_arr[i];
+    for byte in val_arr {
+        bytes[*offset] = byte;
         *offset += 1;
     }
 }
 
 pub fn copy_u16(bytes: &mut [u8], val: u16, offset: &mut usize) {
     let val_arr: [u8; 2] = val.to_ne_bytes();
-    for i in 0..2 {
-        bytes[*offset] = val_arr[i];
+    for byte in val_arr {
+        bytes[*offset] = byte;
         *offset += 1;
     }
 }
@@ -78,6 +78,6 @@ pub fn make_bitmap_file(filename: std::string::String,
                     data: &mut [u8]) -> std::io::Result<()> {
     use std::io::Write;
     let mut file = std::fs::File::create(filename)?;
-    file.write_all(&data)?;
+    file.write_all(data)?;
     Ok(())
 }
\ No newline at end of file
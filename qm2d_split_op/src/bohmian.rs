@@ -0,0 +1,109 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::field::*;
+use crate::random::*;
+
+/* Draw count points distributed according to |psi|^2, with positions
+in [0, 1] as for the grid. Each point is placed uniformly within its
+grid cell. */
+pub fn sample_density(psi: &[Complex<f32>], count: usize,
+                      random: &mut Random) -> Vec<(f32, f32)> {
+    let mut cumulative = std::vec::Vec::<f64>::with_capacity(N*N);
+    let mut total: f64 = 0.0;
+    for z in psi.iter() {
+        total += z.length_squared() as f64;
+        cumulative.push(total);
+    }
+    let mut points = std::vec::Vec::<(f32, f32)>::with_capacity(count);
+    for _ in 0..count {
+        let u: f64 = random.uniform()*total;
+        let k: usize = usize::min(cumulative.partition_point(|&c| c <= u),
+                                  N*N - 1);
+        let x: f32 = ((k % N) as f32 + random.uniform() as f32 - 0.5)
+            /(N as f32);
+        let y: f32 = ((k / N) as f32 + random.uniform() as f32 - 0.5)
+            /(N as f32);
+        points.push((x.rem_euclid(1.0), y.rem_euclid(1.0)));
+    }
+    return points;
+}
+
+/* Bohmian trajectories: points that move with the velocity field
+v = j/|psi|^2 of the wave function. If they start distributed
+according to |psi|^2 they stay distributed according to it, so their
+paths show how probability flows, for example through the two slits
+and into the fringes of a double slit experiment.
+
+References:
+
+Wikipedia - De Broglie-Bohm theory
+https://en.wikipedia.org/wiki/De_Broglie%E2%80%93Bohm_theory
+
+C. Philippidis, C. Dewdney, B. J. Hiley.
+Quantum interference and the quantum potential.
+Il Nuovo Cimento B 52, 15 (1979).
+*/
+pub struct Trajectories {
+    pub positions: Vec<(f32, f32)>, // current positions, in [0, 1]
+    density: Vec<f32>,
+}
+
+impl Trajectories {
+    pub fn new(positions: Vec<(f32, f32)>) -> Trajectories {
+        return Trajectories {positions, density: vec![0.0; N*N]};
+    }
+
+    /* Velocity, in grid points per unit time, at the position (x, y)
+    in grid points. The current and density are interpolated separately
+    before dividing, and the velocity is zero where there is no
+    probability to move. */
+    fn velocity(&self, jx: &Field2D<f32>, jy: &Field2D<f32>,
+                x: f32, y: f32) -> (f32, f32) {
+        let rho: f32 = interpolate_periodic(&self.density, x, y);
+        if rho <= 1e-12 {
+            return (0.0, 0.0);
+        }
+        return (interpolate_periodic(jx.as_slice(), x, y)/rho,
+                interpolate_periodic(jy.as_slice(), x, y)/rho);
+    }
+
+    /* Move the points over a time dt through the velocity field of psi,
+    whose current is jx, jy (see current_density), using the midpoint
+    method with the field held fixed over the step. */
+    pub fn advance(&mut self, psi: &[Complex<f32>], jx: &Field2D<f32>,
+                   jy: &Field2D<f32>, dt: f32) {
+        for (d, z) in self.density.iter_mut().zip(psi.iter()) {
+            *d = z.length_squared();
+        }
+        let nf: f32 = N as f32;
+        for k in 0..self.positions.len() {
+            let (x, y): (f32, f32) = (self.positions[k].0*nf,
+                                      self.positions[k].1*nf);
+            let (vx, vy): (f32, f32) = self.velocity(jx, jy, x, y);
+            let (vx, vy): (f32, f32) = self.velocity(
+                jx, jy, x + 0.5*dt*vx, y + 0.5*dt*vy);
+            self.positions[k] = (((x + dt*vx)/nf).rem_euclid(1.0),
+                                 ((y + dt*vy)/nf).rem_euclid(1.0));
+        }
+    }
+
+    /* Column names x0 y0 x1 y1 ... for writing the positions as a
+    time series. */
+    pub fn names(&self) -> Vec<String> {
+        let mut names = std::vec::Vec::<String>::new();
+        for k in 0..self.positions.len() {
+            names.push(format!("x{}", k));
+            names.push(format!("y{}", k));
+        }
+        return names;
+    }
+
+    pub fn values(&self) -> Vec<f64> {
+        let mut values = std::vec::Vec::<f64>::new();
+        for &(x, y) in &self.positions {
+            values.push(x as f64);
+            values.push(y as f64);
+        }
+        return values;
+    }
+}
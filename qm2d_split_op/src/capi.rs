@@ -0,0 +1,308 @@
+// The safety requirements of the functions are those of the handle, given
+// once below rather than with each.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::constants::*;
+use crate::complex::*;
+use crate::config::*;
+use crate::expression::*;
+use crate::norm::*;
+use crate::potentials::*;
+use crate::precision::*;
+use crate::split_op::*;
+
+/* The simulation as plain C functions on a handle, for embedding the
+solver in programs in C, C++ or anything else that calls C, such as the
+Python module in python/qm2d.py, which loads the library built from this
+crate with ctypes, and for the server of server.rs, which uses the
+methods of Qm2d directly. build.rs writes the declarations of the functions,
+with these comments, to include/qm2d.h. psi and the potential are kept
+in arrays of N*N complex values of two floats each, the real part
+first, as C's float complex and numpy's complex64, whose addresses are
+handed out so that they can be read and written in place. The functions
+are left out of the WebAssembly module, which has functions of its own
+in wasm.rs.
+
+The functions taking a handle are unsafe: the handle must have come from
+qm2d_create and not have been passed to qm2d_destroy, and must not be
+used from two threads at once. Functions returning int return 0 on
+success and 1 on failure, after which qm2d_error gives the reason. */
+pub struct Qm2d {
+    psi: Vec<Complex<f32>>,
+    potential: Vec<Complex<f32>>,
+    p_squared: Vec<f32>,
+    stepper: Stepper,
+    expression: Option<Expression>,
+    potential_changed: bool,
+    dt: Complex<f32>,
+    t: f32,
+    error: std::ffi::CString,
+}
+
+impl Qm2d {
+    /* A simulation with psi and the potential zero. */
+    pub fn new(precision: StepPrecision) -> Qm2d {
+        let zero: Complex<f32> = Complex {real: 0.0, imag: 0.0};
+        let mut p_squared: Vec<f32> = vec![0.0; N*N];
+        init_momentum_squared(&mut p_squared);
+        return Qm2d {
+            psi: vec![zero; N*N], potential: vec![zero; N*N], p_squared,
+            stepper: Stepper::new(precision), expression: None,
+            potential_changed: true, dt: Complex {real: RE_DT, imag: IM_DT},
+            t: 0.0, error: std::ffi::CString::default()};
+    }
+
+    pub fn psi(&self) -> &[Complex<f32>] {
+        return &self.psi;
+    }
+
+    pub fn potential(&self) -> &[Complex<f32>] {
+        return &self.potential;
+    }
+
+    pub fn time(&self) -> f32 {
+        return self.t;
+    }
+
+    /* Advance psi as qm2d_step, returning the norm after the last
+    step. */
+    pub fn step(&mut self, steps: usize) -> f64 {
+        for _ in 0..steps {
+            if let Some(e) = self.expression.as_ref()
+                .filter(|e| e.is_time_dependent()) {
+                e.fill_at(&mut self.potential, self.t);
+                self.potential_changed = true;
+            }
+            self.stepper.step(&mut self.psi, &self.potential,
+                              &self.p_squared, self.dt,
+                              std::mem::take(&mut self.potential_changed),
+                              true);
+            self.t += self.dt.real;
+        }
+        return norm_squared(&self.psi);
+    }
+
+    fn set_expression(&mut self, source: &str) -> Result<(), String> {
+        let expression: Expression = Expression::parse(source)?;
+        expression.fill_at(&mut self.potential, self.t);
+        self.expression = Some(expression);
+        self.potential_changed = true;
+        return Ok(());
+    }
+
+    fn set_wave_packet(&mut self, x0: f32, y0: f32, sigma: f32,
+                       nx: f32, ny: f32) {
+        for i in 0..N {
+            for j in 0..N {
+                let x: f32 = (j as f32)/(N as f32);
+                let y: f32 = (i as f32)/(N as f32);
+                let r2: f32 = (x - x0)*(x - x0) + (y - y0)*(y - y0);
+                let a: f32 = f32::exp(-0.5*r2/(sigma*sigma));
+                let phase: f32 = 2.0*std::f32::consts::PI*(nx*x + ny*y);
+                self.psi[i*N + j] = Complex {real: a*f32::cos(phase),
+                                             imag: a*f32::sin(phase)};
+            }
+        }
+        self.t = 0.0;
+    }
+
+    /* Apply the settings of qm2d_configure. All values are read and
+    checked before any is applied. */
+    pub fn configure(&mut self, text: &str) -> Result<(), String> {
+        let config: Config = Config::parse(text)?;
+        let number = |key: &str, default: f32| -> Result<f32, String> {
+            return config.get_f32(key, default).map_err(|e| e.to_string());
+        };
+        let precision: Option<StepPrecision> = match config.get("precision") {
+            Some(p) => Some(StepPrecision::parse(p)?),
+            None => None,
+        };
+        let dt: Complex<f32> = Complex {
+            real: number("dt", self.dt.real)?,
+            imag: number("dt_imag", self.dt.imag)?};
+        let expression: Option<Expression> = match config.get("V") {
+            Some(v) => Some(Expression::parse(v)?),
+            None => None,
+        };
+        let packet: Option<[f32; 5]> = if config.entries.iter().any(
+            |(k, _)| k.starts_with("packet_")) {
+            Some([number("packet_x0", 0.5)?, number("packet_y0", 0.2)?,
+                  number("packet_sigma", 0.07)?, number("packet_nx", 0.0)?,
+                  number("packet_ny", 60.0)?])
+        } else {
+            None
+        };
+        let unused: Vec<String> = config.unused();
+        if !unused.is_empty() {
+            return Err(format!("unknown settings: {}", unused.join(", ")));
+        }
+        if let Some(precision) = precision {
+            self.stepper = Stepper::new(precision);
+        }
+        self.dt = dt;
+        if let Some([x0, y0, sigma, nx, ny]) = packet {
+            self.set_wave_packet(x0, y0, sigma, nx, ny);
+        }
+        if let Some(expression) = expression {
+            expression.fill_at(&mut self.potential, self.t);
+            self.expression = Some(expression);
+            self.potential_changed = true;
+        }
+        return Ok(());
+    }
+
+    /* 0 for Ok, or 1 for an error, which is kept for qm2d_error. */
+    fn status(&mut self, result: Result<(), String>) -> i32 {
+        return match result {
+            Ok(()) => 0,
+            Err(e) => {
+                self.error = std::ffi::CString::new(e.replace('\0', " "))
+                    .unwrap_or_default();
+                1
+            },
+        };
+    }
+}
+
+/* The side length of the grid. */
+#[no_mangle]
+pub extern "C" fn qm2d_size() -> usize {
+    return N;
+}
+
+/* A new simulation with psi and the potential zero, stepped in double
+precision if double_precision is not 0 (see StepPrecision). Free it
+with qm2d_destroy. */
+#[no_mangle]
+pub extern "C" fn qm2d_create(double_precision: i32) -> *mut Qm2d {
+    let precision: StepPrecision = if double_precision != 0
+        {StepPrecision::Double}
+        else {StepPrecision::Single};
+    return Box::into_raw(Box::new(Qm2d::new(precision)));
+}
+
+/* Free a simulation from qm2d_create. The arrays from qm2d_psi and
+qm2d_potential are freed with it. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_destroy(simulation: *mut Qm2d) {
+    if !simulation.is_null() {
+        drop(Box::from_raw(simulation));
+    }
+}
+
+unsafe fn simulation<'a>(simulation: *mut Qm2d) -> &'a mut Qm2d {
+    return &mut *simulation;
+}
+
+/* Apply settings given as the lines of a configuration file, as a
+NUL-terminated UTF-8 string. The keys are V, as in the configuration,
+precision (single or double), dt and dt_imag for the time step, and
+packet_x0, packet_y0, packet_sigma, packet_nx and packet_ny, any of
+which start psi again from a wave packet as qm2d_set_wave_packet, with
+the others taking the values of the default wave packet. Nothing is
+changed if a value is not valid or a key is not known. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_configure(s: *mut Qm2d,
+                                        text: *const std::ffi::c_char
+                                        ) -> i32 {
+    let s: &mut Qm2d = simulation(s);
+    let result: Result<(), String> = std::ffi::CStr::from_ptr(text).to_str()
+        .map_err(|e| e.to_string()).and_then(|text| s.configure(text));
+    return s.status(result);
+}
+
+/* Why the last call that returned 1 failed, as a NUL-terminated
+string owned by the simulation, valid until the next failure. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_error(s: *mut Qm2d
+                                    ) -> *const std::ffi::c_char {
+    return simulation(s).error.as_ptr();
+}
+
+/* The address of psi, N*N complex values with rows of constant y. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_psi(s: *mut Qm2d) -> *mut Complex<f32> {
+    return simulation(s).psi.as_mut_ptr();
+}
+
+/* Copy psi into values, which has room for N*N complex values. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_get_psi(s: *mut Qm2d,
+                                      values: *mut Complex<f32>) {
+    std::slice::from_raw_parts_mut(values, N*N)
+        .copy_from_slice(&simulation(s).psi);
+}
+
+/* Set psi to the N*N complex values in values. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_set_psi(s: *mut Qm2d,
+                                      values: *const Complex<f32>) {
+    simulation(s).psi.copy_from_slice(
+        std::slice::from_raw_parts(values, N*N));
+}
+
+/* The address of the potential, of which only the real parts are used.
+Call qm2d_potential_changed after writing to it. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_potential(s: *mut Qm2d
+                                        ) -> *mut Complex<f32> {
+    return simulation(s).potential.as_mut_ptr();
+}
+
+/* Tell the simulation the potential was written to, which also drops
+an expression given by qm2d_set_potential. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_potential_changed(s: *mut Qm2d) {
+    let s: &mut Qm2d = simulation(s);
+    s.expression = None;
+    s.potential_changed = true;
+}
+
+/* Set the potential to an expression of x, y and t, as for the V key of
+the configuration, given as a NUL-terminated UTF-8 string. The
+potential is left as it was if it does not parse. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_set_potential(
+    s: *mut Qm2d, expression: *const std::ffi::c_char) -> i32 {
+    let s: &mut Qm2d = simulation(s);
+    let result: Result<(), String> = std::ffi::CStr::from_ptr(expression)
+        .to_str().map_err(|e| e.to_string())
+        .and_then(|source| s.set_expression(source));
+    return s.status(result);
+}
+
+/* Set psi to a Gaussian wave packet centred at (x0, y0), with standard
+deviation sigma and wavenumbers nx and ny, as qm2d_init of the
+WebAssembly module, and the time back to 0. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_set_wave_packet(
+    s: *mut Qm2d, x0: f32, y0: f32, sigma: f32, nx: f32, ny: f32) {
+    simulation(s).set_wave_packet(x0, y0, sigma, nx, ny);
+}
+
+/* Set the time step, complex for imaginary time propagation. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_set_time_step(s: *mut Qm2d, real: f32,
+                                            imag: f32) {
+    simulation(s).dt = Complex {real, imag};
+}
+
+/* The time the simulation has been stepped to. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_time(s: *mut Qm2d) -> f32 {
+    return simulation(s).t;
+}
+
+/* Advance psi by the given number of steps, evaluating a time
+dependent potential expression again before each, and return the norm
+of psi after the last. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_step(s: *mut Qm2d, steps: usize) -> f64 {
+    return simulation(s).step(steps);
+}
+
+/* The norm of psi, the sum of its squared magnitudes. */
+#[no_mangle]
+pub unsafe extern "C" fn qm2d_norm(s: *mut Qm2d) -> f64 {
+    return norm_squared(&simulation(s).psi);
+}
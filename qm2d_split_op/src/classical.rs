@@ -0,0 +1,115 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::bohmian::*;
+use crate::field::*;
+use crate::random::*;
+
+/* Draw count momenta, in units of inverse grid spacing, distributed
+according to the momentum distribution of psi, which is found with an
+fft in scratch. */
+pub fn sample_momenta(psi: &[Complex<f32>], count: usize,
+                      random: &mut Random,
+                      scratch: &mut [Complex<f32>]) -> Vec<(f32, f32)> {
+    scratch.copy_from_slice(psi);
+    fft2_in_place(scratch, true);
+    // The points are fft indices over N, spread over their cells, which
+    // are turned back into signed frequencies and then momenta as in
+    // fft_momentum.
+    let to_momentum = |u: f32| -> f32 {
+        let f: f32 = (u*(N as f32) + (N/2) as f32).rem_euclid(N as f32)
+            - (N/2) as f32;
+        return -2.0*std::f32::consts::PI*f/(N as f32);
+    };
+    return sample_density(scratch, count, random).iter()
+        .map(|&(u, v)| (to_momentum(u), to_momentum(v))).collect();
+}
+
+/* Ensemble of classical point particles of unit mass moving in the
+real part of the potential, to compare with the quantum evolution of a
+wave packet. Started with positions sampled from |psi|^2 and momenta
+from its momentum distribution, the ensemble follows the spreading of
+the wave packet as well as the motion of its centre, and the two only
+part ways through interference, tunnelling or quantum corrections to
+the motion in anharmonic potentials (Ehrenfest's theorem gives the same
+motion for the means only while the force is close to linear over the
+wave packet). Positions are in [0, 1] and wrap around as for the
+wave function, and momenta are in units of inverse grid spacing.
+
+References:
+
+Wikipedia - Ehrenfest theorem
+https://en.wikipedia.org/wiki/Ehrenfest_theorem
+
+Wikipedia - Verlet integration
+https://en.wikipedia.org/wiki/Verlet_integration#Velocity_Verlet
+*/
+pub struct ClassicalEnsemble {
+    pub positions: Vec<(f32, f32)>,
+    pub momenta: Vec<(f32, f32)>,
+    force_x: Vec<f32>,
+    force_y: Vec<f32>,
+}
+
+impl ClassicalEnsemble {
+    pub fn new(positions: Vec<(f32, f32)>, momenta: Vec<(f32, f32)>,
+               potential: &[Complex<f32>]) -> ClassicalEnsemble {
+        let mut ensemble = ClassicalEnsemble {
+            positions, momenta,
+            force_x: vec![0.0; N*N], force_y: vec![0.0; N*N],
+        };
+        ensemble.set_potential(potential);
+        return ensemble;
+    }
+
+    /* Update the force -grad V, with central differences per grid
+    spacing, after the potential has changed. */
+    pub fn set_potential(&mut self, potential: &[Complex<f32>]) {
+        for i in 0..N {
+            for j in 0..N {
+                let left: f32 = potential[i*N + (j + N - 1) % N].real;
+                let right: f32 = potential[i*N + (j + 1) % N].real;
+                let down: f32 = potential[((i + N - 1) % N)*N + j].real;
+                let up: f32 = potential[((i + 1) % N)*N + j].real;
+                self.force_x[i*N + j] = -0.5*(right - left);
+                self.force_y[i*N + j] = -0.5*(up - down);
+            }
+        }
+    }
+
+    fn force(&self, x: f32, y: f32) -> (f32, f32) {
+        return (interpolate_periodic(&self.force_x, x, y),
+                interpolate_periodic(&self.force_y, x, y));
+    }
+
+    /* Advance all particles by dt with the velocity Verlet method. */
+    pub fn advance(&mut self, dt: f32) {
+        let nf: f32 = N as f32;
+        for k in 0..self.positions.len() {
+            let (mut x, mut y): (f32, f32) = (self.positions[k].0*nf,
+                                              self.positions[k].1*nf);
+            let (mut px, mut py): (f32, f32) = self.momenta[k];
+            let (fx, fy): (f32, f32) = self.force(x, y);
+            px += 0.5*dt*fx;
+            py += 0.5*dt*fy;
+            x += dt*px;
+            y += dt*py;
+            let (fx, fy): (f32, f32) = self.force(x, y);
+            px += 0.5*dt*fx;
+            py += 0.5*dt*fy;
+            self.positions[k] = ((x/nf).rem_euclid(1.0),
+                                 (y/nf).rem_euclid(1.0));
+            self.momenta[k] = (px, py);
+        }
+    }
+
+    /* Mean position of the ensemble, to compare with <x> and <y>. As
+    for the expectation values, these do not account for particles
+    wrapping around the boundaries. */
+    pub fn mean_position(&self) -> (f64, f64) {
+        let n: f64 = self.positions.len() as f64;
+        let (sx, sy): (f64, f64) = self.positions.iter().fold(
+            (0.0, 0.0), |s, p| (s.0 + p.0 as f64, s.1 + p.1 as f64));
+        return (sx/n, sy/n);
+    }
+}
@@ -1,5 +1,8 @@
 
+// Laid out as the real part followed by the imaginary part, so that
+// slices of them can be loaded into SIMD registers (see field.rs).
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct Complex<T> {
     pub real: T,
     pub imag: T,
@@ -17,27 +20,27 @@ impl <T: std::ops::Mul<Output=T> + Copy> Complex<T> {
     }
 }
 
-/* Into conversion from the size 64 (2 x f32) to size 128 (2 x f64) 
-bit complex struct. This follows closely to the example given in 
-the Rust documentation:
+/* From (and so Into) conversion from the size 64 (2 x f32) to size 128
+(2 x f64) bit complex struct. This follows closely to the example given
+in the Rust documentation:
 
 https://doc.rust-lang.org/rust-by-example/conversion/from_into.html
 */
-impl std::convert::Into<Complex<f64>> for Complex<f32> {
-    fn into(self) -> Complex<f64> {
-        return Complex{real: self.real as f64, imag: self.imag as f64};
+impl std::convert::From<Complex<f32>> for Complex<f64> {
+    fn from(z: Complex<f32>) -> Complex<f64> {
+        return Complex{real: z.real as f64, imag: z.imag as f64};
     }
 }
 
-/* Into conversion from the size 128 (2 x f64) to size 64 bit (2 x f32) 
-complex struct. This follows closely to the example given in 
-the Rust documentation:
+/* From (and so Into) conversion from the size 128 (2 x f64) to size 64
+bit (2 x f32) complex struct. This follows closely to the example given
+in the Rust documentation:
 
 https://doc.rust-lang.org/rust-by-example/conversion/from_into.html
 */
-impl std::convert::Into<Complex<f32>> for Complex<f64> {
-    fn into(self) -> Complex<f32> {
-        return Complex{real: self.real as f32, imag: self.imag as f32};
+impl std::convert::From<Complex<f64>> for Complex<f32> {
+    fn from(z: Complex<f64>) -> Complex<f32> {
+        return Complex{real: z.real as f32, imag: z.imag as f32};
     }
 }
 
@@ -118,6 +121,9 @@ impl <T: std::ops::Neg<Output=T>
         + std::ops::Mul<Output=T> 
         + std::ops::Div<Output=T> + Copy>std::ops::Div for Complex<T> {
     type Output = Self;
+    // Dividing by other is multiplying by its inverse, so the * here is
+    // not the mistake clippy looks for in a Div impl.
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, other: Self) -> Self {
         return self*other.inv();
     }
@@ -130,9 +136,9 @@ pub fn c64exp(z: Complex<f32>) -> Complex<f32> {
     };
 }
 
-/* fn c128exp(z: Complex<f64>) -> Complex<f64> {
+pub fn c128exp(z: Complex<f64>) -> Complex<f64> {
     return Complex {
         real: f64::exp(z.real)*f64::cos(z.imag),
         imag: f64::exp(z.real)*f64::sin(z.imag),
     };
-}*/
+}
@@ -0,0 +1,154 @@
+/* Simulation settings read at runtime from a plain text file, where
+each line has the form
+
+    key = value
+
+Values may be surrounded by double quotes, which is needed when they
+contain a '#', since everything after an unquoted '#' is a comment.
+Keys that are not recognized are ignored, so the same file can be
+shared between different versions of the program.
+
+The keys looked up are remembered with the values used, defaults
+included, so that the settings of a run can be saved with its output. */
+#[derive(Default)]
+pub struct Config {
+    pub entries: Vec<(String, String)>,
+    read: std::sync::Mutex<Vec<(String, Option<String>)>>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut entries = std::vec::Vec::<(String, String)>::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let trimmed: &str = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match trimmed.split_once('=') {
+                Some(kv) => kv,
+                None => return Err(format!(
+                    "line {}: expected 'key = value'", line_number + 1)),
+            };
+            let value: &str = value.trim();
+            let value: String = if let Some(quoted) = value.strip_prefix('"') {
+                match quoted.find('"') {
+                    Some(end) => quoted[..end].to_string(),
+                    None => return Err(format!(
+                        "line {}: unterminated string", line_number + 1)),
+                }
+            } else {
+                match value.find('#') {
+                    Some(end) => value[..end].trim().to_string(),
+                    None => value.to_string(),
+                }
+            };
+            entries.push((key.trim().to_string(), value));
+        }
+        return Ok(Config {entries, ..Default::default()});
+    }
+
+    pub fn load(filename: &str) -> std::io::Result<Config> {
+        let text: String = std::fs::read_to_string(filename)?;
+        return match Config::parse(&text) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}: {}", filename, e))),
+        };
+    }
+
+    /* The value of the last entry with the given key. */
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let value: Option<&str> = self.lookup(key);
+        self.remember(key, value.map(|v| v.to_string()));
+        return value;
+    }
+
+    fn lookup(&self, key: &str) -> Option<&str> {
+        for (k, v) in self.entries.iter().rev() {
+            if k == key {
+                return Some(v.as_str());
+            }
+        }
+        return None;
+    }
+
+    fn remember(&self, key: &str, value: Option<String>) {
+        if let Ok(mut read) = self.read.lock() {
+            match read.iter_mut().find(|(k, _)| k == key) {
+                Some(entry) => entry.1 = value,
+                None => read.push((key.to_string(), value)),
+            }
+        }
+    }
+
+    fn remember_or(&self, key: &str, default: impl ToString) {
+        self.remember(key, Some(match self.lookup(key) {
+            Some(v) => v.to_string(),
+            None => default.to_string(),
+        }));
+    }
+
+    /* The keys looked up so far, sorted, with the values used for them,
+    or None for those that were not set and have no default. */
+    pub fn resolved(&self) -> Vec<(String, Option<String>)> {
+        let mut read: Vec<(String, Option<String>)> = match self.read.lock() {
+            Ok(read) => read.clone(),
+            Err(_) => Vec::new(),
+        };
+        read.sort();
+        return read;
+    }
+
+    /* The keys in the file that have not been looked up, which are
+    either misspelt or for a different version of the program. */
+    pub fn unused(&self) -> Vec<String> {
+        let read: Vec<(String, Option<String>)> = self.resolved();
+        let mut unused: Vec<String> = Vec::new();
+        for (k, _) in self.entries.iter() {
+            if !read.iter().any(|(r, _)| r == k) && !unused.contains(k) {
+                unused.push(k.clone());
+            }
+        }
+        return unused;
+    }
+
+    pub fn get_f32(&self, key: &str, default: f32) -> std::io::Result<f32> {
+        self.remember_or(key, default);
+        return match self.lookup(key) {
+            None => Ok(default),
+            Some(v) => match v.parse::<f32>() {
+                Ok(val) => Ok(val),
+                Err(_) => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{} = {} is not a number", key, v))),
+            },
+        };
+    }
+
+    pub fn get_usize(&self, key: &str, default: usize
+                     ) -> std::io::Result<usize> {
+        self.remember_or(key, default);
+        return match self.lookup(key) {
+            None => Ok(default),
+            Some(v) => match v.parse::<usize>() {
+                Ok(val) => Ok(val),
+                Err(_) => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{} = {} is not a non-negative integer", key, v))),
+            },
+        };
+    }
+
+    pub fn get_bool(&self, key: &str, default: bool) -> std::io::Result<bool> {
+        self.remember_or(key, default);
+        return match self.lookup(key) {
+            None => Ok(default),
+            Some("true") | Some("1") => Ok(true),
+            Some("false") | Some("0") => Ok(false),
+            Some(v) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} = {} is not true or false", key, v))),
+        };
+    }
+}
@@ -1,6 +1,10 @@
 
 // Simulation side length in number of pixels (currently only squares used)
+#[cfg(not(target_arch = "wasm32"))]
 pub const N: usize = 1024;
+// Smaller in browsers, which run a single thread.
+#[cfg(target_arch = "wasm32")]
+pub const N: usize = 256;
 
 pub const NUMBER_OF_STEPS: usize = 3000;
 // The timestep used. This is a complex value.
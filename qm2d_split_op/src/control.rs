@@ -0,0 +1,260 @@
+use crate::complex::*;
+use crate::norm::*;
+use crate::random::*;
+use crate::split_op::*;
+
+/* The state to steer, the one to steer it into, and the potentials
+V_k the controls multiply, on the N by N grid: over the run the
+potential is V + sum_k u_k(t) V_k, with the real parts of V and V_k. */
+pub struct ControlProblem {
+    pub initial: Vec<Complex<f32>>,
+    pub target: Vec<Complex<f32>>,
+    pub potential: Vec<Complex<f32>>,
+    pub fields: Vec<Vec<f32>>,
+}
+
+pub struct ControlSettings {
+    pub dt: f32,
+    pub steps: usize, // of the pulse
+    pub slices: usize, // over which each control is constant
+    pub iterations: usize,
+    pub step_size: f64, // largest change of a control at the first update
+    pub tolerance: f64, // on 1 - fidelity
+    pub initial_amplitude: f64, // of the random starting controls
+    pub seed: u64,
+}
+
+impl Default for ControlSettings {
+    fn default() -> ControlSettings {
+        return ControlSettings {dt: 0.5, steps: 200, slices: 20,
+                                iterations: 50, step_size: 1e-3,
+                                tolerance: 1e-4, initial_amplitude: 1e-3,
+                                seed: 1};
+    }
+}
+
+/* The optimized controls, controls[k][slice], the fidelity after each
+iteration that improved it, the first that of the starting controls,
+and the final state. */
+pub struct ControlResult {
+    pub controls: Vec<Vec<f64>>,
+    pub fidelities: Vec<f64>,
+    pub psi: Vec<Complex<f32>>,
+}
+
+/* The potential of a slice with the given controls. */
+fn slice_potential(problem: &ControlProblem, controls: &[Vec<f64>],
+                   slice: usize, out: &mut [Complex<f32>]) {
+    for (i, v) in out.iter_mut().enumerate() {
+        let mut real: f32 = problem.potential[i].real;
+        for (field, u) in problem.fields.iter().zip(controls.iter()) {
+            real += (u[slice] as f32)*field[i];
+        }
+        *v = Complex {real, imag: 0.0};
+    }
+}
+
+/* <a|V_k|b> for the real field V_k. */
+pub fn field_element(a: &[Complex<f32>], field: &[f32], b: &[Complex<f32>]
+                 ) -> Complex<f64> {
+    let mut sum: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    for ((x, f), y) in a.iter().zip(field.iter()).zip(b.iter()) {
+        let x: Complex<f64> = (*x).into();
+        let y: Complex<f64> = (*y).into();
+        sum = sum + (x.conj()*y).scale(*f as f64);
+    }
+    return sum;
+}
+
+/* The controls fixed for each slice, and the steps of the pulse with
+them. */
+struct Pulse<'a> {
+    problem: &'a ControlProblem,
+    settings: &'a ControlSettings,
+    p_squared: &'a [f32],
+    forward: Propagators,
+    backward: Propagators,
+    potential: Vec<Complex<f32>>,
+}
+
+/* The slice of the pulse a step is in. */
+fn slice_of(step: usize, settings: &ControlSettings) -> usize {
+    return step*settings.slices/settings.steps;
+}
+
+impl<'a> Pulse<'a> {
+    /* psi at the end of the pulse from the initial state. The absorbing
+    layer of the runs is left out, so that every step is unitary and
+    can be undone. */
+    fn propagate(&mut self, controls: &[Vec<f64>]) -> Vec<Complex<f32>> {
+        let dt: Complex<f32> = Complex {real: self.settings.dt, imag: 0.0};
+        let mut psi: Vec<Complex<f32>> = self.problem.initial.clone();
+        for n in 0..self.settings.steps {
+            let slice: usize = slice_of(n, self.settings);
+            let changed: bool = n == 0
+                || slice != slice_of(n - 1, self.settings);
+            if changed {
+                slice_potential(self.problem, controls, slice,
+                                &mut self.potential);
+            }
+            self.forward.update(&self.potential, self.p_squared, dt, changed);
+            self.forward.propagate_spatial(&mut psi);
+            self.forward.propagate_kinetic(&mut psi, true);
+            self.forward.propagate_spatial(&mut psi);
+        }
+        return psi;
+    }
+
+    /* The gradient of the fidelity |<target|psi>|^2 with respect to the
+    controls, given psi at the end of the pulse with them. With
+    chi_n the target propagated back from the end to step n, and
+    psi_n the state after step n, c = <target|psi>, and U_n the step
+
+        exp(-i V_n dt/2) exp(-i T dt) exp(-i V_n dt/2),
+
+    whose derivative by u_k is -i dt/2 (V_k U_n + U_n V_k), the
+    derivative of the fidelity by the control of step n is
+
+        2 Re(c* (-i dt/2) (<chi_n|V_k|psi_n> + <chi_n-1|V_k|psi_n-1>)),
+
+    exactly, for the steps as they are taken. Both chi and psi are
+    stepped back from the end with the inverse steps, which take psi
+    back to where it was, so that no state but the current one needs to
+    be kept. */
+    fn gradient(&mut self, controls: &[Vec<f64>], psi_end: &[Complex<f32>]
+                ) -> Vec<Vec<f64>> {
+        let settings: &ControlSettings = self.settings;
+        let dt: Complex<f32> = Complex {real: -settings.dt, imag: 0.0};
+        let c: Complex<f64> = inner_product(&self.problem.target, psi_end);
+        let factor: Complex<f64> = c.conj()*Complex {
+            real: 0.0, imag: -(settings.dt as f64)};
+        let mut gradient: Vec<Vec<f64>> = vec![vec![0.0; settings.slices];
+                                               self.problem.fields.len()];
+        let mut psi: Vec<Complex<f32>> = psi_end.to_vec();
+        let mut chi: Vec<Complex<f32>> = self.problem.target.clone();
+        // The term of the boundary between steps n - 1 and n belongs to
+        // both.
+        let fields: &[Vec<f32>] = &self.problem.fields;
+        let mut add = |boundary: usize, psi: &[Complex<f32>],
+                       chi: &[Complex<f32>]| {
+            for (k, field) in fields.iter().enumerate() {
+                let term: f64 = (factor*field_element(chi, field, psi)).real;
+                if boundary > 0 {
+                    gradient[k][slice_of(boundary - 1, settings)] += term;
+                }
+                if boundary < settings.steps {
+                    gradient[k][slice_of(boundary, settings)] += term;
+                }
+            }
+        };
+        let steps: usize = settings.steps;
+        add(steps, &psi, &chi);
+        for n in (0..steps).rev() {
+            let slice: usize = slice_of(n, settings);
+            let changed: bool = n == steps - 1
+                || slice != slice_of(n + 1, settings);
+            if changed {
+                slice_potential(self.problem, controls, slice,
+                                &mut self.potential);
+            }
+            self.backward.update(&self.potential, self.p_squared, dt,
+                                 changed);
+            for state in [&mut psi, &mut chi] {
+                self.backward.propagate_spatial(state);
+                self.backward.propagate_kinetic(state, true);
+                self.backward.propagate_spatial(state);
+            }
+            add(n, &psi, &chi);
+        }
+        return gradient;
+    }
+}
+
+/* Find controls u_k(t), constant over each of settings.slices slices
+of the pulse, that steer the initial state into the target over
+settings.steps steps of dt, by gradient ascent on the fidelity
+|<target|psi(T)>|^2, with the gradient found by propagating the target
+back from the end of the pulse with the same propagators, as in the
+GRAPE method. Each iteration tries the controls moved along the
+gradient; a step that improves the fidelity is taken, and the next is
+made half as long again, while one that does not is tried again at half
+the length. The first moves the controls by at most
+settings.step_size. The controls start at random, uniform in
++-settings.initial_amplitude, as with none at all the gradient of a
+fidelity of 0 vanishes.
+
+The initial and target states are normalized, and the imaginary parts
+of the potentials are left out, as the steps must be unitary.
+
+Reference:
+
+N. Khaneja, T. Reiss, C. Kehlet, T. Schulte-Herbrueggen, S. J. Glaser.
+Optimal control of coupled spin dynamics: design of NMR pulse sequences
+by gradient ascent algorithms. Journal of Magnetic Resonance 172,
+296-305 (2005).
+*/
+pub fn optimize_controls(problem: &ControlProblem, p_squared: &[f32],
+                         settings: &ControlSettings,
+                         mut report: impl FnMut(usize, f64)
+                         ) -> Result<ControlResult, String> {
+    if problem.fields.is_empty() {
+        return Err(String::from("no control fields are given"));
+    }
+    if settings.slices == 0 || settings.slices > settings.steps {
+        return Err(format!("{} slices do not fit into a pulse of {} steps",
+                           settings.slices, settings.steps));
+    }
+    if settings.dt <= 0.0 {
+        return Err(format!("the time step must be positive, not {}",
+                           settings.dt));
+    }
+    let mut normalized = ControlProblem {
+        initial: problem.initial.clone(), target: problem.target.clone(),
+        potential: problem.potential.clone(), fields: problem.fields.clone()};
+    normalize(&mut normalized.initial, 1.0);
+    normalize(&mut normalized.target, 1.0);
+    let mut pulse = Pulse {
+        problem: &normalized, settings, p_squared,
+        forward: Propagators::default(), backward: Propagators::default(),
+        potential: problem.potential.clone()};
+    let mut random: Random = Random::new(settings.seed);
+    let mut controls: Vec<Vec<f64>> = (0..problem.fields.len())
+        .map(|_| (0..settings.slices).map(
+            |_| settings.initial_amplitude*(2.0*random.uniform() - 1.0))
+             .collect()).collect();
+    let fidelity = |psi: &[Complex<f32>]| -> f64 {
+        return inner_product(&normalized.target, psi).length_squared();
+    };
+    let mut psi: Vec<Complex<f32>> = pulse.propagate(&controls);
+    let mut fidelities: Vec<f64> = vec![fidelity(&psi)];
+    report(0, fidelities[0]);
+    let mut gradient: Vec<Vec<f64>> = pulse.gradient(&controls, &psi);
+    let largest = |g: &[Vec<f64>]| -> f64 {
+        return g.iter().flatten().fold(0.0, |m: f64, x| m.max(x.abs()));
+    };
+    let mut step: f64 = if largest(&gradient) > 0.0 {
+        settings.step_size/largest(&gradient)} else {0.0};
+    for iteration in 1..=settings.iterations {
+        let current: f64 = fidelities[fidelities.len() - 1];
+        if 1.0 - current <= settings.tolerance || step == 0.0 {
+            break;
+        }
+        let trial: Vec<Vec<f64>> = controls.iter().zip(gradient.iter())
+            .map(|(u, g)| u.iter().zip(g.iter())
+                 .map(|(u, g)| u + step*g).collect())
+            .collect();
+        let trial_psi: Vec<Complex<f32>> = pulse.propagate(&trial);
+        let f: f64 = fidelity(&trial_psi);
+        if f > current {
+            controls = trial;
+            psi = trial_psi;
+            fidelities.push(f);
+            report(iteration, f);
+            gradient = pulse.gradient(&controls, &psi);
+            step *= 1.5;
+        } else {
+            step *= 0.5;
+        }
+    }
+    return Ok(ControlResult {controls, fidelities, psi});
+}
@@ -0,0 +1,204 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::validation::*;
+
+/* Observed orders of convergence of the propagator, for the convergence
+subcommand. A scenario with an exact answer is run again at several
+values of a step size h, and the order p in error ~ h^p is fitted to
+the errors by least squares on their logarithms, with the orders
+between each pair of runs alongside:
+
+ - dt: a displaced Gaussian in the harmonic potential w^2 r^2/2 is
+   stepped for half a period, after which any state is exactly minus
+   its reflection through the centre, with the propagators of the main
+   loop on the N by N grid, at dt = 2, 1, 0.5 and 0.25. The Strang
+   splitting is of second order, so p should be 2.
+ - dx: a Gaussian spreading freely in a box of fixed side is stepped on
+   grids of 16 to 128 points a side, in doubles, with an exact kinetic
+   step, so that the only error is that of representing it on the grid.
+   The spectral method converges faster than any power of dx, so p
+   should be large and grow as dx shrinks; it is only checked to be
+   above 4.
+
+Errors below 1e-12, where rounding takes over, are left out of the
+fits. A fitted order outside its bounds is most likely a bug in the
+steps, such as a half step of the potential applied twice or a wrong
+momentum grid, rather than imprecision. */
+pub struct ConvergenceStudy {
+    pub name: &'static str,
+    pub parameter: &'static str, // the step size varied, dt or dx
+    pub runs: Vec<(f64, f64)>, // step size and error
+    pub min_order: f64, // the fitted order expected, within
+    pub max_order: f64,
+}
+
+/* Errors below this are rounding rather than the error of the method. */
+const ERROR_FLOOR: f64 = 1e-12;
+
+impl ConvergenceStudy {
+    fn usable(&self) -> Vec<(f64, f64)> {
+        return self.runs.iter().filter(|(_, e)| *e > ERROR_FLOOR)
+            .copied().collect();
+    }
+
+    /* The order between each run and the next. */
+    pub fn observed_orders(&self) -> Vec<f64> {
+        let runs: Vec<(f64, f64)> = self.usable();
+        return runs.windows(2).map(|w| {
+            (w[0].1/w[1].1).ln()/(w[0].0/w[1].0).ln()
+        }).collect();
+    }
+
+    /* The slope of a least squares fit of log(error) to log(h), or NaN
+    if fewer than two runs are usable. */
+    pub fn fitted_order(&self) -> f64 {
+        let points: Vec<(f64, f64)> = self.usable().iter()
+            .map(|(h, e)| (h.ln(), e.ln())).collect();
+        let n: f64 = points.len() as f64;
+        if points.len() < 2 {
+            return f64::NAN;
+        }
+        let mean_x: f64 = points.iter().map(|p| p.0).sum::<f64>()/n;
+        let mean_y: f64 = points.iter().map(|p| p.1).sum::<f64>()/n;
+        let sxy: f64 = points.iter()
+            .map(|p| (p.0 - mean_x)*(p.1 - mean_y)).sum::<f64>();
+        let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2))
+            .sum::<f64>();
+        return sxy/sxx;
+    }
+
+    pub fn passed(&self) -> bool {
+        let order: f64 = self.fitted_order();
+        return order >= self.min_order && order <= self.max_order;
+    }
+}
+
+/* The harmonic scenario of the dt study. */
+fn harmonic_half_period() -> ConvergenceStudy {
+    let period: f64 = 32.0;
+    let w: f64 = 2.0*std::f64::consts::PI/period;
+    let centre: f64 = (N/2) as f64;
+    let sigma: f64 = f64::sqrt(0.5/w);
+    let initial: Vec<Complex<f64>> = gaussian(sigma, centre + 3.0*sigma,
+                                              centre, 0.0);
+    // psi(T/2, r) = -psi(0, 2c - r), with the centre c at N/2 so that
+    // the reflection takes grid points to grid points.
+    let exact: Vec<Complex<f64>> = (0..N*N).map(|k| {
+        let (i, j): (usize, usize) = (k/N, k % N);
+        initial[((N - i) % N)*N + (N - j) % N].scale(-1.0)
+    }).collect();
+    let mut potential: Vec<Complex<f32>> = Vec::with_capacity(N*N);
+    for i in 0..N {
+        for j in 0..N {
+            let r2: f64 = ((j as f64) - centre).powi(2)
+                + ((i as f64) - centre).powi(2);
+            potential.push(Complex {real: (0.5*w*w*r2) as f32, imag: 0.0});
+        }
+    }
+    let runs: Vec<(f64, f64)> = [2.0, 1.0, 0.5, 0.25].iter().map(|dt| {
+        let mut psi: Vec<Complex<f32>> = to_f32(&initial);
+        let steps: usize = (0.5*period/dt).round() as usize;
+        evolve(&mut psi, &potential, *dt as f32, steps);
+        (*dt, relative_error(&psi, &exact))
+    }).collect();
+    return ConvergenceStudy {name: "harmonic_half_period", parameter: "dt",
+                             runs, min_order: 1.8, max_order: 2.2};
+}
+
+/* A Gaussian of width sigma at rest in the middle of an n by n grid of
+spacing dx at t = 0, spread freely for a time t. */
+fn sampled_gaussian(n: usize, dx: f64, sigma: f64, t: f64
+                    ) -> Vec<Complex<f64>> {
+    let a: Complex<f64> = Complex {real: 4.0*sigma*sigma, imag: 2.0*t};
+    let prefactor: Complex<f64> = Complex {real: 1.0, imag: 0.0}
+        /Complex {real: 1.0, imag: t/(2.0*sigma*sigma)};
+    let centre: f64 = 0.5*(n as f64)*dx;
+    return (0..n*n).map(|k| {
+        let x: f64 = ((k % n) as f64)*dx - centre;
+        let y: f64 = ((k/n) as f64)*dx - centre;
+        prefactor*c128exp(Complex {real: -(x*x + y*y), imag: 0.0}/a)
+    }).collect();
+}
+
+/* The free Gaussian of the dx study, in a box of side 128. */
+fn free_gaussian_grid() -> ConvergenceStudy {
+    let (side, sigma, t): (f64, f64, f64) = (128.0, 2.5, 10.0);
+    let runs: Vec<(f64, f64)> = [16, 32, 64, 128].iter().map(|n| {
+        let n: usize = *n;
+        let dx: f64 = side/(n as f64);
+        let mut psi: Vec<Complex<f64>> = sampled_gaussian(n, dx, sigma, 0.0);
+        // One exact kinetic step exp(-i p^2 t/2), with the 2D transform
+        // done as rows, a transpose, rows and a transpose back.
+        let fft2 = |psi: &mut [Complex<f64>], is_inverse: bool| {
+            for _ in 0..2 {
+                for row in psi.chunks_mut(n) {
+                    base_f64_fft_in_place(row, n, is_inverse);
+                }
+                square_transpose_in_place(psi, n);
+            }
+        };
+        fft2(&mut psi, false);
+        for i in 0..n {
+            for j in 0..n {
+                let p: f64 = 2.0*std::f64::consts::PI/((n as f64)*dx);
+                let p2: f64 = p*p*((fft_frequency(i, n).pow(2)
+                                    + fft_frequency(j, n).pow(2)) as f64);
+                psi[i*n + j] = psi[i*n + j]*c128exp(
+                    Complex {real: 0.0, imag: -0.5*p2*t});
+            }
+        }
+        fft2(&mut psi, true);
+        let exact: Vec<Complex<f64>> = sampled_gaussian(n, dx, sigma, t);
+        let difference: f64 = psi.iter().zip(exact.iter())
+            .map(|(z, w)| (*z - *w).length_squared()).sum::<f64>();
+        let norm: f64 = exact.iter().map(|w| w.length_squared()).sum::<f64>();
+        (dx, f64::sqrt(difference/norm))
+    }).collect();
+    return ConvergenceStudy {name: "free_gaussian_grid", parameter: "dx",
+                             runs, min_order: 4.0, max_order: f64::INFINITY};
+}
+
+type Study = fn() -> ConvergenceStudy;
+
+/* Run the studies whose names or parameters contain filter, or all of
+them. */
+pub fn run_convergence(filter: Option<&str>) -> Vec<ConvergenceStudy> {
+    let studies: [(&str, Study); 2] = [
+        ("harmonic_half_period dt", harmonic_half_period),
+        ("free_gaussian_grid dx", free_gaussian_grid)];
+    return studies.iter()
+        .filter(|(name, _)| filter.is_none_or(|f| name.contains(f)))
+        .map(|(_, study)| study()).collect();
+}
+
+/* The errors of each study and its observed orders, as a table. */
+pub fn convergence_report(studies: &[ConvergenceStudy]) -> String {
+    let mut text: String = String::new();
+    for s in studies.iter() {
+        text += &format!("{} ({}):\n{:>12} {:>12} {:>8}\n", s.name,
+                         s.parameter, s.parameter, "error", "order");
+        let orders: Vec<f64> = s.observed_orders();
+        let mut usable: usize = 0;
+        for (h, e) in s.runs.iter() {
+            let order: String = if *e > ERROR_FLOOR && usable > 0 {
+                format!("{:.2}", orders[usable - 1])
+            } else {
+                "".to_string()
+            };
+            if *e > ERROR_FLOOR {
+                usable += 1;
+            }
+            text += &format!("{:>12} {:>12.3e} {:>8}\n", h, e, order);
+        }
+        text += &format!("fitted order {:.2}, expected {} {}\n\n",
+                         s.fitted_order(),
+                         if s.max_order.is_finite() {
+                             format!("{} to {}", s.min_order, s.max_order)
+                         } else {
+                             format!("at least {}", s.min_order)
+                         },
+                         if s.passed() {"ok"} else {"FAILED"});
+    }
+    return text;
+}
@@ -0,0 +1,51 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::field::*;
+use crate::parallel::*;
+use crate::spectral::*;
+
+/* Probability current j = Im(conj(psi)*grad(psi)), in units of
+probability per grid spacing per unit time (hbar = m = 1), with the
+gradient computed spectrally. Returns the x and y components.
+
+Reference:
+
+Wikipedia - Probability current
+https://en.wikipedia.org/wiki/Probability_current
+*/
+pub fn current_density(psi: &[Complex<f32>]) -> (Field2D<f32>, Field2D<f32>) {
+    let zero: Complex<f32> = Complex {real: 0.0, imag: 0.0};
+    let mut ddx: Field2D<Complex<f32>> = Field2D::new(N, N, zero);
+    let mut ddy: Field2D<Complex<f32>> = Field2D::new(N, N, zero);
+    spectral_gradient(psi, ddx.as_mut_slice(), ddy.as_mut_slice());
+    let mut jx: Field2D<f32> = Field2D::new(N, N, 0.0);
+    let mut jy: Field2D<f32> = Field2D::new(N, N, 0.0);
+    for (k, z) in psi.iter().enumerate() {
+        jx.values[k] = (z.conj()*ddx.values[k]).imag;
+        jy.values[k] = (z.conj()*ddy.values[k]).imag;
+    }
+    return (jx, jy);
+}
+
+/* Total current through the part of the horizontal line at row i
+between columns j0 and j1, i.e. the probability per unit time
+crossing it in the +y direction. */
+pub fn flux_through_row(jy: &Field2D<f32>, i: usize,
+                        j0: usize, j1: usize) -> f64 {
+    let mut flux: CompensatedSum = CompensatedSum::default();
+    for j in j0..j1 {
+        flux += jy.get(i, j) as f64;
+    }
+    return flux.value();
+}
+
+/* Same as flux_through_row, for the current in the +x direction through
+the vertical line at column j between rows i0 and i1. */
+pub fn flux_through_column(jx: &Field2D<f32>, j: usize,
+                           i0: usize, i1: usize) -> f64 {
+    let mut flux: CompensatedSum = CompensatedSum::default();
+    for i in i0..i1 {
+        flux += jx.get(i, j) as f64;
+    }
+    return flux.value();
+}
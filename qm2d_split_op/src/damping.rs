@@ -0,0 +1,78 @@
+use crate::complex::*;
+use crate::norm::*;
+
+/* The phenomenological damping of the GPE, for the relaxation of a
+condensate by its thermal cloud, as in
+
+    i dpsi/dt = (1 - i gamma)(H - mu) psi,
+
+with H the Gross-Pitaevskii Hamiltonian and mu its chemical potential.
+The damped equation is stepped as the undamped one with the complex
+time step dt (1 - i gamma), which damps every energy E as
+exp(-gamma E dt), and the norm is then restored to what it was before
+the step, apart from what the absorbing layer took, which is what
+taking mu off H does. Excitations above the lowest state decay at
+gamma times their energy above it, so that a stirred condensate, or a
+vortex lattice nucleated in a rotating trap, relaxes to a steady state,
+where the chemical potential estimated from the norm lost in each
+step,
+
+    mu = -ln(norm after/norm before)/(2 gamma dt),
+
+stops changing.
+
+Reference:
+
+S. Choi, S. A. Morgan, K. Burnett. Phenomenological damping in trapped
+atomic Bose-Einstein condensates. Physical Review A 57, 4057-4060
+(1998).
+*/
+pub struct Damping {
+    pub gamma: f32,
+    pub tolerance: f64, // on the relative change of mu in a step
+    pub mu: Option<f64>, // at the last step
+    pub change: f64, // relative change of mu over the last step
+}
+
+impl Damping {
+    pub fn new(gamma: f32, tolerance: f64) -> Result<Damping, String> {
+        if !(gamma.is_finite() && gamma > 0.0) {
+            return Err(format!("the damping must be positive, not {}",
+                               gamma));
+        }
+        return Ok(Damping {gamma, tolerance, mu: None, change: f64::NAN});
+    }
+
+    /* The time step psi is stepped with, for the undamped step dt. */
+    pub fn damped_dt(&self, dt: Complex<f32>) -> Complex<f32> {
+        return Complex {real: dt.real, imag: dt.imag - self.gamma*dt.real};
+    }
+
+    /* Restore the norm of psi after a step of the damped time step,
+    given the norms from Stepper::step, before the step, after each of
+    its parts and after it, of which the absorbing layer is the fourth.
+    Returns the estimate of mu. */
+    pub fn restore(&mut self, psi: &mut [Complex<f32>], norms: [f64; 5],
+                   dt: Complex<f32>) -> f64 {
+        let [before, _, kinetic, absorbed, after]: [f64; 5] = norms;
+        let layer: f64 = if kinetic > 0.0 {absorbed/kinetic} else {1.0};
+        let mu: f64 = if before > 0.0 && after > 0.0 {
+            -(after/(before*layer)).ln()
+                /(2.0*(self.gamma as f64)*(dt.real as f64))
+        } else {
+            f64::NAN
+        };
+        normalize(psi, before*layer);
+        self.change = match self.mu {
+            Some(last) if mu != 0.0 => ((mu - last)/mu).abs(),
+            _ => f64::NAN,
+        };
+        self.mu = Some(mu);
+        return mu;
+    }
+
+    /* Whether mu has settled to within the tolerance, if one is set. */
+    pub fn is_steady(&self) -> bool {
+        return self.tolerance > 0.0 && self.change <= self.tolerance;
+    }
+}
@@ -0,0 +1,180 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::field::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum DetectorShape {
+    // Line segment from (x0, y0) to (x1, y1), counting the flux that
+    // crosses it to the right when looking from the start to the end.
+    Line {x0: f32, y0: f32, x1: f32, y1: f32},
+    // Circle counting the outgoing flux.
+    Ring {x0: f32, y0: f32, radius: f32},
+}
+
+impl DetectorShape {
+    /* Parse "line x0 y0 x1 y1" or "ring x0 y0 radius". */
+    pub fn parse(s: &str) -> Result<DetectorShape, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let mut args = std::vec::Vec::<f32>::new();
+        for w in words.iter().skip(1) {
+            match w.parse::<f32>() {
+                Ok(val) => args.push(val),
+                Err(_) => return Err(format!("invalid number '{}' in '{}'",
+                                             w, s)),
+            };
+        }
+        return match (words.first(), args.len()) {
+            (Some(&"line"), 4) if args[0] == args[2] && args[1] == args[3] => {
+                Err(format!("the line '{}' has no length", s.trim()))
+            },
+            (Some(&"line"), 4) => Ok(DetectorShape::Line {
+                x0: args[0], y0: args[1], x1: args[2], y1: args[3]}),
+            (Some(&"ring"), 3) => Ok(DetectorShape::Ring {
+                x0: args[0], y0: args[1], radius: args[2]}),
+            _ => Err(format!(
+                "expected 'line x0 y0 x1 y1' or 'ring x0 y0 radius', got '{}'",
+                s)),
+        };
+    }
+}
+
+/* A grid point on a detector, with the normal of the detector there and
+the length of detector it stands for, in grid points. */
+struct DetectorPoint {
+    index: usize,
+    nx: f32, ny: f32,
+    length: f32,
+}
+
+/* Virtual detector that accumulates the flux of probability crossing it,
+binned by the local momentum p = j/|psi|^2 of the wave function where it
+crosses. Since the momentum of a wave packet that has left the
+interaction region no longer changes, this gives the far field
+momentum and angular distributions without having to wait for the wave
+packet to travel far away, so much smaller grids can be used.
+
+Reference:
+
+B. Feuerstein, U. Thumm.
+On the computation of momentum distributions within wavepacket
+propagation calculations.
+Journal of Physics B 36, 707 (2003).
+*/
+pub struct VirtualDetector {
+    pub shape: DetectorShape,
+    points: Vec<DetectorPoint>,
+    pub momentum_max: f32, // upper edge of the last momentum bin
+    pub momentum_histogram: Vec<f64>, // outgoing flux by |p|
+    pub angle_histogram: Vec<f64>, // outgoing flux by angle of p
+    pub outgoing: f64, // total flux crossing in the direction of the normal
+    pub incoming: f64, // total flux crossing against it
+}
+
+impl VirtualDetector {
+    pub fn new(shape: DetectorShape, momentum_bins: usize,
+               momentum_max: f32, angle_bins: usize) -> VirtualDetector {
+        let mut points = std::vec::Vec::<DetectorPoint>::new();
+        let nf: f32 = N as f32;
+        let mut add_point = |x: f32, y: f32, nx: f32, ny: f32, length: f32| {
+            let j: usize = (f32::round(x*nf) as i64)
+                .rem_euclid(N as i64) as usize;
+            let i: usize = (f32::round(y*nf) as i64)
+                .rem_euclid(N as i64) as usize;
+            points.push(DetectorPoint {index: i*N + j, nx, ny, length});
+        };
+        match shape {
+            DetectorShape::Line {x0, y0, x1, y1} => {
+                let length: f32 = f32::hypot(x1 - x0, y1 - y0)*nf;
+                let count: usize = usize::max(1, f32::ceil(length) as usize);
+                let (nx, ny): (f32, f32) = ((y1 - y0)*nf/length,
+                                            -(x1 - x0)*nf/length);
+                for k in 0..count {
+                    let s: f32 = ((k as f32) + 0.5)/(count as f32);
+                    add_point(x0 + s*(x1 - x0), y0 + s*(y1 - y0),
+                              nx, ny, length/(count as f32));
+                }
+            },
+            DetectorShape::Ring {x0, y0, radius} => {
+                let length: f32 = 2.0*std::f32::consts::PI*radius*nf;
+                let count: usize = usize::max(1, f32::ceil(length) as usize);
+                for k in 0..count {
+                    let a: f32 = 2.0*std::f32::consts::PI*(k as f32)
+                        /(count as f32);
+                    add_point(x0 + radius*f32::cos(a), y0 + radius*f32::sin(a),
+                              f32::cos(a), f32::sin(a),
+                              length/(count as f32));
+                }
+            },
+        };
+        return VirtualDetector {
+            shape, points, momentum_max,
+            momentum_histogram: vec![0.0; momentum_bins],
+            angle_histogram: vec![0.0; angle_bins],
+            outgoing: 0.0, incoming: 0.0,
+        };
+    }
+
+    /* Add the flux through the detector over a time interval dt, given
+    the probability current (see current_density). */
+    pub fn record(&mut self, psi: &[Complex<f32>], jx: &Field2D<f32>,
+                  jy: &Field2D<f32>, dt: f32) {
+        let momentum_bins: usize = self.momentum_histogram.len();
+        let angle_bins: usize = self.angle_histogram.len();
+        for p in &self.points {
+            let jxp: f32 = jx.values[p.index];
+            let jyp: f32 = jy.values[p.index];
+            let flux: f64 = ((jxp*p.nx + jyp*p.ny)*p.length*dt) as f64;
+            if flux <= 0.0 {
+                self.incoming -= flux;
+                continue;
+            }
+            self.outgoing += flux;
+            let density: f32 = psi[p.index].length_squared();
+            if density <= 0.0 {
+                continue;
+            }
+            let px: f32 = jxp/density;
+            let py: f32 = jyp/density;
+            let k: usize = (f32::hypot(px, py)/self.momentum_max
+                            *(momentum_bins as f32)) as usize;
+            if k < momentum_bins {
+                self.momentum_histogram[k] += flux;
+            }
+            let a: f32 = (f32::atan2(py, px) + std::f32::consts::PI)
+                /(2.0*std::f32::consts::PI);
+            let k: usize = usize::min((a*(angle_bins as f32)) as usize,
+                                      angle_bins - 1);
+            self.angle_histogram[k] += flux;
+        }
+    }
+
+    /* Write the histograms, as lines "p flux" for the momentum
+    distribution followed by a blank line and lines "angle flux" for
+    the angular distribution, with each value at the centre of its bin. */
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let file = std::fs::File::create(filename)?;
+        let mut writer = std::io::BufWriter::new(file);
+        writeln!(writer, "# {:?}", self.shape)?;
+        writeln!(writer, "# outgoing {:e} incoming {:e}",
+                 self.outgoing, self.incoming)?;
+        writeln!(writer, "# p flux")?;
+        let momentum_bins: usize = self.momentum_histogram.len();
+        for k in 0..momentum_bins {
+            writeln!(writer, "{} {:e}",
+                     ((k as f32) + 0.5)*self.momentum_max/(momentum_bins as f32),
+                     self.momentum_histogram[k])?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "# angle flux")?;
+        let angle_bins: usize = self.angle_histogram.len();
+        for k in 0..angle_bins {
+            writeln!(writer, "{} {:e}",
+                     2.0*std::f32::consts::PI*((k as f32) + 0.5)
+                     /(angle_bins as f32) - std::f32::consts::PI,
+                     self.angle_histogram[k])?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+}
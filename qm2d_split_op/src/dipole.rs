@@ -0,0 +1,120 @@
+use crate::constants::*;
+use crate::complex::*;
+
+/* Dipole acceleration <a> = -<grad V>, in grid points per unit time
+squared, from the real part of the potential with central differences.
+By Ehrenfest's theorem this is the second time derivative of <r>, but
+unlike differentiating <r> numerically it is not dominated by noise at
+high frequencies, which is where the harmonics of a driven run are.
+The potential should include the driving field, as it does when the
+field is given as a time dependent V.
+
+Reference:
+
+K. Burnett, V. C. Reed, J. Cooper, P. L. Knight.
+Calculation of the background emitted during high-harmonic generation.
+Physical Review A 45, 3347 (1992).
+*/
+pub fn dipole_acceleration(psi: &[Complex<f32>], potential: &[Complex<f32>]
+                           ) -> (f64, f64) {
+    let mut norm: f64 = 0.0;
+    let mut ax: f64 = 0.0;
+    let mut ay: f64 = 0.0;
+    for i in 0..N {
+        for j in 0..N {
+            let abs2: f64 = psi[i*N + j].length_squared() as f64;
+            if abs2 == 0.0 {
+                continue;
+            }
+            let left: f32 = potential[i*N + (j + N - 1) % N].real;
+            let right: f32 = potential[i*N + (j + 1) % N].real;
+            let down: f32 = potential[((i + N - 1) % N)*N + j].real;
+            let up: f32 = potential[((i + 1) % N)*N + j].real;
+            norm += abs2;
+            ax -= abs2*0.5*((right - left) as f64);
+            ay -= abs2*0.5*((up - down) as f64);
+        }
+    }
+    return (ax/norm, ay/norm);
+}
+
+/* Time-frequency distribution of a signal sampled every dt. Row k is
+the time times[k] and column l the angular frequency omegas[l]. */
+pub struct Spectrogram {
+    pub times: Vec<f64>,
+    pub omegas: Vec<f64>,
+    pub values: Vec<Vec<f64>>,
+}
+
+/* Gabor transform of the signal: the power spectrum of the signal
+multiplied by a Gaussian window of standard deviation width (in units
+of time) centred on every stride-th sample, at count angular
+frequencies from 0 to omega_max. A narrower window resolves when a
+frequency is emitted more finely but its frequency more coarsely. In
+a high harmonic generation run the signal is the dipole acceleration,
+and the spectrogram shows at which times in the driving cycle each
+harmonic is emitted.
+
+Reference:
+
+Wikipedia - Gabor transform
+https://en.wikipedia.org/wiki/Gabor_transform
+*/
+pub fn gabor_spectrogram(signal: &[f64], dt: f64, width: f64,
+                         stride: usize, omega_max: f64, count: usize
+                         ) -> Spectrogram {
+    let stride: usize = usize::max(1, stride);
+    let omegas: Vec<f64> = (0..count)
+        .map(|l| omega_max*(l as f64)/(usize::max(1, count - 1) as f64))
+        .collect();
+    let mut times = std::vec::Vec::<f64>::new();
+    let mut values = std::vec::Vec::<Vec<f64>>::new();
+    // The window is cut off at 4 widths to either side.
+    let half: usize = f64::ceil(4.0*width/dt) as usize;
+    for centre in (0..signal.len()).step_by(stride) {
+        let start: usize = centre.saturating_sub(half);
+        let end: usize = usize::min(signal.len(), centre + half + 1);
+        let mut row = std::vec::Vec::<f64>::with_capacity(count);
+        for &omega in &omegas {
+            let mut sum: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+            for (n, value) in (start..end).zip(signal[start..end].iter()) {
+                let s: f64 = ((n as f64) - (centre as f64))*dt/width;
+                let w: f64 = f64::exp(-0.5*s*s)*value*dt;
+                let phase: f64 = -omega*(n as f64)*dt;
+                sum = sum + Complex {real: w*f64::cos(phase),
+                                     imag: w*f64::sin(phase)};
+            }
+            row.push(sum.length_squared());
+        }
+        times.push((centre as f64)*dt);
+        values.push(row);
+    }
+    return Spectrogram {times, omegas, values};
+}
+
+impl Spectrogram {
+    /* Write the spectrogram as a matrix with one line per time, after a
+    comment line giving the ranges of time and frequency, as for the
+    phase space distributions. */
+    pub fn save_text(&self, filename: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let file = std::fs::File::create(filename)?;
+        let mut writer = std::io::BufWriter::new(file);
+        writeln!(writer, "# t {} {} omega {} {}",
+                 self.times.first().unwrap_or(&0.0),
+                 self.times.last().unwrap_or(&0.0),
+                 self.omegas.first().unwrap_or(&0.0),
+                 self.omegas.last().unwrap_or(&0.0))?;
+        for row in &self.values {
+            for (l, v) in row.iter().enumerate() {
+                if l > 0 {
+                    write!(writer, " ")?;
+                }
+                write!(writer, "{:e}", v)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+}
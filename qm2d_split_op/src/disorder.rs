@@ -0,0 +1,71 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::random::*;
+
+/* Random realizations of the potential and of the phase of psi, for
+disordered media and for averaging over many realizations with the
+ensemble subcommand. Both are Gaussian random fields with zero mean and
+correlations exp(-r^2/(2 l^2)) over a length l, drawn from a seed so
+that a realization can be run again. */
+pub struct Disorder {
+    pub seed: u64,
+    pub correlation: f32, // l, as a fraction of the side of the grid
+    pub potential: f32, // standard deviation of the potential
+    pub phase: f32, // standard deviation of the phase, in radians
+}
+
+/* A Gaussian random field of standard deviation 1 on the grid, with
+correlations over the length correlation (as a fraction of the side),
+made by smoothing white noise in momentum space: a Gaussian kernel of
+standard deviation l/sqrt(2) gives the correlations of length l. The
+field is periodic, as the grid is. */
+pub fn gaussian_random_field(seed: u64, correlation: f32) -> Vec<f32> {
+    let mut random: Random = Random::new(seed);
+    let mut field: Vec<Complex<f32>> = (0..N*N).map(
+        |_| Complex {real: random.normal() as f32, imag: 0.0}).collect();
+    fft2_in_place(&mut field, true);
+    let l: f32 = correlation*(N as f32);
+    for i in 0..N {
+        for j in 0..N {
+            let k2: f32 = fft_momentum(i, N).powi(2)
+                + fft_momentum(j, N).powi(2);
+            field[i*N + j] = field[i*N + j].scale(f32::exp(-0.25*k2*l*l));
+        }
+    }
+    ifft2_in_place(&mut field, true);
+    let values: Vec<f32> = field.iter().map(|z| z.real).collect();
+    let mean: f64 = values.iter().map(|v| *v as f64).sum::<f64>()
+        /((N*N) as f64);
+    let variance: f64 = values.iter().map(|v| (*v as f64 - mean).powi(2))
+        .sum::<f64>()/((N*N) as f64);
+    let scale: f64 = if variance > 0.0 {1.0/variance.sqrt()} else {0.0};
+    return values.iter().map(|v| ((*v as f64 - mean)*scale) as f32)
+        .collect();
+}
+
+impl Disorder {
+    /* The disordered potential, or None if its strength is 0. */
+    pub fn potential_values(&self) -> Option<Vec<f32>> {
+        if self.potential == 0.0 {
+            return None;
+        }
+        return Some(gaussian_random_field(self.seed, self.correlation)
+                    .iter().map(|v| v*self.potential).collect());
+    }
+
+    /* Multiply psi by exp(i phi) for a random phase phi, if its strength
+    is not 0. The phase is drawn from a seed of its own, so that turning
+    on one of the two does not change the other. */
+    pub fn apply_phase(&self, psi: &mut [Complex<f32>]) {
+        if self.phase == 0.0 {
+            return;
+        }
+        let phi: Vec<f32> = gaussian_random_field(
+            self.seed ^ 0x7068617365, self.correlation);
+        for (z, phi) in psi.iter_mut().zip(phi.iter()) {
+            let phi: f32 = phi*self.phase;
+            *z = *z*Complex {real: f32::cos(phi), imag: f32::sin(phi)};
+        }
+    }
+}
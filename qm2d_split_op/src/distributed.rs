@@ -0,0 +1,304 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::field::*;
+use crate::norm::*;
+use crate::parallel::*;
+use crate::scratch::*;
+use crate::split_op::*;
+use std::io::{Read, Write};
+
+/* Runs split over several processes, on one machine or several, that
+each hold a slab of N/size consecutive rows of the grid and talk over
+TCP. Pointwise steps are done on each slab alone. The 2D fft transforms
+the rows of each slab, then transposes the grid, for which every
+process sends every other a block of N/size by N/size points, and
+transforms the rows again, as fft2_in_place does on one process, so
+that the results are the same to the bit. The absorbing layer needs the
+row after each slab, which comes from the next process, and norms are
+summed over all of them.
+
+Only the stepping is split: the initial state and the potential are
+built whole by each process before it keeps its slab, and the final
+state is gathered whole by the first. There is no MPI or GPU transport,
+since this crate has no dependencies. */
+pub struct Cluster {
+    pub rank: usize,
+    pub size: usize,
+    // Streams to each other process, the second a clone of the first
+    // for writing while the first is read.
+    readers: Vec<Option<std::net::TcpStream>>,
+    writers: Vec<Option<std::net::TcpStream>>,
+}
+
+impl Cluster {
+    /* Join the processes listening on the given addresses, as the one
+    at addresses[rank]. Each process accepts connections from those
+    after it and connects to those before it, retrying for up to
+    timeout while they start. */
+    pub fn connect(rank: usize, addresses: &[String],
+                   timeout: std::time::Duration) -> std::io::Result<Cluster> {
+        let size: usize = addresses.len();
+        if rank >= size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("rank {} is not below the {} addresses given",
+                        rank, size)));
+        }
+        let mut readers: Vec<Option<std::net::TcpStream>>
+            = (0..size).map(|_| None).collect();
+        let listener = std::net::TcpListener::bind(&addresses[rank])?;
+        let start = std::time::Instant::now();
+        for (k, address) in addresses.iter().enumerate().take(rank) {
+            let mut stream = loop {
+                match std::net::TcpStream::connect(address) {
+                    Ok(stream) => break stream,
+                    Err(e) if start.elapsed() > timeout => return Err(e),
+                    Err(_) => std::thread::sleep(
+                        std::time::Duration::from_millis(100)),
+                }
+            };
+            stream.write_all(&(rank as u32).to_le_bytes())?;
+            readers[k] = Some(stream);
+        }
+        for _ in rank + 1..size {
+            let (mut stream, _) = listener.accept()?;
+            let mut id: [u8; 4] = [0; 4];
+            stream.read_exact(&mut id)?;
+            let k: usize = u32::from_le_bytes(id) as usize;
+            if k <= rank || k >= size || readers[k].is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("unexpected connection from rank {}", k)));
+            }
+            readers[k] = Some(stream);
+        }
+        let mut writers: Vec<Option<std::net::TcpStream>> = Vec::new();
+        for reader in readers.iter() {
+            writers.push(match reader {
+                Some(stream) => {
+                    stream.set_nodelay(true)?;
+                    Some(stream.try_clone()?)
+                },
+                None => None,
+            });
+        }
+        return Ok(Cluster {rank, size, readers, writers});
+    }
+
+    /* Send outgoing[k] to each other process k and return what each
+    sent this one, with this one's own entry passed through. Messages
+    are written by a thread of their own while the others are read, in
+    turn from the next process on, so that large ones cannot leave two
+    processes waiting on each other to read. */
+    pub fn exchange(&mut self, mut outgoing: Vec<Vec<u8>>
+                    ) -> std::io::Result<Vec<Vec<u8>>> {
+        let (rank, size) = (self.rank, self.size);
+        let mut incoming: Vec<Vec<u8>> = vec![Vec::new(); size];
+        incoming[rank] = std::mem::take(&mut outgoing[rank]);
+        let writers = &mut self.writers;
+        let readers = &mut self.readers;
+        return std::thread::scope(|s| {
+            let sending = s.spawn(move || -> std::io::Result<()> {
+                for step in 1..size {
+                    let k: usize = (rank + step) % size;
+                    if let Some(w) = writers[k].as_mut() {
+                        w.write_all(&(outgoing[k].len() as u64)
+                                    .to_le_bytes())?;
+                        w.write_all(&outgoing[k])?;
+                        w.flush()?;
+                    }
+                }
+                return Ok(());
+            });
+            for step in 1..size {
+                let k: usize = (rank + size - step) % size;
+                if let Some(r) = readers[k].as_mut() {
+                    let mut length: [u8; 8] = [0; 8];
+                    r.read_exact(&mut length)?;
+                    incoming[k] = vec![0; u64::from_le_bytes(length) as usize];
+                    r.read_exact(&mut incoming[k])?;
+                }
+            }
+            sending.join().unwrap_or_else(|_| Err(std::io::Error::other(
+                "the sending thread panicked")))?;
+            return Ok(incoming);
+        });
+    }
+
+    /* The sum of x over all processes, added in order of rank so that
+    every process gets the same. */
+    pub fn sum(&mut self, x: f64) -> std::io::Result<f64> {
+        let incoming: Vec<Vec<u8>> = self.exchange(
+            vec![x.to_le_bytes().to_vec(); self.size])?;
+        let mut sum: CompensatedSum = CompensatedSum::default();
+        for bytes in incoming.iter() {
+            sum += f64::from_le_bytes(bytes_array(bytes)?);
+        }
+        return Ok(sum.value());
+    }
+}
+
+fn bytes_array<const K: usize>(bytes: &[u8]) -> std::io::Result<[u8; K]> {
+    return bytes.try_into().map_err(|_| std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("expected {} bytes from another process, got {}", K,
+                bytes.len())));
+}
+
+fn complex_bytes(values: &[Complex<f32>]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(8*values.len());
+    for z in values.iter() {
+        bytes.extend(z.real.to_le_bytes());
+        bytes.extend(z.imag.to_le_bytes());
+    }
+    return bytes;
+}
+
+fn bytes_complex(bytes: &[u8], values: &mut [Complex<f32>]
+                 ) -> std::io::Result<()> {
+    if bytes.len() != 8*values.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("expected {} points from another process, got {} bytes",
+                    values.len(), bytes.len())));
+    }
+    for (z, b) in values.iter_mut().zip(bytes.chunks(8)) {
+        *z = Complex {real: f32::from_le_bytes(bytes_array(&b[..4])?),
+                      imag: f32::from_le_bytes(bytes_array(&b[4..])?)};
+    }
+    return Ok(());
+}
+
+/* Transpose the grid whose rows are split into the slabs of the
+processes. The block of slab c of width rows starting at column
+rank*rows goes, transposed, to the same place in slab rank. */
+fn transpose_slabs(cluster: &mut Cluster, slab: &mut [Complex<f32>]
+                   ) -> std::io::Result<()> {
+    let rows: usize = N/cluster.size;
+    return with_scratch(rows*rows, |block| {
+        let mut outgoing: Vec<Vec<u8>> = Vec::with_capacity(cluster.size);
+        for p in 0..cluster.size {
+            for j in 0..rows {
+                for r in 0..rows {
+                    block[j*rows + r] = slab[r*N + p*rows + j];
+                }
+            }
+            outgoing.push(complex_bytes(block));
+        }
+        let incoming: Vec<Vec<u8>> = cluster.exchange(outgoing)?;
+        for (c, bytes) in incoming.iter().enumerate() {
+            bytes_complex(bytes, block)?;
+            for j in 0..rows {
+                slab[j*N + c*rows..j*N + (c + 1)*rows]
+                    .copy_from_slice(&block[j*rows..(j + 1)*rows]);
+            }
+        }
+        return Ok(());
+    });
+}
+
+/* The 2D fft of the grid split into slabs, as base_square_fft2_in_place
+does for the whole grid. */
+pub fn slab_fft2_in_place(cluster: &mut Cluster, slab: &mut [Complex<f32>],
+                          is_inverse: bool) -> std::io::Result<()> {
+    for _ in 0..2 {
+        for row in slab.chunks_mut(N) {
+            base_f32_fft_in_place(row, N, is_inverse);
+        }
+        transpose_slabs(cluster, slab)?;
+    }
+    return Ok(());
+}
+
+/* The slab of one process and what it needs to step it. */
+pub struct SlabSimulation {
+    pub cluster: Cluster,
+    pub first_row: usize,
+    pub psi: Vec<Complex<f32>>,
+    pub potential: Vec<Complex<f32>>,
+    p_squared: Vec<f32>,
+    propagators: Propagators,
+    potential_changed: bool,
+}
+
+impl SlabSimulation {
+    /* The slab of this process of the whole grids psi and potential. */
+    pub fn new(cluster: Cluster, psi: &[Complex<f32>],
+               potential: &[Complex<f32>], p_squared: &[f32]
+               ) -> std::io::Result<SlabSimulation> {
+        if !N.is_multiple_of(cluster.size) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("the {} rows of the grid cannot be split between \
+                         {} processes", N, cluster.size)));
+        }
+        let rows: usize = N/cluster.size;
+        let range = cluster.rank*rows*N..(cluster.rank + 1)*rows*N;
+        return Ok(SlabSimulation {
+            first_row: cluster.rank*rows,
+            psi: psi[range.clone()].to_vec(),
+            potential: potential[range.clone()].to_vec(),
+            p_squared: p_squared[range].to_vec(),
+            propagators: Propagators::default(), potential_changed: true,
+            cluster});
+    }
+
+    /* Replace the potential of the slab with its part of the whole
+    grid. */
+    pub fn set_potential(&mut self, potential: &[Complex<f32>]) {
+        let start: usize = self.first_row*N;
+        let end: usize = start + self.potential.len();
+        self.potential.copy_from_slice(&potential[start..end]);
+        self.potential_changed = true;
+    }
+
+    /* Advance the slab by a time step dt, as split_op::step does
+    without a nonlinear term. */
+    pub fn step(&mut self, dt: Complex<f32>) -> std::io::Result<()> {
+        self.propagators.update(&self.potential, &self.p_squared, dt,
+                                std::mem::take(&mut self.potential_changed));
+        self.propagators.propagate_spatial(&mut self.psi);
+        slab_fft2_in_place(&mut self.cluster, &mut self.psi, false)?;
+        multiply_in_place(&mut self.psi, &self.propagators.kinetic);
+        slab_fft2_in_place(&mut self.cluster, &mut self.psi, true)?;
+        // The first row of each slab is the row after the last of the
+        // one before it.
+        let size: usize = self.cluster.size;
+        let mut outgoing: Vec<Vec<u8>> = vec![Vec::new(); size];
+        outgoing[(self.cluster.rank + size - 1) % size]
+            = complex_bytes(&self.psi[..N]);
+        let incoming: Vec<Vec<u8>> = self.cluster.exchange(outgoing)?;
+        let mut below: Vec<Complex<f32>>
+            = vec![Complex {real: 0.0, imag: 0.0}; N];
+        bytes_complex(&incoming[(self.cluster.rank + 1) % size], &mut below)?;
+        dampen_rows(&mut self.psi, N, self.first_row, Some(&below),
+                    dt.real);
+        self.propagators.propagate_spatial(&mut self.psi);
+        return Ok(());
+    }
+
+    /* The norm of the whole wave function. */
+    pub fn norm_squared(&mut self) -> std::io::Result<f64> {
+        let norm: f64 = norm_squared(&self.psi);
+        return self.cluster.sum(norm);
+    }
+
+    /* Collect the whole wave function in psi on the first process; on
+    the others psi is left as it is. */
+    pub fn gather(&mut self, psi: &mut [Complex<f32>]) -> std::io::Result<()> {
+        let mut outgoing: Vec<Vec<u8>> = vec![Vec::new(); self.cluster.size];
+        if self.cluster.rank != 0 {
+            outgoing[0] = complex_bytes(&self.psi);
+        }
+        let incoming: Vec<Vec<u8>> = self.cluster.exchange(outgoing)?;
+        if self.cluster.rank == 0 {
+            let slab: usize = self.psi.len();
+            psi[..slab].copy_from_slice(&self.psi);
+            for (k, bytes) in incoming.iter().enumerate().skip(1) {
+                bytes_complex(bytes, &mut psi[k*slab..(k + 1)*slab])?;
+            }
+        }
+        return Ok(());
+    }
+}
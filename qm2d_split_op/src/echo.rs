@@ -0,0 +1,72 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::potentials::*;
+use crate::norm::*;
+use crate::split_op::*;
+
+/* Loschmidt echo, or fidelity, of the evolution under a perturbed
+Hamiltonian H + dV with respect to that under H,
+
+    M(t) = |<psi(0)| exp(i(H + dV)t) exp(-iHt) |psi(0)>|^2,
+
+which is the overlap left after propagating forward under H and back
+under H + dV. Writing it as |<psi'(t)|psi(t)>|^2, with psi' propagated
+forward under H + dV, it is found by evolving a second copy of the
+wave function alongside the first, without having to run backwards.
+Its decay with time measures how sensitive the dynamics is to the
+perturbation, and is a standard signature of quantum chaos.
+
+Reference:
+
+Wikipedia - Loschmidt echo
+https://en.wikipedia.org/wiki/Loschmidt_echo
+
+T. Gorin, T. Prosen, T. H. Seligman, M. Znidaric.
+Dynamics of Loschmidt echoes and fidelity decay.
+Physics Reports 435, 33-156 (2006).
+*/
+pub struct LoschmidtEcho {
+    pub psi: Vec<Complex<f32>>, // the copy evolved under H + dV
+    pub perturbation: Box<dyn TimeDependentPotential>,
+    perturbation_values: Vec<Complex<f32>>,
+    potential: Vec<Complex<f32>>,
+}
+
+impl LoschmidtEcho {
+    pub fn new(psi0: &[Complex<f32>],
+               perturbation: Box<dyn TimeDependentPotential>
+               ) -> LoschmidtEcho {
+        let zero: Complex<f32> = Complex {real: 0.0, imag: 0.0};
+        let mut perturbation_values = vec![zero; N*N];
+        perturbation.fill_at(perturbation_values.as_mut_slice(), 0.0);
+        return LoschmidtEcho {
+            psi: psi0.to_vec(), perturbation, perturbation_values,
+            potential: vec![zero; N*N],
+        };
+    }
+
+    /* Advance the perturbed copy by dt from time t, where potential is
+    the unperturbed potential at that time. */
+    pub fn step(&mut self, potential: &[Complex<f32>], p_squared: &[f32],
+                nonlinear: Nonlinear, t: f32, dt: Complex<f32>) {
+        if self.perturbation.is_time_dependent() {
+            self.perturbation.fill_at(
+                self.perturbation_values.as_mut_slice(), t);
+        }
+        for ((v, p), d) in self.potential.iter_mut().zip(potential.iter())
+            .zip(self.perturbation_values.iter()) {
+            *v = *p + *d;
+        }
+        step(self.psi.as_mut_slice(), self.potential.as_slice(), p_squared,
+             nonlinear, dt, true);
+    }
+
+    /* The fidelity |<psi'|psi>|^2, normalized by the norms of both
+    copies so that probability lost to absorbing boundaries does not
+    count as decay. */
+    pub fn fidelity(&self, psi: &[Complex<f32>]) -> f64 {
+        let overlap: Complex<f64> = inner_product(self.psi.as_slice(), psi);
+        return overlap.length_squared()
+            /(norm_squared(self.psi.as_slice())*norm_squared(psi));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::lobpcg::*;
+use crate::norm::*;
+use crate::observables::*;
+use crate::resample::*;
+use crate::split_op::*;
+use crate::stencil::*;
+
+/* A normalized stationary state and its energy. */
+pub struct Eigenstate {
+    pub energy: f64,
+    pub psi: Vec<Complex<f32>>,
+}
+
+pub struct ImaginaryTimeSettings {
+    pub dt: f32, // imaginary time step
+    pub max_steps: usize,
+    pub tolerance: f64, // on the change in energy per check, relative
+    pub check_every: usize, // steps between energy checks
+    pub g: f32, // strength of the nonlinear term, as in Nonlinear::square
+}
+
+impl Default for ImaginaryTimeSettings {
+    fn default() -> ImaginaryTimeSettings {
+        return ImaginaryTimeSettings {dt: 2.0, max_steps: 2000,
+                                      tolerance: 1e-6, check_every: 10,
+                                      g: 0.0};
+    }
+}
+
+/* Make the states orthogonal to each other and of unit norm, with the
+modified Gram-Schmidt process, so that the k-th state keeps only its
+component orthogonal to the states before it. */
+pub fn orthonormalize(states: &mut [Vec<Complex<f32>>]) {
+    for k in 0..states.len() {
+        let (done, rest) = states.split_at_mut(k);
+        let psi: &mut Vec<Complex<f32>> = &mut rest[0];
+        for phi in done.iter() {
+            let c: Complex<f32> = inner_product(phi, psi).into();
+            for i in 0..psi.len() {
+                psi[i] = psi[i] - c*phi[i];
+            }
+        }
+        normalize(psi, 1.0);
+    }
+}
+
+/* Starting guesses for count eigenstates: a Gaussian about the centre
+of the grid times (x - 1/2)^a (y - 1/2)^b, in order of increasing
+a + b, which mimic the lowest states of a trap and overlap with those
+of most other potentials. */
+pub fn default_guesses(count: usize) -> Vec<Vec<Complex<f32>>> {
+    let mut guesses = std::vec::Vec::<Vec<Complex<f32>>>::new();
+    let mut order: usize = 0;
+    while guesses.len() < count {
+        for a in (0..=order).rev() {
+            if guesses.len() == count {
+                break;
+            }
+            let b: usize = order - a;
+            let mut psi = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+            for i in 0..N {
+                for j in 0..N {
+                    let x: f32 = (j as f32)/(N as f32) - 0.5;
+                    let y: f32 = (i as f32)/(N as f32) - 0.5;
+                    psi[i*N + j].real = f32::powi(8.0*x, a as i32)
+                        *f32::powi(8.0*y, b as i32)
+                        *f32::exp(-32.0*(x*x + y*y));
+                }
+            }
+            guesses.push(psi);
+        }
+        order += 1;
+    }
+    return guesses;
+}
+
+/* Find the lowest eigenstates of the Hamiltonian with the given
+potential by propagating the initial states in imaginary time, which
+damps each component by exp(-E*tau), and orthonormalizing them after
+every step so that each converges to the lowest state not taken by
+the ones before it. Stops once no energy changes by more than the
+tolerance between checks, or after max_steps. The returned states are
+in order of increasing energy.
+
+Reference:
+
+L. Lehtovaara, J. Toivanen, J. Eloranta.
+Solution of time-independent Schrodinger equation by the imaginary
+time propagation method.
+Journal of Computational Physics 221, 148-157 (2007).
+*/
+pub fn imaginary_time_eigenstates(initial: Vec<Vec<Complex<f32>>>,
+                                  potential: &[Complex<f32>],
+                                  p_squared: &[f32],
+                                  settings: &ImaginaryTimeSettings
+                                  ) -> Vec<Eigenstate> {
+    let mut states: Vec<Vec<Complex<f32>>> = initial;
+    let dt: Complex<f32> = Complex {real: 0.0, imag: -settings.dt};
+    let nonlinear = Nonlinear {square: settings.g};
+    orthonormalize(states.as_mut_slice());
+    let mut scratch = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+    let mut energies: Vec<f64> = vec![f64::INFINITY; states.len()];
+    let check_every: usize = usize::max(1, settings.check_every);
+    let mut propagators = Propagators::default();
+    propagators.update(potential, p_squared, dt, true);
+    for step in 1..=settings.max_steps {
+        for psi in states.iter_mut() {
+            // The nonlinear term depends on psi, so only the kinetic
+            // factors can be reused when there is one.
+            if nonlinear.square == 0.0 {
+                propagators.propagate_spatial(psi);
+                propagators.propagate_kinetic(psi, true);
+                propagators.propagate_spatial(psi);
+            } else {
+                propagate_spatial_terms(psi, potential, nonlinear,
+                                        dt.scale(0.5));
+                propagators.propagate_kinetic(psi, true);
+                propagate_spatial_terms(psi, potential, nonlinear,
+                                        dt.scale(0.5));
+            }
+        }
+        orthonormalize(states.as_mut_slice());
+        if step % check_every == 0 || step == settings.max_steps {
+            let mut converged: bool = true;
+            for k in 0..states.len() {
+                let e: f64 = compute_expectations(
+                    &states[k], potential, settings.g,
+                    scratch.as_mut_slice()).total;
+                if f64::abs(e - energies[k])
+                    > settings.tolerance*f64::max(1.0, f64::abs(e)) {
+                    converged = false;
+                }
+                energies[k] = e;
+            }
+            if converged {
+                break;
+            }
+        }
+    }
+    let mut eigenstates: Vec<Eigenstate> = states.into_iter()
+        .zip(energies).map(|(psi, energy)| Eigenstate {energy, psi})
+        .collect();
+    eigenstates.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+    return eigenstates;
+}
+
+/* The lowest settings.count eigenstates of the real part of the
+potential on the N by N grid, found by lobpcg_eigenstates with the
+finite difference Hamiltonian of the given order and boundary on a
+coarser grid of the potential at every N/size-th point, size a side,
+and interpolated back to the N by N grid, where they are normalized.
+The states are good approximations of those on the full grid when they
+vary little over N/size points, as the low states of a smooth potential
+do; the imaginary time method gives them at full resolution, and the
+energies of the two can be compared. */
+pub fn stencil_eigenstates(potential: &[Complex<f32>], size: usize,
+                           order: DifferenceOrder, boundary: StencilBoundary,
+                           settings: &LobpcgSettings
+                           ) -> Result<Vec<Eigenstate>, String> {
+    if size == 0 || size > N || !N.is_multiple_of(size)
+        || !size.is_power_of_two() {
+        return Err(format!("eigenstates can be found on grids of a power of \
+                            two points a side up to {}, not {}", N,
+                           size));
+    }
+    let step: usize = N/size;
+    let mut sampled: Vec<Complex<f32>> = Vec::with_capacity(size*size);
+    for i in 0..size {
+        for j in 0..size {
+            sampled.push(Complex {real: potential[i*step*N + j*step].real,
+                                  imag: 0.0});
+        }
+    }
+    let h: StencilHamiltonian = StencilHamiltonian::new(
+        &sampled, size, size, step as f64, order, boundary)?;
+    let result: LobpcgResult = lobpcg_eigenstates(&h, settings)?;
+    if !result.converged {
+        let worst: f64 = result.states.iter().map(|s| s.residual)
+            .fold(0.0, f64::max);
+        return Err(format!("the eigenstates did not converge in {} \
+                            iterations, with residuals up to {:e}",
+                           result.iterations, worst));
+    }
+    let mut eigenstates: Vec<Eigenstate> = Vec::new();
+    for state in result.states.iter() {
+        let coarse: Vec<Complex<f32>> = state.psi.iter()
+            .map(|z| (*z).into()).collect();
+        let mut psi: Vec<Complex<f32>> = interpolate(&coarse, size, N)?;
+        normalize(&mut psi, 1.0);
+        eigenstates.push(Eigenstate {energy: state.energy, psi});
+    }
+    return Ok(eigenstates);
+}
+
+/* Populations |<phi_n|psi>|^2/<psi|psi> of the eigenstates phi_n in
+psi. These sum to one if the eigenstates span psi. */
+pub fn populations(eigenstates: &[Eigenstate], psi: &[Complex<f32>]
+                   ) -> Vec<f64> {
+    let norm: f64 = norm_squared(psi);
+    return eigenstates.iter()
+        .map(|phi| inner_product(&phi.psi, psi).length_squared()/norm)
+        .collect();
+}
@@ -0,0 +1,37 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::field::*;
+use crate::spectral::*;
+
+/* Local energy densities of psi, returned as the kinetic density
+|grad psi|^2/2, with the gradient computed spectrally and per grid
+spacing as for the momentum, and the potential density
+V|psi|^2 + g/2*|psi|^4, including the interaction energy of the GPE.
+Summed over the grid these give the kinetic and potential plus
+interaction energies of Expectations times the norm. The kinetic
+density is the positive definite form, which differs from the real
+part of conj(psi)*(-laplacian psi)/2 by a divergence, so it shows
+where the wave function varies fastest without taking negative values.
+
+Reference:
+
+Wikipedia - Energy density
+https://en.wikipedia.org/wiki/Energy_density
+*/
+pub fn energy_density(psi: &[Complex<f32>], potential: &[Complex<f32>],
+                      g: f32) -> (Field2D<f32>, Field2D<f32>) {
+    let zero: Complex<f32> = Complex {real: 0.0, imag: 0.0};
+    let mut ddx: Field2D<Complex<f32>> = Field2D::new(N, N, zero);
+    let mut ddy: Field2D<Complex<f32>> = Field2D::new(N, N, zero);
+    spectral_gradient(psi, ddx.as_mut_slice(), ddy.as_mut_slice());
+    let mut kinetic: Field2D<f32> = Field2D::new(N, N, 0.0);
+    let mut potential_density: Field2D<f32> = Field2D::new(N, N, 0.0);
+    for k in 0..N*N {
+        let abs2: f32 = psi[k].length_squared();
+        kinetic.values[k] = 0.5*(ddx.values[k].length_squared()
+                                 + ddy.values[k].length_squared());
+        potential_density.values[k] = potential[k].real*abs2
+            + 0.5*g*abs2*abs2;
+    }
+    return (kinetic, potential_density);
+}
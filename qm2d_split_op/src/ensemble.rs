@@ -0,0 +1,88 @@
+/* Averages of the observables of many runs that differ only in their
+random realization, started with the ensemble subcommand, which runs
+them as the jobs of a sweep over disorder_seed (see sweep.rs and
+disorder.rs). Every column of the observables files is averaged, row by
+row, with the standard error of the mean, and the table is written again
+as each run finishes, so that a partial ensemble can be looked at while
+the rest runs; the n column gives how many runs each row averages. */
+#[derive(Default)]
+pub struct EnsembleAverage {
+    pub names: Vec<String>,
+    rows: Vec<EnsembleRow>,
+}
+
+/* The running mean and sum of squared deviations (Welford's method) of
+every column at one row. */
+struct EnsembleRow {
+    t: f64,
+    count: usize,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl EnsembleAverage {
+    /* Add the rows of a CSV file of observables, with a header of t and
+    the names of the columns. Runs whose columns differ from the first
+    are refused. */
+    pub fn add_csv(&mut self, text: &str) -> Result<(), String> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let names: Vec<String> = lines.next().unwrap_or("").split(',')
+            .skip(1).map(|n| n.trim().to_string()).collect();
+        if self.names.is_empty() && self.rows.is_empty() {
+            self.names = names;
+        } else if names != self.names {
+            return Err("the observables differ from those of the first run"
+                       .to_string());
+        }
+        let mut rows: Vec<Vec<f64>> = Vec::new();
+        for line in lines {
+            let values: Vec<f64> = line.split(',')
+                .map(|v| v.trim().parse::<f64>().unwrap_or(f64::NAN))
+                .collect();
+            if values.len() != self.names.len() + 1 {
+                return Err(format!("expected {} columns, found {}",
+                                   self.names.len() + 1, values.len()));
+            }
+            rows.push(values);
+        }
+        for (k, values) in rows.iter().enumerate() {
+            if k == self.rows.len() {
+                self.rows.push(EnsembleRow {
+                    t: values[0], count: 0,
+                    mean: vec![0.0; self.names.len()],
+                    m2: vec![0.0; self.names.len()]});
+            }
+            let row: &mut EnsembleRow = &mut self.rows[k];
+            row.count += 1;
+            for (c, value) in values[1..].iter().enumerate() {
+                let delta: f64 = value - row.mean[c];
+                row.mean[c] += delta/(row.count as f64);
+                row.m2[c] += delta*(value - row.mean[c]);
+            }
+        }
+        return Ok(());
+    }
+
+    /* The table as CSV: t, n, then each observable and its standard
+    error, which is NaN until a row has two runs. */
+    pub fn to_csv(&self) -> String {
+        let mut table: String = String::from("t,n");
+        for name in self.names.iter() {
+            table += &format!(",{},{}_err", name, name);
+        }
+        table.push('\n');
+        for row in self.rows.iter() {
+            table += &format!("{},{}", row.t, row.count);
+            for (mean, m2) in row.mean.iter().zip(row.m2.iter()) {
+                let error: f64 = if row.count > 1 {
+                    f64::sqrt(m2/(((row.count - 1)*row.count) as f64))
+                } else {
+                    f64::NAN
+                };
+                table += &format!(",{:e},{:e}", mean, error);
+            }
+            table.push('\n');
+        }
+        return table;
+    }
+}
@@ -0,0 +1,103 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::field::*;
+use crate::linalg::*;
+
+/* The grid can also be read as the wave function psi(x1, x2) of two
+distinguishable particles on a line, with x1 = x along rows and
+x2 = y along columns, so that an interaction potential depending on
+x - y couples them. The functions here measure how entangled the two
+particles are. */
+
+/* Reduced density matrix of the particle along the kept axis, tracing
+out the other one, normalized to unit trace. For Axis::X this is
+
+    rho(x, x') = sum_y psi(x, y) psi*(x', y),
+
+stored row by row as an N by N matrix. */
+pub fn reduced_density_matrix(psi: &[Complex<f32>], keep: Axis
+                              ) -> Vec<Complex<f64>> {
+    let zero: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    let mut rho = vec![zero; N*N];
+    let element = |line: usize, k: usize| -> Complex<f64> {
+        return match keep {
+            Axis::X => psi[k*N + line].into(),
+            Axis::Y => psi[line*N + k].into(),
+        };
+    };
+    let mut trace: f64 = 0.0;
+    for a in 0..N {
+        for b in a..N {
+            let mut s: Complex<f64> = zero;
+            for k in 0..N {
+                s = s + element(a, k)*element(b, k).conj();
+            }
+            rho[a*N + b] = s;
+            rho[b*N + a] = s.conj();
+        }
+        trace += rho[a*N + a].real;
+    }
+    if trace > 0.0 {
+        for r in rho.iter_mut() {
+            *r = r.scale(1.0/trace);
+        }
+    }
+    return rho;
+}
+
+/* Squared Schmidt coefficients of psi, in decreasing order. These are
+the squared singular values of psi viewed as an N by N matrix, found
+as the eigenvalues of the reduced density matrix, which are the same
+whichever particle is traced out. */
+pub fn schmidt_coefficients(psi: &[Complex<f32>]) -> Vec<f64> {
+    // Rows of psi are contiguous, so tracing over x is faster.
+    let mut rho: Vec<Complex<f64>> = reduced_density_matrix(psi, Axis::Y);
+    let mut values: Vec<f64> = hermitian_eigenvalues(rho.as_mut_slice(), N);
+    for v in values.iter_mut() {
+        *v = f64::max(*v, 0.0);
+    }
+    return values;
+}
+
+/* Measures of entanglement between the two particles. The entropy is
+zero and the Schmidt number one for a product state psi(x, y) =
+f(x) g(y).
+
+References:
+
+Wikipedia - Von Neumann entropy
+https://en.wikipedia.org/wiki/Von_Neumann_entropy
+
+Wikipedia - Schmidt decomposition
+https://en.wikipedia.org/wiki/Schmidt_decomposition
+*/
+pub struct Entanglement {
+    pub entropy: f64, // von Neumann entropy -tr(rho ln rho)
+    pub purity: f64, // tr(rho^2)
+    pub schmidt_number: f64, // 1/purity, the effective number of terms
+}
+
+impl Entanglement {
+    pub fn from_schmidt_coefficients(coefficients: &[f64]) -> Entanglement {
+        let total: f64 = coefficients.iter().sum();
+        let mut entropy: f64 = 0.0;
+        let mut purity: f64 = 0.0;
+        for &c in coefficients {
+            let p: f64 = c/total;
+            if p > 0.0 {
+                entropy -= p*f64::ln(p);
+            }
+            purity += p*p;
+        }
+        return Entanglement {entropy, purity,
+                             schmidt_number: 1.0/purity};
+    }
+
+    pub fn names() -> [&'static str; 3] {
+        return ["entropy", "purity", "schmidt_number"];
+    }
+
+    pub fn values(&self) -> [f64; 3] {
+        return [self.entropy, self.purity, self.schmidt_number];
+    }
+}
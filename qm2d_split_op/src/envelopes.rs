@@ -0,0 +1,172 @@
+use crate::complex::*;
+use crate::potentials::*;
+
+/* Time envelope f(t) that multiplies a static potential. */
+#[derive(Clone, Debug)]
+pub enum Envelope {
+    Constant(f32),
+    LinearRamp {t0: f32, t1: f32}, // 0 before t0 rising to 1 at t1
+    Sin2 {t0: f32, duration: f32}, // sin^2 pulse, 0 outside
+    GaussianPulse {t0: f32, width: f32}, // width is the standard deviation
+    SquarePulse {t0: f32, t1: f32}, // 1 for t0 <= t < t1, otherwise 0
+    Periodic {omega: f32, phase: f32}, // cos(omega*t + phase)
+    Product(Vec<Envelope>),
+}
+
+impl Envelope {
+    pub fn value(&self, t: f32) -> f32 {
+        match self {
+            Envelope::Constant(a) => return *a,
+            Envelope::LinearRamp {t0, t1} => {
+                if t <= *t0 {
+                    return 0.0;
+                } else if t >= *t1 {
+                    return 1.0;
+                }
+                return (t - t0)/(t1 - t0);
+            },
+            Envelope::Sin2 {t0, duration} => {
+                if t < *t0 || t > t0 + duration {
+                    return 0.0;
+                }
+                let s: f32 = f32::sin(std::f32::consts::PI*(t - t0)/duration);
+                return s*s;
+            },
+            Envelope::GaussianPulse {t0, width} => {
+                return f32::exp(-0.5*(t - t0)*(t - t0)/(width*width));
+            },
+            Envelope::SquarePulse {t0, t1} => {
+                return if t >= *t0 && t < *t1 {1.0} else {0.0};
+            },
+            Envelope::Periodic {omega, phase} => {
+                return f32::cos(omega*t + phase);
+            },
+            Envelope::Product(factors) => {
+                let mut val: f32 = 1.0;
+                for f in factors {
+                    val *= f.value(t);
+                }
+                return val;
+            },
+        };
+    }
+
+    pub fn is_constant(&self) -> bool {
+        return match self {
+            Envelope::Constant(_) => true,
+            Envelope::Product(factors) => factors.iter().all(|f| f.is_constant()),
+            _ => false,
+        };
+    }
+
+    /* Parse an envelope written as a product of factors, such as
+
+        ramp(0, 50)*periodic(0.2, 0)
+
+    where each factor is a number or one of ramp(t0, t1),
+    sin2(t0, duration), gaussian(t0, width), square(t0, t1) and
+    periodic(omega, phase). */
+    pub fn parse(s: &str) -> Result<Envelope, String> {
+        let mut factors = std::vec::Vec::<Envelope>::new();
+        for factor in s.split('*') {
+            let factor: &str = factor.trim();
+            if let Ok(a) = factor.parse::<f32>() {
+                factors.push(Envelope::Constant(a));
+                continue;
+            }
+            let (name, rest) = match factor.split_once('(') {
+                Some(nr) => nr,
+                None => return Err(format!("invalid envelope '{}'", factor)),
+            };
+            let args_str: &str = match rest.strip_suffix(')') {
+                Some(a) => a,
+                None => return Err(format!("expected ')' in '{}'", factor)),
+            };
+            let mut args = std::vec::Vec::<f32>::new();
+            for a in args_str.split(',') {
+                match a.trim().parse::<f32>() {
+                    Ok(val) => args.push(val),
+                    Err(_) => return Err(format!(
+                        "invalid number '{}' in '{}'", a.trim(), factor)),
+                };
+            }
+            if args.len() != 2 {
+                return Err(format!("{} takes 2 arguments", name.trim()));
+            }
+            factors.push(match name.trim() {
+                "ramp" => Envelope::LinearRamp {t0: args[0], t1: args[1]},
+                "sin2" => Envelope::Sin2 {t0: args[0], duration: args[1]},
+                "gaussian" => Envelope::GaussianPulse {t0: args[0],
+                                                       width: args[1]},
+                "square" => Envelope::SquarePulse {t0: args[0], t1: args[1]},
+                "periodic" => Envelope::Periodic {omega: args[0],
+                                                  phase: args[1]},
+                other => return Err(format!("unknown envelope '{}'", other)),
+            });
+        }
+        if factors.len() == 1 {
+            return Ok(factors.pop().unwrap());
+        }
+        return Ok(Envelope::Product(factors));
+    }
+}
+
+/* A static potential multiplied by a time envelope,
+V(x, y, t) = f(t)*V(x, y). The static part is rasterized once when
+this is created, so refilling the potential each step only costs a
+multiplication per grid point. */
+pub struct Modulated {
+    pub potential: GridPotential,
+    pub envelope: Envelope,
+}
+
+impl Modulated {
+    pub fn new(potential: &dyn Potential, envelope: Envelope) -> Modulated {
+        return Modulated {potential: GridPotential::rasterize(potential),
+                          envelope};
+    }
+}
+
+impl TimeDependentPotential for Modulated {
+    fn value_at(&self, x: f32, y: f32, t: f32) -> f32 {
+        return self.envelope.value(t)*self.potential.value(x, y);
+    }
+
+    fn is_time_dependent(&self) -> bool {
+        return !self.envelope.is_constant();
+    }
+
+    fn fill_at(&self, potential: &mut [Complex<f32>], t: f32) {
+        let f: f32 = self.envelope.value(t);
+        for i in 0..self.potential.values.len() {
+            potential[i].real = f*self.potential.values[i];
+        }
+    }
+}
+
+/* A time dependent potential multiplied by a time envelope,
+V(x, y, t) = f(t)*V(x, y, t), which is rasterized again at each time,
+for the potentials Modulated would freeze at t = 0. */
+pub struct ModulatedInTime {
+    pub potential: Box<dyn TimeDependentPotential>,
+    pub envelope: Envelope,
+}
+
+impl TimeDependentPotential for ModulatedInTime {
+    fn value_at(&self, x: f32, y: f32, t: f32) -> f32 {
+        return self.envelope.value(t)*self.potential.value_at(x, y, t);
+    }
+
+    fn is_time_dependent(&self) -> bool {
+        return self.potential.is_time_dependent()
+            || !self.envelope.is_constant();
+    }
+
+    fn fill_at(&self, potential: &mut [Complex<f32>], t: f32) {
+        self.potential.fill_at(potential, t);
+        let f: f32 = self.envelope.value(t);
+        for v in potential.iter_mut() {
+            v.real *= f;
+        }
+    }
+}
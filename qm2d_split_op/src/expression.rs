@@ -0,0 +1,526 @@
+use crate::potentials::*;
+
+/* Parser for arithmetic expressions in x, y and t, so that potentials
+can be given as strings at runtime, for example
+
+    0.1*exp(-((x - 0.5)^2 + (y - 0.6)^2)/0.002) + 2*(y > 0.9)
+
+Supported are the operators + - * / ^ (right associative), the
+comparisons < > <= >= (which give 1.0 or 0.0), parentheses,
+the constants pi and e, and the functions sin, cos, tan, exp, log,
+sqrt, abs, tanh, floor, atan2, min and max, and, in a
+ParametricExpression, the names of its parameters. The parsed expression
+tree is compiled into nested closures, so that evaluation does not
+need to walk the tree or look up names.
+
+Reference:
+
+Wikipedia - Recursive descent parser
+https://en.wikipedia.org/wiki/Recursive_descent_parser
+*/
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Op(char),
+    Le, Ge,
+    LParen, RParen, Comma,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = std::vec::Vec::<Token>::new();
+    let mut i: usize = 0;
+    while i < chars.len() {
+        let c: char = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start: usize = i;
+            while i < chars.len() && (chars[i].is_ascii_digit()
+                                      || chars[i] == '.') {
+                i += 1;
+            }
+            /* Exponent, as in 1e-3 */
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E')
+                && i + 1 < chars.len() && (chars[i+1].is_ascii_digit()
+                    || ((chars[i+1] == '-' || chars[i+1] == '+')
+                        && i + 2 < chars.len()
+                        && chars[i+2].is_ascii_digit())) {
+                i += 2;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            match text.parse::<f32>() {
+                Ok(val) => tokens.push(Token::Number(val)),
+                Err(_) => return Err(format!(
+                    "invalid number '{}' at position {}", text, start)),
+            };
+        } else if c.is_alphabetic() || c == '_' {
+            let start: usize = i;
+            while i < chars.len() && (chars[i].is_alphanumeric()
+                                      || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if (c == '<' || c == '>')
+            && i + 1 < chars.len() && chars[i+1] == '=' {
+            tokens.push(if c == '<' {Token::Le} else {Token::Ge});
+            i += 2;
+        } else if "+-*/^<>".contains(c) {
+            tokens.push(Token::Op(c));
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else {
+            return Err(format!("unexpected character '{}' at position {}",
+                               c, i));
+        }
+    }
+    return Ok(tokens);
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f32),
+    X, Y, T,
+    Parameter(usize), // the index of a named parameter
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    parameters: &'a [&'a str], // names beyond x, y and t
+}
+
+/* Grammar, from lowest to highest precedence:
+
+    comparison = sum (('<' | '>' | '<=' | '>=') sum)?
+    sum = product (('+' | '-') product)*
+    product = unary (('*' | '/') unary)*
+    unary = '-' unary | power
+    power = atom ('^' unary)?
+    atom = number | name | name '(' comparison (',' comparison)* ')'
+         | '(' comparison ')'
+*/
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.position);
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        return t;
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        let op: char = match self.peek() {
+            Some(Token::Op('<')) => '<',
+            Some(Token::Op('>')) => '>',
+            Some(Token::Le) => 'l',
+            Some(Token::Ge) => 'g',
+            _ => return Ok(left),
+        };
+        self.next();
+        let right = self.sum()?;
+        return Ok(Expr::Binary(op, Box::new(left), Box::new(right)));
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        while let Some(Token::Op(c)) = self.peek() {
+            let c: char = *c;
+            if c != '+' && c != '-' {
+                break;
+            }
+            self.next();
+            let right = self.product()?;
+            left = Expr::Binary(c, Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Op(c)) = self.peek() {
+            let c: char = *c;
+            if c != '*' && c != '/' {
+                break;
+            }
+            self.next();
+            let right = self.unary()?;
+            left = Expr::Binary(c, Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.next();
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if let Some(Token::Op('+')) = self.peek() {
+            self.next();
+            return self.unary();
+        }
+        return self.power();
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.atom()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.next();
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(exponent)));
+        }
+        return Ok(base);
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(val)) => return Ok(Expr::Number(val)),
+            Some(Token::LParen) => {
+                let e = self.comparison()?;
+                match self.next() {
+                    Some(Token::RParen) => return Ok(e),
+                    _ => return Err("expected ')'".to_string()),
+                };
+            },
+            Some(Token::Name(name)) => {
+                if let Some(Token::LParen) = self.peek() {
+                    self.next();
+                    let mut args = std::vec::Vec::<Expr>::new();
+                    loop {
+                        args.push(self.comparison()?);
+                        match self.next() {
+                            Some(Token::Comma) => continue,
+                            Some(Token::RParen) => break,
+                            _ => return Err(format!(
+                                "expected ',' or ')' in call to {}", name)),
+                        };
+                    }
+                    return check_call(name, args);
+                }
+                return match name.as_str() {
+                    "x" => Ok(Expr::X),
+                    "y" => Ok(Expr::Y),
+                    "t" => Ok(Expr::T),
+                    "pi" => Ok(Expr::Number(std::f32::consts::PI)),
+                    "e" => Ok(Expr::Number(std::f32::consts::E)),
+                    _ if self.parameters.contains(&name.as_str()) => Ok(
+                        Expr::Parameter(self.parameters.iter()
+                                        .position(|p| *p == name).unwrap())),
+                    _ => Err(format!("unknown variable '{}'", name)),
+                };
+            },
+            Some(t) => return Err(format!("unexpected token {:?}", t)),
+            None => return Err("unexpected end of expression".to_string()),
+        };
+    }
+}
+
+fn check_call(name: String, args: Vec<Expr>) -> Result<Expr, String> {
+    let arg_count: usize = match name.as_str() {
+        "sin" | "cos" | "tan" | "exp" | "log" | "sqrt" | "abs"
+            | "tanh" | "floor" => 1,
+        "atan2" | "min" | "max" => 2,
+        _ => return Err(format!("unknown function '{}'", name)),
+    };
+    if args.len() != arg_count {
+        return Err(format!("{} takes {} argument(s) but {} were given",
+                           name, arg_count, args.len()));
+    }
+    return Ok(Expr::Call(name, args));
+}
+
+type Compiled = Box<dyn Fn(f32, f32, f32) -> f32 + Send + Sync>;
+
+/* Compile the tree, with the given values of its parameters. */
+fn compile(e: &Expr, values: &[f32]) -> Compiled {
+    match e {
+        Expr::Number(val) => {
+            let val: f32 = *val;
+            return Box::new(move |_, _, _| val);
+        },
+        Expr::X => return Box::new(|x, _, _| x),
+        Expr::Y => return Box::new(|_, y, _| y),
+        Expr::T => return Box::new(|_, _, t| t),
+        Expr::Parameter(k) => {
+            let val: f32 = values[*k];
+            return Box::new(move |_, _, _| val);
+        },
+        Expr::Neg(a) => {
+            let a = compile(a, values);
+            return Box::new(move |x, y, t| -a(x, y, t));
+        },
+        Expr::Binary(op, a, b) => {
+            let a = compile(a, values);
+            let b = compile(b, values);
+            return match op {
+                '+' => Box::new(move |x, y, t| a(x, y, t) + b(x, y, t)),
+                '-' => Box::new(move |x, y, t| a(x, y, t) - b(x, y, t)),
+                '*' => Box::new(move |x, y, t| a(x, y, t)*b(x, y, t)),
+                '/' => Box::new(move |x, y, t| a(x, y, t)/b(x, y, t)),
+                '^' => Box::new(move |x, y, t| f32::powf(a(x, y, t),
+                                                         b(x, y, t))),
+                '<' => Box::new(move |x, y, t|
+                    if a(x, y, t) < b(x, y, t) {1.0} else {0.0}),
+                '>' => Box::new(move |x, y, t|
+                    if a(x, y, t) > b(x, y, t) {1.0} else {0.0}),
+                'l' => Box::new(move |x, y, t|
+                    if a(x, y, t) <= b(x, y, t) {1.0} else {0.0}),
+                _ => Box::new(move |x, y, t|
+                    if a(x, y, t) >= b(x, y, t) {1.0} else {0.0}),
+            };
+        },
+        Expr::Call(name, args) => {
+            let a = compile(&args[0], values);
+            if args.len() == 2 {
+                let b = compile(&args[1], values);
+                return match name.as_str() {
+                    "atan2" => Box::new(move |x, y, t|
+                        f32::atan2(a(x, y, t), b(x, y, t))),
+                    "min" => Box::new(move |x, y, t|
+                        f32::min(a(x, y, t), b(x, y, t))),
+                    _ => Box::new(move |x, y, t|
+                        f32::max(a(x, y, t), b(x, y, t))),
+                };
+            }
+            let f: fn(f32) -> f32 = match name.as_str() {
+                "sin" => f32::sin,
+                "cos" => f32::cos,
+                "tan" => f32::tan,
+                "exp" => f32::exp,
+                "log" => f32::ln,
+                "sqrt" => f32::sqrt,
+                "abs" => f32::abs,
+                "tanh" => f32::tanh,
+                _ => f32::floor,
+            };
+            return Box::new(move |x, y, t| f(a(x, y, t)));
+        },
+    };
+}
+
+/* A parsed and compiled expression f(x, y, t). */
+pub struct Expression {
+    pub source: String,
+    uses_t: bool,
+    f: Compiled,
+}
+
+fn uses_t(e: &Expr) -> bool {
+    return match e {
+        Expr::T => true,
+        Expr::Neg(a) => uses_t(a),
+        Expr::Binary(_, a, b) => uses_t(a) || uses_t(b),
+        Expr::Call(_, args) => args.iter().any(uses_t),
+        _ => false,
+    };
+}
+
+fn parse_tree(s: &str, parameters: &[&str]) -> Result<Expr, String> {
+    let mut parser = Parser {tokens: tokenize(s)?, position: 0, parameters};
+    let e = parser.comparison()?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("unexpected token {:?} after expression",
+                           parser.tokens[parser.position]));
+    }
+    return Ok(e);
+}
+
+impl Expression {
+    pub fn parse(s: &str) -> Result<Expression, String> {
+        let e = parse_tree(s, &[])?;
+        return Ok(Expression {source: s.to_string(),
+                              uses_t: uses_t(&e), f: compile(&e, &[])});
+    }
+
+    pub fn eval(&self, x: f32, y: f32, t: f32) -> f32 {
+        return (self.f)(x, y, t);
+    }
+
+    /* Whether the expression depends on the time t, in which case
+    it needs to be re-evaluated as the simulation runs. */
+    pub fn is_time_dependent(&self) -> bool {
+        return self.uses_t;
+    }
+}
+
+/* As a static potential the expression is evaluated at t = 0. */
+impl Potential for Expression {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        return self.eval(x, y, 0.0);
+    }
+}
+
+impl TimeDependentPotential for Expression {
+    fn value_at(&self, x: f32, y: f32, t: f32) -> f32 {
+        return self.eval(x, y, t);
+    }
+
+    fn is_time_dependent(&self) -> bool {
+        return self.uses_t;
+    }
+}
+
+fn depends_on(e: &Expr, k: usize) -> bool {
+    return match e {
+        Expr::Parameter(j) => *j == k,
+        Expr::Neg(a) => depends_on(a, k),
+        Expr::Binary(_, a, b) => depends_on(a, k) || depends_on(b, k),
+        Expr::Call(_, args) => args.iter().any(|a| depends_on(a, k)),
+        _ => false,
+    };
+}
+
+/* The derivative of e by the parameter k, as a tree. Comparisons and
+floor are taken as constant, which they are but at their steps, and abs,
+min and max take the slope of the side they are on. */
+fn derivative(e: &Expr, k: usize) -> Expr {
+    if !depends_on(e, k) {
+        return Expr::Number(0.0);
+    }
+    let bin = |op: char, a: Expr, b: Expr| -> Expr {
+        return Expr::Binary(op, Box::new(a), Box::new(b));
+    };
+    let call = |name: &str, args: Vec<Expr>| -> Expr {
+        return Expr::Call(name.to_string(), args);
+    };
+    return match e {
+        Expr::Neg(a) => Expr::Neg(Box::new(derivative(a, k))),
+        Expr::Binary(op, a, b) => {
+            let (a, b): (&Expr, &Expr) = (a, b);
+            let (da, db): (Expr, Expr) = (derivative(a, k), derivative(b, k));
+            match op {
+                '+' | '-' => bin(*op, da, db),
+                '*' => bin('+', bin('*', da, b.clone()),
+                           bin('*', a.clone(), db)),
+                '/' => bin('-', bin('/', da, b.clone()),
+                           bin('/', bin('*', a.clone(), db),
+                               bin('*', b.clone(), b.clone()))),
+                // b a^(b - 1) a' + a^b log(a) b', leaving out the terms of
+                // a side that does not depend on the parameter, so that
+                // a negative base with a constant exponent is fine.
+                '^' => {
+                    let mut terms: Vec<Expr> = Vec::new();
+                    if depends_on(a, k) {
+                        terms.push(bin('*', bin('*', b.clone(), bin(
+                            '^', a.clone(), bin('-', b.clone(),
+                                                Expr::Number(1.0)))), da));
+                    }
+                    if depends_on(b, k) {
+                        terms.push(bin('*', bin('*', e.clone(), call(
+                            "log", vec![a.clone()])), db));
+                    }
+                    terms.into_iter().reduce(|x, y| bin('+', x, y)).unwrap()
+                },
+                _ => Expr::Number(0.0),
+            }
+        },
+        Expr::Call(name, args) => {
+            let a: &Expr = &args[0];
+            let da: Expr = derivative(a, k);
+            match name.as_str() {
+                "sin" => bin('*', call("cos", vec![a.clone()]), da),
+                "cos" => Expr::Neg(Box::new(
+                    bin('*', call("sin", vec![a.clone()]), da))),
+                "tan" => {
+                    let c: Expr = call("cos", vec![a.clone()]);
+                    bin('/', da, bin('*', c.clone(), c))
+                },
+                "exp" => bin('*', e.clone(), da),
+                "log" => bin('/', da, a.clone()),
+                "sqrt" => bin('/', da, bin('*', Expr::Number(2.0), e.clone())),
+                "abs" => bin('*', bin('-', bin('>', a.clone(),
+                                               Expr::Number(0.0)),
+                                      bin('<', a.clone(), Expr::Number(0.0))),
+                             da),
+                "tanh" => bin('*', bin('-', Expr::Number(1.0),
+                                       bin('*', e.clone(), e.clone())), da),
+                "atan2" => {
+                    let b: &Expr = &args[1];
+                    let db: Expr = derivative(b, k);
+                    bin('/', bin('-', bin('*', b.clone(), da),
+                                 bin('*', a.clone(), db)),
+                        bin('+', bin('*', a.clone(), a.clone()),
+                            bin('*', b.clone(), b.clone())))
+                },
+                "min" | "max" => {
+                    let b: &Expr = &args[1];
+                    let db: Expr = derivative(b, k);
+                    let first: Expr = if name == "min" {
+                        bin('<', a.clone(), b.clone())
+                    } else {
+                        bin('>', a.clone(), b.clone())
+                    };
+                    let second: Expr = bin('-', Expr::Number(1.0),
+                                           first.clone());
+                    bin('+', bin('*', first, da), bin('*', second, db))
+                },
+                _ => Expr::Number(0.0),
+            }
+        },
+        _ => Expr::Number(1.0), // the parameter itself
+    };
+}
+
+/* An expression in x, y, t and named parameters, such as
+
+    a*((x - 0.5)^2 + (y - 0.5)^2) + b*exp(-(x - c)^2/0.001)
+
+which gives an Expression for any values of the parameters, and one for
+its derivative by each, found by differentiating the tree. */
+pub struct ParametricExpression {
+    pub source: String,
+    pub parameters: Vec<String>,
+    tree: Expr,
+}
+
+impl ParametricExpression {
+    pub fn parse(s: &str, parameters: &[&str]
+                 ) -> Result<ParametricExpression, String> {
+        for p in parameters.iter() {
+            if ["x", "y", "t", "pi", "e"].contains(p) {
+                return Err(format!("'{}' cannot be the name of a parameter",
+                                   p));
+            }
+        }
+        return Ok(ParametricExpression {
+            source: s.to_string(),
+            parameters: parameters.iter().map(|p| p.to_string()).collect(),
+            tree: parse_tree(s, parameters)?});
+    }
+
+    /* The expression with the parameters set to values. */
+    pub fn with_values(&self, values: &[f32]) -> Expression {
+        assert_eq!(values.len(), self.parameters.len());
+        return Expression {source: self.source.clone(),
+                           uses_t: uses_t(&self.tree),
+                           f: compile(&self.tree, values)};
+    }
+
+    /* Its derivative by the parameter k at the values given. */
+    pub fn derivative(&self, k: usize, values: &[f32]) -> Expression {
+        assert_eq!(values.len(), self.parameters.len());
+        let d: Expr = derivative(&self.tree, k);
+        return Expression {
+            source: format!("d/d{} ({})", self.parameters[k], self.source),
+            uses_t: uses_t(&d), f: compile(&d, values)};
+    }
+}
@@ -1,12 +1,12 @@
 use crate::constants::*;
 use crate::complex::*;
+use crate::parallel::*;
+use crate::log::*;
 
-pub fn square_transpose_in_place<T: Copy>(array: &mut [Complex<T>], n: usize) {
+pub fn square_transpose_in_place<T: Copy>(array: &mut [T], n: usize) {
     for i in 0..n {
         for j in i+1..n {
-            let tmp = array[i*n + j];
-            array[i*n + j] = array[j*n + i];
-            array[j*n + i] = tmp;
+            array.swap(i*n + j, j*n + i);
         }
     }
 }
@@ -14,7 +14,7 @@ pub fn square_transpose_in_place<T: Copy>(array: &mut [Complex<T>], n: usize) {
 /* Reverse bit sort an array, where the size of the array
 must be a power of two.
 */
-fn reverse_bit_sort<T: Copy>(array: &mut [Complex<T>], n: usize) {
+pub fn reverse_bit_sort<T: Copy>(array: &mut [Complex<T>], n: usize) {
     let mut u: usize;
     let mut d: usize;
     let mut rev: usize;
@@ -28,9 +28,7 @@ fn reverse_bit_sort<T: Copy>(array: &mut [Complex<T>], n: usize) {
             d >>= 1;
         }
         if rev >= i {
-            let tmp = array[i];
-            array[i] = array[rev];
-            array[rev] = tmp;
+            array.swap(i, rev);
         } 
     }
 }
@@ -81,6 +79,61 @@ pub fn base_f32_fft_in_place(array: &mut [Complex<f32>],
     }
 }
 
+/* base_f32_fft_in_place for an array of doubles. */
+pub fn base_f64_fft_in_place(array: &mut [Complex<f64>],
+                             size: usize, is_inverse: bool) {
+    reverse_bit_sort(array, size);
+    let sgn: f64 = if is_inverse {-1.0} else {1.0};
+    let mut block_size: usize = 2;
+    while block_size <= size {
+        let s: f64 = if is_inverse && block_size == size
+            {1.0/(size as f64)} else {1.0};
+        let mut j: usize = 0;
+        while j < size {
+            for i in 0..block_size/2 {
+                let e: Complex<f64> = Complex {
+                    real: f64::cos(2.0*std::f64::consts::PI
+                                   *(i as f64)/(block_size as f64)),
+                    imag: sgn*f64::sin(2.0*std::f64::consts::PI
+                                       *(i as f64)/(block_size as f64)),
+                };
+                let even: Complex<f64> = array[j + i];
+                let odd: Complex<f64> = array[j + i + block_size/2];
+                array[j + i] = (even + odd*e).scale(s);
+                array[j + i + block_size/2] = (even - odd*e).scale(s);
+            }
+            j += block_size;
+        }
+        block_size *= 2;
+    }
+}
+
+/* Type I discrete sine transform of the values v_1 .. v_{n-1} in
+array[1..n], S_k = sum_j v_j sin(pi j k/n) for k = 1 .. n-1, found from
+the fft of their odd extension to 2n values, whose k-th element is
+2i S_k. array[0] is on the boundary, where the sines vanish, and is set
+to zero. n must be a power of two. Applied twice the transform gives
+the values back times n/2.
+
+Reference:
+
+Wikipedia - Discrete sine transform
+https://en.wikipedia.org/wiki/Discrete_sine_transform#DST-I
+*/
+pub fn dst1_in_place(array: &mut [f64], n: usize) {
+    let mut extended: Vec<Complex<f64>> = vec![Complex {real: 0.0, imag: 0.0};
+                                               2*n];
+    for j in 1..n {
+        extended[j].real = array[j];
+        extended[2*n - j].real = -array[j];
+    }
+    base_f64_fft_in_place(&mut extended, 2*n, false);
+    array[0] = 0.0;
+    for k in 1..n {
+        array[k] = 0.5*extended[k].imag;
+    }
+}
+
 pub fn fft_in_place(array: &mut [Complex<f32>], size: usize) {
     base_f32_fft_in_place(array, size, false);
 }
@@ -89,6 +142,54 @@ pub fn ifft_in_place(array: &mut [Complex<f32>], size: usize) {
     base_f32_fft_in_place(array, size, true);
 }
 
+/* Threads that transform groups of rows for horizontal_square_fft,
+each with a buffer for its group. They are started on first use and
+kept for the rest of the program, so that transforms after the first
+neither start threads nor allocate memory. */
+struct RowWorkers {
+    buffers: Vec<std::sync::Arc<std::sync::Mutex<Vec<Complex<f32>>>>>,
+    jobs: Vec<std::sync::mpsc::SyncSender<bool>>,
+    done: std::sync::mpsc::Receiver<()>,
+}
+
+static ROW_WORKERS: std::sync::OnceLock<std::sync::Mutex<RowWorkers>>
+    = std::sync::OnceLock::new();
+
+impl RowWorkers {
+    fn start() -> RowWorkers {
+        let (done_tx, done) = std::sync::mpsc::sync_channel::<()>(TH_COUNT);
+        let mut buffers = std::vec::Vec::<
+            std::sync::Arc<std::sync::Mutex<Vec<Complex<f32>>>>
+            >::with_capacity(TH_COUNT);
+        let mut jobs = std::vec::Vec::<std::sync::mpsc::SyncSender<bool>>
+            ::with_capacity(TH_COUNT);
+        for _ in 0..TH_COUNT {
+            let buffer = std::sync::Arc::new(std::sync::Mutex::new(
+                vec![Complex {real: 0.0, imag: 0.0}; N*N/TH_COUNT]));
+            let (tx, rx) = std::sync::mpsc::sync_channel::<bool>(1);
+            let v = buffer.clone();
+            let done_tx = done_tx.clone();
+            std::thread::spawn(move || {
+                for is_inverse in rx {
+                    let mut v = v.lock().unwrap();
+                    for i in 0..N/TH_COUNT {
+                        if is_inverse {
+                            ifft_in_place(&mut v[i*N..(i+1)*N], N);
+                        } else {
+                            fft_in_place(&mut v[i*N..(i+1)*N], N);
+                        }
+                    }
+                    drop(v);
+                    done_tx.send(()).unwrap();
+                }
+            });
+            buffers.push(buffer);
+            jobs.push(tx);
+        }
+        return RowWorkers {buffers, jobs, done};
+    }
+}
+
 /* Perform the fft algorithm on each row of an array.
 Rows are placed into separate groups, where each group is
 handled by its own thread.
@@ -98,46 +199,103 @@ https://doc.rust-lang.org/book/ch16-01-threads.html
 https://doc.rust-lang.org/book/ch16-02-message-passing.html
 */
 pub fn horizontal_square_fft(is_inverse: bool, array: &mut [Complex<f32>]) {
-    let mut receivers = std::vec::Vec::<
-        std::sync::mpsc::Receiver<std::vec::Vec<Complex<f32>>>
-        >::with_capacity(TH_COUNT);
+    let workers = ROW_WORKERS.get_or_init(
+        || std::sync::Mutex::new(RowWorkers::start())).lock().unwrap();
+    let group: usize = N*N/TH_COUNT;
+    for th_index in 0..TH_COUNT {
+        workers.buffers[th_index].lock().unwrap().copy_from_slice(
+            &array[th_index*group..(th_index + 1)*group]);
+        workers.jobs[th_index].send(is_inverse).unwrap();
+    }
+    for _ in 0..TH_COUNT {
+        workers.done.recv().unwrap();
+    }
     for th_index in 0..TH_COUNT {
-        let (tx, rx) = std::sync::mpsc::channel();
-        let mut v
-            = std::vec::Vec::<Complex<f32>>::with_capacity(N*N/TH_COUNT);
-        for i in th_index*N*N/TH_COUNT..(th_index + 1)*N*N/TH_COUNT {
-            v.push(array[i]);
+        array[th_index*group..(th_index + 1)*group].copy_from_slice(
+            &workers.buffers[th_index].lock().unwrap());
+    }
+}
+
+/* 2D fft of a square N by N array. Each row is transformed, the array
+is transposed so that the columns become rows, these are transformed
+and then the array is transposed back. If use_mt is true and the
+threads feature is enabled the rows are transformed using
+horizontal_square_fft. */
+pub fn base_square_fft2_in_place(array: &mut [Complex<f32>],
+                                 is_inverse: bool, use_mt: bool) {
+    let _span: LogSpan = LogSpan::enter(LogLevel::Trace, "fft", "fft2", &[
+        ("inverse", is_inverse.into())]);
+    for _ in 0..2 {
+        if use_mt && cfg!(feature = "threads") {
+            horizontal_square_fft(is_inverse, array);
+        } else {
+            for i in 0..N {
+                base_f32_fft_in_place(&mut array[i*N..(i+1)*N],
+                                      N, is_inverse);
+            }
         }
-        std::thread::spawn(move || {
-            for i in 0..N/TH_COUNT {
-                if is_inverse {
-                    ifft_in_place(&mut v.as_mut_slice()[i*N..(i+1)*N], N);
-                } else {
-                    fft_in_place(&mut v.as_mut_slice()[i*N..(i+1)*N], N);
-                }
+        square_transpose_in_place(array, N);
+    }
+}
+
+pub fn fft2_in_place(array: &mut [Complex<f32>], use_mt: bool) {
+    base_square_fft2_in_place(array, false, use_mt);
+}
+
+pub fn ifft2_in_place(array: &mut [Complex<f32>], use_mt: bool) {
+    base_square_fft2_in_place(array, true, use_mt);
+}
+
+/* base_square_fft2_in_place for an N by N array of doubles, with the
+rows split between threads by rows_parts_mut. */
+pub fn f64_square_fft2_in_place(array: &mut [Complex<f64>],
+                                is_inverse: bool) {
+    let _span: LogSpan = LogSpan::enter(LogLevel::Trace, "fft", "fft2_f64", &[
+        ("inverse", is_inverse.into())]);
+    for _ in 0..2 {
+        rows_parts_mut(array, N, |rows| {
+            for row in rows.chunks_mut(N) {
+                base_f64_fft_in_place(row, N, is_inverse);
             }
-            tx.send(v).unwrap();
         });
-        receivers.push(rx);
+        square_transpose_in_place(array, N);
     }
-    /* let mut vec_vec = std::vec::Vec::<
-        std::vec::Vec<Complex<f32>>
-        >::with_capacity(TH_COUNT);
-    for i in 0..TH_COUNT {
-        vec_vec.push(std::vec::Vec
-            <Complex<f32>>::with_capacity(N*N/TH_COUNT));
-    }*/
-    let mut th_index: usize = TH_COUNT - 1;
-    while let Some(r) = receivers.pop() {
-        let v = r.recv().unwrap();
-        for i in th_index*N/TH_COUNT..(th_index + 1)*N/TH_COUNT {
-            let i_get = i - th_index*N/TH_COUNT;
-            for j in 0..N {
-                // let transpose_index: usize = j*N + i;
-                // let src_val = v[i_get*N + j];
-                array[N*i + j] = v[i_get*N + j];
-            }
+}
+
+/* Signed frequency of the k-th output element of an fft of the given
+size, so that indices past size/2 correspond to negative frequencies. */
+pub fn fft_frequency(k: usize, size: usize) -> i32 {
+    return if k < size/2 {k as i32} else {(k as i32) - (size as i32)};
+}
+
+/* Momentum, in units of inverse grid spacing, of the plane wave that
+the forward transform places in its k-th output element. Since the
+forward transform uses exp(+i*...), this is minus the usual value
+2*pi*fft_frequency(k, size)/size. */
+pub fn fft_momentum(k: usize, size: usize) -> f32 {
+    return -2.0*std::f32::consts::PI*(fft_frequency(k, size) as f32)
+        /(size as f32);
+}
+
+/* Position of the k-th output element of an fft of the given size once
+the elements are put in order of increasing momentum, running from
+-pi + 2*pi/size to pi with zero momentum at size/2 - 1. This is the
+reordering that numpy.fft.fftshift does by frequency, reversed since
+momentum is minus the frequency. */
+pub fn momentum_order(k: usize, size: usize) -> usize {
+    return ((size/2) as i32 - 1 - fft_frequency(k, size)) as usize;
+}
+
+/* Reorder a square array transformed by fft2_in_place so that the
+momenta along its rows and columns increase with index, as given by
+momentum_order. */
+pub fn fft_shift_momentum<T: Copy>(array: &[T], size: usize) -> Vec<T> {
+    let mut shifted: Vec<T> = array.to_vec();
+    for i in 0..size {
+        let si: usize = momentum_order(i, size);
+        for j in 0..size {
+            shifted[si*size + momentum_order(j, size)] = array[i*size + j];
         }
-        th_index = if th_index == 0 {th_index} else {th_index-1};
     }
+    return shifted;
 }
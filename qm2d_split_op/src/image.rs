@@ -0,0 +1,337 @@
+use crate::constants::*;
+use crate::potentials::*;
+use crate::zlib::*;
+
+/* Grayscale image with intensities in [0, 1]. Rows are stored from the
+top of the image to the bottom, as they appear in a paint program. */
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<f32>,
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    return std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+}
+
+fn luminance(r: f32, g: f32, b: f32) -> f32 {
+    return 0.299*r + 0.587*g + 0.114*b;
+}
+
+fn read_u16_le(bytes: &[u8], offset: usize) -> u16 {
+    return u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+}
+
+fn read_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    return u32::from_le_bytes([bytes[offset], bytes[offset + 1],
+                               bytes[offset + 2], bytes[offset + 3]]);
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> u32 {
+    return u32::from_be_bytes([bytes[offset], bytes[offset + 1],
+                               bytes[offset + 2], bytes[offset + 3]]);
+}
+
+/* Decode an uncompressed 8, 24 or 32 bit BMP file.
+
+Reference:
+
+Wikipedia - BMP file format
+https://en.wikipedia.org/wiki/BMP_file_format
+*/
+pub fn decode_bmp(bytes: &[u8]) -> std::io::Result<GrayImage> {
+    if bytes.len() < 54 || bytes[0] != b'B' || bytes[1] != b'M' {
+        return Err(invalid_data("not a BMP file".to_string()));
+    }
+    let data_offset: usize = read_u32_le(bytes, 10) as usize;
+    let header_size: usize = read_u32_le(bytes, 14) as usize;
+    let width: i32 = read_u32_le(bytes, 18) as i32;
+    let height: i32 = read_u32_le(bytes, 22) as i32;
+    let bits_per_pixel: u16 = read_u16_le(bytes, 28);
+    let compression_method: u32 = read_u32_le(bytes, 30);
+    if compression_method != 0 && compression_method != 3 {
+        return Err(invalid_data(
+            "compressed BMP files are not supported".to_string()));
+    }
+    if bits_per_pixel != 8 && bits_per_pixel != 24 && bits_per_pixel != 32 {
+        return Err(invalid_data(format!(
+            "{} bit BMP files are not supported", bits_per_pixel)));
+    }
+    let w: usize = width.unsigned_abs() as usize;
+    let h: usize = height.unsigned_abs() as usize;
+    if w == 0 || h == 0 {
+        return Err(invalid_data(format!("BMP image is {} by {} pixels",
+                                        w, h)));
+    }
+    let bytes_per_pixel: usize = (bits_per_pixel/8) as usize;
+    let row_size: usize = (bytes_per_pixel*w).div_ceil(4)*4;
+    let end: Option<usize> = row_size.checked_mul(h)
+        .and_then(|size| size.checked_add(data_offset));
+    if end.is_none_or(|end| end > bytes.len()) {
+        return Err(invalid_data("BMP file is truncated".to_string()));
+    }
+    let palette_offset: usize = 14 + header_size;
+    let mut values = std::vec::Vec::<f32>::with_capacity(w*h);
+    for i in 0..h {
+        // Rows are stored bottom to top unless the height is negative.
+        let row: usize = if height > 0 {h - 1 - i} else {i};
+        for j in 0..w {
+            let k: usize = data_offset + row*row_size + bytes_per_pixel*j;
+            let (b, g, r): (u8, u8, u8) = if bits_per_pixel == 8 {
+                let p: usize = palette_offset + 4*(bytes[k] as usize);
+                if p + 2 >= bytes.len() {
+                    return Err(invalid_data(
+                        "BMP palette index out of range".to_string()));
+                }
+                (bytes[p], bytes[p + 1], bytes[p + 2])
+            } else {
+                (bytes[k], bytes[k + 1], bytes[k + 2])
+            };
+            values.push(luminance(r as f32, g as f32, b as f32)/255.0);
+        }
+    }
+    return Ok(GrayImage {width: w, height: h, values});
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p: i16 = a as i16 + b as i16 - c as i16;
+    let pa: i16 = (p - a as i16).abs();
+    let pb: i16 = (p - b as i16).abs();
+    let pc: i16 = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        return a;
+    } else if pb <= pc {
+        return b;
+    }
+    return c;
+}
+
+/* Decode a non-interlaced PNG file of any color type and bit depth.
+Transparency is ignored.
+
+Reference:
+
+W3C - Portable Network Graphics (PNG) Specification
+https://www.w3.org/TR/png/
+*/
+pub fn decode_png(bytes: &[u8]) -> std::io::Result<GrayImage> {
+    let signature: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    if bytes.len() < 8 || bytes[0..8] != signature {
+        return Err(invalid_data("not a PNG file".to_string()));
+    }
+    let mut offset: usize = 8;
+    let mut width: usize = 0;
+    let mut height: usize = 0;
+    let mut bit_depth: usize = 8;
+    let mut color_type: u8 = 0;
+    let mut palette = std::vec::Vec::<u8>::new();
+    let mut compressed = std::vec::Vec::<u8>::new();
+    while offset + 8 <= bytes.len() {
+        let length: usize = read_u32_be(bytes, offset) as usize;
+        let chunk_type: &[u8] = &bytes[offset + 4..offset + 8];
+        let start: usize = offset + 8;
+        if start + length > bytes.len() {
+            return Err(invalid_data("PNG file is truncated".to_string()));
+        }
+        let chunk: &[u8] = &bytes[start..start + length];
+        if chunk_type == b"IHDR" {
+            if length != 13 {
+                return Err(invalid_data(format!(
+                    "PNG header is {} bytes long, not 13", length)));
+            }
+            width = read_u32_be(chunk, 0) as usize;
+            height = read_u32_be(chunk, 4) as usize;
+            bit_depth = chunk[8] as usize;
+            color_type = chunk[9];
+            if chunk[12] != 0 {
+                return Err(invalid_data(
+                    "interlaced PNG files are not supported".to_string()));
+            }
+        } else if chunk_type == b"PLTE" {
+            palette.extend_from_slice(chunk);
+        } else if chunk_type == b"IDAT" {
+            compressed.extend_from_slice(chunk);
+        } else if chunk_type == b"IEND" {
+            break;
+        }
+        offset = start + length + 4; // skip the CRC
+    }
+    if width == 0 || height == 0 {
+        return Err(invalid_data(format!("PNG image is {} by {} pixels",
+                                        width, height)));
+    }
+    let (channels, depths): (usize, &[usize]) = match color_type {
+        0 => (1, &[1, 2, 4, 8, 16]),
+        2 => (3, &[8, 16]),
+        3 => (1, &[1, 2, 4, 8]),
+        4 => (2, &[8, 16]),
+        6 => (4, &[8, 16]),
+        _ => return Err(invalid_data(format!(
+            "invalid PNG color type {}", color_type))),
+    };
+    if !depths.contains(&bit_depth) {
+        return Err(invalid_data(format!(
+            "invalid PNG bit depth {} for color type {}", bit_depth,
+            color_type)));
+    }
+    let raw: Vec<u8> = match zlib_decompress(&compressed) {
+        Ok(raw) => raw,
+        Err(e) => return Err(invalid_data(format!("PNG: {}", e))),
+    };
+    let bits_per_pixel: usize = channels*bit_depth;
+    let stride: usize = (bits_per_pixel*width).div_ceil(8);
+    let filter_step: usize = usize::max(1, bits_per_pixel/8);
+    let size: Option<usize> = (stride + 1).checked_mul(height);
+    if size.is_none_or(|size| raw.len() < size) {
+        return Err(invalid_data("PNG image data is truncated".to_string()));
+    }
+    // Undo the per-row filters.
+    let mut pixels = vec![0u8; height*stride];
+    for i in 0..height {
+        let filter: u8 = raw[i*(stride + 1)];
+        for k in 0..stride {
+            let x: u8 = raw[i*(stride + 1) + 1 + k];
+            let a: u8 = if k >= filter_step {
+                pixels[i*stride + k - filter_step]} else {0};
+            let b: u8 = if i > 0 {pixels[(i - 1)*stride + k]} else {0};
+            let c: u8 = if i > 0 && k >= filter_step {
+                pixels[(i - 1)*stride + k - filter_step]} else {0};
+            pixels[i*stride + k] = match filter {
+                0 => x,
+                1 => x.wrapping_add(a),
+                2 => x.wrapping_add(b),
+                3 => x.wrapping_add(((a as u16 + b as u16)/2) as u8),
+                4 => x.wrapping_add(paeth(a, b, c)),
+                _ => return Err(invalid_data(format!(
+                    "invalid PNG filter type {}", filter))),
+            };
+        }
+    }
+    // Read the samples of each pixel, scaled to [0, 1].
+    let max_sample: f32 = ((1u32 << bit_depth) - 1) as f32;
+    let sample = |row: usize, index: usize| -> u32 {
+        let bit: usize = index*bit_depth;
+        let byte: usize = row*stride + bit/8;
+        if bit_depth == 16 {
+            return ((pixels[byte] as u32) << 8) | pixels[byte + 1] as u32;
+        } else if bit_depth == 8 {
+            return pixels[byte] as u32;
+        }
+        let shift: usize = 8 - bit_depth - bit % 8;
+        return ((pixels[byte] >> shift) as u32) & ((1 << bit_depth) - 1);
+    };
+    let mut values = std::vec::Vec::<f32>::with_capacity(width*height);
+    for i in 0..height {
+        for j in 0..width {
+            let val: f32 = match color_type {
+                0 | 4 => (sample(i, channels*j) as f32)/max_sample,
+                2 | 6 => luminance(sample(i, channels*j) as f32,
+                                   sample(i, channels*j + 1) as f32,
+                                   sample(i, channels*j + 2) as f32)
+                    /max_sample,
+                _ => {
+                    let p: usize = 3*(sample(i, j) as usize);
+                    if p + 2 >= palette.len() {
+                        return Err(invalid_data(
+                            "PNG palette index out of range".to_string()));
+                    }
+                    luminance(palette[p] as f32, palette[p + 1] as f32,
+                              palette[p + 2] as f32)/255.0
+                },
+            };
+            values.push(val);
+        }
+    }
+    return Ok(GrayImage {width, height, values});
+}
+
+/* Load a PNG or BMP file, choosing the decoder from the file contents. */
+pub fn load_image(filename: &str) -> std::io::Result<GrayImage> {
+    let bytes: Vec<u8> = std::fs::read(filename)?;
+    if bytes.len() >= 2 && bytes[0] == b'B' && bytes[1] == b'M' {
+        return decode_bmp(&bytes);
+    }
+    return decode_png(&bytes);
+}
+
+/* Blur the N by N array with a Gaussian of standard deviation sigma,
+in grid points, using periodic boundaries. */
+fn gaussian_smooth(values: &mut [f32], sigma: f32) {
+    let radius: i64 = f32::ceil(3.0*sigma) as i64;
+    let mut kernel = std::vec::Vec::<f32>::new();
+    let mut total: f32 = 0.0;
+    for k in -radius..=radius {
+        let w: f32 = f32::exp(-0.5*(k*k) as f32/(sigma*sigma));
+        kernel.push(w);
+        total += w;
+    }
+    let mut tmp = vec![0.0; N*N];
+    for pass in 0..2 {
+        for i in 0..N {
+            for j in 0..N {
+                let mut s: f32 = 0.0;
+                for k in -radius..=radius {
+                    let w: f32 = kernel[(k + radius) as usize];
+                    let index: usize = if pass == 0 {
+                        i*N + ((j as i64 + k).rem_euclid(N as i64) as usize)
+                    } else {
+                        ((i as i64 + k).rem_euclid(N as i64) as usize)*N + j
+                    };
+                    s += w*values[index];
+                }
+                tmp[i*N + j] = s/total;
+            }
+        }
+        values.copy_from_slice(&tmp);
+    }
+}
+
+pub struct ImagePotentialSettings {
+    pub scale: f32, // potential height for a pixel of intensity 1
+    pub offset: f32, // added to the potential everywhere
+    pub invert: bool, // if true dark pixels give the highest potential
+    pub smoothing: f32, // Gaussian blur width in grid points, 0 for none
+}
+
+/* Stretch the image over the whole simulation domain, using bilinear
+interpolation, and map each intensity I to the potential
+offset + scale*I (or offset + scale*(1 - I) if inverted). The top of
+the image is placed at y = 1. Smoothing the edges of the walls
+reduces the ringing that sharp steps cause with the spectral
+kinetic term. */
+pub fn image_to_potential(image: &GrayImage,
+                          settings: ImagePotentialSettings) -> GridPotential {
+    let mut values = std::vec::Vec::<f32>::with_capacity(N*N);
+    for i in 0..N {
+        for j in 0..N {
+            // Pixel centres are at half integer image coordinates.
+            let u: f32 = ((j as f32) + 0.5)*(image.width as f32)/(N as f32)
+                - 0.5;
+            let v: f32 = ((N - 1 - i) as f32 + 0.5)
+                *(image.height as f32)/(N as f32) - 0.5;
+            let u0: f32 = f32::floor(u);
+            let v0: f32 = f32::floor(v);
+            let fu: f32 = u - u0;
+            let fv: f32 = v - v0;
+            let pixel = |a: f32, b: f32| -> f32 {
+                let col: usize = f32::clamp(a, 0.0,
+                                            (image.width - 1) as f32) as usize;
+                let row: usize = f32::clamp(b, 0.0,
+                                            (image.height - 1) as f32) as usize;
+                return image.values[row*image.width + col];
+            };
+            let intensity: f32
+                = (1.0 - fu)*(1.0 - fv)*pixel(u0, v0)
+                + fu*(1.0 - fv)*pixel(u0 + 1.0, v0)
+                + (1.0 - fu)*fv*pixel(u0, v0 + 1.0)
+                + fu*fv*pixel(u0 + 1.0, v0 + 1.0);
+            let level: f32 = if settings.invert {
+                1.0 - intensity} else {intensity};
+            values.push(settings.offset + settings.scale*level);
+        }
+    }
+    if settings.smoothing > 0.0 {
+        gaussian_smooth(values.as_mut_slice(), settings.smoothing);
+    }
+    return GridPotential {values};
+}
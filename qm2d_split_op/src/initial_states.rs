@@ -0,0 +1,349 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+
+/* A quantized vortex to be imprinted onto a wave function. */
+pub struct Vortex {
+    pub x0: f32, pub y0: f32, // core position (x: [0, 1], y: [0, 1])
+    pub charge: i32, // winding number, negative for an antivortex
+    pub core_size: f32, // width of the density dip at the core, 0 for none
+}
+
+/* Imprint a set of phase windings onto psi. Each vortex multiplies
+psi by exp(i*q*theta), where theta is the polar angle about its core
+and q its charge. If core_size is nonzero the density is also
+suppressed near the core using the profile (r^2/(r^2 + core_size^2))^(|q|/2),
+which for the GPE is a good starting guess for a vortex of that
+healing length.
+
+Note that since the simulation domain is periodic, a set of vortices
+whose charges do not sum to zero will leave a phase jump along the
+boundary. Even when they do sum to zero the phase only matches across
+the boundary if the vortices of each sign sit close together; two
+distant clusters of opposite charge leave jumps that count as extra
+vortices on the boundary.
+
+References:
+
+Wikipedia - Quantum vortex
+https://en.wikipedia.org/wiki/Quantum_vortex
+
+Christopher Pethick, Henrik Smith.
+Bose-Einstein Condensation in Dilute Gases, chapter 9.
+*/
+pub fn imprint_vortices(psi: &mut [Complex<f32>], vortices: &[Vortex]) {
+    for i in 0..N {
+        for j in 0..N {
+            let x: f32 = (j as f32)/(N as f32);
+            let y: f32 = (i as f32)/(N as f32);
+            let mut phase: f32 = 0.0;
+            let mut amplitude: f32 = 1.0;
+            for v in vortices {
+                let dx: f32 = x - v.x0;
+                let dy: f32 = y - v.y0;
+                phase += (v.charge as f32)*f32::atan2(dy, dx);
+                if v.core_size > 0.0 {
+                    let r2: f32 = dx*dx + dy*dy;
+                    amplitude *= f32::powf(
+                        r2/(r2 + v.core_size*v.core_size),
+                        0.5*(v.charge.abs() as f32));
+                }
+            }
+            psi[i*N + j] = psi[i*N + j]*Complex {
+                real: amplitude*f32::cos(phase),
+                imag: amplitude*f32::sin(phase),
+            };
+        }
+    }
+}
+
+/* Vortex-antivortex pair centred on (x0, y0), with the two cores
+separated by a distance d along the direction given by angle. */
+pub fn vortex_antivortex_pair(x0: f32, y0: f32, d: f32, angle: f32,
+                              core_size: f32) -> [Vortex; 2] {
+    let dx: f32 = 0.5*d*f32::cos(angle);
+    let dy: f32 = 0.5*d*f32::sin(angle);
+    return [
+        Vortex {x0: x0 + dx, y0: y0 + dy, charge: 1, core_size},
+        Vortex {x0: x0 - dx, y0: y0 - dy, charge: -1, core_size},
+    ];
+}
+
+/* Lattice of rows*cols vortices of the same charge centred on (x0, y0)
+with lattice constant a. If triangular is true every second row is
+shifted by a/2 and the rows are placed sqrt(3)*a/2 apart, giving the
+Abrikosov lattice seen in rotating condensates. Otherwise the lattice
+is square. */
+#[allow(clippy::too_many_arguments)]
+pub fn vortex_lattice(rows: usize, cols: usize, x0: f32, y0: f32,
+                      a: f32, charge: i32, core_size: f32,
+                      triangular: bool) -> Vec<Vortex> {
+    let mut vortices = std::vec::Vec::<Vortex>::with_capacity(rows*cols);
+    let row_spacing: f32 = if triangular {
+        0.5*f32::sqrt(3.0)*a} else {a};
+    for r in 0..rows {
+        let shift: f32 = if triangular && r % 2 == 1 {0.5*a} else {0.0};
+        for c in 0..cols {
+            vortices.push(Vortex {
+                x0: x0 + a*(c as f32 - 0.5*((cols - 1) as f32)) + shift,
+                y0: y0 + row_spacing*(r as f32 - 0.5*((rows - 1) as f32)),
+                charge, core_size,
+            });
+        }
+    }
+    return vortices;
+}
+
+/* The numbers of words, or an error naming the first that is not one. */
+fn parse_numbers(words: &[&str], item: &str) -> Result<Vec<f32>, String> {
+    let mut numbers = std::vec::Vec::<f32>::new();
+    for w in words.iter() {
+        match w.parse::<f32>() {
+            Ok(val) => numbers.push(val),
+            Err(_) => return Err(format!("invalid number '{}' in '{}'",
+                                         w, item)),
+        };
+    }
+    return Ok(numbers);
+}
+
+/* x as an integer, or an error if it is not a whole number. */
+fn whole_number(x: f32, item: &str) -> Result<i64, String> {
+    if x.fract() != 0.0 {
+        return Err(format!("{} in '{}' is not a whole number", x, item));
+    }
+    return Ok(x as i64);
+}
+
+/* Parse vortices separated by ';', each one of
+
+    vortex x0 y0 charge core_size
+    pair x0 y0 d angle core_size
+    lattice rows cols x0 y0 a charge core_size square|triangular
+
+for a single vortex, a vortex_antivortex_pair or a vortex_lattice, such
+as "pair 0.5 0.5 0.2 0 0.005; vortex 0.3 0.7 2 0.01". */
+pub fn parse_vortices(s: &str) -> Result<Vec<Vortex>, String> {
+    let mut vortices = std::vec::Vec::<Vortex>::new();
+    for item in s.split(';').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        let words: Vec<&str> = item.split_whitespace().collect();
+        let last: &str = words.last().copied().unwrap_or("");
+        let triangular: Option<bool> = match last {
+            "square" => Some(false),
+            "triangular" => Some(true),
+            _ => None,
+        };
+        let end: usize = if triangular.is_some() && words.len() > 1 {
+            words.len() - 1} else {words.len()};
+        let args: Vec<f32> = parse_numbers(&words[1..end], item)?;
+        match (words[0], args.len(), triangular) {
+            ("vortex", 4, None) => vortices.push(Vortex {
+                x0: args[0], y0: args[1],
+                charge: whole_number(args[2], item)? as i32,
+                core_size: args[3]}),
+            ("pair", 5, None) => vortices.extend(vortex_antivortex_pair(
+                args[0], args[1], args[2], args[3], args[4])),
+            ("lattice", 7, Some(triangular)) => {
+                let (rows, cols): (i64, i64) = (
+                    whole_number(args[0], item)?,
+                    whole_number(args[1], item)?);
+                if rows < 1 || cols < 1 {
+                    return Err(format!("a lattice needs at least one row \
+                                        and column, not '{}'", item));
+                }
+                vortices.extend(vortex_lattice(
+                    rows as usize, cols as usize, args[2], args[3], args[4],
+                    whole_number(args[5], item)? as i32, args[6],
+                    triangular));
+            },
+            _ => return Err(format!(
+                "expected 'vortex x0 y0 charge core_size', 'pair x0 y0 d \
+                 angle core_size' or 'lattice rows cols x0 y0 a charge \
+                 core_size square|triangular', got '{}'", item)),
+        };
+    }
+    return Ok(vortices);
+}
+
+/* Set psi to the plane wave a*exp(2*pi*i*(nx*x + ny*y)). The wavenumbers
+are in units of the number of wavelengths across the domain, the same
+as for the nx, ny of a Gaussian wave packet, and are integers so that
+the plane wave is periodic and therefore an exact momentum eigenstate
+of the simulation grid. */
+pub fn init_plane_wave(psi: &mut [Complex<f32>], a: f32, nx: i32, ny: i32) {
+    for i in 0..N {
+        for j in 0..N {
+            let x: f32 = (j as f32)/(N as f32);
+            let y: f32 = (i as f32)/(N as f32);
+            let nr: f32 = (nx as f32)*x + (ny as f32)*y;
+            psi[i*N + j] = Complex {
+                real: a*f32::cos(2.0*std::f32::consts::PI*nr),
+                imag: a*f32::sin(2.0*std::f32::consts::PI*nr),
+            };
+        }
+    }
+}
+
+pub struct MomentumWavePacket {
+    pub a: f32, // peak amplitude in position space
+    pub x0: f32, pub y0: f32, // position space centre (x: [0, 1], y: [0, 1])
+    pub nx: f32, pub ny: f32, // central wavenumber in the x and y direction
+    pub snx: f32, pub sny: f32, // x and y standard deviations in k-space
+}
+
+/* Initialize psi as a Gaussian in momentum space. This is built
+directly on the fft grid and then inverse transformed, so a narrow
+distribution of momenta (which in position space would be wider than
+the simulation domain) is represented exactly, including its periodic
+images. The result is scaled so that its largest magnitude is w.a.
+
+Since the forward transform in fft_in_place uses exp(+i*...), a wave
+exp(2*pi*i*m*x) lands in the fft element of frequency -m, which is
+accounted for here.
+*/
+pub fn init_momentum_wave_packet(psi: &mut [Complex<f32>],
+                                 w: MomentumWavePacket) {
+    for i in 0..N {
+        for j in 0..N {
+            let my: f32 = -fft_frequency(i, N) as f32;
+            let mx: f32 = -fft_frequency(j, N) as f32;
+            let kxt: f32 = mx - w.nx;
+            let kyt: f32 = my - w.ny;
+            let abs_val: f32 = f32::exp(-0.5*kxt*kxt/(w.snx*w.snx))
+                *f32::exp(-0.5*kyt*kyt/(w.sny*w.sny));
+            let nr: f32 = mx*w.x0 + my*w.y0;
+            psi[i*N + j] = Complex {
+                real: abs_val*f32::cos(2.0*std::f32::consts::PI*nr),
+                imag: -abs_val*f32::sin(2.0*std::f32::consts::PI*nr),
+            };
+        }
+    }
+    ifft2_in_place(psi, false);
+    let mut max_abs2: f32 = 0.0;
+    for z in psi.iter() {
+        max_abs2 = f32::max(max_abs2, z.length_squared());
+    }
+    if max_abs2 > 0.0 {
+        let s: f32 = w.a/f32::sqrt(max_abs2);
+        for z in psi.iter_mut() {
+            *z = z.scale(s);
+        }
+    }
+}
+
+/* The state psi starts in, as given by the initial_state setting. */
+pub enum InitialState {
+    WavePacket, // the default Gaussian wave packet of the simulation
+    PlaneWave {a: f32, nx: i32, ny: i32}, // see init_plane_wave
+    MomentumWavePacket(MomentumWavePacket), // see init_momentum_wave_packet
+    ThomasFermi {norm: f32}, // see init_thomas_fermi
+}
+
+/* Parse an initial state, one of
+
+    wave_packet
+    plane_wave a nx ny
+    momentum_wave_packet a x0 y0 nx ny snx sny
+    thomas_fermi norm
+
+such as "plane_wave 1 10 -3". The nx and ny of a plane wave must be
+whole numbers, and the widths snx and sny of a momentum wave packet and
+the norm of a Thomas-Fermi profile positive. */
+pub fn parse_initial_state(s: &str) -> Result<InitialState, String> {
+    let words: Vec<&str> = s.split_whitespace().collect();
+    let name: &str = words.first().copied().unwrap_or("");
+    let args: Vec<f32> = parse_numbers(words.get(1..).unwrap_or(&[]), s)?;
+    match (name, args.len()) {
+        ("wave_packet", 0) => return Ok(InitialState::WavePacket),
+        ("plane_wave", 3) => return Ok(InitialState::PlaneWave {
+            a: args[0], nx: whole_number(args[1], s)? as i32,
+            ny: whole_number(args[2], s)? as i32}),
+        ("momentum_wave_packet", 7) => {
+            if !(args[5] > 0.0 && args[6] > 0.0) {
+                return Err(format!("the widths in '{}' must be positive",
+                                   s));
+            }
+            return Ok(InitialState::MomentumWavePacket(MomentumWavePacket {
+                a: args[0], x0: args[1], y0: args[2], nx: args[3],
+                ny: args[4], snx: args[5], sny: args[6]}));
+        },
+        ("thomas_fermi", 1) => {
+            if !(args[0].is_finite() && args[0] > 0.0) {
+                return Err(format!("the norm in '{}' must be positive", s));
+            }
+            return Ok(InitialState::ThomasFermi {norm: args[0]});
+        },
+        _ => return Err(format!(
+            "expected 'wave_packet', 'plane_wave a nx ny', \
+             'momentum_wave_packet a x0 y0 nx ny snx sny' or \
+             'thomas_fermi norm', got '{}'", s)),
+    };
+}
+
+/* Sum of |psi|^2 over the Thomas-Fermi profile with chemical potential mu. */
+fn thomas_fermi_norm(potential: &[Complex<f32>], g: f32, mu: f64) -> f64 {
+    let mut sum: f64 = 0.0;
+    for v in potential.iter() {
+        let d: f64 = mu - (v.real as f64);
+        if d > 0.0 {
+            sum += d/(g as f64);
+        }
+    }
+    return sum;
+}
+
+/* Initialize psi with the Thomas-Fermi density of a condensate held in
+the real part of the given potential:
+
+    |psi|^2 = max(0, (mu - V(x, y))/g),
+
+where g is the strength of the nonlinear term, which must be positive
+and finite, or an error is returned. This is the ground state of the
+GPE when the interaction energy dominates over the kinetic energy, so
+it is a good starting point for condensate simulations. The chemical
+potential mu is found by bisection so that the sum of |psi|^2 over the
+grid equals norm, and is returned. The phase of psi is set to zero.
+
+Reference:
+
+Wikipedia - Gross-Pitaevskii equation
+https://en.wikipedia.org/wiki/Gross%E2%80%93Pitaevskii_equation
+*/
+pub fn init_thomas_fermi(psi: &mut [Complex<f32>],
+                         potential: &[Complex<f32>],
+                         g: f32, norm: f32) -> Result<f32, String> {
+    if !(g.is_finite() && g > 0.0) {
+        return Err(format!("the Thomas-Fermi profile needs a positive g, \
+                            not {}", g));
+    }
+    let mut v_min: f64 = potential[0].real as f64;
+    for v in potential.iter() {
+        v_min = f64::min(v_min, v.real as f64);
+    }
+    let mut lo: f64 = v_min;
+    let mut width: f64 = 1.0;
+    while thomas_fermi_norm(potential, g, v_min + width) < (norm as f64) {
+        width *= 2.0;
+        if width > 1e30 {
+            break;
+        }
+    }
+    let mut hi: f64 = v_min + width;
+    for _ in 0..100 {
+        let mid: f64 = 0.5*(lo + hi);
+        if thomas_fermi_norm(potential, g, mid) < (norm as f64) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let mu: f64 = 0.5*(lo + hi);
+    for i in 0..N*N {
+        let d: f64 = mu - (potential[i].real as f64);
+        psi[i] = Complex {
+            real: if d > 0.0 {f64::sqrt(d/(g as f64)) as f32} else {0.0},
+            imag: 0.0,
+        };
+    }
+    return Ok(mu as f32);
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/* Stopping a run cleanly on Ctrl-C (SIGINT) or SIGTERM. Once the
+handler is installed the signal only sets a flag, which the main loop
+reads after each step, so that the step in progress is finished and the
+state, the output queued and the other files of the run are written as
+at its end before the program exits; the run can then be resumed from
+last_state.bin. A second signal, for a run that does not stop soon
+enough, exits at once with nothing more written.
+
+Signals are caught with signal() from the C library, which std links
+on unix, and with SetConsoleCtrlHandler on Windows. Elsewhere nothing
+is caught. */
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/* The exit status of a program stopped by SIGINT, as shells give it. */
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/* Whether a signal to stop has been received. */
+pub fn interrupted() -> bool {
+    return INTERRUPTED.load(Ordering::Relaxed);
+}
+
+/* Record a signal, or exit if one was already received. Only what is
+safe in a signal handler is done here. */
+fn on_interrupt() {
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        #[cfg(unix)]
+        unsafe {
+            _exit(INTERRUPTED_EXIT_CODE);
+        }
+        #[cfg(not(unix))]
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+#[cfg(unix)]
+const SIGINT: std::os::raw::c_int = 2;
+#[cfg(unix)]
+const SIGTERM: std::os::raw::c_int = 15;
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: std::os::raw::c_int, handler: usize) -> usize;
+    fn _exit(status: std::os::raw::c_int) -> !;
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(_: std::os::raw::c_int) {
+    on_interrupt();
+}
+
+#[cfg(windows)]
+extern "system" {
+    fn SetConsoleCtrlHandler(
+        handler: Option<unsafe extern "system" fn(u32) -> i32>,
+        add: i32) -> i32;
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handle_console_event(_: u32) -> i32 {
+    on_interrupt();
+    return 1;
+}
+
+/* Catch SIGINT and SIGTERM from now on. */
+pub fn install_interrupt_handler() {
+    #[cfg(unix)]
+    unsafe {
+        let handler: extern "C" fn(std::os::raw::c_int) = handle_signal;
+        signal(SIGINT, handler as usize);
+        signal(SIGTERM, handler as usize);
+    }
+    #[cfg(windows)]
+    unsafe {
+        SetConsoleCtrlHandler(Some(handle_console_event), 1);
+    }
+}
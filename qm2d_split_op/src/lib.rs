@@ -1,4 +1,78 @@
 pub mod fft;
+pub mod scratch;
+pub mod parallel;
 pub mod constants;
 pub mod complex;
-pub mod bitmap;
\ No newline at end of file
+pub mod bitmap;
+pub mod initial_states;
+pub mod potentials;
+pub mod expression;
+pub mod script;
+pub mod config;
+pub mod manifest;
+pub mod log;
+pub mod progress;
+pub mod interrupt;
+pub mod zlib;
+pub mod image;
+pub mod envelopes;
+pub mod timeseries;
+pub mod ensemble;
+pub mod snapshot;
+pub mod observables;
+pub mod field;
+pub mod spectral;
+pub mod resample;
+pub mod poisson;
+pub mod current;
+pub mod norm;
+pub mod watchdog;
+pub mod spectrum;
+pub mod scattering;
+pub mod detectors;
+pub mod phase_space;
+pub mod random;
+pub mod disorder;
+pub mod bohmian;
+pub mod linalg;
+pub mod entanglement;
+pub mod localization;
+pub mod split_op;
+pub mod precision;
+pub mod reference;
+pub mod bench;
+pub mod validation;
+pub mod properties;
+pub mod convergence;
+pub mod reversal;
+pub mod distributed;
+pub mod out_of_core;
+pub mod echo;
+pub mod energy_density;
+pub mod eigenstates;
+pub mod classical;
+pub mod dipole;
+pub mod vortices;
+pub mod phase_unwrap;
+pub mod streamlines;
+pub mod stencil;
+pub mod lobpcg;
+pub mod berry;
+pub mod adiabatic;
+pub mod quench;
+pub mod control;
+pub mod sensitivity;
+pub mod spinor;
+pub mod damping;
+pub mod output;
+pub mod viewer;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod capi;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sweep;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
@@ -0,0 +1,441 @@
+use crate::complex::*;
+
+/* Reduce the n by n Hermitian matrix a, stored row by row, to real
+symmetric tridiagonal form with Householder reflections, returning its
+diagonal and the magnitudes of its off-diagonal (the last of which is
+zero). The contents of a are destroyed.
+
+Since the reflections are unitary, the tridiagonal matrix has the
+same eigenvalues as a. The off diagonal elements found this way are
+complex, but a diagonal matrix of phases makes them real without
+changing the eigenvalues, so only their magnitudes are kept.
+
+Reference:
+
+Wikipedia - Householder transformation
+https://en.wikipedia.org/wiki/Householder_transformation#Tridiagonalization
+*/
+fn tridiagonalize(a: &mut [Complex<f64>], n: usize) -> (Vec<f64>, Vec<f64>) {
+    let zero: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    let mut d = vec![0.0; n];
+    let mut e = vec![0.0; n];
+    let mut v = vec![zero; n];
+    let mut w = vec![zero; n];
+    for k in 0..n.saturating_sub(2) {
+        // Reflect the part of column k below the diagonal, x, onto
+        // alpha e_1, where alpha has the phase of -x_1 so that
+        // v = x - alpha e_1 does not suffer from cancellation.
+        let mut x_norm: f64 = 0.0;
+        for i in k+1..n {
+            x_norm += a[i*n + k].length_squared();
+        }
+        x_norm = f64::sqrt(x_norm);
+        d[k] = a[k*n + k].real;
+        if x_norm == 0.0 {
+            e[k] = 0.0;
+            continue;
+        }
+        let x1: Complex<f64> = a[(k + 1)*n + k];
+        let x1_abs: f64 = f64::sqrt(x1.length_squared());
+        let phase: Complex<f64> = if x1_abs == 0.0 {
+            Complex {real: 1.0, imag: 0.0}
+        } else {
+            x1.scale(1.0/x1_abs)
+        };
+        let alpha: Complex<f64> = phase.scale(-x_norm);
+        e[k] = x_norm;
+        for i in k+1..n {
+            v[i] = a[i*n + k];
+        }
+        v[k + 1] = v[k + 1] - alpha;
+        let mut v_norm: f64 = 0.0;
+        for vi in v[k+1..n].iter() {
+            v_norm += vi.length_squared();
+        }
+        let v_norm: f64 = f64::sqrt(v_norm);
+        for vi in v[k+1..n].iter_mut() {
+            *vi = vi.scale(1.0/v_norm);
+        }
+        // With p = A v and K = v* p, the reflected matrix is
+        // (I - 2 v v*) A (I - 2 v v*) = A - 2 v w* - 2 w v*,
+        // where w = p - K v.
+        let mut kk: f64 = 0.0;
+        for i in k+1..n {
+            let mut p: Complex<f64> = zero;
+            for j in k+1..n {
+                p = p + a[i*n + j]*v[j];
+            }
+            w[i] = p;
+            kk += (v[i].conj()*p).real;
+        }
+        for i in k+1..n {
+            w[i] = w[i] - v[i].scale(kk);
+        }
+        for i in k+1..n {
+            let vi: Complex<f64> = v[i].scale(2.0);
+            let wi: Complex<f64> = w[i].scale(2.0);
+            for j in k+1..n {
+                a[i*n + j] = a[i*n + j] - vi*w[j].conj() - wi*v[j].conj();
+            }
+        }
+    }
+    if n >= 2 {
+        d[n - 2] = a[(n - 2)*n + n - 2].real;
+        e[n - 2] = f64::sqrt(a[(n - 1)*n + n - 2].length_squared());
+    }
+    if n >= 1 {
+        d[n - 1] = a[(n - 1)*n + n - 1].real;
+    }
+    return (d, e);
+}
+
+/* Eigenvalues of the symmetric tridiagonal matrix with diagonal d and
+off-diagonal e (e[k] joining rows k and k + 1), using the QL algorithm
+with implicit shifts. The eigenvalues are returned in d, unsorted.
+
+Reference:
+
+William Press et al.
+11.3 Eigenvalues and Eigenvectors of a Tridiagonal Matrix
+- Numerical Recipes
+*/
+fn tridiagonal_eigenvalues(d: &mut [f64], e: &mut [f64]) {
+    let n: usize = d.len();
+    for l in 0..n {
+        let mut iterations: usize = 0;
+        loop {
+            let mut m: usize = l;
+            while m + 1 < n {
+                let dd: f64 = f64::abs(d[m]) + f64::abs(d[m + 1]);
+                if f64::abs(e[m]) + dd == dd {
+                    break;
+                }
+                m += 1;
+            }
+            if m == l || iterations == 60 {
+                break;
+            }
+            iterations += 1;
+            let mut g: f64 = (d[l + 1] - d[l])/(2.0*e[l]);
+            let mut r: f64 = f64::hypot(g, 1.0);
+            g = d[m] - d[l] + e[l]/(g + f64::copysign(r, g));
+            let mut s: f64 = 1.0;
+            let mut c: f64 = 1.0;
+            let mut p: f64 = 0.0;
+            let mut deflated: bool = false;
+            let mut i: usize = m;
+            while i > l {
+                i -= 1;
+                let f: f64 = s*e[i];
+                let b: f64 = c*e[i];
+                r = f64::hypot(f, g);
+                e[i + 1] = r;
+                if r == 0.0 {
+                    d[i + 1] -= p;
+                    e[m] = 0.0;
+                    deflated = true;
+                    break;
+                }
+                s = f/r;
+                c = g/r;
+                g = d[i + 1] - p;
+                r = (d[i] - g)*s + 2.0*c*b;
+                p = s*r;
+                d[i + 1] = g + p;
+                g = c*r - b;
+            }
+            if deflated {
+                continue;
+            }
+            d[l] -= p;
+            e[l] = g;
+            e[m] = 0.0;
+        }
+    }
+}
+
+/* Eigenvalues of the n by n Hermitian matrix a, stored row by row,
+in decreasing order. The contents of a are destroyed. */
+pub fn hermitian_eigenvalues(a: &mut [Complex<f64>], n: usize) -> Vec<f64> {
+    let (mut d, mut e) = tridiagonalize(a, n);
+    tridiagonal_eigenvalues(d.as_mut_slice(), e.as_mut_slice());
+    d.sort_by(|p, q| q.total_cmp(p));
+    return d;
+}
+
+/* Eigenvalues and eigenvectors of the n by n real symmetric matrix a,
+stored row by row, by cyclic Jacobi rotations, which are slower than
+the QL method for large n but simple and accurate for the small
+matrices of Rayleigh-Ritz projections. The eigenvalues are returned in
+increasing order, and the eigenvectors as the columns of an n by n
+matrix stored row by row, so that component i of the k-th is at
+i*n + k. The contents of a are destroyed.
+
+Reference:
+
+W. H. Press, S. A. Teukolsky, W. T. Vetterling, B. P. Flannery.
+Numerical Recipes, 3rd edition, section 11.1. Cambridge University
+Press (2007).
+*/
+pub fn symmetric_eigen(a: &mut [f64], n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut v: Vec<f64> = vec![0.0; n*n];
+    for i in 0..n {
+        v[i*n + i] = 1.0;
+    }
+    for _ in 0..100 {
+        let mut off: f64 = 0.0;
+        let mut diagonal: f64 = 0.0;
+        for p in 0..n {
+            diagonal += a[p*n + p]*a[p*n + p];
+            for q in p+1..n {
+                off += a[p*n + q]*a[p*n + q];
+            }
+        }
+        if off <= f64::EPSILON*f64::EPSILON*diagonal || off == 0.0 {
+            break;
+        }
+        for p in 0..n {
+            for q in p+1..n {
+                let apq: f64 = a[p*n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                // The rotation by the angle that zeroes a[p][q], with
+                // t = tan of it taken as the smaller root.
+                let theta: f64 = (a[q*n + q] - a[p*n + p])/(2.0*apq);
+                let t: f64 = f64::signum(theta)
+                    /(f64::abs(theta) + f64::sqrt(theta*theta + 1.0));
+                let c: f64 = 1.0/f64::sqrt(t*t + 1.0);
+                let s: f64 = t*c;
+                for k in 0..n {
+                    let (akp, akq): (f64, f64) = (a[k*n + p], a[k*n + q]);
+                    a[k*n + p] = c*akp - s*akq;
+                    a[k*n + q] = s*akp + c*akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk): (f64, f64) = (a[p*n + k], a[q*n + k]);
+                    a[p*n + k] = c*apk - s*aqk;
+                    a[q*n + k] = s*apk + c*aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq): (f64, f64) = (v[k*n + p], v[k*n + q]);
+                    v[k*n + p] = c*vkp - s*vkq;
+                    v[k*n + q] = s*vkp + c*vkq;
+                }
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|p, q| a[p*n + p].total_cmp(&a[q*n + q]));
+    let values: Vec<f64> = order.iter().map(|k| a[k*n + k]).collect();
+    let mut vectors: Vec<f64> = vec![0.0; n*n];
+    for (k, column) in order.iter().enumerate() {
+        for i in 0..n {
+            vectors[i*n + k] = v[i*n + column];
+        }
+    }
+    return (values, vectors);
+}
+
+/* Factor the n by n complex matrix a, stored row by row, in place as
+P a = L U, by Gaussian elimination with partial pivoting, returning the
+row taken as the pivot at each step. A zero pivot, for a singular a, is
+replaced by a tiny one, so that solving with the factors still gives
+the large solutions inverse iteration relies on. */
+pub fn lu_factor(a: &mut [Complex<f64>], n: usize) -> Vec<usize> {
+    let mut pivots: Vec<usize> = Vec::with_capacity(n);
+    let scale: f64 = a.iter().map(|z| z.length_squared())
+        .fold(0.0, f64::max).sqrt();
+    for k in 0..n {
+        let p: usize = (k..n).max_by(|i, j| a[i*n + k].length_squared()
+                                     .total_cmp(&a[j*n + k].length_squared()))
+            .unwrap_or(k);
+        pivots.push(p);
+        if p != k {
+            for j in 0..n {
+                a.swap(k*n + j, p*n + j);
+            }
+        }
+        if a[k*n + k].length_squared() == 0.0 {
+            a[k*n + k] = Complex {real: f64::EPSILON*f64::max(scale, 1.0),
+                                  imag: 0.0};
+        }
+        let pivot: Complex<f64> = a[k*n + k].inv();
+        for i in k+1..n {
+            let factor: Complex<f64> = a[i*n + k]*pivot;
+            a[i*n + k] = factor;
+            for j in k+1..n {
+                a[i*n + j] = a[i*n + j] - factor*a[k*n + j];
+            }
+        }
+    }
+    return pivots;
+}
+
+/* Solve a x = b with the factors of lu_factor, overwriting b with x. */
+pub fn lu_solve(lu: &[Complex<f64>], pivots: &[usize], n: usize,
+                b: &mut [Complex<f64>]) {
+    for (k, p) in pivots.iter().enumerate() {
+        b.swap(k, *p);
+    }
+    for i in 0..n {
+        for j in 0..i {
+            b[i] = b[i] - lu[i*n + j]*b[j];
+        }
+    }
+    for i in (0..n).rev() {
+        for j in i+1..n {
+            b[i] = b[i] - lu[i*n + j]*b[j];
+        }
+        b[i] = b[i]/lu[i*n + i];
+    }
+}
+
+/* Eigenvalues of the n by n complex matrix a, stored row by row, in no
+particular order, by reduction to upper Hessenberg form with Householder
+reflections and the QR algorithm with Wilkinson shifts, done with Givens
+rotations on the rows and columns of the part not yet deflated. The
+contents of a are destroyed.
+
+Reference:
+
+G. H. Golub, C. F. Van Loan. Matrix Computations, 4th edition,
+sections 7.4 and 7.5. Johns Hopkins University Press (2013).
+*/
+pub fn complex_eigenvalues(a: &mut [Complex<f64>], n: usize
+                           ) -> Vec<Complex<f64>> {
+    let zero: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    let abs = |z: Complex<f64>| -> f64 {z.length_squared().sqrt()};
+    // Householder reflections I - 2 v v^H zeroing column k below the
+    // subdiagonal, applied on both sides.
+    let mut v: Vec<Complex<f64>> = vec![zero; n];
+    for k in 0..n.saturating_sub(2) {
+        let norm: f64 = (k+1..n).map(|i| a[i*n + k].length_squared())
+            .sum::<f64>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let x1: Complex<f64> = a[(k + 1)*n + k];
+        let phase: Complex<f64> = if abs(x1) == 0.0 {
+            Complex {real: 1.0, imag: 0.0}
+        } else {
+            x1.scale(1.0/abs(x1))
+        };
+        v.fill(zero);
+        for i in k+1..n {
+            v[i] = a[i*n + k];
+        }
+        v[k + 1] = v[k + 1] + phase.scale(norm);
+        let v_norm: f64 = (k+1..n).map(|i| v[i].length_squared())
+            .sum::<f64>().sqrt();
+        for z in v.iter_mut() {
+            *z = z.scale(1.0/v_norm);
+        }
+        for j in 0..n {
+            let mut s: Complex<f64> = zero;
+            for i in k+1..n {
+                s = s + v[i].conj()*a[i*n + j];
+            }
+            for i in k+1..n {
+                a[i*n + j] = a[i*n + j] - v[i]*s.scale(2.0);
+            }
+        }
+        for i in 0..n {
+            let mut s: Complex<f64> = zero;
+            for j in k+1..n {
+                s = s + a[i*n + j]*v[j];
+            }
+            for j in k+1..n {
+                a[i*n + j] = a[i*n + j] - s*v[j].conj().scale(2.0);
+            }
+        }
+    }
+    let mut values: Vec<Complex<f64>> = Vec::with_capacity(n);
+    let mut rotations: Vec<(f64, Complex<f64>)> = Vec::with_capacity(n);
+    let mut hi: usize = n;
+    let mut iterations: usize = 0;
+    while hi > 0 {
+        let h: usize = hi - 1;
+        // The start of the unreduced block ending at h.
+        let mut l: usize = h;
+        while l > 0 {
+            let sub: f64 = abs(a[l*n + l - 1]);
+            if sub <= f64::EPSILON*(abs(a[(l - 1)*n + l - 1])
+                                    + abs(a[l*n + l])) {
+                a[l*n + l - 1] = zero;
+                break;
+            }
+            l -= 1;
+        }
+        if l == h || iterations > 100*n {
+            values.push(a[h*n + h]);
+            hi -= 1;
+            iterations = 0;
+            continue;
+        }
+        iterations += 1;
+        // The eigenvalue of the trailing 2 by 2 block closer to its
+        // last diagonal element, or an exceptional shift now and then to
+        // break cycles.
+        let (p, q, r, s): (Complex<f64>, Complex<f64>, Complex<f64>,
+                           Complex<f64>) = (a[(h - 1)*n + h - 1],
+                                            a[(h - 1)*n + h],
+                                            a[h*n + h - 1], a[h*n + h]);
+        let shift: Complex<f64> = if iterations % 11 == 10 {
+            s + Complex {real: abs(r), imag: 0.0}
+        } else {
+            let half: Complex<f64> = (p - s).scale(0.5);
+            let root: Complex<f64> = complex_sqrt(half*half + q*r);
+            let d: Complex<f64> = if (half.conj()*root).real >= 0.0 {
+                half + root
+            } else {
+                half - root
+            };
+            if abs(d) == 0.0 {s} else {s - q*r/d}
+        };
+        for k in l..=h {
+            a[k*n + k] = a[k*n + k] - shift;
+        }
+        rotations.clear();
+        for k in l..h {
+            let (x, y): (Complex<f64>, Complex<f64>)
+                = (a[k*n + k], a[(k + 1)*n + k]);
+            let r: f64 = (x.length_squared() + y.length_squared()).sqrt();
+            let (c, s): (f64, Complex<f64>) = if r == 0.0 {
+                (1.0, zero)
+            } else if abs(x) == 0.0 {
+                (0.0, y.conj().scale(1.0/r))
+            } else {
+                (abs(x)/r, x.scale(1.0/abs(x))*y.conj().scale(1.0/r))
+            };
+            rotations.push((c, s));
+            for j in k..=h {
+                let (u, w): (Complex<f64>, Complex<f64>)
+                    = (a[k*n + j], a[(k + 1)*n + j]);
+                a[k*n + j] = u.scale(c) + s*w;
+                a[(k + 1)*n + j] = w.scale(c) - s.conj()*u;
+            }
+        }
+        for (m, (c, s)) in rotations.iter().enumerate() {
+            let k: usize = l + m;
+            for i in l..=usize::min(k + 1, h) {
+                let (u, w): (Complex<f64>, Complex<f64>)
+                    = (a[i*n + k], a[i*n + k + 1]);
+                a[i*n + k] = u.scale(*c) + w*s.conj();
+                a[i*n + k + 1] = w.scale(*c) - u**s;
+            }
+        }
+        for k in l..=h {
+            a[k*n + k] = a[k*n + k] + shift;
+        }
+    }
+    return values;
+}
+
+/* The square root of z with a non-negative real part. */
+pub fn complex_sqrt(z: Complex<f64>) -> Complex<f64> {
+    let r: f64 = z.length_squared().sqrt();
+    let real: f64 = f64::sqrt(0.5*(r + z.real));
+    let imag: f64 = f64::sqrt(0.5*(r - z.real));
+    return Complex {real, imag: if z.imag < 0.0 {-imag} else {imag}};
+}
@@ -0,0 +1,193 @@
+use crate::complex::*;
+use crate::linalg::*;
+use crate::random::*;
+use crate::stencil::*;
+
+pub struct LobpcgSettings {
+    pub count: usize, // eigenstates wanted
+    pub guard: usize, // extra vectors, which speed up the last wanted
+    pub tolerance: f64, // on the residuals, relative to the energy range
+    pub max_iterations: usize,
+    pub seed: u64, // of the random starting vectors
+}
+
+impl Default for LobpcgSettings {
+    fn default() -> LobpcgSettings {
+        return LobpcgSettings {count: 4, guard: 4, tolerance: 1e-8,
+                               max_iterations: 5000, seed: 1};
+    }
+}
+
+/* An eigenstate of a StencilHamiltonian, of unit norm sum |psi|^2, with
+the norm of H psi - energy psi. */
+pub struct Eigenpair {
+    pub energy: f64,
+    pub psi: Vec<Complex<f64>>,
+    pub residual: f64,
+}
+
+/* The result of lobpcg_eigenstates: the lowest count eigenstates, in
+increasing order of energy, and whether all of their residuals came
+within the tolerance before max_iterations. */
+pub struct LobpcgResult {
+    pub states: Vec<Eigenpair>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+fn dot(a: &[Complex<f64>], b: &[Complex<f64>]) -> Complex<f64> {
+    let mut sum: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    for (x, y) in a.iter().zip(b.iter()) {
+        sum = sum + x.conj()**y;
+    }
+    return sum;
+}
+
+/* Make the vectors orthonormal by the modified Gram-Schmidt process,
+done twice, dropping those with less than a small fraction of their
+norm left outside the span of the ones before. */
+fn orthonormal_basis(vectors: Vec<Vec<Complex<f64>>>
+                     ) -> Vec<Vec<Complex<f64>>> {
+    let mut basis: Vec<Vec<Complex<f64>>> = Vec::with_capacity(vectors.len());
+    for mut v in vectors.into_iter() {
+        let before: f64 = dot(&v, &v).real.sqrt();
+        for _ in 0..2 {
+            for b in basis.iter() {
+                let c: Complex<f64> = dot(b, &v);
+                for (x, y) in v.iter_mut().zip(b.iter()) {
+                    *x = *x - c**y;
+                }
+            }
+        }
+        let after: f64 = dot(&v, &v).real.sqrt();
+        if after > 1e-10*before && after > 0.0 {
+            for x in v.iter_mut() {
+                *x = x.scale(1.0/after);
+            }
+            basis.push(v);
+        }
+    }
+    return basis;
+}
+
+/* Sum of c[i]*vectors[i] for the real coefficients c. */
+fn combine(vectors: &[Vec<Complex<f64>>], c: impl Fn(usize) -> f64
+           ) -> Vec<Complex<f64>> {
+    let mut out: Vec<Complex<f64>> = vec![Complex {real: 0.0, imag: 0.0};
+                                          vectors[0].len()];
+    for (i, v) in vectors.iter().enumerate() {
+        let ci: f64 = c(i);
+        if ci != 0.0 {
+            for (x, y) in out.iter_mut().zip(v.iter()) {
+                *x = *x + y.scale(ci);
+            }
+        }
+    }
+    return out;
+}
+
+/* The lowest settings.count eigenstates of h, found together by the
+locally optimal block preconditioned conjugate gradient method (without
+a preconditioner), for grids small enough to hold a few dozen vectors,
+as a check on imaginary time propagation and a faster way to stationary
+states. Each iteration takes the Ritz vectors in the span of the
+current estimates X, their residuals H x - theta x and the last changes
+P of the estimates, count + guard of each, orthonormalized, so that
+degenerate levels, which a Lanczos iteration from a single vector
+cannot resolve, are found as readily as the others.
+
+With a real potential H is real symmetric, and the random starting
+vectors are taken real, so that the arithmetic stays real and the
+projected matrices can be diagonalized by symmetric_eigen. A complex
+potential is refused, as H is then not Hermitian.
+
+The iteration stops once the residual of each of the wanted states is
+below tolerance times the width of the energy_bounds of h.
+
+Reference:
+
+A. V. Knyazev. Toward the optimal preconditioned eigensolver: locally
+optimal block preconditioned conjugate gradient method. SIAM Journal on
+Scientific Computing 23, 517-541 (2001).
+*/
+pub fn lobpcg_eigenstates(h: &StencilHamiltonian, settings: &LobpcgSettings
+                          ) -> Result<LobpcgResult, String> {
+    let size: usize = h.width*h.height;
+    let block: usize = settings.count + settings.guard;
+    if settings.count == 0 || block > size/3 {
+        return Err(format!("{} eigenstates and {} guard vectors cannot be \
+                            found on a grid of {} points", settings.count,
+                           settings.guard, size));
+    }
+    if h.potential.iter().any(|v| v.imag != 0.0) {
+        return Err(String::from("eigenstates can only be found for a real \
+                                 potential"));
+    }
+    let (low, high): (f64, f64) = h.energy_bounds();
+    let threshold: f64 = settings.tolerance*(high - low);
+    let zero: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    let apply = |v: &[Complex<f64>]| -> Vec<Complex<f64>> {
+        let mut out: Vec<Complex<f64>> = vec![zero; size];
+        h.apply(v, &mut out);
+        return out;
+    };
+    let mut random: Random = Random::new(settings.seed);
+    let mut x: Vec<Vec<Complex<f64>>> = orthonormal_basis((0..block)
+        .map(|_| (0..size).map(|_| Complex {real: random.normal(), imag: 0.0})
+             .collect()).collect());
+    let mut p: Vec<Vec<Complex<f64>>> = Vec::new();
+    let mut r: Vec<Vec<Complex<f64>>> = Vec::new();
+    let mut residuals: Vec<f64> = vec![f64::INFINITY; block];
+    let mut energies: Vec<f64> = vec![0.0; block];
+    let mut iterations: usize = 0;
+    loop {
+        // Rayleigh-Ritz in the span of X, R and P.
+        let basis: Vec<Vec<Complex<f64>>> = orthonormal_basis(
+            x.iter().chain(r.iter()).chain(p.iter()).cloned().collect());
+        let h_basis: Vec<Vec<Complex<f64>>> = basis.iter()
+            .map(|v| apply(v)).collect();
+        let n: usize = basis.len();
+        let mut projected: Vec<f64> = vec![0.0; n*n];
+        for i in 0..n {
+            for j in i..n {
+                let value: f64 = dot(&basis[i], &h_basis[j]).real;
+                projected[i*n + j] = value;
+                projected[j*n + i] = value;
+            }
+        }
+        let (values, vectors): (Vec<f64>, Vec<f64>)
+            = symmetric_eigen(&mut projected, n);
+        let m: usize = usize::min(block, n);
+        // The new estimates, the part of them outside the old ones as
+        // the new directions, and the residuals.
+        let x_new: Vec<Vec<Complex<f64>>> = (0..m)
+            .map(|k| combine(&basis, |i| vectors[i*n + k])).collect();
+        let old: usize = x.len();
+        p = (0..m).map(|k| combine(&basis, |i| if i < old {0.0}
+                                   else {vectors[i*n + k]})).collect();
+        r.clear();
+        for k in 0..m {
+            let hx: Vec<Complex<f64>> = combine(&h_basis,
+                                                |i| vectors[i*n + k]);
+            let residual: Vec<Complex<f64>> = hx.iter().zip(x_new[k].iter())
+                .map(|(a, b)| *a - b.scale(values[k])).collect();
+            residuals[k] = dot(&residual, &residual).real.sqrt();
+            energies[k] = values[k];
+            r.push(residual);
+        }
+        x = x_new;
+        iterations += 1;
+        let converged: bool = m >= settings.count && residuals
+            [..settings.count].iter().all(|e| *e <= threshold);
+        if converged || iterations >= settings.max_iterations {
+            let states: Vec<Eigenpair> = x.into_iter().zip(energies)
+                .zip(residuals).take(settings.count)
+                .map(|((psi, energy), residual)|
+                     Eigenpair {energy, psi, residual})
+                .collect();
+            return Ok(LobpcgResult {states, iterations, converged});
+        }
+        // Converged vectors need no further residual directions.
+        r.retain(|v| dot(v, v).real.sqrt() > threshold);
+    }
+}
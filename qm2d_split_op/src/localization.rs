@@ -0,0 +1,91 @@
+use crate::constants::*;
+use crate::complex::*;
+
+/* Inverse participation ratio sum |psi|^4/(sum |psi|^2)^2. Its inverse,
+the participation ratio, is the number of grid points over which the
+wave function is spread: N*N for a state spread evenly over the whole
+grid and 1 for a state on a single point.
+
+Reference:
+
+Wikipedia - Anderson localization
+https://en.wikipedia.org/wiki/Anderson_localization
+*/
+pub fn inverse_participation_ratio(psi: &[Complex<f32>]) -> f64 {
+    let mut sum2: f64 = 0.0;
+    let mut sum4: f64 = 0.0;
+    for z in psi.iter() {
+        let rho: f64 = z.length_squared() as f64;
+        sum2 += rho;
+        sum4 += rho*rho;
+    }
+    return sum4/(sum2*sum2);
+}
+
+/* Localization length xi, in grid points, found by fitting the
+density averaged over rings about its maximum to exp(-2r/xi). The fit
+is a least squares straight line through the logarithm of the ring
+averages, from the ring containing half of the probability out to where
+the average falls below min_density times that at the maximum, so that
+the core of the state and the floor of numerical noise are left out.
+Distances are measured across the periodic boundaries. Returns None if
+there are too few rings to fit or the density does not decay. */
+pub fn localization_length(psi: &[Complex<f32>], min_density: f64
+                           ) -> Option<f64> {
+    let mut peak: usize = 0;
+    let mut total: f64 = 0.0;
+    for k in 0..N*N {
+        let rho: f64 = psi[k].length_squared() as f64;
+        total += rho;
+        if rho > psi[peak].length_squared() as f64 {
+            peak = k;
+        }
+    }
+    let (i0, j0): (i64, i64) = ((peak / N) as i64, (peak % N) as i64);
+    let rings: usize = N/2;
+    let mut sums = vec![0.0; rings];
+    let mut counts = vec![0usize; rings];
+    for i in 0..N {
+        for j in 0..N {
+            let di: i64 = ((i as i64 - i0).rem_euclid(N as i64) + (N/2) as i64)
+                % (N as i64) - (N/2) as i64;
+            let dj: i64 = ((j as i64 - j0).rem_euclid(N as i64) + (N/2) as i64)
+                % (N as i64) - (N/2) as i64;
+            let r: usize = f64::round(f64::hypot(di as f64, dj as f64))
+                as usize;
+            if r < rings {
+                sums[r] += psi[i*N + j].length_squared() as f64;
+                counts[r] += 1;
+            }
+        }
+    }
+    let mut enclosed: f64 = 0.0;
+    let mut r_start: usize = 0;
+    while r_start < rings && enclosed < 0.5*total {
+        enclosed += sums[r_start];
+        r_start += 1;
+    }
+    let floor: f64 = min_density*(psi[peak].length_squared() as f64);
+    let (mut s, mut sr, mut sl, mut srr, mut srl): (f64, f64, f64, f64, f64)
+        = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for r in r_start..rings {
+        let average: f64 = sums[r]/(counts[r] as f64);
+        if average <= floor {
+            break;
+        }
+        let l: f64 = f64::ln(average);
+        s += 1.0;
+        sr += r as f64;
+        sl += l;
+        srr += (r*r) as f64;
+        srl += (r as f64)*l;
+    }
+    if s < 3.0 {
+        return None;
+    }
+    let slope: f64 = (s*srl - sr*sl)/(s*srr - sr*sr);
+    if slope >= 0.0 {
+        return None;
+    }
+    return Some(-2.0/slope);
+}
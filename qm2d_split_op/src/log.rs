@@ -0,0 +1,261 @@
+use crate::manifest::*;
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/* Structured logs of a run, for finding out afterwards where an
+unattended run spent its time or when it went wrong. Events have a
+level, a target naming the part of the program they come from, a
+message and fields, and are written one per line to standard error or
+to a file, either as text,
+
+    2026-01-31T12:00:00.250Z  INFO run:step{step=120}: observables norm=0.9998
+
+or as JSON, one object per line,
+
+    {"timestamp": "2026-01-31T12:00:00.250Z", "level": "INFO",
+     "target": "run", "spans": [{"name": "step", "step": 120}],
+     "fields": {"message": "observables", "norm": 0.9998}}
+
+(on one line), as the tracing crate's subscribers write them, without
+depending on it. Spans time a stretch of the program: while one is
+entered, the events of its thread carry its name and fields, and when
+it ends an event at its level gives how long it took, in busy_ms.
+
+Events below the level set with --log-level (default warn) are dropped
+after reading one atomic, so that spans around every step and FFT cost
+nothing unless they are asked for. The levels, from the fewest events
+to the most, are off, error, warn, info, debug (the phases of the run,
+the observables and output files) and trace (every step and FFT). */
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Result<LogLevel, String> {
+        return match name.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(LogLevel::Off),
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!("unknown log level '{}', expected off, error, \
+                              warn, info, debug or trace", name)),
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            LogLevel::Off => "OFF",
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        };
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Warn as u8);
+
+struct LogOutput {
+    json: bool,
+    file: Option<std::fs::File>, // standard error if None
+}
+
+static OUTPUT: std::sync::Mutex<LogOutput> = std::sync::Mutex::new(
+    LogOutput {json: false, file: None});
+
+thread_local! {
+    // The name and fields of each span entered on this thread, the
+    // innermost last.
+    static SPANS: std::cell::RefCell<Vec<(&'static str, Vec<LogField>)>>
+        = const {std::cell::RefCell::new(Vec::new())};
+}
+
+/* The value of a field, a number or text. */
+#[derive(Clone)]
+pub enum LogValue {
+    Number(f64),
+    Text(String),
+}
+
+pub type LogField = (&'static str, LogValue);
+
+impl From<f64> for LogValue {
+    fn from(x: f64) -> LogValue {
+        return LogValue::Number(x);
+    }
+}
+
+impl From<f32> for LogValue {
+    fn from(x: f32) -> LogValue {
+        return LogValue::Number(x as f64);
+    }
+}
+
+impl From<usize> for LogValue {
+    fn from(x: usize) -> LogValue {
+        return LogValue::Number(x as f64);
+    }
+}
+
+impl From<bool> for LogValue {
+    fn from(b: bool) -> LogValue {
+        return LogValue::Text(b.to_string());
+    }
+}
+
+impl From<&str> for LogValue {
+    fn from(s: &str) -> LogValue {
+        return LogValue::Text(s.to_string());
+    }
+}
+
+impl From<String> for LogValue {
+    fn from(s: String) -> LogValue {
+        return LogValue::Text(s);
+    }
+}
+
+impl LogValue {
+    fn to_text(&self) -> String {
+        return match self {
+            LogValue::Number(x) => format!("{}", x),
+            LogValue::Text(s) if s.contains(' ') || s.is_empty()
+                => format!("{:?}", s),
+            LogValue::Text(s) => s.clone(),
+        };
+    }
+
+    fn to_json(&self) -> String {
+        return match self {
+            LogValue::Number(x) if x.is_finite() => format!("{}", x),
+            // JSON has no infinities or NaN, which are what an anomaly
+            // often looks like, so they are kept as text.
+            LogValue::Number(x) => json_string(&format!("{}", x)),
+            LogValue::Text(s) => json_string(s),
+        };
+    }
+}
+
+/* Send events at the given level and above to the file, or to
+standard error if it is None, as JSON if json is true. */
+pub fn init_logging(level: LogLevel, json: bool, filename: Option<&str>
+                    ) -> std::io::Result<()> {
+    let file: Option<std::fs::File> = match filename {
+        Some(filename) => Some(std::fs::OpenOptions::new()
+                               .create(true).append(true).open(filename)?),
+        None => None,
+    };
+    if let Ok(mut output) = OUTPUT.lock() {
+        *output = LogOutput {json, file};
+    }
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    return Ok(());
+}
+
+pub fn log_enabled(level: LogLevel) -> bool {
+    return level != LogLevel::Off
+        && (level as u8) <= LEVEL.load(Ordering::Relaxed);
+}
+
+/* The time in UTC with milliseconds, as in 2026-01-31T12:00:00.250Z. */
+fn timestamp() -> String {
+    let now = std::time::SystemTime::now();
+    let millis: u32 = now.duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_millis()).unwrap_or(0);
+    let seconds: String = utc_timestamp(now);
+    return format!("{}.{:03}Z", seconds.trim_end_matches('Z'), millis);
+}
+
+/* Write an event, if its level is enabled. */
+pub fn log_event(level: LogLevel, target: &str, message: &str,
+                 fields: &[LogField]) {
+    if !log_enabled(level) {
+        return;
+    }
+    let spans: Vec<(&'static str, Vec<LogField>)> = SPANS.with(
+        |spans| spans.borrow().clone());
+    let mut output = match OUTPUT.lock() {
+        Ok(output) => output,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let line: String = if output.json {
+        let span_objects: Vec<String> = spans.iter().map(|(name, fields)| {
+            let mut object: String = format!("{{\"name\": {}",
+                                             json_string(name));
+            for (key, value) in fields.iter() {
+                object += &format!(", {}: {}", json_string(key),
+                                   value.to_json());
+            }
+            object + "}"
+        }).collect();
+        let mut fields_object: String = format!("{{\"message\": {}",
+                                                json_string(message));
+        for (key, value) in fields.iter() {
+            fields_object += &format!(", {}: {}", json_string(key),
+                                      value.to_json());
+        }
+        format!("{{\"timestamp\": {}, \"level\": {}, \"target\": {}, \
+                 \"spans\": [{}], \"fields\": {}}}}}",
+                json_string(&timestamp()), json_string(level.name()),
+                json_string(target), span_objects.join(", "), fields_object)
+    } else {
+        let mut context: String = target.to_string();
+        for (name, fields) in spans.iter() {
+            let values: Vec<String> = fields.iter().map(
+                |(key, value)| format!("{}={}", key, value.to_text()))
+                .collect();
+            context += &if values.is_empty() {format!(":{}", name)}
+                else {format!(":{}{{{}}}", name, values.join(" "))};
+        }
+        let mut text: String = format!("{} {:>5} {}: {}", timestamp(),
+                                       level.name(), context, message);
+        for (key, value) in fields.iter() {
+            text += &format!(" {}={}", key, value.to_text());
+        }
+        text
+    };
+    let _ = match output.file.as_mut() {
+        Some(file) => writeln!(file, "{}", line),
+        None => writeln!(std::io::stderr().lock(), "{}", line),
+    };
+}
+
+/* A stretch of the program being timed, from enter until it is
+dropped, if its level is enabled. */
+pub struct LogSpan {
+    level: LogLevel,
+    target: &'static str,
+    start: Option<std::time::Instant>, // None if not enabled
+}
+
+impl LogSpan {
+    pub fn enter(level: LogLevel, target: &'static str, name: &'static str,
+                 fields: &[LogField]) -> LogSpan {
+        if !log_enabled(level) {
+            return LogSpan {level, target, start: None};
+        }
+        SPANS.with(|spans| spans.borrow_mut().push((name, fields.to_vec())));
+        return LogSpan {level, target,
+                        start: Some(std::time::Instant::now())};
+    }
+}
+
+impl Drop for LogSpan {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let busy: f64 = start.elapsed().as_secs_f64()*1000.0;
+            log_event(self.level, self.target, "close",
+                      &[("busy_ms", LogValue::Number(busy))]);
+            SPANS.with(|spans| spans.borrow_mut().pop());
+        }
+    }
+}
@@ -1,8 +1,82 @@
 
 use qm2d_split_op::constants::*;
-use qm2d_split_op::fft::*;
 use qm2d_split_op::complex::*;
 use qm2d_split_op::bitmap::*;
+use qm2d_split_op::potentials::*;
+use qm2d_split_op::expression::*;
+use qm2d_split_op::script::*;
+use qm2d_split_op::plugin::*;
+use qm2d_split_op::config::*;
+use qm2d_split_op::manifest::*;
+use qm2d_split_op::log::*;
+use qm2d_split_op::progress::*;
+use qm2d_split_op::interrupt::*;
+use qm2d_split_op::image::*;
+use qm2d_split_op::envelopes::*;
+use qm2d_split_op::timeseries::*;
+use qm2d_split_op::snapshot::*;
+use qm2d_split_op::observables::*;
+use qm2d_split_op::field::*;
+use qm2d_split_op::spectral::*;
+use qm2d_split_op::streamlines::*;
+use qm2d_split_op::current::*;
+use qm2d_split_op::norm::*;
+use qm2d_split_op::watchdog::*;
+use qm2d_split_op::spectrum::*;
+use qm2d_split_op::scattering::*;
+use qm2d_split_op::detectors::*;
+use qm2d_split_op::phase_space::*;
+use qm2d_split_op::random::*;
+use qm2d_split_op::disorder::*;
+use qm2d_split_op::ensemble::*;
+use qm2d_split_op::bohmian::*;
+use qm2d_split_op::entanglement::*;
+use qm2d_split_op::localization::*;
+use qm2d_split_op::split_op::*;
+use qm2d_split_op::initial_states::*;
+use qm2d_split_op::precision::*;
+use qm2d_split_op::reference::*;
+use qm2d_split_op::resample::*;
+use qm2d_split_op::bench::*;
+use qm2d_split_op::validation::*;
+use qm2d_split_op::convergence::*;
+use qm2d_split_op::reversal::*;
+use qm2d_split_op::distributed::*;
+use qm2d_split_op::out_of_core::*;
+use qm2d_split_op::echo::*;
+use qm2d_split_op::energy_density::*;
+use qm2d_split_op::eigenstates::*;
+use qm2d_split_op::lobpcg::*;
+use qm2d_split_op::stencil::*;
+use qm2d_split_op::classical::*;
+use qm2d_split_op::dipole::*;
+use qm2d_split_op::vortices::*;
+use qm2d_split_op::berry::*;
+use qm2d_split_op::adiabatic::*;
+use qm2d_split_op::quench::*;
+use qm2d_split_op::control::*;
+use qm2d_split_op::sensitivity::*;
+use qm2d_split_op::spinor::*;
+use qm2d_split_op::damping::*;
+use qm2d_split_op::output::colormap::*;
+use qm2d_split_op::output::png::*;
+use qm2d_split_op::output::domain_coloring::*;
+use qm2d_split_op::output::video::*;
+use qm2d_split_op::output::overlay::*;
+use qm2d_split_op::output::surface::*;
+use qm2d_split_op::output::npy::*;
+use qm2d_split_op::output::mat::*;
+use qm2d_split_op::output::vtk::*;
+use qm2d_split_op::output::writer::*;
+#[cfg(feature = "hdf5")]
+use qm2d_split_op::output::hdf5::*;
+use qm2d_split_op::viewer::*;
+use qm2d_split_op::viewer::controls::*;
+use qm2d_split_op::viewer::terminal::*;
+use qm2d_split_op::viewer::web::*;
+use qm2d_split_op::viewer::x11::*;
+use qm2d_split_op::server::*;
+use qm2d_split_op::sweep::*;
 use std::env;
 
 const W_LOW_RES: usize = 32;
@@ -52,6 +126,32 @@ struct WavePacket {
     nx: f32, ny: f32, // Wavenumber in the x and y direction
 }
 
+impl WavePacket {
+    /* The value of the packet at x and y in [0, 1]. */
+    fn value(&self, x: f32, y: f32) -> Complex<f32> {
+        let xt: f32 = x - self.x0;
+        let yt: f32 = y - self.y0;
+        let abs_val: f32 = self.a
+            *f32::exp(-0.5*xt*xt/(self.sx*self.sx))
+            *f32::exp(-0.5*yt*yt/(self.sy*self.sy));
+        let nr = self.nx*x + self.ny*y;
+        return Complex {
+            real: abs_val*f32::cos(2.0*std::f32::consts::PI*nr),
+            imag: abs_val*f32::sin(2.0*std::f32::consts::PI*nr),
+        };
+    }
+}
+
+/* The wave packet the simulation starts from unless a state is given. */
+fn default_wave_packet() -> WavePacket {
+    return WavePacket {a: 25.0, x0: 0.5, y0: 0.2,
+                       sx: 0.07, sy: 0.07,
+                       nx: 0.0,
+                       // ny: 50.0*(N as f32)/512.0,
+                       ny: 60.0,
+                      };
+}
+
 fn init_wave_packet(
     array: &mut [Complex<f32>],
     w: WavePacket) {
@@ -59,44 +159,47 @@ fn init_wave_packet(
         for j in 0..N  {
             let x: f32 = (j as f32)/(N as f32);
             let y: f32 = (i as f32)/(N as f32);
-            let xt: f32 = x - w.x0;
-            let yt: f32 = y - w.y0;
-            let abs_val: f32 = w.a
-                *f32::exp(-0.5*xt*xt/(w.sx*w.sx))
-                *f32::exp(-0.5*yt*yt/(w.sy*w.sy));
-            let nr = w.nx*x + w.ny*y;
-            array[i*N + j] = Complex {
-                real: abs_val*f32::cos(2.0*std::f32::consts::PI*nr),
-                imag: abs_val*f32::sin(2.0*std::f32::consts::PI*nr),
-            };
-
+            array[i*N + j] = w.value(x, y);
         }
     }
 }
 
-/* Initialize the V(x, y) term of the Shrodinger equation. */
-fn init_potential(potential: &mut [Complex<f32>]) {
+/* The characters of POTENTIAL_ASCII without the line breaks. */
+fn potential_low_res() -> Vec<u8> {
     let mut potential_low_res = std::vec::Vec::<u8>::with_capacity(32*16);
-    for i in 0..(W_LOW_RES*H_LOW_RES + H_LOW_RES) {
-        let c: u8 = POTENTIAL_ASCII[i];
+    for &c in POTENTIAL_ASCII.iter().take(W_LOW_RES*H_LOW_RES + H_LOW_RES) {
         if c != b'\n' {
             potential_low_res.push(c);
         }
     }
+    return potential_low_res;
+}
+
+/* The potential drawn by POTENTIAL_ASCII at row i (counted from the top
+of the drawing) and column j of an n by n grid. */
+fn potential_ascii_at(potential_low_res: &[u8], n: usize, i: usize,
+                      j: usize) -> Complex<f32> {
+    let d_i: usize = i/(n/H_LOW_RES);
+    let d_j: usize = j/(n/W_LOW_RES);
+    let c: u8 = potential_low_res[d_i*W_LOW_RES + d_j];
+    let re_phi: f32 = if c == b'#' {
+        (b'.' - c) as f32} else {0.0};
+    let im_phi: f32 = if c == b'I' {
+        (c - b'.') as f32} else {0.0};
+    return Complex {
+        // real: 0.0*re_phi,
+        real: 0.1*re_phi,
+        imag: -im_phi,
+    };
+}
+
+/* Initialize the V(x, y) term of the Shrodinger equation. */
+fn init_potential(potential: &mut [Complex<f32>]) {
+    let potential_low_res: Vec<u8> = potential_low_res();
     for i in 0..N { // Height
         for j in 0..N { // Width
-            let d_i: usize = i/(N/H_LOW_RES);
-            let d_j: usize = j/(N/W_LOW_RES);
-            let c: u8 = potential_low_res[d_i*W_LOW_RES + d_j];
-            let re_phi: f32 = if c == b'#' {
-                (b'.' - c) as f32} else {0.0};
-            let im_phi: f32 = if c == b'I' {
-                (c - b'.') as f32} else {0.0};
-            potential[(N - 1 - i)*N + j] = Complex {
-                // real: 0.0*re_phi,
-                real: 0.1*re_phi,
-                imag: -im_phi,
-            };
+            potential[(N - 1 - i)*N + j]
+                = potential_ascii_at(&potential_low_res, N, i, j);
         }
     }
     /* for i in 0..N {
@@ -129,254 +232,39 @@ fn init_potential(potential: &mut [Complex<f32>]) {
 
 }*/
 
-/* Initialize the square of the momentum values that correspond to the
-real-space simulation domain. These are shifted to match the fft output. */ 
-fn init_momentum_squared(p_squared: &mut [f32]) {
-    for i in 0..N {
-        for j in 0..N {
-            let i_shift: i32 = if i < N/2 {i as i32} 
-                else {-(N as i32) + (i as i32)};
-            let j_shift: i32 = if j < N/2 {j as i32}
-                else {-(N as i32) + (j as i32)};
-            let px: f32 
-                = 2.0*std::f32::consts::PI*(i_shift as f32)/(N as f32);
-            let py: f32 
-                = 2.0*std::f32::consts::PI*(j_shift as f32)/(N as f32);
-            p_squared[i*N + j] = px*px + py*py;
-        }
-    }
-}
-
-
-/* Propagate the wave function psi with free-space periodic boundary
-conditions for time step dt:
-    |psi(dt)> = exp(-i*p_squared*dt/2)|psi(0)>.*/
-fn propagate_kinetic(psi: &mut [Complex<f32>], 
-                     p_squared: &[f32], dt: Complex<f32>,
-                     use_mt: bool) {
-    if use_mt {
-        horizontal_square_fft(false, psi);
-        square_transpose_in_place(psi, N);
-        horizontal_square_fft(false, psi);
-        square_transpose_in_place(psi, N);
-    } else {
-        for i in 0..N {
-            fft_in_place(&mut psi[i*N..(i+1)*N], N);
-        }
-        square_transpose_in_place(psi, N);
-        for i in 0..N {
-            fft_in_place(&mut psi[i*N..(i+1)*N], N);
-        }
-        square_transpose_in_place(psi, N);
+/* Read psi and, if it was saved, the potential from a snapshot file
+of the N by N grid. psi saved on a square grid of another size is
+resampled to N by N without its potential (see src/resample.rs). */
+fn load_snapshot(filename: &str, psi: &mut [Complex<f32>],
+                 potential: &mut [Complex<f32>]) -> std::io::Result<()> {
+    let mut snapshot: Snapshot = Snapshot::load(filename)?;
+    if snapshot.width != N || snapshot.height != N {
+        println!("Resampling {} from {}x{} to {}x{}", filename,
+                 snapshot.width, snapshot.height, N, N);
+        snapshot = resample_snapshot(&snapshot, N, false)
+            .map_err(std::io::Error::other)?;
     }
-    for i in 0..N {
-        for j in 0..N {
-            psi[i*N + j] = psi[i*N + j]*c64exp(
-                Complex {real: 0.0, imag: -0.5*p_squared[i*N + j]} * dt);
-        }
-    }
-    if use_mt {
-        horizontal_square_fft(true, psi);
-        square_transpose_in_place(psi, N);
-        horizontal_square_fft(true, psi);
-        square_transpose_in_place(psi, N);
-    } else {
-        for i in 0..N {
-            ifft_in_place(&mut psi[i*N..(i+1)*N], N);
-        }
-        square_transpose_in_place(psi, N);
-        for i in 0..N {
-            ifft_in_place(&mut psi[i*N..(i+1)*N], N);
-        }
-        square_transpose_in_place(psi, N);
+    psi.copy_from_slice(&snapshot.psi);
+    if let Some(v) = &snapshot.potential {
+        potential.copy_from_slice(v);
     }
+    return Ok(());
 }
 
-struct Nonlinear {
-    square: f32,
-
-}
-
-/* Apply the transformation 
-    exp(-i*(potential + nonlinear(psi))*dt) on psi */
-fn propagate_spatial_terms(
-    psi: &mut [Complex<f32>], 
-    potential: &[Complex<f32>],
-    // vec_potential_x: &[Complex<f32>],
-    // vec_potential_y: &[Complex<f32>],
-    nonlinear: Nonlinear,
-    dt: Complex<f32>) {
-    for i in 0..N {
-        for j in 0..N {
-            let ij: usize = i*N + j;
-            let psi_ij = psi[ij];
-            let potential_ij = potential[ij];
-            let nonlinear_term 
-                = (psi_ij*psi_ij.conj()).scale(nonlinear.square);
-            psi[i*N + j] = psi_ij*c64exp(
-                Complex {real: 0.0, imag: -1.0} 
-                * (potential_ij + nonlinear_term)* dt);
-        }
-    }
-}
-
-/* Dampen the wavefunction inside a region, where the probability
- * current inside this region is used to compute the decay. 
- *
- * References:
- *
- * Wikipedia - Probability current
- * https://en.wikipedia.org/wiki/Probability_current
- *
- * Widipedia - Perfectly matched layer
- * https://en.wikipedia.org/wiki/Perfectly_matched_layer
- */
-fn dampen(psi: &mut [Complex<f32>], dt: f32) {
-    let modi = |val: usize| {
-        return val % N;
-    };
-    let mut jx = std::vec::Vec::<f32>::with_capacity(N*N);
-    let mut jy = std::vec::Vec::<f32>::with_capacity(N*N);
-    for i in 0..N { // height
-        for j in 0..N { // width
-            let y = (i as f32)/(N as f32); 
-            let abs_psi2 = (psi[i*N + j]*psi[i*N + j].conj()).real;
-            if y > 0.9 && abs_psi2 > 1e-30 {
-                let ddx_psi = 
-                    psi[N*i + modi(j+1)] - psi[N*i + modi(j)];
-                let ddy_psi = 
-                    psi[N*modi(i+1) + j] - psi[N*modi(i) + j];
-                let val = 0.05 - f32::abs(y - 0.95);
-                // let val = y - 0.9;
-                // let val = 0.25*f32::exp(-0.5*(y - 0.95)*(y - 0.95)/(0.0225*0.0225));
-                jx.push(val*(psi[i*N + j]*ddx_psi).imag);
-                jy.push(val*(psi[i*N + j]*ddy_psi).imag);
-            } else {
-                jx.push(0.0);
-                jy.push(0.0);
-            }
-        }
-    }
-    for i in 0..N {
-        for j in 0..N {
-            let damp_factor
-                = f32::exp(-0.35*dt*f32::sqrt(jx[N*i + j]*jx[N*i + j]
-                                + jy[N*i + j]*jy[N*i + j]));
-            psi[N*i + j].real *= damp_factor;
-            psi[N*i + j].imag *= damp_factor;
-        }
-    }
-}
-
-struct Color {
-    r: f64, g: f64, b: f64,
-}
-
-/* Function that converts a hue angle to its corresponding color.
-
-References:
-
-Wikipedia - Domain coloring
-https://en.wikipedia.org/wiki/Domain_coloring
-
-Wikipedia - Hue
-https://en.wikipedia.org/wiki/Hue
-
-https://en.wikipedia.org/wiki/Hue#/media/File:HSV-RGB-comparison.svg
-
- */
-fn argument_to_color(arg_val: f64) -> Color {
-    let pi: f64 = std::f64::consts::PI;
-    let max_col: f64 = 1.0;
-    let min_col: f64 = 50.0/255.0;
-    let col_range: f64 = max_col - min_col;
-    if arg_val <= pi/3.0 && arg_val >= 0.0 {
-        return Color {
-            r: max_col,
-            g: min_col + col_range*arg_val/(pi/3.0), 
-            b: min_col};
-    } else if arg_val > pi/3.0 && arg_val <= 2.0*pi/3.0 {
-        return Color {
-            r: max_col - col_range*(arg_val - pi/3.0)/(pi/3.0),
-            g: max_col, 
-            b: min_col};
-    } else if arg_val > 2.0*pi/3.0 && arg_val <= pi {
-        return Color {
-            r: min_col, 
-            g: max_col,
-            b: min_col + col_range*(arg_val - 2.0*pi/3.0)/(pi/3.0)};
-    } else if arg_val < 0.0 && arg_val > -pi/3.0 {
-        return Color {
-            r: max_col, 
-            g: min_col,
-            b: min_col - col_range*arg_val/(pi/3.0)};
-    } else if arg_val <= -pi/3.0 && arg_val > -2.0*pi/3.0 {
-        return Color {
-            r: max_col + (col_range*(arg_val + pi/3.0)/(pi/3.0)),
-            g: min_col, 
-            b: max_col};
-    } else if arg_val <= -2.0*pi/3.0 && arg_val >= -pi {
-        return Color {
-            r: min_col,
-            g: min_col - (col_range*(arg_val + 2.0*pi/3.0)/(pi/3.0)),
-            b: max_col};
-    }
-    else {
-        return Color {r: min_col, g: max_col, b: max_col};
-    }
-}
-
-fn load_f32_simulation_data(psi: &mut [Complex<f32>],
-                            potential: &mut [Complex<f32>],
-                            filename: std::string::String,
-                            ) -> std::io::Result<()> {
-    let sizeof_complex: usize = 2*4;
-    let header_size: usize = 8;
-    let width: u32 = N as u32;
-    let height: u32 = N as u32;
-    let total_size: usize 
-        = header_size + 2*sizeof_complex*((width*height) as usize);
-    use std::io::prelude::*;
-    let mut bytes = std::vec::Vec::<u8>::with_capacity(total_size);
-    let file = std::io::BufReader::new(std::fs::File::open(filename)?);
-    let mut size = 0;
-    // https://doc.rust-lang.org/std/io/trait.Read.html#method.bytes
-    for b in file.bytes() {
-// https://doc.rust-lang.org/rust-by-example/error/result/early_returns.html
-        match b {
-            Ok(val) => bytes.push(val),
-            Err(e) => return Err(e),
-        };
-        size += 1;
-        if size > total_size {
-            break; // TODO!
-            // return Err::<&str, ()>(());
-        }
-    }
-    let width2: u32 = write_u32(&bytes.as_slice()[0..4]);
-    let height2: u32 = write_u32(&bytes.as_slice()[4..8]);
-    // println!("{}, {}", width2, height2);
-    if width2 == width && height2 == height {
-        let arr = &bytes.as_slice()[8..total_size];
-        let s = sizeof_complex*2;
-        for i in 0..width*height {
-            let k = s*(i as usize);
-            psi[i as usize] = Complex {
-                real: write_f32(&arr[k..k+4]),
-                imag: write_f32(&arr[k+4..k+8])};
-            potential[i as usize] = Complex {
-                real: write_f32(&arr[k+8..k+12]), 
-                imag: write_f32(&arr[k+12..k+16])};
-        }
-    } else {
-        // TODO
-    }
-    Ok(())
-
+/* Save psi and the potential at the given step as a snapshot file by
+the writer, compressed at the given level unless it is 0. */
+fn save_snapshot(writer: &OutputWriter, filename: String, step: usize,
+                 dt: Complex<f32>, psi: &[Complex<f32>],
+                 potential: &[Complex<f32>], compression: u32) {
+    let snapshot: Snapshot = Snapshot::new(
+        N, N, step as u64, (step as f64)*(dt.real as f64), dt, psi,
+        Some(potential));
+    writer.submit(move || snapshot.save(
+        &filename, Precision::Single,
+        if compression > 0 {Some(compression)} else {None}));
 }
 
-
-
+#[allow(clippy::too_many_arguments)]
 fn fill_pixel_data(pixels: &mut [u8], pixel_offset: usize,
                    psi: & [Complex<f32>], psi_brightness: f64,
                    phi: & [Complex<f32>], phi_brightness: f64,
@@ -385,7 +273,7 @@ fn fill_pixel_data(pixels: &mut [u8], pixel_offset: usize,
         for j in 0..w {
             let index: usize = i*w + j;
             let abs_val2: f64 = psi[index].length_squared() as f64;
-            let c_psi: Color = argument_to_color(psi[index].arg() as f64);
+            let c_psi: Color = argument_to_color(psi[index].arg());
             let phi_val: f64 = (phi[index].real as f64)*phi_brightness;
             let c = Color {
                 r: phi_val + c_psi.r*abs_val2*psi_brightness,
@@ -400,109 +288,1285 @@ fn fill_pixel_data(pixels: &mut [u8], pixel_offset: usize,
 
 
 
-fn copy_f32(bytes: &mut [u8], val: f32, offset: &mut usize) {
-    let val_arr: [u8; 4] = val.to_ne_bytes();
-    for i in 0..4 {
-        bytes[*offset] = val_arr[i];
-        *offset += 1;
+/* Print the total transmission and reflection probabilities through
+the barrier, and write them for each incident momentum to
+transmission_file as columns p T R. */
+fn write_transmission(config: &Config, barrier: &BarrierRegions,
+                      initial: &[f64], initial_norm: f64,
+                      psi: &[Complex<f32>]) -> std::io::Result<()> {
+    use std::io::Write;
+    let p: [f64; 3] = barrier.probabilities(psi);
+    println!("Transmission: {}, reflection: {}, inside: {}",
+             p[2]/initial_norm, p[0]/initial_norm, p[1]/initial_norm);
+    let mut scratch = psi.to_vec();
+    let transmitted: Vec<f64> = barrier.momentum_distribution(
+        psi, Some(Side::After), scratch.as_mut_slice());
+    let reflected: Vec<f64> = barrier.momentum_distribution(
+        psi, Some(Side::Before), scratch.as_mut_slice());
+    let fname: &str = config.get("transmission_file")
+        .unwrap_or("transmission.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    writeln!(file, "# p T R")?;
+    for (p, t, r) in transmission_by_momentum(initial, &transmitted,
+                                              &reflected, 1e-3) {
+        writeln!(file, "{} {:e} {:e}", p, t, r)?;
+    }
+    return Ok(());
+}
+
+/* Write the spectrum of the autocorrelation function to spectrum_file,
+as columns E S(E), and print the energies of its largest peaks. */
+fn write_energy_spectrum(config: &Config, c: &Autocorrelation
+                         ) -> std::io::Result<()> {
+    use std::io::Write;
+    let window: Window = match Window::parse(
+        config.get("spectrum_window").unwrap_or("hann")) {
+        Ok(w) => w,
+        Err(e) => return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData, e)),
+    };
+    let e_max_default: f32 = std::f32::consts::PI/(c.dt as f32);
+    let spectrum: Vec<(f64, f64)> = energy_spectrum(
+        c, window,
+        config.get_f32("spectrum_energy_min", 0.0)? as f64,
+        config.get_f32("spectrum_energy_max", e_max_default)? as f64,
+        config.get_usize("spectrum_points", 2048)?);
+    let fname: &str = config.get("spectrum_file").unwrap_or("spectrum.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    writeln!(file, "# E S")?;
+    for (e, val) in &spectrum {
+        writeln!(file, "{} {:e}", e, val)?;
+    }
+    println!("Spectrum peaks: {:?}", spectrum_peaks(&spectrum, 0.05));
+    if config.get_bool("harmonic_inversion", false)? {
+        let lines: Vec<SpectralLine> = filter_diagonalization(
+            c, config.get_f32("spectrum_energy_min", 0.0)? as f64,
+            config.get_f32("spectrum_energy_max", e_max_default)? as f64,
+            config.get_usize("harmonic_inversion_basis", 32)?,
+            config.get_f32("harmonic_inversion_tolerance", 1e-3)? as f64)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                             e))?;
+        write_spectral_lines(config.get("harmonic_inversion_file")
+                             .unwrap_or("lines.txt"), &lines)?;
+    }
+    return Ok(());
+}
+
+/* Write the lines found by filter diagonalization to fname, as columns
+E width |d| arg(d) error, and print their energies. */
+fn write_spectral_lines(fname: &str, lines: &[SpectralLine]
+                        ) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    writeln!(file, "# E width amplitude phase error")?;
+    for l in lines.iter() {
+        writeln!(file, "{} {:e} {:e} {} {:e}", l.energy, l.width,
+                 l.amplitude.length_squared().sqrt(),
+                 f64::atan2(l.amplitude.imag, l.amplitude.real), l.error)?;
+    }
+    file.flush()?;
+    println!("Spectral lines: {:?}",
+             lines.iter().map(|l| l.energy).collect::<Vec<f64>>());
+    return Ok(());
+}
+
+/* Write the Gabor spectrogram of the dipole acceleration along
+spectrogram_axis to spectrogram_file. */
+fn write_spectrogram(config: &Config, fname: &str, ax: &[f64], ay: &[f64],
+                     dt: f64) -> std::io::Result<()> {
+    let axis: Axis = match Axis::parse(
+        config.get("spectrogram_axis").unwrap_or("x")) {
+        Ok(a) => a,
+        Err(e) => return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData, e)),
+    };
+    let spectrogram: Spectrogram = gabor_spectrogram(
+        if axis == Axis::X {ax} else {ay}, dt,
+        config.get_f32("spectrogram_width", (100.0*dt) as f32)? as f64,
+        config.get_usize("spectrogram_stride", 10)?,
+        config.get_f32("spectrogram_omega_max",
+                       (std::f64::consts::PI/dt) as f32)? as f64,
+        config.get_usize("spectrogram_points", 256)?);
+    return spectrogram.save_text(fname);
+}
+
+/* Compute the Berry phase around the loop of potentials given by the
+expression berry_V, in which t is the loop parameter from 0 to 1,
+printing it and writing the energy and overlap with the next point at
+each point of the loop to berry_file. */
+fn run_berry_phase(config: &Config, v: &str, p_squared: &[f32]
+                   ) -> std::io::Result<()> {
+    use std::io::Write;
+    let invalid = |e: String| std::io::Error::new(
+        std::io::ErrorKind::InvalidData, format!("berry_V = \"{}\": {}", v, e));
+    let potential: Expression = Expression::parse(v).map_err(invalid)?;
+    let settings = ImaginaryTimeSettings {
+        dt: config.get_f32("berry_dt", 2.0)?,
+        max_steps: config.get_usize("berry_steps", 2000)?,
+        ..Default::default()
+    };
+    let points: usize = config.get_usize("berry_points", 16)?;
+    let result: BerryLoop = berry_phase(
+        &potential, config.get_usize("berry_level", 0)?, points,
+        p_squared, &settings);
+    println!("Berry phase: {}", result.phase);
+    let fname: &str = config.get("berry_file").unwrap_or("berry.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    writeln!(file, "# s energy re_overlap im_overlap")?;
+    for k in 0..result.energies.len() {
+        writeln!(file, "{} {:e} {:e} {:e}",
+                 (k as f64)/(result.energies.len() as f64),
+                 result.energies[k], result.overlaps[k].real,
+                 result.overlaps[k].imag)?;
     }
+    writeln!(file, "# phase {}", result.phase)?;
+    return Ok(());
 }
 
-fn save_f32_simulation_data(filename: std::string::String,
-                            psi: &[Complex<f32>],
-                            potential: &[Complex<f32>]
-                            ) -> std::io::Result<()> {
-    let sizeof_complex: usize = 2*4;
-    let header_size: usize = 8;
-    let width: u32 = N as u32;
-    let height: u32 = N as u32;
-    let total_size: usize 
-        = header_size + 2*sizeof_complex*((width*height) as usize);
-    let mut bytes = std::vec::Vec::<u8>::with_capacity(total_size);
-    for _ in 0..total_size {
-        bytes.push(0);
+/* Set psi to the ground state of the potential given by the
+expression v, found in imaginary time as for eigenstate_count, and
+turn that potential into the one of the run by adiabatic_ramp, writing
+the checks along the way to adiabatic_file. */
+fn prepare_adiabatic(config: &Config, v: &str, psi: &mut [Complex<f32>],
+                     potential: &[Complex<f32>], p_squared: &[f32],
+                     dt: Complex<f32>) -> std::io::Result<()> {
+    use std::io::Write;
+    let invalid = |e: String| std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("adiabatic_V0 = \"{}\": {}", v, e));
+    let mut v0: Vec<Complex<f32>> = potential.to_vec();
+    Expression::parse(v).map_err(invalid)?.fill_at(&mut v0, 0.0);
+    let settings = AdiabaticSettings {
+        steps: config.get_usize("adiabatic_steps", 2000)?,
+        shape: RampShape::parse(config.get("adiabatic_ramp")
+                                .unwrap_or("smooth")).map_err(invalid)?,
+        check_every: config.get_usize("adiabatic_check_every", 100)?,
+        imaginary_time: ImaginaryTimeSettings {
+            dt: config.get_f32("eigenstate_dt", 2.0)?,
+            max_steps: config.get_usize("eigenstate_steps", 2000)?,
+            ..Default::default()
+        },
+        grid: match config.get("adiabatic_grid") {
+            Some(_) => Some(config.get_usize("adiabatic_grid", 128)?),
+            None => None,
+        },
+        ..Default::default()
     };
-    let mut offset: usize = 0;
-    copy_u32(bytes.as_mut_slice(), width, &mut offset);
-    copy_u32(bytes.as_mut_slice(), height, &mut offset);
-    for i in 0..width*height {
-        copy_f32(bytes.as_mut_slice(), psi[i as usize].real, &mut offset);
-        copy_f32(bytes.as_mut_slice(), psi[i as usize].imag, &mut offset);
-        copy_f32(bytes.as_mut_slice(), potential[i as usize].real, &mut offset);
-        copy_f32(bytes.as_mut_slice(), potential[i as usize].imag, &mut offset);
+    let ground: Vec<Eigenstate> = imaginary_time_eigenstates(
+        default_guesses(1), &v0, p_squared, &settings.imaginary_time);
+    println!("Ground state of adiabatic_V0: energy {}", ground[0].energy);
+    psi.copy_from_slice(&ground[0].psi);
+    let checks: Vec<AdiabaticCheck> = adiabatic_ramp(
+        psi, &v0, potential, p_squared, dt, &settings).map_err(invalid)?;
+    let fname: &str = config.get("adiabatic_file").unwrap_or("adiabatic.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    writeln!(file, "# step lambda energy ground_energy gap overlap")?;
+    for c in checks.iter() {
+        writeln!(file, "{} {} {:e} {:e} {:e} {:e}", c.step, c.lambda,
+                 c.energy, c.ground_energy, c.gap, c.overlap)?;
     }
+    if let Some(last) = checks.last() {
+        println!("Adiabatic ramp over {} steps: overlap {} with the ground \
+                  state, energy {} above it", settings.steps, last.overlap,
+                 last.energy - last.ground_energy);
+    }
+    return file.flush();
+}
+
+/* Find the controls of the fields given in control_fields, expressions
+separated by ';', that steer psi into the state in control_target by
+optimize_controls, writing the fidelity after each iteration to
+control_file, the controls to control_pulse_file and the state reached
+to control_state.bin. */
+#[allow(clippy::too_many_arguments)]
+fn run_optimal_control(config: &Config, fields: &str, psi: &[Complex<f32>],
+                       potential: &[Complex<f32>], p_squared: &[f32],
+                       dt: Complex<f32>, writer: &OutputWriter,
+                       compression: u32) -> std::io::Result<()> {
     use std::io::Write;
-    let mut file = std::fs::File::create(filename)?;
-    file.write_all(bytes.as_slice())?;
-    Ok(())
-}
-
-fn write_f32(bytes: &[u8]) -> f32 {
-    let mut val_arr: [u8; 4] = [0; 4];
-    for i in 0..4 {
-        val_arr[i] = bytes[i];
-    }
-    return f32::from_ne_bytes(val_arr);
-}
-
-fn write_u32(bytes: &[u8]) -> u32 {
-    let mut val_arr: [u8; 4] = [0; 4];
-    for i in 0..4 {
-        val_arr[i] = bytes[i];
-    }
-    return u32::from_ne_bytes(val_arr);
-}
-
-// fn load_f32_simulation_data(psi: &mut [Complex<f32>],
-//                             potential: &mut [Complex<f32>],
-//                             filename: std::string::String,
-//                             ) -> std::io::Result<()> {
-//     let sizeof_complex: usize = 2*4;
-//     let header_size: usize = 8;
-//     let width: u32 = N as u32;
-//     let height: u32 = N as u32;
-//     let total_size: usize 
-//         = header_size + 2*sizeof_complex*((width*height) as usize);
-//     use std::io::prelude::*;
-//     let mut bytes = std::vec::Vec::<u8>::with_capacity(total_size);
-//     let file = std::io::BufReader::new(std::fs::File::open(filename)?);
-//     let mut size = 0;
-//     // https://doc.rust-lang.org/std/io/trait.Read.html#method.bytes
-//     for b in file.bytes() {
-// // https://doc.rust-lang.org/rust-by-example/error/result/early_returns.html
-//         match b {
-//             Ok(val) => bytes.push(val),
-//             Err(e) => return Err(e),
-//         };
-//         size += 1;
-//         if size > total_size {
-//             break; // TODO!
-//             // return Err::<&str, ()>(());
-//         }
-//     }
-//     let width2: u32 = write_u32(&bytes.as_slice()[0..4]);
-//     let height2: u32 = write_u32(&bytes.as_slice()[4..8]);
-//     // println!("{}, {}", width2, height2);
-//     if width2 == width && height2 == height {
-//         let arr = &bytes.as_slice()[8..total_size];
-//         let s = sizeof_complex*2;
-//         for i in 0..width*height {
-//             let k = s*(i as usize);
-//             psi[i as usize] = Complex {
-//                 real: write_f32(&arr[k..k+4]),
-//                 imag: write_f32(&arr[k+4..k+8])};
-//             potential[i as usize] = Complex {
-//                 real: write_f32(&arr[k+8..k+12]), 
-//                 imag: write_f32(&arr[k+12..k+16])};
-//         }
-//     } else {
-//         // TODO
-//     }
-//     Ok(())
-
-// }
+    let invalid = |e: String| std::io::Error::new(
+        std::io::ErrorKind::InvalidData, e);
+    let mut grids: Vec<Vec<f32>> = Vec::new();
+    let mut grid: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                           N*N];
+    for f in fields.split(';').filter(|f| !f.trim().is_empty()) {
+        Expression::parse(f).map_err(|e| invalid(format!(
+            "control_fields: \"{}\": {}", f.trim(), e)))?
+            .fill_at(&mut grid, 0.0);
+        grids.push(grid.iter().map(|v| v.real).collect());
+    }
+    let target_file: &str = config.get("control_target").ok_or_else(
+        || invalid(String::from("control_fields needs a state file in \
+                                 control_target")))?;
+    let mut target: Vec<Complex<f32>> = grid.clone();
+    load_snapshot(target_file, &mut target, &mut grid)
+        .map_err(|e| invalid(format!("{}: {}", target_file, e)))?;
+    let problem = ControlProblem {
+        initial: psi.to_vec(), target, potential: potential.to_vec(),
+        fields: grids};
+    let settings = ControlSettings {
+        dt: dt.real,
+        steps: config.get_usize("control_steps", 200)?,
+        slices: config.get_usize("control_slices", 20)?,
+        iterations: config.get_usize("control_iterations", 50)?,
+        step_size: config.get_f32("control_step", 1e-3)? as f64,
+        tolerance: config.get_f32("control_tolerance", 1e-4)? as f64,
+        initial_amplitude: config.get_f32("control_initial", 1e-3)? as f64,
+        seed: config.get_usize("control_seed", 1)? as u64,
+    };
+    let result: ControlResult = optimize_controls(
+        &problem, p_squared, &settings,
+        |iteration, f| println!("Iteration {}: fidelity {}", iteration, f))
+        .map_err(invalid)?;
+    let fname: &str = config.get("control_file").unwrap_or("control.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    writeln!(file, "# update fidelity")?;
+    for (k, f) in result.fidelities.iter().enumerate() {
+        writeln!(file, "{} {:e}", k, f)?;
+    }
+    file.flush()?;
+    // The controls as step functions, one row at the start of each
+    // slice and one at the end of the pulse.
+    let fname: &str = config.get("control_pulse_file")
+        .unwrap_or("pulse.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    let names: Vec<String> = (0..result.controls.len())
+        .map(|k| format!("u{}", k)).collect();
+    writeln!(file, "# t {}", names.join(" "))?;
+    for slice in 0..=settings.slices {
+        let step: usize = slice*settings.steps/settings.slices;
+        let values: Vec<String> = result.controls.iter()
+            .map(|u| format!("{:e}", u[usize::min(slice,
+                                                   settings.slices - 1)]))
+            .collect();
+        writeln!(file, "{} {}", (step as f32)*settings.dt,
+                 values.join(" "))?;
+    }
+    save_snapshot(writer, String::from("control_state.bin"), settings.steps,
+                  dt, &result.psi, potential, compression);
+    println!("Fidelity {} after {} updates", result.fidelities
+             .last().copied().unwrap_or(0.0), result.fidelities.len() - 1);
+    return file.flush();
+}
 
-fn main() {
+/* The derivatives of the observables in sensitivity_observables at the
+end of a run of sensitivity_steps steps by the parameters of the
+potential in sensitivity_V, given with their values as name=value
+separated by ';' in sensitivity_parameters, by sensitivities, written
+to sensitivity_file with, if sensitivity_targets gives measured values
+of the observables, the misfit to them and its gradient. */
+fn run_sensitivity(config: &Config, v: &str, psi: &[Complex<f32>],
+                   p_squared: &[f32], dt: Complex<f32>
+                   ) -> std::io::Result<()> {
+    use std::io::Write;
+    let invalid = |e: String| std::io::Error::new(
+        std::io::ErrorKind::InvalidData, e);
+    let list = |key: &str, default: &str| -> Vec<String> {
+        return config.get(key).unwrap_or(default).split(';')
+            .map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+            .collect();
+    };
+    let mut names: Vec<String> = Vec::new();
+    let mut values: Vec<f32> = Vec::new();
+    for p in list("sensitivity_parameters", "") {
+        let (name, value): (&str, &str) = p.split_once('=').ok_or_else(
+            || invalid(format!("sensitivity_parameters: expected \
+                                name=value, not '{}'", p)))?;
+        names.push(name.trim().to_string());
+        values.push(value.trim().parse::<f32>().map_err(|_| invalid(format!(
+            "sensitivity_parameters: '{}' is not a number", value.trim())))?);
+    }
+    let name_refs: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+    let potential: ParametricExpression = ParametricExpression::parse(
+        v, &name_refs).map_err(|e| invalid(format!("sensitivity_V: {}", e)))?;
+    let observables: Vec<Expression> = list("sensitivity_observables", "x; y")
+        .iter().map(|o| Expression::parse(o).map_err(|e| invalid(format!(
+            "sensitivity_observables: \"{}\": {}", o, e))))
+        .collect::<std::io::Result<Vec<Expression>>>()?;
+    let targets: Vec<f64> = list("sensitivity_targets", "").iter()
+        .map(|t| t.parse::<f64>().map_err(|_| invalid(format!(
+            "sensitivity_targets: '{}' is not a number", t))))
+        .collect::<std::io::Result<Vec<f64>>>()?;
+    if !targets.is_empty() && targets.len() != observables.len() {
+        return Err(invalid(format!(
+            "sensitivity_targets gives {} values for {} observables",
+            targets.len(), observables.len())));
+    }
+    let settings = SensitivitySettings {
+        dt: dt.real, steps: config.get_usize("sensitivity_steps", 200)?};
+    let result: Sensitivities = sensitivities(
+        psi, &potential, &values, &observables, p_squared, &settings)
+        .map_err(invalid)?;
+    let fname: &str = config.get("sensitivity_file")
+        .unwrap_or("sensitivity.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    for (name, value) in names.iter().zip(values.iter()) {
+        writeln!(file, "# {} = {}", name, value)?;
+    }
+    let columns: Vec<String> = names.iter().map(|n| format!("d/d{}", n))
+        .collect();
+    writeln!(file, "# observable value {}", columns.join(" "))?;
+    let row = |label: &str, value: f64, derivatives: &[f64]| -> String {
+        let d: Vec<String> = derivatives.iter().map(|d| format!("{:e}", d))
+            .collect();
+        return format!("\"{}\" {:e} {}", label, value, d.join(" "));
+    };
+    for ((o, value), d) in observables.iter().zip(result.values.iter())
+        .zip(result.derivatives.iter()) {
+        writeln!(file, "{}", row(&o.source, *value, d))?;
+        println!("{}", row(&o.source, *value, d));
+    }
+    if !targets.is_empty() {
+        let (misfit, gradient): (f64, Vec<f64>) = result.misfit(&targets);
+        writeln!(file, "{}", row("misfit", misfit, &gradient))?;
+        println!("{}", row("misfit", misfit, &gradient));
+    }
+    return file.flush();
+}
+
+/* Step a state of several components with the potentials given by the
+expressions of spinor_V, separated by ';', one for each, coupled by the
+interactions of spinor_g and the coupling of spinor_coupling. psi is
+shared between the components as spinor_populations gives. The norm of
+each component and the energy are written every spinor_every steps to
+spinor_file, and the components at the end to spinor_K.bin. */
+fn run_spinor(config: &Config, v: &str, psi: &[Complex<f32>],
+              p_squared: &[f32], dt: Complex<f32>, writer: &OutputWriter,
+              compression: u32) -> std::io::Result<()> {
+    use std::io::Write;
+    let invalid = |e: String| std::io::Error::new(
+        std::io::ErrorKind::InvalidData, e);
+    let expressions: Vec<Expression> = v.split(';')
+        .filter(|e| !e.trim().is_empty())
+        .map(|e| Expression::parse(e).map_err(|err| invalid(format!(
+            "spinor_V: \"{}\": {}", e.trim(), err))))
+        .collect::<std::io::Result<Vec<Expression>>>()?;
+    let n: usize = expressions.len();
+    let matrix = |key: &str| -> std::io::Result<Vec<f64>> {
+        return match config.get(key) {
+            Some(s) => parse_symmetric_matrix(s, n)
+                .map_err(|e| invalid(format!("{}: {}", key, e))),
+            None => Ok(vec![0.0; n*n]),
+        };
+    };
+    let coupling = SpinorCoupling {
+        interactions: matrix("spinor_g")?.iter().map(|g| *g as f32)
+            .collect(),
+        coupling: matrix("spinor_coupling")?};
+    let populations: Vec<f64> = match config.get("spinor_populations") {
+        Some(s) => s.split(';').map(|p| p.trim().parse::<f64>().map_err(
+            |_| invalid(format!("spinor_populations: '{}' is not a number",
+                                p.trim()))))
+            .collect::<std::io::Result<Vec<f64>>>()?,
+        None => (0..n).map(|a| if a == 0 {1.0} else {0.0}).collect(),
+    };
+    if populations.len() != n || populations.iter().any(|p| *p < 0.0) {
+        return Err(invalid(format!("spinor_populations must give {} \
+                                    fractions that are not negative", n)));
+    }
+    let total: f64 = populations.iter().sum();
+    let components: Vec<Vec<Complex<f32>>> = populations.iter()
+        .map(|p| {
+            let a: f32 = if total > 0.0 {(p/total).sqrt() as f32} else {0.0};
+            psi.iter().map(|z| z.scale(a)).collect()
+        }).collect();
+    let mut spinor: Spinor = Spinor::new(components, coupling)
+        .map_err(invalid)?;
+    let mut potentials: Vec<Vec<Complex<f32>>> = vec![
+        vec![Complex {real: 0.0, imag: 0.0}; N*N]; n];
+    let time_dependent: bool = expressions.iter()
+        .any(|e| e.is_time_dependent());
+    let every: usize = config.get_usize("spinor_every", 10)?.max(1);
+    let fname: &str = config.get("spinor_file").unwrap_or("spinor.txt");
+    let mut file = std::io::BufWriter::new(std::fs::File::create(fname)?);
+    let names: Vec<String> = (0..n).map(|a| format!("norm_{}", a)).collect();
+    writeln!(file, "# t {} energy", names.join(" "))?;
+    let mut scratch: Vec<Complex<f32>> = psi.to_vec();
+    for i in 0..=NUMBER_OF_STEPS {
+        let t: f32 = (i as f32)*dt.real;
+        let changed: bool = i == 0 || time_dependent;
+        if changed {
+            for (e, v) in expressions.iter().zip(potentials.iter_mut()) {
+                e.fill_at(v, t);
+            }
+        }
+        if i % every == 0 || i == NUMBER_OF_STEPS {
+            let norms: Vec<String> = spinor.populations().iter()
+                .map(|p| format!("{:e}", p)).collect();
+            writeln!(file, "{} {} {:e}", t, norms.join(" "),
+                     spinor.energy(&potentials, &mut scratch))?;
+        }
+        if i < NUMBER_OF_STEPS {
+            spinor.step(&potentials, p_squared, dt, changed, true);
+        }
+    }
+    for (a, (c, v)) in spinor.components.iter().zip(potentials.iter())
+        .enumerate() {
+        save_snapshot(writer, format!("spinor_{}.bin", a), NUMBER_OF_STEPS,
+                      dt, c, v, compression);
+    }
+    println!("Norms of the components: {:?}", spinor.populations());
+    return file.flush();
+}
+
+/* Step psi as the process of the given rank among those listening on
+the comma separated addresses of cluster_hosts, each with its slab of
+rows, printing the norm every cluster_report_every steps. The first
+process gathers the final state and saves it to last_state.bin. */
+#[allow(clippy::too_many_arguments)]
+fn run_distributed(config: &Config, hosts: &str, rank: usize,
+                   psi: &mut [Complex<f32>], potential: &mut [Complex<f32>],
+                   potential_terms: &TimeDependentSum, p_squared: &[f32],
+                   dt: Complex<f32>) -> std::io::Result<()> {
+    let addresses: Vec<String> = hosts.split(',')
+        .map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+    let timeout = std::time::Duration::from_secs_f32(
+        config.get_f32("cluster_timeout", 60.0)?);
+    let report_every: usize = config.get_usize("cluster_report_every", 100)?
+        .max(1);
+    let cluster: Cluster = Cluster::connect(rank, &addresses, timeout)?;
+    println!("Process {} of {}", rank, addresses.len());
+    let mut slab: SlabSimulation = SlabSimulation::new(
+        cluster, psi, potential, p_squared)?;
+    for i in 0..NUMBER_OF_STEPS {
+        if potential_terms.is_time_dependent() {
+            potential_terms.fill_at(potential, (i as f32)*dt.real);
+            slab.set_potential(potential);
+        }
+        slab.step(dt)?;
+        if (i + 1) % report_every == 0 {
+            let norm: f64 = slab.norm_squared()?;
+            if rank == 0 {
+                println!("Step {}: norm {}", i + 1, norm);
+            }
+        }
+    }
+    slab.gather(psi)?;
+    if rank == 0 {
+        let snapshot: Snapshot = Snapshot::new(
+            N, N, NUMBER_OF_STEPS as u64,
+            (NUMBER_OF_STEPS as f64)*(dt.real as f64), dt, psi,
+            Some(potential));
+        let compression: u32 = config.get_usize("snapshot_compression", 0)?
+            .min(9) as u32;
+        snapshot.save("last_state.bin", Precision::Single,
+                      if compression > 0 {Some(compression)} else {None})?;
+    }
+    return Ok(());
+}
+
+/* The real part of the potential of init_potential on an n by n grid,
+for runs whose grid is not N by N. */
+struct AsciiPotential {
+    low_res: Vec<u8>,
+    n: usize,
+}
+
+impl TimeDependentPotential for AsciiPotential {
+    fn value_at(&self, x: f32, y: f32, _t: f32) -> f32 {
+        let row: usize = usize::min((y*(self.n as f32)) as usize, self.n - 1);
+        let j: usize = usize::min((x*(self.n as f32)) as usize, self.n - 1);
+        return potential_ascii_at(&self.low_res, self.n, self.n - 1 - row, j)
+            .real;
+    }
+
+    fn is_time_dependent(&self) -> bool {
+        return false;
+    }
+}
+
+/* Step the initial wave packet on an out_of_core_size by
+out_of_core_size grid kept in the file out_of_core_file, in slabs of
+out_of_core_slab rows, printing the norm after each step. The potential
+is that of the config, or the real part of the default one. */
+fn run_out_of_core(config: &Config, filename: &str,
+                   potential_terms: &TimeDependentSum, dt: Complex<f32>
+                   ) -> std::io::Result<()> {
+    let n: usize = config.get_usize("out_of_core_size", N)?;
+    let slab_rows: usize = config.get_usize("out_of_core_slab",
+                                            usize::min(64, n))?;
+    let mut grid: OutOfCore = OutOfCore::create(filename, n, slab_rows)?;
+    let packet: WavePacket = default_wave_packet();
+    grid.fill(|x, y| packet.value(x, y))?;
+    let default_potential = AsciiPotential {low_res: potential_low_res(), n};
+    let potential: &dyn TimeDependentPotential
+        = if potential_terms.terms.is_empty() {&default_potential}
+        else {potential_terms};
+    for i in 0..NUMBER_OF_STEPS {
+        let start = std::time::Instant::now();
+        let norm: f64 = grid.step(potential, (i as f32)*dt.real, dt)?;
+        println!("Step {}: norm {}, {:.1} s", i + 1, norm,
+                 start.elapsed().as_secs_f64());
+    }
+    return Ok(());
+}
+
+/* Build the potential from the V and V_image entries of the config,
+each optionally multiplied by a time envelope given by V_envelope
+and V_image_envelope respectively. */
+fn potential_from_config(config: &Config
+                         ) -> std::io::Result<TimeDependentSum> {
+    let invalid = |key: &str, value: &str, e: String| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData,
+                            format!("{} = \"{}\": {}", key, value, e))
+    };
+    let envelope = |key: &str| -> std::io::Result<Option<Envelope>> {
+        return match config.get(key) {
+            None => Ok(None),
+            Some(v) => match Envelope::parse(v) {
+                Ok(e) => Ok(Some(e)),
+                Err(e) => Err(invalid(key, v, e)),
+            },
+        };
+    };
+    let mut terms = std::vec::Vec::<Box<dyn TimeDependentPotential>>::new();
+    if let Some(v) = config.get("V") {
+        let e: Expression = match Expression::parse(v) {
+            Ok(e) => e,
+            Err(e) => return Err(invalid("V", v, e)),
+        };
+        match envelope("V_envelope")? {
+            Some(f) if e.is_time_dependent() => terms.push(Box::new(
+                ModulatedInTime {potential: Box::new(e), envelope: f})),
+            Some(f) => terms.push(Box::new(Modulated::new(&e, f))),
+            None => terms.push(Box::new(e)),
+        };
+    }
+    if let Some(fname) = config.get("V_image") {
+        let settings = ImagePotentialSettings {
+            scale: config.get_f32("V_image_scale", 1.0)?,
+            offset: config.get_f32("V_image_offset", 0.0)?,
+            invert: config.get_bool("V_image_invert", false)?,
+            smoothing: config.get_f32("V_image_smoothing", 0.0)?,
+        };
+        let p = image_to_potential(&load_image(fname)?, settings);
+        let f: Envelope = envelope("V_image_envelope")?
+            .unwrap_or(Envelope::Constant(1.0));
+        terms.push(Box::new(Modulated {potential: p, envelope: f}));
+    }
+    if let Some(values) = disorder_from_config(config)?.potential_values() {
+        terms.push(Box::new(Modulated {
+            potential: GridPotential {values},
+            envelope: Envelope::Constant(1.0)}));
+    }
+    return Ok(TimeDependentSum {terms});
+}
+
+/* The random realization of the config: a disordered potential of
+standard deviation disorder_strength and a random phase of psi of
+standard deviation disorder_phase, correlated over disorder_correlation
+and drawn from disorder_seed. */
+fn disorder_from_config(config: &Config) -> std::io::Result<Disorder> {
+    return Ok(Disorder {
+        seed: config.get_usize("disorder_seed", 1)? as u64,
+        correlation: config.get_f32("disorder_correlation", 0.01)?,
+        potential: config.get_f32("disorder_strength", 0.0)?,
+        phase: config.get_f32("disorder_phase", 0.0)?,
+    });
+}
+
+/* Save psi as the dataset psi/step_XXXXX of the HDF5 file. */
+#[cfg(feature = "hdf5")]
+fn write_hdf5_psi(writer: &mut Hdf5Writer, step: usize, dt: Complex<f32>,
+                  psi: &[Complex<f32>]) -> std::io::Result<()> {
+    return writer.write_dataset(
+        &format!("psi/step_{:05}", step), &[N, N], Hdf5Data::Complex(psi),
+        &[("step", Hdf5Attribute::I64(step as i64)),
+          ("t", Hdf5Attribute::F64((step as f64)*(dt.real as f64)))]);
+}
+
+/* Save each column of the observables, and their times as t, as
+datasets of the group observables of the HDF5 file. */
+#[cfg(feature = "hdf5")]
+fn write_hdf5_observables(writer: &mut Hdf5Writer, series: &TimeSeries
+                          ) -> std::io::Result<()> {
+    let n: usize = series.times.len();
+    writer.write_dataset("observables/t", &[n], Hdf5Data::F64(&series.times),
+                         &[])?;
+    for name in series.names.iter() {
+        let values: Vec<f64> = series.column(name).unwrap_or_default();
+        writer.write_dataset(&format!("observables/{}", name), &[n],
+                             Hdf5Data::F64(&values), &[])?;
+    }
+    return Ok(());
+}
+
+/* The settings of the PNG frames of the config (png_*), which the video
+and the render subcommand draw frames with as well. */
+fn png_frames_from_config(config: &Config) -> PngFrames {
+    return PngFrames {
+        every: config.get_usize("png_every", 0)
+            .unwrap_or_else(|e| exit_with_error(e)),
+        style: match config.get("png_style").unwrap_or("density") {
+            "density" => FrameStyle::Density(
+                match config.get("png_colormap_file") {
+                    Some(f) => Colormap::load(f)
+                        .unwrap_or_else(|e| exit_with_error(e)),
+                    None => Colormap::parse(config.get("png_colormap")
+                                            .unwrap_or("viridis"))
+                        .unwrap_or_else(|e| exit_with_error(e)),
+                }),
+            "phase" => FrameStyle::Phase,
+            s => exit_with_error(format!(
+                "unknown png_style '{}', expected density or phase", s)),
+        },
+        space: FrameSpace::parse(config.get("png_space")
+                                 .unwrap_or("position"))
+            .unwrap_or_else(|e| exit_with_error(e)),
+        prefix: String::from(SAVE_DIRECTORY)
+            + config.get("png_prefix").unwrap_or("density_"),
+        scale: config.get_f32("png_scale", 0.0)
+            .unwrap_or_else(|e| exit_with_error(e)),
+        overlay: PotentialOverlay::parse(
+            config.get("png_potential").unwrap_or("none"),
+            config.get_usize("png_potential_levels", 8)
+                .unwrap_or_else(|e| exit_with_error(e)),
+            config.get_f32("png_potential_opacity", 0.5)
+                .unwrap_or_else(|e| exit_with_error(e)))
+            .unwrap_or_else(|e| exit_with_error(e)),
+        surface: if config.get_bool("png_surface", false)
+            .unwrap_or_else(|e| exit_with_error(e)) {
+            let default = Camera::default();
+            Some(Camera {
+                azimuth: config.get_f32("png_surface_azimuth",
+                                        default.azimuth.to_degrees())
+                    .unwrap_or_else(|e| exit_with_error(e)).to_radians(),
+                elevation: config.get_f32("png_surface_elevation",
+                                          default.elevation.to_degrees())
+                    .unwrap_or_else(|e| exit_with_error(e)).to_radians(),
+                distance: config.get_f32("png_surface_distance",
+                                         default.distance)
+                    .unwrap_or_else(|e| exit_with_error(e)),
+            })
+        } else {
+            None
+        },
+        streamlines: if config.get_bool("png_streamlines", false)
+            .unwrap_or_else(|e| exit_with_error(e)) {
+            streamline_spacing(config)
+        } else {
+            0
+        },
+        streamline_min_current: streamline_min_current(config),
+    };
+}
+
+/* The distance in grid points between the seeds of current streamlines,
+for the streamline files and frames. */
+fn streamline_spacing(config: &Config) -> usize {
+    return config.get_usize("streamline_spacing", 32)
+        .unwrap_or_else(|e| exit_with_error(e));
+}
+
+/* The fraction of the largest current below which streamlines stop. */
+fn streamline_min_current(config: &Config) -> f32 {
+    return config.get_f32("streamline_min_current", 0.01)
+        .unwrap_or_else(|e| exit_with_error(e));
+}
+
+/* Find the count lowest eigenstates of the potential as set by
+eigenstate_method: by imaginary time propagation, with eigenstate_dt and
+eigenstate_steps, by LOBPCG on the coarser grid of eigenstate_grid points
+a side, or both, comparing their energies and keeping those found in
+imaginary time. They are saved as eigenstate_K.bin. */
+fn find_eigenstates(config: &Config, count: usize,
+                    potential: &[Complex<f32>], p_squared: &[f32],
+                    writer: &OutputWriter, dt: Complex<f32>,
+                    compression: u32) -> Vec<Eigenstate> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let method: &str = config.get("eigenstate_method")
+        .unwrap_or("imaginary_time");
+    if !["imaginary_time", "lobpcg", "both"].contains(&method) {
+        exit_with_error(format!("unknown eigenstate_method '{}', expected \
+                                 imaginary_time, lobpcg or both", method));
+    }
+    let sparse: Option<Vec<Eigenstate>> = if method != "imaginary_time" {
+        let size: usize = config.get_usize("eigenstate_grid", 128)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let order: DifferenceOrder = DifferenceOrder::parse(
+            config.get("eigenstate_order").unwrap_or("4"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        let boundary: StencilBoundary = StencilBoundary::parse(
+            config.get("eigenstate_boundary").unwrap_or("dirichlet"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        let settings = LobpcgSettings {
+            count,
+            tolerance: config.get_f32("eigenstate_tolerance", 1e-8)
+                .unwrap_or_else(|e| exit_with_error(e)) as f64,
+            max_iterations: config.get_usize("eigenstate_iterations", 5000)
+                .unwrap_or_else(|e| exit_with_error(e)),
+            ..Default::default()
+        };
+        println!("Finding {} eigenstates by LOBPCG on a {}x{} grid", count,
+                 size, size);
+        Some(stencil_eigenstates(potential, size, order, boundary,
+                                 &settings)
+             .unwrap_or_else(|e| exit_with_error(e)))
+    } else {
+        None
+    };
+    let found: Vec<Eigenstate> = match sparse {
+        Some(states) if method == "lobpcg" => states,
+        sparse => {
+            let settings = ImaginaryTimeSettings {
+                dt: config.get_f32("eigenstate_dt", 2.0)
+                    .unwrap_or_else(|e| exit_with_error(e)),
+                max_steps: config.get_usize("eigenstate_steps", 2000)
+                    .unwrap_or_else(|e| exit_with_error(e)),
+                ..Default::default()
+            };
+            println!("Finding {} eigenstates in imaginary time", count);
+            let found: Vec<Eigenstate> = imaginary_time_eigenstates(
+                default_guesses(count), potential, p_squared, &settings);
+            if let Some(states) = sparse {
+                println!("{:>10} {:>16} {:>16} {:>12}", "eigenstate",
+                         "imaginary time", "LOBPCG", "difference");
+                for (k, (a, b)) in found.iter().zip(states.iter())
+                    .enumerate() {
+                    println!("{:>10} {:>16.9} {:>16.9} {:>12.3e}", k,
+                             a.energy, b.energy, b.energy - a.energy);
+                }
+            }
+            found
+        },
+    };
+    for (k, phi) in found.iter().enumerate() {
+        println!("Eigenstate {}: energy {}", k, phi.energy);
+        save_snapshot(writer, format!("eigenstate_{}.bin", k), 0, dt,
+                      phi.psi.as_slice(), potential, compression);
+    }
+    return found;
+}
+
+/* Save the potential, psi at the start and end of the run and the
+observables with their times, if recorded, to an npz file, laid out
+as in the HDF5 file. */
+fn write_npz(fname: &str, initial_psi: &[Complex<f32>], psi: &[Complex<f32>],
+             potential: &[Complex<f32>], observables: &Option<TimeSeries>
+             ) -> std::io::Result<()> {
+    let mut npz = NpzWriter::create(fname)?;
+    npz.add("potential", &[N, N], NpyData::Complex(potential))?;
+    npz.add("psi_initial", &[N, N], NpyData::Complex(initial_psi))?;
+    npz.add("psi_final", &[N, N], NpyData::Complex(psi))?;
+    if let Some(series) = observables {
+        npz.add("observables/t", &[series.times.len()],
+                NpyData::F64(&series.times))?;
+        for name in series.names.iter() {
+            let values: Vec<f64> = series.column(name).unwrap_or_default();
+            npz.add(&format!("observables/{}", name), &[values.len()],
+                    NpyData::F64(&values))?;
+        }
+    }
+    return npz.finish();
+}
+
+/* Save the run as write_npz does to a MATLAB file, with the columns of
+the observables as observables_<name>, any characters MATLAB does not
+allow in names replaced by underscores, and the grid and time step. */
+fn write_mat(fname: &str, initial_psi: &[Complex<f32>], psi: &[Complex<f32>],
+             potential: &[Complex<f32>], observables: &Option<TimeSeries>,
+             dt: Complex<f32>) -> std::io::Result<()> {
+    let mut mat = MatWriter::create(fname)?;
+    let grid: Vec<f64> = (0..N).map(|k| (k as f64)/(N as f64)).collect();
+    mat.add("x", &[1, N], MatData::F64(&grid))?;
+    mat.add("y", &[N], MatData::F64(&grid))?;
+    mat.add("dt", &[1, 2], MatData::F64(&[dt.real as f64, dt.imag as f64]))?;
+    mat.add("potential", &[N, N], MatData::Complex(potential))?;
+    mat.add("psi_initial", &[N, N], MatData::Complex(initial_psi))?;
+    mat.add("psi_final", &[N, N], MatData::Complex(psi))?;
+    if let Some(series) = observables {
+        mat.add("observables_t", &[series.times.len()],
+                MatData::F64(&series.times))?;
+        for name in series.names.iter() {
+            let values: Vec<f64> = series.column(name).unwrap_or_default();
+            let name: String = name.chars().map(
+                |c| if c.is_ascii_alphanumeric() {c} else {'_'}).collect();
+            mat.add(&format!("observables_{}", name), &[values.len()],
+                    MatData::F64(&values))?;
+        }
+    }
+    return mat.finish();
+}
+
+/* Save psi at the start and end of the run as QuTiP kets, with the
+operators of the Hamiltonian, to an npz file. The kets have the shape
+(N*N, 1) and the dimensions in dims, [[N, N], [1, 1]], the first factor
+being y, for Qobj(f["psi_final"], dims=f["dims"].tolist()). The
+potential V is diagonal on the grid, and the kinetic energy diagonal in
+the basis of numpy.fft.fft2, as kinetic with the shape (N, N). */
+fn write_qutip(fname: &str, initial_psi: &[Complex<f32>],
+               psi: &[Complex<f32>], potential: &[Complex<f32>],
+               p_squared: &[f32], dt: Complex<f32>) -> std::io::Result<()> {
+    let mut npz = NpzWriter::create(fname)?;
+    npz.add("dims", &[2, 2], NpyData::I64(&[N as i64, N as i64, 1, 1]))?;
+    npz.add("psi_initial", &[N*N, 1], NpyData::Complex(initial_psi))?;
+    npz.add("psi_final", &[N*N, 1], NpyData::Complex(psi))?;
+    npz.add("V", &[N*N], NpyData::Complex(potential))?;
+    let kinetic: Vec<f32> = p_squared.iter().map(|p| 0.5*p).collect();
+    npz.add("kinetic", &[N, N], NpyData::F32(&kinetic))?;
+    npz.add("dt", &[2], NpyData::F64(&[dt.real as f64, dt.imag as f64]))?;
+    return npz.finish();
+}
+
+/* The options of the render and analyze subcommands, --config, and one
+taking a file name, with the snapshot files that follow. */
+fn snapshot_command_args(args: &[String], option: &str
+                         ) -> (Config, Option<String>, Vec<String>) {
+    let mut config: Config = Config::default();
+    let mut value: Option<String> = None;
+    let mut files: Vec<String> = Vec::new();
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            config = Config::load(&args.next().unwrap_or_default())
+                .unwrap_or_else(|e| exit_with_error(e));
+        } else if arg == option {
+            value = Some(args.next().unwrap_or_default());
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        exit_with_error("no snapshot files given");
+    }
+    return (config, value, files);
+}
+
+/* Load a snapshot of an N by N grid, with the potential zero if it does
+not hold one. */
+fn load_grid_snapshot(filename: &str) -> Snapshot {
+    let snapshot: Snapshot = Snapshot::load(filename)
+        .and_then(|s| s.check_size(N, N).map(|_| s))
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", filename, e)));
+    return snapshot;
+}
+
+/* render [--config settings.txt] [--record out.mp4] snapshots...: draw
+each snapshot as the PNG frames of a run are drawn, to a PNG file of
+the same name, or as the frames of a video with --record. */
+fn run_render(args: &[String]) {
+    let (config, video_file, files) = snapshot_command_args(args, "--record");
+    let png_frames: PngFrames = png_frames_from_config(&config);
+    let mut video: Option<VideoRecorder> = video_file.as_ref().map(|f| {
+        let framerate: f32 = config.get_f32("record_framerate", 30.0)
+            .unwrap_or_else(|e| exit_with_error(e));
+        VideoRecorder::start(f, N, N, framerate)
+            .unwrap_or_else(|e| exit_with_error(e))
+    });
+    let zero: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+    for filename in files.iter() {
+        let snapshot: Snapshot = load_grid_snapshot(filename);
+        let rgb: Vec<u8> = png_frames.render(
+            png_frames.space, &snapshot.psi,
+            snapshot.potential.as_deref().unwrap_or(&zero));
+        match video.as_mut() {
+            Some(v) => v.write_frame(&rgb)
+                .unwrap_or_else(|e| exit_with_error(e)),
+            None => {
+                let png: String = std::path::Path::new(filename)
+                    .with_extension("png").to_string_lossy().into_owned();
+                std::fs::write(&png, encode_png_rgb(N, N, &rgb))
+                    .unwrap_or_else(|e| exit_with_error(
+                        format!("{}: {}", png, e)));
+                println!("Saved {}", png);
+            },
+        }
+    }
+    if let Some(v) = video {
+        v.finish().unwrap_or_else(|e| exit_with_error(e));
+        println!("Saved {}", video_file.unwrap_or_default());
+    }
+}
+
+/* analyze [--config settings.txt] [--output analysis.txt] snapshots...:
+write the expectation values and uncertainties of each snapshot, at its
+time, as the observables of a run are written, in its potential (or in
+the potential of the config, if it gives one). */
+fn run_analyze(args: &[String]) {
+    let (config, output, files) = snapshot_command_args(args, "--output");
+    let fname: String = output.unwrap_or_else(|| "analysis.txt".to_string());
+    let mut sink: Box<dyn TimeSeriesSink> = if fname.ends_with(".csv") {
+        Box::new(CsvSeriesWriter::create(&fname, 1)
+                 .unwrap_or_else(|e| exit_with_error(e)))
+    } else {
+        Box::new(TextSeriesWriter::create(&fname)
+                 .unwrap_or_else(|e| exit_with_error(e)))
+    };
+    let names: Vec<&str> = Expectations::names().iter()
+        .chain(Uncertainties::names().iter()).copied().collect();
+    sink.write_header(&names).unwrap_or_else(|e| exit_with_error(e));
+    let potential_terms: TimeDependentSum = potential_from_config(&config)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let mut potential: Vec<Complex<f32>>
+        = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+    let mut scratch: Vec<Complex<f32>> = potential.clone();
+    for filename in files.iter() {
+        let snapshot: Snapshot = load_grid_snapshot(filename);
+        match &snapshot.potential {
+            Some(v) => potential.copy_from_slice(v),
+            None => potential.fill(Complex {real: 0.0, imag: 0.0}),
+        }
+        if !potential_terms.terms.is_empty() {
+            potential_terms.fill_at(&mut potential, snapshot.t as f32);
+        }
+        let e: Expectations = compute_expectations(
+            &snapshot.psi, &potential, 0.0, &mut scratch);
+        let u: Uncertainties = Uncertainties::from_expectations(&e);
+        let values: Vec<f64> = e.values().iter().chain(u.values().iter())
+            .copied().collect();
+        sink.write_row(snapshot.t, &values)
+            .unwrap_or_else(|e| exit_with_error(e));
+    }
+    println!("Saved {}", fname);
+}
+
+/* sweep [--config base.txt] --param key=values... [--jobs J]
+[--dir sweep] [--output sweep.csv]: run the simulation for every
+combination of the values of the parameters (see src/sweep.rs). */
+fn run_sweep(options: &[String]) {
+    let mut sweep = Sweep {
+        base: String::new(), parameters: Vec::new(),
+        jobs: Sweep::default_jobs(), directory: "sweep".to_string(),
+        output: "sweep.csv".to_string()};
+    let mut options = options.iter().cloned();
+    while let Some(option) = options.next() {
+        let value: String = options.next().unwrap_or_else(|| exit_with_error(
+            format!("{} needs a value", option)));
+        match option.as_str() {
+            "--config" => sweep.base = std::fs::read_to_string(&value)
+                .unwrap_or_else(|e| exit_with_error(format!("{}: {}", value, e))),
+            "--param" => sweep.parameters.push(SweepParameter::parse(&value)
+                .unwrap_or_else(|e| exit_with_error(e))),
+            "--jobs" => sweep.jobs = value.parse::<usize>().ok()
+                .filter(|j| *j > 0).unwrap_or_else(|| exit_with_error(
+                    "--jobs must be followed by a positive number")),
+            "--dir" => sweep.directory = value,
+            "--output" => sweep.output = value,
+            _ => exit_with_error(format!("unknown option {}\n\n{}", option,
+                                         USAGE)),
+        }
+    }
+    if sweep.parameters.is_empty() {
+        exit_with_error("a sweep needs at least one --param key=values");
+    }
+    // The base config is checked before starting any jobs.
+    Config::parse(&sweep.base).unwrap_or_else(|e| exit_with_error(e));
+    let program: std::path::PathBuf = std::env::current_exe()
+        .and_then(|p| p.canonicalize())
+        .unwrap_or_else(|e| exit_with_error(e));
+    let failed: usize = sweep.run(&program)
+        .unwrap_or_else(|e| exit_with_error(e));
+    println!("Saved {}", sweep.output);
+    if failed > 0 {
+        exit_with_error(format!("{} jobs failed, see log.txt in their \
+                                 directories", failed));
+    }
+}
+
+/* ensemble [--config base.txt] --realizations M [--seed S] [--jobs J]
+[--dir ensemble] [--output ensemble.csv]: run M realizations differing
+only in disorder_seed, from S on, averaging their observables (see
+src/ensemble.rs). */
+/* reference [--size n] [--steps S] [--dt DT] [--output file.npz] steps
+a Gaussian in a harmonic well on an n by n grid in single, double and
+double-double precision, and prints the errors of the first two against
+the last (see src/reference.rs). */
+fn run_reference(options: &[String]) {
+    let mut size: usize = 64;
+    let mut steps: usize = 100;
+    let mut dt: f64 = 0.5;
+    let mut output: Option<String> = None;
+    let mut options = options.iter().cloned();
+    while let Some(option) = options.next() {
+        let value: String = options.next().unwrap_or_else(|| exit_with_error(
+            format!("{} needs a value", option)));
+        let number = || value.parse::<usize>().unwrap_or_else(
+            |_| exit_with_error(format!("{} must be followed by a number",
+                                        option)));
+        match option.as_str() {
+            "--size" => size = number(),
+            "--steps" => steps = number(),
+            "--dt" => dt = value.parse::<f64>().unwrap_or_else(
+                |_| exit_with_error("--dt must be followed by a number")),
+            "--output" => output = Some(value),
+            _ => exit_with_error(format!("unknown option {}\n\n{}", option,
+                                         USAGE)),
+        }
+    }
+    let (initial, potential): (Vec<Complex<f32>>, Vec<f32>)
+        = harmonic_reference_scenario(size);
+    let run: ReferenceRun = ReferenceRun::new(&initial, &potential, size, dt,
+                                              steps)
+        .unwrap_or_else(|e| exit_with_error(e));
+    print!("{}", run.report());
+    if let Some(fname) = output {
+        let shape: [usize; 2] = [size, size];
+        let write = || -> std::io::Result<()> {
+            let mut npz: NpzWriter = NpzWriter::create(&fname)?;
+            npz.add("potential", &shape, NpyData::F32(&run.potential))?;
+            npz.add("psi_initial", &shape, NpyData::Complex(&run.initial))?;
+            npz.add("psi_reference", &shape,
+                    NpyData::Complex128(&run.reference_f64()))?;
+            npz.add("psi_single", &shape, NpyData::Complex(&run.single))?;
+            npz.add("psi_double", &shape, NpyData::Complex128(&run.double))?;
+            npz.add("dt", &[1], NpyData::F64(&[run.dt]))?;
+            return npz.finish();
+        };
+        write().unwrap_or_else(|e| exit_with_error(format!("{}: {}", fname, e)));
+        println!("Saved {}", fname);
+    }
+}
+
+/* fdm [--min E] [--max E] [--basis K] [--tolerance e] [--output file]
+autocorrelation.txt finds the lines of the C(t) recorded by a run in
+autocorrelation_file by filter diagonalization (see src/spectrum.rs),
+from E 0 to pi/dt by default, and writes them to file, lines.txt by
+default. */
+fn run_fdm(options: &[String]) {
+    let mut e_min: Option<f64> = None;
+    let mut e_max: Option<f64> = None;
+    let mut basis: usize = 32;
+    let mut tolerance: f64 = 1e-3;
+    let mut output: String = String::from("lines.txt");
+    let mut input: Option<String> = None;
+    let mut options = options.iter().cloned();
+    while let Some(option) = options.next() {
+        if !option.starts_with("--") {
+            input = Some(option);
+            continue;
+        }
+        let value: String = options.next().unwrap_or_else(|| exit_with_error(
+            format!("{} needs a value", option)));
+        let number = || value.parse::<f64>().unwrap_or_else(
+            |_| exit_with_error(format!("{} must be followed by a number",
+                                        option)));
+        match option.as_str() {
+            "--min" => e_min = Some(number()),
+            "--max" => e_max = Some(number()),
+            "--basis" => basis = number() as usize,
+            "--tolerance" => tolerance = number(),
+            "--output" => output = value,
+            _ => exit_with_error(format!("unknown option {}\n\n{}", option,
+                                         USAGE)),
+        }
+    }
+    let input: String = input.unwrap_or_else(|| exit_with_error(format!(
+        "fdm needs an autocorrelation file\n\n{}", USAGE)));
+    let c: Autocorrelation = Autocorrelation::load(&input)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let lines: Vec<SpectralLine> = filter_diagonalization(
+        &c, e_min.unwrap_or(0.0),
+        e_max.unwrap_or(std::f64::consts::PI/c.dt), basis, tolerance)
+        .unwrap_or_else(|e| exit_with_error(e));
+    write_spectral_lines(&output, &lines)
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", output, e)));
+    println!("Saved {}", output);
+}
+
+/* resample --size S [--potential] [--output file] snapshot writes the
+snapshot with psi resampled to an S by S grid, with the potential too
+if --potential, to file, by default the name of the snapshot with _S
+added, or psi alone if file ends with .npy (see src/resample.rs). */
+fn run_resample(options: &[String]) {
+    let mut size: Option<usize> = None;
+    let mut with_potential: bool = false;
+    let mut output: Option<String> = None;
+    let mut input: Option<String> = None;
+    let mut options = options.iter().cloned();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--size" => size = Some(options.next().and_then(
+                |v| v.parse::<usize>().ok()).unwrap_or_else(
+                || exit_with_error("--size must be followed by a number"))),
+            "--potential" => with_potential = true,
+            "--output" => output = Some(options.next().unwrap_or_else(
+                || exit_with_error("--output needs a value"))),
+            _ if input.is_none() && !option.starts_with("--") =>
+                input = Some(option),
+            _ => exit_with_error(format!("unknown option {}\n\n{}", option,
+                                         USAGE)),
+        }
+    }
+    let (size, input): (usize, String) = match (size, input) {
+        (Some(size), Some(input)) => (size, input),
+        _ => exit_with_error(format!("resample needs --size and a snapshot\n\n{}",
+                                     USAGE)),
+    };
+    let snapshot: Snapshot = Snapshot::load(&input)
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", input, e)));
+    let resampled: Snapshot = resample_snapshot(&snapshot, size, with_potential)
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", input, e)));
+    let fname: String = output.unwrap_or_else(|| {
+        let stem: &str = input.strip_suffix(".bin").unwrap_or(&input);
+        format!("{}_{}.bin", stem, size)
+    });
+    let written: std::io::Result<()> = if fname.ends_with(".npy") {
+        save_npy(&fname, &[size, size], NpyData::Complex(&resampled.psi))
+    } else {
+        resampled.save(&fname, Precision::Single, None)
+    };
+    written.unwrap_or_else(|e| exit_with_error(format!("{}: {}", fname, e)));
+    println!("Resampled {} from {}x{} to {}x{} as {}", input, snapshot.width,
+             snapshot.height, size, size, fname);
+}
+
+fn run_ensemble(options: &[String]) {
+    let mut realizations: usize = 0;
+    let mut first_seed: usize = 1;
+    let mut sweep = Sweep {
+        base: String::new(), parameters: Vec::new(),
+        jobs: Sweep::default_jobs(), directory: "ensemble".to_string(),
+        output: "ensemble.csv".to_string()};
+    let mut options = options.iter().cloned();
+    while let Some(option) = options.next() {
+        let value: String = options.next().unwrap_or_else(|| exit_with_error(
+            format!("{} needs a value", option)));
+        let number = || value.parse::<usize>().unwrap_or_else(
+            |_| exit_with_error(format!("{} must be followed by a number",
+                                        option)));
+        match option.as_str() {
+            "--config" => sweep.base = std::fs::read_to_string(&value)
+                .unwrap_or_else(|e| exit_with_error(format!("{}: {}", value, e))),
+            "--realizations" => realizations = number(),
+            "--seed" => first_seed = number(),
+            "--jobs" => sweep.jobs = usize::max(1, number()),
+            "--dir" => sweep.directory = value,
+            "--output" => sweep.output = value,
+            _ => exit_with_error(format!("unknown option {}\n\n{}", option,
+                                         USAGE)),
+        }
+    }
+    if realizations == 0 {
+        exit_with_error("an ensemble needs --realizations M, at least 1");
+    }
+    Config::parse(&sweep.base).unwrap_or_else(|e| exit_with_error(e));
+    sweep.parameters.push(SweepParameter {
+        key: "disorder_seed".to_string(),
+        values: (first_seed..first_seed + realizations)
+            .map(|seed| seed.to_string()).collect()});
+    let program: std::path::PathBuf = std::env::current_exe()
+        .and_then(|p| p.canonicalize())
+        .unwrap_or_else(|e| exit_with_error(e));
+    // The average is written again as each realization finishes.
+    let average: std::sync::Mutex<EnsembleAverage>
+        = std::sync::Mutex::new(EnsembleAverage::default());
+    let done = |k: usize, ok: bool| {
+        if !ok {
+            return;
+        }
+        let fname: std::path::PathBuf
+            = sweep.job_directory(k).join("observables.csv");
+        let result: Result<(), String> = std::fs::read_to_string(&fname)
+            .map_err(|e| e.to_string())
+            .and_then(|text| match average.lock() {
+                Ok(mut average) => {
+                    average.add_csv(&text)?;
+                    std::fs::write(&sweep.output, average.to_csv())
+                        .map_err(|e| e.to_string())
+                },
+                Err(_) => Err("the average failed".to_string()),
+            });
+        if let Err(e) = result {
+            println!("{}: {}", fname.display(), e);
+        }
+    };
+    let succeeded: Vec<bool> = sweep.run_jobs(&program, &done)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let failed: usize = succeeded.iter().filter(|ok| !**ok).count();
+    println!("Saved {}, averaging {} realizations", sweep.output,
+             realizations - failed);
+    if failed > 0 {
+        exit_with_error(format!("{} realizations failed, see log.txt in \
+                                 their directories", failed));
+    }
+}
+
+const USAGE: &str = "\
+Usage: qm2d_split_op [command] [options]
+       [--log-level off|error|warn|info|debug|trace] [--log-json]
+       [--log-file run.log]
+
+Commands:
+    run       step the simulation (the default, if no command is given)
+              [--config settings.txt] [--record out.mp4]
+              [--view | --tui | --web] [--precision single|double]
+              [--rank R] [last_state.bin]
+    eigen     find eigenstates of the potential of the config, saved as
+              eigenstate_K.bin [--config settings.txt] [state.bin]
+    reversal  step the initial state of the config forward and back,
+              reporting how far it is from where it started and the
+              change in norm over each step [--config settings.txt]
+              [state.bin]
+    render    draw snapshots as PNG images, or as a video with --record
+              [--config settings.txt] [--record out.mp4] snapshots...
+    analyze   write the observables of snapshots to a file
+              [--config settings.txt] [--output analysis.txt] snapshots...
+    sweep     run the simulation over a grid of settings
+              [--config base.txt] --param key=a,b,c --param key=start:stop:count
+              [--jobs J] [--dir sweep] [--output sweep.csv]
+    ensemble  average the observables of runs with different disorder_seed
+              [--config base.txt] --realizations M [--seed S] [--jobs J]
+              [--dir ensemble] [--output ensemble.csv]
+    bench     time the kernels [filter]
+    validate  compare runs of scenarios with their exact answers [filter]
+    convergence
+              fit the order of convergence in dt and dx [filter]
+    reference compare single and double precision steps with a reference in
+              double-double precision on a small grid
+              [--size 64] [--steps 100] [--dt 0.5] [--output reference.npz]
+    resample  resample the psi of a snapshot to another grid size
+              --size S [--potential] [--output state_S.bin | psi.npy]
+              snapshot
+    fdm       find the energies and widths of the lines of a recorded C(t)
+              by filter diagonalization [--min 0] [--max pi/dt]
+              [--basis 32] [--tolerance 1e-3] [--output lines.txt]
+              autocorrelation.txt
+    serve     control simulations over HTTP [address]
+    help      print this message
+";
+
+fn exit_with_error(e: impl std::fmt::Display) -> ! {
+    eprintln!("{}", e);
+    std::process::exit(1);
+}
+
+/* Set up logging from the --log-level, --log-json and --log-file
+options, which can be given with any command, and return the other
+arguments (see src/log.rs). */
+fn logging_from_args(args: &[String]) -> Vec<String> {
+    let mut level: LogLevel = LogLevel::Warn;
+    let mut json: bool = false;
+    let mut file: Option<String> = None;
+    let mut rest: Vec<String> = Vec::new();
+    let mut input_args = args.iter().cloned();
+    while let Some(arg) = input_args.next() {
+        if arg == "--log-level" {
+            level = LogLevel::parse(&input_args.next().unwrap_or_default())
+                .unwrap_or_else(|e| exit_with_error(e));
+        } else if arg == "--log-json" {
+            json = true;
+        } else if arg == "--log-file" {
+            file = Some(input_args.next().unwrap_or_default());
+        } else {
+            rest.push(arg);
+        }
+    }
+    init_logging(level, json, file.as_deref())
+        .unwrap_or_else(|e| exit_with_error(format!(
+            "{}: {}", file.unwrap_or_default(), e)));
+    return rest;
+}
+
+/* run [options] [state.bin], and the eigen and reversal subcommands,
+which start from the same state and potential: step psi as the config
+and options give, or do what replaces the run, and return the exit code
+of the process, so that the outputs are written and closed before it
+exits. args are all the arguments, for the manifest. */
+fn run_simulation(command: &str, options: &[String], args: Vec<String>
+                  ) -> i32 {
+    let mut timer = PhaseTimer::default();
 
     let mut boxed_pixels: Box<[u8; 54 + 3*N*N]> 
         = Box::new([0; 54 + 3*N*N]);
@@ -555,39 +1619,1341 @@ fn main() {
 
     // https://doc.rust-lang.org/book/
     //   ch12-01-accepting-command-line-arguments.html
-    let mut input_args: Vec<String> = env::args().collect();
-    if input_args.len() > 1 {
-        let fname = input_args.pop();
-        match load_f32_simulation_data(psi_vec.as_mut_slice(),
-                                       potential_vec.as_mut_slice(),
-                                       fname.unwrap()) {
-            Ok(a) => a,
-            Err(e) => println!("{}", e),
-        };
+    let mut config: Config = Config::default();
+    let mut state_file: Option<String> = None;
+    let mut video_file: Option<String> = None;
+    let mut view: bool = false;
+    let mut tui: bool = false;
+    let mut web: bool = false;
+    let mut rank: usize = 0;
+    let mut precision: StepPrecision = StepPrecision::Single;
+    let mut input_args = options.iter().cloned();
+    while let Some(arg) = input_args.next() {
+        if arg == "--config" {
+            let fname: String = input_args.next().unwrap_or_default();
+            config = Config::load(&fname)
+                .unwrap_or_else(|e| exit_with_error(e));
+        } else if arg == "--record" {
+            video_file = Some(input_args.next().unwrap_or_default());
+        } else if arg == "--view" {
+            view = true;
+        } else if arg == "--tui" {
+            tui = true;
+        } else if arg == "--web" {
+            web = true;
+        } else if arg == "--precision" {
+            precision = StepPrecision::parse(
+                &input_args.next().unwrap_or_default())
+                .unwrap_or_else(|e| exit_with_error(e));
+        } else if arg == "--rank" {
+            rank = input_args.next().unwrap_or_default().parse::<usize>()
+                .unwrap_or_else(|_| exit_with_error(
+                    "--rank must be followed by a number"));
+        } else if arg.starts_with("--") {
+            exit_with_error(format!("unknown option {}\n\n{}", arg, USAGE));
+        } else {
+            state_file = Some(arg);
+        }
+    }
+    // How to carry on from last_state.bin if the run is interrupted: the
+    // same options, with it in place of any state file given.
+    let mut resume_args: Vec<String> = vec![
+        env::args().next().unwrap_or_else(|| "qm2d_split_op".to_string()),
+        "run".to_string()];
+    resume_args.extend(options.iter().filter(|a| Some(*a) != state_file.as_ref())
+                       .cloned());
+    resume_args.push("last_state.bin".to_string());
+    let initial_state_setting: &str = config.get("initial_state")
+        .unwrap_or("wave_packet");
+    let initial_state: InitialState = parse_initial_state(
+        initial_state_setting).unwrap_or_else(|e| exit_with_error(format!(
+            "initial_state = \"{}\": {}", initial_state_setting, e)));
+    // Whether psi is loaded or given by the script rather than starting
+    // in initial_state.
+    let mut psi_given: bool = state_file.is_some();
+    if let Some(fname) = state_file {
+        if let Err(e) = load_snapshot(&fname, psi_vec.as_mut_slice(),
+                                      potential_vec.as_mut_slice()) {
+            exit_with_error(format!("{}: {}", fname, e));
+        }
     } else {
-        init_wave_packet(psi_vec.as_mut_slice(), 
-                         WavePacket {a: 25.0, x0: 0.5, y0: 0.2,
-                         sx: 0.07, sy: 0.07, 
-                         nx: 0.0, 
-                         // ny: 50.0*(N as f32)/512.0,
-                         ny: 60.0,
-                        });
         init_potential(potential_vec.as_mut_slice());
     }
+    // A potential given in the config replaces the real part of the
+    // default (or loaded) potential.
+    let potential_terms: TimeDependentSum = potential_from_config(&config)
+        .unwrap_or_else(|e| exit_with_error(e));
+    if !potential_terms.terms.is_empty() {
+        potential_terms.fill_at(potential_vec.as_mut_slice(), 0.0);
+    }
+    // A script gives the potential, the initial state and events during
+    // the run, replacing those of the config (see src/script.rs).
+    let mut script: Option<Script> = config.get("script_file").map(
+        |fname| Script::load(fname).unwrap_or_else(|e| exit_with_error(e)));
+    if let Some(s) = script.as_ref() {
+        psi_given |= s.init_psi(psi_vec.as_mut_slice());
+        if let Some(e) = s.potential() {
+            e.fill_at(potential_vec.as_mut_slice(), 0.0);
+        }
+    }
+    // Plugins add their potentials on top of the others, which are kept
+    // to add them to again if they change with time (see src/plugin.rs).
+    let plugins: Plugins = Plugins::load_directory(
+        config.get("plugin_dir").unwrap_or("plugins"))
+        .unwrap_or_else(|e| exit_with_error(e));
+    for plugin in plugins.plugins.iter() {
+        println!("Loaded plugin {}", plugin.name);
+    }
+    let mut plugin_base: Vec<Complex<f32>> = if plugins.is_time_dependent() {
+        potential_vec.clone()} else {Vec::new()};
+    plugins.add_potential(potential_vec.as_mut_slice(), 0.0);
+    // Quenches change the potential or the interaction at the times
+    // given in quenches, with the observables just before and after each
+    // and sampled every quench_every steps until the next written to
+    // quench_file at the end of the run (see src/quench.rs).
+    let mut quenches: Option<QuenchSchedule> = config.get("quenches").map(
+        |q| QuenchSchedule::parse(q).unwrap_or_else(
+            |e| exit_with_error(format!("quenches = \"{}\": {}", q, e))));
+    if !psi_given {
+        match initial_state {
+            InitialState::WavePacket => init_wave_packet(
+                psi_vec.as_mut_slice(), default_wave_packet()),
+            InitialState::PlaneWave {a, nx, ny} => init_plane_wave(
+                psi_vec.as_mut_slice(), a, nx, ny),
+            InitialState::MomentumWavePacket(w) => init_momentum_wave_packet(
+                psi_vec.as_mut_slice(), w),
+            // The profile is that of the potential so far and of the g of
+            // the quenches at t = 0, the interaction the run starts with.
+            InitialState::ThomasFermi {norm} => {
+                let g: f32 = quenches.as_ref().map_or(0.0, |q| q.g_at(0.0));
+                let mu: f32 = init_thomas_fermi(
+                    psi_vec.as_mut_slice(), potential_vec.as_slice(), g,
+                    norm).unwrap_or_else(|e| exit_with_error(format!(
+                        "initial_state = \"{}\": {}", initial_state_setting,
+                        e)));
+                log_event(LogLevel::Info, "run", "thomas_fermi", &[
+                    ("mu", mu.into()), ("g", g.into())]);
+            },
+        };
+    }
+    // The vortices of initial_vortices are imprinted on psi, whether it
+    // was loaded or not (see src/initial_states.rs).
+    if let Some(v) = config.get("initial_vortices") {
+        let vortices: Vec<Vortex> = parse_vortices(v).unwrap_or_else(
+            |e| exit_with_error(format!("initial_vortices = \"{}\": {}", v,
+                                        e)));
+        imprint_vortices(psi_vec.as_mut_slice(), &vortices);
+    }
+    disorder_from_config(&config).unwrap_or_else(|e| exit_with_error(e))
+        .apply_phase(psi_vec.as_mut_slice());
     init_momentum_squared(p_squared_vec.as_mut_slice());
+    // Snapshots (last_state.bin, eigenstates and those saved from the
+    // viewer) are compressed at this zlib level, if it is not 0.
+    let snapshot_compression: u32 = config.get_usize(
+        "snapshot_compression", 0).unwrap_or_else(|e| exit_with_error(e))
+        .min(9) as u32;
+    // Frames, snapshots and observables are written by a thread of their
+    // own, so that the steps only wait for the disk once output_queue
+    // files are waiting to be written. With output_queue 0 they are
+    // written between steps instead.
+    let output_queue: usize = config.get_usize("output_queue", 8)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let writer = std::sync::Arc::new(OutputWriter::new(output_queue));
+
+    // The eigen subcommand finds the eigenstates instead of running, as
+    // many as eigenstate_count, or 4 if it is not set.
+    if command == "eigen" {
+        let count: usize = config.get_usize("eigenstate_count", 4)
+            .unwrap_or_else(|e| exit_with_error(e));
+        find_eigenstates(&config, count, potential_vec.as_slice(),
+                         p_squared_vec.as_slice(), &writer, dt,
+                         snapshot_compression);
+        writer.finish();
+        return 0;
+    }
+
+    // The reversal subcommand steps the initial state reversal_steps
+    // times forward and back with each stepper instead of running.
+    if command == "reversal" {
+        let steps: usize = config.get_usize("reversal_steps", 20)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let tolerance: f64 = config.get_f32("reversal_tolerance", 1e-4)
+            .unwrap_or_else(|e| exit_with_error(e)) as f64;
+        let checks: Vec<ReversalCheck> = run_reversal_checks(
+            psi_vec.as_slice(), potential_vec.as_slice(),
+            p_squared_vec.as_slice(), dt, steps);
+        print!("{}", reversal_report(&checks, tolerance));
+        if checks.iter().any(|c| c.return_error > tolerance) {
+            return 1;
+        }
+        return 0;
+    }
+
+    // Computing a Berry phase replaces the run.
+    if let Some(v) = config.get("berry_V") {
+        run_berry_phase(&config, v, p_squared_vec.as_slice())
+            .unwrap_or_else(|e| exit_with_error(e));
+        return 0;
+    }
+
+    // Optimizing a control pulse replaces the run.
+    if let Some(fields) = config.get("control_fields") {
+        run_optimal_control(&config, fields, psi_vec.as_slice(),
+                            potential_vec.as_slice(), p_squared_vec.as_slice(),
+                            dt, &writer, snapshot_compression)
+            .unwrap_or_else(|e| exit_with_error(e));
+        writer.finish();
+        return 0;
+    }
+
+    // Finding the sensitivities to the parameters of a potential
+    // replaces the run.
+    if let Some(v) = config.get("sensitivity_V") {
+        run_sensitivity(&config, v, psi_vec.as_slice(),
+                        p_squared_vec.as_slice(), dt)
+            .unwrap_or_else(|e| exit_with_error(e));
+        return 0;
+    }
+
+    // Stepping a state of several components replaces the run.
+    if let Some(v) = config.get("spinor_V") {
+        run_spinor(&config, v, psi_vec.as_slice(), p_squared_vec.as_slice(),
+                   dt, &writer, snapshot_compression)
+            .unwrap_or_else(|e| exit_with_error(e));
+        writer.finish();
+        return 0;
+    }
+
+    // Stepping a grid kept in a file replaces the run.
+    if let Some(fname) = config.get("out_of_core_file") {
+        run_out_of_core(&config, fname, &potential_terms, dt)
+            .unwrap_or_else(|e| exit_with_error(e));
+        return 0;
+    }
+
+    // Splitting the steps between processes replaces the run.
+    if let Some(hosts) = config.get("cluster_hosts") {
+        run_distributed(&config, hosts, rank, psi_vec.as_mut_slice(),
+                        potential_vec.as_mut_slice(), &potential_terms,
+                        p_squared_vec.as_slice(), dt)
+            .unwrap_or_else(|e| exit_with_error(e));
+        return 0;
+    }
+
+    // An adiabatic ramp from adiabatic_V0 to the potential of the run
+    // prepares the initial state in place of the one given.
+    if let Some(v) = config.get("adiabatic_V0") {
+        prepare_adiabatic(&config, v, psi_vec.as_mut_slice(),
+                          potential_vec.as_slice(), p_squared_vec.as_slice(),
+                          dt)
+            .unwrap_or_else(|e| exit_with_error(e));
+    }
+
+    // The norm is tracked every step, except for imaginary time steps
+    // where it is not conserved.
+    let norm_tolerance: f64 = config.get_f32("norm_tolerance", 1e-3)
+        .unwrap_or_else(|e| exit_with_error(e)) as f64;
+    let norm_action: NormAction = NormAction::parse(
+        config.get("norm_action").unwrap_or("log"))
+        .unwrap_or_else(|e| exit_with_error(e));
+    let mut norm_monitor: Option<NormMonitor> = if dt.imag == 0.0 {
+        Some(NormMonitor::new(psi_vec.as_slice(), norm_tolerance,
+                              norm_action))
+    } else {
+        None
+    };
+    let has_absorbing_potential: bool
+        = potential_vec.iter().any(|v| v.imag != 0.0);
+
+    // If a barrier is given, the probabilities before, inside and after
+    // it are recorded with the other observables, and the transmission
+    // and reflection coefficients are found at the end of the run.
+    let barrier: Option<BarrierRegions> = config.get("barrier_axis")
+        .map(|a| BarrierRegions {
+            axis: Axis::parse(a).unwrap_or_else(|e| exit_with_error(e)),
+            start: config.get_f32("barrier_start", 0.0)
+                .unwrap_or_else(|e| exit_with_error(e)),
+            end: config.get_f32("barrier_end", 0.0)
+                .unwrap_or_else(|e| exit_with_error(e)),
+        });
+    let initial_momentum: Option<(Vec<f64>, f64)> = barrier.map(|b| {
+        let mut scratch = psi_vec.clone();
+        (b.momentum_distribution(psi_vec.as_slice(), None,
+                                 scratch.as_mut_slice()),
+         norm_squared(psi_vec.as_slice()))
+    });
+
+    // Expectation values are recorded every observables_every steps,
+    // if this is set, together with the inverse participation ratio and
+    // localization length if localization = true, and <L_z> if
+    // angular_momentum = true.
+    let observables_every: usize = config.get_usize("observables_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let localization: bool = config.get_bool("localization", false)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let localization_floor: f64 = config.get_f32("localization_floor", 1e-8)
+        .unwrap_or_else(|e| exit_with_error(e)) as f64;
+    let angular_momentum_origin: Option<(f32, f32)> = if config.get_bool(
+        "angular_momentum", false).unwrap_or_else(|e| exit_with_error(e)) {
+        Some((config.get_f32("angular_momentum_x0", 0.5)
+              .unwrap_or_else(|e| exit_with_error(e)),
+              config.get_f32("angular_momentum_y0", 0.5)
+              .unwrap_or_else(|e| exit_with_error(e))))
+    } else {
+        None
+    };
+    let mut derivative_scratch = std::vec::Vec::<Complex<f32>>::new();
+    if angular_momentum_origin.is_some() {
+        derivative_scratch.resize(2*N*N, Complex {real: 0.0, imag: 0.0});
+    }
+    // With the hdf5 feature the potential, psi at the start and end and
+    // every hdf5_every steps, and the observables are also saved to
+    // hdf5_file, if set.
+    let hdf5_file: Option<String> = config.get("hdf5_file")
+        .map(|f| f.to_string());
+    if hdf5_file.is_some() && !cfg!(feature = "hdf5") {
+        exit_with_error("hdf5_file needs a build with --features hdf5");
+    }
+    // psi is saved as npy_prefix followed by the step padded to five
+    // digits every npy_every steps, if set, and the potential, psi at the
+    // start and end and the observables to npz_file, if set.
+    let npy_every: usize = config.get_usize("npy_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let npy_prefix: String = String::from(SAVE_DIRECTORY)
+        + config.get("npy_prefix").unwrap_or("psi_");
+    let npz_file: Option<String> = config.get("npz_file")
+        .map(|f| f.to_string());
+    let mat_file: Option<String> = config.get("mat_file")
+        .map(|f| f.to_string());
+    let qutip_file: Option<String> = config.get("qutip_file")
+        .map(|f| f.to_string());
+    let mut observables_memory: Option<TimeSeries> = None;
+    // An observables_file ending in .csv is written as comma separated
+    // values, flushed every observables_flush_every rows.
+    let mut observables_sink: Option<Box<dyn TimeSeriesSink>> = None;
+    let mut scratch_vec = std::vec::Vec::<Complex<f32>>::new();
+    if observables_every > 0 {
+        let fname: &str = config.get("observables_file")
+            .unwrap_or("observables.txt");
+        let mut sink: Box<dyn TimeSeriesSink + Send> = if fname.ends_with(
+            ".csv") {
+            let flush_every: usize = config.get_usize(
+                "observables_flush_every", 1)
+                .unwrap_or_else(|e| exit_with_error(e));
+            Box::new(CsvSeriesWriter::create(fname, flush_every)
+                     .unwrap_or_else(|e| exit_with_error(e)))
+        } else {
+            Box::new(TextSeriesWriter::create(fname)
+                     .unwrap_or_else(|e| exit_with_error(e)))
+        };
+        let mut names: Vec<&str> = Expectations::names().iter()
+            .chain(Uncertainties::names().iter())
+            .chain(["absorbed", "norm_drift"].iter())
+            .chain(if barrier.is_some() {
+                ["p_before", "p_inside", "p_after"].iter()} else {[].iter()})
+            .chain(if localization {
+                ["ipr", "localization_length"].iter()} else {[].iter()})
+            .chain(if angular_momentum_origin.is_some() {
+                ["lz"].iter()} else {[].iter()})
+            .copied().collect();
+        names.extend(plugins.observable_names());
+        sink.write_header(&names).unwrap_or_else(|e| exit_with_error(e));
+        observables_sink = Some(Box::new(QueuedSink::new(sink,
+                                                         writer.clone())));
+        if hdf5_file.is_some() || npz_file.is_some() || mat_file.is_some() {
+            let mut memory = TimeSeries::default();
+            memory.write_header(&names)
+                .unwrap_or_else(|e| exit_with_error(e));
+            observables_memory = Some(memory);
+        }
+        scratch_vec.resize(N*N, Complex {real: 0.0, imag: 0.0});
+    }
+    let mut uncertainty_warned: bool = false;
+    let mut record_observables = |step: usize, psi: &[Complex<f32>],
+                                  potential: &[Complex<f32>], g: f32,
+                                  monitor: &Option<NormMonitor>| {
+        if let Some(sink) = observables_sink.as_mut() {
+            let e: Expectations = compute_expectations(
+                psi, potential, g, scratch_vec.as_mut_slice());
+            let u: Uncertainties = Uncertainties::from_expectations(&e);
+            log_event(LogLevel::Debug, "run", "observables", &[
+                ("step", step.into()), ("norm", e.norm.into()),
+                ("energy", e.total.into()), ("x", e.x.into()),
+                ("y", e.y.into())]);
+            if let Some(warning) = u.check(1e-3) {
+                if !uncertainty_warned {
+                    log_event(LogLevel::Warn, "run", "uncertainty",
+                              &[("step", step.into()),
+                                ("warning", warning.clone().into())]);
+                    println!("Warning at step {}: {}", step, warning);
+                    uncertainty_warned = true;
+                }
+            }
+            let norm_values: [f64; 2] = match monitor {
+                Some(m) => [m.absorbed, m.relative_drift()],
+                None => [0.0, 0.0],
+            };
+            let barrier_values: Vec<f64> = match barrier {
+                Some(b) => b.probabilities(psi).to_vec(),
+                None => vec![],
+            };
+            let localization_values: Vec<f64> = if localization {
+                vec![inverse_participation_ratio(psi),
+                     localization_length(psi, localization_floor)
+                     .unwrap_or(f64::NAN)]
+            } else {
+                vec![]
+            };
+            let lz_values: Vec<f64> = match angular_momentum_origin {
+                Some((x0, y0)) => {
+                    let (ddx, ddy) = derivative_scratch.split_at_mut(N*N);
+                    vec![angular_momentum(psi, x0, y0, ddx, ddy)]
+                },
+                None => vec![],
+            };
+            let values: Vec<f64> = e.values().iter()
+                .chain(u.values().iter())
+                .chain(norm_values.iter())
+                .chain(barrier_values.iter())
+                .chain(localization_values.iter())
+                .chain(lz_values.iter())
+                .chain(plugins.observables(psi, potential).iter())
+                .copied().collect();
+            let t: f64 = (step as f64)*(dt.real as f64);
+            if let Err(e) = sink.write_row(t, &values) {
+                eprintln!("{}", e);
+            }
+            if let Some(memory) = observables_memory.as_mut() {
+                let _ = memory.write_row(t, &values);
+            }
+        }
+    };
+    record_observables(0, psi_vec.as_slice(), potential_vec.as_slice(), 0.0,
+                       &norm_monitor);
+    #[cfg(feature = "hdf5")]
+    let hdf5_every: usize = config.get_usize("hdf5_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    #[cfg(feature = "hdf5")]
+    let hdf5: Option<std::sync::Arc<std::sync::Mutex<Hdf5Writer>>>
+        = hdf5_file.as_ref().map(|f| {
+        let mut writer = Hdf5Writer::create(f)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let attributes: [(&str, Hdf5Attribute); 4] = [
+            ("N", Hdf5Attribute::I64(N as i64)),
+            ("dt_real", Hdf5Attribute::F64(dt.real as f64)),
+            ("dt_imag", Hdf5Attribute::F64(dt.imag as f64)),
+            ("grid", Hdf5Attribute::Text(
+                "psi[i, j] and potential[i, j] are at x = j/N, y = i/N, \
+                 with x and y in [0, 1)".to_string()))];
+        for (name, value) in attributes {
+            writer.set_group_attribute("", name, value)
+                .unwrap_or_else(|e| exit_with_error(e));
+        }
+        writer.write_dataset(
+            "potential", &[N, N], Hdf5Data::Complex(potential_vec.as_slice()),
+            &[("description", Hdf5Attribute::Text(
+                "real part V(x, y) at t = 0, imaginary part absorbing"
+                .to_string()))])
+            .unwrap_or_else(|e| exit_with_error(e));
+        write_hdf5_psi(&mut writer, 0, dt, psi_vec.as_slice())
+            .unwrap_or_else(|e| exit_with_error(e));
+        std::sync::Arc::new(std::sync::Mutex::new(writer))
+    });
+
+    // If autocorrelation_file is given, C(t) = <psi(0)|psi(t)> and the
+    // survival probability |C(t)/C(0)|^2 are recorded every step and the
+    // spectrum of C(t) is written at the end.
+    let mut autocorrelation: Option<(Autocorrelation, Vec<Complex<f32>>,
+                                     TextSeriesWriter<_>)> = None;
+    if let Some(fname) = config.get("autocorrelation_file") {
+        let mut sink = TextSeriesWriter::create(fname)
+            .unwrap_or_else(|e| exit_with_error(e));
+        sink.write_header(&["re_c", "im_c", "survival"])
+            .unwrap_or_else(|e| exit_with_error(e));
+        let mut c = Autocorrelation::new(dt.real as f64);
+        c.record(psi_vec.as_slice(), psi_vec.as_slice());
+        let _ = sink.write_row(0.0, &[c.values[0].real, c.values[0].imag,
+                                      1.0]);
+        autocorrelation = Some((c, psi_vec.clone(), sink));
+    }
+
+    // The populations of eigenstates in the wave function are written
+    // every step to populations_file. The eigenstates are either loaded
+    // from the files listed in eigenstates, saved by earlier runs, or
+    // found by imaginary time propagation in the initial potential if
+    // eigenstate_count is set, in which case they are saved as
+    // eigenstate_K.bin for reuse.
+    let mut eigenstates = std::vec::Vec::<Eigenstate>::new();
+    if let Some(files) = config.get("eigenstates") {
+        let mut scratch = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+        for fname in files.split(',').map(|f| f.trim()) {
+            let mut phi = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+            let mut phi_potential = phi.clone();
+            load_snapshot(fname, phi.as_mut_slice(),
+                          phi_potential.as_mut_slice())
+                .unwrap_or_else(|e| exit_with_error(
+                    format!("{}: {}", fname, e)));
+            normalize(phi.as_mut_slice(), 1.0);
+            let energy: f64 = compute_expectations(
+                phi.as_slice(), potential_vec.as_slice(), 0.0,
+                scratch.as_mut_slice()).total;
+            eigenstates.push(Eigenstate {energy, psi: phi});
+        }
+    }
+    let eigenstate_count: usize = config.get_usize("eigenstate_count", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    eigenstates.extend(find_eigenstates(
+        &config, eigenstate_count, potential_vec.as_slice(),
+        p_squared_vec.as_slice(), &writer, dt, snapshot_compression));
+    let mut populations_sink: Option<TextSeriesWriter<_>> = None;
+    if !eigenstates.is_empty() {
+        let mut sink = TextSeriesWriter::create(
+            config.get("populations_file").unwrap_or("populations.txt"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        let names: Vec<String> = (0..eigenstates.len())
+            .map(|k| format!("p{}", k)).collect();
+        let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+        sink.write_header(&names).unwrap_or_else(|e| exit_with_error(e));
+        let _ = sink.write_row(0.0, &populations(&eigenstates,
+                                                 psi_vec.as_slice()));
+        populations_sink = Some(sink);
+    }
+
+    // If echo_V is given, a second copy of the wave function is evolved
+    // with echo_V added to the potential, and the fidelity between the
+    // two copies is written every step to echo_file.
+    let mut echo: Option<(LoschmidtEcho, TextSeriesWriter<_>)> = None;
+    if let Some(v) = config.get("echo_V") {
+        let perturbation: Expression = Expression::parse(v)
+            .unwrap_or_else(|e| exit_with_error(
+                format!("echo_V = \"{}\": {}", v, e)));
+        let mut sink = TextSeriesWriter::create(
+            config.get("echo_file").unwrap_or("echo.txt"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        sink.write_header(&["fidelity"])
+            .unwrap_or_else(|e| exit_with_error(e));
+        let _ = sink.write_row(0.0, &[1.0]);
+        echo = Some((LoschmidtEcho::new(psi_vec.as_slice(),
+                                        Box::new(perturbation)), sink));
+    }
+
+    // The probability current is saved every current_every steps, if
+    // this is set, keeping every current_stride-th point.
+    let current_every: usize = config.get_usize("current_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let current_stride: usize = config.get_usize("current_stride", 16)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let current_svg: bool = config.get_bool("current_svg", false)
+        .unwrap_or_else(|e| exit_with_error(e));
+    // Streamlines of the current are saved every streamline_every steps,
+    // if set.
+    let streamline_every: usize = config.get_usize("streamline_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let streamline_spacing: usize = streamline_spacing(&config);
+    let streamline_min_current: f32 = streamline_min_current(&config);
+    // The density, phase, potential and current are saved for ParaView
+    // every vtk_every steps, if set, as vtk_prefix followed by the frame
+    // number padded to five digits.
+    let vtk_every: usize = config.get_usize("vtk_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let vtk_prefix: String = String::from(SAVE_DIRECTORY)
+        + config.get("vtk_prefix").unwrap_or("fields_");
+
+    // Virtual detectors, given as "ring x0 y0 radius" or
+    // "line x0 y0 x1 y1" separated by ';', accumulate the outgoing flux
+    // by momentum every detector_every steps.
+    let mut detectors = std::vec::Vec::<VirtualDetector>::new();
+    if let Some(d) = config.get("detectors") {
+        let momentum_bins: usize = config.get_usize(
+            "detector_momentum_bins", 128)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let momentum_max: f32 = config.get_f32(
+            "detector_momentum_max", std::f32::consts::PI)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let angle_bins: usize = config.get_usize("detector_angle_bins", 180)
+            .unwrap_or_else(|e| exit_with_error(e));
+        if momentum_bins == 0 || angle_bins == 0 {
+            exit_with_error("detector_momentum_bins and detector_angle_bins \
+                             must be at least 1");
+        }
+        for s in d.split(';').filter(|s| !s.trim().is_empty()) {
+            let shape: DetectorShape = DetectorShape::parse(s)
+                .unwrap_or_else(|e| exit_with_error(e));
+            detectors.push(VirtualDetector::new(shape, momentum_bins,
+                                                momentum_max, angle_bins));
+        }
+    }
+    let detector_every: usize = usize::max(
+        1, config.get_usize("detector_every", 1)
+        .unwrap_or_else(|e| exit_with_error(e)));
+
+    // If trajectories is set, this many Bohmian trajectories are started
+    // from points sampled from the initial density, and their positions
+    // are written every step to trajectories_file.
+    let mut trajectories: Option<(Trajectories, TextSeriesWriter<_>)> = None;
+    let trajectory_count: usize = config.get_usize("trajectories", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    if trajectory_count > 0 {
+        let seed: usize = config.get_usize("trajectories_seed", 1)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let mut random = Random::new(seed as u64);
+        let t = Trajectories::new(sample_density(
+            psi_vec.as_slice(), trajectory_count, &mut random));
+        let mut sink = TextSeriesWriter::create(
+            config.get("trajectories_file").unwrap_or("trajectories.txt"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        let names: Vec<String> = t.names();
+        let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+        sink.write_header(&names).unwrap_or_else(|e| exit_with_error(e));
+        let _ = sink.write_row(0.0, &t.values());
+        trajectories = Some((t, sink));
+    }
+
+    // Reading the grid as two particles on a line, the entanglement
+    // between them is written every entanglement_every steps, if set.
+    // Each sample diagonalizes an N by N matrix, so this is slow.
+    let entanglement_every: usize = config.get_usize("entanglement_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let mut entanglement_sink: Option<TextSeriesWriter<_>> = None;
+    if entanglement_every > 0 {
+        let mut sink = TextSeriesWriter::create(
+            config.get("entanglement_file").unwrap_or("entanglement.txt"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        sink.write_header(&Entanglement::names())
+            .unwrap_or_else(|e| exit_with_error(e));
+        let e = Entanglement::from_schmidt_coefficients(
+            &schmidt_coefficients(psi_vec.as_slice()));
+        let _ = sink.write_row(0.0, &e.values());
+        entanglement_sink = Some(sink);
+    }
+
+    // If classical_count is set, an ensemble of this many classical
+    // particles, with positions and momenta sampled from the initial
+    // wave function, is moved through the same potential. The quantum
+    // and classical mean positions and the first classical_saved
+    // trajectories are written every step to classical_file.
+    let mut classical: Option<(ClassicalEnsemble, usize,
+                               TextSeriesWriter<_>)> = None;
+    let classical_count: usize = config.get_usize("classical_count", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    if classical_count > 0 {
+        let seed: usize = config.get_usize("classical_seed", 1)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let saved: usize = usize::min(
+            classical_count, config.get_usize("classical_saved", 10)
+            .unwrap_or_else(|e| exit_with_error(e)));
+        let mut random = Random::new(seed as u64);
+        let mut scratch = psi_vec.clone();
+        let positions: Vec<(f32, f32)> = sample_density(
+            psi_vec.as_slice(), classical_count, &mut random);
+        let momenta: Vec<(f32, f32)> = sample_momenta(
+            psi_vec.as_slice(), classical_count, &mut random,
+            scratch.as_mut_slice());
+        let ensemble = ClassicalEnsemble::new(positions, momenta,
+                                              potential_vec.as_slice());
+        let mut sink = TextSeriesWriter::create(
+            config.get("classical_file").unwrap_or("classical.txt"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        let mut names: Vec<String> = ["x_quantum", "y_quantum",
+                                      "x_classical", "y_classical"]
+            .iter().map(|n| n.to_string()).collect();
+        for k in 0..saved {
+            names.push(format!("x{}", k));
+            names.push(format!("y{}", k));
+        }
+        let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+        sink.write_header(&names).unwrap_or_else(|e| exit_with_error(e));
+        classical = Some((ensemble, saved, sink));
+    }
+    let write_classical = |t: f64, psi: &[Complex<f32>],
+                           ensemble: &ClassicalEnsemble, saved: usize,
+                           sink: &mut TextSeriesWriter<_>| {
+        let (xq, yq): (f64, f64) = position_expectation(psi);
+        let (xc, yc): (f64, f64) = ensemble.mean_position();
+        let mut values: Vec<f64> = vec![xq, yq, xc, yc];
+        for &(x, y) in ensemble.positions.iter().take(saved) {
+            values.push(x as f64);
+            values.push(y as f64);
+        }
+        if let Err(e) = sink.write_row(t, &values) {
+            eprintln!("{}", e);
+        }
+    };
+    if let Some((ensemble, saved, sink)) = classical.as_mut() {
+        write_classical(0.0, psi_vec.as_slice(), ensemble, *saved, sink);
+    }
+
+    // If dipole_file is set, <x>, <y> and the dipole acceleration are
+    // written to it every step, and if spectrogram_file is also set the
+    // spectrogram of the acceleration is written at the end.
+    let mut dipole: Option<(TextSeriesWriter<_>, Vec<f64>, Vec<f64>)> = None;
+    if let Some(fname) = config.get("dipole_file") {
+        let mut sink = TextSeriesWriter::create(fname)
+            .unwrap_or_else(|e| exit_with_error(e));
+        sink.write_header(&["x", "y", "ax", "ay"])
+            .unwrap_or_else(|e| exit_with_error(e));
+        dipole = Some((sink, vec![], vec![]));
+    }
+    let record_dipole = |t: f64, psi: &[Complex<f32>],
+                         potential: &[Complex<f32>],
+                         (sink, ax, ay): &mut (TextSeriesWriter<_>,
+                                               Vec<f64>, Vec<f64>)| {
+        let (x, y): (f64, f64) = position_expectation(psi);
+        let (a_x, a_y): (f64, f64) = dipole_acceleration(psi, potential);
+        ax.push(a_x);
+        ay.push(a_y);
+        if let Err(e) = sink.write_row(t, &[x, y, a_x, a_y]) {
+            eprintln!("{}", e);
+        }
+    };
+    if let Some(d) = dipole.as_mut() {
+        record_dipole(0.0, psi_vec.as_slice(), potential_vec.as_slice(), d);
+    }
+
+    // The vortices of the wave function are found every vortices_every
+    // steps, if set, and written to vortices_file as lines t x y charge.
+    let vortices_every: usize = config.get_usize("vortices_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let vortices_min_density: f32 = config.get_f32(
+        "vortices_min_density", 1e-4).unwrap_or_else(|e| exit_with_error(e));
+    let mut vortices_writer: Option<std::io::BufWriter<std::fs::File>> = None;
+    if vortices_every > 0 {
+        let file = std::fs::File::create(
+            config.get("vortices_file").unwrap_or("vortices.txt"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        vortices_writer = Some(std::io::BufWriter::new(file));
+    }
+    let write_vortices = |writer: &mut std::io::BufWriter<std::fs::File>,
+                          t: f64, psi: &[Complex<f32>]
+                          | -> std::io::Result<()> {
+        use std::io::Write;
+        for v in find_vortices(psi, vortices_min_density) {
+            writeln!(writer, "{} {} {} {}", t, v.x, v.y, v.charge)?;
+        }
+        return writer.flush();
+    };
+    if let Some(writer) = vortices_writer.as_mut() {
+        use std::io::Write;
+        let _ = writeln!(writer, "# t x y charge");
+        if let Err(e) = write_vortices(writer, 0.0, psi_vec.as_slice()) {
+            eprintln!("{}", e);
+        }
+    }
+
+    // psi is saved as a PNG image every png_every steps, if set, drawn
+    // as its density with png_colormap (or one loaded from
+    // png_colormap_file) or with domain coloring if png_style = phase, in
+    // momentum space if png_space = momentum, with the potential drawn
+    // over it as png_potential, seen as a surface from the camera of
+    // png_surface_* if png_surface = true, and named png_prefix followed
+    // by the frame number padded to five digits.
+    let mut png_frames: PngFrames = png_frames_from_config(&config);
+
+    // With --record, frames drawn in the same way are encoded by ffmpeg
+    // into a video every record_every steps at record_framerate frames
+    // per second.
+    let record_every: usize = usize::max(1, config.get_usize(
+        "record_every", 3).unwrap_or_else(|e| exit_with_error(e)));
+    let mut video: Option<VideoRecorder> = video_file.as_ref().map(|f| {
+        let framerate: f32 = config.get_f32("record_framerate", 30.0)
+            .unwrap_or_else(|e| exit_with_error(e));
+        VideoRecorder::start(f, N, N, framerate)
+            .unwrap_or_else(|e| exit_with_error(e))
+    });
+
+    // The phase space distributions listed in phase_space (wigner
+    // and/or husimi) of the slice along phase_space_axis through
+    // phase_space_slice are saved every phase_space_every steps, if set.
+    let phase_space_every: usize = config.get_usize("phase_space_every", 0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let phase_space_axis: Axis = Axis::parse(
+        config.get("phase_space_axis").unwrap_or("x"))
+        .unwrap_or_else(|e| exit_with_error(e));
+    let phase_space_index: usize = (config.get_f32("phase_space_slice", 0.5)
+        .unwrap_or_else(|e| exit_with_error(e))*(N as f32)) as usize % N;
+    let phase_space_stride: usize = config.get_usize("phase_space_stride", 4)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let husimi_width: f32 = config.get_f32("husimi_width", 16.0)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let phase_space_names: Vec<&str> = config.get("phase_space")
+        .unwrap_or("wigner").split(',').map(|s| s.trim()).collect();
+    for name in &phase_space_names {
+        if *name != "wigner" && *name != "husimi" {
+            exit_with_error(format!(
+                "unknown phase space distribution '{}', expected wigner \
+                 or husimi", name));
+        }
+    }
+    // The local kinetic and potential energy densities are saved every
+    // energy_density_every steps, if set.
+    let energy_density_every: usize = config.get_usize(
+        "energy_density_every", 0).unwrap_or_else(|e| exit_with_error(e));
+    let energy_density_stride: usize = config.get_usize(
+        "energy_density_stride", 16).unwrap_or_else(|e| exit_with_error(e));
+
+    // With --view, frames drawn as the PNG frames are shown in a window
+    // every view_every steps while the simulation runs, next to a panel
+    // whose settings are applied between steps. With --tui they are
+    // drawn in the terminal instead, with tui_graphics, tui_width pixels
+    // across, at most tui_fps times a second, and with --web they are
+    // served to browsers at web_address, web_width pixels across, at
+    // most web_fps times a second.
+    let view_every: usize = usize::max(1, config.get_usize(
+        "view_every", 1).unwrap_or_else(|e| exit_with_error(e)));
+    // The norm, energy and <x> are charted in the viewer every
+    // view_chart_every steps.
+    let view_chart_every: usize = usize::max(1, config.get_usize(
+        "view_chart_every", 10).unwrap_or_else(|e| exit_with_error(e)));
+    let mut chart_scratch = std::vec::Vec::<Complex<f32>>::new();
+    if view || tui || web {
+        chart_scratch.resize(N*N, Complex {real: 0.0, imag: 0.0});
+    }
+    let mut settings = LiveSettings::new(dt.real, png_frames.style.clone());
+    settings.surface = png_frames.surface;
+    let mut viewer: Option<(SimulationLink, std::thread::JoinHandle<()>)>
+        = if view {
+        let window = X11Window::open(N + PANEL_WIDTH, N, "qm2d_split_op")
+            .unwrap_or_else(|e| exit_with_error(e));
+        let (simulation_link, viewer_link) = link(settings.clone());
+        let handle = std::thread::spawn(move || {
+            if let Err(e) = window.run(viewer_link, settings,
+                                       "qm2d_split_op") {
+                eprintln!("{}", e);
+            }
+        });
+        Some((simulation_link, handle))
+    } else if tui {
+        let graphics: TerminalGraphics = TerminalGraphics::parse(
+            config.get("tui_graphics").unwrap_or("blocks"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        let terminal = TerminalViewer {
+            graphics,
+            width: config.get_usize("tui_width", if graphics
+                                    == TerminalGraphics::Blocks {80}
+                                    else {512})
+                .unwrap_or_else(|e| exit_with_error(e)),
+            max_fps: config.get_f32("tui_fps", 10.0)
+                .unwrap_or_else(|e| exit_with_error(e)) as f64,
+        };
+        let (simulation_link, viewer_link) = link(settings.clone());
+        let handle = std::thread::spawn(move || {
+            if let Err(e) = terminal.run(viewer_link, settings,
+                                         "qm2d_split_op") {
+                eprintln!("{}", e);
+            }
+        });
+        Some((simulation_link, handle))
+    } else if web {
+        let server = WebViewer {
+            address: config.get("web_address").unwrap_or("127.0.0.1:8080")
+                .to_string(),
+            width: config.get_usize("web_width", 512)
+                .unwrap_or_else(|e| exit_with_error(e)),
+            max_fps: config.get_f32("web_fps", 10.0)
+                .unwrap_or_else(|e| exit_with_error(e)) as f64,
+        };
+        let (simulation_link, viewer_link) = link(settings.clone());
+        let handle = std::thread::spawn(move || {
+            if let Err(e) = server.run(viewer_link, settings) {
+                eprintln!("{}", e);
+            }
+        });
+        Some((simulation_link, handle))
+    } else {
+        None
+    };
+    // The potential as given with the walls painted in the viewer, from
+    // which the one stepped with is made by the live settings, and the
+    // time the drive of those runs on.
+    let mut given_potential: Vec<Complex<f32>> = potential_vec.clone();
+    // What r in the viewer resets psi to.
+    let initial_psi: Vec<Complex<f32>> = psi_vec.clone();
+    // Painted again over a time dependent potential at every step.
+    let mut painted: Vec<Stroke> = Vec::new();
+    let mut live_time: f32 = 0.0;
 
+    // The potential only needs to be exponentiated again when it changes
+    // with time or from the viewer. With --precision double the wave
+    // function is stepped in doubles and rounded into psi_vec after each
+    // step.
+    let mut stepper: Stepper = Stepper::new(precision);
+    // With spectral_filter set, the spectrum of psi is damped past
+    // spectral_filter_cutoff of the largest momentum at every step (see
+    // src/spectral.rs).
+    let spectral_filter: Option<SpectralFilter> = if config.get_bool(
+        "spectral_filter", false).unwrap_or_else(|e| exit_with_error(e)) {
+        let get = |key: &str, default: f32| -> f64 {
+            return config.get_f32(key, default)
+                .unwrap_or_else(|e| exit_with_error(e)) as f64;
+        };
+        Some(SpectralFilter::new(get("spectral_filter_cutoff", 0.66),
+                                 get("spectral_filter_order", 8.0),
+                                 get("spectral_filter_strength", 36.0))
+             .unwrap_or_else(|e| exit_with_error(e)))
+    } else {
+        None
+    };
+    stepper.set_filter(spectral_filter);
+    // A progress line is drawn on standard error if progress is set,
+    // which it is by default when that is a terminal (see
+    // src/progress.rs). The frames saved are not listed while it is.
+    let mut progress: Option<Progress> = if config.get_bool(
+        "progress", std::io::IsTerminal::is_terminal(&std::io::stderr()))
+        .unwrap_or_else(|e| exit_with_error(e)) {
+        Some(Progress::new(NUMBER_OF_STEPS))
+    } else {
+        None
+    };
+    if let Some(p) = progress.as_mut() {
+        p.show(0, 0.0, psi_vec.as_slice(), potential_vec.as_slice(),
+               writer.backlog());
+    }
+    let mut steps_done: usize = 0;
+    // psi is checked for values that are not finite and a norm that has
+    // run away every watchdog_every steps, the norm only in real time.
+    let watchdog_every: usize = config.get_usize("watchdog_every", 10)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let watchdog_growth: f64 = config.get_f32("watchdog_max_norm", 2.0)
+        .unwrap_or_else(|e| exit_with_error(e)) as f64;
+    let watchdog_action: WatchdogAction = WatchdogAction::parse(
+        config.get("watchdog_action").unwrap_or("abort"))
+        .unwrap_or_else(|e| exit_with_error(e));
+    let mut watchdog: Option<Watchdog> = if watchdog_every > 0 {
+        Some(Watchdog::new(psi_vec.as_slice(), watchdog_every,
+                           if dt.imag == 0.0 {watchdog_growth} else {0.0},
+                           watchdog_action))
+    } else {
+        None
+    };
+    let mut diverged: bool = false;
+    let quench_every: usize = config.get_usize("quench_every", 10)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let mut quench_scratch = std::vec::Vec::<Complex<f32>>::new();
+    if quenches.is_some() {
+        quench_scratch.resize(N*N, Complex {real: 0.0, imag: 0.0});
+    }
+    // With damping_gamma the GPE is damped, with the norm restored after
+    // each step, until the chemical potential, written to damping_file
+    // every damping_every steps, changes by less than damping_tolerance
+    // in a step (see src/damping.rs).
+    let mut damping: Option<(Damping, TextSeriesWriter<_>)> = None;
+    let damping_every: usize = config.get_usize("damping_every", 10)
+        .unwrap_or_else(|e| exit_with_error(e)).max(1);
+    if config.get("damping_gamma").is_some() {
+        let gamma: f32 = config.get_f32("damping_gamma", 0.0)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let tolerance: f64 = config.get_f32("damping_tolerance", 0.0)
+            .unwrap_or_else(|e| exit_with_error(e)) as f64;
+        let d: Damping = Damping::new(gamma, tolerance)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let mut sink = TextSeriesWriter::create(
+            config.get("damping_file").unwrap_or("damping.txt"))
+            .unwrap_or_else(|e| exit_with_error(e));
+        sink.write_header(&["mu", "change"])
+            .unwrap_or_else(|e| exit_with_error(e));
+        damping = Some((d, sink));
+    }
+    // Ctrl-C or SIGTERM stop the run after the step in progress, with
+    // its output written (see src/interrupt.rs).
+    install_interrupt_handler();
+    timer.lap("setup");
+    log_event(LogLevel::Info, "run", "stepping", &[
+        ("steps", NUMBER_OF_STEPS.into()), ("n", N.into()),
+        ("dt_real", dt.real.into()), ("dt_imag", dt.imag.into())]);
     for i in 0..NUMBER_OF_STEPS {
-        propagate_spatial_terms(psi_vec.as_mut_slice(), 
-                                potential_vec.as_slice(),
-                                Nonlinear {square: 0.0},
-                                dt.scale(0.5));
-        propagate_kinetic(psi_vec.as_mut_slice(),
-                          p_squared_vec.as_slice(), dt, true);
-        dampen(psi_vec.as_mut_slice(), dt.real);
-        propagate_spatial_terms(psi_vec.as_mut_slice(),
-                                potential_vec.as_slice(),
-                                Nonlinear {square: 0.0}, 
-                                dt.scale(0.5));
+        let _step_span: LogSpan = LogSpan::enter(
+            LogLevel::Trace, "run", "step", &[
+                ("step", i.into()), ("t", ((i as f32)*dt.real).into())]);
+        if let Some((l, _)) = viewer.as_mut() {
+            if !l.poll() {
+                break;
+            }
+            png_frames.style = l.settings.style.clone();
+            for stroke in l.strokes.drain(..) {
+                stroke.apply(given_potential.as_mut_slice());
+                painted.push(stroke);
+            }
+            if std::mem::take(&mut l.reset) {
+                psi_vec.copy_from_slice(initial_psi.as_slice());
+            }
+            if std::mem::take(&mut l.snapshot) {
+                let filename: String = format!("{}snapshot_{:05}.bin",
+                                               SAVE_DIRECTORY, i);
+                println!("Saving {}", filename);
+                save_snapshot(&writer, filename, i, dt, psi_vec.as_slice(),
+                              potential_vec.as_slice(), snapshot_compression);
+            }
+        }
+        let dt: Complex<f32> = match viewer.as_ref() {
+            Some((l, _)) => Complex {real: l.settings.dt, imag: dt.imag},
+            None => dt,
+        };
+        // Events of the script run before the step they are due at, and
+        // may change the potential.
+        let mut potential_changed: bool = false;
+        if let Some(s) = script.as_mut() {
+            let outcome: ScriptOutcome = s.run_events(
+                i, (i as f32)*dt.real, psi_vec.as_mut_slice());
+            if outcome.stop {
+                println!("Stopping: the script stops at step {}", i);
+                break;
+            }
+            potential_changed = outcome.potential_changed;
+            if let Some(monitor) = norm_monitor.as_mut() {
+                monitor.record_absorbed(outcome.absorbed, 0.0);
+            }
+        }
+        // Quenches due are made after the events, with the observables
+        // of psi taken in the potential and interaction on either side.
+        let mut quenched: Option<(Expectations, QuenchChange)> = None;
+        if let Some(q) = quenches.as_mut()
+            .filter(|q| q.is_due((i as f32)*dt.real)) {
+            let before: Expectations = compute_expectations(
+                psi_vec.as_slice(), potential_vec.as_slice(), q.g,
+                quench_scratch.as_mut_slice());
+            let change: QuenchChange = q.apply_due((i as f32)*dt.real);
+            potential_changed |= change.potential_changed;
+            if change.interaction_changed {
+                stepper.set_nonlinear(q.nonlinear());
+            }
+            quenched = Some((before, change));
+        }
+        let scripted: Option<&Expression>
+            = quenches.as_ref().and_then(|q| q.potential())
+            .or(script.as_ref().and_then(|s| s.potential()));
+        let mut time_dependent: bool = match scripted {
+            Some(e) => e.is_time_dependent() || potential_changed,
+            None => potential_terms.is_time_dependent(),
+        };
+        // A potential that is not filled in again is scaled by what the
+        // frequency quenches change its scale by.
+        if let Some((_, change)) = quenched.as_ref()
+            .filter(|(_, c)| c.ratio != 1.0 && !time_dependent) {
+            for v in potential_vec.iter_mut().chain(plugin_base.iter_mut()) {
+                v.real *= change.ratio;
+            }
+        }
+        if time_dependent {
+            match scripted {
+                Some(e) => e.fill_at(potential_vec.as_mut_slice(),
+                                     (i as f32)*dt.real),
+                None => potential_terms.fill_at(potential_vec.as_mut_slice(),
+                                                (i as f32)*dt.real),
+            };
+            if let Some(q) = quenches.as_ref() {
+                q.scale_potential(potential_vec.as_mut_slice());
+            }
+            if plugins.is_time_dependent() {
+                plugin_base.copy_from_slice(potential_vec.as_slice());
+            }
+        } else if plugins.is_time_dependent() {
+            potential_vec.copy_from_slice(plugin_base.as_slice());
+        }
+        if time_dependent || plugins.is_time_dependent() {
+            plugins.add_potential(potential_vec.as_mut_slice(),
+                                  (i as f32)*dt.real);
+            time_dependent = true;
+        }
+        if let (Some((before, _)), Some(q))
+            = (quenched.take(), quenches.as_mut()) {
+            let after: Expectations = compute_expectations(
+                psi_vec.as_slice(), potential_vec.as_slice(), q.g,
+                quench_scratch.as_mut_slice());
+            println!("Quench at t = {}: energy {} to {}", (i as f32)*dt.real,
+                     before.total, after.total);
+            q.record((i as f64)*(dt.real as f64), before, after);
+            time_dependent = true;
+        }
+        if let Some((l, _)) = viewer.as_ref() {
+            if time_dependent {
+                given_potential.copy_from_slice(potential_vec.as_slice());
+                for stroke in painted.iter() {
+                    stroke.apply(given_potential.as_mut_slice());
+                }
+            }
+            l.settings.fill_potential(given_potential.as_slice(),
+                                      potential_vec.as_mut_slice(),
+                                      live_time);
+            live_time += dt.real;
+        }
+        let step_dt: Complex<f32> = damping.as_ref()
+            .map_or(dt, |(d, _)| d.damped_dt(dt));
+        let norms: [f64; 5] = stepper.step(
+            psi_vec.as_mut_slice(), potential_vec.as_slice(),
+            p_squared_vec.as_slice(), step_dt,
+            time_dependent || viewer.is_some(), true);
+        let [norm_0, norm_1, norm_2, norm_3, norm_4]: [f64; 5] = norms;
+        let mut steady: bool = false;
+        if let Some((d, sink)) = damping.as_mut() {
+            let mu: f64 = d.restore(psi_vec.as_mut_slice(), norms, dt);
+            if (i + 1) % damping_every == 0 {
+                if let Err(e) = sink.write_row(
+                    ((i + 1) as f64)*(dt.real as f64), &[mu, d.change]) {
+                    eprintln!("{}", e);
+                }
+            }
+            steady = d.is_steady();
+        }
+        // Changes plugins make to the norm are theirs, not drift.
+        let plugin_change: f64 = if plugins.has_step() {
+            plugins.step(psi_vec.as_mut_slice(), potential_vec.as_slice(), dt,
+                         ((i + 1) as f32)*dt.real)} else {0.0};
+        if let Some(monitor) = norm_monitor.as_mut() {
+            monitor.record_absorbed(-plugin_change, 0.0);
+            // The damped parts of a step change the norm on purpose, and
+            // it is restored after them.
+            if damping.is_none() {
+                if has_absorbing_potential {
+                    monitor.record_absorbed(norm_0, norm_1);
+                    monitor.record_absorbed(norm_3, norm_4);
+                } else {
+                    monitor.record_numerical(norm_0, norm_1);
+                    monitor.record_numerical(norm_3, norm_4);
+                }
+                if spectral_filter.is_some() {
+                    monitor.record_absorbed(norm_1, norm_2);
+                } else {
+                    monitor.record_numerical(norm_1, norm_2);
+                }
+            }
+            monitor.record_absorbed(norm_2, norm_3);
+            if let Err(e) = monitor.check(psi_vec.as_mut_slice(), i + 1) {
+                println!("Stopping: {}", e);
+                break;
+            }
+        }
+        if let Some(w) = watchdog.as_mut().filter(|w| w.is_due(i + 1)) {
+            if let Err(e) = w.check(psi_vec.as_mut_slice(), i + 1) {
+                println!("Stopping: {}", e);
+                steps_done = w.last_good_step().unwrap_or(i + 1);
+                diverged = true;
+                break;
+            }
+        }
+        if let Some((ensemble, saved, sink)) = classical.as_mut() {
+            if time_dependent || viewer.is_some() {
+                ensemble.set_potential(potential_vec.as_slice());
+            }
+            ensemble.advance(dt.real);
+            write_classical(((i + 1) as f64)*(dt.real as f64),
+                            psi_vec.as_slice(), ensemble, *saved, sink);
+        }
+        if let Some(writer) = vortices_writer.as_mut() {
+            if (i + 1) % vortices_every == 0 {
+                if let Err(e) = write_vortices(
+                    writer, ((i + 1) as f64)*(dt.real as f64),
+                    psi_vec.as_slice()) {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        if let Some(d) = dipole.as_mut() {
+            record_dipole(((i + 1) as f64)*(dt.real as f64),
+                          psi_vec.as_slice(), potential_vec.as_slice(), d);
+        }
+        if let Some(sink) = populations_sink.as_mut() {
+            let t: f64 = ((i + 1) as f64)*(dt.real as f64);
+            if let Err(e) = sink.write_row(
+                t, &populations(&eigenstates, psi_vec.as_slice())) {
+                eprintln!("{}", e);
+            }
+        }
+        if let Some((e, sink)) = echo.as_mut() {
+            e.step(potential_vec.as_slice(), p_squared_vec.as_slice(),
+                   Nonlinear {square: 0.0}, (i as f32)*dt.real, dt);
+            let t: f64 = ((i + 1) as f64)*(dt.real as f64);
+            if let Err(e) = sink.write_row(
+                t, &[e.fidelity(psi_vec.as_slice())]) {
+                eprintln!("{}", e);
+            }
+        }
+        if let Some((c, psi0, sink)) = autocorrelation.as_mut() {
+            c.record(psi0.as_slice(), psi_vec.as_slice());
+            let val: Complex<f64> = c.values[c.values.len() - 1];
+            let t: f64 = ((i + 1) as f64)*c.dt;
+            let survival: f64 = val.length_squared()
+                /c.values[0].length_squared();
+            if let Err(e) = sink.write_row(t, &[val.real, val.imag,
+                                                survival]) {
+                eprintln!("{}", e);
+            }
+        }
+        if let Some(q) = quenches.as_mut().filter(
+            |q| !q.records.is_empty() && quench_every > 0
+                && (i + 1) % quench_every == 0) {
+            q.sample(&compute_expectations(
+                psi_vec.as_slice(), potential_vec.as_slice(), q.g,
+                quench_scratch.as_mut_slice()));
+        }
+        if observables_every > 0 && (i + 1) % observables_every == 0 {
+            record_observables(i + 1, psi_vec.as_slice(),
+                               potential_vec.as_slice(),
+                               quenches.as_ref().map_or(0.0, |q| q.g),
+                               &norm_monitor);
+        }
+        let save_current: bool
+            = current_every > 0 && (i + 1) % current_every == 0;
+        let record_detectors: bool
+            = !detectors.is_empty() && (i + 1) % detector_every == 0;
+        let save_vtk: bool = vtk_every > 0 && (i + 1) % vtk_every == 0;
+        let save_streamlines: bool
+            = streamline_every > 0 && (i + 1) % streamline_every == 0;
+        if save_current || record_detectors || trajectories.is_some()
+            || save_vtk || save_streamlines {
+            let (jx, jy) = current_density(psi_vec.as_slice());
+            if save_vtk {
+                let filename: String = format!("{}{:05}.vti", vtk_prefix,
+                                               (i + 1)/vtk_every);
+                let time: f64 = ((i + 1) as f64)*(dt.real as f64);
+                let (psi, potential) = (psi_vec.clone(), potential_vec.clone());
+                let (jx, jy) = (jx.clone(), jy.clone());
+                writer.submit(move || save_fields_vti(
+                    &filename, time, &psi, &potential, &jx, &jy));
+            }
+            if save_current {
+                let filename: String = format!("{}current_{:04}.csv",
+                                               SAVE_DIRECTORY,
+                                               (i + 1)/current_every);
+                if let Err(e) = save_vector_field_csv(&filename, &jx, &jy,
+                                                      current_stride) {
+                    eprintln!("{}", e);
+                }
+                if current_svg {
+                    let filename: String = filename.replace(".csv", ".svg");
+                    if let Err(e) = save_quiver_svg(&filename, &jx, &jy,
+                                                    current_stride) {
+                        eprintln!("{}", e);
+                    }
+                }
+            }
+            if save_streamlines {
+                let lines: Vec<Vec<(f32, f32)>> = trace_streamlines(
+                    &jx, &jy, streamline_spacing, streamline_min_current);
+                let name: String = format!("{}streamlines_{:04}",
+                                           SAVE_DIRECTORY,
+                                           (i + 1)/streamline_every);
+                if let Err(e) = save_streamlines_csv(&(name.clone() + ".csv"),
+                                                     &lines)
+                    .and_then(|_| save_streamlines_svg(&(name + ".svg"),
+                                                       &lines)) {
+                    eprintln!("{}", e);
+                }
+            }
+            if record_detectors {
+                for d in detectors.iter_mut() {
+                    d.record(psi_vec.as_slice(), &jx, &jy,
+                             (detector_every as f32)*dt.real);
+                }
+            }
+            if let Some((t, sink)) = trajectories.as_mut() {
+                t.advance(psi_vec.as_slice(), &jx, &jy, dt.real);
+                let time: f64 = ((i + 1) as f64)*(dt.real as f64);
+                if let Err(e) = sink.write_row(time, &t.values()) {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        if let Some(sink) = entanglement_sink.as_mut() {
+            if (i + 1) % entanglement_every == 0 {
+                let e = Entanglement::from_schmidt_coefficients(
+                    &schmidt_coefficients(psi_vec.as_slice()));
+                let t: f64 = ((i + 1) as f64)*(dt.real as f64);
+                if let Err(e) = sink.write_row(t, &e.values()) {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        if energy_density_every > 0 && (i + 1) % energy_density_every == 0 {
+            let (kinetic, potential) = energy_density(
+                psi_vec.as_slice(), potential_vec.as_slice(), 0.0);
+            let mut total: Field2D<f32> = kinetic.clone();
+            for k in 0..N*N {
+                total.values[k] += potential.values[k];
+            }
+            let filename: String = format!("{}energy_density_{:04}.csv",
+                                           SAVE_DIRECTORY,
+                                           (i + 1)/energy_density_every);
+            if let Err(e) = save_scalar_fields_csv(
+                &filename, &["kinetic", "potential", "total"],
+                &[&kinetic, &potential, &total], energy_density_stride) {
+                eprintln!("{}", e);
+            }
+        }
+        if phase_space_every > 0 && (i + 1) % phase_space_every == 0 {
+            let f: Vec<Complex<f32>> = extract_slice(
+                psi_vec.as_slice(), phase_space_axis, phase_space_index);
+            for name in &phase_space_names {
+                let distribution: PhaseSpace = if *name == "wigner" {
+                    wigner(&f)
+                } else {
+                    husimi(&f, husimi_width)
+                };
+                let filename: String = format!("{}{}_{:04}.txt",
+                                               SAVE_DIRECTORY, name,
+                                               (i + 1)/phase_space_every);
+                if let Err(e) = distribution.save_text(&filename,
+                                                       phase_space_stride) {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        if png_frames.is_due(i) {
+            let filename: String = png_frames.filename(i/png_frames.every);
+            let rgb: Vec<u8> = png_frames.render(
+                png_frames.space, psi_vec.as_slice(), potential_vec.as_slice());
+            writer.submit(move || std::fs::write(
+                filename, encode_png_rgb(N, N, &rgb)));
+        }
+        if npy_every > 0 && (i + 1) % npy_every == 0 {
+            let filename: String = format!("{}{:05}.npy", npy_prefix, i + 1);
+            let psi: Vec<Complex<f32>> = psi_vec.clone();
+            writer.submit(move || save_npy(&filename, &[N, N],
+                                           NpyData::Complex(&psi)));
+        }
+        #[cfg(feature = "hdf5")]
+        if let Some(hdf5) = hdf5.as_ref() {
+            if (hdf5_every > 0 && (i + 1) % hdf5_every == 0)
+                || i + 1 == NUMBER_OF_STEPS {
+                let hdf5 = hdf5.clone();
+                let psi: Vec<Complex<f32>> = psi_vec.clone();
+                writer.submit(move || match hdf5.lock() {
+                    Ok(mut h) => write_hdf5_psi(&mut h, i + 1, dt, &psi),
+                    Err(_) => Ok(()),
+                });
+            }
+        }
+        if let Some((l, _)) = viewer.as_ref() {
+            if i % view_every == 0 {
+                let space: FrameSpace = if l.settings.momentum_space {
+                    FrameSpace::Momentum
+                } else {
+                    FrameSpace::Position
+                };
+                l.send_frame(Frame {
+                    width: N, height: N, step: i + 1,
+                    rgb: png_frames.render_view(
+                        space, l.settings.surface.as_ref(),
+                        psi_vec.as_slice(), potential_vec.as_slice())});
+            }
+            if (i + 1) % view_chart_every == 0 {
+                let e: Expectations = compute_expectations(
+                    psi_vec.as_slice(), potential_vec.as_slice(), 0.0,
+                    chart_scratch.as_mut_slice());
+                l.send_sample(Sample {step: i + 1, values: vec![
+                    ("norm", e.norm), ("energy", e.total), ("<x>", e.x)]});
+            }
+        }
+        if let Some(v) = video.as_mut() {
+            if i % record_every == 0 {
+                let rgb: Vec<u8> = png_frames.render(
+                    png_frames.space, psi_vec.as_slice(),
+                    potential_vec.as_slice());
+                if let Err(e) = v.write_frame(&rgb) {
+                    eprintln!("{}", e);
+                    video = None;
+                }
+            }
+        }
         let at_every_step: usize = 3;
         if i % at_every_step == 0 {
             fill_pixel_data(&mut *boxed_pixels, 54,
@@ -604,12 +2970,229 @@ fn main() {
             } else {
                 frame_number.to_string()
             };
-            let filename: String = prefix + &number_str + &".bmp".to_string();
-            println!("Saving {}", filename);
-            let _ = make_bitmap_file(filename, &mut *boxed_pixels);
+            let filename: String = prefix + &number_str + ".bmp";
+            if progress.is_none() {
+                println!("Saving {}", filename);
+            }
+            let mut data: Vec<u8> = boxed_pixels.to_vec();
+            writer.submit(move || make_bitmap_file(filename, &mut data));
+        }
+        steps_done = i + 1;
+        if let Some(p) = progress.as_mut().filter(|p| p.is_due()) {
+            p.show(steps_done, (steps_done as f64)*(dt.real as f64),
+                   psi_vec.as_slice(), potential_vec.as_slice(),
+                   writer.backlog());
         }
+        if interrupted() {
+            if let Some(p) = progress.as_mut() {
+                p.finish(steps_done);
+            }
+            println!("Interrupted after step {}, saving the output \
+                      (interrupt again to exit at once)", steps_done);
+            log_event(LogLevel::Warn, "run", "interrupted",
+                      &[("step", steps_done.into())]);
+            break;
+        }
+        if steady {
+            println!("Stopping: the chemical potential has settled at {} \
+                      after step {}", damping.as_ref()
+                     .and_then(|(d, _)| d.mu).unwrap_or(f64::NAN),
+                     steps_done);
+            break;
+        }
+    }
+    if let Some(p) = progress.as_mut().filter(|_| !interrupted()) {
+        p.finish(steps_done);
+    }
+    timer.lap("steps");
+    if let Some((l, handle)) = viewer {
+        drop(l);
+        let _ = handle.join();
+    }
+    if let Some(v) = video {
+        match v.finish() {
+            Ok(()) => println!("Saved {}", video_file.unwrap_or_default()),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    if let (Some(b), Some((initial, initial_norm)))
+        = (barrier, &initial_momentum) {
+        if let Err(e) = write_transmission(&config, &b, initial,
+                                           *initial_norm,
+                                           psi_vec.as_slice()) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some(q) = quenches.as_ref() {
+        if let Err(e) = q.save(config.get("quench_file")
+                               .unwrap_or("quench.txt")) {
+            eprintln!("{}", e);
+        }
+    }
+    for (k, d) in detectors.iter().enumerate() {
+        let filename: String = format!("detector_{}.txt", k);
+        println!("Detector {}: outgoing flux {}", k, d.outgoing);
+        if let Err(e) = d.save(&filename) {
+            eprintln!("{}", e);
+        }
+    }
+    if let (Some((_, ax, ay)), Some(fname))
+        = (&dipole, config.get("spectrogram_file")) {
+        if let Err(e) = write_spectrogram(&config, fname, ax, ay,
+                                          dt.real as f64) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some((c, _, _)) = &autocorrelation {
+        if let Err(e) = write_energy_spectrum(&config, c) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some(f) = &npz_file {
+        if let Err(e) = write_npz(f, &initial_psi, psi_vec.as_slice(),
+                                  potential_vec.as_slice(),
+                                  &observables_memory) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some(f) = &mat_file {
+        if let Err(e) = write_mat(f, &initial_psi, psi_vec.as_slice(),
+                                  potential_vec.as_slice(),
+                                  &observables_memory, dt) {
+            eprintln!("{}", e);
+        }
+    }
+    if let Some(f) = &qutip_file {
+        if let Err(e) = write_qutip(f, &initial_psi, psi_vec.as_slice(),
+                                    potential_vec.as_slice(),
+                                    p_squared_vec.as_slice(), dt) {
+            eprintln!("{}", e);
+        }
+    }
+    // The HDF5 file is finished once the psi queued for it is written.
+    writer.finish();
+    #[cfg(feature = "hdf5")]
+    if let Some(mut writer) = hdf5.and_then(std::sync::Arc::into_inner)
+        .and_then(|h| h.into_inner().ok()) {
+        let result: std::io::Result<()> = match &observables_memory {
+            Some(series) => write_hdf5_observables(&mut writer, series),
+            None => Ok(()),
+        }.and_then(|_| writer.finish());
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+    }
+    save_snapshot(&writer, "last_state.bin".to_string(), steps_done, dt,
+                  psi_vec.as_slice(), potential_vec.as_slice(),
+                  snapshot_compression);
+    // The settings, build, machine and timing of the run are saved to
+    // manifest_file.
+    let manifest_file: String = config.get("manifest_file")
+        .unwrap_or("run.json").to_string();
+    timer.lap("output");
+    if let Err(e) = RunManifest::new(&config, args, &timer)
+        .save(&manifest_file) {
+        eprintln!("{}", e);
+    }
+    if interrupted() {
+        println!("Saved last_state.bin at step {}. Resume with:\n    {}",
+                 steps_done, resume_args.join(" "));
+        return INTERRUPTED_EXIT_CODE;
+    }
+    if diverged {
+        return 1;
+    }
+    return 0;
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command_args: Vec<String> = logging_from_args(&args);
+    // The first argument names a subcommand, or is the first option of
+    // a run, as before there were subcommands (see USAGE).
+    let (command, options): (&str, &[String]) = match command_args.first()
+        .map(String::as_str) {
+        Some(c @ ("run" | "eigen" | "reversal" | "render" | "analyze" | "sweep"
+                  | "ensemble" | "bench" | "validate" | "convergence"
+                  | "reference" | "resample" | "fdm" | "serve" | "help"))
+            => (c, &command_args[1..]),
+        Some("--help" | "-h") => ("help", &command_args[1..]),
+        _ => ("run", &command_args[..]),
+    };
+    match command {
+        "help" => {
+            print!("{}", USAGE);
+            return;
+        },
+        "render" => {
+            run_render(options);
+            return;
+        },
+        "analyze" => {
+            run_analyze(options);
+            return;
+        },
+        "sweep" => {
+            run_sweep(options);
+            return;
+        },
+        "ensemble" => {
+            run_ensemble(options);
+            return;
+        },
+        "reference" => {
+            run_reference(options);
+            return;
+        },
+        "resample" => {
+            run_resample(options);
+            return;
+        },
+        "fdm" => {
+            run_fdm(options);
+            return;
+        },
+        _ => (),
+    }
+    // bench [filter] times the kernels instead of running, as cargo bench
+    // does (see src/bench.rs).
+    if command == "bench" {
+        let filter: Option<&str> = options.first().map(String::as_str);
+        let accuracies: Vec<FftAccuracy> = run_fft_accuracy(filter);
+        print!("{}", report(&run_benchmarks(1.0, filter), &accuracies));
+        if !accuracies.iter().all(FftAccuracy::within_limit) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    // validate [filter] checks the steps against answers in closed form,
+    // as cargo test does (see src/validation.rs).
+    if command == "validate" {
+        let validations: Vec<Validation> = run_validation(
+            options.first().map(String::as_str));
+        print!("{}", validation_report(&validations));
+        if !validations.iter().all(Validation::passed) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if command == "convergence" {
+        let studies: Vec<ConvergenceStudy> = run_convergence(
+            options.first().map(String::as_str));
+        print!("{}", convergence_report(&studies));
+        if !studies.iter().all(ConvergenceStudy::passed) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if command == "serve" {
+        let address: String = options.first().cloned()
+            .unwrap_or_else(|| "127.0.0.1:8090".to_string());
+        Server {address}.run().unwrap_or_else(|e| exit_with_error(e));
+        return;
+    }
+    let code: i32 = run_simulation(command, options, args);
+    if code != 0 {
+        std::process::exit(code);
     }
-    let _ = save_f32_simulation_data("last_state.bin".to_string(),
-                                     psi_vec.as_slice(),
-                                     potential_vec.as_slice());
 }
@@ -0,0 +1,191 @@
+use crate::config::*;
+use crate::log::*;
+
+/* A record of how a run was made, written as JSON next to its output
+so that results can be traced back to the settings, build and machine
+that produced them:
+
+    {
+      "version": "0.1.0",
+      "git_hash": "...",
+      "args": ["--config", "settings.txt"],
+      "hostname": "...",
+      "started": "2026-01-31T12:00:00Z",
+      "wall_time": 1234.5,
+      "phases": {"setup": 1.2, "steps": 1230.1, "output": 3.2},
+      "seeds": {"trajectories_seed": 1},
+      "config": {"observables_every": "10", "hdf5_file": null, ...},
+      "unused_config": ["misspelt_key"]
+    }
+
+Times are in seconds. The config has every key looked up during the
+run with the value used, its default if it was not set, or null if it
+has none, and unused_config the keys of the file that were not. The
+git hash is that of the source tree at build time, followed by "-dirty"
+if it had uncommitted changes, or "unknown" if it was not built from a
+git checkout. */
+pub struct RunManifest {
+    pub args: Vec<String>,
+    pub hostname: String,
+    pub started: std::time::SystemTime,
+    pub wall_time: f64,
+    pub phases: Vec<(String, f64)>,
+    pub config: Vec<(String, Option<String>)>,
+    pub unused_config: Vec<String>,
+}
+
+/* Measures the time taken by consecutive phases of a run. */
+pub struct PhaseTimer {
+    pub started: std::time::SystemTime,
+    start: std::time::Instant,
+    last: std::time::Instant,
+    pub phases: Vec<(String, f64)>,
+}
+
+impl Default for PhaseTimer {
+    fn default() -> PhaseTimer {
+        let now = std::time::Instant::now();
+        return PhaseTimer {started: std::time::SystemTime::now(),
+                           start: now, last: now, phases: Vec::new()};
+    }
+}
+
+impl PhaseTimer {
+    /* End the current phase, giving it a name. */
+    pub fn lap(&mut self, name: &str) {
+        let now = std::time::Instant::now();
+        let seconds: f64 = (now - self.last).as_secs_f64();
+        log_event(LogLevel::Info, "run", "phase",
+                  &[("phase", name.into()), ("seconds", seconds.into())]);
+        self.phases.push((name.to_string(), seconds));
+        self.last = now;
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        return self.start.elapsed().as_secs_f64();
+    }
+}
+
+/* The name of this machine, or "unknown". */
+pub fn hostname() -> String {
+    if let Ok(name) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        return name.trim().to_string();
+    }
+    for variable in ["HOSTNAME", "COMPUTERNAME"] {
+        if let Ok(name) = std::env::var(variable) {
+            return name;
+        }
+    }
+    return "unknown".to_string();
+}
+
+/* The time in UTC as in 2026-01-31T12:00:00Z. */
+pub fn utc_timestamp(time: std::time::SystemTime) -> String {
+    let seconds: u64 = time.duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs()).unwrap_or(0);
+    // Days since 1970-01-01 to a date, from
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z: i64 = (seconds/86400) as i64 + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let doe: i64 = z - era*146097;
+    let yoe: i64 = (doe - doe/1460 + doe/36524 - doe/146096)/365;
+    let doy: i64 = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp: i64 = (5*doy + 2)/153;
+    let day: i64 = doy - (153*mp + 2)/5 + 1;
+    let month: i64 = if mp < 10 {mp + 3} else {mp - 9};
+    let year: i64 = yoe + era*400 + if month <= 2 {1} else {0};
+    let s: u64 = seconds % 86400;
+    return format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day,
+                   s/3600, s/60 % 60, s % 60);
+}
+
+/* The string as a JSON string literal. */
+pub fn json_string(s: &str) -> String {
+    let mut out: String = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(
+                &format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    return out;
+}
+
+/* A number as JSON, which has no infinities or NaN. */
+fn json_number(x: f64) -> String {
+    return if x.is_finite() {format!("{}", x)} else {"null".to_string()};
+}
+
+impl RunManifest {
+    /* The manifest of a run with the given config and command line
+    arguments, timed by timer. */
+    pub fn new(config: &Config, args: Vec<String>, timer: &PhaseTimer
+               ) -> RunManifest {
+        return RunManifest {
+            args, hostname: hostname(), started: timer.started,
+            wall_time: timer.elapsed(), phases: timer.phases.clone(),
+            config: config.resolved(), unused_config: config.unused()};
+    }
+
+    /* The seeds of the random number generators, which are the config
+    keys ending in _seed. */
+    pub fn seeds(&self) -> Vec<(String, String)> {
+        return self.config.iter()
+            .filter(|(k, _)| k.ends_with("_seed"))
+            .filter_map(|(k, v)| v.as_ref().map(|v| (k.clone(), v.clone())))
+            .collect();
+    }
+
+    pub fn to_json(&self) -> String {
+        let list = |items: Vec<String>| -> String {
+            return format!("[{}]", items.join(", "));
+        };
+        let object = |items: Vec<(String, String)>, indent: &str| -> String {
+            if items.is_empty() {
+                return "{}".to_string();
+            }
+            let lines: Vec<String> = items.iter().map(
+                |(k, v)| format!("{}  {}: {}", indent, json_string(k), v))
+                .collect();
+            return format!("{{\n{}\n{}}}", lines.join(",\n"), indent);
+        };
+        let fields: Vec<(String, String)> = vec![
+            ("version".to_string(), json_string(env!("CARGO_PKG_VERSION"))),
+            ("git_hash".to_string(), json_string(
+                option_env!("QM2D_GIT_HASH").unwrap_or("unknown"))),
+            ("args".to_string(), list(
+                self.args.iter().map(|a| json_string(a)).collect())),
+            ("hostname".to_string(), json_string(&self.hostname)),
+            ("started".to_string(), json_string(
+                &utc_timestamp(self.started))),
+            ("wall_time".to_string(), json_number(self.wall_time)),
+            ("phases".to_string(), object(
+                self.phases.iter().map(|(k, t)| (k.clone(), json_number(*t)))
+                    .collect(), "  ")),
+            ("seeds".to_string(), object(
+                self.seeds().into_iter().map(|(k, v)| (k, match v.parse::<u64>() {
+                    Ok(n) => n.to_string(),
+                    Err(_) => json_string(&v),
+                })).collect(), "  ")),
+            ("config".to_string(), object(
+                self.config.iter().map(|(k, v)| (k.clone(), match v {
+                    Some(v) => json_string(v),
+                    None => "null".to_string(),
+                })).collect(), "  ")),
+            ("unused_config".to_string(), list(
+                self.unused_config.iter().map(|k| json_string(k)).collect())),
+        ];
+        return object(fields, "") + "\n";
+    }
+
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        return std::fs::write(filename, self.to_json());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::complex::*;
+use crate::parallel::*;
+use crate::log::*;
+
+pub fn norm_squared(psi: &[Complex<f32>]) -> f64 {
+    let [sum] = sum_parts(psi.len(), psi.len(), |range| {
+        let mut sum: CompensatedSum = CompensatedSum::default();
+        for i in range {
+            sum += psi[i].length_squared() as f64;
+        }
+        return [sum.value()];
+    });
+    return sum;
+}
+
+pub fn normalize(psi: &mut [Complex<f32>], target: f64) {
+    let norm: f64 = norm_squared(psi);
+    if norm > 0.0 {
+        let s: f32 = f64::sqrt(target/norm) as f32;
+        for z in psi.iter_mut() {
+            *z = z.scale(s);
+        }
+    }
+}
+
+/* What to do when the norm drifts by more than the tolerance. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NormAction {
+    Log, // print a warning the first time
+    Renormalize, // rescale psi to undo the numerical drift
+    Abort, // stop the simulation
+}
+
+impl NormAction {
+    pub fn parse(s: &str) -> Result<NormAction, String> {
+        return match s {
+            "log" => Ok(NormAction::Log),
+            "renormalize" => Ok(NormAction::Renormalize),
+            "abort" => Ok(NormAction::Abort),
+            _ => Err(format!(
+                "unknown norm action '{}', expected log, renormalize or abort",
+                s)),
+        };
+    }
+}
+
+/* Keeps track of the change in the norm of psi over the simulation,
+separating the part removed on purpose by absorbing regions from
+that caused by numerical error. The split operator steps with a real
+potential and real time step are unitary, so any change in the norm
+over them is numerical error, while the change over the damping
+step or a step with a complex potential is counted as absorbed.
+
+For imaginary time steps the norm is not conserved by design and
+the monitor should not be used. */
+pub struct NormMonitor {
+    pub initial: f64, // norm at the start
+    pub absorbed: f64, // total norm removed by absorbing regions
+    pub numerical: f64, // total change in norm from numerical error
+    pub tolerance: f64, // allowed relative numerical drift
+    pub action: NormAction,
+    warned: bool,
+}
+
+impl NormMonitor {
+    pub fn new(psi: &[Complex<f32>], tolerance: f64,
+               action: NormAction) -> NormMonitor {
+        return NormMonitor {initial: norm_squared(psi), absorbed: 0.0,
+                            numerical: 0.0, tolerance, action,
+                            warned: false};
+    }
+
+    pub fn record_absorbed(&mut self, before: f64, after: f64) {
+        self.absorbed += before - after;
+    }
+
+    pub fn record_numerical(&mut self, before: f64, after: f64) {
+        self.numerical += after - before;
+    }
+
+    /* Numerical change in the norm relative to the initial norm. */
+    pub fn relative_drift(&self) -> f64 {
+        if self.initial == 0.0 {
+            return 0.0;
+        }
+        return self.numerical/self.initial;
+    }
+
+    /* Apply the action if the drift exceeds the tolerance. Renormalizing
+    restores the norm to the initial norm minus what was absorbed, and
+    resets the drift. Returns an error if the run should stop. */
+    pub fn check(&mut self, psi: &mut [Complex<f32>],
+                 step: usize) -> Result<(), String> {
+        let drift: f64 = self.relative_drift();
+        if f64::abs(drift) <= self.tolerance {
+            return Ok(());
+        }
+        let message: String = format!(
+            "relative norm drift of {:e} at step {} exceeds the tolerance {:e}",
+            drift, step, self.tolerance);
+        let fields: [LogField; 2] = [("drift", drift.into()),
+                                     ("step", step.into())];
+        match self.action {
+            NormAction::Log => {
+                if !self.warned {
+                    log_event(LogLevel::Warn, "norm", "drift", &fields);
+                    println!("Warning: {}", message);
+                    self.warned = true;
+                }
+            },
+            NormAction::Renormalize => {
+                log_event(LogLevel::Warn, "norm", "renormalizing", &fields);
+                normalize(psi, self.initial - self.absorbed);
+                self.numerical = 0.0;
+            },
+            NormAction::Abort => {
+                log_event(LogLevel::Error, "norm", "drift", &fields);
+                return Err(message);
+            },
+        };
+        return Ok(());
+    }
+}
+
+/* <a|b> = sum(conj(a)*b) over the grid. */
+pub fn inner_product(a: &[Complex<f32>], b: &[Complex<f32>]) -> Complex<f64> {
+    let mut sum: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    for i in 0..a.len() {
+        let ai: Complex<f64> = a[i].into();
+        let bi: Complex<f64> = b[i].into();
+        sum = sum + ai.conj()*bi;
+    }
+    return sum;
+}
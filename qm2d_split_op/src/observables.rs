@@ -0,0 +1,204 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::spectral::*;
+use crate::parallel::*;
+
+/* Expectation values of a state, normalized by its norm so that they
+remain meaningful when it is not normalized (or when an absorbing
+region removes part of it). Positions are in [0, 1] as for the
+initial wave packet, and momenta and energies are in the units of
+the simulation, in which the kinetic energy is p^2/2 with p in units
+of inverse grid spacing. */
+pub struct Expectations {
+    pub norm: f64, // sum of |psi|^2 over the grid
+    pub x: f64, pub y: f64,
+    pub px: f64, pub py: f64,
+    pub kinetic: f64,
+    pub potential: f64, // from the real part of the potential
+    pub interaction: f64, // g/2*|psi|^4 term of the GPE
+    pub total: f64,
+    // Second moments, used for the uncertainties
+    pub x2: f64, pub y2: f64,
+    pub px2: f64, pub py2: f64,
+}
+
+impl Expectations {
+    pub fn names() -> [&'static str; 9] {
+        return ["norm", "x", "y", "px", "py",
+                "kinetic", "potential", "interaction", "total"];
+    }
+
+    pub fn values(&self) -> [f64; 9] {
+        return [self.norm, self.x, self.y, self.px, self.py,
+                self.kinetic, self.potential, self.interaction, self.total];
+    }
+}
+
+/* <x> and <y> alone, which unlike compute_expectations need no fft. */
+pub fn position_expectation(psi: &[Complex<f32>]) -> (f64, f64) {
+    let mut norm: CompensatedSum = CompensatedSum::default();
+    let mut x_sum: CompensatedSum = CompensatedSum::default();
+    let mut y_sum: CompensatedSum = CompensatedSum::default();
+    for i in 0..N {
+        for j in 0..N {
+            let abs2: f64 = psi[i*N + j].length_squared() as f64;
+            norm += abs2;
+            x_sum += abs2*(j as f64)/(N as f64);
+            y_sum += abs2*(i as f64)/(N as f64);
+        }
+    }
+    return (x_sum.value()/norm.value(), y_sum.value()/norm.value());
+}
+
+/* Compute the position and energy expectation values of psi directly,
+and the momentum and kinetic energy from its fft, which is stored in
+scratch. g is the strength of the nonlinear term, as in
+Nonlinear::square. */
+pub fn compute_expectations(psi: &[Complex<f32>],
+                            potential: &[Complex<f32>], g: f32,
+                            scratch: &mut [Complex<f32>]) -> Expectations {
+    // Sums over the rows, split between threads.
+    let [norm, x_sum, y_sum, x2_sum, y2_sum, potential_sum, interaction_sum]
+        = sum_parts(N, N*N, |rows| {
+        let mut sums: [CompensatedSum; 7] = [CompensatedSum::default(); 7];
+        for i in rows {
+            for j in 0..N {
+                let abs2: f64 = psi[i*N + j].length_squared() as f64;
+                let x: f64 = (j as f64)/(N as f64);
+                let y: f64 = (i as f64)/(N as f64);
+                sums[0] += abs2;
+                sums[1] += abs2*x;
+                sums[2] += abs2*y;
+                sums[3] += abs2*x*x;
+                sums[4] += abs2*y*y;
+                sums[5] += abs2*(potential[i*N + j].real as f64);
+                sums[6] += 0.5*(g as f64)*abs2*abs2;
+            }
+        }
+        return sums.map(|s| s.value());
+    });
+    scratch.copy_from_slice(psi);
+    fft2_in_place(scratch, true);
+    let scratch: &[Complex<f32>] = scratch;
+    let [norm_p, px_sum, py_sum, px2_sum, py2_sum, kinetic_sum]
+        = sum_parts(N, N*N, |rows| {
+        let mut sums: [CompensatedSum; 6] = [CompensatedSum::default(); 6];
+        for i in rows {
+            let py: f64 = fft_momentum(i, N) as f64;
+            for j in 0..N {
+                let px: f64 = fft_momentum(j, N) as f64;
+                let abs2: f64 = scratch[i*N + j].length_squared() as f64;
+                sums[0] += abs2;
+                sums[1] += abs2*px;
+                sums[2] += abs2*py;
+                sums[3] += abs2*px*px;
+                sums[4] += abs2*py*py;
+                sums[5] += 0.5*abs2*(px*px + py*py);
+            }
+        }
+        return sums.map(|s| s.value());
+    });
+    if norm == 0.0 {
+        return Expectations {norm, x: 0.0, y: 0.0, px: 0.0, py: 0.0,
+                             kinetic: 0.0, potential: 0.0, interaction: 0.0,
+                             total: 0.0,
+                             x2: 0.0, y2: 0.0, px2: 0.0, py2: 0.0};
+    }
+    let kinetic: f64 = kinetic_sum/norm_p;
+    let potential: f64 = potential_sum/norm;
+    let interaction: f64 = interaction_sum/norm;
+    return Expectations {
+        norm, x: x_sum/norm, y: y_sum/norm,
+        px: px_sum/norm_p, py: py_sum/norm_p,
+        kinetic, potential, interaction,
+        total: kinetic + potential + interaction,
+        x2: x2_sum/norm, y2: y2_sum/norm,
+        px2: px2_sum/norm_p, py2: py2_sum/norm_p,
+    };
+}
+
+/* Standard deviations of position and momentum, as well as the
+uncertainty products. So that the products are dimensionless (in units
+of hbar = 1) they use the position spreads in grid points, while dx and
+dy are in [0, 1] units like the expectation values.
+
+Since these are computed on a periodic grid, a state that is spread
+over the boundary will have a spuriously large position spread. */
+pub struct Uncertainties {
+    pub dx: f64, pub dy: f64,
+    pub dpx: f64, pub dpy: f64,
+    pub dx_dpx: f64, pub dy_dpy: f64,
+}
+
+impl Uncertainties {
+    pub fn from_expectations(e: &Expectations) -> Uncertainties {
+        // Rounding can make a variance of zero slightly negative.
+        let sd = |m2: f64, m: f64| f64::sqrt(f64::max(0.0, m2 - m*m));
+        let dx: f64 = sd(e.x2, e.x);
+        let dy: f64 = sd(e.y2, e.y);
+        let dpx: f64 = sd(e.px2, e.px);
+        let dpy: f64 = sd(e.py2, e.py);
+        return Uncertainties {
+            dx, dy, dpx, dpy,
+            dx_dpx: dx*(N as f64)*dpx,
+            dy_dpy: dy*(N as f64)*dpy,
+        };
+    }
+
+    pub fn names() -> [&'static str; 6] {
+        return ["dx", "dy", "dpx", "dpy", "dx_dpx", "dy_dpy"];
+    }
+
+    pub fn values(&self) -> [f64; 6] {
+        return [self.dx, self.dy, self.dpx, self.dpy,
+                self.dx_dpx, self.dy_dpy];
+    }
+
+    /* The Heisenberg uncertainty principle requires both products to
+    be at least hbar/2. If one is smaller by more than the relative
+    tolerance, the state has been corrupted by numerical error, and a
+    description of the violation is returned. */
+    pub fn check(&self, tolerance: f64) -> Option<String> {
+        let bound: f64 = 0.5*(1.0 - tolerance);
+        if self.dx_dpx < bound || self.dy_dpy < bound {
+            return Some(format!(
+                "uncertainty products dx*dpx = {}, dy*dpy = {} are \
+                 below hbar/2, which indicates numerical error",
+                self.dx_dpx, self.dy_dpy));
+        }
+        return None;
+    }
+}
+
+/* <L_z> = <x p_y - y p_x>, in units of hbar, about the point (x0, y0)
+given in [0, 1], with the momenta applied as spectral derivatives and
+the positions measured in grid points. ddx and ddy are scratch arrays
+for the derivatives. The positions are not wrapped, so the wave
+function should stay clear of the boundaries relative to the origin.
+
+Reference:
+
+Wikipedia - Angular momentum operator
+https://en.wikipedia.org/wiki/Angular_momentum_operator
+*/
+pub fn angular_momentum(psi: &[Complex<f32>], x0: f32, y0: f32,
+                        ddx: &mut [Complex<f32>], ddy: &mut [Complex<f32>]
+                        ) -> f64 {
+    spectral_gradient(psi, ddx, ddy);
+    let mut norm: CompensatedSum = CompensatedSum::default();
+    let mut lz: CompensatedSum = CompensatedSum::default();
+    for i in 0..N {
+        let y: f64 = (i as f64) - (y0 as f64)*(N as f64);
+        for j in 0..N {
+            let x: f64 = (j as f64) - (x0 as f64)*(N as f64);
+            let k: usize = i*N + j;
+            let conj_psi: Complex<f32> = psi[k].conj();
+            norm += psi[k].length_squared() as f64;
+            // Re(conj(psi)*(-i)*(x*ddy - y*ddx)) = Im(conj(psi)*(...))
+            lz += x*((conj_psi*ddy[k]).imag as f64)
+                - y*((conj_psi*ddx[k]).imag as f64);
+        }
+    }
+    return lz.value()/norm.value();
+}
@@ -0,0 +1,281 @@
+use crate::complex::*;
+use crate::fft::*;
+use crate::field::*;
+use crate::parallel::*;
+use crate::potentials::*;
+use crate::scratch::*;
+use crate::split_op::*;
+use std::io::{Read, Seek, Write};
+
+/* An n by n grid of complex values kept in a file instead of memory,
+row after row, each value as the little-endian f32 real and imaginary
+parts, which numpy reads with fromfile(name, complex64).reshape(n, n).
+It is read and written a slab of rows, or a square block, at a time,
+so that the memory needed does not grow with n^2. The operating system
+caches what fits of the file, so a grid that fits in memory is only
+slowed by the copies; one that does not is as fast as the disk.
+
+Memory mapping the file would save the copies, but needs system calls
+that the standard library does not wrap. */
+pub struct DiskGrid {
+    file: std::fs::File,
+    pub n: usize,
+}
+
+impl DiskGrid {
+    /* Create, or truncate, the file for an n by n grid of zeros. */
+    pub fn create(filename: &str, n: usize) -> std::io::Result<DiskGrid> {
+        let file = std::fs::OpenOptions::new().read(true).write(true)
+            .create(true).truncate(true).open(filename)?;
+        file.set_len((8*n*n) as u64)?;
+        return Ok(DiskGrid {file, n});
+    }
+
+    /* Read count values starting from the given index of the grid. */
+    fn read_at(&mut self, index: usize, values: &mut [Complex<f32>]
+               ) -> std::io::Result<()> {
+        let mut bytes: Vec<u8> = vec![0; 8*values.len()];
+        self.file.seek(std::io::SeekFrom::Start((8*index) as u64))?;
+        self.file.read_exact(&mut bytes)?;
+        for (z, b) in values.iter_mut().zip(bytes.chunks(8)) {
+            *z = Complex {
+                real: f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                imag: f32::from_le_bytes([b[4], b[5], b[6], b[7]])};
+        }
+        return Ok(());
+    }
+
+    fn write_at(&mut self, index: usize, values: &[Complex<f32>]
+                ) -> std::io::Result<()> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(8*values.len());
+        for z in values.iter() {
+            bytes.extend(z.real.to_le_bytes());
+            bytes.extend(z.imag.to_le_bytes());
+        }
+        self.file.seek(std::io::SeekFrom::Start((8*index) as u64))?;
+        return self.file.write_all(&bytes);
+    }
+
+    /* Read the rows from first_row on into rows, which holds a whole
+    number of them. */
+    pub fn read_rows(&mut self, first_row: usize, rows: &mut [Complex<f32>]
+                     ) -> std::io::Result<()> {
+        return self.read_at(first_row*self.n, rows);
+    }
+
+    pub fn write_rows(&mut self, first_row: usize, rows: &[Complex<f32>]
+                      ) -> std::io::Result<()> {
+        return self.write_at(first_row*self.n, rows);
+    }
+
+    /* Call f with each slab of slab_rows rows (fewer for the last) and
+    the index of its first row, writing the slab back afterwards. */
+    pub fn for_each_slab(&mut self, slab_rows: usize,
+                         mut f: impl FnMut(usize, &mut [Complex<f32>])
+                         ) -> std::io::Result<()> {
+        let n: usize = self.n;
+        return with_scratch(slab_rows*n, |slab| {
+            for first_row in (0..n).step_by(slab_rows) {
+                let rows: usize = usize::min(slab_rows, n - first_row);
+                let slab: &mut [Complex<f32>] = &mut slab[..rows*n];
+                self.read_rows(first_row, slab)?;
+                f(first_row, slab);
+                self.write_rows(first_row, slab)?;
+            }
+            return Ok(());
+        });
+    }
+
+    /* Read the square block of size values a side whose top left
+    corner is at row i and column j. */
+    fn read_block(&mut self, i: usize, j: usize, size: usize,
+                  block: &mut [Complex<f32>]) -> std::io::Result<()> {
+        for r in 0..size {
+            self.read_at((i + r)*self.n + j, &mut block[r*size..(r + 1)*size])?;
+        }
+        return Ok(());
+    }
+
+    fn write_block(&mut self, i: usize, j: usize, size: usize,
+                   block: &[Complex<f32>]) -> std::io::Result<()> {
+        for r in 0..size {
+            self.write_at((i + r)*self.n + j, &block[r*size..(r + 1)*size])?;
+        }
+        return Ok(());
+    }
+
+    /* Transpose the grid by swapping transposed blocks of size values
+    a side, which must divide n. */
+    pub fn transpose(&mut self, size: usize) -> std::io::Result<()> {
+        return with_scratch_pair(size*size, |a, b| {
+            for i in (0..self.n).step_by(size) {
+                for j in (i..self.n).step_by(size) {
+                    self.read_block(i, j, size, a)?;
+                    square_transpose_in_place(a, size);
+                    if i == j {
+                        self.write_block(i, j, size, a)?;
+                        continue;
+                    }
+                    self.read_block(j, i, size, b)?;
+                    square_transpose_in_place(b, size);
+                    self.write_block(j, i, size, a)?;
+                    self.write_block(i, j, size, b)?;
+                }
+            }
+            return Ok(());
+        });
+    }
+
+    /* The 2D fft of the grid, as base_square_fft2_in_place: its rows are
+    transformed slab_rows at a time, it is transposed, and the rows are
+    transformed and it is transposed again. slab_rows must divide n. */
+    pub fn fft2(&mut self, is_inverse: bool, slab_rows: usize
+                ) -> std::io::Result<()> {
+        let n: usize = self.n;
+        for _ in 0..2 {
+            self.for_each_slab(slab_rows, |_, slab| {
+                rows_parts_mut(slab, n, |rows| {
+                    for row in rows.chunks_mut(n) {
+                        base_f32_fft_in_place(row, n, is_inverse);
+                    }
+                });
+            })?;
+            self.transpose(slab_rows)?;
+        }
+        return Ok(());
+    }
+}
+
+/* The split-step method on a grid kept in files, as split_op::step
+without a nonlinear term, for grids of any power of two size, which
+may not fit in memory. psi and the factors of the potential are each a
+DiskGrid; the kinetic factors are computed as they are needed. At most
+a few slabs of slab_rows rows are in memory at a time. */
+pub struct OutOfCore {
+    pub psi: DiskGrid,
+    spatial: DiskGrid,
+    pub slab_rows: usize,
+    spatial_t: Option<f32>,
+}
+
+impl OutOfCore {
+    /* A grid of n by n points kept in filename, with the factors of the
+    potential kept next to it in filename.spatial. */
+    pub fn create(filename: &str, n: usize, slab_rows: usize
+                  ) -> std::io::Result<OutOfCore> {
+        if !n.is_power_of_two() || slab_rows == 0
+            || !n.is_multiple_of(slab_rows) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("the grid size {} must be a power of two and a \
+                         multiple of the {} rows of a slab", n, slab_rows)));
+        }
+        return Ok(OutOfCore {
+            psi: DiskGrid::create(filename, n)?,
+            spatial: DiskGrid::create(&format!("{}.spatial", filename), n)?,
+            slab_rows, spatial_t: None});
+    }
+
+    /* Set psi to f(x, y) with x and y in [0, 1]. */
+    pub fn fill(&mut self, f: impl Fn(f32, f32) -> Complex<f32>
+                ) -> std::io::Result<()> {
+        let n: usize = self.psi.n;
+        return self.psi.for_each_slab(self.slab_rows, |first_row, slab| {
+            for (k, z) in slab.iter_mut().enumerate() {
+                *z = f(((k % n) as f32)/(n as f32),
+                       ((first_row + k/n) as f32)/(n as f32));
+            }
+        });
+    }
+
+    /* Compute the factors exp(-i*V*dt/2) of the potential at time t,
+    unless they were computed before and it is not time dependent. */
+    fn update_spatial(&mut self, potential: &dyn TimeDependentPotential,
+                      t: f32, dt: Complex<f32>) -> std::io::Result<()> {
+        if self.spatial_t.is_some() && !potential.is_time_dependent() {
+            return Ok(());
+        }
+        let n: usize = self.psi.n;
+        let half_dt: Complex<f32> = dt.scale(0.5);
+        self.spatial.for_each_slab(self.slab_rows, |first_row, slab| {
+            for (k, u) in slab.iter_mut().enumerate() {
+                let v: f32 = potential.value_at(
+                    ((k % n) as f32)/(n as f32),
+                    ((first_row + k/n) as f32)/(n as f32), t);
+                *u = c64exp(Complex {real: 0.0, imag: -1.0}
+                            * Complex {real: v, imag: 0.0} * half_dt);
+            }
+        })?;
+        self.spatial_t = Some(t);
+        return Ok(());
+    }
+
+    /* Multiply psi by the factors of the potential. */
+    fn propagate_spatial(&mut self) -> std::io::Result<()> {
+        let (n, slab_rows) = (self.psi.n, self.slab_rows);
+        return with_scratch_pair(slab_rows*n, |factors, slab| {
+            for first_row in (0..n).step_by(slab_rows) {
+                self.spatial.read_rows(first_row, factors)?;
+                self.psi.read_rows(first_row, slab)?;
+                multiply_in_place(slab, factors);
+                self.psi.write_rows(first_row, slab)?;
+            }
+            return Ok(());
+        });
+    }
+
+    /* Advance psi by a time step dt from time t, with the potential
+    given by its value_at, returning the norm after the step. */
+    pub fn step(&mut self, potential: &dyn TimeDependentPotential, t: f32,
+                dt: Complex<f32>) -> std::io::Result<f64> {
+        let (n, slab_rows) = (self.psi.n, self.slab_rows);
+        self.update_spatial(potential, t, dt)?;
+        self.propagate_spatial()?;
+        self.psi.fft2(false, slab_rows)?;
+        // The momenta as in init_momentum_squared.
+        let momentum = |k: usize| -> f32 {
+            let shift: i32 = if k < n/2 {k as i32} else {k as i32 - n as i32};
+            return 2.0*std::f32::consts::PI*(shift as f32)/(n as f32);
+        };
+        self.psi.for_each_slab(slab_rows, |first_row, slab| {
+            with_scratch(slab.len(), |factors| {
+                for (k, u) in factors.iter_mut().enumerate() {
+                    let (px, py): (f32, f32)
+                        = (momentum(first_row + k/n), momentum(k % n));
+                    *u = c64exp(Complex {
+                        real: 0.0, imag: -0.5*(px*px + py*py)} * dt);
+                }
+                multiply_in_place(slab, factors);
+            });
+        })?;
+        self.psi.fft2(true, slab_rows)?;
+        // The absorbing layer of each slab needs the first row of the
+        // next, which is read before it is damped.
+        with_scratch(n, |below| with_scratch(slab_rows*n, |slab| -> std::io::Result<()> {
+            for first_row in (0..n).step_by(slab_rows) {
+                self.psi.read_rows(first_row, slab)?;
+                self.psi.read_rows((first_row + slab_rows) % n, below)?;
+                dampen_rows(slab, n, first_row, Some(below), dt.real);
+                self.psi.write_rows(first_row, slab)?;
+            }
+            return Ok(());
+        }))?;
+        self.propagate_spatial()?;
+        return self.norm_squared();
+    }
+
+    pub fn norm_squared(&mut self) -> std::io::Result<f64> {
+        let mut norm: CompensatedSum = CompensatedSum::default();
+        let (n, slab_rows) = (self.psi.n, self.slab_rows);
+        with_scratch(slab_rows*n, |slab| -> std::io::Result<()> {
+            for first_row in (0..n).step_by(slab_rows) {
+                self.psi.read_rows(first_row, slab)?;
+                for z in slab.iter() {
+                    norm += z.length_squared() as f64;
+                }
+            }
+            return Ok(());
+        })?;
+        return Ok(norm.value());
+    }
+}
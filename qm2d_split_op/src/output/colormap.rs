@@ -0,0 +1,154 @@
+/* Colormaps for drawing a scalar field in [0, 1]. */
+#[derive(Clone, PartialEq, Debug)]
+pub enum Colormap {
+    Gray,
+    Viridis,
+    Magma,
+    Inferno,
+    Turbo,
+    Hot,
+    Table(Vec<[u8; 3]>), // equally spaced colors, as loaded from a file
+}
+
+// Samples of the colormaps at 0, 1/8, ..., 1, between which colors are
+// interpolated linearly.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84], [72, 40, 120], [62, 73, 137], [49, 104, 142],
+    [38, 130, 142], [31, 158, 137], [53, 183, 121], [110, 206, 88],
+    [253, 231, 37]];
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4], [28, 16, 68], [79, 18, 123], [129, 37, 129],
+    [181, 54, 122], [229, 80, 100], [251, 135, 97], [254, 194, 135],
+    [252, 253, 191]];
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4], [31, 12, 72], [85, 15, 109], [136, 34, 106],
+    [186, 54, 85], [227, 89, 51], [249, 142, 9], [249, 203, 53],
+    [252, 255, 164]];
+
+fn interpolate_table(table: &[[u8; 3]], v: f32) -> [u8; 3] {
+    if table.len() == 1 {
+        return table[0];
+    }
+    let s: f32 = v*((table.len() - 1) as f32);
+    let k: usize = usize::min(s as usize, table.len() - 2);
+    let f: f32 = s - k as f32;
+    let mut color: [u8; 3] = [0; 3];
+    for c in 0..3 {
+        let a: f32 = table[k][c] as f32;
+        let b: f32 = table[k + 1][c] as f32;
+        color[c] = f32::round(a + f*(b - a)) as u8;
+    }
+    return color;
+}
+
+/* Turbo, from the polynomial fit published with it, which is within a
+few percent of the original table.
+
+Reference:
+
+Anton Mikhailov.
+Turbo, An Improved Rainbow Colormap for Visualization.
+https://research.google/blog/turbo-an-improved-rainbow-colormap-for-visualization/
+*/
+fn turbo(v: f32) -> [f32; 3] {
+    // In double precision, as the terms largely cancel.
+    let v: f64 = v as f64;
+    let polynomial = |c: [f64; 6]| -> f32 {
+        (c[0] + v*(c[1] + v*(c[2] + v*(c[3] + v*(c[4] + v*c[5]))))) as f32
+    };
+    return [polynomial([0.13572138, 4.61539260, -42.66032258,
+                        132.13108234, -152.94239396, 59.28637943]),
+            polynomial([0.09140261, 2.19418839, 4.84296658,
+                        -14.18503333, 4.27729857, 2.82956604]),
+            polynomial([0.10667330, 12.64194608, -60.58204836,
+                        110.36276771, -89.90310912, 27.34824973])];
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    return std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+}
+
+impl Colormap {
+    pub fn parse(name: &str) -> Result<Colormap, String> {
+        return match name {
+            "gray" | "grey" => Ok(Colormap::Gray),
+            "viridis" => Ok(Colormap::Viridis),
+            "magma" => Ok(Colormap::Magma),
+            "inferno" => Ok(Colormap::Inferno),
+            "turbo" => Ok(Colormap::Turbo),
+            "hot" => Ok(Colormap::Hot),
+            _ => Err(format!("unknown colormap {}", name)),
+        };
+    }
+
+    /* Load a colormap from a file of equally spaced colors, from the
+    color for 0 to the one for 1. Either each line holds the red, green
+    and blue components separated by commas or spaces, with lines that
+    are not numbers (such as a header) skipped, or the file is a JSON
+    array of [r, g, b] arrays. Components are from 0 to 255, or from 0
+    to 1 if none is above 1. */
+    pub fn load(filename: &str) -> std::io::Result<Colormap> {
+        let text: String = std::fs::read_to_string(filename)?;
+        let mut components = std::vec::Vec::<f32>::new();
+        if text.trim_start().starts_with('[') {
+            let numbers = text.split(|c: char| {
+                !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'
+                  || c == 'e' || c == 'E')
+            });
+            for number in numbers.filter(|s| !s.is_empty()) {
+                components.push(number.parse::<f32>().map_err(
+                    |_| invalid_data(format!("invalid number '{}' in {}",
+                                             number, filename)))?);
+            }
+        } else {
+            for line in text.lines() {
+                let values: Result<Vec<f32>, _> = line
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty()).map(|s| s.parse::<f32>())
+                    .collect();
+                match values {
+                    Ok(v) if v.len() == 3 => components.extend(v),
+                    _ => continue,
+                }
+            }
+        }
+        if components.len() < 3 || !components.len().is_multiple_of(3) {
+            return Err(invalid_data(format!(
+                "{} does not hold a list of colors", filename)));
+        }
+        let scale: f32 = if components.iter().all(|&c| c <= 1.0) {
+            255.0
+        } else {
+            1.0
+        };
+        let table: Vec<[u8; 3]> = components.chunks(3).map(|c| {
+            [f32::round(f32::clamp(scale*c[0], 0.0, 255.0)) as u8,
+             f32::round(f32::clamp(scale*c[1], 0.0, 255.0)) as u8,
+             f32::round(f32::clamp(scale*c[2], 0.0, 255.0)) as u8]
+        }).collect();
+        return Ok(Colormap::Table(table));
+    }
+
+    /* Color of the value v, which is clamped to [0, 1].
+
+    Reference:
+
+    Stefan van der Walt, Nathaniel Smith.
+    A Better Default Colormap for Matplotlib.
+    https://bids.github.io/colormap/
+    */
+    pub fn color(&self, v: f32) -> [u8; 3] {
+        let v: f32 = if v.is_nan() {0.0} else {f32::clamp(v, 0.0, 1.0)};
+        let to_u8 = |c: f32| f32::round(255.0*f32::clamp(c, 0.0, 1.0)) as u8;
+        return match self {
+            Colormap::Gray => [to_u8(v); 3],
+            Colormap::Viridis => interpolate_table(&VIRIDIS, v),
+            Colormap::Magma => interpolate_table(&MAGMA, v),
+            Colormap::Inferno => interpolate_table(&INFERNO, v),
+            Colormap::Turbo => turbo(v).map(to_u8),
+            Colormap::Hot => [to_u8(3.0*v), to_u8(3.0*v - 1.0),
+                              to_u8(3.0*v - 2.0)],
+            Colormap::Table(table) => interpolate_table(table, v),
+        };
+    }
+}
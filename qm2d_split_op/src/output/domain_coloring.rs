@@ -0,0 +1,100 @@
+use crate::constants::*;
+use crate::complex::*;
+
+pub struct Color {
+    pub r: f64, pub g: f64, pub b: f64,
+}
+
+/* Function that converts a hue angle to its corresponding color.
+
+References:
+
+Wikipedia - Domain coloring
+https://en.wikipedia.org/wiki/Domain_coloring
+
+Wikipedia - Hue
+https://en.wikipedia.org/wiki/Hue
+
+https://en.wikipedia.org/wiki/Hue#/media/File:HSV-RGB-comparison.svg
+
+ */
+pub fn argument_to_color(arg_val: f64) -> Color {
+    let pi: f64 = std::f64::consts::PI;
+    let max_col: f64 = 1.0;
+    let min_col: f64 = 50.0/255.0;
+    let col_range: f64 = max_col - min_col;
+    if arg_val <= pi/3.0 && arg_val >= 0.0 {
+        return Color {
+            r: max_col,
+            g: min_col + col_range*arg_val/(pi/3.0), 
+            b: min_col};
+    } else if arg_val > pi/3.0 && arg_val <= 2.0*pi/3.0 {
+        return Color {
+            r: max_col - col_range*(arg_val - pi/3.0)/(pi/3.0),
+            g: max_col, 
+            b: min_col};
+    } else if arg_val > 2.0*pi/3.0 && arg_val <= pi {
+        return Color {
+            r: min_col, 
+            g: max_col,
+            b: min_col + col_range*(arg_val - 2.0*pi/3.0)/(pi/3.0)};
+    } else if arg_val < 0.0 && arg_val > -pi/3.0 {
+        return Color {
+            r: max_col, 
+            g: min_col,
+            b: min_col - col_range*arg_val/(pi/3.0)};
+    } else if arg_val <= -pi/3.0 && arg_val > -2.0*pi/3.0 {
+        return Color {
+            r: max_col + (col_range*(arg_val + pi/3.0)/(pi/3.0)),
+            g: min_col, 
+            b: max_col};
+    } else if arg_val <= -2.0*pi/3.0 && arg_val >= -pi {
+        return Color {
+            r: min_col,
+            g: min_col - (col_range*(arg_val + 2.0*pi/3.0)/(pi/3.0)),
+            b: max_col};
+    }
+    else {
+        return Color {r: min_col, g: max_col, b: max_col};
+    }
+}
+
+/* Color of the complex value z in 8 bit RGB, with its argument as the
+hue and scale*|z|^2 as the brightness. Values brighter than one are
+washed out towards white instead of being clipped, so that the phase
+can still be told apart near the peaks of the density, while the dark
+regions show the nodal lines where interference fringes meet. */
+pub fn complex_to_rgb(z: Complex<f32>, scale: f32) -> [u8; 3] {
+    let c: Color = argument_to_color(z.arg());
+    let v: f64 = (scale*z.length_squared()) as f64;
+    let (brightness, whiteness): (f64, f64) = if v <= 1.0 {
+        (v, 0.0)
+    } else {
+        (1.0, 1.0 - 1.0/v)
+    };
+    let to_u8 = |c: f64| {
+        f64::round(255.0*f64::clamp(brightness*(c + whiteness*(1.0 - c)),
+                                    0.0, 1.0)) as u8
+    };
+    return [to_u8(c.r), to_u8(c.g), to_u8(c.b)];
+}
+
+/* Domain coloring of psi as an 8 bit RGB image, with rows from the top
+of the image to the bottom and y increasing upwards. If scale is zero
+the density is divided by its maximum. */
+pub fn phase_rgb(psi: &[Complex<f32>], scale: f32) -> Vec<u8> {
+    let scale: f32 = if scale > 0.0 {
+        scale
+    } else {
+        let peak: f32 = psi.iter().map(|z| z.length_squared())
+            .fold(0.0, f32::max);
+        if peak > 0.0 {1.0/peak} else {1.0}
+    };
+    let mut rgb = std::vec::Vec::<u8>::with_capacity(3*N*N);
+    for i in (0..N).rev() {
+        for j in 0..N {
+            rgb.extend(complex_to_rgb(psi[i*N + j], scale));
+        }
+    }
+    return rgb;
+}
@@ -0,0 +1,393 @@
+use crate::complex::*;
+use std::io::{Seek, Write};
+
+/* A writer for a small subset of HDF5, enough to store arrays of
+floats, integers and complex numbers in a tree of groups, with numbers
+and strings as attributes, so that the output of a run opens directly
+in h5py, Matlab and other HDF5 tools. The data of each dataset is
+written as soon as it is given, and the groups, dataset headers and
+the superblock that describe it when the file is finished.
+
+The file uses version 2 of the superblock and of the object headers,
+with the links of each group stored compactly in its header and the
+data of each dataset stored contiguously. Complex numbers are
+compounds of two floats named r and i, which h5py reads as complex64.
+
+References:
+
+The HDF Group - HDF5 File Format Specification Version 3.0
+https://docs.hdfgroup.org/hdf5/develop/_f_m_t3.html
+
+Bob Jenkins - lookup3.c (the checksum used for the metadata)
+http://burtleburtle.net/bob/c/lookup3.c
+*/
+pub struct Hdf5Writer {
+    file: std::io::BufWriter<std::fs::File>,
+    end: u64,
+    root: Group,
+}
+
+/* Arrays that can be written as datasets, in row major order. */
+pub enum Hdf5Data<'a> {
+    F32(&'a [f32]),
+    F64(&'a [f64]),
+    I64(&'a [i64]),
+    Complex(&'a [Complex<f32>]),
+}
+
+/* Values of attributes. */
+#[derive(Clone, Debug)]
+pub enum Hdf5Attribute {
+    F64(f64),
+    I64(i64),
+    Text(String),
+}
+
+struct Dataset {
+    name: String,
+    shape: Vec<usize>,
+    datatype: Vec<u8>,
+    address: u64,
+    size: u64,
+    attributes: Vec<(String, Hdf5Attribute)>,
+}
+
+#[derive(Default)]
+struct Group {
+    name: String,
+    groups: Vec<Group>,
+    datasets: Vec<Dataset>,
+    attributes: Vec<(String, Hdf5Attribute)>,
+}
+
+const SIGNATURE: [u8; 8] = [0x89, b'H', b'D', b'F', b'\r', b'\n', 0x1a, b'\n'];
+const SUPERBLOCK_SIZE: u64 = 48;
+const UNDEFINED: u64 = u64::MAX;
+
+// Types of the header messages used.
+const DATASPACE: u8 = 0x01;
+const LINK_INFO: u8 = 0x02;
+const DATATYPE: u8 = 0x03;
+const FILL_VALUE: u8 = 0x05;
+const LINK: u8 = 0x06;
+const LAYOUT: u8 = 0x08;
+const GROUP_INFO: u8 = 0x0a;
+const ATTRIBUTE: u8 = 0x0c;
+
+/* The lookup3 hash of data (hashlittle with an initial value of 0),
+which HDF5 uses as the checksum of its metadata. */
+pub fn lookup3(data: &[u8]) -> u32 {
+    let mix = |a: &mut u32, b: &mut u32, c: &mut u32| {
+        *a = a.wrapping_sub(*c); *a ^= c.rotate_left(4); *c = c.wrapping_add(*b);
+        *b = b.wrapping_sub(*a); *b ^= a.rotate_left(6); *a = a.wrapping_add(*c);
+        *c = c.wrapping_sub(*b); *c ^= b.rotate_left(8); *b = b.wrapping_add(*a);
+        *a = a.wrapping_sub(*c); *a ^= c.rotate_left(16); *c = c.wrapping_add(*b);
+        *b = b.wrapping_sub(*a); *b ^= a.rotate_left(19); *a = a.wrapping_add(*c);
+        *c = c.wrapping_sub(*b); *c ^= b.rotate_left(4); *b = b.wrapping_add(*a);
+    };
+    let word = |bytes: &[u8]| -> u32 {
+        let mut w: [u8; 4] = [0; 4];
+        w[..bytes.len()].copy_from_slice(bytes);
+        u32::from_le_bytes(w)
+    };
+    let initial: u32 = 0xdeadbeef_u32.wrapping_add(data.len() as u32);
+    let (mut a, mut b, mut c) = (initial, initial, initial);
+    let mut rest: &[u8] = data;
+    while rest.len() > 12 {
+        a = a.wrapping_add(word(&rest[0..4]));
+        b = b.wrapping_add(word(&rest[4..8]));
+        c = c.wrapping_add(word(&rest[8..12]));
+        mix(&mut a, &mut b, &mut c);
+        rest = &rest[12..];
+    }
+    if rest.is_empty() {
+        return c;
+    }
+    a = a.wrapping_add(word(&rest[..usize::min(4, rest.len())]));
+    if rest.len() > 4 {
+        b = b.wrapping_add(word(&rest[4..usize::min(8, rest.len())]));
+    }
+    if rest.len() > 8 {
+        c = c.wrapping_add(word(&rest[8..]));
+    }
+    c ^= b; c = c.wrapping_sub(b.rotate_left(14));
+    a ^= c; a = a.wrapping_sub(c.rotate_left(11));
+    b ^= a; b = b.wrapping_sub(a.rotate_left(25));
+    c ^= b; c = c.wrapping_sub(b.rotate_left(16));
+    a ^= c; a = a.wrapping_sub(c.rotate_left(4));
+    b ^= a; b = b.wrapping_sub(a.rotate_left(14));
+    c ^= b; c = c.wrapping_sub(b.rotate_left(24));
+    return c;
+}
+
+/* IEEE little endian floats of 4 or 8 bytes. */
+fn float_type(size: usize) -> Vec<u8> {
+    let (exponent_location, exponent_size, mantissa_size, bias): (
+        u8, u8, u8, u32) = if size == 4 {(23, 8, 23, 127)}
+        else {(52, 11, 52, 1023)};
+    let mut t: Vec<u8> = vec![0x11, 0x20, (8*size - 1) as u8, 0];
+    t.extend((size as u32).to_le_bytes());
+    t.extend(0_u16.to_le_bytes());
+    t.extend(((8*size) as u16).to_le_bytes());
+    t.extend([exponent_location, exponent_size, 0, mantissa_size]);
+    t.extend(bias.to_le_bytes());
+    return t;
+}
+
+/* Signed little endian 64 bit integers. */
+fn integer_type() -> Vec<u8> {
+    let mut t: Vec<u8> = vec![0x10, 0x08, 0, 0];
+    t.extend(8_u32.to_le_bytes());
+    t.extend(0_u16.to_le_bytes());
+    t.extend(64_u16.to_le_bytes());
+    return t;
+}
+
+/* A compound of the real part r and imaginary part i as 4 byte floats. */
+fn complex_type() -> Vec<u8> {
+    let mut t: Vec<u8> = vec![0x16, 2, 0, 0];
+    t.extend(8_u32.to_le_bytes());
+    for (name, offset) in [(b'r', 0_u32), (b'i', 4)] {
+        // The name, null terminated and padded to 8 bytes.
+        t.extend([name, 0, 0, 0, 0, 0, 0, 0]);
+        t.extend(offset.to_le_bytes());
+        // No array dimensions.
+        t.extend([0_u8; 28]);
+        t.extend(float_type(4));
+    }
+    return t;
+}
+
+/* ASCII strings of a fixed length, padded with nulls. */
+fn string_type(length: usize) -> Vec<u8> {
+    let mut t: Vec<u8> = vec![0x13, 0x01, 0, 0];
+    t.extend((length as u32).to_le_bytes());
+    return t;
+}
+
+fn dataspace(shape: &[usize]) -> Vec<u8> {
+    let mut s: Vec<u8> = vec![2, shape.len() as u8, 0,
+                              if shape.is_empty() {0} else {1}];
+    for &n in shape {
+        s.extend((n as u64).to_le_bytes());
+    }
+    return s;
+}
+
+fn pad8(bytes: &mut Vec<u8>) {
+    while !bytes.len().is_multiple_of(8) {
+        bytes.push(0);
+    }
+}
+
+fn attribute_message(name: &str, value: &Hdf5Attribute) -> Vec<u8> {
+    let (datatype, data): (Vec<u8>, Vec<u8>) = match value {
+        Hdf5Attribute::F64(v) => (float_type(8), v.to_le_bytes().to_vec()),
+        Hdf5Attribute::I64(v) => (integer_type(), v.to_le_bytes().to_vec()),
+        Hdf5Attribute::Text(s) => (string_type(usize::max(1, s.len())),
+                                   if s.is_empty() {vec![0]}
+                                   else {s.as_bytes().to_vec()}),
+    };
+    let space: Vec<u8> = dataspace(&[]);
+    let mut m: Vec<u8> = vec![1, 0];
+    m.extend(((name.len() + 1) as u16).to_le_bytes());
+    m.extend((datatype.len() as u16).to_le_bytes());
+    m.extend((space.len() as u16).to_le_bytes());
+    m.extend(name.as_bytes());
+    m.push(0);
+    pad8(&mut m);
+    m.extend(&datatype);
+    pad8(&mut m);
+    m.extend(&space);
+    pad8(&mut m);
+    m.extend(data);
+    return m;
+}
+
+/* A version 2 object header holding the given messages. */
+fn object_header(messages: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut body: Vec<u8> = Vec::new();
+    for (kind, data) in messages {
+        body.push(*kind);
+        body.extend((data.len() as u16).to_le_bytes());
+        body.push(0);
+        body.extend(data);
+    }
+    // Flags: the size of the first chunk is stored in 4 bytes.
+    let mut h: Vec<u8> = vec![b'O', b'H', b'D', b'R', 2, 0x02];
+    h.extend((body.len() as u32).to_le_bytes());
+    h.extend(body);
+    let checksum: u32 = lookup3(&h);
+    h.extend(checksum.to_le_bytes());
+    return h;
+}
+
+fn valid_name(name: &str) -> std::io::Result<()> {
+    if name.is_empty() || name.len() > 255 || name.contains('/') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid HDF5 name {:?}", name)));
+    }
+    return Ok(());
+}
+
+impl Group {
+    /* The group at the path of names separated by '/', relative to
+    this one, created if it does not exist yet. */
+    fn group(&mut self, path: &str) -> std::io::Result<&mut Group> {
+        let mut group: &mut Group = self;
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            valid_name(name)?;
+            let k: usize = match group.groups.iter()
+                .position(|g| g.name == name) {
+                Some(k) => k,
+                None => {
+                    group.groups.push(Group {name: name.to_string(),
+                                             ..Group::default()});
+                    group.groups.len() - 1
+                },
+            };
+            group = &mut group.groups[k];
+        }
+        return Ok(group);
+    }
+
+    /* Write the headers of the datasets and groups below this group and
+    then its own, returning the address of its header. */
+    fn write_headers(&self, file: &mut impl Write, end: &mut u64
+                     ) -> std::io::Result<u64> {
+        let mut links = std::vec::Vec::<(String, u64)>::new();
+        for g in self.groups.iter() {
+            links.push((g.name.clone(), g.write_headers(file, end)?));
+        }
+        for d in self.datasets.iter() {
+            // Space allocated early, with no fill value defined.
+            let fill_value: Vec<u8> = vec![3, 0x09];
+            let mut layout: Vec<u8> = vec![3, 1];
+            layout.extend(d.address.to_le_bytes());
+            layout.extend(d.size.to_le_bytes());
+            let mut messages: Vec<(u8, Vec<u8>)> = vec![
+                (DATASPACE, dataspace(&d.shape)),
+                (DATATYPE, d.datatype.clone()),
+                (FILL_VALUE, fill_value),
+                (LAYOUT, layout)];
+            messages.extend(d.attributes.iter()
+                .map(|(n, v)| (ATTRIBUTE, attribute_message(n, v))));
+            let header: Vec<u8> = object_header(&messages);
+            links.push((d.name.clone(), *end));
+            file.write_all(&header)?;
+            *end += header.len() as u64;
+        }
+        let mut link_info: Vec<u8> = vec![0, 0];
+        link_info.extend(UNDEFINED.to_le_bytes());
+        link_info.extend(UNDEFINED.to_le_bytes());
+        let mut messages: Vec<(u8, Vec<u8>)> = vec![
+            (LINK_INFO, link_info), (GROUP_INFO, vec![0, 0])];
+        for (name, address) in links {
+            let mut link: Vec<u8> = vec![1, 0, name.len() as u8];
+            link.extend(name.as_bytes());
+            link.extend(address.to_le_bytes());
+            messages.push((LINK, link));
+        }
+        messages.extend(self.attributes.iter()
+            .map(|(n, v)| (ATTRIBUTE, attribute_message(n, v))));
+        let header: Vec<u8> = object_header(&messages);
+        let address: u64 = *end;
+        file.write_all(&header)?;
+        *end += header.len() as u64;
+        return Ok(address);
+    }
+}
+
+/* Split a path into the group it is in and its name. */
+fn split_path(path: &str) -> (&str, &str) {
+    return match path.rfind('/') {
+        Some(k) => (&path[..k], &path[k + 1..]),
+        None => ("", path),
+    };
+}
+
+impl Hdf5Writer {
+    pub fn create(filename: &str) -> std::io::Result<Hdf5Writer> {
+        let mut file = std::io::BufWriter::new(
+            std::fs::File::create(filename)?);
+        // Room for the superblock, which is written by finish.
+        file.write_all(&[0; SUPERBLOCK_SIZE as usize])?;
+        return Ok(Hdf5Writer {file, end: SUPERBLOCK_SIZE,
+                              root: Group::default()});
+    }
+
+    /* Write the array data with the given shape as the dataset at path,
+    where the groups it is in are separated by '/' and created as
+    needed, with the given attributes. */
+    pub fn write_dataset(&mut self, path: &str, shape: &[usize],
+                         data: Hdf5Data, attributes: &[(&str, Hdf5Attribute)]
+                         ) -> std::io::Result<()> {
+        let (group, name) = split_path(path);
+        valid_name(name)?;
+        let count: usize = shape.iter().product();
+        let (datatype, bytes): (Vec<u8>, Vec<u8>) = match data {
+            Hdf5Data::F32(v) => (float_type(4), v.iter()
+                .flat_map(|x| x.to_le_bytes()).collect()),
+            Hdf5Data::F64(v) => (float_type(8), v.iter()
+                .flat_map(|x| x.to_le_bytes()).collect()),
+            Hdf5Data::I64(v) => (integer_type(), v.iter()
+                .flat_map(|x| x.to_le_bytes()).collect()),
+            Hdf5Data::Complex(v) => (complex_type(), v.iter()
+                .flat_map(|z| z.real.to_le_bytes().into_iter()
+                          .chain(z.imag.to_le_bytes())).collect()),
+        };
+        let element_size: usize = match datatype[0] & 0x0f {
+            6 => 8,
+            _ => u32::from_le_bytes([datatype[4], datatype[5], datatype[6],
+                                     datatype[7]]) as usize,
+        };
+        if bytes.len() != count*element_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} has {} elements, not the {} of its shape {:?}",
+                        path, bytes.len()/element_size, count, shape)));
+        }
+        for (n, _) in attributes.iter() {
+            valid_name(n)?;
+        }
+        let address: u64 = self.end;
+        self.file.write_all(&bytes)?;
+        self.end += bytes.len() as u64;
+        self.root.group(group)?.datasets.push(Dataset {
+            name: name.to_string(), shape: shape.to_vec(), datatype,
+            address, size: bytes.len() as u64,
+            attributes: attributes.iter()
+                .map(|(n, v)| (n.to_string(), v.clone())).collect()});
+        return Ok(());
+    }
+
+    /* Set an attribute of the group at path, which is "" for the root
+    group. */
+    pub fn set_group_attribute(&mut self, path: &str, name: &str,
+                               value: Hdf5Attribute) -> std::io::Result<()> {
+        valid_name(name)?;
+        let group: &mut Group = self.root.group(path)?;
+        group.attributes.retain(|(n, _)| n != name);
+        group.attributes.push((name.to_string(), value));
+        return Ok(());
+    }
+
+    /* Write the headers of the groups and datasets and the superblock,
+    which makes the file readable. */
+    pub fn finish(mut self) -> std::io::Result<()> {
+        let mut end: u64 = self.end;
+        let root: u64 = self.root.write_headers(&mut self.file, &mut end)?;
+        let mut superblock: Vec<u8> = SIGNATURE.to_vec();
+        superblock.extend([2, 8, 8, 0]);
+        superblock.extend(0_u64.to_le_bytes());
+        superblock.extend(UNDEFINED.to_le_bytes());
+        superblock.extend(end.to_le_bytes());
+        superblock.extend(root.to_le_bytes());
+        let checksum: u32 = lookup3(&superblock);
+        superblock.extend(checksum.to_le_bytes());
+        self.file.seek(std::io::SeekFrom::Start(0))?;
+        self.file.write_all(&superblock)?;
+        return self.file.flush();
+    }
+}
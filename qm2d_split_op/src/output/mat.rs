@@ -0,0 +1,165 @@
+use crate::complex::*;
+use std::io::Write;
+
+/* A writer for MATLAB's Level 5 MAT-file format, which MATLAB's load
+and scipy.io.loadmat read natively, holding numeric arrays of floats,
+doubles and complex numbers. Version 7.3 files are HDF5 files, which
+hdf5.rs writes only in builds with the hdf5 feature; version 5 needs
+nothing beyond std, and holds up to 2 GiB per array.
+
+Arrays are given in row major order, as everywhere else in this crate,
+and stored in MATLAB's column major order, so that psi(i, j) in MATLAB
+is psi[i*N + j] here, with i along y and j along x (counting from 1
+in MATLAB). One dimensional arrays are stored as column vectors.
+
+References:
+
+MathWorks - MAT-File Format, Level 5 MAT-File Format
+https://www.mathworks.com/help/pdf_doc/matlab/matfile_format.pdf
+*/
+pub struct MatWriter {
+    file: std::io::BufWriter<std::fs::File>,
+}
+
+pub enum MatData<'a> {
+    F32(&'a [f32]),
+    F64(&'a [f64]),
+    Complex(&'a [Complex<f32>]),
+}
+
+// Data types of the elements and classes of the arrays.
+const MI_INT8: u32 = 1;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_SINGLE: u32 = 7;
+const MI_DOUBLE: u32 = 9;
+const MI_MATRIX: u32 = 14;
+const MX_DOUBLE_CLASS: u32 = 6;
+const MX_SINGLE_CLASS: u32 = 7;
+const COMPLEX_FLAG: u32 = 0x0800;
+
+/* A data element: its type, its size and its data, padded to a
+multiple of 8 bytes. */
+fn element(data_type: u32, data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = data_type.to_le_bytes().to_vec();
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend(data);
+    out.resize(out.len().next_multiple_of(8), 0);
+    return out;
+}
+
+/* The values in column major order, for an array of the given shape
+in row major order. */
+fn column_major<T: Copy>(shape: &[usize], values: &[T]) -> Vec<T> {
+    if shape.len() < 2 {
+        return values.to_vec();
+    }
+    let mut out: Vec<T> = Vec::with_capacity(values.len());
+    let mut index: Vec<usize> = vec![0; shape.len()];
+    for _ in 0..values.len() {
+        let offset: usize = index.iter().zip(shape.iter())
+            .fold(0, |offset, (k, n)| offset*n + k);
+        out.push(values[offset]);
+        // Count up with the first index changing fastest.
+        for (k, n) in index.iter_mut().zip(shape.iter()) {
+            *k += 1;
+            if *k < *n {
+                break;
+            }
+            *k = 0;
+        }
+    }
+    return out;
+}
+
+fn f32_bytes(values: impl Iterator<Item=f32>) -> Vec<u8> {
+    return values.flat_map(|x| x.to_le_bytes()).collect();
+}
+
+/* Whether name can be a MATLAB variable: a letter followed by up to
+62 letters, digits or underscores. */
+pub fn is_mat_name(name: &str) -> bool {
+    return name.len() <= 63
+        && name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+}
+
+impl MatWriter {
+    /* Create the file and write its header. */
+    pub fn create(filename: &str) -> std::io::Result<MatWriter> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        let mut text: Vec<u8> = format!(
+            "MATLAB 5.0 MAT-file, written by qm2d_split_op {}",
+            env!("CARGO_PKG_VERSION")).into_bytes();
+        text.resize(116, b' ');
+        file.write_all(&text)?;
+        // No subsystem data, version 0x0100, and the endian indicator,
+        // which reads "IM" in a little endian file.
+        file.write_all(&[0; 8])?;
+        file.write_all(&0x0100_u16.to_le_bytes())?;
+        file.write_all(b"IM")?;
+        return Ok(MatWriter {file});
+    }
+
+    /* Add the array as the variable name, of the given shape. */
+    pub fn add(&mut self, name: &str, shape: &[usize], data: MatData
+               ) -> std::io::Result<()> {
+        let len: usize = match &data {
+            MatData::F32(v) => v.len(),
+            MatData::F64(v) => v.len(),
+            MatData::Complex(v) => v.len(),
+        };
+        if shape.iter().product::<usize>() != len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} elements do not have the shape {:?}", len, shape)));
+        }
+        if !is_mat_name(name) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a MATLAB variable name", name)));
+        }
+        let (class, flags, parts): (u32, u32, Vec<Vec<u8>>) = match data {
+            MatData::F32(v) => (MX_SINGLE_CLASS, 0, vec![element(
+                MI_SINGLE, &f32_bytes(column_major(shape, v).into_iter()))]),
+            MatData::F64(v) => (MX_DOUBLE_CLASS, 0, vec![element(
+                MI_DOUBLE, &column_major(shape, v).iter()
+                    .flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>())]),
+            MatData::Complex(v) => {
+                let values: Vec<Complex<f32>> = column_major(shape, v);
+                (MX_SINGLE_CLASS, COMPLEX_FLAG, vec![
+                    element(MI_SINGLE, &f32_bytes(values.iter().map(|z| z.real))),
+                    element(MI_SINGLE, &f32_bytes(values.iter().map(|z| z.imag)))])
+            },
+        };
+        // MATLAB arrays have at least two dimensions.
+        let mut dims: Vec<usize> = shape.to_vec();
+        while dims.len() < 2 {
+            dims.push(1);
+        }
+        if dims.iter().any(|n| *n > i32::MAX as usize) {
+            return Err(std::io::Error::other("the array is too large"));
+        }
+        let mut contents: Vec<u8> = element(
+            MI_UINT32, &[(class | flags).to_le_bytes(), [0; 4]].concat());
+        contents.extend(element(MI_INT32, &dims.iter()
+                                .flat_map(|n| (*n as i32).to_le_bytes())
+                                .collect::<Vec<u8>>()));
+        contents.extend(element(MI_INT8, name.as_bytes()));
+        for part in parts {
+            contents.extend(part);
+        }
+        if contents.len() > i32::MAX as usize {
+            return Err(std::io::Error::other(
+                "arrays over 2 GiB are not supported"));
+        }
+        self.file.write_all(&MI_MATRIX.to_le_bytes())?;
+        self.file.write_all(&(contents.len() as u32).to_le_bytes())?;
+        self.file.write_all(&contents)?;
+        return Ok(());
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        return self.file.flush();
+    }
+}
@@ -0,0 +1,14 @@
+/* Writers for simulation frames in formats other than the BMP files
+saved every few steps, and the thread that writes output files. */
+pub mod colormap;
+pub mod png;
+pub mod domain_coloring;
+pub mod video;
+pub mod overlay;
+pub mod surface;
+pub mod npy;
+pub mod mat;
+pub mod vtk;
+pub mod writer;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
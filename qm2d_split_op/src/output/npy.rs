@@ -0,0 +1,160 @@
+use crate::complex::*;
+use crate::output::png::crc32;
+use std::io::Write;
+
+/* Writers for the .npy format of a single NumPy array and the .npz
+format of several, so that np.load reads psi, the potential and the
+observables of a run without any parsing code. An .npz file is a zip
+archive of .npy files, which are stored here without compression.
+
+References:
+
+NumPy - The .npy format
+https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+
+PKWARE - .ZIP File Format Specification
+https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+*/
+pub enum NpyData<'a> {
+    F32(&'a [f32]),
+    F64(&'a [f64]),
+    I64(&'a [i64]),
+    Complex(&'a [Complex<f32>]),
+    Complex128(&'a [Complex<f64>]),
+}
+
+impl NpyData<'_> {
+    fn len(&self) -> usize {
+        return match self {
+            NpyData::F32(v) => v.len(),
+            NpyData::F64(v) => v.len(),
+            NpyData::I64(v) => v.len(),
+            NpyData::Complex(v) => v.len(),
+            NpyData::Complex128(v) => v.len(),
+        };
+    }
+}
+
+/* The array data, in row major order with the given shape, as the
+contents of an .npy file. */
+pub fn npy_bytes(shape: &[usize], data: NpyData) -> std::io::Result<Vec<u8>> {
+    if shape.iter().product::<usize>() != data.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} elements do not have the shape {:?}", data.len(),
+                    shape)));
+    }
+    let (descr, values): (&str, Vec<u8>) = match data {
+        NpyData::F32(v) => ("<f4", v.iter().flat_map(|x| x.to_le_bytes())
+                            .collect()),
+        NpyData::F64(v) => ("<f8", v.iter().flat_map(|x| x.to_le_bytes())
+                            .collect()),
+        NpyData::I64(v) => ("<i8", v.iter().flat_map(|x| x.to_le_bytes())
+                            .collect()),
+        NpyData::Complex(v) => ("<c8", v.iter()
+                                .flat_map(|z| z.real.to_le_bytes().into_iter()
+                                          .chain(z.imag.to_le_bytes()))
+                                .collect()),
+        NpyData::Complex128(v) => ("<c16", v.iter()
+                                   .flat_map(|z| z.real.to_le_bytes()
+                                             .into_iter()
+                                             .chain(z.imag.to_le_bytes()))
+                                   .collect()),
+    };
+    let dims: Vec<String> = shape.iter().map(|n| n.to_string()).collect();
+    let shape: String = if dims.len() == 1 {
+        format!("({},)", dims[0])
+    } else {
+        format!("({})", dims.join(", "))
+    };
+    let mut header: String = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape);
+    // The magic string, version and header length take 10 bytes, and
+    // the data starts at a multiple of 64 bytes after a newline.
+    while !(10 + header.len() + 1).is_multiple_of(64) {
+        header.push(' ');
+    }
+    header.push('\n');
+    let mut out: Vec<u8> = b"\x93NUMPY\x01\x00".to_vec();
+    out.extend((header.len() as u16).to_le_bytes());
+    out.extend(header.as_bytes());
+    out.extend(values);
+    return Ok(out);
+}
+
+pub fn save_npy(filename: &str, shape: &[usize], data: NpyData
+                ) -> std::io::Result<()> {
+    return std::fs::write(filename, npy_bytes(shape, data)?);
+}
+
+/* An .npz file written one array at a time, each as soon as it is
+added, with the directory of the archive written by finish. */
+pub struct NpzWriter {
+    file: std::io::BufWriter<std::fs::File>,
+    offset: usize,
+    // The name, CRC-32, size and offset of each entry.
+    entries: Vec<(String, u32, usize, usize)>,
+}
+
+impl NpzWriter {
+    pub fn create(filename: &str) -> std::io::Result<NpzWriter> {
+        let file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        return Ok(NpzWriter {file, offset: 0, entries: Vec::new()});
+    }
+
+    /* Add the array as name, which np.load gives back as the key name
+    (the file in the archive is name.npy). */
+    pub fn add(&mut self, name: &str, shape: &[usize], data: NpyData
+               ) -> std::io::Result<()> {
+        let contents: Vec<u8> = npy_bytes(shape, data)?;
+        let filename: String = format!("{}.npy", name);
+        if self.offset + contents.len() > u32::MAX as usize
+            || self.entries.len() >= u16::MAX as usize {
+            return Err(std::io::Error::other(
+                "npz files over 4 GiB or of over 65535 arrays are not supported"));
+        }
+        let crc: u32 = crc32(&contents);
+        let mut header: Vec<u8> = 0x04034b50_u32.to_le_bytes().to_vec();
+        // Version 2.0, no flags, stored, dated 1980-01-01 00:00.
+        header.extend([20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+        header.extend(crc.to_le_bytes());
+        header.extend((contents.len() as u32).to_le_bytes());
+        header.extend((contents.len() as u32).to_le_bytes());
+        header.extend((filename.len() as u16).to_le_bytes());
+        header.extend(0_u16.to_le_bytes());
+        header.extend(filename.as_bytes());
+        self.file.write_all(&header)?;
+        self.file.write_all(&contents)?;
+        self.entries.push((filename, crc, contents.len(), self.offset));
+        self.offset += header.len() + contents.len();
+        return Ok(());
+    }
+
+    /* Write the central directory of the archive. */
+    pub fn finish(mut self) -> std::io::Result<()> {
+        let mut directory: Vec<u8> = Vec::new();
+        for (filename, crc, size, offset) in self.entries.iter() {
+            directory.extend(0x02014b50_u32.to_le_bytes());
+            directory.extend([20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+            directory.extend(crc.to_le_bytes());
+            directory.extend((*size as u32).to_le_bytes());
+            directory.extend((*size as u32).to_le_bytes());
+            directory.extend((filename.len() as u16).to_le_bytes());
+            // No extra field or comment, disk 0 and no attributes.
+            directory.extend([0_u8; 12]);
+            directory.extend((*offset as u32).to_le_bytes());
+            directory.extend(filename.as_bytes());
+        }
+        let mut end: Vec<u8> = 0x06054b50_u32.to_le_bytes().to_vec();
+        end.extend([0, 0, 0, 0]);
+        end.extend((self.entries.len() as u16).to_le_bytes());
+        end.extend((self.entries.len() as u16).to_le_bytes());
+        end.extend((directory.len() as u32).to_le_bytes());
+        end.extend((self.offset as u32).to_le_bytes());
+        end.extend(0_u16.to_le_bytes());
+        self.file.write_all(&directory)?;
+        self.file.write_all(&end)?;
+        return self.file.flush();
+    }
+}
@@ -0,0 +1,69 @@
+use crate::constants::*;
+use crate::complex::*;
+
+/* How the real part of the potential is drawn over a frame, so that
+the walls and wells the wave function moves through can be seen. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PotentialOverlay {
+    None,
+    Contours {levels: usize}, // white lines between equally spaced levels
+    Shade {opacity: f32}, // white with opacity times the scaled potential
+}
+
+impl PotentialOverlay {
+    pub fn parse(name: &str, levels: usize, opacity: f32
+                 ) -> Result<PotentialOverlay, String> {
+        return match name {
+            "none" => Ok(PotentialOverlay::None),
+            "contours" => Ok(PotentialOverlay::Contours {
+                levels: usize::max(1, levels)}),
+            "shade" => Ok(PotentialOverlay::Shade {opacity}),
+            _ => Err(format!("unknown potential overlay {}, expected none, \
+                              contours or shade", name)),
+        };
+    }
+
+    /* Draw the potential over an 8 bit RGB frame of the grid, with rows
+    from the top of the image down and y increasing upwards. The
+    potential is scaled so that its minimum is 0 and its maximum is 1,
+    and a flat potential is not drawn. */
+    pub fn apply(&self, rgb: &mut [u8], potential: &[Complex<f32>]) {
+        let (mut v_min, mut v_max): (f32, f32) = (f32::INFINITY,
+                                                  f32::NEG_INFINITY);
+        for v in potential.iter() {
+            v_min = f32::min(v_min, v.real);
+            v_max = f32::max(v_max, v.real);
+        }
+        if *self == PotentialOverlay::None || v_max <= v_min {
+            return;
+        }
+        let scaled = |i: usize, j: usize| -> f32 {
+            (potential[i*N + j].real - v_min)/(v_max - v_min)
+        };
+        for row in 0..N {
+            let i: usize = N - 1 - row;
+            for j in 0..N {
+                let alpha: f32 = match *self {
+                    PotentialOverlay::Contours {levels} => {
+                        // On a line if the level changes towards the
+                        // neighbour to the right or below.
+                        let level = |v: f32| f32::floor(v*(levels as f32))
+                            .min((levels - 1) as f32);
+                        let v: f32 = level(scaled(i, j));
+                        let right: f32 = level(scaled(i, (j + 1) % N));
+                        let below: f32 = level(scaled((i + N - 1) % N, j));
+                        if v != right || v != below {1.0} else {0.0}
+                    },
+                    PotentialOverlay::Shade {opacity}
+                        => f32::clamp(opacity*scaled(i, j), 0.0, 1.0),
+                    PotentialOverlay::None => 0.0,
+                };
+                for c in 0..3 {
+                    let k: usize = 3*(row*N + j) + c;
+                    rgb[k] = f32::round((rgb[k] as f32)*(1.0 - alpha)
+                                        + 255.0*alpha) as u8;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::current::*;
+use crate::spectral::*;
+use crate::streamlines::*;
+use crate::zlib::*;
+use crate::output::colormap::*;
+use crate::output::domain_coloring::*;
+use crate::output::overlay::*;
+use crate::output::surface::*;
+
+/* CRC-32 of data, as used for the PNG chunks.
+
+Reference:
+
+Wikipedia - Cyclic redundancy check
+https://en.wikipedia.org/wiki/Cyclic_redundancy_check
+*/
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffffffff;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {(crc >> 1) ^ 0xedb88320} else {crc >> 1};
+        }
+    }
+    return !crc;
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start: usize = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc: u32 = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+/* Encode an 8 bit RGB image, with rows from the top of the image to
+the bottom, as a PNG file. Each row is stored with the Sub filter,
+which turns smooth gradients into runs of small numbers that compress
+well.
+
+Reference:
+
+Portable Network Graphics (PNG) Specification (Third Edition)
+https://www.w3.org/TR/png-3/
+*/
+pub fn encode_png_rgb(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut raw = std::vec::Vec::<u8>::with_capacity((3*width + 1)*height);
+    for i in 0..height {
+        let row: &[u8] = &rgb[3*width*i..3*width*(i + 1)];
+        raw.push(1); // Sub filter
+        for k in 0..3*width {
+            let left: u8 = if k >= 3 {row[k - 3]} else {0};
+            raw.push(row[k].wrapping_sub(left));
+        }
+    }
+    let mut header = std::vec::Vec::<u8>::with_capacity(13);
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, default compression and filters,
+    // not interlaced
+    header.extend([8, 2, 0, 0, 0]);
+    let mut out: Vec<u8> = vec![0x89, b'P', b'N', b'G', b'\r', b'\n',
+                                0x1a, b'\n'];
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &zlib_compress(&raw));
+    write_chunk(&mut out, b"IEND", &[]);
+    return out;
+}
+
+/* |psi|^2 drawn with the colormap as an 8 bit RGB image, with rows from
+the top of the image to the bottom and y increasing upwards, as in the
+BMP frames. The density is multiplied by scale before the colormap is
+applied, or divided by its maximum if scale is zero. */
+pub fn density_rgb(psi: &[Complex<f32>], colormap: &Colormap, scale: f32
+                   ) -> Vec<u8> {
+    let scale: f32 = if scale > 0.0 {
+        scale
+    } else {
+        let peak: f32 = psi.iter().map(|z| z.length_squared())
+            .fold(0.0, f32::max);
+        if peak > 0.0 {1.0/peak} else {1.0}
+    };
+    let mut rgb = std::vec::Vec::<u8>::with_capacity(3*N*N);
+    for i in (0..N).rev() {
+        for j in 0..N {
+            rgb.extend(colormap.color(scale*psi[i*N + j].length_squared()));
+        }
+    }
+    return rgb;
+}
+
+/* How frames of the wave function are drawn: its density with a
+colormap, or its phase as the hue with domain coloring. */
+#[derive(Clone, PartialEq, Debug)]
+pub enum FrameStyle {
+    Density(Colormap),
+    Phase,
+}
+
+impl FrameStyle {
+    /* The 8 bit RGB image of psi, as from density_rgb or phase_rgb. */
+    pub fn render(&self, psi: &[Complex<f32>], scale: f32) -> Vec<u8> {
+        return match self {
+            FrameStyle::Density(colormap) => density_rgb(psi, colormap,
+                                                         scale),
+            FrameStyle::Phase => phase_rgb(psi, scale),
+        };
+    }
+}
+
+/* Whether frames show the wave function in position space, or in
+momentum space with zero momentum at the centre (see momentum_space),
+where Bragg scattering and Bloch oscillations show up as peaks moving
+between discrete momenta. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameSpace {
+    Position,
+    Momentum,
+}
+
+impl FrameSpace {
+    pub fn parse(name: &str) -> Result<FrameSpace, String> {
+        return match name {
+            "position" => Ok(FrameSpace::Position),
+            "momentum" => Ok(FrameSpace::Momentum),
+            _ => Err(format!("unknown frame space {}, expected position \
+                              or momentum", name)),
+        };
+    }
+
+    /* The 8 bit RGB image of psi in this space, drawn with the style. */
+    pub fn render(&self, style: &FrameStyle, psi: &[Complex<f32>],
+                  scale: f32) -> Vec<u8> {
+        return match self {
+            FrameSpace::Position => style.render(psi, scale),
+            FrameSpace::Momentum => style.render(&momentum_space(psi),
+                                                 scale),
+        };
+    }
+}
+
+/* Save |psi|^2 as a PNG image, drawn as in density_rgb. */
+pub fn save_density_png(filename: &str, psi: &[Complex<f32>],
+                        colormap: &Colormap, scale: f32
+                        ) -> std::io::Result<()> {
+    return std::fs::write(filename, encode_png_rgb(
+        N, N, &density_rgb(psi, colormap, scale)));
+}
+
+/* Writes a PNG frame of psi every few steps, named with the prefix
+and the frame number padded with zeros to five digits, so that the
+files sort in order and can be read by ffmpeg with the pattern
+prefix%05d.png. */
+pub struct PngFrames {
+    pub every: usize, // steps between frames
+    pub style: FrameStyle,
+    pub space: FrameSpace,
+    pub prefix: String,
+    pub scale: f32, // multiplies |psi|^2, or 0 to divide by its maximum
+    pub overlay: PotentialOverlay, // drawn over frames in position space
+    pub surface: Option<Camera>, // draw the density as a surface if set
+    // Seed spacing of the current streamlines drawn over frames in
+    // position space, or 0 for none, and where they stop.
+    pub streamlines: usize,
+    pub streamline_min_current: f32,
+}
+
+impl PngFrames {
+    /* The 8 bit RGB image of psi in the given space, with the potential
+    drawn over it in position space. The video and the live viewer use
+    this as well, so that they show the same frames as the files. */
+    pub fn render(&self, space: FrameSpace, psi: &[Complex<f32>],
+                  potential: &[Complex<f32>]) -> Vec<u8> {
+        return self.render_view(space, self.surface.as_ref(), psi,
+                                potential);
+    }
+
+    /* As render, but seen as a surface from the given camera, if any,
+    rather than the one of these frames. */
+    pub fn render_view(&self, space: FrameSpace, surface: Option<&Camera>,
+                       psi: &[Complex<f32>], potential: &[Complex<f32>]
+                       ) -> Vec<u8> {
+        let momentum: Vec<Complex<f32>>;
+        let psi: &[Complex<f32>] = match space {
+            FrameSpace::Position => psi,
+            FrameSpace::Momentum => {
+                momentum = momentum_space(psi);
+                &momentum
+            },
+        };
+        let mut rgb: Vec<u8> = self.style.render(psi, self.scale);
+        if space == FrameSpace::Position {
+            self.overlay.apply(&mut rgb, potential);
+            if self.streamlines > 0 {
+                let (jx, jy) = current_density(psi);
+                draw_streamlines(&mut rgb, &trace_streamlines(
+                    &jx, &jy, self.streamlines, self.streamline_min_current));
+            }
+        }
+        return match surface {
+            Some(camera) => surface_rgb(psi, &rgb, self.scale, camera, N, N),
+            None => rgb,
+        };
+    }
+
+    pub fn filename(&self, frame: usize) -> String {
+        return format!("{}{:05}.png", self.prefix, frame);
+    }
+
+    /* Whether a frame is saved at the given step. */
+    pub fn is_due(&self, step: usize) -> bool {
+        return self.every > 0 && step.is_multiple_of(self.every);
+    }
+
+    /* Save a frame if step is a multiple of every, returning the name of
+    the file written. */
+    pub fn record(&self, step: usize, psi: &[Complex<f32>],
+                  potential: &[Complex<f32>]
+                  ) -> std::io::Result<Option<String>> {
+        if !self.is_due(step) {
+            return Ok(None);
+        }
+        let filename: String = self.filename(step/self.every);
+        std::fs::write(&filename, encode_png_rgb(
+            N, N, &self.render(self.space, psi, potential)))?;
+        return Ok(Some(filename));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::constants::*;
+use crate::complex::*;
+
+/* A camera looking at the centre of the grid from a distance, at an
+azimuth measured from the x axis and an elevation above the plane of the
+grid, both in radians. The grid spans [-0.5, 0.5] in x and y. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Camera {
+    pub azimuth: f32,
+    pub elevation: f32,
+    pub distance: f32,
+}
+
+impl Default for Camera {
+    /* Looking from in front of the bottom edge of the flat frames, so
+    that the surface shows the same way round as they do. */
+    fn default() -> Camera {
+        return Camera {azimuth: -0.5*std::f32::consts::PI, elevation: 0.6,
+                       distance: 1.8};
+    }
+}
+
+impl Camera {
+    /* Orbit by the given angles, keeping the camera above the grid. */
+    pub fn orbit(&mut self, azimuth: f32, elevation: f32) {
+        self.azimuth += azimuth;
+        self.elevation = f32::clamp(self.elevation + elevation, 0.05, 1.55);
+    }
+
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = f32::clamp(self.distance*factor, 0.6, 6.0);
+    }
+}
+
+// Points of the grid along each side that the surface is made of.
+const MESH: usize = if N < 256 {N} else {256};
+// Height of the surface where the scaled density is 1.
+const HEIGHT: f32 = 0.35;
+const BACKGROUND: [u8; 3] = [16, 16, 20];
+
+type Vector = [f32; 3];
+
+fn sub(a: Vector, b: Vector) -> Vector {
+    return [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+}
+
+fn dot(a: Vector, b: Vector) -> f32 {
+    return a[0]*b[0] + a[1]*b[1] + a[2]*b[2];
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    return [a[1]*b[2] - a[2]*b[1], a[2]*b[0] - a[0]*b[2],
+            a[0]*b[1] - a[1]*b[0]];
+}
+
+fn normalize(a: Vector) -> Vector {
+    let length: f32 = f32::sqrt(dot(a, a));
+    return if length > 0.0 {[a[0]/length, a[1]/length, a[2]/length]}
+        else {a};
+}
+
+/* The 8 bit RGB image, of the given size and with rows from the top
+down, of |psi|^2 drawn as a surface whose height is the density and
+whose color is taken from texture, an image of the grid in the layout of
+the frames (rows from the top down with y increasing upwards). The
+density is multiplied by scale, or divided by its maximum if scale is
+zero, and the surface is lit from one side so that its shape shows.
+It is drawn on a coarser mesh than the grid, in perspective, by filling
+its triangles with a depth buffer. */
+pub fn surface_rgb(psi: &[Complex<f32>], texture: &[u8], scale: f32,
+                   camera: &Camera, width: usize, height: usize) -> Vec<u8> {
+    let scale: f32 = if scale > 0.0 {
+        scale
+    } else {
+        let peak: f32 = psi.iter().map(|z| z.length_squared())
+            .fold(0.0, f32::max);
+        if peak > 0.0 {1.0/peak} else {1.0}
+    };
+    let stride: usize = N/MESH;
+    let target: Vector = [0.0, 0.0, 0.3*HEIGHT];
+    let eye: Vector = [
+        target[0] + camera.distance*f32::cos(camera.elevation)
+            *f32::cos(camera.azimuth),
+        target[1] + camera.distance*f32::cos(camera.elevation)
+            *f32::sin(camera.azimuth),
+        target[2] + camera.distance*f32::sin(camera.elevation)];
+    let forward: Vector = normalize(sub(target, eye));
+    let right: Vector = normalize(cross(forward, [0.0, 0.0, 1.0]));
+    let up: Vector = cross(right, forward);
+    let light: Vector = normalize([-0.3, -0.5, 0.8]);
+    // Focal length for a vertical field of view of 45 degrees.
+    let focal: f32 = 0.5*(height as f32)/f32::tan(std::f32::consts::PI/8.0);
+
+    // The points of the mesh in the world and on the screen, with the
+    // screen depth, or None behind the camera.
+    let mut world = std::vec::Vec::<Vector>::with_capacity(MESH*MESH);
+    let mut screen = std::vec::Vec::<Option<Vector>>::with_capacity(
+        MESH*MESH);
+    for a in 0..MESH {
+        for b in 0..MESH {
+            let (i, j) = (a*stride, b*stride);
+            let z: f32 = HEIGHT*f32::min(
+                1.0, scale*psi[i*N + j].length_squared());
+            let p: Vector = [(j as f32)/(N as f32) - 0.5,
+                             (i as f32)/(N as f32) - 0.5, z];
+            let d: Vector = sub(p, eye);
+            let depth: f32 = dot(d, forward);
+            world.push(p);
+            screen.push(if depth > 1e-3 {
+                Some([0.5*(width as f32) + focal*dot(d, right)/depth,
+                      0.5*(height as f32) - focal*dot(d, up)/depth, depth])
+            } else {
+                None
+            });
+        }
+    }
+
+    let mut rgb: Vec<u8> = BACKGROUND.iter().copied().cycle()
+        .take(3*width*height).collect();
+    let mut depths: Vec<f32> = vec![f32::INFINITY; width*height];
+    for a in 0..MESH - 1 {
+        for b in 0..MESH - 1 {
+            let (i, j) = (a*stride, b*stride);
+            let t: usize = 3*((N - 1 - i)*N + j);
+            let color: [f32; 3] = [texture[t] as f32, texture[t + 1] as f32,
+                                   texture[t + 2] as f32];
+            let corners: [usize; 4] = [a*MESH + b, a*MESH + b + 1,
+                                       (a + 1)*MESH + b + 1, (a + 1)*MESH + b];
+            for triangle in [[0, 1, 2], [0, 2, 3]] {
+                let k: [usize; 3] = triangle.map(|c| corners[c]);
+                let normal: Vector = normalize(cross(
+                    sub(world[k[1]], world[k[0]]),
+                    sub(world[k[2]], world[k[0]])));
+                let shade: f32 = 0.35 + 0.65*f32::abs(dot(normal, light));
+                let c: [u8; 3] = color.map(|v| f32::min(255.0, v*shade) as u8);
+                if let (Some(p0), Some(p1), Some(p2))
+                    = (screen[k[0]], screen[k[1]], screen[k[2]]) {
+                    fill_triangle(&mut rgb, &mut depths, width, height,
+                                  [p0, p1, p2], c);
+                }
+            }
+        }
+    }
+    return rgb;
+}
+
+/* Fill a triangle given by its corners on the screen with their depths,
+where it is nearer than what has been drawn there. */
+fn fill_triangle(rgb: &mut [u8], depths: &mut [f32], width: usize,
+                 height: usize, p: [Vector; 3], color: [u8; 3]) {
+    let edge = |a: Vector, b: Vector, x: f32, y: f32| -> f32 {
+        (b[0] - a[0])*(y - a[1]) - (b[1] - a[1])*(x - a[0])
+    };
+    let area: f32 = edge(p[0], p[1], p[2][0], p[2][1]);
+    if f32::abs(area) < 1e-6 {
+        return;
+    }
+    let x_min: f32 = f32::max(0.0, f32::floor(f32::min(p[0][0], f32::min(
+        p[1][0], p[2][0]))));
+    let x_max: f32 = f32::min((width - 1) as f32, f32::ceil(f32::max(
+        p[0][0], f32::max(p[1][0], p[2][0]))));
+    let y_min: f32 = f32::max(0.0, f32::floor(f32::min(p[0][1], f32::min(
+        p[1][1], p[2][1]))));
+    let y_max: f32 = f32::min((height - 1) as f32, f32::ceil(f32::max(
+        p[0][1], f32::max(p[1][1], p[2][1]))));
+    if x_min > x_max || y_min > y_max {
+        return;
+    }
+    for y in (y_min as usize)..=(y_max as usize) {
+        for x in (x_min as usize)..=(x_max as usize) {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let w0: f32 = edge(p[1], p[2], px, py)/area;
+            let w1: f32 = edge(p[2], p[0], px, py)/area;
+            let w2: f32 = 1.0 - w0 - w1;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+            let depth: f32 = w0*p[0][2] + w1*p[1][2] + w2*p[2][2];
+            if depth < depths[y*width + x] {
+                depths[y*width + x] = depth;
+                rgb[3*(y*width + x)..3*(y*width + x) + 3]
+                    .copy_from_slice(&color);
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+/* Encodes frames into a video by piping them as raw RGB to an ffmpeg
+process, which chooses the format from the extension of the file name:
+H.264 for .mp4, or a GIF with a palette made from the first frames for
+.gif. ffmpeg has to be installed and on the PATH.
+
+Reference:
+
+FFmpeg documentation - ffmpeg
+https://ffmpeg.org/ffmpeg.html
+*/
+pub struct VideoRecorder {
+    child: std::process::Child,
+    width: usize,
+    height: usize,
+}
+
+impl VideoRecorder {
+    pub fn start(filename: &str, width: usize, height: usize,
+                 framerate: f32) -> std::io::Result<VideoRecorder> {
+        let size: String = format!("{}x{}", width, height);
+        let rate: String = format!("{}", framerate);
+        let mut command = std::process::Command::new("ffmpeg");
+        command.args(["-loglevel", "error", "-y",
+                      "-f", "rawvideo", "-pix_fmt", "rgb24",
+                      "-s", &size, "-r", &rate, "-i", "-"]);
+        if filename.to_lowercase().ends_with(".gif") {
+            command.args(["-vf", "split[a][b];[a]palettegen[p];\
+                                  [b][p]paletteuse"]);
+        } else {
+            // Most players only decode H.264 with 4:2:0 chroma.
+            command.args(["-pix_fmt", "yuv420p"]);
+        }
+        command.arg(filename).stdin(std::process::Stdio::piped());
+        let child = command.spawn().map_err(|e| std::io::Error::new(
+            e.kind(), format!("could not start ffmpeg: {}", e)))?;
+        return Ok(VideoRecorder {child, width, height});
+    }
+
+    /* Add an 8 bit RGB frame, with rows from the top of the image to
+    the bottom. */
+    pub fn write_frame(&mut self, rgb: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+        if rgb.len() != 3*self.width*self.height {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("frame of {} bytes, expected {}", rgb.len(),
+                        3*self.width*self.height)));
+        }
+        return match self.child.stdin.as_mut() {
+            Some(stdin) => stdin.write_all(rgb),
+            None => Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe,
+                                            "ffmpeg has already exited")),
+        };
+    }
+
+    /* Close the input of ffmpeg and wait for it to finish writing the
+    video. */
+    pub fn finish(mut self) -> std::io::Result<()> {
+        drop(self.child.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(std::io::Error::other(
+                format!("ffmpeg failed with {}", status)));
+        }
+        return Ok(());
+    }
+}
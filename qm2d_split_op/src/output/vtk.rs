@@ -0,0 +1,86 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::field::*;
+use crate::phase_unwrap::*;
+use std::io::Write;
+
+/* Fields on the grid saved as VTK image data (.vti files), which
+ParaView and VisIt open as a series when the files are numbered. The
+XML header lists the arrays and the data of each follows it in binary,
+as a 4 byte length and then little endian 4 byte floats, with x varying
+fastest as in the rows of psi. Vector fields are stored with three
+components, the last zero, as ParaView expects.
+
+Reference:
+
+Kitware - VTK File Formats
+https://docs.vtk.org/en/latest/design_documents/VTKFileFormats.html
+*/
+pub fn save_vti(filename: &str, time: f64, scalars: &[(&str, &[f32])],
+                vectors: &[(&str, &[f32], &[f32])]) -> std::io::Result<()> {
+    let mut arrays: Vec<(&str, usize, Vec<f32>)> = Vec::new();
+    for &(name, values) in scalars {
+        arrays.push((name, 1, values.to_vec()));
+    }
+    for &(name, vx, vy) in vectors {
+        arrays.push((name, 3, vx.iter().zip(vy.iter())
+                     .flat_map(|(&x, &y)| [x, y, 0.0]).collect()));
+    }
+    if arrays.iter().any(|(_, c, v)| v.len() != c*N*N) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                       "fields must have N*N points"));
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+    let h: f64 = 1.0/(N as f64);
+    writeln!(out, "<?xml version=\"1.0\"?>")?;
+    writeln!(out, "<VTKFile type=\"ImageData\" version=\"1.0\" \
+                   byte_order=\"LittleEndian\" header_type=\"UInt32\">")?;
+    writeln!(out, "  <ImageData WholeExtent=\"0 {} 0 {} 0 0\" \
+                   Origin=\"0 0 0\" Spacing=\"{} {} {}\">", N - 1, N - 1,
+             h, h, h)?;
+    writeln!(out, "    <FieldData>")?;
+    writeln!(out, "      <DataArray type=\"Float64\" Name=\"TimeValue\" \
+                   NumberOfTuples=\"1\" format=\"ascii\">{}</DataArray>",
+             time)?;
+    writeln!(out, "    </FieldData>")?;
+    writeln!(out, "    <Piece Extent=\"0 {} 0 {} 0 0\">", N - 1, N - 1)?;
+    writeln!(out, "      <PointData>")?;
+    let mut offset: usize = 0;
+    for (name, components, values) in arrays.iter() {
+        writeln!(out, "        <DataArray type=\"Float32\" Name=\"{}\" \
+                       NumberOfComponents=\"{}\" format=\"appended\" \
+                       offset=\"{}\"/>", name, components, offset)?;
+        offset += 4 + 4*values.len();
+    }
+    writeln!(out, "      </PointData>")?;
+    writeln!(out, "    </Piece>")?;
+    writeln!(out, "  </ImageData>")?;
+    write!(out, "  <AppendedData encoding=\"raw\">\n   _")?;
+    for (_, _, values) in arrays.iter() {
+        out.write_all(&((4*values.len()) as u32).to_le_bytes())?;
+        for v in values.iter() {
+            out.write_all(&v.to_le_bytes())?;
+        }
+    }
+    writeln!(out, "\n  </AppendedData>")?;
+    writeln!(out, "</VTKFile>")?;
+    return out.flush();
+}
+
+/* Save the density and phase of psi, its phase unwrapped (see
+src/phase_unwrap.rs), the real part of the potential and the
+probability current jx, jy as a .vti file. */
+pub fn save_fields_vti(filename: &str, time: f64, psi: &[Complex<f32>],
+                       potential: &[Complex<f32>], jx: &Field2D<f32>,
+                       jy: &Field2D<f32>) -> std::io::Result<()> {
+    let density: Vec<f32> = psi.iter().map(|z| z.length_squared()).collect();
+    let phase: Vec<f32> = psi.iter().map(|z| f32::atan2(z.imag, z.real))
+        .collect();
+    let unwrapped: Vec<f32> = unwrap_psi_phase(psi, N, N)
+        .iter().map(|p| *p as f32).collect();
+    let v: Vec<f32> = potential.iter().map(|z| z.real).collect();
+    return save_vti(filename, time,
+                    &[("density", &density), ("phase", &phase),
+                      ("phase_unwrapped", &unwrapped), ("potential", &v)],
+                    &[("current", jx.as_slice(), jy.as_slice())]);
+}
@@ -0,0 +1,176 @@
+use crate::timeseries::*;
+use crate::log::*;
+
+type Job = Box<dyn FnOnce() -> std::io::Result<()> + Send>;
+
+/* Print the error of a job on standard error, apart from the progress
+on standard output, and log it. */
+fn report_error(e: &std::io::Error) {
+    eprintln!("{}", e);
+    log_event(LogLevel::Error, "output", "write failed",
+              &[("error", e.to_string().into())]);
+}
+
+/* Writes output files on a thread of its own, so that a slow disk does
+not hold up the steps of the simulation. Jobs, each encoding and
+writing a file from data copied out of the simulation, run in the order
+they are submitted, and errors are printed to standard error as they
+happen. At most capacity jobs wait at a time: submitting another blocks
+until one is done, which bounds the memory taken by copies when the disk
+cannot keep up. With a capacity of 0 jobs run on the calling thread
+instead. */
+pub struct OutputWriter {
+    jobs: std::sync::Mutex<Option<std::sync::mpsc::SyncSender<Job>>>,
+    handle: std::sync::Mutex<Option<std::thread::JoinHandle<()>>>,
+    // Jobs submitted but not yet done.
+    pending: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl OutputWriter {
+    pub fn new(capacity: usize) -> OutputWriter {
+        let pending = std::sync::Arc::new(
+            std::sync::atomic::AtomicUsize::new(0));
+        if capacity == 0 {
+            return OutputWriter {jobs: std::sync::Mutex::new(None),
+                                 handle: std::sync::Mutex::new(None),
+                                 pending};
+        }
+        let (jobs, receiver) = std::sync::mpsc::sync_channel::<Job>(capacity);
+        let done = pending.clone();
+        let handle = std::thread::spawn(move || {
+            for job in receiver {
+                let span: LogSpan = LogSpan::enter(
+                    LogLevel::Debug, "output", "write", &[]);
+                if let Err(e) = job() {
+                    report_error(&e);
+                }
+                drop(span);
+                done.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+            }
+        });
+        return OutputWriter {jobs: std::sync::Mutex::new(Some(jobs)),
+                             handle: std::sync::Mutex::new(Some(handle)),
+                             pending};
+    }
+
+    pub fn submit(&self, job: impl FnOnce() -> std::io::Result<()>
+                  + Send + 'static) {
+        // The sender is cloned so that the lock is not held while
+        // waiting for room in the queue.
+        let jobs: Option<std::sync::mpsc::SyncSender<Job>> = match
+            self.jobs.lock() {
+            Ok(jobs) => jobs.clone(),
+            Err(_) => None,
+        };
+        match jobs {
+            Some(jobs) => {
+                let backlog: usize = self.pending.fetch_add(
+                    1, std::sync::atomic::Ordering::Relaxed);
+                // Waiting for room in a full queue is timed, as the steps
+                // are held up by the disk then. Sending only fails if the
+                // thread has panicked, in which case the job is run here
+                // instead.
+                let job: Job = match jobs.try_send(Box::new(job)) {
+                    Ok(()) => return,
+                    Err(std::sync::mpsc::TrySendError::Full(job)) => {
+                        let _span: LogSpan = LogSpan::enter(
+                            LogLevel::Debug, "output", "queue_full",
+                            &[("backlog", backlog.into())]);
+                        match jobs.send(job) {
+                            Ok(()) => return,
+                            Err(std::sync::mpsc::SendError(job)) => job,
+                        }
+                    },
+                    Err(std::sync::mpsc::TrySendError::Disconnected(job))
+                        => job,
+                };
+                self.pending.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+                if let Err(e) = job() {
+                    report_error(&e);
+                }
+            },
+            None => {
+                if let Err(e) = job() {
+                    report_error(&e);
+                }
+            },
+        }
+    }
+
+    /* The number of jobs submitted that are not done yet. */
+    pub fn backlog(&self) -> usize {
+        return self.pending.load(std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /* Write data to a file. */
+    pub fn write_file(&self, filename: String, data: Vec<u8>) {
+        self.submit(move || std::fs::write(filename, data));
+    }
+
+    /* Wait for the jobs submitted so far to be done, which also happens
+    when the writer is dropped. Jobs submitted after this run on the
+    calling thread. */
+    pub fn finish(&self) {
+        if let Ok(mut jobs) = self.jobs.lock() {
+            *jobs = None;
+        }
+        let handle: Option<std::thread::JoinHandle<()>> = match
+            self.handle.lock() {
+            Ok(mut handle) => handle.take(),
+            Err(_) => None,
+        };
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for OutputWriter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/* A time series sink whose rows are written by an OutputWriter, in
+order with the other files it writes. */
+pub struct QueuedSink {
+    sink: std::sync::Arc<std::sync::Mutex<Box<dyn TimeSeriesSink + Send>>>,
+    writer: std::sync::Arc<OutputWriter>,
+}
+
+impl QueuedSink {
+    pub fn new(sink: Box<dyn TimeSeriesSink + Send>,
+               writer: std::sync::Arc<OutputWriter>) -> QueuedSink {
+        return QueuedSink {sink: std::sync::Arc::new(
+            std::sync::Mutex::new(sink)), writer};
+    }
+}
+
+impl TimeSeriesSink for QueuedSink {
+    fn write_header(&mut self, names: &[&str]) -> std::io::Result<()> {
+        let names: Vec<String> = names.iter().map(|n| n.to_string())
+            .collect();
+        let sink = self.sink.clone();
+        self.writer.submit(move || {
+            let names: Vec<&str> = names.iter().map(|n| n.as_str())
+                .collect();
+            return match sink.lock() {
+                Ok(mut s) => s.write_header(&names),
+                Err(_) => Ok(()),
+            };
+        });
+        return Ok(());
+    }
+
+    fn write_row(&mut self, t: f64, values: &[f64]) -> std::io::Result<()> {
+        let values: Vec<f64> = values.to_vec();
+        let sink = self.sink.clone();
+        self.writer.submit(move || {
+            return match sink.lock() {
+                Ok(mut s) => s.write_row(t, &values),
+                Err(_) => Ok(()),
+            };
+        });
+        return Ok(());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::constants::*;
+
+/* Pointwise operations and sums over long arrays, split into TH_COUNT
+contiguous parts that are worked on by threads of their own when the
+threads feature is enabled and there is more than one processor.
+Arrays shorter than PARALLEL_MIN are not worth starting threads for and
+are worked on whole by the calling thread.
+
+Sums are added up part by part in order, so they are the same from run
+to run and machine to machine, though not to the bit the same as a sum
+in one pass. */
+pub const PARALLEL_MIN: usize = 1 << 16;
+
+static CORES: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+
+/* The number of parts an array of len elements is split into. */
+pub fn part_count(len: usize) -> usize {
+    return if cfg!(feature = "threads") && len >= PARALLEL_MIN {TH_COUNT}
+        else {1};
+}
+
+/* Whether parts are worked on by threads, which on a single processor
+would only add the cost of starting them. */
+fn use_threads() -> bool {
+    return *CORES.get_or_init(|| std::thread::available_parallelism()
+                              .map(|n| n.get()).unwrap_or(1)) > 1;
+}
+
+/* Call f with the parts of a and the matching parts of b, as long as
+the shorter of them. */
+pub fn zip_parts_mut<A: Send, B: Sync>(a: &mut [A], b: &[B],
+                                       f: impl Fn(&mut [A], &[B]) + Sync) {
+    let n: usize = usize::min(a.len(), b.len());
+    let parts: usize = part_count(n);
+    if parts == 1 || !use_threads() {
+        f(&mut a[..n], &b[..n]);
+        return;
+    }
+    let size: usize = n.div_ceil(parts);
+    let f = &f;
+    std::thread::scope(|s| {
+        for (a, b) in a[..n].chunks_mut(size).zip(b[..n].chunks(size)) {
+            s.spawn(move || f(a, b));
+        }
+    });
+}
+
+/* Call f with the parts of a, each a whole number of rows of row_len
+elements. */
+pub fn rows_parts_mut<A: Send>(a: &mut [A], row_len: usize,
+                               f: impl Fn(&mut [A]) + Sync) {
+    let rows: usize = a.len()/row_len;
+    let parts: usize = usize::min(part_count(a.len()), usize::max(rows, 1));
+    if parts == 1 || !use_threads() {
+        f(a);
+        return;
+    }
+    let size: usize = rows.div_ceil(parts)*row_len;
+    let f = &f;
+    std::thread::scope(|s| {
+        for a in a.chunks_mut(size) {
+            s.spawn(move || f(a));
+        }
+    });
+}
+
+/* A sum that keeps the rounding error of each addition and adds it back
+when the value is read (Neumaier's variant of Kahan summation), so that
+summing millions of squared amplitudes loses no more than a digit or
+two whatever their number and order. Values are added with +=.
+
+Reference:
+
+Wikipedia - Kahan summation algorithm
+https://en.wikipedia.org/wiki/Kahan_summation_algorithm
+*/
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    pub fn value(self) -> f64 {
+        return self.sum + self.compensation;
+    }
+}
+
+impl std::ops::AddAssign<f64> for CompensatedSum {
+    fn add_assign(&mut self, x: f64) {
+        let t: f64 = self.sum + x;
+        // The low order digits lost from whichever term is smaller.
+        self.compensation += if f64::abs(self.sum) >= f64::abs(x) {
+            (self.sum - t) + x
+        } else {
+            (x - t) + self.sum
+        };
+        self.sum = t;
+    }
+}
+
+/* The sums of the K values f gives for each part of the range 0..len,
+such as the rows of a grid, which cover points array elements in all. */
+pub fn sum_parts<const K: usize>(
+    len: usize, points: usize,
+    f: impl Fn(std::ops::Range<usize>) -> [f64; K] + Sync) -> [f64; K] {
+    let parts: usize = usize::min(part_count(points), usize::max(len, 1));
+    if parts == 1 {
+        return f(0..len);
+    }
+    let size: usize = len.div_ceil(parts);
+    let f = &f;
+    let sums: Vec<[f64; K]> = if !use_threads() {
+        (0..len).step_by(size).map(
+            |start| f(start..usize::min(start + size, len))).collect()
+    } else {
+        std::thread::scope(|s| {
+            let handles: Vec<std::thread::ScopedJoinHandle<[f64; K]>>
+                = (0..len).step_by(size).map(
+                    |start| s.spawn(move || f(start..usize::min(start + size,
+                                                                 len))))
+                .collect();
+            return handles.into_iter().map(|h| h.join().unwrap()).collect();
+        })
+    };
+    let mut total: [CompensatedSum; K] = [CompensatedSum::default(); K];
+    for part in sums.iter() {
+        for k in 0..K {
+            total[k] += part[k];
+        }
+    }
+    return total.map(|s| s.value());
+}
@@ -0,0 +1,149 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::field::*;
+
+/* The values of psi along the line at the given row (for Axis::X) or
+column (for Axis::Y), as a one dimensional wave function. */
+pub fn extract_slice(psi: &[Complex<f32>], axis: Axis, index: usize
+                     ) -> Vec<Complex<f32>> {
+    let mut f = std::vec::Vec::<Complex<f32>>::with_capacity(N);
+    for k in 0..N {
+        f.push(match axis {
+            Axis::X => psi[index*N + k],
+            Axis::Y => psi[k*N + index],
+        });
+    }
+    return f;
+}
+
+/* A distribution over the phase space of a one dimensional slice.
+Column j is the position j/size along the slice, as for the wave
+function, and row i is the momentum p_min + i*dp, in units of inverse
+grid spacing, so that rows go from negative to positive momenta. */
+pub struct PhaseSpace {
+    pub values: Field2D<f32>,
+    pub p_min: f32,
+    pub dp: f32,
+}
+
+impl PhaseSpace {
+    pub fn momentum(&self, i: usize) -> f32 {
+        return self.p_min + (i as f32)*self.dp;
+    }
+
+    /* Write the distribution as a matrix of whitespace separated
+    values, one line per momentum, keeping every stride-th point in
+    each direction, so that it can be loaded with numpy.loadtxt and
+    shown with imshow, or plotted with gnuplot's "matrix" option. The
+    ranges of position and momentum are given on a first comment line. */
+    pub fn save_text(&self, filename: &str, stride: usize
+                     ) -> std::io::Result<()> {
+        use std::io::Write;
+        let stride: usize = usize::max(1, stride);
+        let file = std::fs::File::create(filename)?;
+        let mut writer = std::io::BufWriter::new(file);
+        writeln!(writer, "# x 0 {} p {} {}",
+                 ((self.values.width - 1) as f32)/(self.values.width as f32),
+                 self.p_min, self.momentum(self.values.height - 1))?;
+        for i in (0..self.values.height).step_by(stride) {
+            for j in (0..self.values.width).step_by(stride) {
+                if j > 0 {
+                    write!(writer, " ")?;
+                }
+                write!(writer, "{:e}", self.values.get(i, j))?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+}
+
+/* Wigner quasi-probability distribution of a one dimensional, periodic
+wave function f,
+
+    W(x, p) = 1/pi sum_m f*(x + m) f(x - m) exp(2ipm),
+
+with x and m in grid points. Its integral over momentum gives |f(x)|^2
+and its integral over position the momentum distribution, but it can
+be negative, which is the signature of interference. Since the lag
+between the two points is 2m, the momenta resolved are |p| < pi/2 with
+spacing pi/size, half the range of the fft of f itself, so the wave
+function should not have components beyond this.
+
+References:
+
+Wikipedia - Wigner quasiprobability distribution
+https://en.wikipedia.org/wiki/Wigner_quasiprobability_distribution
+
+W. B. Case.
+Wigner functions and Weyl transforms for pedestrians.
+American Journal of Physics 76, 937 (2008).
+*/
+pub fn wigner(f: &[Complex<f32>]) -> PhaseSpace {
+    let size: usize = f.len();
+    let mut values = Field2D::<f32>::new(size, size, 0.0);
+    let mut column = vec![Complex {real: 0.0, imag: 0.0}; size];
+    for n in 0..size {
+        for m in 0..size {
+            column[m] = f[(n + m) % size].conj()
+                * f[(n + size - m) % size];
+        }
+        // The forward transform uses exp(+i...), so element k is
+        // the momentum pi*fft_frequency(k, size)/size.
+        fft_in_place(column.as_mut_slice(), size);
+        for (k, z) in column.iter().enumerate() {
+            let i: usize = (fft_frequency(k, size) + (size/2) as i32) as usize;
+            values.set(i, n, z.real/std::f32::consts::PI);
+        }
+    }
+    let dp: f32 = std::f32::consts::PI/(size as f32);
+    return PhaseSpace {values, p_min: -((size/2) as f32)*dp, dp};
+}
+
+/* Husimi Q distribution of a one dimensional, periodic wave function f,
+
+    Q(x, p) = 1/(2 pi) |<x, p|f>|^2,
+
+where |x, p> is the coherent state of position width sigma, in grid
+points, centred on x with momentum p. This is the Wigner function
+smoothed by a Gaussian of widths sigma and 1/(2 sigma), which takes
+away its negative parts and fine interference structure and so is
+easier to read as a picture of where the wave function is in phase
+space. The overlaps for each x are found with a single fft of f times
+a Gaussian window, so the momenta cover the full range of the fft,
+-pi < p <= pi, and the integral over phase space is the norm of f.
+
+References:
+
+Wikipedia - Husimi Q representation
+https://en.wikipedia.org/wiki/Husimi_Q_representation
+
+Wikipedia - Short-time Fourier transform
+https://en.wikipedia.org/wiki/Short-time_Fourier_transform
+*/
+pub fn husimi(f: &[Complex<f32>], sigma: f32) -> PhaseSpace {
+    let size: usize = f.len();
+    let mut values = Field2D::<f32>::new(size, size, 0.0);
+    let mut window = std::vec::Vec::<f32>::with_capacity(size);
+    let a: f32 = f32::powf(2.0*std::f32::consts::PI*sigma*sigma, -0.25);
+    for m in 0..size {
+        let d: f32 = fft_frequency(m, size) as f32;
+        window.push(a*f32::exp(-d*d/(4.0*sigma*sigma)));
+    }
+    let mut column = vec![Complex {real: 0.0, imag: 0.0}; size];
+    for n in 0..size {
+        for m in 0..size {
+            column[m] = f[m].scale(window[(m + size - n) % size]);
+        }
+        fft_in_place(column.as_mut_slice(), size);
+        for (k, z) in column.iter().enumerate() {
+            // Momentum fft_momentum(k, size), counted up from p_min.
+            values.set(momentum_order(k, size), n, z.length_squared()
+                       /(2.0*std::f32::consts::PI));
+        }
+    }
+    let dp: f32 = 2.0*std::f32::consts::PI/(size as f32);
+    return PhaseSpace {values, p_min: -std::f32::consts::PI + dp, dp};
+}
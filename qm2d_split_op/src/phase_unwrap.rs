@@ -0,0 +1,90 @@
+use crate::complex::*;
+
+/* The phase difference d taken into (-pi, pi]. */
+pub fn wrap_phase(d: f64) -> f64 {
+    let two_pi: f64 = 2.0*std::f64::consts::PI;
+    let wrapped: f64 = d - two_pi*(d/two_pi).round();
+    return if wrapped <= -std::f64::consts::PI {wrapped + two_pi}
+        else {wrapped};
+}
+
+/* Unwrap phases in (-pi, pi] along a line, by adding the multiple of
+2 pi to each that takes it closest to the one before, so that the result
+is continuous wherever neighbouring phases differ by less than pi. */
+pub fn unwrap_1d(phase: &[f64]) -> Vec<f64> {
+    let mut out: Vec<f64> = Vec::with_capacity(phase.len());
+    for (k, p) in phase.iter().enumerate() {
+        out.push(if k == 0 {*p}
+                 else {out[k - 1] + wrap_phase(p - phase[k - 1])});
+    }
+    return out;
+}
+
+/* Unwrap the phases of a width by height grid, stored row by row, in
+order of quality, so that the unreliable points are reached last: the
+point of highest quality keeps its phase, and then, of the points next
+to those done, the one of highest quality is unwrapped from the
+neighbour it was reached from, until all are done. Around a vortex, or
+any closed path over which the wrapped differences add up to a multiple
+of 2 pi, no unwrapping is continuous everywhere; the jumps by 2 pi left
+then lie along the paths of lowest quality, which for a wave function
+are where |psi| is small, and the velocity field is only meaningful away
+from them. Quality must not be negative; NaN is taken as 0.
+
+Neighbours are the four nearest points, without wrapping round the
+edges of the grid, as the phase need not be periodic over it.
+
+Reference:
+
+D. C. Ghiglia, M. D. Pritt. Two-Dimensional Phase Unwrapping: Theory,
+Algorithms, and Software, chapter 4.3. Wiley (1998).
+*/
+pub fn unwrap_2d(phase: &[f64], quality: &[f64], width: usize, height: usize
+                 ) -> Vec<f64> {
+    assert_eq!(phase.len(), width*height);
+    assert_eq!(quality.len(), width*height);
+    let mut out: Vec<f64> = phase.to_vec();
+    if phase.is_empty() {
+        return out;
+    }
+    // Non-negative floats order as their bits do.
+    let key = |k: usize| -> u64 {
+        return if quality[k] > 0.0 {quality[k].to_bits()} else {0};
+    };
+    let mut done: Vec<bool> = vec![false; width*height];
+    // The quality of a point next to those done, the point and the
+    // neighbour it is unwrapped from.
+    let mut queue: std::collections::BinaryHeap<(u64, usize, usize)>
+        = std::collections::BinaryHeap::new();
+    let start: usize = (0..width*height).max_by_key(|k| key(*k)).unwrap_or(0);
+    queue.push((key(start), start, start));
+    while let Some((_, k, from)) = queue.pop() {
+        if done[k] {
+            continue;
+        }
+        done[k] = true;
+        out[k] = out[from] + wrap_phase(phase[k] - phase[from]);
+        let (i, j): (usize, usize) = (k/width, k % width);
+        let neighbours: [(bool, usize); 4] = [
+            (j > 0, k.wrapping_sub(1)), (j + 1 < width, k + 1),
+            (i > 0, k.wrapping_sub(width)), (i + 1 < height, k + width)];
+        for (inside, m) in neighbours {
+            if inside && !done[m] {
+                queue.push((key(m), m, k));
+            }
+        }
+    }
+    return out;
+}
+
+/* The unwrapped phase of psi on a width by height grid, with |psi|^2 as
+the quality. With hbar = m = 1 it is the velocity potential of the
+flow: the velocity is its gradient, wherever psi is not small. */
+pub fn unwrap_psi_phase(psi: &[Complex<f32>], width: usize, height: usize
+                        ) -> Vec<f64> {
+    let phase: Vec<f64> = psi.iter()
+        .map(|z| (z.imag as f64).atan2(z.real as f64)).collect();
+    let quality: Vec<f64> = psi.iter().map(|z| z.length_squared() as f64)
+        .collect();
+    return unwrap_2d(&phase, &quality, width, height);
+}
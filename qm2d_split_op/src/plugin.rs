@@ -0,0 +1,237 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::norm::*;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+
+/* Physics terms compiled separately from this crate, as shared libraries
+in the plugin directory (plugin_dir, default plugins/), which are loaded
+at startup. Each plugin exports one function,
+
+    const qm2d_plugin_description *qm2d_plugin(void);
+
+returning a description of what it adds, declared with the C types in
+include/qm2d_plugin.h:
+
+    abi_version    QM2D_PLUGIN_ABI, checked against PLUGIN_ABI so that a
+                   plugin built for another layout is refused, not called
+    name           for the messages and the observables file
+    potential      adds a term to the real parts of the potential at time
+                   t, called at startup and, if time_dependent is not 0,
+                   before every step
+    observables    writes observable_count values, named by
+                   observable_names, to the observables file with the
+                   others
+    step           changes psi after every step, as a propagator term
+                   the split operator steps do not cover
+
+Any of the functions may be NULL. They are passed the side length of the
+grid, N, with psi and the potential as N*N values laid out as Complex,
+in rows of constant y. The layout only grows at the end, with
+PLUGIN_ABI raised when it changes otherwise. A trait for plugins would
+not be stable between compiler versions, and libloading a dependency
+this crate avoids, so plugins are loaded with dlopen, on Unix only. */
+pub const PLUGIN_ABI: u32 = 1;
+
+#[repr(C)]
+pub struct PluginDescription {
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub potential: Option<extern "C" fn(usize, f32, *mut Complex<f32>)>,
+    pub time_dependent: c_int,
+    pub observable_count: usize,
+    pub observable_names: *const *const c_char,
+    pub observables: Option<extern "C" fn(usize, *const Complex<f32>,
+                                          *const Complex<f32>, *mut f64)>,
+    pub step: Option<extern "C" fn(usize, *mut Complex<f32>,
+                                   *const Complex<f32>, f32, f32, f32)>,
+}
+
+/* A loaded plugin, whose library stays open for as long as the program
+runs, since its functions are called until the end. */
+pub struct Plugin {
+    pub name: String,
+    pub observable_names: Vec<String>,
+    description: &'static PluginDescription,
+}
+
+/* The plugins of a run, called in the order of their file names. */
+#[derive(Default)]
+pub struct Plugins {
+    pub plugins: Vec<Plugin>,
+}
+
+#[cfg(unix)]
+mod dl {
+    use std::ffi::{c_char, c_int, c_void};
+
+    pub const RTLD_NOW: c_int = 2;
+
+    #[cfg_attr(target_os = "linux", link(name = "dl"))]
+    extern "C" {
+        pub fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        pub fn dlsym(handle: *mut c_void, symbol: *const c_char)
+                     -> *mut c_void;
+        pub fn dlerror() -> *mut c_char;
+    }
+}
+
+/* The message of the last dlopen or dlsym failure. */
+#[cfg(unix)]
+fn dl_error() -> String {
+    let message: *mut c_char = unsafe {dl::dlerror()};
+    if message.is_null() {
+        return "unknown error".to_string();
+    }
+    return unsafe {CStr::from_ptr(message)}.to_string_lossy().into_owned();
+}
+
+/* A string from the plugin, or an error naming what it is. */
+fn plugin_string(text: *const c_char, what: &str) -> Result<String, String> {
+    if text.is_null() {
+        return Err(format!("{} is NULL", what));
+    }
+    return Ok(unsafe {CStr::from_ptr(text)}.to_string_lossy().into_owned());
+}
+
+impl Plugin {
+    /* Load the plugin in the shared library at path. */
+    #[cfg(unix)]
+    pub fn load(path: &std::path::Path) -> Result<Plugin, String> {
+        let error = |e: String| format!("plugin {}: {}", path.display(), e);
+        let filename: CString = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|e| error(e.to_string()))?;
+        let handle: *mut c_void = unsafe {
+            dl::dlopen(filename.as_ptr(), dl::RTLD_NOW)};
+        if handle.is_null() {
+            return Err(error(dl_error()));
+        }
+        let symbol: *mut c_void = unsafe {
+            dl::dlsym(handle, c"qm2d_plugin".as_ptr())};
+        if symbol.is_null() {
+            return Err(error(dl_error()));
+        }
+        let describe: extern "C" fn() -> *const PluginDescription
+            = unsafe {std::mem::transmute::<*mut c_void, _>(symbol)};
+        let description: *const PluginDescription = describe();
+        if description.is_null() {
+            return Err(error("qm2d_plugin returned NULL".to_string()));
+        }
+        // The library is never closed, so the description lives as long
+        // as the program.
+        let description: &'static PluginDescription = unsafe {&*description};
+        if description.abi_version != PLUGIN_ABI {
+            return Err(error(format!(
+                "built for plugin ABI {}, but this program has {}",
+                description.abi_version, PLUGIN_ABI)));
+        }
+        let name: String = plugin_string(description.name, "name")
+            .map_err(error)?;
+        let mut observable_names: Vec<String> = Vec::new();
+        for k in 0..description.observable_count {
+            if description.observable_names.is_null() {
+                return Err(error("observable_names is NULL".to_string()));
+            }
+            let observable: *const c_char = unsafe {
+                *description.observable_names.add(k)};
+            observable_names.push(format!(
+                "{}.{}", name, plugin_string(observable, "an observable name")
+                    .map_err(error)?));
+        }
+        if description.observable_count > 0
+            && description.observables.is_none() {
+            return Err(error("observables is NULL".to_string()));
+        }
+        return Ok(Plugin {name, observable_names, description});
+    }
+
+    #[cfg(not(unix))]
+    pub fn load(path: &std::path::Path) -> Result<Plugin, String> {
+        return Err(format!("plugin {}: plugins are only loaded on Unix",
+                           path.display()));
+    }
+}
+
+impl Plugins {
+    /* Load every shared library in directory, which need not exist. */
+    pub fn load_directory(directory: &str) -> Result<Plugins, String> {
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Plugins::default());
+            },
+            Err(e) => return Err(format!("{}: {}", directory, e)),
+        };
+        let mut paths: Vec<std::path::PathBuf> = entries.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(
+                |e| e == std::env::consts::DLL_EXTENSION))
+            .collect();
+        paths.sort();
+        let plugins: Vec<Plugin> = paths.iter().map(|path| Plugin::load(path))
+            .collect::<Result<Vec<Plugin>, String>>()?;
+        return Ok(Plugins {plugins});
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.plugins.is_empty();
+    }
+
+    /* Whether a plugin adds a potential that changes with time. */
+    pub fn is_time_dependent(&self) -> bool {
+        return self.plugins.iter().any(|p| p.description.potential.is_some()
+                                       && p.description.time_dependent != 0);
+    }
+
+    pub fn has_step(&self) -> bool {
+        return self.plugins.iter().any(|p| p.description.step.is_some());
+    }
+
+    /* Add the potentials of the plugins at time t. */
+    pub fn add_potential(&self, potential: &mut [Complex<f32>], t: f32) {
+        assert_eq!(potential.len(), N*N);
+        for plugin in self.plugins.iter() {
+            if let Some(f) = plugin.description.potential {
+                f(N, t, potential.as_mut_ptr());
+            }
+        }
+    }
+
+    pub fn observable_names(&self) -> Vec<&str> {
+        return self.plugins.iter()
+            .flat_map(|p| p.observable_names.iter().map(String::as_str))
+            .collect();
+    }
+
+    /* The observables of the plugins, in the order of their names. */
+    pub fn observables(&self, psi: &[Complex<f32>],
+                       potential: &[Complex<f32>]) -> Vec<f64> {
+        assert_eq!(psi.len(), N*N);
+        assert_eq!(potential.len(), N*N);
+        let mut values: Vec<f64> = Vec::new();
+        for plugin in self.plugins.iter() {
+            if let Some(f) = plugin.description.observables {
+                let start: usize = values.len();
+                values.resize(start + plugin.observable_names.len(), f64::NAN);
+                f(N, psi.as_ptr(), potential.as_ptr(),
+                  values[start..].as_mut_ptr());
+            }
+        }
+        return values;
+    }
+
+    /* Apply the step functions of the plugins to psi, after a step of dt
+    ending at time t, returning the change in the norm they made. */
+    pub fn step(&self, psi: &mut [Complex<f32>], potential: &[Complex<f32>],
+                dt: Complex<f32>, t: f32) -> f64 {
+        assert_eq!(psi.len(), N*N);
+        assert_eq!(potential.len(), N*N);
+        let before: f64 = norm_squared(psi);
+        for plugin in self.plugins.iter() {
+            if let Some(f) = plugin.description.step {
+                f(N, psi.as_mut_ptr(), potential.as_ptr(), dt.real, dt.imag,
+                  t);
+            }
+        }
+        return norm_squared(psi) - before;
+    }
+}
@@ -0,0 +1,106 @@
+use crate::complex::*;
+use crate::fft::*;
+
+/* Boundaries of the box a Poisson equation is solved in. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PoissonBoundary {
+    Periodic, // as the grid of the split operator steps
+    Dirichlet, // phi = 0 on row 0 and column 0 and, periodically, at n
+}
+
+impl PoissonBoundary {
+    pub fn parse(s: &str) -> Result<PoissonBoundary, String> {
+        return match s {
+            "periodic" => Ok(PoissonBoundary::Periodic),
+            "dirichlet" => Ok(PoissonBoundary::Dirichlet),
+            _ => Err(format!("unknown boundary '{}', expected periodic or \
+                              dirichlet", s)),
+        };
+    }
+}
+
+/* Solve laplacian(phi) = rho on an n by n grid of the given spacing,
+with n a power of two, rows of rho being along x, spectrally: rho is
+expanded in the eigenfunctions of the laplacian that satisfy the
+boundaries, and each term divided by its eigenvalue, so that the
+solution is exact for the band limited functions the grid represents.
+
+ - Periodic: the eigenfunctions are plane waves exp(i k.r), with
+   k = 2 pi m/(n spacing) for the frequencies m of fft_frequency, and
+   the eigenvalues -|k|^2. A periodic phi only exists for rho of zero
+   mean, so the mean of rho is taken away, as by a uniform background
+   of the opposite charge, and phi is given the mean zero.
+ - Dirichlet: phi vanishes on the walls at row and column 0 and n, the
+   latter one spacing past the last row and column of the grid. The
+   eigenfunctions are sin(pi m x/L) sin(pi l y/L) with L = n spacing and
+   0 < m, l < n, found by dst1_in_place, with eigenvalues
+   -(pi/L)^2 (m^2 + l^2). rho on row and column 0 is ignored.
+
+For the potential of a charge density in Gaussian units rho is
+-4 pi times the density, and in SI units minus the density over
+epsilon_0. */
+pub fn solve_poisson(rho: &[f64], n: usize, spacing: f64,
+                     boundary: PoissonBoundary) -> Result<Vec<f64>, String> {
+    if !n.is_power_of_two() || n < 2 {
+        return Err(format!("a Poisson grid must have a power of two points \
+                            a side, not {}", n));
+    }
+    if rho.len() != n*n {
+        return Err(format!("rho has {} values, not {} by {}", rho.len(), n,
+                           n));
+    }
+    if spacing <= 0.0 || !spacing.is_finite() {
+        return Err(format!("the grid spacing must be positive, not {}",
+                           spacing));
+    }
+    let length: f64 = (n as f64)*spacing;
+    return Ok(match boundary {
+        PoissonBoundary::Periodic => {
+            let mut a: Vec<Complex<f64>> = rho.iter()
+                .map(|r| Complex {real: *r, imag: 0.0}).collect();
+            let fft2 = |a: &mut [Complex<f64>], is_inverse: bool| {
+                for _ in 0..2 {
+                    for row in a.chunks_mut(n) {
+                        base_f64_fft_in_place(row, n, is_inverse);
+                    }
+                    square_transpose_in_place(a, n);
+                }
+            };
+            fft2(&mut a, false);
+            let dk: f64 = 2.0*std::f64::consts::PI/length;
+            for i in 0..n {
+                for j in 0..n {
+                    let k2: f64 = dk*dk*((fft_frequency(i, n).pow(2)
+                                          + fft_frequency(j, n).pow(2)) as f64);
+                    a[i*n + j] = if k2 > 0.0 {a[i*n + j].scale(-1.0/k2)}
+                        else {Complex {real: 0.0, imag: 0.0}};
+                }
+            }
+            fft2(&mut a, true);
+            a.iter().map(|z| z.real).collect()
+        },
+        PoissonBoundary::Dirichlet => {
+            let mut a: Vec<f64> = rho.to_vec();
+            let dst2 = |a: &mut [f64]| {
+                for _ in 0..2 {
+                    for row in a.chunks_mut(n) {
+                        dst1_in_place(row, n);
+                    }
+                    square_transpose_in_place(a, n);
+                }
+            };
+            dst2(&mut a);
+            // The inverse of the two transforms is themselves times
+            // (2/n)^2.
+            let dk: f64 = std::f64::consts::PI/length;
+            let inverse: f64 = 4.0/((n*n) as f64);
+            for i in 1..n {
+                for j in 1..n {
+                    a[i*n + j] *= -inverse/(dk*dk*((i*i + j*j) as f64));
+                }
+            }
+            dst2(&mut a);
+            a
+        },
+    });
+}
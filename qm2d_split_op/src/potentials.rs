@@ -0,0 +1,452 @@
+use crate::constants::*;
+use crate::complex::*;
+
+/* A static potential V(x, y), where x and y are in [0, 1] as for the
+initial wave packet. Lengths that appear in the parameters of the
+potentials below are also in these units, except where the shape of
+the potential is tied to the kinetic term (the traps and the Coulomb
+potential), where distances are measured in grid points to match
+the units of the momentum in init_momentum_squared. */
+pub trait Potential {
+    fn value(&self, x: f32, y: f32) -> f32;
+
+    /* Rasterize the potential onto the N by N simulation grid,
+    replacing the real part of each element and leaving the imaginary
+    (absorbing) part as it is. */
+    fn fill(&self, potential: &mut [Complex<f32>]) {
+        for i in 0..N {
+            for j in 0..N {
+                let x: f32 = (j as f32)/(N as f32);
+                let y: f32 = (i as f32)/(N as f32);
+                potential[i*N + j].real = self.value(x, y);
+            }
+        }
+    }
+}
+
+/* A potential V(x, y, t) that may change as the simulation runs. */
+pub trait TimeDependentPotential {
+    fn value_at(&self, x: f32, y: f32, t: f32) -> f32;
+
+    /* Whether the potential actually changes with time. If not it only
+    needs to be rasterized once. */
+    fn is_time_dependent(&self) -> bool {
+        return true;
+    }
+
+    /* Rasterize the potential at time t, replacing the real part of
+    each element as in Potential::fill. */
+    fn fill_at(&self, potential: &mut [Complex<f32>], t: f32) {
+        for i in 0..N {
+            for j in 0..N {
+                let x: f32 = (j as f32)/(N as f32);
+                let y: f32 = (i as f32)/(N as f32);
+                potential[i*N + j].real = self.value_at(x, y, t);
+            }
+        }
+    }
+}
+
+/* Sum of several time dependent potentials. */
+pub struct TimeDependentSum {
+    pub terms: Vec<Box<dyn TimeDependentPotential>>,
+}
+
+impl TimeDependentPotential for TimeDependentSum {
+    fn value_at(&self, x: f32, y: f32, t: f32) -> f32 {
+        let mut v: f32 = 0.0;
+        for term in &self.terms {
+            v += term.value_at(x, y, t);
+        }
+        return v;
+    }
+
+    fn is_time_dependent(&self) -> bool {
+        return self.terms.iter().any(|term| term.is_time_dependent());
+    }
+
+    fn fill_at(&self, potential: &mut [Complex<f32>], t: f32) {
+        let mut term_values = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+        for v in potential.iter_mut() {
+            v.real = 0.0;
+        }
+        for term in &self.terms {
+            term.fill_at(term_values.as_mut_slice(), t);
+            for (v, term_value) in potential.iter_mut()
+                .zip(term_values.iter()) {
+                v.real += term_value.real;
+            }
+        }
+    }
+}
+
+impl <F: Fn(f32, f32) -> f32> Potential for F {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        return self(x, y);
+    }
+}
+
+/* Sum of several potentials. */
+pub struct SumPotential {
+    pub terms: Vec<Box<dyn Potential>>,
+}
+
+impl Potential for SumPotential {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let mut v: f32 = 0.0;
+        for term in &self.terms {
+            v += term.value(x, y);
+        }
+        return v;
+    }
+}
+
+pub struct RectangularBarrier {
+    pub x0: f32, pub y0: f32, // centre
+    pub w: f32, pub h: f32, // width and height
+    pub height: f32, // value of the potential inside the barrier
+}
+
+impl Potential for RectangularBarrier {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        if f32::abs(x - self.x0) <= 0.5*self.w
+            && f32::abs(y - self.y0) <= 0.5*self.h {
+            return self.height;
+        }
+        return 0.0;
+    }
+}
+
+pub struct GaussianBarrier {
+    pub x0: f32, pub y0: f32, // centre
+    pub sx: f32, pub sy: f32, // x and y standard deviations
+    pub height: f32, // peak value, negative for a well
+}
+
+impl Potential for GaussianBarrier {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let xt: f32 = x - self.x0;
+        let yt: f32 = y - self.y0;
+        return self.height
+            *f32::exp(-0.5*xt*xt/(self.sx*self.sx))
+            *f32::exp(-0.5*yt*yt/(self.sy*self.sy));
+    }
+}
+
+/* Circular finite square well. */
+pub struct SingleWell {
+    pub x0: f32, pub y0: f32, // centre
+    pub radius: f32,
+    pub depth: f32, // the potential is -depth inside the well
+}
+
+impl Potential for SingleWell {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let xt: f32 = x - self.x0;
+        let yt: f32 = y - self.y0;
+        if xt*xt + yt*yt <= self.radius*self.radius {
+            return -self.depth;
+        }
+        return 0.0;
+    }
+}
+
+/* Two circular finite square wells placed symmetrically about (x0, y0)
+along the x direction. */
+pub struct DoubleWell {
+    pub x0: f32, pub y0: f32, // midpoint between the two wells
+    pub separation: f32, // distance between the centres of the wells
+    pub radius: f32,
+    pub depth: f32, // the potential is -depth inside either well
+}
+
+impl Potential for DoubleWell {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let left = SingleWell {x0: self.x0 - 0.5*self.separation,
+                               y0: self.y0, radius: self.radius,
+                               depth: self.depth};
+        let right = SingleWell {x0: self.x0 + 0.5*self.separation,
+                                y0: self.y0, radius: self.radius,
+                                depth: self.depth};
+        return f32::min(left.value(x, y), right.value(x, y));
+    }
+}
+
+/* A horizontal wall at y = y0 with two openings centred about x0,
+like the barrier drawn in the default POTENTIAL_ASCII. */
+pub struct DoubleSlit {
+    pub x0: f32, pub y0: f32, // centre of the wall
+    pub thickness: f32, // thickness of the wall in the y direction
+    pub slit_width: f32,
+    pub slit_separation: f32, // distance between the centres of the slits
+    pub height: f32, // value of the potential inside the wall
+}
+
+impl Potential for DoubleSlit {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        if f32::abs(y - self.y0) > 0.5*self.thickness {
+            return 0.0;
+        }
+        let d: f32 = f32::abs(f32::abs(x - self.x0)
+                              - 0.5*self.slit_separation);
+        if d < 0.5*self.slit_width {
+            return 0.0;
+        }
+        return self.height;
+    }
+}
+
+/* V = omega_x^2*dx^2/2 + omega_y^2*dy^2/2, where the displacements from
+the centre are in grid points, so that omega_x and omega_y are
+the oscillation frequencies in units of inverse simulation time. */
+pub struct HarmonicTrap {
+    pub x0: f32, pub y0: f32, // centre
+    pub omega_x: f32, pub omega_y: f32,
+}
+
+impl Potential for HarmonicTrap {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let dx: f32 = (x - self.x0)*(N as f32);
+        let dy: f32 = (y - self.y0)*(N as f32);
+        return 0.5*(self.omega_x*self.omega_x*dx*dx
+                    + self.omega_y*self.omega_y*dy*dy);
+    }
+}
+
+/* Isotropic harmonic trap with an additional quartic term:
+V = omega^2*r^2/2 + quartic*r^4, with r in grid points. */
+pub struct AnharmonicTrap {
+    pub x0: f32, pub y0: f32, // centre
+    pub omega: f32,
+    pub quartic: f32,
+}
+
+impl Potential for AnharmonicTrap {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let dx: f32 = (x - self.x0)*(N as f32);
+        let dy: f32 = (y - self.y0)*(N as f32);
+        let r2: f32 = dx*dx + dy*dy;
+        return 0.5*self.omega*self.omega*r2 + self.quartic*r2*r2;
+    }
+}
+
+/* Soft-core Coulomb potential V = -charge/sqrt(r^2 + softening^2) of
+an attractive centre, where r and softening are in grid points.
+The softening removes the singularity at r = 0, which the grid
+cannot resolve.
+
+Reference:
+
+Wikipedia - Coulomb's law
+https://en.wikipedia.org/wiki/Coulomb%27s_law
+*/
+pub struct SoftCoulomb {
+    pub x0: f32, pub y0: f32, // centre
+    pub charge: f32,
+    pub softening: f32,
+}
+
+impl Potential for SoftCoulomb {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let dx: f32 = (x - self.x0)*(N as f32);
+        let dy: f32 = (y - self.y0)*(N as f32);
+        return -self.charge/f32::sqrt(dx*dx + dy*dy
+                                      + self.softening*self.softening);
+    }
+}
+
+/* Potential step of the given height for all points past the line
+through (x0, y0) whose normal points in the direction of angle. */
+pub struct StepPotential {
+    pub x0: f32, pub y0: f32, // a point on the edge of the step
+    pub angle: f32, // direction of the normal to the edge, in radians
+    pub height: f32,
+}
+
+impl Potential for StepPotential {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let d: f32 = (x - self.x0)*f32::cos(self.angle)
+            + (y - self.y0)*f32::sin(self.angle);
+        return if d >= 0.0 {self.height} else {0.0};
+    }
+}
+
+/* A potential stored as values on the N by N simulation grid, so that
+an expensive potential only needs to be evaluated once. Values between
+grid points are taken from the nearest grid point. */
+pub struct GridPotential {
+    pub values: Vec<f32>,
+}
+
+impl GridPotential {
+    pub fn rasterize(p: &dyn Potential) -> GridPotential {
+        let mut values = std::vec::Vec::<f32>::with_capacity(N*N);
+        for i in 0..N {
+            for j in 0..N {
+                values.push(p.value((j as f32)/(N as f32),
+                                    (i as f32)/(N as f32)));
+            }
+        }
+        return GridPotential {values};
+    }
+}
+
+impl Potential for GridPotential {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let j: usize = (f32::round(x*(N as f32)) as i64)
+            .rem_euclid(N as i64) as usize;
+        let i: usize = (f32::round(y*(N as f32)) as i64)
+            .rem_euclid(N as i64) as usize;
+        return self.values[i*N + j];
+    }
+
+    fn fill(&self, potential: &mut [Complex<f32>]) {
+        for (v, value) in potential.iter_mut().zip(self.values.iter()) {
+            v.real = *value;
+        }
+    }
+}
+
+/* One standing wave of an optical lattice, contributing
+sin^2(pi*(nx*x + ny*y) + phase) to the potential. The lattice then has
+nx sites across the domain in the x direction and ny in the y direction,
+so these should be integers for the lattice to fit the periodic
+boundaries. */
+pub struct LatticeBeam {
+    pub nx: f32, pub ny: f32,
+    pub phase: f32, // in radians, shifts the lattice sites
+}
+
+/* V = depth*sum(sin^2(pi*(nx*x + ny*y) + phase)) over the beams.
+
+Reference:
+
+Wikipedia - Optical lattice
+https://en.wikipedia.org/wiki/Optical_lattice
+*/
+pub struct OpticalLattice {
+    pub depth: f32,
+    pub beams: Vec<LatticeBeam>,
+}
+
+impl OpticalLattice {
+    /* Square lattice with the given number of sites along each axis. */
+    pub fn square(depth: f32, sites_x: f32, sites_y: f32) -> OpticalLattice {
+        return OpticalLattice {depth, beams: vec![
+            LatticeBeam {nx: sites_x, ny: 0.0, phase: 0.0},
+            LatticeBeam {nx: 0.0, ny: sites_y, phase: 0.0},
+        ]};
+    }
+
+    /* Triangular lattice from three beams 120 degrees apart. The beams
+    that are not along x cannot be exactly periodic over the domain,
+    so there is a seam at the boundary unless the wave function stays
+    away from it. */
+    pub fn triangular(depth: f32, sites: f32) -> OpticalLattice {
+        let s: f32 = 0.5*f32::sqrt(3.0)*sites;
+        return OpticalLattice {depth, beams: vec![
+            LatticeBeam {nx: sites, ny: 0.0, phase: 0.0},
+            LatticeBeam {nx: -0.5*sites, ny: s, phase: 0.0},
+            LatticeBeam {nx: -0.5*sites, ny: -s, phase: 0.0},
+        ]};
+    }
+}
+
+impl Potential for OpticalLattice {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let mut v: f32 = 0.0;
+        for b in &self.beams {
+            let s: f32 = f32::sin(std::f32::consts::PI*(b.nx*x + b.ny*y)
+                                  + b.phase);
+            v += s*s;
+        }
+        return self.depth*v;
+    }
+}
+
+/* A primary lattice with a second, usually weaker and incommensurate
+or longer period, lattice on top, as used for bichromatic
+(Aubry-Andre) localization and double-well lattices. */
+pub struct Superlattice {
+    pub primary: OpticalLattice,
+    pub secondary: OpticalLattice,
+}
+
+impl Superlattice {
+    /* Square primary lattice with sites sites along each axis and a
+    secondary square lattice of secondary_sites sites along each axis,
+    shifted by phase radians. */
+    pub fn square(depth: f32, sites: f32,
+                  secondary_depth: f32, secondary_sites: f32,
+                  phase: f32) -> Superlattice {
+        let mut secondary = OpticalLattice::square(secondary_depth,
+                                                   secondary_sites,
+                                                   secondary_sites);
+        for b in secondary.beams.iter_mut() {
+            b.phase = phase;
+        }
+        return Superlattice {
+            primary: OpticalLattice::square(depth, sites, sites),
+            secondary,
+        };
+    }
+}
+
+impl Potential for Superlattice {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        return self.primary.value(x, y) + self.secondary.value(x, y);
+    }
+}
+
+pub struct PointCharge {
+    pub x0: f32, pub y0: f32, // position
+    pub charge: f32, // positive charges attract the wave function
+}
+
+/* Sum of soft-core Coulomb potentials from a set of point charges,
+all with the same softening (in grid points, as for SoftCoulomb). */
+pub struct ChargeArrangement {
+    pub charges: Vec<PointCharge>,
+    pub softening: f32,
+}
+
+impl ChargeArrangement {
+    /* Two unit charges a distance bond_length apart centred on (x0, y0),
+    with the bond at the given angle from the x axis, giving a 2D
+    analogue of the H2+ molecular ion once an electron is placed near
+    them. */
+    pub fn h2_plus(x0: f32, y0: f32, bond_length: f32, angle: f32,
+                   softening: f32) -> ChargeArrangement {
+        let dx: f32 = 0.5*bond_length*f32::cos(angle);
+        let dy: f32 = 0.5*bond_length*f32::sin(angle);
+        return ChargeArrangement {charges: vec![
+            PointCharge {x0: x0 - dx, y0: y0 - dy, charge: 1.0},
+            PointCharge {x0: x0 + dx, y0: y0 + dy, charge: 1.0},
+        ], softening};
+    }
+
+    /* count charges of the given charge evenly spaced on a circle. */
+    pub fn ring(x0: f32, y0: f32, radius: f32, count: usize, charge: f32,
+                softening: f32) -> ChargeArrangement {
+        let mut charges = std::vec::Vec::<PointCharge>::with_capacity(count);
+        for k in 0..count {
+            let angle: f32 = 2.0*std::f32::consts::PI*(k as f32)
+                /(count as f32);
+            charges.push(PointCharge {x0: x0 + radius*f32::cos(angle),
+                                      y0: y0 + radius*f32::sin(angle),
+                                      charge});
+        }
+        return ChargeArrangement {charges, softening};
+    }
+}
+
+impl Potential for ChargeArrangement {
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let mut v: f32 = 0.0;
+        for c in &self.charges {
+            v += SoftCoulomb {x0: c.x0, y0: c.y0, charge: c.charge,
+                              softening: self.softening}.value(x, y);
+        }
+        return v;
+    }
+}
@@ -0,0 +1,240 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::norm::*;
+use crate::parallel::*;
+use crate::spectral::*;
+use crate::split_op::*;
+
+/* The floating point type the wave function is stepped in. Everything
+else, the potential, observables and output, stays in single precision,
+so that a run at either precision gives the same files and can be
+compared point by point. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StepPrecision {
+    Single,
+    Double,
+}
+
+impl StepPrecision {
+    pub fn parse(name: &str) -> Result<StepPrecision, String> {
+        return match name {
+            "single" => Ok(StepPrecision::Single),
+            "double" => Ok(StepPrecision::Double),
+            _ => Err(format!(
+                "unknown precision '{}', expected single or double", name)),
+        };
+    }
+}
+
+/* Steps of the splitting at the precision chosen for the run, without
+a nonlinear term unless one is set. */
+pub enum Stepper {
+    Single(Propagators),
+    Double(DoubleStepper),
+}
+
+impl Stepper {
+    pub fn new(precision: StepPrecision) -> Stepper {
+        return match precision {
+            StepPrecision::Single => Stepper::Single(Propagators::default()),
+            StepPrecision::Double => Stepper::Double(DoubleStepper::default()),
+        };
+    }
+
+    /* Damp the spectrum of psi with the filter at each step, or stop
+    damping it if None (see SpectralFilter). */
+    pub fn set_filter(&mut self, filter: Option<SpectralFilter>) {
+        match self {
+            Stepper::Single(propagators) => propagators.set_filter(filter),
+            Stepper::Double(stepper) => stepper.set_filter(filter),
+        }
+    }
+
+    /* Step the nonlinear term of the Gross-Pitaevskii equation with the
+    potential from the next step on, or stop if None, as
+    Propagators::set_nonlinear. */
+    pub fn set_nonlinear(&mut self, nonlinear: Option<Nonlinear>) {
+        match self {
+            Stepper::Single(propagators) => {
+                propagators.set_nonlinear(nonlinear);
+            },
+            Stepper::Double(stepper) => {
+                stepper.nonlinear = nonlinear.filter(|n| n.square != 0.0);
+            },
+        }
+    }
+
+    /* Advance psi by a time step dt, as split_op::step does, returning
+    the norm before the step and after each of its four parts. The
+    propagators are computed again as by Propagators::update. */
+    pub fn step(&mut self, psi: &mut [Complex<f32>],
+                potential: &[Complex<f32>], p_squared: &[f32],
+                dt: Complex<f32>, potential_changed: bool,
+                use_mt: bool) -> [f64; 5] {
+        return match self {
+            Stepper::Single(propagators) => {
+                propagators.update(potential, p_squared, dt,
+                                   potential_changed);
+                let norm_0: f64 = norm_squared(psi);
+                propagators.propagate_spatial(psi);
+                let norm_1: f64 = norm_squared(psi);
+                propagators.propagate_kinetic(psi, use_mt);
+                let norm_2: f64 = norm_squared(psi);
+                dampen(psi, dt.real);
+                let norm_3: f64 = norm_squared(psi);
+                propagators.propagate_spatial(psi);
+                [norm_0, norm_1, norm_2, norm_3, norm_squared(psi)]
+            },
+            Stepper::Double(stepper) => {
+                stepper.update(potential, dt, potential_changed);
+                stepper.step(psi, dt)
+            },
+        };
+    }
+}
+
+/* The splitting in double precision. The wave function is kept in
+doubles between steps and rounded into psi after each, and is only
+replaced by psi when psi is found to differ from what was last written
+to it, as after the viewer resets it or the norm monitor rescales it. */
+#[derive(Default)]
+pub struct DoubleStepper {
+    psi: Vec<Complex<f64>>,
+    written: Vec<Complex<f32>>,
+    spatial: Vec<Complex<f64>>,
+    kinetic: Vec<Complex<f64>>,
+    dt: Option<(f32, f32)>,
+    filter: Option<SpectralFilter>,
+    nonlinear: Option<Nonlinear>,
+}
+
+impl DoubleStepper {
+    /* Compute the factors again when dt or the potential have changed.
+    The momenta are computed in doubles here instead of being taken from
+    the single precision p_squared. */
+    pub fn update(&mut self, potential: &[Complex<f32>], dt: Complex<f32>,
+                  potential_changed: bool) {
+        let dt_64: Complex<f64> = dt.into();
+        let dt_changed: bool = self.dt != Some((dt.real, dt.imag));
+        if dt_changed {
+            self.kinetic.clear();
+            for i in 0..N {
+                for j in 0..N {
+                    let px: f64 = 2.0*std::f64::consts::PI
+                        *(fft_frequency(i, N) as f64)/(N as f64);
+                    let py: f64 = 2.0*std::f64::consts::PI
+                        *(fft_frequency(j, N) as f64)/(N as f64);
+                    let p2: f64 = px*px + py*py;
+                    let factor: f64 = self.filter.map_or(
+                        1.0, |filter| filter.factor(p2));
+                    self.kinetic.push(c128exp(Complex {
+                        real: 0.0, imag: -0.5*p2} * dt_64).scale(factor));
+                }
+            }
+        }
+        if dt_changed || potential_changed
+            || self.spatial.len() != potential.len() {
+            let half_dt: Complex<f64> = dt_64.scale(0.5);
+            self.spatial.clear();
+            self.spatial.extend(potential.iter().map(|&v| c128exp(
+                Complex {real: 0.0, imag: -1.0} * v.into() * half_dt)));
+        }
+        self.dt = Some((dt.real, dt.imag));
+    }
+
+    /* As Propagators::set_filter. */
+    pub fn set_filter(&mut self, filter: Option<SpectralFilter>) {
+        if filter != self.filter {
+            self.filter = filter;
+            self.dt = None;
+        }
+    }
+
+    /* Half a step of the nonlinear term, if set, as
+    split_op::propagate_nonlinear. */
+    fn propagate_nonlinear(&mut self, dt: Complex<f32>) {
+        if let Some(nonlinear) = self.nonlinear {
+            let g_dt: Complex<f64> = Complex {
+                real: 0.0, imag: -0.5*(nonlinear.square as f64)}*dt.into();
+            for z in self.psi.iter_mut() {
+                *z = *z*c128exp(g_dt.scale(z.length_squared()));
+            }
+        }
+    }
+
+    /* One step of psi, as Stepper::step. */
+    fn step(&mut self, psi: &mut [Complex<f32>], dt: Complex<f32>) -> [f64; 5] {
+        let changed: bool = self.written.len() != psi.len()
+            || self.written.iter().zip(psi.iter()).any(
+                |(a, b)| a.real.to_bits() != b.real.to_bits()
+                    || a.imag.to_bits() != b.imag.to_bits());
+        if changed {
+            self.psi.clear();
+            self.psi.extend(psi.iter().map(|&z| -> Complex<f64> {z.into()}));
+        }
+        let norm_0: f64 = norm_squared_f64(&self.psi);
+        multiply_f64(&mut self.psi, &self.spatial);
+        self.propagate_nonlinear(dt);
+        let norm_1: f64 = norm_squared_f64(&self.psi);
+        f64_square_fft2_in_place(&mut self.psi, false);
+        multiply_f64(&mut self.psi, &self.kinetic);
+        f64_square_fft2_in_place(&mut self.psi, true);
+        let norm_2: f64 = norm_squared_f64(&self.psi);
+        dampen_f64(&mut self.psi, dt.real as f64);
+        let norm_3: f64 = norm_squared_f64(&self.psi);
+        multiply_f64(&mut self.psi, &self.spatial);
+        self.propagate_nonlinear(dt);
+        for (z, w) in psi.iter_mut().zip(self.psi.iter()) {
+            *z = (*w).into();
+        }
+        self.written.clear();
+        self.written.extend_from_slice(psi);
+        return [norm_0, norm_1, norm_2, norm_3, norm_squared_f64(&self.psi)];
+    }
+}
+
+fn multiply_f64(a: &mut [Complex<f64>], b: &[Complex<f64>]) {
+    zip_parts_mut(a, b, |a, b| {
+        for (u, &v) in a.iter_mut().zip(b.iter()) {
+            *u = *u*v;
+        }
+    });
+}
+
+fn norm_squared_f64(psi: &[Complex<f64>]) -> f64 {
+    let [sum] = sum_parts(psi.len(), psi.len(), |range| {
+        let mut sum: CompensatedSum = CompensatedSum::default();
+        for i in range {
+            sum += psi[i].length_squared();
+        }
+        return [sum.value()];
+    });
+    return sum;
+}
+
+/* dampen for the whole grid in doubles. */
+fn dampen_f64(psi: &mut [Complex<f64>], dt: f64) {
+    let mut damp_factors: Vec<f64> = vec![1.0; N];
+    for i in 0..N {
+        let y: f64 = (i as f64)/(N as f64);
+        if y <= 0.9 {
+            continue;
+        }
+        for j in 0..N {
+            let p: Complex<f64> = psi[i*N + j];
+            let (jx, jy): (f64, f64) = if (p*p.conj()).real > 1e-30 {
+                let ddx_psi: Complex<f64> = psi[i*N + (j + 1) % N] - p;
+                let ddy_psi: Complex<f64> = psi[((i + 1) % N)*N + j] - p;
+                let val: f64 = 0.05 - f64::abs(y - 0.95);
+                (val*(p*ddx_psi).imag, val*(p*ddy_psi).imag)
+            } else {
+                (0.0, 0.0)
+            };
+            damp_factors[j] = f64::exp(-0.35*dt*f64::sqrt(jx*jx + jy*jy));
+        }
+        for j in 0..N {
+            psi[i*N + j] = psi[i*N + j].scale(damp_factors[j]);
+        }
+    }
+}
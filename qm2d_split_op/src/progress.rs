@@ -0,0 +1,80 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::observables::*;
+use std::io::Write;
+
+/* A line on standard error, redrawn in place a few times a second,
+showing how far a run has got: a bar, the step, the step rate, the
+simulated time, the time left at the rate so far, the norm relative to
+the start and the energy, and the number of output files waiting to be
+written. It is drawn with a carriage return, as indicatif would, without
+depending on it, so it is meant for a terminal and is off by default
+when standard error is not one. */
+pub struct Progress {
+    total: usize, // steps in the run
+    start: std::time::Instant,
+    last: Option<std::time::Instant>,
+    interval: std::time::Duration,
+    initial_norm: Option<f64>,
+    scratch: Vec<Complex<f32>>,
+}
+
+const BAR_WIDTH: usize = 24;
+
+/* A duration as 1h02m03s, 2m03s or 3s. */
+fn format_duration(seconds: f64) -> String {
+    if !seconds.is_finite() {
+        return "?".to_string();
+    }
+    let seconds: u64 = seconds.round() as u64;
+    let (h, m, s): (u64, u64, u64) = (seconds/3600, (seconds/60)%60,
+                                      seconds%60);
+    return if h > 0 {format!("{}h{:02}m{:02}s", h, m, s)}
+        else if m > 0 {format!("{}m{:02}s", m, s)}
+        else {format!("{}s", s)};
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Progress {
+        return Progress {total, start: std::time::Instant::now(), last: None,
+                         interval: std::time::Duration::from_millis(250),
+                         initial_norm: None,
+                         scratch: vec![Complex {real: 0.0, imag: 0.0}; N*N]};
+    }
+
+    /* Whether the line is due to be drawn again. */
+    pub fn is_due(&self) -> bool {
+        return self.last.is_none_or(|last| last.elapsed() >= self.interval);
+    }
+
+    /* Draw the line after the given number of steps, at time t. */
+    pub fn show(&mut self, steps: usize, t: f64, psi: &[Complex<f32>],
+                potential: &[Complex<f32>], backlog: usize) {
+        self.last = Some(std::time::Instant::now());
+        let e: Expectations = compute_expectations(
+            psi, potential, 0.0, &mut self.scratch);
+        let initial_norm: f64 = *self.initial_norm.get_or_insert(e.norm);
+        let elapsed: f64 = self.start.elapsed().as_secs_f64();
+        let rate: f64 = (steps as f64)/elapsed;
+        let left: f64 = (self.total.saturating_sub(steps) as f64)/rate;
+        let filled: usize = BAR_WIDTH*steps.min(self.total)
+            /usize::max(1, self.total);
+        let line: String = format!(
+            "[{}{}] {}/{} {:.1} steps/s t = {:.1} ETA {} norm {:.6} \
+             E {:.6} queue {}",
+            "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), steps,
+            self.total, rate, t, format_duration(left),
+            e.norm/initial_norm, e.total, backlog);
+        let mut out = std::io::stderr().lock();
+        let _ = write!(out, "\r{}\x1b[K", line);
+        let _ = out.flush();
+    }
+
+    /* End the line, with the time the run took. */
+    pub fn finish(&mut self, steps: usize) {
+        let elapsed: f64 = self.start.elapsed().as_secs_f64();
+        let mut out = std::io::stderr().lock();
+        let _ = writeln!(out, "\r{} steps in {}\x1b[K", steps,
+                         format_duration(elapsed));
+    }
+}
@@ -0,0 +1,369 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::random::*;
+use crate::resample::*;
+
+/* Properties of the transforms that any implementation must have,
+checked on random inputs by `cargo test`, so that rewriting a kernel
+(for SIMD, threads or another backend) is guarded by more than the
+closed form scenarios of src/validation.rs:
+
+ - round_trip: the inverse transform undoes the forward one.
+ - parseval: sum |X_k|^2 = n sum |x_j|^2, as the forward transform is
+   not normalized.
+ - linearity: F(a x + b y) = a F(x) + b F(y) for complex a and b.
+ - dst_round_trip: the sine transform dst1_in_place applied twice gives
+   the values back times n/2.
+ - resample_round_trip: refining a grid by resample and coarsening it
+   back gives psi back, as refining only pads its spectrum.
+ - transpose: transposing twice gives the array back exactly, and
+   element (i, j) goes to (j, i), for any n, not only powers of two.
+ - fft2 round trip on the N by N grid of the main loop, and the
+   threaded rows giving exactly the result of a single thread.
+
+Each property is run on a number of cases, each with a size and data
+drawn from a generator seeded by a seed drawn in turn from the seed of
+the run, so that a failure is reproduced by running again with the same
+seed. Data are Gaussian noise, a single spike, a constant or a plane
+wave, times a scale of 1e-3 to 1e3. When a case fails, smaller sizes are
+tried with its seed, and the smallest that still fails is reported, as
+proptest shrinks its inputs; proptest itself would be a dependency this
+crate does not have. */
+pub struct PropertyResult {
+    pub name: &'static str,
+    pub cases: usize, // run, up to and including the first failure
+    pub failure: Option<String>, // the smallest failing case found
+}
+
+impl PropertyResult {
+    pub fn passed(&self) -> bool {
+        return self.failure.is_none();
+    }
+}
+
+/* The seed of a run when none is given. */
+pub const DEFAULT_PROPERTY_SEED: u64 = 0x5eed;
+
+/* Random complex data of the given length. */
+pub fn random_data(rng: &mut Random, length: usize) -> Vec<Complex<f64>> {
+    let scale: f64 = f64::powf(10.0, 6.0*rng.uniform() - 3.0);
+    let kind: u64 = rng.next_u64() % 4;
+    let spike: usize = (rng.next_u64() as usize) % usize::max(1, length);
+    let k: f64 = 2.0*std::f64::consts::PI*rng.uniform();
+    let constant: Complex<f64> = Complex {real: rng.normal(),
+                                          imag: rng.normal()};
+    return (0..length).map(|j| {
+        let z: Complex<f64> = match kind {
+            0 => Complex {real: rng.normal(), imag: rng.normal()},
+            1 => Complex {real: if j == spike {1.0} else {0.0}, imag: 0.0},
+            2 => constant,
+            _ => Complex {real: f64::cos(k*(j as f64)),
+                          imag: f64::sin(k*(j as f64))},
+        };
+        z.scale(scale)
+    }).collect();
+}
+
+fn to_f32(v: &[Complex<f64>]) -> Vec<Complex<f32>> {
+    return v.iter().map(|z| (*z).into()).collect();
+}
+
+/* ||a - b||/||b||, or ||a|| if b is zero. */
+fn relative_difference(a: &[Complex<f64>], b: &[Complex<f64>]) -> f64 {
+    let difference: f64 = a.iter().zip(b.iter())
+        .map(|(x, y)| (*x - *y).length_squared()).sum::<f64>();
+    let norm: f64 = b.iter().map(|y| y.length_squared()).sum::<f64>();
+    return if norm > 0.0 {f64::sqrt(difference/norm)}
+        else {f64::sqrt(difference)};
+}
+
+/* Whether a and b hold exactly the same values. */
+fn identical<T: PartialEq>(a: &[Complex<T>], b: &[Complex<T>]) -> bool {
+    return a.len() == b.len() && a.iter().zip(b.iter())
+        .all(|(x, y)| x.real == y.real && x.imag == y.imag);
+}
+
+fn within(what: &str, error: f64, tolerance: f64) -> Result<(), String> {
+    return if error <= tolerance {
+        Ok(())
+    } else {
+        Err(format!("{} is off by {:.3e}, more than {:.1e}", what, error,
+                    tolerance))
+    };
+}
+
+/* A transform of a row of doubles, in single or double precision. */
+#[derive(Clone, Copy)]
+enum Precision {
+    Single,
+    Double,
+}
+
+impl Precision {
+    /* v rounded to the precision, so that the inputs compared are
+    those transformed. */
+    fn round(self, v: Vec<Complex<f64>>) -> Vec<Complex<f64>> {
+        return match self {
+            Precision::Single => to_f32(&v).iter().map(|z| (*z).into())
+                .collect(),
+            Precision::Double => v,
+        };
+    }
+
+    fn fft(self, v: &[Complex<f64>], is_inverse: bool) -> Vec<Complex<f64>> {
+        return match self {
+            Precision::Single => {
+                let mut w: Vec<Complex<f32>> = to_f32(v);
+                base_f32_fft_in_place(&mut w, v.len(), is_inverse);
+                w.iter().map(|z| (*z).into()).collect()
+            },
+            Precision::Double => {
+                let mut w: Vec<Complex<f64>> = v.to_vec();
+                base_f64_fft_in_place(&mut w, v.len(), is_inverse);
+                w
+            },
+        };
+    }
+
+    /* The tolerance of a relative error, a few times the rounding of
+    the log2(n) stages of a transform of n values. */
+    fn tolerance(self, n: usize) -> f64 {
+        let epsilon: f64 = match self {
+            Precision::Single => f32::EPSILON as f64,
+            Precision::Double => f64::EPSILON,
+        };
+        return 8.0*epsilon*(1.0 + (n as f64).log2());
+    }
+}
+
+fn round_trip(precision: Precision, rng: &mut Random, n: usize
+              ) -> Result<(), String> {
+    let x: Vec<Complex<f64>> = precision.round(random_data(rng, n));
+    let x_back: Vec<Complex<f64>> = precision.fft(&precision.fft(&x, false),
+                                                  true);
+    return within("ifft(fft(x))", relative_difference(&x_back, &x),
+                  precision.tolerance(n));
+}
+
+/* sum |z|^2, with Kahan summation, so that its rounding, which grows
+with n for values of equal size, is not taken for the transform's. */
+fn sum_of_squares(v: &[Complex<f64>]) -> f64 {
+    let mut sum: f64 = 0.0;
+    let mut compensation: f64 = 0.0;
+    for z in v.iter() {
+        let y: f64 = z.length_squared() - compensation;
+        let t: f64 = sum + y;
+        compensation = (t - sum) - y;
+        sum = t;
+    }
+    return sum;
+}
+
+fn parseval(precision: Precision, rng: &mut Random, n: usize
+            ) -> Result<(), String> {
+    let x: Vec<Complex<f64>> = precision.round(random_data(rng, n));
+    let energy: f64 = sum_of_squares(&x);
+    let spectrum: f64 = sum_of_squares(&precision.fft(&x, false));
+    let error: f64 = if energy > 0.0 {
+        f64::abs(spectrum/((n as f64)*energy) - 1.0)
+    } else {
+        spectrum
+    };
+    // Both sums of squares double the relative error.
+    return within("sum |X|^2/(n sum |x|^2)", error,
+                  4.0*precision.tolerance(n));
+}
+
+fn linearity(precision: Precision, rng: &mut Random, n: usize
+             ) -> Result<(), String> {
+    let round = |v: Vec<Complex<f64>>| precision.round(v);
+    let x: Vec<Complex<f64>> = round(random_data(rng, n));
+    let y: Vec<Complex<f64>> = round(random_data(rng, n));
+    let a: Complex<f64> = Complex {real: rng.normal(), imag: rng.normal()};
+    let b: Complex<f64> = Complex {real: rng.normal(), imag: rng.normal()};
+    let sum: Vec<Complex<f64>> = round(x.iter().zip(y.iter())
+                                       .map(|(u, v)| a**u + b**v).collect());
+    let fx: Vec<Complex<f64>> = precision.fft(&x, false);
+    let fy: Vec<Complex<f64>> = precision.fft(&y, false);
+    let expected: Vec<Complex<f64>> = fx.iter().zip(fy.iter())
+        .map(|(u, v)| a**u + b**v).collect();
+    // Relative to the size of the terms, as they can cancel.
+    let terms: f64 = f64::sqrt(a.length_squared()*fx.iter()
+                               .map(|z| z.length_squared()).sum::<f64>())
+        + f64::sqrt(b.length_squared()*fy.iter()
+                    .map(|z| z.length_squared()).sum::<f64>());
+    let difference: f64 = f64::sqrt(
+        precision.fft(&sum, false).iter().zip(expected.iter())
+            .map(|(u, v)| (*u - *v).length_squared()).sum::<f64>());
+    return within("F(ax + by) - aF(x) - bF(y)",
+                  if terms > 0.0 {difference/terms} else {difference},
+                  2.0*precision.tolerance(n));
+}
+
+fn dst_round_trip(rng: &mut Random, n: usize) -> Result<(), String> {
+    let mut x: Vec<f64> = random_data(rng, n).iter().map(|z| z.real)
+        .collect();
+    x[0] = 0.0;
+    let mut y: Vec<f64> = x.clone();
+    dst1_in_place(&mut y, n);
+    dst1_in_place(&mut y, n);
+    let back: Vec<Complex<f64>> = y.iter()
+        .map(|v| Complex {real: 2.0*v/(n as f64), imag: 0.0}).collect();
+    let x: Vec<Complex<f64>> = x.iter().map(|v| Complex {real: *v, imag: 0.0})
+        .collect();
+    return within("dst(dst(x))*2/n", relative_difference(&back, &x),
+                  2.0*Precision::Double.tolerance(2*n));
+}
+
+fn resample_round_trip(rng: &mut Random, n: usize) -> Result<(), String> {
+    let x: Vec<Complex<f32>> = to_f32(&random_data(rng, n*n));
+    let factor: usize = 2 << (rng.next_u64() % 2);
+    let back: Vec<Complex<f32>> = resample(&x, n, factor*n)
+        .and_then(|fine| resample(&fine, factor*n, n))?;
+    let back: Vec<Complex<f64>> = back.iter().map(|z| (*z).into()).collect();
+    let x: Vec<Complex<f64>> = x.iter().map(|z| (*z).into()).collect();
+    return within("coarsened refined psi", relative_difference(&back, &x),
+                  4.0*Precision::Single.tolerance(factor*n));
+}
+
+fn transpose(rng: &mut Random, n: usize) -> Result<(), String> {
+    let x: Vec<Complex<f64>> = random_data(rng, n*n);
+    let mut y: Vec<Complex<f64>> = x.clone();
+    square_transpose_in_place(&mut y, n);
+    for i in 0..n {
+        for j in 0..n {
+            if !identical(&y[j*n + i..j*n + i + 1], &x[i*n + j..i*n + j + 1]) {
+                return Err(format!("element ({}, {}) is not at ({}, {})",
+                                   i, j, j, i));
+            }
+        }
+    }
+    square_transpose_in_place(&mut y, n);
+    return if identical(&y, &x) {
+        Ok(())
+    } else {
+        Err("transposing twice does not give the array back".to_string())
+    };
+}
+
+fn fft2_round_trip(rng: &mut Random, _n: usize) -> Result<(), String> {
+    let x: Vec<Complex<f32>> = to_f32(&random_data(rng, N*N));
+    let mut threaded: Vec<Complex<f32>> = x.clone();
+    let mut single: Vec<Complex<f32>> = x.clone();
+    fft2_in_place(&mut threaded, true);
+    fft2_in_place(&mut single, false);
+    if !identical(&threaded, &single) {
+        return Err("the threaded fft2 differs from a single thread's"
+                   .to_string());
+    }
+    ifft2_in_place(&mut threaded, true);
+    let back: Vec<Complex<f64>> = threaded.iter().map(|z| (*z).into())
+        .collect();
+    let x: Vec<Complex<f64>> = x.iter().map(|z| (*z).into()).collect();
+    return within("ifft2(fft2(x))", relative_difference(&back, &x),
+                  2.0*Precision::Single.tolerance(N));
+}
+
+type Check = fn(&mut Random, usize) -> Result<(), String>;
+
+/* Run the property on cases cases, with sizes drawn by size from the
+seeds drawn from seed. */
+fn check(name: &'static str, seed: u64, cases: usize,
+         size: impl Fn(&mut Random) -> usize, smaller: impl Fn(usize) -> usize,
+         property: impl Fn(&mut Random, usize) -> Result<(), String>
+         ) -> PropertyResult {
+    let mut seeds: Random = Random::new(seed);
+    for case in 0..cases {
+        let case_seed: u64 = seeds.next_u64();
+        let mut n: usize = size(&mut Random::new(case_seed));
+        let mut failure: Result<(), String>
+            = property(&mut Random::new(case_seed), n);
+        if let Err(message) = failure {
+            // Shrink: the case is run again with its seed at smaller
+            // sizes, for as long as it still fails.
+            let mut message: String = message;
+            loop {
+                let m: usize = smaller(n);
+                if m == n {
+                    break;
+                }
+                failure = property(&mut Random::new(case_seed), m);
+                match failure {
+                    Err(e) => {
+                        n = m;
+                        message = e;
+                    },
+                    Ok(()) => break,
+                }
+            }
+            return PropertyResult {
+                name, cases: case + 1,
+                failure: Some(format!("case seed {:#x}, size {}: {}",
+                                      case_seed, n, message))};
+        }
+    }
+    return PropertyResult {name, cases, failure: None};
+}
+
+/* A power of two from 1 to 2^max_log. */
+fn power_of_two(max_log: u64) -> impl Fn(&mut Random) -> usize {
+    return move |rng: &mut Random| 1 << (rng.next_u64() % (max_log + 1));
+}
+
+/* Run the properties whose names contain filter, or all of them, with
+cases random cases each (fewer for the transforms of the N by N grid)
+from the given seed. */
+pub fn run_properties(filter: Option<&str>, seed: u64, cases: usize
+                      ) -> Vec<PropertyResult> {
+    let mut results: Vec<PropertyResult> = Vec::new();
+    let wanted = |name: &str| filter.is_none_or(|f| name.contains(f));
+    let halve = |n: usize| usize::max(1, n/2);
+    let one_dimensional: [(&'static str, Check); 6] = [
+        ("round_trip_f32", |rng, n| round_trip(Precision::Single, rng, n)),
+        ("round_trip_f64", |rng, n| round_trip(Precision::Double, rng, n)),
+        ("parseval_f32", |rng, n| parseval(Precision::Single, rng, n)),
+        ("parseval_f64", |rng, n| parseval(Precision::Double, rng, n)),
+        ("linearity_f32", |rng, n| linearity(Precision::Single, rng, n)),
+        ("linearity_f64", |rng, n| linearity(Precision::Double, rng, n)),
+    ];
+    for (name, property) in one_dimensional.iter() {
+        if wanted(name) {
+            results.push(check(name, seed, cases, power_of_two(12), halve,
+                               property));
+        }
+    }
+    if wanted("dst_round_trip") {
+        results.push(check("dst_round_trip", seed, cases,
+                           |rng| 2 << (rng.next_u64() % 12), halve,
+                           dst_round_trip));
+    }
+    if wanted("resample_round_trip") {
+        results.push(check("resample_round_trip", seed, cases,
+                           power_of_two(6), halve, resample_round_trip));
+    }
+    if wanted("transpose") {
+        results.push(check("transpose", seed, cases,
+                           |rng| 1 + (rng.next_u64() % 96) as usize,
+                           |n| n - usize::from(n > 1), transpose));
+    }
+    if wanted("fft2_round_trip") {
+        results.push(check("fft2_round_trip", seed, usize::min(cases, 3),
+                           |_| N, |n| n, fft2_round_trip));
+    }
+    return results;
+}
+
+/* The properties as a table, with the failing case of each that failed. */
+pub fn properties_report(results: &[PropertyResult], seed: u64) -> String {
+    let mut text: String = format!("seed {:#x}\n{:<18} {:>6}  result\n", seed,
+                                   "property", "cases");
+    for r in results.iter() {
+        text += &format!("{:<18} {:>6}  {}\n", r.name, r.cases,
+                         match r.failure.as_ref() {
+                             None => "ok".to_string(),
+                             Some(f) => format!("FAILED: {}", f),
+                         });
+    }
+    return text;
+}
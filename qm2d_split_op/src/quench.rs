@@ -0,0 +1,233 @@
+use crate::complex::*;
+use crate::expression::*;
+use crate::observables::*;
+use crate::split_op::*;
+use std::io::Write;
+
+/* What changes at a quench. */
+pub enum QuenchAction {
+    Potential(Expression), // V is this expression from then on
+    // The real part of the potential is multiplied by the square of the
+    // ratio, which for a harmonic trap about any centre multiplies its
+    // frequency by the ratio.
+    Frequency(f32),
+    Interaction(f32), // the strength g of the nonlinear term from then on
+}
+
+/* A sudden change at time t, made before the first step at or after
+it. */
+pub struct Quench {
+    pub t: f32,
+    pub action: QuenchAction,
+}
+
+impl Quench {
+    /* Parse "<t> V <expression>", "<t> omega <ratio>" or "<t> g <g>". */
+    pub fn parse(s: &str) -> Result<Quench, String> {
+        let s: &str = s.trim();
+        let (t, rest): (&str, &str) = s.split_once(char::is_whitespace)
+            .unwrap_or((s, ""));
+        let t: f32 = t.parse::<f32>().map_err(
+            |_| format!("invalid time '{}' in quench '{}'", t, s))?;
+        let rest: &str = rest.trim_start();
+        let (name, value): (&str, &str) = rest
+            .split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let number = |value: &str| -> Result<f32, String> {
+            return value.trim().parse::<f32>().map_err(
+                |_| format!("invalid number '{}' in quench '{}'",
+                            value.trim(), s));
+        };
+        let action: QuenchAction = match name {
+            "V" => QuenchAction::Potential(Expression::parse(value)?),
+            "omega" => QuenchAction::Frequency(number(value)?),
+            "g" => QuenchAction::Interaction(number(value)?),
+            _ => return Err(format!("expected '<t> V <expression>', \
+                                     '<t> omega <ratio>' or '<t> g <g>', \
+                                     got '{}'", s)),
+        };
+        return Ok(Quench {t, action});
+    }
+}
+
+/* The observables of psi just before and after a quench, and how the
+uncertainties in x and y, which show the breathing of a trapped state,
+and the energy went between it and the next quench or the end of the
+run. */
+pub struct QuenchRecord {
+    pub t: f64,
+    pub g: f32, // after the quench
+    pub before: Expectations,
+    pub after: Expectations,
+    pub samples: usize,
+    pub energy_sum: f64,
+    pub dx_sum: f64, pub dy_sum: f64,
+    pub dx_range: (f64, f64), pub dy_range: (f64, f64),
+}
+
+/* Quenches, given in the config as quenches separated by ';', which
+change the potential or the interaction at once at the times given, so
+that a quench study needs no code of its own: for example
+
+    quenches = "200 omega 2; 600 g 1e-6; 900 V 0"
+
+doubles the frequency of a harmonic trap at t = 200, switches on the
+interaction at t = 600 and lets the state expand freely from t = 900.
+The changes hold until the next quench changes the same thing; a V
+quench replaces the potential of the config or the script, and
+frequency quenches scale whichever potential is in use.
+
+The work done on psi by a quench is the difference of its energies just
+after and before, with psi the same; the state then rings at the
+frequencies the quench has excited, which the uncertainties and energy
+sampled until the next quench summarize. */
+pub struct QuenchSchedule {
+    quenches: Vec<Quench>, // in order of time
+    next: usize, // the first not yet made
+    potential: Option<usize>, // the V quench in force
+    pub scale: f32, // of the real part of the potential
+    pub g: f32,
+    pub records: Vec<QuenchRecord>,
+}
+
+/* What the quenches made before a step changed. */
+#[derive(Default)]
+pub struct QuenchChange {
+    pub potential_changed: bool, // a new expression for V
+    pub ratio: f32, // of the scale after to that before
+    pub interaction_changed: bool,
+}
+
+impl QuenchSchedule {
+    pub fn parse(s: &str) -> Result<QuenchSchedule, String> {
+        let mut quenches: Vec<Quench> = s.split(';')
+            .filter(|q| !q.trim().is_empty()).map(Quench::parse)
+            .collect::<Result<Vec<Quench>, String>>()?;
+        quenches.sort_by(|a, b| a.t.total_cmp(&b.t));
+        return Ok(QuenchSchedule {quenches, next: 0, potential: None,
+                                  scale: 1.0, g: 0.0, records: Vec::new()});
+    }
+
+    /* Whether a quench is due before a step at time t. */
+    pub fn is_due(&self, t: f32) -> bool {
+        return self.quenches.get(self.next).is_some_and(|q| q.t <= t);
+    }
+
+    /* Make the quenches due before a step at time t. */
+    pub fn apply_due(&mut self, t: f32) -> QuenchChange {
+        let mut change = QuenchChange {ratio: 1.0, ..Default::default()};
+        while self.is_due(t) {
+            match self.quenches[self.next].action {
+                QuenchAction::Potential(_) => {
+                    self.potential = Some(self.next);
+                    change.potential_changed = true;
+                },
+                QuenchAction::Frequency(r) => {
+                    self.scale *= r*r;
+                    change.ratio *= r*r;
+                },
+                QuenchAction::Interaction(g) => {
+                    self.g = g;
+                    change.interaction_changed = true;
+                },
+            }
+            self.next += 1;
+        }
+        return change;
+    }
+
+    /* The potential of the last V quench made, if any. */
+    pub fn potential(&self) -> Option<&Expression> {
+        return self.potential.map(|k| match &self.quenches[k].action {
+            QuenchAction::Potential(e) => e,
+            _ => unreachable!(),
+        });
+    }
+
+    /* Scale the real part of a potential just filled in, as the
+    frequency quenches made so far do. */
+    pub fn scale_potential(&self, potential: &mut [Complex<f32>]) {
+        if self.scale != 1.0 {
+            for v in potential.iter_mut() {
+                v.real *= self.scale;
+            }
+        }
+    }
+
+    /* The g set by the last g quench at or before time t, or 0 if there
+    is none, whether the quenches have been made or not. */
+    pub fn g_at(&self, t: f32) -> f32 {
+        let mut g: f32 = 0.0;
+        for q in self.quenches.iter().take_while(|q| q.t <= t) {
+            if let QuenchAction::Interaction(value) = q.action {
+                g = value;
+            }
+        }
+        return g;
+    }
+
+    pub fn nonlinear(&self) -> Option<Nonlinear> {
+        return if self.g != 0.0 {Some(Nonlinear {square: self.g})}
+            else {None};
+    }
+
+    /* Record a quench made at time t, with the observables of psi
+    before and after it. */
+    pub fn record(&mut self, t: f64, before: Expectations,
+                  after: Expectations) {
+        self.records.push(QuenchRecord {
+            t, g: self.g, before, after, samples: 0, energy_sum: 0.0,
+            dx_sum: 0.0, dy_sum: 0.0,
+            dx_range: (f64::INFINITY, f64::NEG_INFINITY),
+            dy_range: (f64::INFINITY, f64::NEG_INFINITY)});
+    }
+
+    /* Add the observables at a step to those since the last quench. */
+    pub fn sample(&mut self, e: &Expectations) {
+        if let Some(r) = self.records.last_mut() {
+            let u: Uncertainties = Uncertainties::from_expectations(e);
+            r.samples += 1;
+            r.energy_sum += e.total;
+            r.dx_sum += u.dx;
+            r.dy_sum += u.dy;
+            r.dx_range = (r.dx_range.0.min(u.dx), r.dx_range.1.max(u.dx));
+            r.dy_range = (r.dy_range.0.min(u.dy), r.dy_range.1.max(u.dy));
+        }
+    }
+
+    /* Write a line for each quench made: its time, g after it, the
+    energies before and after and the work done, the uncertainties
+    before and after, and the mean energy and uncertainties, and half
+    the range of the uncertainties, over the samples until the next
+    (NaN without samples). */
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let mut writer = std::io::BufWriter::new(
+            std::fs::File::create(filename)?);
+        writeln!(writer, "# t g energy_before energy_after work dx_before \
+                          dy_before dx_after dy_after dpx_after dpy_after \
+                          mean_energy mean_dx mean_dy breathing_dx \
+                          breathing_dy samples")?;
+        for r in self.records.iter() {
+            let before: Uncertainties
+                = Uncertainties::from_expectations(&r.before);
+            let after: Uncertainties
+                = Uncertainties::from_expectations(&r.after);
+            let n: f64 = r.samples as f64;
+            let (mean_energy, mean_dx, mean_dy, breathing_dx, breathing_dy)
+                : (f64, f64, f64, f64, f64) = if r.samples > 0 {
+                (r.energy_sum/n, r.dx_sum/n, r.dy_sum/n,
+                 0.5*(r.dx_range.1 - r.dx_range.0),
+                 0.5*(r.dy_range.1 - r.dy_range.0))
+            } else {
+                (f64::NAN, f64::NAN, f64::NAN, f64::NAN, f64::NAN)
+            };
+            writeln!(writer, "{} {:e} {:e} {:e} {:e} {:e} {:e} {:e} {:e} \
+                              {:e} {:e} {:e} {:e} {:e} {:e} {:e} {}",
+                     r.t, r.g, r.before.total, r.after.total,
+                     r.after.total - r.before.total, before.dx, before.dy,
+                     after.dx, after.dy, after.dpx, after.dpy, mean_energy,
+                     mean_dx, mean_dy, breathing_dx, breathing_dy,
+                     r.samples)?;
+        }
+        return writer.flush();
+    }
+}
@@ -0,0 +1,45 @@
+/* Small pseudo-random number generator, so that runs with the same
+seed are reproducible. This is xorshift64*, which is fast and good
+enough for sampling but not for cryptography.
+
+Reference:
+
+Wikipedia - Xorshift
+https://en.wikipedia.org/wiki/Xorshift#xorshift*
+*/
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        // The state must not be zero, and nearby seeds should give
+        // unrelated sequences, so the seed is first mixed with splitmix64.
+        let mut z: u64 = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        return Random {state: if z == 0 {1} else {z}};
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return self.state.wrapping_mul(0x2545f4914f6cdd1d);
+    }
+
+    /* Uniformly distributed in [0, 1). */
+    pub fn uniform(&mut self) -> f64 {
+        return ((self.next_u64() >> 11) as f64)/((1u64 << 53) as f64);
+    }
+
+    /* Normally distributed with mean 0 and standard deviation 1,
+    using the Box-Muller transform. */
+    pub fn normal(&mut self) -> f64 {
+        let u1: f64 = 1.0 - self.uniform();
+        let u2: f64 = self.uniform();
+        return f64::sqrt(-2.0*f64::ln(u1))
+            *f64::cos(2.0*std::f64::consts::PI*u2);
+    }
+}
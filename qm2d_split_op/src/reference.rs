@@ -0,0 +1,398 @@
+use crate::complex::*;
+use crate::fft::*;
+
+/* Reference solutions in extended precision, on small grids, for
+measuring the rounding error of the fast paths with the reference
+subcommand. The same Strang splitting is computed three ways from the
+same start: in single precision and in double precision, with the
+radix-2 transforms the main loop uses, and in double-double arithmetic,
+where each number is the unevaluated sum of two doubles, hi + lo with
+|lo| <= ulp(hi)/2, giving about 32 significant digits. The difference
+between the fast paths and the reference is then their rounding error
+alone, since all three make the same splitting error.
+
+Double-double arithmetic is built on error-free transformations of
+doubles (the sum and product of two doubles as a double and its
+rounding error), with the product's error found by a fused multiply-add.
+Sines and cosines are summed as Taylor series after reducing the angle
+by multiples of 2 pi and halving it, and doubled back. An arbitrary
+precision backend, with the rug crate behind a feature, would need a
+dependency this crate does not have.
+
+References:
+
+D. H. Bailey, Y. Hida, X. S. Li. Algorithms for quad-double precision
+floating point arithmetic. Proceedings of ARITH 15 (2001).
+
+T. J. Dekker. A floating-point technique for extending the available
+precision. Numerische Mathematik 18, 224-242 (1971).
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+/* a + b as a double and the rounding error of the sum. */
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s: f64 = a + b;
+    let bb: f64 = s - a;
+    return (s, (a - (s - bb)) + (b - bb));
+}
+
+/* two_sum for |a| >= |b|. */
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s: f64 = a + b;
+    return (s, b - (s - a));
+}
+
+/* a*b as a double and the rounding error of the product. */
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p: f64 = a*b;
+    return (p, a.mul_add(b, -p));
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(x: f64) -> DoubleDouble {
+        return DoubleDouble {hi: x, lo: 0.0};
+    }
+}
+
+impl std::ops::Add for DoubleDouble {
+    type Output = DoubleDouble;
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (s, e): (f64, f64) = two_sum(self.hi, other.hi);
+        let (t, f): (f64, f64) = two_sum(self.lo, other.lo);
+        let (s, e): (f64, f64) = quick_two_sum(s, e + t);
+        let (hi, lo): (f64, f64) = quick_two_sum(s, e + f);
+        return DoubleDouble {hi, lo};
+    }
+}
+
+impl std::ops::Neg for DoubleDouble {
+    type Output = DoubleDouble;
+    fn neg(self) -> DoubleDouble {
+        return DoubleDouble {hi: -self.hi, lo: -self.lo};
+    }
+}
+
+impl std::ops::Sub for DoubleDouble {
+    type Output = DoubleDouble;
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        return self + (-other);
+    }
+}
+
+impl std::ops::Mul for DoubleDouble {
+    type Output = DoubleDouble;
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (p, e): (f64, f64) = two_prod(self.hi, other.hi);
+        let e: f64 = e + (self.hi*other.lo + self.lo*other.hi);
+        let (hi, lo): (f64, f64) = quick_two_sum(p, e);
+        return DoubleDouble {hi, lo};
+    }
+}
+
+impl DoubleDouble {
+    pub const PI: DoubleDouble = DoubleDouble {
+        hi: std::f64::consts::PI, lo: 1.2246467991473532e-16};
+
+    pub fn to_f64(self) -> f64 {
+        return self.hi + self.lo;
+    }
+
+    /* self/b for a double b. */
+    pub fn div_f64(self, b: f64) -> DoubleDouble {
+        let q1: f64 = self.hi/b;
+        let (p, p_error): (f64, f64) = two_prod(q1, b);
+        let (s, e): (f64, f64) = two_sum(self.hi, -p);
+        let q2: f64 = (s + (e - p_error + self.lo))/b;
+        let (hi, lo): (f64, f64) = quick_two_sum(q1, q2);
+        return DoubleDouble {hi, lo};
+    }
+
+    /* The sine and cosine of self. */
+    pub fn sin_cos(self) -> (DoubleDouble, DoubleDouble) {
+        // Reduce to [-pi, pi] and halve 8 times, so that the series
+        // converge in a few terms, then double back with
+        // sin 2x = 2 sin x cos x and cos 2x = 1 - 2 sin^2 x.
+        let two_pi: DoubleDouble = DoubleDouble::PI + DoubleDouble::PI;
+        let k: f64 = (self.hi/two_pi.hi).round();
+        let x: DoubleDouble = (self - two_pi*DoubleDouble::from(k))
+            .div_f64(256.0);
+        let x2: DoubleDouble = x*x;
+        let mut sin: DoubleDouble = x;
+        let mut cos: DoubleDouble = DoubleDouble::from(1.0);
+        let mut sin_term: DoubleDouble = x;
+        let mut cos_term: DoubleDouble = DoubleDouble::from(1.0);
+        for k in 1..12 {
+            let k: f64 = k as f64;
+            sin_term = -(sin_term*x2).div_f64((2.0*k)*(2.0*k + 1.0));
+            cos_term = -(cos_term*x2).div_f64((2.0*k - 1.0)*(2.0*k));
+            sin = sin + sin_term;
+            cos = cos + cos_term;
+        }
+        for _ in 0..8 {
+            let two_sin: DoubleDouble = sin + sin;
+            (sin, cos) = (two_sin*cos, DoubleDouble::from(1.0) - two_sin*sin);
+        }
+        return (sin, cos);
+    }
+}
+
+/* exp(i angle). */
+fn dd_cis(angle: DoubleDouble) -> Complex<DoubleDouble> {
+    let (sin, cos): (DoubleDouble, DoubleDouble) = angle.sin_cos();
+    return Complex {real: cos, imag: sin};
+}
+
+/* The factors exp(+-2 pi i k/size) for k < size/2 of a transform of
+the given size, the sign being that of base_f64_fft_in_place. */
+pub fn dd_twiddles(size: usize, is_inverse: bool) -> Vec<Complex<DoubleDouble>> {
+    let two_pi: DoubleDouble = DoubleDouble::PI + DoubleDouble::PI;
+    let sgn: f64 = if is_inverse {-1.0} else {1.0};
+    return (0..size/2).map(|k| dd_cis(
+        (two_pi*DoubleDouble::from(sgn*(k as f64))).div_f64(size as f64)))
+        .collect();
+}
+
+/* base_f64_fft_in_place in double-double arithmetic, with the factors
+of dd_twiddles(size, is_inverse). */
+pub fn dd_fft_in_place(array: &mut [Complex<DoubleDouble>], size: usize,
+                       twiddles: &[Complex<DoubleDouble>], is_inverse: bool) {
+    reverse_bit_sort(array, size);
+    let mut block_size: usize = 2;
+    while block_size <= size {
+        let mut j: usize = 0;
+        while j < size {
+            for i in 0..block_size/2 {
+                let e: Complex<DoubleDouble> = twiddles[i*(size/block_size)];
+                let even: Complex<DoubleDouble> = array[j + i];
+                let odd: Complex<DoubleDouble> = array[j + i + block_size/2]*e;
+                array[j + i] = even + odd;
+                array[j + i + block_size/2] = even - odd;
+            }
+            j += block_size;
+        }
+        block_size *= 2;
+    }
+    if is_inverse {
+        // 1/size is exact for a power of two.
+        let s: DoubleDouble = DoubleDouble::from(1.0/(size as f64));
+        for z in array.iter_mut().take(size) {
+            *z = z.scale(s);
+        }
+    }
+}
+
+/* One Strang step exp(-iV dt/2) exp(-ip^2 dt/2) exp(-iV dt/2) of psi
+on an n by n grid, with the factors given, and the 2D transform done as
+rows, a transpose, rows and a transpose back by fft, which transforms a
+row. */
+fn strang_step<T>(psi: &mut [Complex<T>], n: usize, spatial: &[Complex<T>],
+                  kinetic: &[Complex<T>],
+                  fft: impl Fn(&mut [Complex<T>], bool))
+    where T: std::ops::Add<Output=T> + std::ops::Sub<Output=T>
+        + std::ops::Mul<Output=T> + Copy {
+    let fft2 = |psi: &mut [Complex<T>], is_inverse: bool| {
+        for _ in 0..2 {
+            for row in psi.chunks_mut(n) {
+                fft(row, is_inverse);
+            }
+            square_transpose_in_place(psi, n);
+        }
+    };
+    for (z, u) in psi.iter_mut().zip(spatial.iter()) {
+        *z = *z**u;
+    }
+    fft2(psi, false);
+    for (z, u) in psi.iter_mut().zip(kinetic.iter()) {
+        *z = *z**u;
+    }
+    fft2(psi, true);
+    for (z, u) in psi.iter_mut().zip(spatial.iter()) {
+        *z = *z**u;
+    }
+}
+
+/* The frequencies of the k-th element of the 2D transform of an n by n
+grid, whose momenta are 2 pi/n times them, as in init_momentum_squared
+for the N by N grid. */
+fn frequencies(k: usize, n: usize) -> (i64, i64) {
+    return (fft_frequency(k/n, n) as i64, fft_frequency(k % n, n) as i64);
+}
+
+/* The same splitting of a real potential on a small grid, in single
+precision as the main loop steps it, in double precision, and in
+double-double precision for reference, each step starting from
+the state left by the last. */
+pub struct ReferenceRun {
+    pub size: usize, // of the grid, n by n
+    pub dt: f64,
+    pub steps: usize,
+    pub initial: Vec<Complex<f32>>,
+    pub potential: Vec<f32>,
+    pub single: Vec<Complex<f32>>,
+    pub double: Vec<Complex<f64>>,
+    pub reference: Vec<Complex<DoubleDouble>>,
+}
+
+impl ReferenceRun {
+    /* Step the initial state in the potential, both on an n by n grid
+    with n a power of two. */
+    pub fn new(initial: &[Complex<f32>], potential: &[f32], size: usize,
+               dt: f64, steps: usize) -> Result<ReferenceRun, String> {
+        if !size.is_power_of_two() || size < 2 {
+            return Err(format!(
+                "the size of a reference grid must be a power of two, not {}",
+                size));
+        }
+        if initial.len() != size*size || potential.len() != size*size {
+            return Err(format!("psi and the potential must have {} by {} \
+                                values", size, size));
+        }
+        let n: usize = size;
+        let dp: f64 = 2.0*std::f64::consts::PI/(n as f64);
+        // Single precision, with the factors computed as
+        // Propagators::update does.
+        let dt32: f32 = dt as f32;
+        let spatial32: Vec<Complex<f32>> = potential.iter().map(
+            |v| c64exp(Complex {real: 0.0, imag: -0.5*v*dt32})).collect();
+        let kinetic32: Vec<Complex<f32>> = (0..n*n).map(|k| {
+            let (i, j): (i64, i64) = frequencies(k, n);
+            let (px, py): (f32, f32) = ((dp as f32)*(i as f32),
+                                        (dp as f32)*(j as f32));
+            c64exp(Complex {real: 0.0, imag: -0.5*(px*px + py*py)*dt32})
+        }).collect();
+        let mut single: Vec<Complex<f32>> = initial.to_vec();
+        // Double precision.
+        let spatial64: Vec<Complex<f64>> = potential.iter().map(
+            |v| c128exp(Complex {real: 0.0, imag: -0.5*(*v as f64)*dt}))
+            .collect();
+        let kinetic64: Vec<Complex<f64>> = (0..n*n).map(|k| {
+            let (i, j): (i64, i64) = frequencies(k, n);
+            c128exp(Complex {real: 0.0,
+                             imag: -0.5*dp*dp*((i*i + j*j) as f64)*dt})
+        }).collect();
+        let mut double: Vec<Complex<f64>> = initial.iter()
+            .map(|z| (*z).into()).collect();
+        // Double-double precision.
+        let dd_dt: DoubleDouble = DoubleDouble::from(dt);
+        let spatial_dd: Vec<Complex<DoubleDouble>> = potential.iter().map(
+            |v| dd_cis(-(DoubleDouble::from(*v as f64)*dd_dt).div_f64(2.0)))
+            .collect();
+        let two_pi: DoubleDouble = DoubleDouble::PI + DoubleDouble::PI;
+        let dd_dp: DoubleDouble = two_pi.div_f64(n as f64);
+        let kinetic_dd: Vec<Complex<DoubleDouble>> = (0..n*n).map(|k| {
+            let (i, j): (i64, i64) = frequencies(k, n);
+            dd_cis(-(dd_dp*dd_dp*DoubleDouble::from((i*i + j*j) as f64)
+                     *dd_dt).div_f64(2.0))
+        }).collect();
+        let forward: Vec<Complex<DoubleDouble>> = dd_twiddles(n, false);
+        let inverse: Vec<Complex<DoubleDouble>> = dd_twiddles(n, true);
+        let mut reference: Vec<Complex<DoubleDouble>> = initial.iter()
+            .map(|z| Complex {real: DoubleDouble::from(z.real as f64),
+                              imag: DoubleDouble::from(z.imag as f64)})
+            .collect();
+        for _ in 0..steps {
+            strang_step(&mut single, n, &spatial32, &kinetic32,
+                        |row, is_inverse| base_f32_fft_in_place(
+                            row, n, is_inverse));
+            strang_step(&mut double, n, &spatial64, &kinetic64,
+                        |row, is_inverse| base_f64_fft_in_place(
+                            row, n, is_inverse));
+            strang_step(&mut reference, n, &spatial_dd, &kinetic_dd,
+                        |row, is_inverse| dd_fft_in_place(
+                            row, n, if is_inverse {&inverse} else {&forward},
+                            is_inverse));
+        }
+        return Ok(ReferenceRun {size, dt, steps, initial: initial.to_vec(),
+                                potential: potential.to_vec(), single, double,
+                                reference});
+    }
+
+    /* The reference rounded to doubles. */
+    pub fn reference_f64(&self) -> Vec<Complex<f64>> {
+        return self.reference.iter().map(
+            |z| Complex {real: z.real.to_f64(), imag: z.imag.to_f64()})
+            .collect();
+    }
+
+    /* ||psi - reference||/||reference|| for psi given as doubles. */
+    fn error_of(&self, psi: impl Iterator<Item=Complex<f64>>) -> f64 {
+        let mut difference: f64 = 0.0;
+        let mut norm: f64 = 0.0;
+        for (z, w) in psi.zip(self.reference.iter()) {
+            let d: Complex<DoubleDouble> = Complex {
+                real: DoubleDouble::from(z.real), imag: DoubleDouble::from(z.imag)
+            } - *w;
+            difference += d.real.to_f64().powi(2) + d.imag.to_f64().powi(2);
+            norm += w.real.to_f64().powi(2) + w.imag.to_f64().powi(2);
+        }
+        return f64::sqrt(difference/norm);
+    }
+
+    /* The relative error of the single precision steps. */
+    pub fn single_error(&self) -> f64 {
+        return self.error_of(self.single.iter().map(|z| (*z).into()));
+    }
+
+    /* The relative error of the double precision steps. */
+    pub fn double_error(&self) -> f64 {
+        return self.error_of(self.double.iter().copied());
+    }
+
+    /* |<psi|psi>/<psi_0|psi_0> - 1| of the reference, which is only
+    its rounding, as each step keeps the norm. */
+    pub fn reference_norm_drift(&self) -> f64 {
+        let mut norm: DoubleDouble = DoubleDouble::from(0.0);
+        for z in self.reference.iter() {
+            norm = norm + z.real*z.real + z.imag*z.imag;
+        }
+        let mut norm0: DoubleDouble = DoubleDouble::from(0.0);
+        for z in self.initial.iter() {
+            let z: Complex<f64> = (*z).into();
+            norm0 = norm0 + DoubleDouble::from(z.real)*DoubleDouble::from(z.real)
+                + DoubleDouble::from(z.imag)*DoubleDouble::from(z.imag);
+        }
+        return f64::abs((norm - norm0).to_f64()/norm0.to_f64());
+    }
+
+    /* The errors as a table. */
+    pub fn report(&self) -> String {
+        return format!(
+            "{} by {} grid, {} steps of dt = {}\n\
+             {:<10} {:>14}\n\
+             {:<10} {:>14.3e}\n\
+             {:<10} {:>14.3e}\n\
+             reference norm drift {:.3e}\n",
+            self.size, self.size, self.steps, self.dt, "steps", "error",
+            "single", self.single_error(), "double", self.double_error(),
+            self.reference_norm_drift());
+    }
+}
+
+/* A Gaussian of the width of the ground state, displaced by a quarter
+of the way to the edge, in the harmonic potential with a period of n/2
+centred on an n by n grid: a state which is not an eigenstate, and
+moves through both the potential and the kinetic steps. */
+pub fn harmonic_reference_scenario(n: usize) -> (Vec<Complex<f32>>, Vec<f32>) {
+    let period: f64 = (n as f64)/2.0;
+    let w: f64 = 2.0*std::f64::consts::PI/period;
+    let centre: f64 = (n/2) as f64;
+    let sigma: f64 = f64::sqrt(0.5/w);
+    let x0: f64 = centre + (n as f64)/8.0;
+    let mut psi: Vec<Complex<f32>> = Vec::with_capacity(n*n);
+    let mut potential: Vec<f32> = Vec::with_capacity(n*n);
+    for i in 0..n {
+        for j in 0..n {
+            let (x, y): (f64, f64) = (j as f64, i as f64);
+            let r2: f64 = (x - x0).powi(2) + (y - centre).powi(2);
+            psi.push(Complex {real: f64::exp(-r2/(4.0*sigma*sigma)) as f32,
+                              imag: 0.0});
+            potential.push((0.5*w*w*((x - centre).powi(2)
+                                     + (y - centre).powi(2))) as f32);
+        }
+    }
+    return (psi, potential);
+}
@@ -0,0 +1,120 @@
+use crate::complex::*;
+use crate::fft::*;
+use crate::norm::*;
+use crate::snapshot::*;
+
+/* Resampling of wave functions between grids of different sizes that
+cover the same box, so that a state found on a coarse grid (relaxed in
+imaginary time, say) can be refined to go on at full resolution, or a
+state coarsened for a quick look. The spectrum of psi is padded with
+zeros to refine it, which interpolates it with the band limited
+function the grid represents, or truncated to its lowest frequencies to
+coarsen it. Along each axis the Nyquist frequency of the smaller grid,
+which stands for both +n/2 and -n/2, is split evenly between them when
+padding and the two are added when truncating, so that a real psi stays
+real.
+
+In units of the grid spacing, lengths on the new grid are to/from
+times those on the old. The norm sum |psi|^2, with the spacing as the
+unit, is kept: the values are scaled by about from/to, and psi is then
+normalized to the norm it had, which also puts back what truncating
+the frequencies of a coarsened psi took away. */
+pub fn resample(psi: &[Complex<f32>], from: usize, to: usize
+                ) -> Result<Vec<Complex<f32>>, String> {
+    let mut out: Vec<Complex<f32>> = interpolate(psi, from, to)?;
+    normalize(&mut out, norm_squared(psi));
+    return Ok(out);
+}
+
+/* The values on the from by from grid interpolated to the to by to
+grid, or low pass filtered and sampled on it, keeping their size. */
+pub fn interpolate(values: &[Complex<f32>], from: usize, to: usize
+                   ) -> Result<Vec<Complex<f32>>, String> {
+    if !from.is_power_of_two() || !to.is_power_of_two() {
+        return Err(format!("grids can only be resampled between sizes that \
+                            are powers of two, not from {} to {}", from, to));
+    }
+    if values.len() != from*from {
+        return Err(format!("{} values are not a {} by {} grid", values.len(),
+                           from, from));
+    }
+    let a: Vec<Complex<f64>> = values.iter().map(|z| (*z).into()).collect();
+    // Each axis in turn: the rows, which a transpose makes of the
+    // columns, and a transpose back.
+    let a: Vec<Complex<f64>> = transpose(&resample_rows(&a, from, to), from,
+                                         to);
+    let a: Vec<Complex<f64>> = transpose(&resample_rows(&a, from, to), to,
+                                         to);
+    return Ok(a.iter().map(|z| (*z).into()).collect());
+}
+
+/* Resample each of the rows of length from of a to length to. */
+fn resample_rows(a: &[Complex<f64>], from: usize, to: usize
+                 ) -> Vec<Complex<f64>> {
+    let zero: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    let m: usize = usize::min(from, to);
+    let mut out: Vec<Complex<f64>> = Vec::with_capacity(a.len()/from*to);
+    let mut spectrum: Vec<Complex<f64>> = vec![zero; from];
+    let mut resampled: Vec<Complex<f64>> = vec![zero; to];
+    for row in a.chunks(from) {
+        spectrum.copy_from_slice(row);
+        base_f64_fft_in_place(&mut spectrum, from, false);
+        resampled.fill(zero);
+        // The frequencies below the Nyquist frequency of the smaller
+        // grid, which both grids have.
+        for k in 0..m.div_ceil(2) {
+            resampled[k] = spectrum[k];
+            if k > 0 {
+                resampled[to - k] = spectrum[from - k];
+            }
+        }
+        if m > 1 && m.is_multiple_of(2) {
+            let k: usize = m/2;
+            if to > from {
+                resampled[k] = spectrum[k].scale(0.5);
+                resampled[to - k] = spectrum[k].scale(0.5);
+            } else if to < from {
+                resampled[k] = spectrum[k] + spectrum[from - k];
+            } else {
+                resampled[k] = spectrum[k];
+            }
+        }
+        base_f64_fft_in_place(&mut resampled, to, true);
+        // The inverse is normalized for to values rather than from.
+        let scale: f64 = (to as f64)/(from as f64);
+        out.extend(resampled.iter().map(|z| z.scale(scale)));
+    }
+    return out;
+}
+
+/* The transpose of a, which has rows rows of cols values. */
+fn transpose(a: &[Complex<f64>], rows: usize, cols: usize
+             ) -> Vec<Complex<f64>> {
+    let mut out: Vec<Complex<f64>> = Vec::with_capacity(a.len());
+    for j in 0..cols {
+        for i in 0..rows {
+            out.push(a[i*cols + j]);
+        }
+    }
+    return out;
+}
+
+/* The snapshot with psi resampled to a size by size grid, and the
+potential too if with_potential, or else left out, so that a run
+resumed from it uses the potential of its settings at the new size, as
+a potential with sharp walls would ring when resampled. */
+pub fn resample_snapshot(snapshot: &Snapshot, size: usize,
+                         with_potential: bool) -> Result<Snapshot, String> {
+    if snapshot.width != snapshot.height {
+        return Err(format!("only square grids can be resampled, not {}x{}",
+                           snapshot.width, snapshot.height));
+    }
+    let from: usize = snapshot.width;
+    let psi: Vec<Complex<f32>> = resample(&snapshot.psi, from, size)?;
+    let potential: Option<Vec<Complex<f32>>> = match &snapshot.potential {
+        Some(v) if with_potential => Some(interpolate(v, from, size)?),
+        _ => None,
+    };
+    return Ok(Snapshot {width: size, height: size, step: snapshot.step,
+                        t: snapshot.t, dt: snapshot.dt, psi, potential});
+}
@@ -0,0 +1,93 @@
+use crate::complex::*;
+use crate::norm::*;
+use crate::precision::*;
+
+/* Time reversal and unitarity checks of the ways psi can be stepped,
+for the reversal subcommand. psi is stepped forward by dt a number of
+times and back by -dt as many, which in exact arithmetic returns it to
+where it started, since each step of the splitting is undone by the
+same step with -dt; the difference left, relative to the norm, measures
+the rounding error and any step that is not its own inverse, as a bug
+in the order of the parts would make it. Each step of a real potential
+should also keep the norm, and the largest and mean relative change in
+the norm over a step are reported.
+
+The absorbing layer near the top edge is not undone by stepping back,
+and an imaginary potential changes the norm on purpose, so the checks
+are only meaningful for a state away from the layer in a real
+potential. */
+pub struct ReversalCheck {
+    pub name: &'static str, // how psi was stepped
+    pub steps: usize, // forward, and as many back
+    pub return_error: f64, // ||psi_final - psi_0||/||psi_0||
+    pub max_step_drift: f64, // the largest |norm after/norm before - 1|
+    pub mean_step_drift: f64,
+}
+
+impl ReversalCheck {
+    /* Step psi0 steps times forward and back with step, which steps
+    psi by the given dt and returns its norm before and after. */
+    pub fn run(name: &'static str, psi0: &[Complex<f32>], steps: usize,
+               dt: Complex<f32>,
+               mut step: impl FnMut(&mut [Complex<f32>], Complex<f32>)
+               -> (f64, f64)) -> ReversalCheck {
+        let mut psi: Vec<Complex<f32>> = psi0.to_vec();
+        let mut drifts: Vec<f64> = Vec::with_capacity(2*steps);
+        for k in 0..2*steps {
+            let (before, after): (f64, f64) = step(
+                &mut psi, if k < steps {dt} else {dt.scale(-1.0)});
+            drifts.push(if before > 0.0 {f64::abs(after/before - 1.0)}
+                        else {0.0});
+        }
+        let mut difference: f64 = 0.0;
+        for (z, z0) in psi.iter().zip(psi0.iter()) {
+            let d: Complex<f64> = (*z - *z0).into();
+            difference += d.length_squared();
+        }
+        let norm: f64 = norm_squared(psi0);
+        return ReversalCheck {
+            name, steps,
+            return_error: if norm > 0.0 {f64::sqrt(difference/norm)}
+                else {0.0},
+            max_step_drift: drifts.iter().cloned().fold(0.0, f64::max),
+            mean_step_drift: drifts.iter().sum::<f64>()
+                /usize::max(1, drifts.len()) as f64};
+    }
+}
+
+/* Check every way of stepping: the steppers of the main loop at single
+and double precision. */
+pub fn run_reversal_checks(psi0: &[Complex<f32>],
+                           potential: &[Complex<f32>], p_squared: &[f32],
+                           dt: Complex<f32>, steps: usize
+                           ) -> Vec<ReversalCheck> {
+    let mut checks: Vec<ReversalCheck> = Vec::new();
+    for (name, precision) in [("stepper single", StepPrecision::Single),
+                              ("stepper double", StepPrecision::Double)] {
+        let mut stepper: Stepper = Stepper::new(precision);
+        let mut first: bool = true;
+        checks.push(ReversalCheck::run(name, psi0, steps, dt, |psi, dt| {
+            let norms: [f64; 5] = stepper.step(psi, potential, p_squared, dt,
+                                               std::mem::take(&mut first),
+                                               true);
+            (norms[0], norms[4])
+        }));
+    }
+    return checks;
+}
+
+/* The checks as a table, with whether the return error of each is
+within tolerance. */
+pub fn reversal_report(checks: &[ReversalCheck], tolerance: f64) -> String {
+    let mut text: String = format!(
+        "{:<16} {:>6} {:>14} {:>14} {:>14}  result\n", "stepped by",
+        "steps", "return error", "max drift", "mean drift");
+    for c in checks.iter() {
+        text += &format!("{:<16} {:>6} {:>14.3e} {:>14.3e} {:>14.3e}  {}\n",
+                         c.name, c.steps, c.return_error, c.max_step_drift,
+                         c.mean_step_drift,
+                         if c.return_error <= tolerance {"ok"}
+                         else {"FAILED"});
+    }
+    return text;
+}
@@ -0,0 +1,111 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::field::*;
+use crate::parallel::*;
+
+/* A barrier occupying the band start <= s <= end along the given axis,
+with s in [0, 1], which splits the domain into the region before it
+(where the wave packet starts), the barrier itself and the region
+after it. */
+#[derive(Clone, Copy)]
+pub struct BarrierRegions {
+    pub axis: Axis,
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Side {
+    Before, Inside, After,
+}
+
+impl BarrierRegions {
+    pub fn side(&self, i: usize, j: usize) -> Side {
+        let s: f32 = match self.axis {
+            Axis::X => (j as f32)/(N as f32),
+            Axis::Y => (i as f32)/(N as f32),
+        };
+        if s < self.start {
+            return Side::Before;
+        } else if s > self.end {
+            return Side::After;
+        }
+        return Side::Inside;
+    }
+
+    /* Sums of |psi|^2 before, inside and after the barrier. */
+    pub fn probabilities(&self, psi: &[Complex<f32>]) -> [f64; 3] {
+        let mut p: [CompensatedSum; 3] = [CompensatedSum::default(); 3];
+        for i in 0..N {
+            for j in 0..N {
+                let k: usize = match self.side(i, j) {
+                    Side::Before => 0, Side::Inside => 1, Side::After => 2,
+                };
+                p[k] += psi[i*N + j].length_squared() as f64;
+            }
+        }
+        return p.map(|s| s.value());
+    }
+
+    /* Distribution of the momentum along the barrier axis of the part
+    of psi on the given side (or all of psi if side is None), summed
+    over the momentum in the other direction. Element k is for the
+    momentum fft_momentum(k, N). */
+    pub fn momentum_distribution(&self, psi: &[Complex<f32>],
+                                 side: Option<Side>,
+                                 scratch: &mut [Complex<f32>]) -> Vec<f64> {
+        for i in 0..N {
+            for j in 0..N {
+                let keep: bool = match side {
+                    None => true,
+                    Some(s) => self.side(i, j) == s,
+                };
+                scratch[i*N + j] = if keep {psi[i*N + j]}
+                    else {Complex {real: 0.0, imag: 0.0}};
+            }
+        }
+        fft2_in_place(scratch, true);
+        let mut distribution = vec![CompensatedSum::default(); N];
+        for i in 0..N {
+            for j in 0..N {
+                let k: usize = match self.axis {Axis::X => j, Axis::Y => i};
+                distribution[k] += scratch[i*N + j].length_squared() as f64;
+            }
+        }
+        return distribution.iter().map(|s| s.value()).collect();
+    }
+}
+
+/* Transmission and reflection coefficients for each incident momentum.
+
+The initial distribution is the momentum distribution of the incoming
+wave packet, and the transmitted and reflected ones are of the parts
+of psi after and before the barrier once scattering is over. Since
+momentum is conserved away from the barrier, T(p) is the ratio of the
+transmitted to the incident probability at momentum p, and R(p) the
+ratio of the reflected probability at -p to the incident one at p.
+Momenta for which the incident probability is below threshold times
+its largest value are skipped, since the ratios are just noise there.
+Returns rows of (p, T, R). */
+pub fn transmission_by_momentum(initial: &[f64], transmitted: &[f64],
+                                reflected: &[f64], threshold: f64
+                                ) -> Vec<(f64, f64, f64)> {
+    let n: usize = initial.len();
+    let mut max_val: f64 = 0.0;
+    for &v in initial {
+        max_val = f64::max(max_val, v);
+    }
+    let mut rows = std::vec::Vec::<(f64, f64, f64)>::new();
+    for k in 0..n {
+        if initial[k] <= threshold*max_val || initial[k] == 0.0 {
+            continue;
+        }
+        let k_reflected: usize = (n - k) % n;
+        rows.push((fft_momentum(k, n) as f64,
+                   transmitted[k]/initial[k],
+                   reflected[k_reflected]/initial[k]));
+    }
+    rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+    return rows;
+}
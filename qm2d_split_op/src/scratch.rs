@@ -0,0 +1,45 @@
+use crate::complex::*;
+use std::collections::HashMap;
+
+/* Buffers of complex values lent to transforms for their rows, tiles
+and slabs, so that a program calling transforms of several sizes over
+and over only allocates the first time each size is needed. Buffers are
+kept for each thread, keyed by their length, until release_scratch is
+called on that thread. A buffer lent out is taken out of the arena
+until the borrow ends, so that nested borrows of the same length get
+different buffers. The contents of a lent buffer are whatever was last
+left in it. */
+thread_local! {
+    static ARENA: std::cell::RefCell<HashMap<usize, Vec<Vec<Complex<f32>>>>>
+        = std::cell::RefCell::new(HashMap::new());
+}
+
+/* Call f with a buffer of len values from the arena. */
+pub fn with_scratch<R>(len: usize, f: impl FnOnce(&mut [Complex<f32>]) -> R
+                       ) -> R {
+    let mut buffer: Vec<Complex<f32>> = ARENA.with_borrow_mut(
+        |arena| arena.get_mut(&len).and_then(|free| free.pop()))
+        .unwrap_or_else(|| vec![Complex {real: 0.0, imag: 0.0}; len]);
+    let result: R = f(&mut buffer);
+    ARENA.with_borrow_mut(
+        |arena| arena.entry(len).or_default().push(buffer));
+    return result;
+}
+
+/* with_scratch with two buffers of len values. */
+pub fn with_scratch_pair<R>(len: usize,
+                            f: impl FnOnce(&mut [Complex<f32>],
+                                           &mut [Complex<f32>]) -> R) -> R {
+    return with_scratch(len, |a| with_scratch(len, |b| f(a, b)));
+}
+
+/* Free the buffers of the arena of this thread. */
+pub fn release_scratch() {
+    ARENA.with_borrow_mut(|arena| arena.clear());
+}
+
+/* The number of values held by the arena of this thread. */
+pub fn scratch_len() -> usize {
+    return ARENA.with_borrow(|arena| arena.iter().map(
+        |(len, free)| len*free.len()).sum());
+}
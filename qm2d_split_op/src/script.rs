@@ -0,0 +1,267 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::expression::*;
+use crate::norm::*;
+
+/* Scenarios read at runtime from a script file (script_file), which
+gives the potential, the initial state and what happens as the run goes
+on, in the expressions of expression.rs, for example
+
+    # A packet sent at a barrier that is lowered half way through.
+    V = 2*(y > 0.5)*(y < 0.52)
+    psi_abs = exp(-((x - 0.5)^2 + (y - 0.2)^2)/0.01)
+    psi_phase = 2*pi*60*y
+
+    at t >= 500:
+        V = 0.5*(y > 0.5)*(y < 0.52)
+    every 100: phase += 0.1*sin(2*pi*x)
+    when t > 1200: psi *= 1 - (y > 0.9)
+    at t >= 1400: stop
+
+V replaces the potential of the config, and psi_abs and psi_phase the
+initial state, psi being psi_abs*exp(i*psi_phase). Events start with a
+trigger and a colon, followed by actions on the same line or on the
+indented lines below it:
+
+    at <condition>:   once, at the first step where the condition holds
+    every <n>:        every n steps, starting at step 0
+    when <condition>: at every step where the condition holds
+
+Conditions are expressions of t, nonzero when they hold. The actions
+are
+
+    V = <expression>      replace the potential
+    psi *= <expression>   multiply psi by a real function of x, y and t
+    phase += <expression> multiply psi by exp(i*expression)
+    stop                  end the run
+
+Events run before the step they are due at, in the order they are
+written. An embedded language such as Rhai or Lua would need a crate
+this one does not depend on; this covers the scenarios the presets and
+the config could not, without recompiling. */
+pub struct Script {
+    // The potentials of V = lines, of which the one in use is current.
+    potentials: Vec<Expression>,
+    current: Option<usize>,
+    amplitude: Option<Expression>,
+    phase: Option<Expression>,
+    events: Vec<Event>,
+}
+
+enum Trigger {
+    At(Expression),
+    Every(usize),
+    When(Expression),
+}
+
+enum Action {
+    Potential(usize), // index into Script::potentials
+    Multiply(Expression),
+    Phase(Expression),
+    Stop,
+}
+
+struct Event {
+    trigger: Trigger,
+    actions: Vec<Action>,
+    fired: bool,
+}
+
+/* What the events run before a step did. */
+#[derive(Default)]
+pub struct ScriptOutcome {
+    pub potential_changed: bool,
+    pub stop: bool,
+    // The norm taken out of psi by psi *= actions, so that it is not
+    // mistaken for numerical drift.
+    pub absorbed: f64,
+}
+
+/* The expression after the name and operator starting text, if it
+starts with them. */
+fn assignment<'a>(text: &'a str, name: &str, operator: &str
+                  ) -> Option<&'a str> {
+    return text.strip_prefix(name)?.trim_start().strip_prefix(operator);
+}
+
+fn parse_trigger(text: &str) -> Result<Trigger, String> {
+    let text: &str = text.trim();
+    if let Some(rest) = text.strip_prefix("at ") {
+        return Ok(Trigger::At(Expression::parse(rest)?));
+    }
+    if let Some(rest) = text.strip_prefix("when ") {
+        return Ok(Trigger::When(Expression::parse(rest)?));
+    }
+    if let Some(rest) = text.strip_prefix("every ") {
+        return match rest.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Trigger::Every(n)),
+            _ => Err(format!("every needs a number of steps, not '{}'",
+                             rest.trim())),
+        };
+    }
+    return Err(format!("unknown trigger '{}', expected at, every or when",
+                       text));
+}
+
+impl Script {
+    fn parse_action(&mut self, text: &str) -> Result<Action, String> {
+        let text: &str = text.trim();
+        if text == "stop" {
+            return Ok(Action::Stop);
+        }
+        if let Some(e) = assignment(text, "V", "=") {
+            self.potentials.push(Expression::parse(e)?);
+            return Ok(Action::Potential(self.potentials.len() - 1));
+        }
+        if let Some(e) = assignment(text, "psi", "*=") {
+            return Ok(Action::Multiply(Expression::parse(e)?));
+        }
+        if let Some(e) = assignment(text, "phase", "+=") {
+            return Ok(Action::Phase(Expression::parse(e)?));
+        }
+        return Err(format!("unknown action '{}', expected V =, psi *=, \
+                            phase += or stop", text));
+    }
+
+    pub fn parse(text: &str) -> Result<Script, String> {
+        let mut script = Script {potentials: Vec::new(), current: None,
+                                 amplitude: None, phase: None,
+                                 events: Vec::new()};
+        for (line_number, line) in text.lines().enumerate() {
+            let error = |e: String| format!("line {}: {}", line_number + 1, e);
+            let line: &str = match line.find('#') {
+                Some(end) => &line[..end],
+                None => line,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with(char::is_whitespace) {
+                if script.events.is_empty() {
+                    return Err(error("an indented action needs an event \
+                                      above it".to_string()));
+                }
+                let action: Action = script.parse_action(line)
+                    .map_err(error)?;
+                script.events.last_mut().unwrap().actions.push(action);
+                continue;
+            }
+            // A top level line is either an assignment or the start of an
+            // event, told apart by the trigger keyword.
+            let keyword: &str = line.split_whitespace().next().unwrap_or("");
+            if ["at", "every", "when"].contains(&keyword) {
+                let (trigger, action) = line.split_once(':').ok_or_else(
+                    || error("expected ':' after the trigger".to_string()))?;
+                let mut event = Event {trigger: parse_trigger(trigger)
+                                       .map_err(error)?,
+                                       actions: Vec::new(), fired: false};
+                if !action.trim().is_empty() {
+                    event.actions.push(script.parse_action(action)
+                                       .map_err(error)?);
+                }
+                script.events.push(event);
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(
+                || error(format!("expected 'V =', 'psi_abs =', 'psi_phase =' \
+                                  or an event, not '{}'", line.trim())))?;
+            let expression: Expression = Expression::parse(value)
+                .map_err(error)?;
+            match key.trim() {
+                "V" => {
+                    script.potentials.push(expression);
+                    script.current = Some(script.potentials.len() - 1);
+                },
+                "psi_abs" => script.amplitude = Some(expression),
+                "psi_phase" => script.phase = Some(expression),
+                other => return Err(error(format!("unknown setting '{}'",
+                                                  other))),
+            };
+        }
+        if script.events.iter().any(|e| e.actions.is_empty()) {
+            return Err("an event has no actions".to_string());
+        }
+        return Ok(script);
+    }
+
+    pub fn load(filename: &str) -> std::io::Result<Script> {
+        let text: String = std::fs::read_to_string(filename)?;
+        return Script::parse(&text).map_err(|e| std::io::Error::new(
+            std::io::ErrorKind::InvalidData, format!("{}: {}", filename, e)));
+    }
+
+    /* The potential in use, which replaces the potential of the config
+    if there is one. */
+    pub fn potential(&self) -> Option<&Expression> {
+        return self.current.map(|k| &self.potentials[k]);
+    }
+
+    /* Set psi to the initial state of the script, if it gives one,
+    returning whether it did. A missing psi_abs is taken as 1 and a
+    missing psi_phase as 0. */
+    pub fn init_psi(&self, psi: &mut [Complex<f32>]) -> bool {
+        if self.amplitude.is_none() && self.phase.is_none() {
+            return false;
+        }
+        for i in 0..N {
+            for j in 0..N {
+                let (x, y): (f32, f32) = ((j as f32)/(N as f32),
+                                          (i as f32)/(N as f32));
+                let a: f32 = self.amplitude.as_ref()
+                    .map_or(1.0, |e| e.eval(x, y, 0.0));
+                let phase: f32 = self.phase.as_ref()
+                    .map_or(0.0, |e| e.eval(x, y, 0.0));
+                psi[i*N + j] = Complex {real: a*f32::cos(phase),
+                                        imag: a*f32::sin(phase)};
+            }
+        }
+        return true;
+    }
+
+    /* Run the events due before the given step, at time t. */
+    pub fn run_events(&mut self, step: usize, t: f32,
+                      psi: &mut [Complex<f32>]) -> ScriptOutcome {
+        let mut outcome: ScriptOutcome = ScriptOutcome::default();
+        for event in self.events.iter_mut() {
+            let due: bool = match &event.trigger {
+                Trigger::At(c) => !event.fired && c.eval(0.0, 0.0, t) != 0.0,
+                Trigger::Every(n) => step.is_multiple_of(*n),
+                Trigger::When(c) => c.eval(0.0, 0.0, t) != 0.0,
+            };
+            if !due {
+                continue;
+            }
+            event.fired = true;
+            for action in event.actions.iter() {
+                match action {
+                    Action::Potential(k) => {
+                        self.current = Some(*k);
+                        outcome.potential_changed = true;
+                    },
+                    Action::Multiply(e) => {
+                        let before: f64 = norm_squared(psi);
+                        apply(psi, |x, y| Complex {real: e.eval(x, y, t),
+                                                   imag: 0.0});
+                        outcome.absorbed += before - norm_squared(psi);
+                    },
+                    Action::Phase(e) => apply(psi, |x, y| {
+                        let phase: f32 = e.eval(x, y, t);
+                        Complex {real: f32::cos(phase), imag: f32::sin(phase)}
+                    }),
+                    Action::Stop => outcome.stop = true,
+                }
+            }
+        }
+        return outcome;
+    }
+}
+
+/* Multiply psi by f(x, y). */
+fn apply(psi: &mut [Complex<f32>], f: impl Fn(f32, f32) -> Complex<f32>) {
+    for i in 0..N {
+        for j in 0..N {
+            psi[i*N + j] = psi[i*N + j]*f((j as f32)/(N as f32),
+                                          (i as f32)/(N as f32));
+        }
+    }
+}
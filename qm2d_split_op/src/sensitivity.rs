@@ -0,0 +1,174 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::control::*;
+use crate::expression::*;
+use crate::norm::*;
+use crate::potentials::*;
+use crate::split_op::*;
+
+pub struct SensitivitySettings {
+    pub dt: f32,
+    pub steps: usize,
+}
+
+/* The observables at the end of the run, and their derivatives by the
+parameters, derivatives[observable][parameter], and the final state. */
+pub struct Sensitivities {
+    pub values: Vec<f64>,
+    pub derivatives: Vec<Vec<f64>>,
+    pub psi: Vec<Complex<f32>>,
+}
+
+impl Sensitivities {
+    /* The misfit sum_m (O_m - target_m)^2 of the observables to measured
+    values, and its gradient by the parameters, for fitting them. */
+    pub fn misfit(&self, targets: &[f64]) -> (f64, Vec<f64>) {
+        let count: usize = self.derivatives.first().map_or(0, |d| d.len());
+        let mut gradient: Vec<f64> = vec![0.0; count];
+        let mut misfit: f64 = 0.0;
+        for ((value, target), d) in self.values.iter().zip(targets.iter())
+            .zip(self.derivatives.iter()) {
+            let r: f64 = value - target;
+            misfit += r*r;
+            for (g, d) in gradient.iter_mut().zip(d.iter()) {
+                *g += 2.0*r*d;
+            }
+        }
+        return (misfit, gradient);
+    }
+}
+
+/* The potential of the run at time t and its derivatives by the
+parameters there. */
+struct ParametricPotential {
+    potential: Expression,
+    derivatives: Vec<Expression>,
+    grid: Vec<Complex<f32>>,
+    fields: Vec<Vec<f32>>,
+}
+
+impl ParametricPotential {
+    fn is_time_dependent(&self) -> bool {
+        return self.potential.is_time_dependent()
+            || self.derivatives.iter().any(|d| d.is_time_dependent());
+    }
+
+    fn fill_at(&mut self, t: f32) {
+        self.potential.fill_at(&mut self.grid, t);
+        let mut field: Vec<Complex<f32>> = vec![
+            Complex {real: 0.0, imag: 0.0}; N*N];
+        for (d, out) in self.derivatives.iter()
+            .zip(self.fields.iter_mut()) {
+            d.fill_at(&mut field, t);
+            for (o, f) in out.iter_mut().zip(field.iter()) {
+                *o = f.real;
+            }
+        }
+    }
+}
+
+/* The derivatives by the parameters p_j of a potential V(x, y, t; p) of
+the expectations <O_m> at the end of a run of settings.steps steps from
+psi, for observables O_m(x, y), by adjoint propagation, so that a run
+can be fitted to measured values of them without a sweep of runs for
+finite differences. With chi = O psi_T propagated back to step n as
+chi_n, psi_n the state after step n, and W_n = dV/dp at the time of
+step n, as in the gradient of optimize_controls
+
+    d<O>/dp = 2 Re sum_n (-i dt/2) (<chi_n|W_n|psi_n>
+                                    + <chi_n-1|W_n|psi_n-1>),
+
+exactly, for the steps as they are taken. One run forward and one back,
+with psi and each chi stepped back together, give the derivatives of
+all the observables by all the parameters, where finite differences
+need a run for every parameter. The steps are those of the runs at the
+potential at the start of each, but with no absorbing layer and no
+interaction, so that they are unitary and can be undone; psi is
+normalized, and does not depend on the parameters.
+
+Reference:
+
+R.-E. Plessix. A review of the adjoint-state method for computing the
+gradient of a functional with geophysical applications. Geophysical
+Journal International 167, 495-503 (2006).
+*/
+pub fn sensitivities(psi: &[Complex<f32>], potential: &ParametricExpression,
+                     values: &[f32], observables: &[Expression],
+                     p_squared: &[f32], settings: &SensitivitySettings
+                     ) -> Result<Sensitivities, String> {
+    if values.len() != potential.parameters.len() {
+        return Err(format!("{} values given for {} parameters",
+                           values.len(), potential.parameters.len()));
+    }
+    if observables.is_empty() {
+        return Err(String::from("no observables are given"));
+    }
+    if settings.dt <= 0.0 || settings.steps == 0 {
+        return Err(String::from("the run needs a positive time step and \
+                                 at least one step"));
+    }
+    let zero: Complex<f32> = Complex {real: 0.0, imag: 0.0};
+    let mut v = ParametricPotential {
+        potential: potential.with_values(values),
+        derivatives: (0..values.len())
+            .map(|k| potential.derivative(k, values)).collect(),
+        grid: vec![zero; N*N], fields: vec![vec![0.0; N*N]; values.len()]};
+    let time_dependent: bool = v.is_time_dependent();
+    let time = |n: usize| -> f32 {(n as f32)*settings.dt};
+    let mut forward: Propagators = Propagators::default();
+    let mut psi: Vec<Complex<f32>> = psi.to_vec();
+    normalize(&mut psi, 1.0);
+    let dt: Complex<f32> = Complex {real: settings.dt, imag: 0.0};
+    for n in 0..settings.steps {
+        let changed: bool = n == 0 || time_dependent;
+        if changed {
+            v.fill_at(time(n));
+        }
+        forward.update(&v.grid, p_squared, dt, changed);
+        forward.propagate_spatial(&mut psi);
+        forward.propagate_kinetic(&mut psi, true);
+        forward.propagate_spatial(&mut psi);
+    }
+    let psi_end: Vec<Complex<f32>> = psi.clone();
+    // chi_m = O_m psi_T, and <O_m> = <psi_T|chi_m>.
+    let mut chis: Vec<Vec<Complex<f32>>> = Vec::new();
+    let mut result_values: Vec<f64> = Vec::new();
+    let mut o: Vec<Complex<f32>> = vec![zero; N*N];
+    for observable in observables.iter() {
+        observable.fill_at(&mut o, time(settings.steps));
+        let chi: Vec<Complex<f32>> = psi.iter().zip(o.iter())
+            .map(|(p, o)| p.scale(o.real)).collect();
+        result_values.push(inner_product(&psi, &chi).real);
+        chis.push(chi);
+    }
+    let factor: Complex<f64> = Complex {real: 0.0,
+                                        imag: -(settings.dt as f64)};
+    let mut derivatives: Vec<Vec<f64>> = vec![vec![0.0; values.len()];
+                                              observables.len()];
+    let mut add = |fields: &[Vec<f32>], psi: &[Complex<f32>],
+                   chis: &[Vec<Complex<f32>>]| {
+        for (chi, d) in chis.iter().zip(derivatives.iter_mut()) {
+            for (field, d) in fields.iter().zip(d.iter_mut()) {
+                *d += (factor*field_element(chi, field, psi)).real;
+            }
+        }
+    };
+    let mut backward: Propagators = Propagators::default();
+    let back: Complex<f32> = Complex {real: -settings.dt, imag: 0.0};
+    for n in (0..settings.steps).rev() {
+        let changed: bool = n == settings.steps - 1 || time_dependent;
+        if changed {
+            v.fill_at(time(n));
+        }
+        add(&v.fields, &psi, &chis);
+        backward.update(&v.grid, p_squared, back, changed);
+        for state in std::iter::once(&mut psi).chain(chis.iter_mut()) {
+            backward.propagate_spatial(state);
+            backward.propagate_kinetic(state, true);
+            backward.propagate_spatial(state);
+        }
+        add(&v.fields, &psi, &chis);
+    }
+    return Ok(Sensitivities {values: result_values, derivatives,
+                             psi: psi_end});
+}
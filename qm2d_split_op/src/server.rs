@@ -0,0 +1,300 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::capi::*;
+use crate::log::*;
+use crate::observables::*;
+use crate::output::colormap::*;
+use crate::output::npy::*;
+use crate::output::png::*;
+use crate::precision::*;
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+use std::sync::{Arc, Mutex};
+
+/* Simulations run on this machine on behalf of programs elsewhere,
+through a REST interface over HTTP, started with the serve subcommand.
+Each simulation is a Qm2d, as in the C interface, and is configured
+with the keys of qm2d_configure. The endpoints are
+
+    POST   /simulations                 create one, configured by the
+                                        body if there is one
+    GET    /simulations                 the ids of those there are
+    DELETE /simulations/<id>            remove one
+    POST   /simulations/<id>/config     configure it by the body
+    POST   /simulations/<id>/step?steps=<n>
+                                        advance it by n steps (default 1)
+    GET    /simulations/<id>/observables
+                                        the expectation values
+    GET    /simulations/<id>/psi        psi as an .npy file
+    GET    /simulations/<id>/potential  the potential as an .npy file
+    GET    /simulations/<id>/frame.png  the density as a PNG image
+
+Replies are JSON, except for the files, with errors as {"error": ...}
+and the status 400 or 404. Each connection is served by a thread of its
+own and closed after one request; a simulation only serves one request
+at a time, but different simulations step at once. gRPC would need a
+protocol buffer and HTTP/2 stack this crate does not depend on. There
+is no authentication, so the server should only listen where the
+network is trusted. */
+pub struct Server {
+    pub address: String, // host:port to listen on
+}
+
+// Bodies longer than this are refused, since they hold only settings.
+const MAX_BODY: usize = 1 << 20;
+
+type Simulations = Mutex<HashMap<usize, Arc<Mutex<Qm2d>>>>;
+
+/* A reply to a request: the status, the content type and the body. */
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, json: String) -> Reply {
+        return Reply {status, content_type: "application/json",
+                      body: json.into_bytes()};
+    }
+
+    fn error(status: u16, message: &str) -> Reply {
+        return Reply::json(status, format!("{{\"error\": \"{}\"}}",
+                                           json_escape(message)));
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped: String = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+/* A number for JSON, which has no infinities or NaN. */
+fn json_number(value: f64) -> String {
+    return if value.is_finite() {format!("{}", value)}
+        else {"null".to_string()};
+}
+
+fn status_text(status: u16) -> &'static str {
+    return match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+}
+
+/* The value of a parameter of the query string, as in steps=10. */
+fn query_value<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    return query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key == name {Some(value)} else {None}
+    });
+}
+
+fn observables_json(simulation: &Qm2d) -> String {
+    let mut scratch: Vec<Complex<f32>>
+        = vec![Complex {real: 0.0, imag: 0.0}; N*N];
+    let e: Expectations = compute_expectations(
+        simulation.psi(), simulation.potential(), 0.0, &mut scratch);
+    let fields: Vec<String> = std::iter::once(
+        format!("\"time\": {}", json_number(simulation.time() as f64)))
+        .chain(Expectations::names().iter().zip(e.values().iter()).map(
+            |(name, value)| format!("\"{}\": {}", name, json_number(*value))))
+        .collect();
+    return format!("{{{}}}", fields.join(", "));
+}
+
+/* Answer a request for one simulation, after /simulations/<id>. */
+fn simulation_reply(method: &str, rest: &str, query: &str, body: &str,
+                    simulation: &Mutex<Qm2d>) -> Reply {
+    let mut simulation = match simulation.lock() {
+        Ok(s) => s,
+        Err(_) => return Reply::error(500, "the simulation failed"),
+    };
+    return match (method, rest) {
+        ("POST", "/config") => match simulation.configure(body) {
+            Ok(()) => Reply::json(200, "{}".to_string()),
+            Err(e) => Reply::error(400, &e),
+        },
+        ("POST", "/step") => {
+            let steps: usize = match query_value(query, "steps")
+                .map(str::parse::<usize>) {
+                None => 1,
+                Some(Ok(n)) => n,
+                Some(Err(_)) => return Reply::error(
+                    400, "steps must be a non-negative integer"),
+            };
+            let norm: f64 = simulation.step(steps);
+            Reply::json(200, format!("{{\"time\": {}, \"norm\": {}}}",
+                                     json_number(simulation.time() as f64),
+                                     json_number(norm)))
+        },
+        ("GET", "/observables") => Reply::json(200, observables_json(
+            &simulation)),
+        ("GET", "/psi") => match npy_bytes(&[N, N], NpyData::Complex(
+            simulation.psi())) {
+            Ok(bytes) => Reply {status: 200, body: bytes,
+                                content_type: "application/octet-stream"},
+            Err(e) => Reply::error(500, &e.to_string()),
+        },
+        ("GET", "/potential") => {
+            let real: Vec<f32> = simulation.potential().iter()
+                .map(|v| v.real).collect();
+            match npy_bytes(&[N, N], NpyData::F32(&real)) {
+                Ok(bytes) => Reply {status: 200, body: bytes,
+                                    content_type: "application/octet-stream"},
+                Err(e) => Reply::error(500, &e.to_string()),
+            }
+        },
+        ("GET", "/frame.png") => {
+            let rgb: Vec<u8> = FrameStyle::Density(Colormap::Inferno)
+                .render(simulation.psi(), 0.0);
+            Reply {status: 200, content_type: "image/png",
+                   body: encode_png_rgb(N, N, &rgb)}
+        },
+        ("GET", _) | ("POST", _) => Reply::error(404, "no such endpoint"),
+        _ => Reply::error(405, "method not allowed"),
+    };
+}
+
+/* Answer a request from its method, path, query string and body. */
+fn reply(method: &str, path: &str, query: &str, body: &str,
+         simulations: &Simulations, next_id: &Mutex<usize>) -> Reply {
+    let path: &str = path.trim_end_matches('/');
+    if path == "/simulations" {
+        return match method {
+            "GET" => {
+                let mut ids: Vec<usize> = simulations.lock()
+                    .map(|s| s.keys().copied().collect()).unwrap_or_default();
+                ids.sort();
+                let ids: Vec<String> = ids.iter().map(usize::to_string)
+                    .collect();
+                Reply::json(200, format!("{{\"simulations\": [{}]}}",
+                                         ids.join(", ")))
+            },
+            "POST" => {
+                let mut simulation: Qm2d = Qm2d::new(StepPrecision::Single);
+                if let Err(e) = simulation.configure(body) {
+                    return Reply::error(400, &e);
+                }
+                let id: usize = match next_id.lock() {
+                    Ok(mut next) => {
+                        *next += 1;
+                        *next
+                    },
+                    Err(_) => return Reply::error(500, "no id"),
+                };
+                if let Ok(mut s) = simulations.lock() {
+                    s.insert(id, Arc::new(Mutex::new(simulation)));
+                }
+                Reply::json(201, format!("{{\"id\": {}}}", id))
+            },
+            _ => Reply::error(405, "method not allowed"),
+        };
+    }
+    let rest: &str = match path.strip_prefix("/simulations/") {
+        Some(rest) => rest,
+        None => return Reply::error(404, "no such endpoint"),
+    };
+    let (id, rest) = match rest.find('/') {
+        Some(k) => rest.split_at(k),
+        None => (rest, ""),
+    };
+    let id: usize = match id.parse::<usize>() {
+        Ok(id) => id,
+        Err(_) => return Reply::error(404, "no such simulation"),
+    };
+    if method == "DELETE" && rest.is_empty() {
+        return match simulations.lock().ok().and_then(|mut s| s.remove(&id)) {
+            Some(_) => Reply::json(200, "{}".to_string()),
+            None => Reply::error(404, "no such simulation"),
+        };
+    }
+    // The simulation is taken out of the map before it is locked, so that
+    // a long step does not hold up requests for the others.
+    let simulation: Option<Arc<Mutex<Qm2d>>> = simulations.lock().ok()
+        .and_then(|s| s.get(&id).cloned());
+    return match simulation {
+        Some(s) => simulation_reply(method, rest, query, body, &s),
+        None => Reply::error(404, "no such simulation"),
+    };
+}
+
+/* Read one request from a connection and answer it. */
+fn serve_connection(stream: std::net::TcpStream, simulations: &Simulations,
+                    next_id: &Mutex<usize>) -> std::io::Result<()> {
+    let mut reader = std::io::BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length: usize = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().unwrap_or(0);
+            }
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let method: &str = parts.next().unwrap_or("");
+    let target: &str = parts.next().unwrap_or("");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let answer: Reply = if content_length > MAX_BODY {
+        Reply::error(413, "the body is too long")
+    } else {
+        let mut body: Vec<u8> = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        match String::from_utf8(body) {
+            Ok(body) => reply(method, path, query, &body, simulations,
+                              next_id),
+            Err(_) => Reply::error(400, "the body is not UTF-8"),
+        }
+    };
+    log_event(LogLevel::Info, "server", "request", &[
+        ("method", method.into()), ("path", path.into()),
+        ("status", (answer.status as usize).into())]);
+    let mut writer = stream;
+    write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n\
+                    Content-Length: {}\r\nConnection: close\r\n\r\n",
+           answer.status, status_text(answer.status), answer.content_type,
+           answer.body.len())?;
+    writer.write_all(&answer.body)?;
+    return Ok(());
+}
+
+impl Server {
+    /* Serve requests until the program is stopped. */
+    pub fn run(self) -> std::io::Result<()> {
+        let listener = std::net::TcpListener::bind(&self.address)?;
+        println!("Serving simulations at http://{}/simulations", self.address);
+        let simulations: Arc<Simulations> = Arc::new(Mutex::new(HashMap::new()));
+        let next_id: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
+        for stream in listener.incoming().flatten() {
+            let simulations = simulations.clone();
+            let next_id = next_id.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = serve_connection(stream, &simulations,
+                                                 &next_id) {
+                    eprintln!("{}", e);
+                }
+            });
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,311 @@
+use crate::complex::*;
+use crate::output::png::crc32;
+use crate::zlib::*;
+
+/* The state of a run saved to a file, to resume from or analyze later.
+
+The format is little endian throughout:
+
+    magic       8 bytes, "QM2DSNAP"
+    version     u32, currently 1
+    width       u32
+    height      u32
+    precision   u8, 4 for 4 byte floats or 8 for 8 byte floats
+    flags       u8, bit 0 set if the potential follows psi, bit 1 set if
+                psi and the potential are compressed
+    reserved    2 bytes of zeros
+    step        u64
+    t           f64
+    dt          f64 real part, f64 imaginary part
+    psi         width*height complex numbers, as real and imaginary parts
+    potential   width*height complex numbers, if flag 0 is set
+    crc         u32, CRC-32 of everything before it
+
+Compressed, psi and the potential are split into chunks of at most
+CHUNK_SIZE bytes, each stored as its u32 size, the u32 size of its
+compressed data and then that, a zlib stream of the chunk with its
+bytes shuffled: the first byte of every float, then the second and so
+on. The sign and exponent bytes of neighbouring values are then close
+together and similar, which deflate compresses much better than the
+floats as they are. Chunks keep the memory needed bounded for large
+grids.
+
+Files from before this format, a u32 width and height followed by psi
+and the potential interleaved as four 4 byte floats per point, are
+still read, with their step and time taken as zero. */
+#[derive(Clone)]
+pub struct Snapshot {
+    pub width: usize,
+    pub height: usize,
+    pub step: u64,
+    pub t: f64,
+    pub dt: Complex<f64>,
+    pub psi: Vec<Complex<f32>>,
+    pub potential: Option<Vec<Complex<f32>>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Precision {
+    Single,
+    Double,
+}
+
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"QM2DSNAP";
+pub const SNAPSHOT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 56;
+pub const CHUNK_SIZE: usize = 1 << 20;
+const HAS_POTENTIAL: u8 = 1;
+const COMPRESSED: u8 = 2;
+
+/* Group the k-th bytes of each value of the given size together. */
+fn shuffle(data: &[u8], size: usize) -> Vec<u8> {
+    let count: usize = data.len()/size;
+    let mut out: Vec<u8> = vec![0; data.len()];
+    for (i, value) in data.chunks(size).enumerate() {
+        for (k, &b) in value.iter().enumerate() {
+            out[k*count + i] = b;
+        }
+    }
+    return out;
+}
+
+fn unshuffle(data: &[u8], size: usize) -> Vec<u8> {
+    let count: usize = data.len()/size;
+    let mut out: Vec<u8> = vec![0; data.len()];
+    for (i, value) in out.chunks_mut(size).enumerate() {
+        for (k, b) in value.iter_mut().enumerate() {
+            *b = data[k*count + i];
+        }
+    }
+    return out;
+}
+
+fn invalid(message: String) -> std::io::Error {
+    return std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+}
+
+/* Reads little endian values from the front of a slice. */
+struct Cursor<'a> {
+    bytes: &'a [u8],
+}
+
+impl Cursor<'_> {
+    fn take<const K: usize>(&mut self) -> [u8; K] {
+        let mut out: [u8; K] = [0; K];
+        out.copy_from_slice(&self.bytes[..K]);
+        self.bytes = &self.bytes[K..];
+        return out;
+    }
+
+    fn complex(&mut self, precision: Precision) -> Complex<f32> {
+        return match precision {
+            Precision::Single => Complex {
+                real: f32::from_le_bytes(self.take()),
+                imag: f32::from_le_bytes(self.take())},
+            Precision::Double => Complex {
+                real: f64::from_le_bytes(self.take()) as f32,
+                imag: f64::from_le_bytes(self.take()) as f32},
+        };
+    }
+}
+
+impl Snapshot {
+    pub fn new(width: usize, height: usize, step: u64, t: f64,
+               dt: Complex<f32>, psi: &[Complex<f32>],
+               potential: Option<&[Complex<f32>]>) -> Snapshot {
+        return Snapshot {
+            width, height, step, t,
+            dt: Complex {real: dt.real as f64, imag: dt.imag as f64},
+            psi: psi.to_vec(), potential: potential.map(|v| v.to_vec())};
+    }
+
+    /* The snapshot as the contents of a file, with psi and the potential
+    compressed at the given zlib level (1 to 9) if it is not None. */
+    pub fn encode(&self, precision: Precision, compression: Option<u32>
+                  ) -> Vec<u8> {
+        let points: usize = self.width*self.height;
+        let size: usize = match precision {
+            Precision::Single => 8,
+            Precision::Double => 16,
+        };
+        let arrays: usize = if self.potential.is_some() {2} else {1};
+        let mut data = std::vec::Vec::<u8>::with_capacity(
+            arrays*size*points);
+        for array in std::iter::once(&self.psi).chain(self.potential.iter()) {
+            for z in array.iter() {
+                match precision {
+                    Precision::Single => {
+                        data.extend(z.real.to_le_bytes());
+                        data.extend(z.imag.to_le_bytes());
+                    },
+                    Precision::Double => {
+                        data.extend((z.real as f64).to_le_bytes());
+                        data.extend((z.imag as f64).to_le_bytes());
+                    },
+                }
+            }
+        }
+        let mut flags: u8 = if self.potential.is_some() {HAS_POTENTIAL}
+            else {0};
+        if let Some(level) = compression {
+            flags |= COMPRESSED;
+            let mut compressed: Vec<u8> = Vec::new();
+            for chunk in data.chunks(CHUNK_SIZE) {
+                let z: Vec<u8> = zlib_compress_level(
+                    &shuffle(chunk, size/2), level);
+                compressed.extend((chunk.len() as u32).to_le_bytes());
+                compressed.extend((z.len() as u32).to_le_bytes());
+                compressed.extend(z);
+            }
+            data = compressed;
+        }
+        let mut out = std::vec::Vec::<u8>::with_capacity(
+            HEADER_SIZE + data.len() + 4);
+        out.extend(SNAPSHOT_MAGIC);
+        out.extend(SNAPSHOT_VERSION.to_le_bytes());
+        out.extend((self.width as u32).to_le_bytes());
+        out.extend((self.height as u32).to_le_bytes());
+        out.push(if precision == Precision::Single {4} else {8});
+        out.push(flags);
+        out.extend([0, 0]);
+        out.extend(self.step.to_le_bytes());
+        out.extend(self.t.to_le_bytes());
+        out.extend(self.dt.real.to_le_bytes());
+        out.extend(self.dt.imag.to_le_bytes());
+        out.extend(data);
+        let crc: u32 = crc32(&out);
+        out.extend(crc.to_le_bytes());
+        return out;
+    }
+
+    pub fn decode(bytes: &[u8]) -> std::io::Result<Snapshot> {
+        if bytes.len() < 8 || bytes[..8] != SNAPSHOT_MAGIC {
+            return Snapshot::decode_raw(bytes);
+        }
+        if bytes.len() < HEADER_SIZE + 4 {
+            return Err(invalid("truncated snapshot header".to_string()));
+        }
+        let (data, crc) = bytes.split_at(bytes.len() - 4);
+        if crc32(data) != u32::from_le_bytes([crc[0], crc[1], crc[2],
+                                              crc[3]]) {
+            return Err(invalid("snapshot checksum does not match".to_string()));
+        }
+        let mut c = Cursor {bytes: &data[8..]};
+        let version: u32 = u32::from_le_bytes(c.take());
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(format!("unsupported snapshot version {}",
+                                       version)));
+        }
+        let width: usize = u32::from_le_bytes(c.take()) as usize;
+        let height: usize = u32::from_le_bytes(c.take()) as usize;
+        let [precision, flags, _, _] = c.take::<4>();
+        let precision: Precision = match precision {
+            4 => Precision::Single,
+            8 => Precision::Double,
+            p => return Err(invalid(format!("unknown snapshot precision {}",
+                                            p))),
+        };
+        let step: u64 = u64::from_le_bytes(c.take());
+        let t: f64 = f64::from_le_bytes(c.take());
+        let dt: Complex<f64> = Complex {real: f64::from_le_bytes(c.take()),
+                                        imag: f64::from_le_bytes(c.take())};
+        if flags & !(HAS_POTENTIAL | COMPRESSED) != 0 {
+            return Err(invalid(format!("unknown snapshot flags {:#x}",
+                                       flags)));
+        }
+        let has_potential: bool = flags & HAS_POTENTIAL != 0;
+        let size: usize = if precision == Precision::Single {8} else {16};
+        let arrays: usize = if has_potential {2} else {1};
+        let decompressed: Vec<u8>;
+        if flags & COMPRESSED != 0 {
+            decompressed = Snapshot::decompress(c.bytes, size/2)?;
+            c = Cursor {bytes: &decompressed};
+        }
+        if c.bytes.len() != arrays*size*width*height {
+            return Err(invalid(format!(
+                "snapshot of {}x{} points has {} bytes of data", width,
+                height, c.bytes.len())));
+        }
+        let psi: Vec<Complex<f32>> = (0..width*height)
+            .map(|_| c.complex(precision)).collect();
+        let potential: Option<Vec<Complex<f32>>> = if has_potential {
+            Some((0..width*height).map(|_| c.complex(precision)).collect())
+        } else {
+            None
+        };
+        return Ok(Snapshot {width, height, step, t, dt, psi, potential});
+    }
+
+    /* Undo the compression of the chunks in data, of values of the
+    given size. */
+    fn decompress(data: &[u8], size: usize) -> std::io::Result<Vec<u8>> {
+        let mut c = Cursor {bytes: data};
+        let mut out: Vec<u8> = Vec::new();
+        while !c.bytes.is_empty() {
+            if c.bytes.len() < 8 {
+                return Err(invalid("truncated snapshot chunk".to_string()));
+            }
+            let length: usize = u32::from_le_bytes(c.take()) as usize;
+            let compressed: usize = u32::from_le_bytes(c.take()) as usize;
+            if c.bytes.len() < compressed {
+                return Err(invalid("truncated snapshot chunk".to_string()));
+            }
+            let chunk: Vec<u8> = zlib_decompress(&c.bytes[..compressed])
+                .map_err(invalid)?;
+            if chunk.len() != length {
+                return Err(invalid("snapshot chunk has the wrong size"
+                                   .to_string()));
+            }
+            out.extend(unshuffle(&chunk, size));
+            c.bytes = &c.bytes[compressed..];
+        }
+        return Ok(out);
+    }
+
+    /* The raw dumps written before this format. */
+    fn decode_raw(bytes: &[u8]) -> std::io::Result<Snapshot> {
+        if bytes.len() < 8 {
+            return Err(invalid("file is too short to be a snapshot"
+                               .to_string()));
+        }
+        let mut c = Cursor {bytes};
+        let width: usize = u32::from_le_bytes(c.take()) as usize;
+        let height: usize = u32::from_le_bytes(c.take()) as usize;
+        if c.bytes.len() < 16*width*height {
+            return Err(invalid(format!(
+                "raw snapshot of {}x{} points has only {} bytes of data",
+                width, height, c.bytes.len())));
+        }
+        let mut psi = std::vec::Vec::<Complex<f32>>::with_capacity(
+            width*height);
+        let mut potential = std::vec::Vec::<Complex<f32>>::with_capacity(
+            width*height);
+        for _ in 0..width*height {
+            psi.push(c.complex(Precision::Single));
+            potential.push(c.complex(Precision::Single));
+        }
+        return Ok(Snapshot {width, height, step: 0, t: 0.0,
+                            dt: Complex {real: 0.0, imag: 0.0}, psi,
+                            potential: Some(potential)});
+    }
+
+    pub fn save(&self, filename: &str, precision: Precision,
+                compression: Option<u32>) -> std::io::Result<()> {
+        return std::fs::write(filename, self.encode(precision, compression));
+    }
+
+    pub fn load(filename: &str) -> std::io::Result<Snapshot> {
+        return Snapshot::decode(&std::fs::read(filename)?);
+    }
+
+    /* Check that the snapshot is of a width by height grid. */
+    pub fn check_size(&self, width: usize, height: usize
+                      ) -> std::io::Result<()> {
+        if self.width != width || self.height != height {
+            return Err(invalid(format!(
+                "snapshot is of a {}x{} grid, not {}x{}", self.width,
+                self.height, width, height)));
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+
+/* Compute the x and y derivatives of psi, per grid spacing, by
+multiplying its fft by i*p and transforming back. This is exact for
+the band limited functions represented on the grid, but assumes
+periodic boundaries. */
+pub fn spectral_gradient(psi: &[Complex<f32>],
+                         ddx: &mut [Complex<f32>], ddy: &mut [Complex<f32>]) {
+    ddx.copy_from_slice(psi);
+    fft2_in_place(ddx, true);
+    for i in 0..N {
+        let py: f32 = fft_momentum(i, N);
+        for j in 0..N {
+            let px: f32 = fft_momentum(j, N);
+            let val: Complex<f32> = ddx[i*N + j];
+            // Multiplying by i*p
+            ddx[i*N + j] = Complex {real: -px*val.imag, imag: px*val.real};
+            ddy[i*N + j] = Complex {real: -py*val.imag, imag: py*val.real};
+        }
+    }
+    ifft2_in_place(ddx, true);
+    ifft2_in_place(ddy, true);
+}
+
+/* The wave function in momentum space, normalized to the same norm as
+psi and with the momenta increasing along rows and columns from
+-pi + 2*pi/N to pi in units of inverse grid spacing, as given by
+momentum_order, so that it can be drawn like psi itself. */
+pub fn momentum_space(psi: &[Complex<f32>]) -> Vec<Complex<f32>> {
+    let mut transformed: Vec<Complex<f32>> = psi.to_vec();
+    fft2_in_place(transformed.as_mut_slice(), true);
+    for z in transformed.iter_mut() {
+        *z = z.scale(1.0/(N as f32));
+    }
+    return fft_shift_momentum(&transformed, N);
+}
+
+/* A smooth low pass filter of the spectrum of psi, applied at each step
+to damp noise at the scale of the grid, which grows in long nonlinear
+runs and aliases back to long wavelengths. The component of momentum p
+is multiplied by
+
+    sigma(eta) = 1                                    for eta <= cutoff
+    sigma(eta) = exp(-strength*((eta - cutoff)/(1 - cutoff))^order)
+                                                      for eta > cutoff
+
+where eta = |p|/p_max with p_max = pi, the largest momentum along an
+axis in units of inverse grid spacing. The default strength of 36 takes
+the components at p_max down to about machine precision in doubles, and
+the order sets how sharply the filter turns on past the cutoff. The
+filter takes norm away at the momenta it damps, which the norm monitor
+counts as absorbed rather than drift.
+
+Reference:
+
+T. Y. Hou, R. Li. Computing nearly singular solutions using
+pseudo-spectral methods. Journal of Computational Physics 226, 379-397
+(2007).
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpectralFilter {
+    pub cutoff: f64, // the fraction of p_max where damping starts
+    pub order: f64,
+    pub strength: f64,
+}
+
+impl SpectralFilter {
+    pub fn new(cutoff: f64, order: f64, strength: f64
+               ) -> Result<SpectralFilter, String> {
+        if !(0.0..1.0).contains(&cutoff) {
+            return Err(format!("the spectral filter cutoff must be from 0 to \
+                                below 1, not {}", cutoff));
+        }
+        if order <= 0.0 || strength < 0.0 {
+            return Err(format!("the spectral filter order must be positive \
+                                and its strength not negative, not {} and {}",
+                               order, strength));
+        }
+        return Ok(SpectralFilter {cutoff, order, strength});
+    }
+
+    /* The factor for the momentum with |p|^2 = p_squared. */
+    pub fn factor(&self, p_squared: f64) -> f64 {
+        let eta: f64 = f64::sqrt(p_squared)/std::f64::consts::PI;
+        if eta <= self.cutoff {
+            return 1.0;
+        }
+        return f64::exp(-self.strength*((eta - self.cutoff)
+                                         /(1.0 - self.cutoff)).powf(self.order));
+    }
+}
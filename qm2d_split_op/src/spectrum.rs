@@ -0,0 +1,339 @@
+use crate::complex::*;
+use crate::linalg::*;
+use crate::norm::*;
+
+/* The autocorrelation function C(t) = <psi(0)|psi(t)>, sampled every
+dt units of time. Expanding psi(0) in eigenstates with energies E_n
+gives C(t) = sum(|c_n|^2*exp(-i*E_n*t)), so the eigenenergies can be
+read off from the peaks of its Fourier transform.
+
+Reference:
+
+M. D. Feit, J. A. Fleck Jr., A. Steiger.
+Solution of the Schrodinger equation by a spectral method.
+Journal of Computational Physics 47, 412-433 (1982).
+*/
+pub struct Autocorrelation {
+    pub dt: f64,
+    pub values: Vec<Complex<f64>>,
+}
+
+impl Autocorrelation {
+    pub fn new(dt: f64) -> Autocorrelation {
+        return Autocorrelation {dt, values: std::vec::Vec::new()};
+    }
+
+    pub fn record(&mut self, psi0: &[Complex<f32>], psi: &[Complex<f32>]) {
+        self.values.push(inner_product(psi0, psi));
+    }
+
+    /* Read C(t) back from an autocorrelation_file of a run, with rows
+    t re_c im_c ... after a header starting with #, and dt the time
+    between its first two rows. */
+    pub fn load(filename: &str) -> Result<Autocorrelation, String> {
+        let text: String = std::fs::read_to_string(filename)
+            .map_err(|e| format!("{}: {}", filename, e))?;
+        let mut times: Vec<f64> = Vec::new();
+        let mut values: Vec<Complex<f64>> = Vec::new();
+        for (k, line) in text.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let numbers: Vec<f64> = line.split_whitespace().take(3)
+                .map(|v| v.parse::<f64>()).collect::<Result<_, _>>()
+                .map_err(|e| format!("{} line {}: {}", filename, k + 1, e))?;
+            if numbers.len() < 3 {
+                return Err(format!("{} line {}: expected t re_c im_c",
+                                   filename, k + 1));
+            }
+            times.push(numbers[0]);
+            values.push(Complex {real: numbers[1], imag: numbers[2]});
+        }
+        if times.len() < 2 || times[1] <= times[0] {
+            return Err(format!("{} does not hold a sampled C(t)", filename));
+        }
+        return Ok(Autocorrelation {dt: times[1] - times[0], values});
+    }
+}
+
+/* Window applied to C(t) before transforming it, to reduce the
+ringing caused by truncating the signal at the end of the run. Each
+window is 1 at t = 0 and falls to (nearly) 0 at the last sample. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Gaussian, // exp(-(3*t/T)^2/2), with T the length of the signal
+}
+
+impl Window {
+    pub fn parse(s: &str) -> Result<Window, String> {
+        return match s {
+            "rectangular" => Ok(Window::Rectangular),
+            "hann" => Ok(Window::Hann),
+            "gaussian" => Ok(Window::Gaussian),
+            _ => Err(format!("unknown window '{}'", s)),
+        };
+    }
+
+    /* Value of the window at the fraction s = t/T of the signal length. */
+    pub fn value(&self, s: f64) -> f64 {
+        return match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5*(1.0 + f64::cos(std::f64::consts::PI*s)),
+            Window::Gaussian => f64::exp(-0.5*9.0*s*s),
+        };
+    }
+}
+
+/* Energy spectrum S(E) = Re(integral(w(t)*C(t)*exp(i*E*t) dt)) of a
+sampled autocorrelation function, evaluated at count energies from
+e_min to e_max. Since C(-t) = conj(C(t)), this is half of the Fourier
+transform over all times. E values above pi/dt are aliased. */
+pub fn energy_spectrum(c: &Autocorrelation, window: Window,
+                       e_min: f64, e_max: f64, count: usize
+                       ) -> Vec<(f64, f64)> {
+    let mut spectrum = std::vec::Vec::<(f64, f64)>::with_capacity(count);
+    let n: usize = c.values.len();
+    if n == 0 {
+        return spectrum;
+    }
+    let length: f64 = (n as f64)*c.dt;
+    let mut weighted = std::vec::Vec::<Complex<f64>>::with_capacity(n);
+    for k in 0..n {
+        // Trapezoidal rule, the first sample is weighted by half.
+        let w: f64 = if k == 0 {0.5} else {1.0};
+        weighted.push(c.values[k].scale(
+            w*c.dt*window.value((k as f64)*c.dt/length)));
+    }
+    for m in 0..count {
+        let e: f64 = if count > 1 {
+            e_min + (e_max - e_min)*(m as f64)/((count - 1) as f64)
+        } else {e_min};
+        let mut s: f64 = 0.0;
+        for (k, z) in weighted.iter().enumerate() {
+            let phase: f64 = e*(k as f64)*c.dt;
+            s += z.real*f64::cos(phase) - z.imag*f64::sin(phase);
+        }
+        spectrum.push((e, s));
+    }
+    return spectrum;
+}
+
+/* Energies of the local maxima of a spectrum that are larger than
+threshold times the largest value, as estimates of the eigenenergies. */
+pub fn spectrum_peaks(spectrum: &[(f64, f64)], threshold: f64) -> Vec<f64> {
+    let mut max_val: f64 = 0.0;
+    for &(_, s) in spectrum {
+        max_val = f64::max(max_val, s);
+    }
+    let mut peaks = std::vec::Vec::<f64>::new();
+    for k in 1..spectrum.len().saturating_sub(1) {
+        let s: f64 = spectrum[k].1;
+        if s > spectrum[k-1].1 && s >= spectrum[k+1].1
+            && s > threshold*max_val {
+            peaks.push(spectrum[k].0);
+        }
+    }
+    return peaks;
+}
+
+/* A line of the spectrum found by filter_diagonalization: a term
+amplitude*exp(-i*(energy - i*width/2)*t) of C(t), and the error of
+the fit. For a bound state the width is zero and |amplitude| is its
+population in psi(0); for a resonance decaying into the absorbing layer
+it is the decay rate. */
+#[derive(Clone, Copy)]
+pub struct SpectralLine {
+    pub energy: f64,
+    pub width: f64,
+    pub amplitude: Complex<f64>,
+    pub error: f64,
+}
+
+/* Fit C(t) = sum_k d_k exp(-i*w_k*t), with complex w_k = E_k - i*G_k/2,
+to the recorded autocorrelation by filter diagonalization, which finds
+the lines from 2M + 2 samples with no limit on their resolution but the
+noise of the signal, where the peaks of energy_spectrum are about
+2*pi/(M*dt) wide and merge when closer. The energies from e_min to
+e_max are covered by windows of basis_per_window functions
+exp(-i*phi_j*t) whose frequencies phi_j are 2*pi/(M*dt) apart, and 8
+more on each side; in each window C(t) gives the matrices, for p = 0, 1 and 2,
+
+    U_p[j][l] = sum_{n, m = 0}^{M} z_j^-n z_l^-m c_{n+m+p}
+
+of the evolution operator over p steps in the Krylov vectors filtered
+at the phi_j, z_j = exp(-i*phi_j*dt), which the formulas of the
+reference sum in O(M) each. The eigenvalues u_k of U_1 b = u U_0 b give
+w_k = i*ln(u_k)/dt, and the eigenvectors, normalized to b^T U_0 b = 1,
+the amplitudes d_k = (sum_j b_j sum_{n=0}^{M} z_j^-n c_n)^2. Lines
+outside the core of their window are left out of the result, which is
+sorted by energy, as are those that stand for noise and the rest of the
+spectrum rather than lines in the window: lines with amplitudes below
+1e-6 |C(0)|, about the rounding error of C(t) in single precision, and
+lines whose error, |b^T U_2 b - u^2| for the normalized eigenvector,
+which vanishes for a line of the signal, is above max_error. Energies
+are only known modulo 2*pi/dt, so e_min to e_max must be narrower than
+that.
+
+Reference:
+
+V. A. Mandelshtam, H. S. Taylor. Harmonic inversion of time signals and
+its applications. Journal of Chemical Physics 107, 6756-6769 (1997).
+*/
+pub fn filter_diagonalization(c: &Autocorrelation, e_min: f64, e_max: f64,
+                              basis_per_window: usize, max_error: f64
+                              ) -> Result<Vec<SpectralLine>, String> {
+    let tau: f64 = c.dt;
+    if c.values.len() < 6 {
+        return Err(format!("{} samples are too few for filter \
+                            diagonalization", c.values.len()));
+    }
+    if !e_min.is_finite() || !e_max.is_finite() || e_max <= e_min
+        || (e_max - e_min)*tau >= 2.0*std::f64::consts::PI {
+        return Err(format!("the energies {} to {} must be increasing and \
+                            less than 2*pi/dt apart", e_min, e_max));
+    }
+    let m: usize = (c.values.len() - 3)/2;
+    let spacing: f64 = 2.0*std::f64::consts::PI/((m as f64)*tau);
+    let core: usize = usize::max(1, basis_per_window);
+    let margin: usize = 8;
+    let mut lines: Vec<SpectralLine> = Vec::new();
+    let mut start: f64 = e_min;
+    while start < e_max {
+        let end: f64 = f64::min(start + (core as f64)*spacing, e_max);
+        let phi: Vec<f64> = (0..core + 2*margin)
+            .map(|j| start + ((j as f64) - (margin as f64) + 0.5)*spacing)
+            .collect();
+        lines.extend(window_lines(&c.values, tau, m, &phi)?.into_iter()
+                     .filter(|l| l.energy >= start && l.energy < end));
+        start = end;
+    }
+    let smallest: f64 = 1e-6*c.values[0].length_squared().sqrt();
+    lines.retain(|l| l.error <= max_error
+                 && l.amplitude.length_squared().sqrt() > smallest);
+    lines.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+    return Ok(lines);
+}
+
+/* The lines of one window of filter_diagonalization, with basis
+frequencies phi. */
+fn window_lines(c: &[Complex<f64>], tau: f64, m: usize, phi: &[f64]
+                ) -> Result<Vec<SpectralLine>, String> {
+    let k: usize = phi.len();
+    let zero: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+    // z^-n for the basis frequencies, computed directly rather than by
+    // repeated products, which would lose accuracy over long signals.
+    let z_power = |j: usize, n: f64| -> Complex<f64> {
+        return c128exp(Complex {real: 0.0, imag: phi[j]*tau*n});
+    };
+    let z: Vec<Complex<f64>> = (0..k).map(|j| z_power(j, -1.0)).collect();
+    // The sums of c_{n+p} z^-n over n = 0..M and of c_{n+p} z^(M-n+1)
+    // over n = M+1..2M, and the weighted sum over n = 0..2M for the
+    // diagonal, for p = 0 and 1.
+    let mut f: [Vec<Complex<f64>>; 3] = std::array::from_fn(|_| vec![zero; k]);
+    let mut g: [Vec<Complex<f64>>; 3] = std::array::from_fn(|_| vec![zero; k]);
+    let mut diagonal: [Vec<Complex<f64>>; 3]
+        = std::array::from_fn(|_| vec![zero; k]);
+    for j in 0..k {
+        for n in 0..=2*m {
+            let power: Complex<f64> = z_power(j, n as f64);
+            let weight: f64 = (m - m.abs_diff(n) + 1) as f64;
+            for p in 0..3 {
+                diagonal[p][j] = diagonal[p][j]
+                    + (c[n + p]*power).scale(weight);
+                if n <= m {
+                    f[p][j] = f[p][j] + c[n + p]*power;
+                } else {
+                    g[p][j] = g[p][j] + c[n + p]
+                        *z_power(j, (m as f64) - (n as f64) + 1.0)
+                            .conj();
+                }
+            }
+        }
+    }
+    let z_m: Vec<Complex<f64>> = (0..k).map(|j| z_power(j, m as f64))
+        .collect();
+    let mut u: [Vec<Complex<f64>>; 3]
+        = std::array::from_fn(|_| vec![zero; k*k]);
+    for p in 0..3 {
+        for j in 0..k {
+            for l in 0..k {
+                u[p][j*k + l] = if j == l {
+                    diagonal[p][j]
+                } else {
+                    (z[j]*f[p][l] - z[l]*f[p][j] + z_m[l]*g[p][j]
+                     - z_m[j]*g[p][l])/(z[j] - z[l])
+                };
+            }
+        }
+    }
+    // The eigenvalues of U_0^-1 U_1.
+    let mut lu: Vec<Complex<f64>> = u[0].clone();
+    let pivots: Vec<usize> = lu_factor(&mut lu, k);
+    let mut a: Vec<Complex<f64>> = vec![zero; k*k];
+    let mut column: Vec<Complex<f64>> = vec![zero; k];
+    for l in 0..k {
+        for j in 0..k {
+            column[j] = u[1][j*k + l];
+        }
+        lu_solve(&lu, &pivots, k, &mut column);
+        for j in 0..k {
+            a[j*k + l] = column[j];
+        }
+    }
+    let mut lines: Vec<SpectralLine> = Vec::with_capacity(k);
+    for value in complex_eigenvalues(&mut a, k).into_iter() {
+        let modulus: f64 = value.length_squared().sqrt();
+        if modulus == 0.0 || !modulus.is_finite() {
+            continue;
+        }
+        // w = i*ln(u)/dt, with the energy taken as the alias nearest the
+        // middle of the window.
+        let middle: f64 = 0.5*(phi[0] + phi[k - 1]);
+        let period: f64 = 2.0*std::f64::consts::PI/tau;
+        let mut energy: f64 = -f64::atan2(value.imag, value.real)/tau;
+        energy -= period*((energy - middle)/period).round();
+        let width: f64 = -2.0*f64::ln(modulus)/tau;
+        // The eigenvector by inverse iteration with U_1 - u U_0.
+        let mut shifted: Vec<Complex<f64>> = (0..k*k)
+            .map(|i| u[1][i] - value*u[0][i]).collect();
+        let shifted_pivots: Vec<usize> = lu_factor(&mut shifted, k);
+        let mut b: Vec<Complex<f64>> = vec![Complex {real: 1.0, imag: 0.0};
+                                            k];
+        for _ in 0..2 {
+            let mut rhs: Vec<Complex<f64>> = vec![zero; k];
+            for j in 0..k {
+                for l in 0..k {
+                    rhs[j] = rhs[j] + u[0][j*k + l]*b[l];
+                }
+            }
+            lu_solve(&shifted, &shifted_pivots, k, &mut rhs);
+            let norm: f64 = rhs.iter().map(|z| z.length_squared())
+                .sum::<f64>().sqrt();
+            b = rhs.iter().map(|z| z.scale(1.0/norm)).collect();
+        }
+        let form = |p: usize| -> Complex<f64> {
+            let mut sum: Complex<f64> = zero;
+            for j in 0..k {
+                for l in 0..k {
+                    sum = sum + b[j]*u[p][j*k + l]*b[l];
+                }
+            }
+            return sum;
+        };
+        let b_u_b: Complex<f64> = form(0);
+        let mut b_f: Complex<f64> = zero;
+        for j in 0..k {
+            b_f = b_f + b[j]*f[0][j];
+        }
+        let amplitude: Complex<f64> = b_f*b_f/b_u_b;
+        // A true line evolves over two steps as u^2.
+        let error: f64 = (form(2)/b_u_b - value*value).length_squared()
+            .sqrt();
+        if amplitude.real.is_finite() && amplitude.imag.is_finite() {
+            lines.push(SpectralLine {energy, width, amplitude, error});
+        }
+    }
+    return Ok(lines);
+}
@@ -0,0 +1,251 @@
+use crate::complex::*;
+use crate::linalg::*;
+use crate::norm::*;
+use crate::observables::*;
+use crate::split_op::*;
+
+/* How the components of a state act on each other: the interactions
+g[a*n + b], which add g_ab |psi_b|^2 to the potential of component a,
+with g_aa the strength of its own nonlinear term, and the coupling
+C[a*n + b] between the components at every point, a real symmetric
+matrix whose off-diagonal entries are Rabi frequencies Omega/2 and
+whose diagonal holds the detunings. Both are n by n and symmetric. */
+#[derive(Clone)]
+pub struct SpinorCoupling {
+    pub interactions: Vec<f32>,
+    pub coupling: Vec<f64>,
+}
+
+impl SpinorCoupling {
+    /* No interactions and no coupling. */
+    pub fn none(n: usize) -> SpinorCoupling {
+        return SpinorCoupling {interactions: vec![0.0; n*n],
+                               coupling: vec![0.0; n*n]};
+    }
+}
+
+/* Parse an n by n symmetric matrix given as rows separated by ';' of
+numbers separated by spaces or commas, such as "0 0.05; 0.05 0". */
+pub fn parse_symmetric_matrix(s: &str, n: usize) -> Result<Vec<f64>, String> {
+    let rows: Vec<&str> = s.split(';').map(|r| r.trim())
+        .filter(|r| !r.is_empty()).collect();
+    if rows.len() != n {
+        return Err(format!("expected {} rows separated by ';', got {}",
+                           n, rows.len()));
+    }
+    let mut m: Vec<f64> = Vec::with_capacity(n*n);
+    for row in rows.iter() {
+        let values: Vec<f64> = row.split(|c: char| c.is_whitespace()
+                                         || c == ',')
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse::<f64>().map_err(
+                |_| format!("'{}' is not a number", v)))
+            .collect::<Result<Vec<f64>, String>>()?;
+        if values.len() != n {
+            return Err(format!("expected {} numbers in row '{}'", n, row));
+        }
+        m.extend(values);
+    }
+    for a in 0..n {
+        for b in 0..a {
+            if m[a*n + b] != m[b*n + a] {
+                return Err(format!("the matrix is not symmetric: {} at \
+                                    ({}, {}) but {} at ({}, {})",
+                                   m[a*n + b], a, b, m[b*n + a], b, a));
+            }
+        }
+    }
+    return Ok(m);
+}
+
+/* A state of several components on the N by N grid, such as the
+hyperfine states of a BEC mixture or the two of a Rabi-coupled atom,
+stepped together by the splitting of the single component runs,
+
+    D(dt/2) C(dt/2) K(dt) C(dt/2) D(dt/2),
+
+where D is the potential of each component with the interactions, K
+the kinetic step of each, which only share the momenta, and C the
+coupling, the same at every point, whose exponential exp(-i C dt/2) is
+found once from the eigenvectors of C. With no coupling and one
+component this is the step of the runs. Each step conserves the total
+norm, but moves it between coupled components, and in real time the
+interactions only change the phases, so that the densities they depend
+on stay the same through D. The absorbing layer damps every
+component. */
+pub struct Spinor {
+    pub components: Vec<Vec<Complex<f32>>>,
+    pub coupling: SpinorCoupling,
+    propagators: Vec<Propagators>,
+    coupling_step: Vec<Complex<f32>>, // exp(-i C dt/2), n by n
+    coupling_dt: Option<(f32, f32)>,
+}
+
+impl Spinor {
+    pub fn new(components: Vec<Vec<Complex<f32>>>, coupling: SpinorCoupling
+               ) -> Result<Spinor, String> {
+        let n: usize = components.len();
+        if n == 0 {
+            return Err(String::from("a spinor needs at least one \
+                                     component"));
+        }
+        if components.iter().any(|c| c.len() != components[0].len()) {
+            return Err(String::from("the components differ in size"));
+        }
+        if coupling.interactions.len() != n*n
+            || coupling.coupling.len() != n*n {
+            return Err(format!("the interactions and the coupling must be \
+                                {} by {}", n, n));
+        }
+        return Ok(Spinor {
+            components, coupling,
+            propagators: (0..n).map(|_| Propagators::default()).collect(),
+            coupling_step: Vec::new(), coupling_dt: None});
+    }
+
+    pub fn count(&self) -> usize {
+        return self.components.len();
+    }
+
+    /* The norm sum |psi_a|^2 of each component. */
+    pub fn populations(&self) -> Vec<f64> {
+        return self.components.iter().map(|c| norm_squared(c))
+            .collect();
+    }
+
+    /* The factors exp(-i C dt) = Q exp(-i Lambda dt) Q^T of the coupling
+    C = Q Lambda Q^T, again only if dt has changed. */
+    fn update_coupling(&mut self, dt: Complex<f32>) {
+        if self.coupling_dt == Some((dt.real, dt.imag)) {
+            return;
+        }
+        let n: usize = self.count();
+        let mut c: Vec<f64> = self.coupling.coupling.clone();
+        let (values, vectors): (Vec<f64>, Vec<f64>) = symmetric_eigen(&mut c,
+                                                                      n);
+        let dt64: Complex<f64> = dt.into();
+        let phases: Vec<Complex<f64>> = values.iter()
+            .map(|l| c128exp(Complex {real: 0.0, imag: -l}*dt64)).collect();
+        self.coupling_step = vec![Complex {real: 0.0, imag: 0.0}; n*n];
+        for a in 0..n {
+            for b in 0..n {
+                let mut sum: Complex<f64> = Complex {real: 0.0, imag: 0.0};
+                for (k, phase) in phases.iter().enumerate() {
+                    sum = sum + phase.scale(vectors[a*n + k]*vectors[b*n + k]);
+                }
+                self.coupling_step[a*n + b] = Complex {
+                    real: sum.real as f32, imag: sum.imag as f32};
+            }
+        }
+        self.coupling_dt = Some((dt.real, dt.imag));
+    }
+
+    /* Apply exp(-i C dt) at every point, with update_coupling done. */
+    fn propagate_coupling(&mut self) {
+        let n: usize = self.count();
+        if n == 1 || self.coupling.coupling.iter().all(|c| *c == 0.0) {
+            return;
+        }
+        let mut old: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                              n];
+        for i in 0..self.components[0].len() {
+            for (a, o) in old.iter_mut().enumerate() {
+                *o = self.components[a][i];
+            }
+            for a in 0..n {
+                let mut sum: Complex<f32> = Complex {real: 0.0, imag: 0.0};
+                for (b, o) in old.iter().enumerate() {
+                    sum = sum + self.coupling_step[a*n + b]*(*o);
+                }
+                self.components[a][i] = sum;
+            }
+        }
+    }
+
+    /* Apply exp(-i sum_b g_ab |psi_b|^2 dt) to each component a, with
+    the densities from before. */
+    fn propagate_interactions(&mut self, dt: Complex<f32>) {
+        let n: usize = self.count();
+        if self.coupling.interactions.iter().all(|g| *g == 0.0) {
+            return;
+        }
+        let minus_i_dt: Complex<f32> = Complex {real: 0.0, imag: -1.0}*dt;
+        let mut densities: Vec<f32> = vec![0.0; n];
+        for i in 0..self.components[0].len() {
+            for (a, d) in densities.iter_mut().enumerate() {
+                *d = self.components[a][i].length_squared();
+            }
+            for a in 0..n {
+                let mut v: f32 = 0.0;
+                for (b, d) in densities.iter().enumerate() {
+                    v += self.coupling.interactions[a*n + b]*d;
+                }
+                if v != 0.0 {
+                    let z: Complex<f32> = self.components[a][i];
+                    self.components[a][i] = z*c64exp(minus_i_dt.scale(v));
+                }
+            }
+        }
+    }
+
+    /* Advance the components by one time step dt in the potentials of
+    each, whose factors are computed again if potentials_changed. */
+    pub fn step(&mut self, potentials: &[Vec<Complex<f32>>],
+                p_squared: &[f32], dt: Complex<f32>, potentials_changed: bool,
+                use_mt: bool) {
+        let half_dt: Complex<f32> = dt.scale(0.5);
+        self.update_coupling(half_dt);
+        for (p, v) in self.propagators.iter_mut().zip(potentials.iter()) {
+            p.update(v, p_squared, dt, potentials_changed);
+        }
+        let spatial = |s: &mut Spinor| {
+            for (p, psi) in s.propagators.iter()
+                .zip(s.components.iter_mut()) {
+                p.propagate_spatial(psi);
+            }
+            s.propagate_interactions(half_dt);
+        };
+        spatial(self);
+        self.propagate_coupling();
+        for (p, psi) in self.propagators.iter()
+            .zip(self.components.iter_mut()) {
+            p.propagate_kinetic(psi, use_mt);
+            dampen(psi, dt.real);
+        }
+        self.propagate_coupling();
+        spatial(self);
+    }
+
+    /* The energy of the whole state, sum_a <psi_a|T + V_a|psi_a> with
+    the interactions (1/2) sum_ab g_ab int |psi_a|^2 |psi_b|^2 and the
+    coupling sum_ab C_ab <psi_a|psi_b>, over the total norm. */
+    pub fn energy(&self, potentials: &[Vec<Complex<f32>>],
+                  scratch: &mut [Complex<f32>]) -> f64 {
+        let n: usize = self.count();
+        let mut energy: f64 = 0.0;
+        let mut norm: f64 = 0.0;
+        for (psi, v) in self.components.iter().zip(potentials.iter()) {
+            let e: Expectations = compute_expectations(psi, v, 0.0, scratch);
+            energy += e.norm*(e.kinetic + e.potential);
+            norm += e.norm;
+        }
+        for a in 0..n {
+            for b in 0..n {
+                let g: f64 = self.coupling.interactions[a*n + b] as f64;
+                if g != 0.0 {
+                    let overlap: f64 = self.components[a].iter()
+                        .zip(self.components[b].iter())
+                        .map(|(x, y)| (x.length_squared() as f64)
+                             *(y.length_squared() as f64)).sum();
+                    energy += 0.5*g*overlap;
+                }
+                let c: f64 = self.coupling.coupling[a*n + b];
+                if c != 0.0 {
+                    energy += c*inner_product(&self.components[a],
+                                             &self.components[b]).real;
+                }
+            }
+        }
+        return if norm > 0.0 {energy/norm} else {0.0};
+    }
+}
@@ -0,0 +1,233 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::fft::*;
+use crate::field::*;
+use crate::parallel::*;
+use crate::spectral::*;
+
+/* Initialize the square of the momentum values that correspond to the
+real-space simulation domain. These are shifted to match the fft output. */ 
+pub fn init_momentum_squared(p_squared: &mut [f32]) {
+    for i in 0..N {
+        for j in 0..N {
+            let i_shift: i32 = if i < N/2 {i as i32} 
+                else {-(N as i32) + (i as i32)};
+            let j_shift: i32 = if j < N/2 {j as i32}
+                else {-(N as i32) + (j as i32)};
+            let px: f32 
+                = 2.0*std::f32::consts::PI*(i_shift as f32)/(N as f32);
+            let py: f32 
+                = 2.0*std::f32::consts::PI*(j_shift as f32)/(N as f32);
+            p_squared[i*N + j] = px*px + py*py;
+        }
+    }
+}
+
+
+/* Propagate the wave function psi with free-space periodic boundary
+conditions for time step dt:
+    |psi(dt)> = exp(-i*p_squared*dt/2)|psi(0)>.*/
+pub fn propagate_kinetic(psi: &mut [Complex<f32>], 
+                     p_squared: &[f32], dt: Complex<f32>,
+                     use_mt: bool) {
+    fft2_in_place(psi, use_mt);
+    for i in 0..N {
+        for j in 0..N {
+            psi[i*N + j] = psi[i*N + j]*c64exp(
+                Complex {real: 0.0, imag: -0.5*p_squared[i*N + j]} * dt);
+        }
+    }
+    ifft2_in_place(psi, use_mt);
+}
+
+/* The factors psi is multiplied by in a step of the splitting without
+a nonlinear term, exp(-i*V*dt/2) for each half step of the potential
+and exp(-i*p_squared*dt/2) for the kinetic step, computed once instead
+of at every point of every step. update only computes them again when
+dt or the potential has changed. */
+#[derive(Default)]
+pub struct Propagators {
+    pub spatial: Vec<Complex<f32>>,
+    pub kinetic: Vec<Complex<f32>>,
+    dt: Option<(f32, f32)>,
+    // Multiplies the kinetic factors if set (see src/spectral.rs).
+    filter: Option<SpectralFilter>,
+    // Applied with the potential at each half step if set.
+    nonlinear: Option<Nonlinear>,
+}
+
+impl Propagators {
+    /* Compute the factors for the potential and time step dt again if
+    dt, or the number of points, differ from the last update, or
+    potential_changed is set. */
+    pub fn update(&mut self, potential: &[Complex<f32>], p_squared: &[f32],
+                  dt: Complex<f32>, potential_changed: bool) {
+        let dt_changed: bool = self.dt != Some((dt.real, dt.imag))
+            || self.kinetic.len() != p_squared.len();
+        if dt_changed {
+            self.kinetic.clear();
+            self.kinetic.extend(p_squared.iter().map(
+                |&p2| c64exp(Complex {real: 0.0, imag: -0.5*p2} * dt)));
+            if let Some(filter) = self.filter {
+                for (u, &p2) in self.kinetic.iter_mut().zip(p_squared.iter()) {
+                    *u = u.scale(filter.factor(p2 as f64) as f32);
+                }
+            }
+        }
+        if dt_changed || potential_changed
+            || self.spatial.len() != potential.len() {
+            let half_dt: Complex<f32> = dt.scale(0.5);
+            self.spatial.resize(potential.len(),
+                                Complex {real: 0.0, imag: 0.0});
+            zip_parts_mut(&mut self.spatial, potential, |u, v| {
+                for (u, &v) in u.iter_mut().zip(v.iter()) {
+                    *u = c64exp(Complex {real: 0.0, imag: -1.0} * v * half_dt);
+                }
+            });
+        }
+        self.dt = Some((dt.real, dt.imag));
+    }
+
+    /* Damp the spectrum of psi with the filter at each kinetic step
+    from the next update on, or stop damping it if None. */
+    pub fn set_filter(&mut self, filter: Option<SpectralFilter>) {
+        if filter != self.filter {
+            self.filter = filter;
+            self.dt = None;
+        }
+    }
+
+    /* Take the nonlinear term into the half steps of the potential from
+    now on, or leave it out again if None. */
+    pub fn set_nonlinear(&mut self, nonlinear: Option<Nonlinear>) {
+        self.nonlinear = nonlinear.filter(|n| n.square != 0.0);
+    }
+
+    /* Half a step of the potential, and of the nonlinear term if set,
+    as propagate_spatial_terms. */
+    pub fn propagate_spatial(&self, psi: &mut [Complex<f32>]) {
+        multiply_in_place(psi, &self.spatial);
+        if let (Some(nonlinear), Some((re, im))) = (self.nonlinear, self.dt) {
+            propagate_nonlinear(psi, nonlinear,
+                                Complex {real: 0.5*re, imag: 0.5*im});
+        }
+    }
+
+    /* A kinetic step, as propagate_kinetic. */
+    pub fn propagate_kinetic(&self, psi: &mut [Complex<f32>], use_mt: bool) {
+        fft2_in_place(psi, use_mt);
+        multiply_in_place(psi, &self.kinetic);
+        ifft2_in_place(psi, use_mt);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Nonlinear {
+    pub square: f32,
+
+}
+
+/* Apply the transformation 
+    exp(-i*(potential + nonlinear(psi))*dt) on psi */
+pub fn propagate_spatial_terms(
+    psi: &mut [Complex<f32>], 
+    potential: &[Complex<f32>],
+    // vec_potential_x: &[Complex<f32>],
+    // vec_potential_y: &[Complex<f32>],
+    nonlinear: Nonlinear,
+    dt: Complex<f32>) {
+    for i in 0..N {
+        for j in 0..N {
+            let ij: usize = i*N + j;
+            let psi_ij = psi[ij];
+            let potential_ij = potential[ij];
+            let nonlinear_term 
+                = (psi_ij*psi_ij.conj()).scale(nonlinear.square);
+            psi[i*N + j] = psi_ij*c64exp(
+                Complex {real: 0.0, imag: -1.0} 
+                * (potential_ij + nonlinear_term)* dt);
+        }
+    }
+}
+
+/* Apply exp(-i*nonlinear(psi)*dt) on psi, the part of
+propagate_spatial_terms that the potential has no part in. In real time
+it only changes the phase, so |psi| and the term itself stay the same
+through it. */
+pub fn propagate_nonlinear(psi: &mut [Complex<f32>], nonlinear: Nonlinear,
+                           dt: Complex<f32>) {
+    let g_dt: Complex<f32> = Complex {real: 0.0, imag: -nonlinear.square}*dt;
+    for z in psi.iter_mut() {
+        *z = *z*c64exp(g_dt.scale(z.length_squared()));
+    }
+}
+
+/* Dampen the wavefunction inside a region, where the probability
+ * current inside this region is used to compute the decay. 
+ *
+ * References:
+ *
+ * Wikipedia - Probability current
+ * https://en.wikipedia.org/wiki/Probability_current
+ *
+ * Widipedia - Perfectly matched layer
+ * https://en.wikipedia.org/wiki/Perfectly_matched_layer
+ */
+pub fn dampen(psi: &mut [Complex<f32>], dt: f32) {
+    dampen_rows(psi, N, 0, None, dt);
+}
+
+/* As dampen, for psi holding the rows of an n by n grid from first_row
+on, with below the row after the last of them, or None if psi is the
+whole grid (whose last row is followed by its first). */
+pub fn dampen_rows(psi: &mut [Complex<f32>], n: usize, first_row: usize,
+                   below: Option<&[Complex<f32>]>, dt: f32) {
+    // The factor of a point depends on the next point of its row and on
+    // the point below it, neither of which is damped yet when the points
+    // are damped in order, except for the first point of the row, which
+    // is kept for the last. Rows outside the region are left as they are.
+    let rows: usize = psi.len()/n;
+    for i in 0..rows { // height
+        let y = ((first_row + i) as f32)/(n as f32);
+        if y <= 0.9 {
+            continue;
+        }
+        let first: Complex<f32> = psi[n*i];
+        for j in 0..n { // width
+            let psi_ij: Complex<f32> = psi[n*i + j];
+            let abs_psi2 = (psi_ij*psi_ij.conj()).real;
+            let (jx, jy): (f32, f32) = if abs_psi2 > 1e-30 {
+                let next: Complex<f32> = if j + 1 == n {first}
+                    else {psi[n*i + j + 1]};
+                let ddx_psi = next - psi_ij;
+                let psi_below: Complex<f32> = match below {
+                    Some(row) if i + 1 == rows => row[j],
+                    _ => psi[n*((i + 1) % rows) + j],
+                };
+                let ddy_psi = psi_below - psi_ij;
+                let val = 0.05 - f32::abs(y - 0.95);
+                // let val = y - 0.9;
+                // let val = 0.25*f32::exp(-0.5*(y - 0.95)*(y - 0.95)/(0.0225*0.0225));
+                (val*(psi_ij*ddx_psi).imag,
+                 val*(psi_ij*ddy_psi).imag)
+            } else {
+                (0.0, 0.0)
+            };
+            let damp_factor: f32 = f32::exp(-0.35*dt*f32::sqrt(jx*jx + jy*jy));
+            psi[n*i + j].real *= damp_factor;
+            psi[n*i + j].imag *= damp_factor;
+        }
+    }
+}
+
+/* Advance psi by one time step dt with the same Strang splitting used
+by the main loop: half a step of the spatial terms, a full kinetic step,
+the absorbing layer and another half step of the spatial terms. */
+pub fn step(psi: &mut [Complex<f32>], potential: &[Complex<f32>],
+            p_squared: &[f32], nonlinear: Nonlinear, dt: Complex<f32>,
+            use_mt: bool) {
+    propagate_spatial_terms(psi, potential, nonlinear, dt.scale(0.5));
+    propagate_kinetic(psi, p_squared, dt, use_mt);
+    dampen(psi, dt.real);
+    propagate_spatial_terms(psi, potential, nonlinear, dt.scale(0.5));
+}
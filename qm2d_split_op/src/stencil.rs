@@ -0,0 +1,178 @@
+use crate::complex::*;
+
+/* Orders of accuracy of the central difference second derivatives. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DifferenceOrder {
+    Second, // 3 points a side
+    Fourth, // 5 points
+    Sixth, // 7 points
+}
+
+impl DifferenceOrder {
+    pub fn parse(s: &str) -> Result<DifferenceOrder, String> {
+        return match s {
+            "2" => Ok(DifferenceOrder::Second),
+            "4" => Ok(DifferenceOrder::Fourth),
+            "6" => Ok(DifferenceOrder::Sixth),
+            _ => Err(format!("unknown difference order '{}', expected 2, 4 \
+                              or 6", s)),
+        };
+    }
+
+    /* The weights of f(x), f(x +- h), f(x +- 2h), ... in h^2 f''(x). */
+    pub fn weights(&self) -> &'static [f64] {
+        return match self {
+            DifferenceOrder::Second => &[-2.0, 1.0],
+            DifferenceOrder::Fourth => &[-5.0/2.0, 4.0/3.0, -1.0/12.0],
+            DifferenceOrder::Sixth => &[-49.0/18.0, 3.0/2.0, -3.0/20.0,
+                                        1.0/90.0],
+        };
+    }
+
+    /* The largest magnitude of h^2 times the second difference, that of
+    the zigzag exp(i pi x/h), which bounds the kinetic energy the grid
+    can hold as pi^2 does for the spectral one. */
+    pub fn largest_eigenvalue(&self) -> f64 {
+        let w: &[f64] = self.weights();
+        return -(w[0] + 2.0*w[1..].iter().enumerate()
+                 .map(|(k, c)| if k % 2 == 0 {-c} else {*c})
+                 .sum::<f64>());
+    }
+}
+
+/* What lies past the edges of the grid for the difference stencils. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StencilBoundary {
+    Periodic, // the grid repeats, as for the split operator steps
+    Dirichlet, // psi = 0 at every point past the edges
+}
+
+impl StencilBoundary {
+    pub fn parse(s: &str) -> Result<StencilBoundary, String> {
+        return match s {
+            "periodic" => Ok(StencilBoundary::Periodic),
+            "dirichlet" => Ok(StencilBoundary::Dirichlet),
+            _ => Err(format!("unknown boundary '{}', expected periodic or \
+                              dirichlet", s)),
+        };
+    }
+}
+
+/* The Hamiltonian H = -laplacian/2 + V, with hbar = m = 1, applied with
+finite difference laplacians on a width by height grid, stored row by
+row with rows along x, for the Krylov propagators, which only need H
+applied to vectors. The spectral laplacian of the split operator steps
+is exact for band limited periodic functions, but with hard walls at
+the edges of the box it sees the jump between one edge and the other;
+a stencil that treats the points past the edges as zero has no such
+jump. The error of the order p stencils is of order (k h)^p for a wave
+of wavenumber k, against none for the spectral one, which gives the
+dispersion of the grid: a plane wave of wavenumber k along an axis has
+the kinetic energy
+
+    -(w0 + 2 sum_m w_m cos(m k h))/(2 h^2)
+
+with the weights w of DifferenceOrder::weights, rather than k^2/2.
+
+The potential may be complex, for absorbing walls, in which case H is
+not Hermitian.
+
+Reference:
+
+B. Fornberg. Generation of finite difference formulas on arbitrarily
+spaced grids. Mathematics of Computation 51, 699-706 (1988).
+*/
+pub struct StencilHamiltonian {
+    pub width: usize,
+    pub height: usize,
+    pub spacing: f64,
+    pub order: DifferenceOrder,
+    pub boundary: StencilBoundary,
+    pub potential: Vec<Complex<f64>>,
+}
+
+impl StencilHamiltonian {
+    pub fn new(potential: &[Complex<f32>], width: usize, height: usize,
+               spacing: f64, order: DifferenceOrder,
+               boundary: StencilBoundary
+               ) -> Result<StencilHamiltonian, String> {
+        if potential.len() != width*height {
+            return Err(format!("the potential has {} values, not {} by {}",
+                               potential.len(), width, height));
+        }
+        if spacing <= 0.0 || !spacing.is_finite() {
+            return Err(format!("the grid spacing must be positive, not {}",
+                               spacing));
+        }
+        let reach: usize = order.weights().len() - 1;
+        if boundary == StencilBoundary::Periodic
+            && usize::min(width, height) < 2*reach + 1 {
+            return Err(format!("a periodic grid of {}x{} is too small for \
+                                the stencil of order {:?}", width, height,
+                               order));
+        }
+        return Ok(StencilHamiltonian {
+            width, height, spacing, order, boundary,
+            potential: potential.iter().map(|v| (*v).into()).collect()});
+    }
+
+    /* Set out to the laplacian of psi. */
+    pub fn laplacian(&self, psi: &[Complex<f64>], out: &mut [Complex<f64>]) {
+        assert_eq!(psi.len(), self.width*self.height);
+        assert_eq!(out.len(), psi.len());
+        let w: &[f64] = self.order.weights();
+        let scale: f64 = 1.0/(self.spacing*self.spacing);
+        let (width, height): (usize, usize) = (self.width, self.height);
+        let periodic: bool = self.boundary == StencilBoundary::Periodic;
+        // The point m steps from index k along an axis of length n, if on
+        // the grid or it repeats.
+        let offset = |k: usize, m: isize, n: usize| -> Option<usize> {
+            let l: isize = (k as isize) + m;
+            return if periodic {
+                Some(l.rem_euclid(n as isize) as usize)
+            } else if l >= 0 && (l as usize) < n {
+                Some(l as usize)
+            } else {
+                None
+            };
+        };
+        for i in 0..height {
+            for j in 0..width {
+                let mut sum: Complex<f64> = psi[i*width + j].scale(2.0*w[0]);
+                for (m, c) in w.iter().enumerate().skip(1) {
+                    for s in [-(m as isize), m as isize] {
+                        if let Some(l) = offset(j, s, width) {
+                            sum = sum + psi[i*width + l].scale(*c);
+                        }
+                        if let Some(l) = offset(i, s, height) {
+                            sum = sum + psi[l*width + j].scale(*c);
+                        }
+                    }
+                }
+                out[i*width + j] = sum.scale(scale);
+            }
+        }
+    }
+
+    /* Set out to H psi. */
+    pub fn apply(&self, psi: &[Complex<f64>], out: &mut [Complex<f64>]) {
+        self.laplacian(psi, out);
+        for ((h, z), v) in out.iter_mut().zip(psi.iter())
+            .zip(self.potential.iter()) {
+            *h = h.scale(-0.5) + *v**z;
+        }
+    }
+
+    /* Bounds on the spectrum of the Hermitian part of H, from those of
+    the kinetic energy, 0 to twice the largest along an axis, and the
+    range of the real part of the potential, as the Chebyshev
+    propagator needs to map it into [-1, 1]. */
+    pub fn energy_bounds(&self) -> (f64, f64) {
+        let kinetic: f64 = self.order.largest_eigenvalue()
+            /(self.spacing*self.spacing);
+        let (low, high): (f64, f64) = self.potential.iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY),
+                  |(a, b), v| (a.min(v.real), b.max(v.real)));
+        return (low, high + kinetic);
+    }
+}
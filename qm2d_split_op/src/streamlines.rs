@@ -0,0 +1,234 @@
+use crate::constants::*;
+use crate::field::*;
+use std::io::Write;
+
+/* Streamlines of the probability current, the paths that are tangent
+to it everywhere, which show how probability flows around obstacles
+and through slits at a glance. The current j and the velocity j/|psi|^2
+point the same way, so they have the same streamlines.
+
+Each line is traced forward and back from a seed, with fourth order
+Runge-Kutta steps of half a grid spacing along the direction j/|j|,
+interpolated bilinearly, until the current falls below min_current
+times its largest magnitude, the line leaves the grid or comes back to
+where it started, or it runs into a cell of the grid another line has
+passed through. Seeds are spaced spacing grid points apart, and those
+in a cell already passed through are skipped, so that the lines are
+spread out rather than bunched where the current converges. Cells are
+half the spacing across.
+
+Points are in grid spacings, x along rows and y along columns.
+
+Reference:
+
+B. Jobard, W. Lefer. Creating evenly-spaced streamlines of arbitrary
+density. Visualization in Scientific Computing '97, 43-55 (1997).
+*/
+pub fn trace_streamlines(jx: &Field2D<f32>, jy: &Field2D<f32>,
+                         spacing: usize, min_current: f32
+                         ) -> Vec<Vec<(f32, f32)>> {
+    let spacing: usize = usize::max(2, spacing);
+    let cell: usize = spacing/2;
+    let cells: usize = N.div_ceil(cell);
+    let max_current: f32 = jx.as_slice().iter().zip(jy.as_slice().iter())
+        .map(|(x, y)| f32::hypot(*x, *y)).fold(0.0, f32::max);
+    let threshold: f32 = min_current*max_current;
+    if max_current <= 0.0 {
+        return Vec::new();
+    }
+    // The line that has passed through each cell, if any.
+    let mut owner: Vec<Option<usize>> = vec![None; cells*cells];
+    let cell_of = |(x, y): (f32, f32)| -> usize {
+        let j: usize = usize::min(x.max(0.0) as usize/cell, cells - 1);
+        let i: usize = usize::min(y.max(0.0) as usize/cell, cells - 1);
+        return i*cells + j;
+    };
+    let direction = |(x, y): (f32, f32)| -> Option<(f32, f32)> {
+        let vx: f32 = interpolate_periodic(jx.as_slice(), x, y);
+        let vy: f32 = interpolate_periodic(jy.as_slice(), x, y);
+        let speed: f32 = f32::hypot(vx, vy);
+        return if speed > threshold && speed > 0.0 {
+            Some((vx/speed, vy/speed))
+        } else {
+            None
+        };
+    };
+    let h: f32 = 0.5;
+    let max_points: usize = 4*N;
+    let inside = |(x, y): (f32, f32)| -> bool {
+        return x >= 0.0 && y >= 0.0 && x <= (N - 1) as f32
+            && y <= (N - 1) as f32;
+    };
+    let mut lines: Vec<Vec<(f32, f32)>> = Vec::new();
+    // Lines too short to draw are numbered too, so that their cells stay
+    // claimed and their seeds are not tried again.
+    let mut count: usize = 0;
+    for i in (cell..N).step_by(spacing) {
+        for j in (cell..N).step_by(spacing) {
+            let seed: (f32, f32) = (j as f32, i as f32);
+            if owner[cell_of(seed)].is_some() || direction(seed).is_none() {
+                continue;
+            }
+            let id: usize = count;
+            count += 1;
+            let mut closed: bool = false;
+            let mut halves: [Vec<(f32, f32)>; 2] = [Vec::new(), Vec::new()];
+            for (half, sign) in halves.iter_mut().zip([1.0f32, -1.0]) {
+                let mut p: (f32, f32) = seed;
+                while !closed && half.len() < max_points {
+                    let next: (f32, f32) = match rk4_step(&direction, p,
+                                                          sign*h) {
+                        Some(q) if inside(q) => q,
+                        _ => break,
+                    };
+                    let c: usize = cell_of(next);
+                    if owner[c].is_some_and(|o| o != id) {
+                        break;
+                    }
+                    owner[c] = Some(id);
+                    half.push(next);
+                    // A closed line has come back round to its seed.
+                    closed = half.len() > 8 && (next.0 - seed.0).powi(2)
+                        + (next.1 - seed.1).powi(2) < h*h;
+                    p = next;
+                }
+            }
+            owner[cell_of(seed)] = Some(id);
+            let [forward, backward] = halves;
+            let mut line: Vec<(f32, f32)> = backward.into_iter().rev()
+                .collect();
+            line.push(seed);
+            line.extend(forward);
+            if line.len() > 2 {
+                lines.push(line);
+            }
+        }
+    }
+    return lines;
+}
+
+/* A fourth order Runge-Kutta step of length h along the direction
+field, or None if it is not defined along the way. */
+fn rk4_step(direction: &impl Fn((f32, f32)) -> Option<(f32, f32)>,
+            p: (f32, f32), h: f32) -> Option<(f32, f32)> {
+    let at = |d: (f32, f32), s: f32| -> (f32, f32) {
+        return (p.0 + s*h*d.0, p.1 + s*h*d.1);
+    };
+    let k1: (f32, f32) = direction(p)?;
+    let k2: (f32, f32) = direction(at(k1, 0.5))?;
+    let k3: (f32, f32) = direction(at(k2, 0.5))?;
+    let k4: (f32, f32) = direction(at(k3, 1.0))?;
+    return Some(at(((k1.0 + 2.0*k2.0 + 2.0*k3.0 + k4.0)/6.0,
+                    (k1.1 + 2.0*k2.1 + 2.0*k3.1 + k4.1)/6.0), 1.0));
+}
+
+/* Save the streamlines as CSV with the columns line,x,y, the number of
+the line and its points in order, with x and y in [0, 1] as in the
+current files. */
+pub fn save_streamlines_csv(filename: &str, lines: &[Vec<(f32, f32)>]
+                            ) -> std::io::Result<()> {
+    let mut writer = std::io::BufWriter::new(
+        std::fs::File::create(filename)?);
+    writeln!(writer, "line,x,y")?;
+    for (k, line) in lines.iter().enumerate() {
+        for (x, y) in line.iter() {
+            writeln!(writer, "{},{},{}", k, x/(N as f32), y/(N as f32))?;
+        }
+    }
+    return writer.flush();
+}
+
+/* The start of an SVG image of the grid, one unit per grid spacing,
+with y upwards as in the PNG frames, on a white background. */
+fn svg_header(writer: &mut impl Write) -> std::io::Result<()> {
+    writeln!(writer, "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+                      viewBox=\"0 0 {} {}\" width=\"{}\" height=\"{}\">",
+             N, N, N, N)?;
+    writeln!(writer, "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>",
+             N, N)?;
+    return Ok(());
+}
+
+/* Save the streamlines as SVG polylines. */
+pub fn save_streamlines_svg(filename: &str, lines: &[Vec<(f32, f32)>]
+                            ) -> std::io::Result<()> {
+    let mut writer = std::io::BufWriter::new(
+        std::fs::File::create(filename)?);
+    svg_header(&mut writer)?;
+    writeln!(writer,
+             "<g fill=\"none\" stroke=\"black\" stroke-width=\"1\">")?;
+    for line in lines.iter() {
+        let points: Vec<String> = line.iter()
+            .map(|(x, y)| format!("{:.2},{:.2}", x, (N - 1) as f32 - y))
+            .collect();
+        writeln!(writer, "<polyline points=\"{}\"/>", points.join(" "))?;
+    }
+    writeln!(writer, "</g>\n</svg>")?;
+    return writer.flush();
+}
+
+/* Save the current at every stride-th grid point as SVG arrows, scaled
+so that the largest is 0.9 stride long. */
+pub fn save_quiver_svg(filename: &str, jx: &Field2D<f32>,
+                       jy: &Field2D<f32>, stride: usize
+                       ) -> std::io::Result<()> {
+    let stride: usize = usize::max(1, stride);
+    let mut largest: f32 = 0.0;
+    for i in (0..N).step_by(stride) {
+        for j in (0..N).step_by(stride) {
+            largest = f32::max(largest,
+                               f32::hypot(jx.get(i, j), jy.get(i, j)));
+        }
+    }
+    let scale: f32 = if largest > 0.0 {0.9*(stride as f32)/largest}
+        else {0.0};
+    let mut writer = std::io::BufWriter::new(
+        std::fs::File::create(filename)?);
+    svg_header(&mut writer)?;
+    writeln!(writer, "<defs><marker id=\"head\" viewBox=\"0 0 10 10\" \
+                      refX=\"10\" refY=\"5\" markerWidth=\"4\" \
+                      markerHeight=\"4\" orient=\"auto\"><path \
+                      d=\"M 0 0 L 10 5 L 0 10 z\"/></marker></defs>")?;
+    writeln!(writer, "<g stroke=\"black\" stroke-width=\"1\">")?;
+    for i in (0..N).step_by(stride) {
+        for j in (0..N).step_by(stride) {
+            let (dx, dy): (f32, f32)
+                = (scale*jx.get(i, j), scale*jy.get(i, j));
+            if dx.hypot(dy) < 0.5 {
+                continue;
+            }
+            let (x, y): (f32, f32) = (j as f32, (N - 1 - i) as f32);
+            writeln!(writer, "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" \
+                              y2=\"{:.2}\" marker-end=\"url(#head)\"/>",
+                     x, y, x + dx, y - dy)?;
+        }
+    }
+    writeln!(writer, "</g>\n</svg>")?;
+    return writer.flush();
+}
+
+/* Draw the streamlines in white over an 8 bit RGB frame of the grid,
+with rows from the top of the image down, as PotentialOverlay::apply
+draws the potential. */
+pub fn draw_streamlines(rgb: &mut [u8], lines: &[Vec<(f32, f32)>]) {
+    let mut plot = |x: f32, y: f32| {
+        let (j, i): (isize, isize)
+            = (x.round() as isize, y.round() as isize);
+        if j >= 0 && i >= 0 && (j as usize) < N && (i as usize) < N {
+            let k: usize = 3*((N - 1 - i as usize)*N + j as usize);
+            rgb[k..k + 3].fill(255);
+        }
+    };
+    for line in lines.iter() {
+        for w in line.windows(2) {
+            let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+            let count: usize = f32::max((x1 - x0).abs(), (y1 - y0).abs())
+                .ceil() as usize;
+            for s in 0..=count {
+                let t: f32 = if count > 0 {(s as f32)/(count as f32)}
+                    else {0.0};
+                plot(x0 + t*(x1 - x0), y0 + t*(y1 - y0));
+            }
+        }
+    }
+}
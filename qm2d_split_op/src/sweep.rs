@@ -0,0 +1,216 @@
+use crate::constants::*;
+use crate::config::*;
+use crate::log::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/* Runs of the simulation over a grid of settings, started with the
+sweep subcommand. Each parameter is a config key with a list of values,
+
+    V_height=0.1,0.2,0.5    the values listed
+    V_height=0.1:0.5:5      5 values from 0.1 to 0.5, evenly spaced
+
+and a job is run for every combination of them, as a separate process
+of this program in a directory of its own, job_00000 and on in the
+sweep directory, with the base config followed by the values of the
+job, which take precedence. Each process steps on TH_COUNT threads, so
+by default as many jobs run at once as there are cores for them; the
+steps run on the CPU, so there are no GPUs to share out.
+
+When the jobs are done, the last row of the observables of each is
+written to one table, with the values of its parameters and whether it
+failed, so that the whole sweep can be read at once. Files the config
+names are read from the job's directory, so they should be given as
+absolute paths. */
+pub struct Sweep {
+    pub base: String, // the text of the base config
+    pub parameters: Vec<SweepParameter>,
+    pub jobs: usize, // how many run at once
+    pub directory: String,
+    pub output: String, // the table of results, as CSV
+}
+
+pub struct SweepParameter {
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+impl SweepParameter {
+    /* A parameter from key=values, the values being a list separated by
+    commas or a range start:stop:count. */
+    pub fn parse(text: &str) -> Result<SweepParameter, String> {
+        let (key, values) = text.split_once('=').ok_or_else(
+            || format!("expected key=values, not '{}'", text))?;
+        let key: String = key.trim().to_string();
+        let values: &str = values.trim();
+        let range: Vec<&str> = values.split(':').collect();
+        let values: Vec<String> = if range.len() == 3 {
+            let number = |s: &str| s.trim().parse::<f64>().map_err(
+                |_| format!("{}: '{}' is not a number", key, s));
+            let (start, stop): (f64, f64) = (number(range[0])?,
+                                             number(range[1])?);
+            let count: usize = range[2].trim().parse::<usize>().ok()
+                .filter(|n| *n > 0).ok_or_else(|| format!(
+                    "{}: the count must be a positive integer, not '{}'",
+                    key, range[2]))?;
+            (0..count).map(|k| {
+                let f: f64 = if count > 1 {(k as f64)/((count - 1) as f64)}
+                    else {0.0};
+                format!("{}", start + f*(stop - start))
+            }).collect()
+        } else if range.len() == 1 {
+            values.split(',').map(|v| v.trim().to_string()).collect()
+        } else {
+            return Err(format!("{}: a range is start:stop:count", key));
+        };
+        if key.is_empty() || values.iter().any(|v| v.is_empty()
+                                               || v.contains('"')) {
+            return Err(format!("'{}' has an empty or quoted value", text));
+        }
+        return Ok(SweepParameter {key, values});
+    }
+}
+
+/* The last row of a CSV file of observables, with its header. */
+fn last_row(filename: &str) -> std::io::Result<(String, String)> {
+    let text: String = std::fs::read_to_string(filename)?;
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header: String = lines.next().unwrap_or("").to_string();
+    let row: String = lines.next_back().unwrap_or("").to_string();
+    return Ok((header, row));
+}
+
+impl Sweep {
+    /* The default number of jobs run at once, one per TH_COUNT cores. */
+    pub fn default_jobs() -> usize {
+        let cores: usize = std::thread::available_parallelism()
+            .map(|n| n.get()).unwrap_or(1);
+        return usize::max(1, cores/TH_COUNT);
+    }
+
+    /* The values of the parameters in every job, the last parameter
+    changing fastest. */
+    pub fn combinations(&self) -> Vec<Vec<&str>> {
+        let mut combinations: Vec<Vec<&str>> = vec![Vec::new()];
+        for parameter in self.parameters.iter() {
+            combinations = combinations.iter().flat_map(
+                |c| parameter.values.iter().map(move |v| {
+                    let mut c: Vec<&str> = c.clone();
+                    c.push(v.as_str());
+                    c
+                })).collect();
+        }
+        return combinations;
+    }
+
+    /* The config of a job: the base, then its values. Observables are
+    written to a CSV file the table is made from, every 100 steps unless
+    the base says otherwise. */
+    fn job_config(&self, values: &[&str]) -> String {
+        let mut text: String = self.base.clone();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text += "\n# Set by the sweep\n";
+        let observed: bool = Config::parse(&self.base).is_ok_and(
+            |c| c.entries.iter().any(|(k, _)| k == "observables_every"));
+        if !observed {
+            text += "observables_every = 100\n";
+        }
+        text += "observables_file = observables.csv\n";
+        for (parameter, value) in self.parameters.iter().zip(values.iter()) {
+            text += &format!("{} = \"{}\"\n", parameter.key, value);
+        }
+        return text;
+    }
+
+    /* The directory job k runs in. */
+    pub fn job_directory(&self, k: usize) -> std::path::PathBuf {
+        return std::path::Path::new(&self.directory)
+            .join(format!("job_{:05}", k));
+    }
+
+    /* Run the jobs with the program at the given path, calling done with
+    the index of each job and whether it succeeded as it finishes, and
+    return whether each succeeded. */
+    pub fn run_jobs(&self, program: &std::path::Path,
+                    done: &(dyn Fn(usize, bool) + Sync)
+                    ) -> std::io::Result<Vec<bool>> {
+        let combinations: Vec<Vec<&str>> = self.combinations();
+        let directories: Vec<std::path::PathBuf> = (0..combinations.len())
+            .map(|k| self.job_directory(k)).collect();
+        for (directory, values) in directories.iter()
+            .zip(combinations.iter()) {
+            std::fs::create_dir_all(directory)?;
+            std::fs::write(directory.join("config.txt"),
+                           self.job_config(values))?;
+        }
+        println!("Running {} jobs, {} at a time", combinations.len(),
+                 self.jobs);
+        let next: AtomicUsize = AtomicUsize::new(0);
+        let succeeded: Mutex<Vec<bool>> = Mutex::new(
+            vec![false; combinations.len()]);
+        std::thread::scope(|scope| {
+            for _ in 0..usize::max(1, self.jobs) {
+                scope.spawn(|| loop {
+                    let k: usize = next.fetch_add(1, Ordering::Relaxed);
+                    if k >= directories.len() {
+                        break;
+                    }
+                    let directory: &std::path::Path = &directories[k];
+                    let status = std::fs::File::create(
+                        directory.join("log.txt")).and_then(|log| {
+                            std::process::Command::new(program)
+                                .args(["run", "--config", "config.txt"])
+                                .current_dir(directory)
+                                .stdout(log.try_clone()?).stderr(log)
+                                .status()
+                        });
+                    let ok: bool = status.as_ref().is_ok_and(|s| s.success());
+                    println!("Job {} of {} {}", k + 1, directories.len(),
+                             if ok {"done"} else {"failed"});
+                    log_event(if ok {LogLevel::Info} else {LogLevel::Warn},
+                              "sweep", "job", &[("job", k.into()),
+                                                ("ok", ok.into())]);
+                    if let Ok(mut succeeded) = succeeded.lock() {
+                        succeeded[k] = ok;
+                    }
+                    done(k, ok);
+                });
+            }
+        });
+        return Ok(succeeded.into_inner().unwrap_or_default());
+    }
+
+    /* Run the jobs with the program at the given path, then write the
+    table, returning how many jobs failed. */
+    pub fn run(&self, program: &std::path::Path) -> std::io::Result<usize> {
+        let succeeded: Vec<bool> = self.run_jobs(program, &|_, _| ())?;
+        let combinations: Vec<Vec<&str>> = self.combinations();
+        // The table has the columns of the first job with observables.
+        let rows: Vec<Option<(String, String)>> = (0..combinations.len())
+            .map(|k| last_row(&self.job_directory(k).join("observables.csv")
+                              .to_string_lossy()).ok()).collect();
+        let header: &str = rows.iter().flatten().map(|(h, _)| h.as_str())
+            .next().unwrap_or("t");
+        let mut table: String = String::from("job");
+        for parameter in self.parameters.iter() {
+            table += &format!(",{}", parameter.key);
+        }
+        table += &format!(",ok,{}\n", header);
+        for (k, values) in combinations.iter().enumerate() {
+            table += &format!("{}", k);
+            for value in values.iter() {
+                // Values listed with commas inside, such as expressions,
+                // are quoted.
+                table += &if value.contains(',') {format!(",\"{}\"", value)}
+                    else {format!(",{}", value)};
+            }
+            let row: &str = rows[k].as_ref().map(|(_, r)| r.as_str())
+                .unwrap_or("");
+            table += &format!(",{},{}\n", succeeded[k], row);
+        }
+        std::fs::write(&self.output, table)?;
+        return Ok(succeeded.iter().filter(|ok| !**ok).count());
+    }
+}
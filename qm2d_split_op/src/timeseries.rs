@@ -0,0 +1,120 @@
+/* Destination for quantities recorded over the course of a simulation.
+Each row has the simulation time followed by one value per column. */
+pub trait TimeSeriesSink {
+    fn write_header(&mut self, names: &[&str]) -> std::io::Result<()>;
+    fn write_row(&mut self, t: f64, values: &[f64]) -> std::io::Result<()>;
+}
+
+/* Time series kept in memory, for analysis after (or during) a run. */
+#[derive(Default)]
+pub struct TimeSeries {
+    pub names: Vec<String>,
+    pub times: Vec<f64>,
+    pub rows: Vec<Vec<f64>>,
+}
+
+impl TimeSeries {
+    /* All recorded values of the named column. */
+    pub fn column(&self, name: &str) -> Option<Vec<f64>> {
+        let k: usize = self.names.iter().position(|n| n == name)?;
+        return Some(self.rows.iter().map(|row| row[k]).collect());
+    }
+}
+
+impl TimeSeriesSink for TimeSeries {
+    fn write_header(&mut self, names: &[&str]) -> std::io::Result<()> {
+        self.names = names.iter().map(|n| n.to_string()).collect();
+        return Ok(());
+    }
+
+    fn write_row(&mut self, t: f64, values: &[f64]) -> std::io::Result<()> {
+        self.times.push(t);
+        self.rows.push(values.to_vec());
+        return Ok(());
+    }
+}
+
+/* Writes whitespace separated columns, with the column names on a
+first line starting with '#', which is readable by gnuplot and
+numpy.loadtxt. */
+pub struct TextSeriesWriter<W: std::io::Write> {
+    pub writer: W,
+}
+
+impl TextSeriesWriter<std::io::BufWriter<std::fs::File>> {
+    pub fn create(filename: &str) -> std::io::Result<Self> {
+        let file = std::fs::File::create(filename)?;
+        return Ok(TextSeriesWriter {writer: std::io::BufWriter::new(file)});
+    }
+}
+
+impl <W: std::io::Write> TimeSeriesSink for TextSeriesWriter<W> {
+    fn write_header(&mut self, names: &[&str]) -> std::io::Result<()> {
+        write!(self.writer, "# t")?;
+        for name in names {
+            write!(self.writer, " {}", name)?;
+        }
+        writeln!(self.writer)?;
+        return Ok(());
+    }
+
+    fn write_row(&mut self, t: f64, values: &[f64]) -> std::io::Result<()> {
+        write!(self.writer, "{}", t)?;
+        for v in values {
+            write!(self.writer, " {:e}", v)?;
+        }
+        writeln!(self.writer)?;
+        return self.writer.flush();
+    }
+}
+
+/* Writes comma separated columns under a header row of the column
+names, which spreadsheets and pandas.read_csv read as they are. Rows
+are flushed every flush_every rows, since flushing each one slows down
+runs that record often. */
+pub struct CsvSeriesWriter<W: std::io::Write> {
+    pub writer: W,
+    pub flush_every: usize,
+    rows: usize,
+}
+
+impl CsvSeriesWriter<std::io::BufWriter<std::fs::File>> {
+    pub fn create(filename: &str, flush_every: usize) -> std::io::Result<Self> {
+        let file = std::fs::File::create(filename)?;
+        return Ok(CsvSeriesWriter {writer: std::io::BufWriter::new(file),
+                                   flush_every: usize::max(1, flush_every),
+                                   rows: 0});
+    }
+}
+
+impl <W: std::io::Write> TimeSeriesSink for CsvSeriesWriter<W> {
+    fn write_header(&mut self, names: &[&str]) -> std::io::Result<()> {
+        write!(self.writer, "t")?;
+        for name in names {
+            write!(self.writer, ",{}", name)?;
+        }
+        writeln!(self.writer)?;
+        return self.writer.flush();
+    }
+
+    fn write_row(&mut self, t: f64, values: &[f64]) -> std::io::Result<()> {
+        write!(self.writer, "{}", t)?;
+        for v in values {
+            write!(self.writer, ",{:e}", v)?;
+        }
+        writeln!(self.writer)?;
+        self.rows += 1;
+        if self.rows.is_multiple_of(self.flush_every) {
+            self.writer.flush()?;
+        }
+        return Ok(());
+    }
+}
+
+/* Rows not yet flushed are written when the writer is dropped, at the
+end of the run. */
+impl <W: std::io::Write> Drop for CsvSeriesWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
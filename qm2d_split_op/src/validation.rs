@@ -0,0 +1,546 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::eigenstates::*;
+use crate::initial_states::*;
+use crate::norm::*;
+use crate::observables::*;
+use crate::phase_unwrap::*;
+use crate::poisson::*;
+use crate::split_op::*;
+use crate::stencil::*;
+use crate::vortices::*;
+
+/* Scenarios with answers in closed form, which the split operator steps
+and the other solvers should reproduce, for `cargo test` and the
+validate subcommand. Those of the steps evolve a wave function on the N
+by N grid, in units of its spacing with hbar = m = 1, without the
+absorbing layer, and compare it with the exact one:
+
+ - free_gaussian: a Gaussian packet of width sigma spreads to
+   sigma*sqrt(1 + (t/(2 sigma^2))^2), and is the exact free solution at
+   any time, as the kinetic step is exact for any dt without a potential.
+ - harmonic_revival: in the potential w^2 r^2/2 the energies are
+   w*(nx + ny + 1), so any state comes back to itself after the period
+   2*pi/w; a displaced Gaussian is stepped over one period, with the
+   error of the splitting, which is of second order in dt.
+ - plane_wave: a plane wave exp(i k.r) with k on the grid, from
+   init_plane_wave, only picks up the phase exp(-i k^2 t/2).
+ - momentum_packet: a packet from init_momentum_wave_packet, a sum
+   of plane waves with Gaussian weights, picks up their phases in the
+   same way, so its centre moves at the velocity k of its central
+   wavenumber.
+ - thomas_fermi: the Thomas-Fermi profile of a condensate with a
+   chemical potential of many times the trap frequency, from
+   init_thomas_fermi, is close to the ground state of the GPE, so
+   relaxing it in imaginary time should change its energy little.
+ - vortices: vortices imprinted from an initial_vortices setting, a pair
+   and two lattices of opposite charge, on a uniform state; the phase
+   should wind by 2 pi times the charge around a loop about each core,
+   and find_vortices should find each core, with its charge, and no
+   others.
+ - phase_unwrap: the phase of a Gaussian packet with a chirp, which
+   goes round many times over the grid, is unwrapped along a line and
+   over the grid, and should be the chirp up to a constant.
+ - poisson_periodic and poisson_dirichlet: solve_poisson is given the
+   laplacian of a Gaussian and should give the Gaussian back, up to a
+   constant with periodic boundaries, on a grid with the Gaussian small
+   at the edges.
+ - stencil: StencilHamiltonian is applied to the same Gaussian, with no
+   potential, at each difference order with psi = 0 past the edges and
+   at sixth order with periodic boundaries, and should give minus half
+   the laplacian, to within the error of the order.
+
+Errors are L2 norms of the difference from the exact wave function
+relative to its norm, and are checked against tolerances set well above
+the rounding of single precision, so that a failure means the steps are
+wrong rather than imprecise. */
+pub struct Validation {
+    pub name: &'static str,
+    pub quantity: &'static str, // what the error is of
+    pub error: f64,
+    pub tolerance: f64,
+}
+
+impl Validation {
+    pub fn passed(&self) -> bool {
+        return self.error <= self.tolerance;
+    }
+}
+
+/* Step psi count times by dt in the potential, as the main loop does
+but without the absorbing layer. */
+pub fn evolve(psi: &mut [Complex<f32>], potential: &[Complex<f32>],
+          dt: f32, count: usize) {
+    let mut p_squared: Vec<f32> = vec![0.0; N*N];
+    init_momentum_squared(&mut p_squared);
+    let mut propagators: Propagators = Propagators::default();
+    propagators.update(potential, &p_squared,
+                       Complex {real: dt, imag: 0.0}, true);
+    for _ in 0..count {
+        propagators.propagate_spatial(psi);
+        propagators.propagate_kinetic(psi, true);
+        propagators.propagate_spatial(psi);
+    }
+}
+
+/* ||psi - exact||/||exact||. */
+pub fn relative_error(psi: &[Complex<f32>], exact: &[Complex<f64>]) -> f64 {
+    let mut difference: f64 = 0.0;
+    let mut norm: f64 = 0.0;
+    for (z, w) in psi.iter().zip(exact.iter()) {
+        let z: Complex<f64> = (*z).into();
+        difference += (z - *w).length_squared();
+        norm += w.length_squared();
+    }
+    return f64::sqrt(difference/norm);
+}
+
+/* A Gaussian of width sigma (that of |psi|^2) centred on (x0, y0), at
+rest at t = 0, after spreading freely for a time t. */
+pub fn gaussian(sigma: f64, x0: f64, y0: f64, t: f64) -> Vec<Complex<f64>> {
+    // psi(x, t) = exp(-x^2/(4 sigma^2 (1 + i tau)))/(1 + i tau)
+    // in two dimensions, with tau = t/(2 sigma^2).
+    let a: Complex<f64> = Complex {real: 4.0*sigma*sigma,
+                                   imag: 2.0*t};
+    let prefactor: Complex<f64> = Complex {real: 1.0, imag: 0.0}
+        /Complex {real: 1.0, imag: t/(2.0*sigma*sigma)};
+    let mut psi: Vec<Complex<f64>> = Vec::with_capacity(N*N);
+    for i in 0..N {
+        for j in 0..N {
+            let r2: f64 = ((j as f64) - x0).powi(2) + ((i as f64) - y0).powi(2);
+            psi.push(prefactor*c128exp(Complex {real: -r2, imag: 0.0}/a));
+        }
+    }
+    return psi;
+}
+
+pub fn to_f32(psi: &[Complex<f64>]) -> Vec<Complex<f32>> {
+    return psi.iter().map(|z| Complex {real: z.real as f32,
+                                       imag: z.imag as f32}).collect();
+}
+
+/* The standard deviation of x under |psi|^2. */
+fn width_x(psi: &[Complex<f32>]) -> f64 {
+    let norm: f64 = norm_squared(psi);
+    let mut mean: f64 = 0.0;
+    let mut mean2: f64 = 0.0;
+    for i in 0..N {
+        for j in 0..N {
+            let p: f64 = psi[i*N + j].length_squared() as f64/norm;
+            mean += p*(j as f64);
+            mean2 += p*(j as f64)*(j as f64);
+        }
+    }
+    return f64::sqrt(mean2 - mean*mean);
+}
+
+fn free_gaussian() -> Vec<Validation> {
+    let (sigma, dt, steps): (f64, f32, usize) = (8.0, 20.0, 10);
+    let t: f64 = (dt as f64)*(steps as f64);
+    let centre: f64 = (N/2) as f64;
+    let mut psi: Vec<Complex<f32>> = to_f32(&gaussian(sigma, centre, centre,
+                                                      0.0));
+    let potential: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                            N*N];
+    evolve(&mut psi, &potential, dt, steps);
+    let exact: Vec<Complex<f64>> = gaussian(sigma, centre, centre, t);
+    let tau: f64 = t/(2.0*sigma*sigma);
+    let expected_width: f64 = sigma*f64::sqrt(1.0 + tau*tau);
+    return vec![
+        Validation {name: "free_gaussian", quantity: "psi", tolerance: 1e-4,
+                    error: relative_error(&psi, &exact)},
+        Validation {name: "free_gaussian", quantity: "width",
+                    tolerance: 1e-4, error: f64::abs(
+                        width_x(&psi) - expected_width)/expected_width},
+    ];
+}
+
+fn harmonic_revival() -> Vec<Validation> {
+    let (period, dt): (f64, f32) = (32.0, 0.5);
+    let steps: usize = (period/(dt as f64)).round() as usize;
+    let w: f64 = 2.0*std::f64::consts::PI/period;
+    let centre: f64 = (N/2) as f64;
+    // The width of the ground state, displaced by a few of them.
+    let sigma: f64 = f64::sqrt(0.5/w);
+    let initial: Vec<Complex<f64>> = gaussian(sigma, centre + 3.0*sigma,
+                                              centre, 0.0);
+    let mut psi: Vec<Complex<f32>> = to_f32(&initial);
+    let mut potential: Vec<Complex<f32>> = Vec::with_capacity(N*N);
+    for i in 0..N {
+        for j in 0..N {
+            let r2: f64 = ((j as f64) - centre).powi(2)
+                + ((i as f64) - centre).powi(2);
+            potential.push(Complex {real: (0.5*w*w*r2) as f32, imag: 0.0});
+        }
+    }
+    evolve(&mut psi, &potential, dt, steps);
+    return vec![Validation {name: "harmonic_revival", quantity: "psi",
+                            tolerance: 2e-2,
+                            error: relative_error(&psi, &initial)}];
+}
+
+fn plane_wave() -> Vec<Validation> {
+    let (dt, steps): (f32, usize) = (0.5, 10);
+    let t: f64 = (dt as f64)*(steps as f64);
+    let (mx, my): (f64, f64) = (37.0, -12.0);
+    let kx: f64 = 2.0*std::f64::consts::PI*mx/(N as f64);
+    let ky: f64 = 2.0*std::f64::consts::PI*my/(N as f64);
+    let wave = |t: f64| -> Vec<Complex<f64>> {
+        let mut psi: Vec<Complex<f64>> = Vec::with_capacity(N*N);
+        for i in 0..N {
+            for j in 0..N {
+                let phase: f64 = kx*(j as f64) + ky*(i as f64)
+                    - 0.5*(kx*kx + ky*ky)*t;
+                psi.push(Complex {real: phase.cos(), imag: phase.sin()});
+            }
+        }
+        return psi;
+    };
+    let mut psi: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                          N*N];
+    init_plane_wave(&mut psi, 1.0, mx as i32, my as i32);
+    let potential: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                            N*N];
+    evolve(&mut psi, &potential, dt, steps);
+    return vec![Validation {name: "plane_wave", quantity: "psi",
+                            tolerance: 1e-4,
+                            error: relative_error(&psi, &wave(t))}];
+}
+
+/* The mean of x and of y under |psi|^2, in grid points. */
+fn centre(psi: &[Complex<f32>]) -> (f64, f64) {
+    let norm: f64 = norm_squared(psi);
+    let (mut x, mut y): (f64, f64) = (0.0, 0.0);
+    for i in 0..N {
+        for j in 0..N {
+            let p: f64 = psi[i*N + j].length_squared() as f64/norm;
+            x += p*(j as f64);
+            y += p*(i as f64);
+        }
+    }
+    return (x, y);
+}
+
+fn momentum_packet() -> Vec<Validation> {
+    let (dt, steps): (f32, usize) = (20.0, 20);
+    let t: f64 = (dt as f64)*(steps as f64);
+    let packet = || MomentumWavePacket {a: 1.0, x0: 0.5, y0: 0.5,
+                                        nx: 20.0, ny: -10.0,
+                                        snx: 4.0, sny: 4.0};
+    let w: MomentumWavePacket = packet();
+    // The packet is the product of the sums over m of
+    // exp(-(m - n)^2/(2 s^2))*exp(2 pi i m (x - x0)) in x and in y,
+    // whose terms pick up the phases exp(-i k^2 t/2), k = 2 pi m/N.
+    let sum = |n: f32, s: f32, x0: f32, t: f64| -> Vec<Complex<f64>> {
+        let mut f: Vec<Complex<f64>> = vec![Complex {real: 0.0, imag: 0.0};
+                                            N];
+        let half: i64 = (N/2) as i64;
+        for m in -half..half {
+            let weight: f64 = f64::exp(
+                -0.5*((m as f64) - (n as f64)).powi(2)/((s*s) as f64));
+            let k: f64 = 2.0*std::f64::consts::PI*(m as f64)/(N as f64);
+            for (j, z) in f.iter_mut().enumerate() {
+                let phase: f64 = k*((j as f64) - (x0 as f64)*(N as f64))
+                    - 0.5*k*k*t;
+                *z = *z + Complex {real: weight*phase.cos(),
+                                   imag: weight*phase.sin()};
+            }
+        }
+        return f;
+    };
+    let exact = |t: f64| -> Vec<Complex<f64>> {
+        let fx: Vec<Complex<f64>> = sum(w.nx, w.snx, w.x0, t);
+        let fy: Vec<Complex<f64>> = sum(w.ny, w.sny, w.y0, t);
+        let mut psi: Vec<Complex<f64>> = Vec::with_capacity(N*N);
+        for y in fy.iter() {
+            for x in fx.iter() {
+                psi.push(*y**x);
+            }
+        }
+        return psi;
+    };
+    let mut psi: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                          N*N];
+    init_momentum_wave_packet(&mut psi, packet());
+    // init_momentum_wave_packet scales the packet, so the exact one is
+    // scaled to match at t = 0.
+    let initial: Vec<Complex<f64>> = exact(0.0);
+    let scale: f64 = f64::sqrt(norm_squared(&psi)/initial.iter().map(
+        |z| z.length_squared()).sum::<f64>());
+    let potential: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                            N*N];
+    let (x0, y0): (f64, f64) = centre(&psi);
+    evolve(&mut psi, &potential, dt, steps);
+    let expected: Vec<Complex<f64>> = exact(t).iter().map(
+        |z| Complex {real: scale*z.real, imag: scale*z.imag}).collect();
+    // The centre moves by k t, k = 2 pi n/N, in grid points.
+    let (x1, y1): (f64, f64) = centre(&psi);
+    let (kx, ky): (f64, f64) = (
+        2.0*std::f64::consts::PI*(w.nx as f64)/(N as f64),
+        2.0*std::f64::consts::PI*(w.ny as f64)/(N as f64));
+    let drift: f64 = f64::hypot(x1 - x0 - kx*t, y1 - y0 - ky*t);
+    return vec![
+        Validation {name: "momentum_packet", quantity: "psi",
+                    tolerance: 1e-4, error: relative_error(&psi, &expected)},
+        Validation {name: "momentum_packet", quantity: "centre",
+                    tolerance: 1e-2, error: drift},
+    ];
+}
+
+fn thomas_fermi() -> Vec<Validation> {
+    let w: f64 = 0.01;
+    // For |psi|^2 of norm 1 in w^2 r^2/2 the chemical potential is
+    // w*sqrt(g/pi), here 20 w, and the healing length 1/sqrt(2 mu) a few
+    // grid points, much less than the radius sqrt(2 mu)/w.
+    let g: f32 = (400.0*std::f64::consts::PI) as f32;
+    let centre: f64 = (N/2) as f64;
+    let mut potential: Vec<Complex<f32>> = Vec::with_capacity(N*N);
+    for i in 0..N {
+        for j in 0..N {
+            let r2: f64 = ((j as f64) - centre).powi(2)
+                + ((i as f64) - centre).powi(2);
+            potential.push(Complex {real: (0.5*w*w*r2) as f32, imag: 0.0});
+        }
+    }
+    let mut psi: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                          N*N];
+    init_thomas_fermi(&mut psi, &potential, g, 1.0)
+        .expect("g of the scenario is positive");
+    let mut scratch: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0};
+                                              N*N];
+    let initial: f64 = compute_expectations(&psi, &potential, g,
+                                            &mut scratch).total;
+    let mut p_squared: Vec<f32> = vec![0.0; N*N];
+    init_momentum_squared(&mut p_squared);
+    // The profile mostly relaxes at its edge, over an imaginary time of
+    // a few 1/mu.
+    let settings = ImaginaryTimeSettings {dt: 1.0, max_steps: 25,
+                                          tolerance: 0.0, check_every: 25,
+                                          g};
+    let relaxed: f64 = imaginary_time_eigenstates(
+        vec![psi], &potential, &p_squared, &settings)[0].energy;
+    return vec![Validation {name: "thomas_fermi", quantity: "energy",
+                            tolerance: 2e-2,
+                            error: f64::abs(initial - relaxed)/relaxed}];
+}
+
+fn vortices() -> Vec<Validation> {
+    // The lattices are kept close, so that the phase matches across the
+    // boundary (see imprint_vortices).
+    let setting: &str = "pair 0.3 0.5 0.1 0.5 0.004; \
+                         lattice 2 2 0.7 0.56 0.05 1 0.004 square; \
+                         lattice 2 2 0.7 0.44 0.05 -1 0.004 triangular";
+    let imprinted: Vec<Vortex> = parse_vortices(setting)
+        .expect("the vortices of the scenario parse");
+    let mut psi: Vec<Complex<f32>> = vec![Complex {real: 1.0, imag: 0.0};
+                                          N*N];
+    imprint_vortices(&mut psi, &imprinted);
+    // The winding of the phase around the square of 2*half + 1 points a
+    // side about the grid point nearest (x, y), counterclockwise.
+    let half: i64 = 4;
+    let winding = |x: f32, y: f32| -> f64 {
+        let (ci, cj): (i64, i64) = ((y*(N as f32)).round() as i64,
+                                    (x*(N as f32)).round() as i64);
+        let mut loop_points: Vec<(i64, i64)> = Vec::new();
+        for k in -half..half {
+            loop_points.push((ci - half, cj + k));
+        }
+        for k in -half..half {
+            loop_points.push((ci + k, cj + half));
+        }
+        for k in -half..half {
+            loop_points.push((ci + half, cj - k));
+        }
+        for k in -half..half {
+            loop_points.push((ci - k, cj - half));
+        }
+        let at = |(i, j): (i64, i64)| -> Complex<f32> {
+            return psi[(i.rem_euclid(N as i64) as usize)*N
+                       + j.rem_euclid(N as i64) as usize];
+        };
+        let mut sum: f64 = 0.0;
+        for k in 0..loop_points.len() {
+            let (a, b): (Complex<f32>, Complex<f32>) = (
+                at(loop_points[k]),
+                at(loop_points[(k + 1) % loop_points.len()]));
+            sum += (a.conj()*b).arg();
+        }
+        return sum/(2.0*std::f64::consts::PI);
+    };
+    let winding_error: f64 = imprinted.iter()
+        .map(|v| f64::abs(winding(v.x0, v.y0) - v.charge as f64))
+        .fold(0.0, f64::max);
+    // Each core should be found in a plaquette next to it.
+    let found: Vec<DetectedVortex> = find_vortices(&psi, 1e-4);
+    let missing: usize = imprinted.iter().filter(|v| !found.iter().any(
+        |f| f.charge == v.charge
+            && f32::abs(f.x - v.x0) <= 1.0/(N as f32)
+            && f32::abs(f.y - v.y0) <= 1.0/(N as f32))).count();
+    let extra: usize = found.len().saturating_sub(imprinted.len());
+    return vec![
+        Validation {name: "vortices", quantity: "winding", tolerance: 1e-3,
+                    error: winding_error},
+        Validation {name: "vortices", quantity: "cores", tolerance: 0.0,
+                    error: (missing + extra) as f64},
+    ];
+}
+
+fn phase_unwrap() -> Vec<Validation> {
+    let n: usize = 256;
+    let centre: f64 = (n/2) as f64;
+    let chirp = |x: f64, y: f64| -> f64 {
+        return 0.002*(x - centre).powi(2) + 0.4*y - 0.001*x*y;
+    };
+    let mut psi: Vec<Complex<f32>> = Vec::with_capacity(n*n);
+    let mut exact: Vec<f64> = Vec::with_capacity(n*n);
+    for i in 0..n {
+        for j in 0..n {
+            let (x, y): (f64, f64) = (j as f64, i as f64);
+            let r2: f64 = (x - centre).powi(2) + (y - centre).powi(2);
+            let amplitude: f64 = f64::exp(-r2/(2.0*60.0*60.0));
+            let phase: f64 = chirp(x, y);
+            psi.push(Complex {real: (amplitude*phase.cos()) as f32,
+                              imag: (amplitude*phase.sin()) as f32});
+            exact.push(phase);
+        }
+    }
+    // The largest difference from the exact phase after taking away
+    // the constant at the first point.
+    let error = |unwrapped: &[f64], exact: &[f64]| -> f64 {
+        let offset: f64 = unwrapped[0] - exact[0];
+        return unwrapped.iter().zip(exact.iter())
+            .map(|(u, e)| f64::abs(u - offset - e)).fold(0.0, f64::max);
+    };
+    let middle: std::ops::Range<usize> = (n/2)*n..(n/2 + 1)*n;
+    let row: Vec<f64> = psi[middle.clone()].iter()
+        .map(|z| (z.imag as f64).atan2(z.real as f64)).collect();
+    return vec![
+        Validation {name: "phase_unwrap", quantity: "line", tolerance: 1e-5,
+                    error: error(&unwrap_1d(&row), &exact[middle])},
+        Validation {name: "phase_unwrap", quantity: "grid", tolerance: 1e-5,
+                    error: error(&unwrap_psi_phase(&psi, n, n), &exact)},
+    ];
+}
+
+/* A Gaussian exp(-r^2/(2 s^2)) centred on an n by n grid and its
+laplacian, with the given spacing. */
+fn gaussian_and_laplacian(n: usize, spacing: f64, s: f64
+                          ) -> (Vec<f64>, Vec<f64>) {
+    let centre: f64 = 0.5*(n as f64)*spacing;
+    let mut phi: Vec<f64> = Vec::with_capacity(n*n);
+    let mut rho: Vec<f64> = Vec::with_capacity(n*n);
+    for i in 0..n {
+        for j in 0..n {
+            let r2: f64 = ((j as f64)*spacing - centre).powi(2)
+                + ((i as f64)*spacing - centre).powi(2);
+            let g: f64 = f64::exp(-r2/(2.0*s*s));
+            phi.push(g);
+            rho.push((r2/s.powi(4) - 2.0/(s*s))*g);
+        }
+    }
+    return (phi, rho);
+}
+
+/* max |a - b|/max |b|, after taking the mean of each away if
+without_mean. */
+fn max_relative_difference(a: &[f64], b: &[f64], without_mean: bool) -> f64 {
+    let mean = |v: &[f64]| if without_mean {
+        v.iter().sum::<f64>()/(v.len() as f64)
+    } else {
+        0.0
+    };
+    let (mean_a, mean_b): (f64, f64) = (mean(a), mean(b));
+    let scale: f64 = b.iter().map(|y| f64::abs(y - mean_b))
+        .fold(0.0, f64::max);
+    return a.iter().zip(b.iter())
+        .map(|(x, y)| f64::abs((x - mean_a) - (y - mean_b)))
+        .fold(0.0, f64::max)/scale;
+}
+
+fn poisson_periodic() -> Vec<Validation> {
+    let (n, spacing): (usize, f64) = (128, 0.5);
+    let (phi, rho): (Vec<f64>, Vec<f64>) = gaussian_and_laplacian(n, spacing,
+                                                                  4.0);
+    let solution: Vec<f64> = solve_poisson(&rho, n, spacing,
+                                           PoissonBoundary::Periodic)
+        .unwrap_or_default();
+    return vec![Validation {name: "poisson_periodic", quantity: "phi",
+                            tolerance: 1e-10,
+                            error: max_relative_difference(&solution, &phi,
+                                                           true)}];
+}
+
+fn poisson_dirichlet() -> Vec<Validation> {
+    let (n, spacing): (usize, f64) = (128, 0.5);
+    let (phi, rho): (Vec<f64>, Vec<f64>) = gaussian_and_laplacian(n, spacing,
+                                                                  4.0);
+    let solution: Vec<f64> = solve_poisson(&rho, n, spacing,
+                                           PoissonBoundary::Dirichlet)
+        .unwrap_or_default();
+    return vec![Validation {name: "poisson_dirichlet", quantity: "phi",
+                            tolerance: 1e-10,
+                            error: max_relative_difference(&solution, &phi,
+                                                           false)}];
+}
+
+fn stencil() -> Vec<Validation> {
+    let (n, spacing): (usize, f64) = (128, 0.5);
+    let (phi, rho): (Vec<f64>, Vec<f64>) = gaussian_and_laplacian(n, spacing,
+                                                                  4.0);
+    let psi: Vec<Complex<f64>> = phi.iter()
+        .map(|p| Complex {real: *p, imag: 0.0}).collect();
+    let exact: Vec<f64> = rho.iter().map(|r| -0.5*r).collect();
+    let zero: Vec<Complex<f32>> = vec![Complex {real: 0.0, imag: 0.0}; n*n];
+    let cases: [(&'static str, DifferenceOrder, StencilBoundary, f64); 4] = [
+        ("order 2", DifferenceOrder::Second, StencilBoundary::Dirichlet, 2e-2),
+        ("order 4", DifferenceOrder::Fourth, StencilBoundary::Dirichlet, 2e-4),
+        ("order 6", DifferenceOrder::Sixth, StencilBoundary::Dirichlet, 5e-6),
+        ("periodic", DifferenceOrder::Sixth, StencilBoundary::Periodic, 5e-6)];
+    let mut out: Vec<Complex<f64>> = psi.clone();
+    return cases.iter().map(|(quantity, order, boundary, tolerance)| {
+        let error: f64 = match StencilHamiltonian::new(&zero, n, n, spacing,
+                                                       *order, *boundary) {
+            Ok(h) => {
+                h.apply(&psi, &mut out);
+                let real: Vec<f64> = out.iter().map(|z| z.real).collect();
+                max_relative_difference(&real, &exact, false)
+            },
+            Err(_) => f64::INFINITY,
+        };
+        return Validation {name: "stencil", quantity, tolerance: *tolerance,
+                           error};
+    }).collect();
+}
+
+type Scenario = fn() -> Vec<Validation>;
+
+/* Run the scenarios whose names contain filter, or all of them. */
+pub fn run_validation(filter: Option<&str>) -> Vec<Validation> {
+    let scenarios: [(&str, Scenario); 10] = [
+        ("free_gaussian", free_gaussian),
+        ("harmonic_revival", harmonic_revival),
+        ("plane_wave", plane_wave),
+        ("momentum_packet", momentum_packet),
+        ("thomas_fermi", thomas_fermi),
+        ("vortices", vortices),
+        ("phase_unwrap", phase_unwrap),
+        ("poisson_periodic", poisson_periodic),
+        ("poisson_dirichlet", poisson_dirichlet),
+        ("stencil", stencil)];
+    return scenarios.iter()
+        .filter(|(name, _)| filter.is_none_or(|f| name.contains(f)))
+        .flat_map(|(_, scenario)| scenario()).collect();
+}
+
+/* The errors as a table, with whether each is within its tolerance. */
+pub fn validation_report(validations: &[Validation]) -> String {
+    let mut text: String = format!("{:<18} {:<8} {:>12} {:>12}  result\n",
+                                   "scenario", "compared", "error",
+                                   "tolerance");
+    for v in validations.iter() {
+        text += &format!("{:<18} {:<8} {:>12.3e} {:>12.3e}  {}\n", v.name,
+                         v.quantity, v.error, v.tolerance,
+                         if v.passed() {"ok"} else {"FAILED"});
+    }
+    return text;
+}
@@ -0,0 +1,124 @@
+use crate::viewer::*;
+
+// Colors of the lines of successive series.
+const LINE_COLORS: [[u8; 3]; 4] = [[60, 130, 230], [230, 120, 50],
+                                   [90, 200, 110], [220, 200, 70]];
+const BACKGROUND: [u8; 3] = [20, 20, 26];
+const AXIS: [u8; 3] = [60, 60, 70];
+
+/* Time series of the observables sent by the simulation, kept for the
+last capacity samples and drawn as line charts stacked above each
+other, each scaled to the range of its own values. */
+pub struct Charts {
+    names: Vec<&'static str>,
+    samples: std::collections::VecDeque<Sample>,
+    capacity: usize,
+}
+
+impl Charts {
+    pub fn new(capacity: usize) -> Charts {
+        return Charts {names: Vec::new(),
+                       samples: std::collections::VecDeque::new(),
+                       capacity: usize::max(2, capacity)};
+    }
+
+    /* Add a sample, starting the series again if it has other
+    observables than the samples before it. */
+    pub fn push(&mut self, sample: Sample) {
+        let names: Vec<&'static str> = sample.values.iter()
+            .map(|&(name, _)| name).collect();
+        if names != self.names {
+            self.names = names;
+            self.samples.clear();
+        }
+        self.samples.push_back(sample);
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    fn series(&self, k: usize) -> Vec<f64> {
+        return self.samples.iter().map(|s| s.values[k].1).collect();
+    }
+
+    /* The line to write over the top of each chart, with the name of
+    the observable, its latest value and the steps covered. */
+    pub fn labels(&self) -> Vec<String> {
+        let (first, last) = match (self.samples.front(), self.samples.back()) {
+            (Some(a), Some(b)) => (a.step, b.step),
+            _ => return Vec::new(),
+        };
+        return self.names.iter().enumerate().map(|(k, name)| {
+            format!("{} {:.4e}  steps {}-{}", name,
+                    self.samples[self.samples.len() - 1].values[k].1,
+                    first, last)
+        }).collect();
+    }
+
+    /* The charts as an 8 bit RGB image, with rows from the top down,
+    each taking an equal share of the height. */
+    pub fn render(&self, width: usize, height: usize) -> Vec<u8> {
+        let mut rgb: Vec<u8> = BACKGROUND.iter().copied().cycle()
+            .take(3*width*height).collect();
+        if self.names.is_empty() || width < 2 {
+            return rgb;
+        }
+        let strip: usize = height/self.names.len();
+        if strip < 32 {
+            return rgb;
+        }
+        let mut put = |x: usize, y: usize, c: [u8; 3]| {
+            if x < width && y < height {
+                rgb[3*(y*width + x)..3*(y*width + x) + 3].copy_from_slice(&c);
+            }
+        };
+        for k in 0..self.names.len() {
+            let top: usize = k*strip;
+            // Leave room for the label at the top and a gap at the bottom.
+            let (plot_top, plot_bottom) = (top + 20, top + strip - 6);
+            for x in 0..width {
+                put(x, plot_bottom, AXIS);
+            }
+            let values: Vec<f64> = self.series(k);
+            let (mut lo, mut hi): (f64, f64) = (f64::INFINITY,
+                                                f64::NEG_INFINITY);
+            for &v in values.iter().filter(|v| v.is_finite()) {
+                lo = f64::min(lo, v);
+                hi = f64::max(hi, v);
+            }
+            if lo > hi {
+                continue; // no finite values
+            }
+            if hi - lo <= 1e-12*f64::max(1.0, f64::abs(hi)) {
+                // A flat line in the middle.
+                let margin: f64 = f64::max(1e-12, 1e-6*f64::abs(hi));
+                lo -= margin;
+                hi += margin;
+            }
+            let row = |v: f64| -> f64 {
+                (plot_bottom as f64)
+                    - (v - lo)/(hi - lo)*((plot_bottom - plot_top) as f64)
+            };
+            let column = |i: usize| -> f64 {
+                (i as f64)*((width - 1) as f64)
+                    /(usize::max(1, self.capacity - 1) as f64)
+            };
+            let color: [u8; 3] = LINE_COLORS[k % LINE_COLORS.len()];
+            for i in 1..values.len() {
+                if !values[i - 1].is_finite() || !values[i].is_finite() {
+                    continue;
+                }
+                let (x0, y0) = (column(i - 1), row(values[i - 1]));
+                let (x1, y1) = (column(i), row(values[i]));
+                let n: usize = 1 + f64::max(f64::abs(x1 - x0),
+                                            f64::abs(y1 - y0)) as usize;
+                for s in 0..=n {
+                    let t: f64 = (s as f64)/(n as f64);
+                    put(f64::round(x0 + t*(x1 - x0)) as usize,
+                        f64::round(y0 + t*(y1 - y0)) as usize, color);
+                }
+            }
+        }
+        return rgb;
+    }
+}
@@ -0,0 +1,470 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::output::colormap::*;
+use crate::output::png::*;
+use crate::output::surface::*;
+use crate::viewer::*;
+
+/* Settings of the simulation that can be changed from the viewer while
+it runs. They are applied between steps. */
+#[derive(Clone, PartialEq, Debug)]
+pub struct LiveSettings {
+    pub dt: f32, // real time step
+    pub potential_scale: f32, // multiplies the real part of the potential
+    // A uniform force along x of drive_amplitude*sin(drive_frequency*t),
+    // in units of energy per grid spacing.
+    pub drive_amplitude: f32,
+    pub drive_frequency: f32,
+    pub force: (f32, f32), // a constant uniform force, in the same units
+    pub style: FrameStyle,
+    pub momentum_space: bool,
+    pub surface: Option<Camera>, // frames are drawn as a surface if set
+}
+
+impl LiveSettings {
+    pub fn new(dt: f32, style: FrameStyle) -> LiveSettings {
+        return LiveSettings {dt, potential_scale: 1.0, drive_amplitude: 0.0,
+                             drive_frequency: 0.05, force: (0.0, 0.0), style,
+                             momentum_space: false, surface: None};
+    }
+
+    /* The potential to step with at time t: the given one with its real
+    part scaled, and the driving and constant forces added as a linear
+    potential about the centre of the grid. As for any linear potential
+    on the periodic grid, it jumps at the edges. */
+    pub fn fill_potential(&self, potential: &[Complex<f32>],
+                          out: &mut [Complex<f32>], t: f32) {
+        let fx: f32 = self.force.0
+            + self.drive_amplitude*f32::sin(self.drive_frequency*t);
+        let fy: f32 = self.force.1;
+        for i in 0..N {
+            for j in 0..N {
+                let x: f32 = (j as f32) - (N/2) as f32;
+                let y: f32 = (i as f32) - (N/2) as f32;
+                let v: Complex<f32> = potential[i*N + j];
+                out[i*N + j] = Complex {
+                    real: self.potential_scale*v.real - fx*x - fy*y,
+                    imag: v.imag};
+            }
+        }
+    }
+}
+
+/* The brush that walls are painted on the potential with from the
+viewer. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Brush {
+    pub radius: f32, // in grid points
+    pub height: f32, // real part of the potential painted
+    pub erase: bool, // paint zero instead of height
+}
+
+impl Default for Brush {
+    fn default() -> Brush {
+        return Brush {radius: 8.0, height: 1.0, erase: false};
+    }
+}
+
+/* A stroke of the brush along the segment between two points of the
+grid, given as (x, y) with x along j and y along i. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Stroke {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    pub brush: Brush,
+}
+
+impl Stroke {
+    /* Set the real part of the potential to the height of the brush,
+    or to zero for the eraser, at the grid points within its radius of
+    the segment, wrapping around the edges of the periodic grid. Only the
+    points around the segment are visited, so that a stroke costs little
+    however large the grid. */
+    pub fn apply(&self, potential: &mut [Complex<f32>]) {
+        let r: f32 = f32::max(0.5, self.brush.radius);
+        let value: f32 = if self.brush.erase {0.0} else {self.brush.height};
+        let (x0, y0) = self.from;
+        let (dx, dy) = (self.to.0 - x0, self.to.1 - y0);
+        let length_squared: f32 = dx*dx + dy*dy;
+        let range = |a: f32, b: f32| -> std::ops::RangeInclusive<i64> {
+            f32::floor(f32::min(a, b) - r) as i64
+                ..=f32::ceil(f32::max(a, b) + r) as i64
+        };
+        for y in range(y0, self.to.1) {
+            for x in range(x0, self.to.0) {
+                let (x, y) = (x as f32, y as f32);
+                // Distance to the closest point of the segment.
+                let t: f32 = if length_squared > 0.0 {
+                    f32::clamp(((x - x0)*dx + (y - y0)*dy)/length_squared,
+                               0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let (ex, ey) = (x - x0 - t*dx, y - y0 - t*dy);
+                if ex*ex + ey*ey <= r*r {
+                    let i: usize = (y as i64).rem_euclid(N as i64) as usize;
+                    let j: usize = (x as i64).rem_euclid(N as i64) as usize;
+                    potential[i*N + j].real = value;
+                }
+            }
+        }
+    }
+}
+
+// The styles the panel cycles through.
+const STYLE_NAMES: [&str; 7] = ["viridis", "magma", "inferno", "turbo",
+                                "hot", "gray", "phase"];
+
+fn style_name(style: &FrameStyle) -> &'static str {
+    return match style {
+        FrameStyle::Phase => "phase",
+        FrameStyle::Density(Colormap::Gray) => "gray",
+        FrameStyle::Density(Colormap::Viridis) => "viridis",
+        FrameStyle::Density(Colormap::Magma) => "magma",
+        FrameStyle::Density(Colormap::Inferno) => "inferno",
+        FrameStyle::Density(Colormap::Turbo) => "turbo",
+        FrameStyle::Density(Colormap::Hot) => "hot",
+        FrameStyle::Density(Colormap::Table(_)) => "file",
+    };
+}
+
+fn next_style(style: &FrameStyle) -> FrameStyle {
+    let k: usize = STYLE_NAMES.iter().position(|&s| s == style_name(style))
+        .map(|k| (k + 1) % STYLE_NAMES.len()).unwrap_or(0);
+    return match STYLE_NAMES[k] {
+        "phase" => FrameStyle::Phase,
+        name => FrameStyle::Density(Colormap::parse(name)
+                                    .unwrap_or(Colormap::Viridis)),
+    };
+}
+
+/* The point of the grid drawn at pixel (x, y) of a frame, counting
+rows from the top of the frame, where y increases upwards. */
+fn grid_point(x: i32, y: i32) -> (f32, f32) {
+    return (x as f32, (N as i32 - 1 - y) as f32);
+}
+
+pub const PANEL_WIDTH: usize = 240;
+const ROW_HEIGHT: i32 = 40;
+const MARGIN: i32 = 12;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Control {
+    Dt,
+    PotentialScale,
+    DriveAmplitude,
+    DriveFrequency,
+    Style,
+    Space,
+    Surface,
+    Pause,
+    Force,
+    BrushMode,
+    BrushRadius,
+    BrushHeight,
+}
+
+const CONTROLS: [Control; 12] = [Control::Pause, Control::Style,
+                                 Control::Space, Control::Surface, Control::Dt,
+                                 Control::PotentialScale,
+                                 Control::DriveAmplitude,
+                                 Control::DriveFrequency, Control::Force,
+                                 Control::BrushMode, Control::BrushRadius,
+                                 Control::BrushHeight];
+
+// Height of the part of the panel taken by the controls.
+pub const CONTROLS_HEIGHT: i32 = 2*MARGIN + (CONTROLS.len() as i32)*ROW_HEIGHT;
+
+const DT_RANGE: (f32, f32) = (0.05, 1.0);
+// Change of each component of the force for each press of an arrow key.
+const FORCE_STEP: f32 = 2e-5;
+
+/* The lines of the help drawn over the frame. */
+pub const HELP: [&str; 11] = [
+    "space   pause or resume",
+    "arrows  nudge the force",
+    "[ ]     smaller or larger dt",
+    "m       position or momentum space",
+    "v       flat or surface view",
+    "- =     zoom the surface out or in",
+    "r       reset psi to its initial state",
+    "s       save a snapshot",
+    "drag    paint walls, or orbit the surface",
+    "h       show or hide this help",
+    "q, esc  quit",
+];
+
+impl Control {
+    /* Range of a slider, or None for controls that are clicked. */
+    fn range(&self) -> Option<(f32, f32)> {
+        return match self {
+            Control::Dt => Some(DT_RANGE),
+            Control::PotentialScale => Some((0.0, 3.0)),
+            Control::DriveAmplitude => Some((0.0, 5e-4)),
+            Control::DriveFrequency => Some((0.0, 0.2)),
+            Control::BrushRadius => Some((1.0, 40.0)),
+            Control::BrushHeight => Some((0.0, 2.0)),
+            _ => None,
+        };
+    }
+
+    fn value(&self, s: &LiveSettings, b: &Brush) -> f32 {
+        return match self {
+            Control::Dt => s.dt,
+            Control::PotentialScale => s.potential_scale,
+            Control::DriveAmplitude => s.drive_amplitude,
+            Control::DriveFrequency => s.drive_frequency,
+            Control::BrushRadius => b.radius,
+            Control::BrushHeight => b.height,
+            _ => 0.0,
+        };
+    }
+
+    fn set(&self, s: &mut LiveSettings, b: &mut Brush, value: f32) {
+        match self {
+            Control::Dt => s.dt = value,
+            Control::PotentialScale => s.potential_scale = value,
+            Control::DriveAmplitude => s.drive_amplitude = value,
+            Control::DriveFrequency => s.drive_frequency = value,
+            Control::BrushRadius => b.radius = value,
+            Control::BrushHeight => b.height = value,
+            _ => (),
+        }
+    }
+
+    // Whether the control belongs to the viewer rather than the
+    // settings of the simulation.
+    fn is_brush(&self) -> bool {
+        return matches!(self, Control::BrushMode | Control::BrushRadius
+                        | Control::BrushHeight);
+    }
+}
+
+/* One row of the panel, as drawn: a label, and for a slider the
+fraction of its range that the value is at. top is the distance of the
+row from the top of the panel, in pixels. */
+pub struct PanelRow {
+    pub label: String,
+    pub fraction: Option<f32>,
+    pub top: i32,
+}
+
+/* Position and size of the bar of a slider within its row. */
+pub const BAR_OFFSET: i32 = 20;
+pub const BAR_HEIGHT: i32 = 10;
+pub const BAR_LEFT: i32 = MARGIN;
+pub const BAR_WIDTH: i32 = PANEL_WIDTH as i32 - 2*MARGIN;
+
+/* A side panel of controls for the live viewer: sliders for the time
+step, the strength of the potential and the amplitude and frequency of
+a driving force, buttons for the drawing style, position or momentum
+space, and pausing, and the brush that walls are painted with by
+dragging over the frame. It only keeps the state and does the layout,
+and is drawn by the window it is shown in. */
+pub struct ControlPanel {
+    pub settings: LiveSettings,
+    pub paused: bool,
+    pub brush: Brush,
+    pub show_help: bool,
+    dragging: Option<Control>,
+    painting: Option<(f32, f32)>, // last point of the stroke on the grid
+    orbiting: Option<(i32, i32)>, // last position of the pointer
+}
+
+fn label(control: Control, s: &LiveSettings, b: &Brush, paused: bool
+         ) -> String {
+    return match control {
+        Control::Pause => if paused {"[ resume ]".to_string()}
+            else {"[ pause ]".to_string()},
+        Control::Style => format!("style: {}", style_name(&s.style)),
+        Control::Space => if s.momentum_space {"view: momentum".to_string()}
+            else {"view: position".to_string()},
+        Control::Surface => if s.surface.is_some() {
+            "render: surface".to_string()} else {"render: flat".to_string()},
+        Control::Dt => format!("dt {:.3}", s.dt),
+        Control::PotentialScale => format!("potential x {:.2}",
+                                           s.potential_scale),
+        Control::DriveAmplitude => format!("drive amplitude {:.2e}",
+                                           s.drive_amplitude),
+        Control::DriveFrequency => format!("drive frequency {:.3}",
+                                           s.drive_frequency),
+        Control::Force => format!("force ({:.1e}, {:.1e})", s.force.0,
+                                  s.force.1),
+        Control::BrushMode => if b.erase {"brush: eraser".to_string()}
+            else {"brush: wall".to_string()},
+        Control::BrushRadius => format!("brush radius {:.0}", b.radius),
+        Control::BrushHeight => format!("brush height {:.2}", b.height),
+    };
+}
+
+impl ControlPanel {
+    pub fn new(settings: LiveSettings) -> ControlPanel {
+        return ControlPanel {settings, paused: false,
+                             brush: Brush::default(), show_help: false,
+                             dragging: None, painting: None,
+                             orbiting: None};
+    }
+
+    pub fn rows(&self) -> Vec<PanelRow> {
+        return CONTROLS.iter().enumerate().map(|(k, &c)| PanelRow {
+            label: label(c, &self.settings, &self.brush, self.paused),
+            fraction: c.range().map(|(a, b)| (c.value(&self.settings,
+                                                      &self.brush) - a)
+                                    /(b - a)),
+            top: MARGIN + (k as i32)*ROW_HEIGHT,
+        }).collect();
+    }
+
+    fn control_at(&self, y: i32) -> Option<Control> {
+        if y < MARGIN {
+            return None;
+        }
+        return CONTROLS.get(((y - MARGIN)/ROW_HEIGHT) as usize).copied();
+    }
+
+    fn slide(&mut self, control: Control, x: i32) -> Option<Command> {
+        let (a, b) = control.range()?;
+        let f: f32 = f32::clamp(((x - BAR_LEFT) as f32)/(BAR_WIDTH as f32),
+                                0.0, 1.0);
+        control.set(&mut self.settings, &mut self.brush, a + f*(b - a));
+        if control.is_brush() {
+            return None;
+        }
+        return Some(Command::Apply(self.settings.clone()));
+    }
+
+    /* Mouse button pressed at (x, y) relative to the top left of the
+    panel. Returns the command to send to the simulation, if any. */
+    pub fn press(&mut self, x: i32, y: i32) -> Option<Command> {
+        let control: Control = self.control_at(y)?;
+        return match control {
+            Control::Pause => {
+                self.paused = !self.paused;
+                Some(Command::TogglePause)
+            },
+            Control::Style => {
+                self.settings.style = next_style(&self.settings.style);
+                Some(Command::Apply(self.settings.clone()))
+            },
+            Control::Space => {
+                self.settings.momentum_space = !self.settings.momentum_space;
+                Some(Command::Apply(self.settings.clone()))
+            },
+            Control::Surface => {
+                self.toggle_surface();
+                Some(Command::Apply(self.settings.clone()))
+            },
+            Control::Force => {
+                self.settings.force = (0.0, 0.0);
+                Some(Command::Apply(self.settings.clone()))
+            },
+            Control::BrushMode => {
+                self.brush.erase = !self.brush.erase;
+                None
+            },
+            _ => {
+                self.dragging = Some(control);
+                self.slide(control, x)
+            },
+        };
+    }
+
+    /* Mouse moved to (x, y) with the button held. */
+    pub fn drag(&mut self, x: i32, _y: i32) -> Option<Command> {
+        let control: Control = self.dragging?;
+        return self.slide(control, x);
+    }
+
+    /* A key pressed in the window. Besides the keys of command_for_key,
+    the arrow keys nudge the constant force in their direction, [ and ]
+    make the time step smaller and larger, and h shows or hides the help.
+    Returns the command to send to the simulation, if any. */
+    pub fn key(&mut self, keysym: u32) -> Option<Command> {
+        let (fx, fy) = self.settings.force;
+        match keysym {
+            KEY_LEFT => self.settings.force = (fx - FORCE_STEP, fy),
+            KEY_RIGHT => self.settings.force = (fx + FORCE_STEP, fy),
+            KEY_UP => self.settings.force = (fx, fy + FORCE_STEP),
+            KEY_DOWN => self.settings.force = (fx, fy - FORCE_STEP),
+            KEY_LEFT_BRACKET | KEY_RIGHT_BRACKET => {
+                let factor: f32 = if keysym == KEY_LEFT_BRACKET {0.8}
+                    else {1.25};
+                self.settings.dt = f32::clamp(self.settings.dt*factor,
+                                              DT_RANGE.0, DT_RANGE.1);
+            },
+            KEY_V => self.toggle_surface(),
+            KEY_MINUS | KEY_EQUAL => match self.settings.surface.as_mut() {
+                Some(camera) => camera.zoom(if keysym == KEY_MINUS {1.25}
+                                            else {0.8}),
+                None => return None,
+            },
+            KEY_H => {
+                self.show_help = !self.show_help;
+                return None;
+            },
+            _ => {
+                let command: Option<Command> = command_for_key(keysym);
+                match command {
+                    Some(Command::TogglePause) => self.paused = !self.paused,
+                    Some(Command::ToggleMomentumSpace)
+                        => self.settings.momentum_space
+                        = !self.settings.momentum_space,
+                    _ => (),
+                }
+                return command;
+            },
+        }
+        return Some(Command::Apply(self.settings.clone()));
+    }
+
+    fn toggle_surface(&mut self) {
+        self.settings.surface = match self.settings.surface {
+            Some(_) => None,
+            None => Some(Camera::default()),
+        };
+    }
+
+    /* Mouse button pressed at (x, y) on the frame, in pixels from its
+    top left, which starts a stroke of the brush, or orbiting the camera
+    when the frame is drawn as a surface. Walls are only painted on flat
+    frames in position space. */
+    pub fn press_frame(&mut self, x: i32, y: i32) -> Option<Command> {
+        if self.settings.surface.is_some() {
+            self.orbiting = Some((x, y));
+            return None;
+        }
+        if self.settings.momentum_space {
+            return None;
+        }
+        let point: (f32, f32) = grid_point(x, y);
+        self.painting = Some(point);
+        return Some(Command::Paint(Stroke {from: point, to: point,
+                                           brush: self.brush}));
+    }
+
+    pub fn is_dragging_frame(&self) -> bool {
+        return self.painting.is_some() || self.orbiting.is_some();
+    }
+
+    /* Mouse moved to (x, y) on the frame with the button held, which
+    continues the stroke from where it last was, or turns the camera by
+    an angle proportional to how far the pointer moved. */
+    pub fn drag_frame(&mut self, x: i32, y: i32) -> Option<Command> {
+        if let Some((x0, y0)) = self.orbiting {
+            self.orbiting = Some((x, y));
+            let camera: &mut Camera = self.settings.surface.as_mut()?;
+            camera.orbit(-0.01*((x - x0) as f32), 0.01*((y - y0) as f32));
+            return Some(Command::Apply(self.settings.clone()));
+        }
+        let from: (f32, f32) = self.painting?;
+        let to: (f32, f32) = grid_point(x, y);
+        self.painting = Some(to);
+        return Some(Command::Paint(Stroke {from, to, brush: self.brush}));
+    }
+
+    pub fn release(&mut self) {
+        self.dragging = None;
+        self.painting = None;
+        self.orbiting = None;
+    }
+}
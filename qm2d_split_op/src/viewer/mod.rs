@@ -0,0 +1,296 @@
+/* Live viewer of a running simulation. The simulation leaves frames
+for the viewer in a FrameSlot, where each replaces the last if the
+viewer has not taken it yet, so that a slow display never holds up the
+computation and always shows the latest frame. The viewer sends back
+commands, which the simulation applies between steps. Samples of
+observables for the charts of the viewer are passed on in full. */
+pub mod charts;
+pub mod controls;
+pub mod terminal;
+pub mod web;
+pub mod x11;
+
+use crate::viewer::controls::*;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::{mpsc, Arc};
+
+/* An 8 bit RGB image, with rows from the top of the image to the
+bottom, of the simulation after the given step. */
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+    pub step: usize,
+}
+
+/* Values of observables after the given step. */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Sample {
+    pub step: usize,
+    pub values: Vec<(&'static str, f64)>,
+}
+
+/* Holds the latest frame sent to the viewer, the back buffer of which
+the frame being drawn by the viewer is the front. Frames are moved in
+and out by swapping a pointer, so neither side ever waits for the other
+or copies a frame while holding a lock, and a frame the viewer has not
+taken is dropped when the next one arrives. The viewer waits for frames
+by parking its thread, which publish wakes. */
+pub struct FrameSlot {
+    frame: AtomicPtr<Frame>,
+    closed: AtomicBool,
+    viewer: std::sync::OnceLock<std::thread::Thread>,
+}
+
+impl Default for FrameSlot {
+    fn default() -> FrameSlot {
+        return FrameSlot {frame: AtomicPtr::new(std::ptr::null_mut()),
+                          closed: AtomicBool::new(false),
+                          viewer: std::sync::OnceLock::new()};
+    }
+}
+
+impl FrameSlot {
+    /* Leave a frame for the viewer, in place of any it has not taken. */
+    pub fn publish(&self, frame: Frame) {
+        let old: *mut Frame = self.frame.swap(
+            Box::into_raw(Box::new(frame)), Ordering::AcqRel);
+        if !old.is_null() {
+            // Safe, since the pointer came from Box::into_raw and the
+            // swap took it out of the slot.
+            drop(unsafe {Box::from_raw(old)});
+        }
+        if let Some(viewer) = self.viewer.get() {
+            viewer.unpark();
+        }
+    }
+
+    /* The latest frame, if there is one the viewer has not taken. */
+    pub fn take(&self) -> Option<Frame> {
+        let frame: *mut Frame = self.frame.swap(std::ptr::null_mut(),
+                                                Ordering::AcqRel);
+        if frame.is_null() {
+            return None;
+        }
+        // Safe as in publish.
+        return Some(*unsafe {Box::from_raw(frame)});
+    }
+
+    /* Mark the simulation as finished, so that the viewer stops once it
+    has taken the last frame. */
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        if let Some(viewer) = self.viewer.get() {
+            viewer.unpark();
+        }
+    }
+
+    /* Wait up to timeout for a frame, as Receiver::recv_timeout, failing
+    with Disconnected once the simulation has finished. Only the viewer
+    thread should wait. */
+    pub fn recv_timeout(&self, timeout: std::time::Duration
+                        ) -> Result<Frame, mpsc::RecvTimeoutError> {
+        self.viewer.get_or_init(std::thread::current);
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let closed: bool = self.closed.load(Ordering::Acquire);
+            if let Some(frame) = self.take() {
+                return Ok(frame);
+            }
+            if closed {
+                return Err(mpsc::RecvTimeoutError::Disconnected);
+            }
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                return Err(mpsc::RecvTimeoutError::Timeout);
+            }
+            std::thread::park_timeout(deadline - now);
+        }
+    }
+}
+
+impl Drop for FrameSlot {
+    fn drop(&mut self) {
+        self.take();
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    TogglePause,
+    ToggleMomentumSpace,
+    Apply(LiveSettings),
+    Paint(Stroke),
+    Reset,
+    Snapshot,
+    Quit,
+}
+
+// X11 keysyms, which are also the Unicode code points for ASCII keys.
+pub const KEY_SPACE: u32 = 0x20;
+pub const KEY_MINUS: u32 = 0x2d;
+pub const KEY_EQUAL: u32 = 0x3d;
+pub const KEY_LEFT_BRACKET: u32 = 0x5b;
+pub const KEY_RIGHT_BRACKET: u32 = 0x5d;
+pub const KEY_H: u32 = 0x68;
+pub const KEY_M: u32 = 0x6d;
+pub const KEY_Q: u32 = 0x71;
+pub const KEY_R: u32 = 0x72;
+pub const KEY_S: u32 = 0x73;
+pub const KEY_V: u32 = 0x76;
+pub const KEY_LEFT: u32 = 0xff51;
+pub const KEY_UP: u32 = 0xff52;
+pub const KEY_RIGHT: u32 = 0xff53;
+pub const KEY_DOWN: u32 = 0xff54;
+pub const KEY_ESCAPE: u32 = 0xff1b;
+
+/* The command bound to a key, if any: space pauses or resumes, m
+switches between position and momentum space, r resets psi to its
+initial state, s saves a snapshot of the state, and q or escape quits.
+The keys that change the live settings are handled by the control
+panel. */
+pub fn command_for_key(keysym: u32) -> Option<Command> {
+    return match keysym {
+        KEY_SPACE => Some(Command::TogglePause),
+        KEY_M => Some(Command::ToggleMomentumSpace),
+        KEY_R => Some(Command::Reset),
+        KEY_S => Some(Command::Snapshot),
+        KEY_Q | KEY_ESCAPE => Some(Command::Quit),
+        _ => None,
+    };
+}
+
+/* The end of the link held by the simulation. */
+pub struct SimulationLink {
+    frames: Arc<FrameSlot>,
+    samples: mpsc::Sender<Sample>,
+    commands: mpsc::Receiver<Command>,
+    pub paused: bool,
+    pub settings: LiveSettings,
+    pub strokes: Vec<Stroke>, // painted since they were last taken
+    // Asked for since they were last done, and cleared by the simulation.
+    pub reset: bool,
+    pub snapshot: bool,
+}
+
+/* The end of the link held by the viewer. */
+pub struct ViewerLink {
+    pub frames: Arc<FrameSlot>,
+    pub samples: mpsc::Receiver<Sample>,
+    pub commands: mpsc::Sender<Command>,
+}
+
+pub fn link(settings: LiveSettings) -> (SimulationLink, ViewerLink) {
+    let frames: Arc<FrameSlot> = Arc::new(FrameSlot::default());
+    let (sample_sender, sample_receiver) = mpsc::channel::<Sample>();
+    let (command_sender, command_receiver) = mpsc::channel::<Command>();
+    return (SimulationLink {frames: frames.clone(), samples: sample_sender,
+                            commands: command_receiver, paused: false,
+                            settings, strokes: Vec::new(), reset: false,
+                            snapshot: false},
+            ViewerLink {frames, samples: sample_receiver,
+                        commands: command_sender});
+}
+
+impl SimulationLink {
+    /* Offer a frame to the viewer, in place of any it has not yet
+    taken. */
+    pub fn send_frame(&self, frame: Frame) {
+        self.frames.publish(frame);
+    }
+
+    pub fn send_sample(&self, sample: Sample) {
+        let _ = self.samples.send(sample);
+    }
+
+    /* Apply the commands sent by the viewer since the last call, and
+    wait here for as long as the simulation is paused. Returns false if
+    the viewer asked to quit or was closed. */
+    pub fn poll(&mut self) -> bool {
+        loop {
+            let command: Command = if self.paused {
+                match self.commands.recv() {
+                    Ok(c) => c,
+                    Err(_) => return false,
+                }
+            } else {
+                match self.commands.try_recv() {
+                    Ok(c) => c,
+                    Err(mpsc::TryRecvError::Empty) => return true,
+                    Err(mpsc::TryRecvError::Disconnected) => return false,
+                }
+            };
+            match command {
+                Command::TogglePause => self.paused = !self.paused,
+                Command::ToggleMomentumSpace => self.settings.momentum_space
+                    = !self.settings.momentum_space,
+                Command::Apply(settings) => self.settings = settings,
+                Command::Paint(stroke) => self.strokes.push(stroke),
+                Command::Reset => self.reset = true,
+                Command::Snapshot => self.snapshot = true,
+                Command::Quit => return false,
+            }
+        }
+    }
+}
+
+impl Drop for SimulationLink {
+    fn drop(&mut self) {
+        self.frames.close();
+    }
+}
+
+/* Rate of events per second over the last second. */
+pub struct FrameRate {
+    times: std::collections::VecDeque<std::time::Instant>,
+}
+
+impl Default for FrameRate {
+    fn default() -> FrameRate {
+        return FrameRate {times: std::collections::VecDeque::new()};
+    }
+}
+
+impl FrameRate {
+    pub fn tick(&mut self) {
+        let now = std::time::Instant::now();
+        self.times.push_back(now);
+        while let Some(&t) = self.times.front() {
+            if now.duration_since(t).as_secs_f64() <= 1.0 {
+                break;
+            }
+            self.times.pop_front();
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        if self.times.len() < 2 {
+            return 0.0;
+        }
+        let span: f64 = self.times[self.times.len() - 1]
+            .duration_since(self.times[0]).as_secs_f64();
+        return if span > 0.0 {((self.times.len() - 1) as f64)/span} else {0.0};
+    }
+}
+
+/* The base64 encoding of data, with padding, as used for images sent
+to terminals and for the WebSocket handshake. */
+pub fn base64(data: &[u8]) -> String {
+    const DIGITS: &[u8; 64]
+        = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(4*data.len().div_ceil(3));
+    for chunk in data.chunks(3) {
+        let b: [u32; 3] = [chunk[0] as u32,
+                           *chunk.get(1).unwrap_or(&0) as u32,
+                           *chunk.get(2).unwrap_or(&0) as u32];
+        let n: u32 = (b[0] << 16) | (b[1] << 8) | b[2];
+        for k in 0..4 {
+            out.push(if k <= chunk.len() {
+                DIGITS[((n >> (18 - 6*k)) & 63) as usize] as char
+            } else {
+                '='
+            });
+        }
+    }
+    return out;
+}
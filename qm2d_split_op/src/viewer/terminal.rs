@@ -0,0 +1,309 @@
+use crate::viewer::*;
+use crate::viewer::controls::*;
+use crate::output::png::*;
+use std::io::{Read, Write};
+
+/* How frames are drawn in the terminal: as Unicode upper half blocks
+with 24 bit colors for the two pixels in each character cell, which
+almost every terminal can show, or as images with the graphics
+protocol of kitty or with sixels, which fewer terminals support but
+show the frames at a higher resolution.
+
+References:
+
+Wikipedia - ANSI escape code
+https://en.wikipedia.org/wiki/ANSI_escape_code
+
+Kitty - Terminal graphics protocol
+https://sw.kovidgoyal.net/kitty/graphics-protocol/
+
+VT330/VT340 Programmer Reference Manual - Sixel Graphics
+https://vt100.net/docs/vt3xx-gp/chapter14.html
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TerminalGraphics {
+    Blocks,
+    Kitty,
+    Sixel,
+}
+
+impl TerminalGraphics {
+    pub fn parse(name: &str) -> Result<TerminalGraphics, String> {
+        return match name {
+            "blocks" => Ok(TerminalGraphics::Blocks),
+            "kitty" => Ok(TerminalGraphics::Kitty),
+            "sixel" => Ok(TerminalGraphics::Sixel),
+            _ => Err(format!("unknown terminal graphics {}, expected \
+                              blocks, kitty or sixel", name)),
+        };
+    }
+}
+
+/* A viewer that draws the frames in the terminal it runs in, for
+watching a simulation on a remote machine over SSH without X
+forwarding. Frames are scaled down to width pixels across, which for
+blocks is also the number of columns taken. */
+pub struct TerminalViewer {
+    pub graphics: TerminalGraphics,
+    pub width: usize,
+    pub max_fps: f64,
+}
+
+/* Scale an 8 bit RGB image down to the given size by averaging the
+pixels that fall in each pixel of the result. */
+pub fn downsample(width: usize, height: usize, rgb: &[u8],
+                  new_width: usize, new_height: usize) -> Vec<u8> {
+    let mut out = std::vec::Vec::<u8>::with_capacity(3*new_width*new_height);
+    for y in 0..new_height {
+        let (y0, y1) = (y*height/new_height,
+                        usize::max(y*height/new_height + 1,
+                                   (y + 1)*height/new_height));
+        for x in 0..new_width {
+            let (x0, x1) = (x*width/new_width,
+                            usize::max(x*width/new_width + 1,
+                                       (x + 1)*width/new_width));
+            let mut sum: [usize; 3] = [0; 3];
+            for i in y0..y1 {
+                for j in x0..x1 {
+                    for c in 0..3 {
+                        sum[c] += rgb[3*(i*width + j) + c] as usize;
+                    }
+                }
+            }
+            let count: usize = (y1 - y0)*(x1 - x0);
+            out.extend(sum.map(|s| (s/count) as u8));
+        }
+    }
+    return out;
+}
+
+/* The image as rows of upper half blocks, with the top pixel of each
+cell as the foreground color and the bottom pixel as the background. */
+fn blocks(width: usize, height: usize, rgb: &[u8]) -> String {
+    let mut out = String::new();
+    for row in (0..height).step_by(2) {
+        for x in 0..width {
+            let top: &[u8] = &rgb[3*(row*width + x)..3*(row*width + x) + 3];
+            let bottom: &[u8] = if row + 1 < height {
+                &rgb[3*((row + 1)*width + x)..3*((row + 1)*width + x) + 3]
+            } else {
+                &[0, 0, 0]
+            };
+            out += &format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                            top[0], top[1], top[2],
+                            bottom[0], bottom[1], bottom[2]);
+        }
+        out += "\x1b[0m\r\n";
+    }
+    return out;
+}
+
+/* The image as a PNG sent with the kitty graphics protocol, in chunks
+of at most 4096 bytes of base64. Each frame replaces the image with the
+same id, and the terminal is asked not to reply, which would otherwise
+come in as keys. */
+fn kitty(width: usize, height: usize, rgb: &[u8]) -> String {
+    let data: String = base64(&encode_png_rgb(width, height, rgb));
+    let mut out = String::new();
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    for (k, chunk) in chunks.iter().enumerate() {
+        let more: u8 = if k + 1 < chunks.len() {1} else {0};
+        if k == 0 {
+            out += &format!("\x1b_Ga=T,f=100,i=1,q=2,m={};", more);
+        } else {
+            out += &format!("\x1b_Gm={};", more);
+        }
+        out += std::str::from_utf8(chunk).unwrap_or("");
+        out += "\x1b\\";
+    }
+    return out + "\r\n";
+}
+
+/* The image as sixels, with its colors rounded to a cube of six levels
+of each of red, green and blue. */
+fn sixel(width: usize, height: usize, rgb: &[u8]) -> String {
+    let level = |v: u8| -> usize {((v as usize)*5 + 127)/255};
+    let index: Vec<usize> = rgb.chunks(3)
+        .map(|c| 36*level(c[0]) + 6*level(c[1]) + level(c[2])).collect();
+    let mut out = String::from("\x1bPq");
+    for k in 0..216 {
+        out += &format!("#{};2;{};{};{}", k, (k/36)*20, ((k/6) % 6)*20,
+                        (k % 6)*20);
+    }
+    for band in (0..height).step_by(6) {
+        let rows: usize = usize::min(6, height - band);
+        let mut used: Vec<bool> = vec![false; 216];
+        for i in band..band + rows {
+            for x in 0..width {
+                used[index[i*width + x]] = true;
+            }
+        }
+        for color in (0..216).filter(|&c| used[c]) {
+            out += &format!("#{}", color);
+            // Run length encoded columns of the six pixels in this color.
+            let mut run: Option<(u8, usize)> = None;
+            let flush = |out: &mut String, run: Option<(u8, usize)>| {
+                if let Some((c, n)) = run {
+                    if n > 3 {
+                        out.push_str(&format!("!{}{}", n, c as char));
+                    } else {
+                        out.extend(std::iter::repeat_n(c as char, n));
+                    }
+                }
+            };
+            for x in 0..width {
+                let mut bits: u8 = 0;
+                for r in 0..rows {
+                    if index[(band + r)*width + x] == color {
+                        bits |= 1 << r;
+                    }
+                }
+                let c: u8 = 63 + bits;
+                run = match run {
+                    Some((d, n)) if d == c => Some((d, n + 1)),
+                    _ => {
+                        flush(&mut out, run);
+                        Some((c, 1))
+                    },
+                };
+            }
+            flush(&mut out, run);
+            out.push('$');
+        }
+        out.push('-');
+    }
+    return out + "\x1b\\\r\n";
+}
+
+/* Switch the terminal in and out of reading single keys without
+echoing them, with stty since this crate has no dependencies. */
+fn set_raw_input(raw: bool) {
+    let args: &[&str] = if raw {&["-icanon", "-echo", "min", "1"]}
+        else {&["icanon", "echo"]};
+    let _ = std::process::Command::new("stty").args(args)
+        .stdin(std::process::Stdio::inherit()).status();
+}
+
+impl TerminalViewer {
+    /* Show the frames sent by the simulation until it finishes or q is
+    pressed, at most max_fps times a second, each followed by a line
+    with the step, the rate at which steps are being computed and the
+    latest samples. Keys are handled as in the window, except that
+    escape does not quit since it starts the sequences of the arrow
+    keys. */
+    pub fn run(self, link: ViewerLink, settings: LiveSettings, title: &str
+               ) -> std::io::Result<()> {
+        let (key_sender, keys) = std::sync::mpsc::channel::<u32>();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin();
+            let mut next = || -> Option<u8> {
+                let mut byte: [u8; 1] = [0];
+                return stdin.read_exact(&mut byte).ok().map(|_| byte[0]);
+            };
+            while let Some(b) = next() {
+                // The arrow keys come as the escape sequences ESC [ A to
+                // ESC [ D, and other sequences are skipped.
+                let key: Option<u32> = if b == 0x1b {
+                    match (next(), next()) {
+                        (Some(b'['), Some(b'A')) => Some(KEY_UP),
+                        (Some(b'['), Some(b'B')) => Some(KEY_DOWN),
+                        (Some(b'['), Some(b'C')) => Some(KEY_RIGHT),
+                        (Some(b'['), Some(b'D')) => Some(KEY_LEFT),
+                        _ => None,
+                    }
+                } else {
+                    Some(b as u32)
+                };
+                if let Some(k) = key {
+                    if key_sender.send(k).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        set_raw_input(true);
+        let result: std::io::Result<()> = self.show(link, settings, title,
+                                                    keys);
+        set_raw_input(false);
+        // Leave the cursor below the last frame, with the colors reset.
+        print!("\x1b[0m\x1b[?25h");
+        let _ = std::io::stdout().flush();
+        return result;
+    }
+
+    fn show(&self, link: ViewerLink, settings: LiveSettings, title: &str,
+            keys: std::sync::mpsc::Receiver<u32>) -> std::io::Result<()> {
+        let mut panel = ControlPanel::new(settings);
+        let mut latest: Option<Sample> = None;
+        let mut step_times = std::collections::VecDeque::<(
+            std::time::Instant, usize)>::new();
+        let mut last_draw: Option<std::time::Instant> = None;
+        let mut stdout = std::io::stdout();
+        // Clear the screen and hide the cursor.
+        write!(stdout, "\x1b[2J\x1b[?25l")?;
+        loop {
+            while let Ok(k) = keys.try_recv() {
+                if let Some(c) = panel.key(k) {
+                    let quit: bool = c == Command::Quit;
+                    let _ = link.commands.send(c);
+                    if quit {
+                        return Ok(());
+                    }
+                }
+            }
+            while let Ok(sample) = link.samples.try_recv() {
+                latest = Some(sample);
+            }
+            let frame: Frame = match link.frames.recv_timeout(
+                std::time::Duration::from_millis(20)) {
+                Ok(frame) => frame,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected)
+                    => return Ok(()),
+            };
+            let now = std::time::Instant::now();
+            step_times.push_back((now, frame.step));
+            while step_times.len() > 2 && now.duration_since(
+                step_times[0].0).as_secs_f64() > 2.0 {
+                step_times.pop_front();
+            }
+            if let Some(t) = last_draw {
+                if now.duration_since(t).as_secs_f64() < 1.0/self.max_fps {
+                    continue;
+                }
+            }
+            last_draw = Some(now);
+            // Cells of blocks are about twice as high as they are wide,
+            // which the two pixels in each make up for.
+            let width: usize = usize::max(2, self.width);
+            let height: usize = width*frame.height/frame.width;
+            let small: Vec<u8> = downsample(frame.width, frame.height,
+                                            &frame.rgb, width, height);
+            let image: String = match self.graphics {
+                TerminalGraphics::Blocks => blocks(width, height, &small),
+                TerminalGraphics::Kitty => kitty(width, height, &small),
+                TerminalGraphics::Sixel => sixel(width, height, &small),
+            };
+            let ((t0, s0), (t1, s1)) = (step_times[0],
+                                        step_times[step_times.len() - 1]);
+            let seconds: f64 = t1.duration_since(t0).as_secs_f64();
+            let steps_per_second: f64 = if seconds > 0.0 {
+                ((s1 - s0) as f64)/seconds
+            } else {
+                0.0
+            };
+            let mut status: String = format!(
+                "{} - step {} - {:.1} steps/s{}", title, frame.step,
+                if panel.paused {0.0} else {steps_per_second},
+                if panel.paused {" (paused)"} else {""});
+            if let Some(sample) = &latest {
+                for (name, value) in sample.values.iter() {
+                    status += &format!(" - {} {:.4e}", name, value);
+                }
+            }
+            // Draw from the top left, over the previous frame.
+            write!(stdout, "\x1b[H{}\x1b[2K{}\r\n", image, status)?;
+            stdout.flush()?;
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::viewer::*;
+use crate::viewer::controls::*;
+use crate::viewer::terminal::*;
+use crate::output::png::*;
+use std::io::{BufRead, Read, Write};
+
+/* A viewer that serves the frames over HTTP, for watching a simulation
+on a headless machine from a browser. The page at / shows the frames
+and the latest samples, and passes the keys pressed in it back to the
+simulation, all through a WebSocket at /ws on which the frames are sent
+as PNG images in binary messages and the samples as JSON text messages.
+Frames are scaled down to width pixels across and sent at most max_fps
+times a second, and a browser that falls behind misses frames rather
+than holding up the others.
+
+References:
+
+RFC 6455 - The WebSocket Protocol
+https://www.rfc-editor.org/rfc/rfc6455
+
+RFC 3174 - US Secure Hash Algorithm 1 (SHA1)
+https://www.rfc-editor.org/rfc/rfc3174
+*/
+pub struct WebViewer {
+    pub address: String, // host:port to listen on
+    pub width: usize,
+    pub max_fps: f64,
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>qm2d_split_op</title>
+<style>
+body {background: #101014; color: #ddd; font-family: monospace;}
+img {image-rendering: pixelated; width: min(90vh, 90vw);}
+</style>
+</head>
+<body>
+<img id="frame" alt="waiting for frames">
+<p id="status">connecting</p>
+<p>space pause, m momentum space, v surface, arrows force, [ ] dt,
+r reset, s snapshot, q quit</p>
+<script>
+const socket = new WebSocket("ws://" + location.host + "/ws");
+socket.binaryType = "blob";
+const frame = document.getElementById("frame");
+const status = document.getElementById("status");
+socket.onmessage = (e) => {
+  if (typeof e.data === "string") {
+    const sample = JSON.parse(e.data);
+    status.textContent = Object.entries(sample)
+      .map(([k, v]) => k + " " + (k === "step" ? v : v.toExponential(4)))
+      .join("  ");
+  } else {
+    const old = frame.src;
+    frame.src = URL.createObjectURL(e.data);
+    if (old) URL.revokeObjectURL(old);
+  }
+};
+socket.onclose = () => status.textContent = "disconnected";
+document.onkeydown = (e) => {
+  if (socket.readyState === WebSocket.OPEN) {
+    socket.send(e.key);
+    e.preventDefault();
+  }
+};
+</script>
+</body>
+</html>
+"#;
+
+/* SHA-1 hash of data, which the WebSocket handshake is made with. */
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476,
+                           0xc3d2e1f0];
+    let mut message: Vec<u8> = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(((data.len() as u64)*8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w: [u32; 80] = [0; 80];
+        for t in 0..16 {
+            w[t] = u32::from_be_bytes([block[4*t], block[4*t + 1],
+                                       block[4*t + 2], block[4*t + 3]]);
+        }
+        for t in 16..80 {
+            w[t] = (w[t - 3] ^ w[t - 8] ^ w[t - 14] ^ w[t - 16]).rotate_left(1);
+        }
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3],
+                                                   h[4]);
+        for (t, wt) in w.iter().enumerate() {
+            let (f, k): (u32, u32) = match t {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp: u32 = a.rotate_left(5).wrapping_add(f).wrapping_add(e)
+                .wrapping_add(k).wrapping_add(*wt);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+    let mut out: [u8; 20] = [0; 20];
+    for k in 0..5 {
+        out[4*k..4*k + 4].copy_from_slice(&h[k].to_be_bytes());
+    }
+    return out;
+}
+
+/* The header of a WebSocket message from the server, which is not
+masked, of the given opcode and length. */
+fn message_header(opcode: u8, length: usize) -> Vec<u8> {
+    let mut header: Vec<u8> = vec![0x80 | opcode];
+    if length < 126 {
+        header.push(length as u8);
+    } else if length < 65536 {
+        header.push(126);
+        header.extend((length as u16).to_be_bytes());
+    } else {
+        header.push(127);
+        header.extend((length as u64).to_be_bytes());
+    }
+    return header;
+}
+
+/* Read a message from a client, whose payload is always masked,
+returning its opcode and unmasked payload. */
+fn read_message(stream: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head: [u8; 2] = [0; 2];
+    stream.read_exact(&mut head)?;
+    let mut length: u64 = (head[1] & 0x7f) as u64;
+    if length == 126 {
+        let mut b: [u8; 2] = [0; 2];
+        stream.read_exact(&mut b)?;
+        length = u16::from_be_bytes(b) as u64;
+    } else if length == 127 {
+        let mut b: [u8; 8] = [0; 8];
+        stream.read_exact(&mut b)?;
+        length = u64::from_be_bytes(b);
+    }
+    if length > 1 << 20 {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                       "WebSocket message too long"));
+    }
+    let mut mask: [u8; 4] = [0; 4];
+    if head[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload: Vec<u8> = vec![0; length as usize];
+    stream.read_exact(&mut payload)?;
+    for (k, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[k % 4];
+    }
+    return Ok((head[0] & 0x0f, payload));
+}
+
+/* The keysym of a key as named by KeyboardEvent.key in the browser. */
+fn keysym(name: &str) -> Option<u32> {
+    return match name {
+        "ArrowLeft" => Some(KEY_LEFT),
+        "ArrowUp" => Some(KEY_UP),
+        "ArrowRight" => Some(KEY_RIGHT),
+        "ArrowDown" => Some(KEY_DOWN),
+        "Escape" => Some(KEY_ESCAPE),
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Some(c as u32),
+                _ => None,
+            }
+        },
+    };
+}
+
+/* Answer one HTTP request on a new connection: the page for /, or the
+WebSocket handshake for /ws, after which the client is added to the
+ones frames are sent to and the keys it sends are passed on. */
+fn serve(stream: std::net::TcpStream,
+         clients: &std::sync::Mutex<Vec<std::sync::mpsc::SyncSender<
+             std::sync::Arc<Vec<u8>>>>>,
+         keys: std::sync::mpsc::Sender<u32>) -> std::io::Result<()> {
+    let mut reader = std::io::BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut websocket_key: Option<String> = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let path: &str = request_line.split_whitespace().nth(1).unwrap_or("");
+    let mut writer = stream;
+    match (path, websocket_key) {
+        ("/ws", Some(key)) => {
+            let accept: String = base64(&sha1(
+                (key + "258EAFA5-E914-47DA-95CA-C5AB0DC85B11").as_bytes()));
+            write!(writer, "HTTP/1.1 101 Switching Protocols\r\n\
+                            Upgrade: websocket\r\nConnection: Upgrade\r\n\
+                            Sec-WebSocket-Accept: {}\r\n\r\n", accept)?;
+            // The messages are written by a thread of their own, and the
+            // two messages queued for it are the most it falls behind by.
+            let (sender, messages) = std::sync::mpsc::sync_channel::<
+                std::sync::Arc<Vec<u8>>>(2);
+            if let Ok(mut c) = clients.lock() {
+                c.push(sender);
+            }
+            let mut out = writer.try_clone()?;
+            std::thread::spawn(move || {
+                for m in messages.iter() {
+                    if out.write_all(&m).is_err() {
+                        break;
+                    }
+                }
+            });
+            loop {
+                let (opcode, payload) = read_message(&mut reader)?;
+                match opcode {
+                    1 => if let Some(k) = keysym(
+                        &String::from_utf8_lossy(&payload)) {
+                        let _ = keys.send(k);
+                    },
+                    8 => return Ok(()),
+                    _ => (),
+                }
+            }
+        },
+        ("/", _) | ("/index.html", _) => {
+            write!(writer, "HTTP/1.1 200 OK\r\n\
+                            Content-Type: text/html; charset=utf-8\r\n\
+                            Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                   PAGE.len(), PAGE)?;
+        },
+        _ => {
+            write!(writer, "HTTP/1.1 404 Not Found\r\n\
+                            Content-Length: 0\r\nConnection: close\r\n\r\n")?;
+        },
+    }
+    return Ok(());
+}
+
+impl WebViewer {
+    /* Serve the frames and samples sent by the simulation until it
+    finishes or q is pressed in a browser. */
+    pub fn run(self, link: ViewerLink, settings: LiveSettings
+               ) -> std::io::Result<()> {
+        let listener = std::net::TcpListener::bind(&self.address)?;
+        println!("Serving the viewer at http://{}/", self.address);
+        let clients = std::sync::Arc::new(std::sync::Mutex::new(Vec::<
+            std::sync::mpsc::SyncSender<std::sync::Arc<Vec<u8>>>>::new()));
+        let (key_sender, keys) = std::sync::mpsc::channel::<u32>();
+        let listening_clients = clients.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let clients = listening_clients.clone();
+                let keys = key_sender.clone();
+                std::thread::spawn(move || {
+                    let _ = serve(stream, &clients, keys);
+                });
+            }
+        });
+        // Send a message to every client that keeps up, forgetting the
+        // ones that have gone.
+        let broadcast = |message: Vec<u8>| {
+            let message = std::sync::Arc::new(message);
+            if let Ok(mut c) = clients.lock() {
+                c.retain(|client| !matches!(
+                    client.try_send(message.clone()),
+                    Err(std::sync::mpsc::TrySendError::Disconnected(_))));
+            }
+        };
+
+        let mut panel = ControlPanel::new(settings);
+        let mut last_sent: Option<std::time::Instant> = None;
+        loop {
+            while let Ok(k) = keys.try_recv() {
+                if let Some(c) = panel.key(k) {
+                    let quit: bool = c == Command::Quit;
+                    let _ = link.commands.send(c);
+                    if quit {
+                        return Ok(());
+                    }
+                }
+            }
+            while let Ok(sample) = link.samples.try_recv() {
+                let fields: Vec<String> = std::iter::once(
+                    format!("\"step\": {}", sample.step))
+                    .chain(sample.values.iter().map(|(name, value)| {
+                        format!("\"{}\": {}", name,
+                                if value.is_finite() {*value} else {0.0})
+                    })).collect();
+                let text: String = format!("{{{}}}", fields.join(", "));
+                let mut message: Vec<u8> = message_header(1, text.len());
+                message.extend(text.as_bytes());
+                broadcast(message);
+            }
+            let frame: Frame = match link.frames.recv_timeout(
+                std::time::Duration::from_millis(20)) {
+                Ok(frame) => frame,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected)
+                    => return Ok(()),
+            };
+            let now = std::time::Instant::now();
+            if let Some(t) = last_sent {
+                if now.duration_since(t).as_secs_f64() < 1.0/self.max_fps {
+                    continue;
+                }
+            }
+            last_sent = Some(now);
+            let width: usize = usize::clamp(self.width, 1, frame.width);
+            let height: usize = usize::max(1, width*frame.height/frame.width);
+            let png: Vec<u8> = encode_png_rgb(width, height, &downsample(
+                frame.width, frame.height, &frame.rgb, width, height));
+            let mut message: Vec<u8> = message_header(2, png.len());
+            message.extend(png);
+            broadcast(message);
+        }
+    }
+}
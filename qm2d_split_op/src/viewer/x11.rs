@@ -0,0 +1,603 @@
+use crate::viewer::*;
+use crate::viewer::charts::*;
+use crate::viewer::controls::*;
+use std::io::{Read, Write};
+
+/* A window on an X server for the live viewer, written against the X11
+protocol directly since this crate has no dependencies. It opens the
+display named by $DISPLAY, either over the local socket or over TCP as
+for X forwarding through SSH, and needs a TrueColor visual with 32 bits
+per pixel and 8 bits each of red, green and blue, which all current
+servers provide.
+
+References:
+
+X Window System Protocol, X Version 11, Release 6.7
+https://www.x.org/releases/X11R7.7/doc/xproto/x11protocol.html
+
+Xau - X Authority Database
+https://www.x.org/releases/X11R7.7/doc/libXau/Xau.html
+*/
+pub struct X11Window {
+    connection: Connection,
+    window: u32,
+    gc: u32,
+    width: usize,
+    height: usize,
+    msb_first: bool, // byte order of pixels in images
+    max_request_bytes: usize,
+    keysyms: Vec<u32>, // first keysym of each keycode from min_keycode
+    min_keycode: u8,
+    wm_delete_window: u32,
+}
+
+enum Connection {
+    Unix(std::os::unix::net::UnixStream),
+    Tcp(std::net::TcpStream),
+}
+
+impl Connection {
+    fn try_clone(&self) -> std::io::Result<Connection> {
+        return match self {
+            Connection::Unix(s) => Ok(Connection::Unix(s.try_clone()?)),
+            Connection::Tcp(s) => Ok(Connection::Tcp(s.try_clone()?)),
+        };
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        return match self {
+            Connection::Unix(s) => s.read(buf),
+            Connection::Tcp(s) => s.read(buf),
+        };
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        return match self {
+            Connection::Unix(s) => s.write(buf),
+            Connection::Tcp(s) => s.write(buf),
+        };
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return match self {
+            Connection::Unix(s) => s.flush(),
+            Connection::Tcp(s) => s.flush(),
+        };
+    }
+}
+
+/* Events from the window, passed from the thread reading them. */
+enum WindowEvent {
+    Key(u32),
+    Press(i32, i32), // position of the pointer in the window
+    Release,
+    Motion(i32, i32),
+    Expose,
+    Close,
+}
+
+fn x11_error(message: String) -> std::io::Error {
+    return std::io::Error::other(message);
+}
+
+fn pad4(n: usize) -> usize {
+    return (4 - n % 4) % 4;
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    return u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    return u32::from_le_bytes([bytes[offset], bytes[offset + 1],
+                               bytes[offset + 2], bytes[offset + 3]]);
+}
+
+/* Build a request from its opcode, the byte following it and its body,
+padding it to a multiple of four bytes and filling in its length. */
+fn request(opcode: u8, data: u8, body: &[u8]) -> Vec<u8> {
+    let mut r: Vec<u8> = vec![opcode, data, 0, 0];
+    r.extend(body);
+    r.extend(std::iter::repeat_n(0, pad4(r.len())));
+    let length: u16 = (r.len()/4) as u16;
+    r[2..4].copy_from_slice(&length.to_le_bytes());
+    return r;
+}
+
+/* The host and display number of a display name [host]:display[.screen]. */
+fn parse_display(name: &str) -> Result<(String, u32), String> {
+    let colon: usize = name.rfind(':')
+        .ok_or(format!("invalid display name '{}'", name))?;
+    let number: &str = name[colon + 1..].split('.').next().unwrap_or("");
+    let display: u32 = number.parse::<u32>()
+        .map_err(|_| format!("invalid display name '{}'", name))?;
+    return Ok((name[..colon].to_string(), display));
+}
+
+/* The authorization protocol name and data for the display from the
+file named by $XAUTHORITY, or ~/.Xauthority. Entries are matched by
+display number only, and only MIT-MAGIC-COOKIE-1 is supported. */
+fn read_authority(display: u32) -> Option<(Vec<u8>, Vec<u8>)> {
+    let filename: String = match std::env::var("XAUTHORITY") {
+        Ok(f) => f,
+        Err(_) => std::env::var("HOME").ok()? + "/.Xauthority",
+    };
+    let bytes: Vec<u8> = std::fs::read(filename).ok()?;
+    let mut offset: usize = 0;
+    let field = |offset: &mut usize| -> Option<Vec<u8>> {
+        let length: usize = u16::from_be_bytes(
+            [*bytes.get(*offset)?, *bytes.get(*offset + 1)?]) as usize;
+        let value: Vec<u8> = bytes.get(*offset + 2..*offset + 2 + length)?
+            .to_vec();
+        *offset += 2 + length;
+        return Some(value);
+    };
+    while offset + 2 <= bytes.len() {
+        offset += 2; // address family
+        let _address: Vec<u8> = field(&mut offset)?;
+        let number: Vec<u8> = field(&mut offset)?;
+        let name: Vec<u8> = field(&mut offset)?;
+        let data: Vec<u8> = field(&mut offset)?;
+        if (number.is_empty() || number == display.to_string().as_bytes())
+            && name == b"MIT-MAGIC-COOKIE-1" {
+            return Some((name, data));
+        }
+    }
+    return None;
+}
+
+/* Read replies until the next one, skipping any events before it. */
+fn read_reply(connection: &mut Connection) -> std::io::Result<Vec<u8>> {
+    loop {
+        let mut reply: Vec<u8> = vec![0; 32];
+        connection.read_exact(&mut reply)?;
+        match reply[0] {
+            0 => return Err(x11_error(format!(
+                "X error {} for request {}", reply[1], reply[10]))),
+            1 => {
+                let extra: usize = 4*(read_u32(&reply, 4) as usize);
+                let mut rest: Vec<u8> = vec![0; extra];
+                connection.read_exact(&mut rest)?;
+                reply.extend(rest);
+                return Ok(reply);
+            },
+            _ => continue,
+        }
+    }
+}
+
+fn intern_atom(connection: &mut Connection, name: &str
+               ) -> std::io::Result<u32> {
+    let mut body = std::vec::Vec::<u8>::new();
+    body.extend((name.len() as u16).to_le_bytes());
+    body.extend([0, 0]);
+    body.extend(name.as_bytes());
+    connection.write_all(&request(16, 0, &body))?;
+    return Ok(read_u32(&read_reply(connection)?, 8));
+}
+
+impl X11Window {
+    pub fn open(width: usize, height: usize, title: &str
+                ) -> std::io::Result<X11Window> {
+        let display_name: String = std::env::var("DISPLAY").map_err(
+            |_| x11_error("DISPLAY is not set".to_string()))?;
+        let (host, display) = parse_display(&display_name)
+            .map_err(x11_error)?;
+        let mut connection: Connection = if host.is_empty() || host == "unix" {
+            Connection::Unix(std::os::unix::net::UnixStream::connect(
+                format!("/tmp/.X11-unix/X{}", display))?)
+        } else {
+            Connection::Tcp(std::net::TcpStream::connect(
+                (host.as_str(), (6000 + display) as u16))?)
+        };
+
+        // Connection setup, with requests and replies in little endian
+        // byte order.
+        let (auth_name, auth_data) = read_authority(display)
+            .unwrap_or_default();
+        let mut setup: Vec<u8> = vec![b'l', 0, 11, 0, 0, 0];
+        setup.extend((auth_name.len() as u16).to_le_bytes());
+        setup.extend((auth_data.len() as u16).to_le_bytes());
+        setup.extend([0, 0]);
+        for field in [&auth_name, &auth_data] {
+            setup.extend(field.iter());
+            setup.extend(std::iter::repeat_n(0, pad4(field.len())));
+        }
+        connection.write_all(&setup)?;
+        let mut head: [u8; 8] = [0; 8];
+        connection.read_exact(&mut head)?;
+        let mut info: Vec<u8> = vec![0; 4*(read_u16(&head, 6) as usize)];
+        connection.read_exact(&mut info)?;
+        if head[0] != 1 {
+            let length: usize = usize::min(head[1] as usize, info.len());
+            return Err(x11_error(format!(
+                "X server refused the connection: {}",
+                String::from_utf8_lossy(&info[..length]).trim())));
+        }
+        let id_base: u32 = read_u32(&info, 4);
+        let vendor_length: usize = read_u16(&info, 16) as usize;
+        let max_request_bytes: usize = 4*(read_u16(&info, 18) as usize);
+        let format_count: usize = info[21] as usize;
+        let msb_first: bool = info[22] == 1;
+        let min_keycode: u8 = info[26];
+        let max_keycode: u8 = info[27];
+        let formats: usize = 32 + vendor_length + pad4(vendor_length);
+        let screen: usize = formats + 8*format_count;
+        let root: u32 = read_u32(&info, screen);
+        let black_pixel: u32 = read_u32(&info, screen + 12);
+        let root_depth: u8 = info[screen + 38];
+        let bits_per_pixel: u8 = (0..format_count)
+            .map(|k| &info[formats + 8*k..formats + 8*k + 2])
+            .find(|f| f[0] == root_depth).map(|f| f[1]).unwrap_or(0);
+        if root_depth < 24 || bits_per_pixel != 32 {
+            return Err(x11_error(format!(
+                "unsupported visual of depth {} and {} bits per pixel",
+                root_depth, bits_per_pixel)));
+        }
+
+        let wm_protocols: u32 = intern_atom(&mut connection,
+                                            "WM_PROTOCOLS")?;
+        let wm_delete_window: u32 = intern_atom(&mut connection,
+                                                "WM_DELETE_WINDOW")?;
+        let keycode_count: u8 = max_keycode - min_keycode + 1;
+        connection.write_all(&request(101, 0, &[min_keycode, keycode_count,
+                                                0, 0]))?;
+        let mapping: Vec<u8> = read_reply(&mut connection)?;
+        let per_keycode: usize = usize::max(1, mapping[1] as usize);
+        let keysyms: Vec<u32> = (0..keycode_count as usize)
+            .map(|k| read_u32(&mapping, 32 + 4*per_keycode*k)).collect();
+
+        let window: u32 = id_base | 1;
+        let gc: u32 = id_base | 2;
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(window.to_le_bytes());
+        body.extend(root.to_le_bytes());
+        body.extend([0, 0, 0, 0]); // x, y
+        body.extend((width as u16).to_le_bytes());
+        body.extend((height as u16).to_le_bytes());
+        body.extend([0, 0, 1, 0]); // border width, InputOutput class
+        body.extend(0u32.to_le_bytes()); // visual of the parent
+        // Background pixel and event mask, for KeyPress, ButtonPress,
+        // ButtonRelease, ButtonMotion, Exposure and StructureNotify events.
+        body.extend(0x802u32.to_le_bytes());
+        body.extend(black_pixel.to_le_bytes());
+        body.extend(0x2a00du32.to_le_bytes());
+        connection.write_all(&request(1, 0, &body))?;
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(window.to_le_bytes());
+        body.extend(wm_protocols.to_le_bytes());
+        body.extend(4u32.to_le_bytes()); // ATOM
+        body.extend([32, 0, 0, 0]);
+        body.extend(1u32.to_le_bytes());
+        body.extend(wm_delete_window.to_le_bytes());
+        connection.write_all(&request(18, 0, &body))?;
+        // The graphics context draws text in the font every server has
+        // under the name fixed.
+        let font: u32 = id_base | 3;
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(font.to_le_bytes());
+        body.extend(5u16.to_le_bytes());
+        body.extend([0, 0]);
+        body.extend(b"fixed");
+        connection.write_all(&request(45, 0, &body))?;
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(gc.to_le_bytes());
+        body.extend(window.to_le_bytes());
+        body.extend(0x4000u32.to_le_bytes());
+        body.extend(font.to_le_bytes());
+        connection.write_all(&request(55, 0, &body))?;
+        connection.write_all(&request(8, 0, &window.to_le_bytes()))?;
+        let mut w = X11Window {connection, window, gc, width, height,
+                               msb_first, max_request_bytes, keysyms,
+                               min_keycode, wm_delete_window};
+        w.set_title(title)?;
+        return Ok(w);
+    }
+
+    pub fn set_title(&mut self, title: &str) -> std::io::Result<()> {
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(self.window.to_le_bytes());
+        body.extend(39u32.to_le_bytes()); // WM_NAME
+        body.extend(31u32.to_le_bytes()); // STRING
+        body.extend([8, 0, 0, 0]);
+        body.extend((title.len() as u32).to_le_bytes());
+        body.extend(title.as_bytes());
+        self.connection.write_all(&request(18, 0, &body))?;
+        return self.connection.flush();
+    }
+
+    /* Draw an 8 bit RGB image, with rows from the top down, with its
+    top left at (x, y) in the window, in strips small enough for the
+    largest request the server accepts. */
+    pub fn draw(&mut self, x: usize, y: usize, width: usize, height: usize,
+                rgb: &[u8]
+                ) -> std::io::Result<()> {
+        let rows_per_request: usize = usize::max(
+            1, (self.max_request_bytes - 24)/(4*width));
+        let mut row: usize = 0;
+        while row < height {
+            let rows: usize = usize::min(rows_per_request, height - row);
+            let mut body = std::vec::Vec::<u8>::with_capacity(
+                20 + 4*width*rows);
+            body.extend(self.window.to_le_bytes());
+            body.extend(self.gc.to_le_bytes());
+            body.extend((width as u16).to_le_bytes());
+            body.extend((rows as u16).to_le_bytes());
+            body.extend((x as u16).to_le_bytes());
+            body.extend(((y + row) as u16).to_le_bytes());
+            body.extend([0, 24, 0, 0]); // left pad, depth
+            for k in row*width..(row + rows)*width {
+                let (r, g, b) = (rgb[3*k], rgb[3*k + 1], rgb[3*k + 2]);
+                if self.msb_first {
+                    body.extend([0, r, g, b]);
+                } else {
+                    body.extend([b, g, r, 0]);
+                }
+            }
+            self.connection.write_all(&request(72, 2, &body))?;
+            row += rows;
+        }
+        return self.connection.flush();
+    }
+
+    fn set_colors(&mut self, foreground: [u8; 3], background: [u8; 3]
+                  ) -> std::io::Result<()> {
+        let pixel = |c: [u8; 3]| -> u32 {
+            ((c[0] as u32) << 16) | ((c[1] as u32) << 8) | c[2] as u32
+        };
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(self.gc.to_le_bytes());
+        body.extend(0xcu32.to_le_bytes());
+        body.extend(pixel(foreground).to_le_bytes());
+        body.extend(pixel(background).to_le_bytes());
+        return self.connection.write_all(&request(56, 0, &body));
+    }
+
+    pub fn fill_rectangle(&mut self, x: i32, y: i32, width: i32, height: i32,
+                          color: [u8; 3]) -> std::io::Result<()> {
+        self.set_colors(color, color)?;
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(self.window.to_le_bytes());
+        body.extend(self.gc.to_le_bytes());
+        body.extend((x as i16).to_le_bytes());
+        body.extend((y as i16).to_le_bytes());
+        body.extend((i32::max(0, width) as u16).to_le_bytes());
+        body.extend((i32::max(0, height) as u16).to_le_bytes());
+        return self.connection.write_all(&request(70, 0, &body));
+    }
+
+    /* Draw a line of text with its baseline at y. */
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str,
+                     color: [u8; 3], background: [u8; 3]
+                     ) -> std::io::Result<()> {
+        let bytes: Vec<u8> = text.bytes().take(255).collect();
+        self.set_colors(color, background)?;
+        let mut body = std::vec::Vec::<u8>::new();
+        body.extend(self.window.to_le_bytes());
+        body.extend(self.gc.to_le_bytes());
+        body.extend((x as i16).to_le_bytes());
+        body.extend((y as i16).to_le_bytes());
+        body.extend(&bytes);
+        return self.connection.write_all(&request(76, bytes.len() as u8,
+                                                  &body));
+    }
+
+    /* Draw the charts in the panel below the controls. */
+    fn draw_charts(&mut self, charts: &Charts) -> std::io::Result<()> {
+        let left: usize = self.width - PANEL_WIDTH;
+        let top: usize = CONTROLS_HEIGHT as usize;
+        if top >= self.height {
+            return Ok(());
+        }
+        let height: usize = self.height - top;
+        self.draw(left, top, PANEL_WIDTH, height,
+                  &charts.render(PANEL_WIDTH, height))?;
+        let labels: Vec<String> = charts.labels();
+        let strip: usize = height/usize::max(1, labels.len());
+        for (k, label) in labels.iter().enumerate() {
+            self.draw_text(left as i32 + 6, (top + k*strip) as i32 + 14,
+                           label, [200, 200, 200], [20, 20, 26])?;
+        }
+        return self.connection.flush();
+    }
+
+    /* Draw the lines of the help over the top left of the frame. */
+    fn draw_help(&mut self) -> std::io::Result<()> {
+        const BACKGROUND: [u8; 3] = [0, 0, 0];
+        self.fill_rectangle(8, 8, 300, 16*(HELP.len() as i32) + 12,
+                            BACKGROUND)?;
+        for (k, line) in HELP.iter().enumerate() {
+            self.draw_text(16, 26 + 16*(k as i32), line, [230, 230, 230],
+                           BACKGROUND)?;
+        }
+        return self.connection.flush();
+    }
+
+    /* Draw the control panel along the right edge of the window. */
+    fn draw_panel(&mut self, panel: &ControlPanel) -> std::io::Result<()> {
+        const BACKGROUND: [u8; 3] = [32, 32, 40];
+        let left: i32 = (self.width - PANEL_WIDTH) as i32;
+        self.fill_rectangle(left, 0, PANEL_WIDTH as i32, CONTROLS_HEIGHT,
+                            BACKGROUND)?;
+        for row in panel.rows() {
+            self.draw_text(left + BAR_LEFT, row.top + 13, &row.label,
+                           [230, 230, 230], BACKGROUND)?;
+            if let Some(f) = row.fraction {
+                let y: i32 = row.top + BAR_OFFSET;
+                self.fill_rectangle(left + BAR_LEFT, y, BAR_WIDTH,
+                                    BAR_HEIGHT, [70, 70, 80])?;
+                self.fill_rectangle(left + BAR_LEFT, y,
+                                    (f32::clamp(f, 0.0, 1.0)
+                                     *(BAR_WIDTH as f32)) as i32,
+                                    BAR_HEIGHT, [60, 130, 230])?;
+            }
+        }
+        return self.connection.flush();
+    }
+
+    /* Show the frames sent by the simulation until it finishes or the
+    window is closed, with a control panel for the given settings to the
+    right of them and charts of the samples sent below it, passing on the
+    commands bound to the keys pressed and the changes made in the
+    panel. The title shows the step, the rate at
+    which steps are being computed and the rate at which frames are
+    drawn. */
+    pub fn run(mut self, link: ViewerLink, settings: LiveSettings,
+               title: &str) -> std::io::Result<()> {
+        let (event_sender, events) = std::sync::mpsc::channel::<WindowEvent>();
+        let mut reader: Connection = self.connection.try_clone()?;
+        let keysyms: Vec<u32> = self.keysyms.clone();
+        let min_keycode: u8 = self.min_keycode;
+        let wm_delete_window: u32 = self.wm_delete_window;
+        std::thread::spawn(move || {
+            let mut event: [u8; 32] = [0; 32];
+            while reader.read_exact(&mut event).is_ok() {
+                let position: (i32, i32) = (read_u16(&event, 24) as i16 as i32,
+                                            read_u16(&event, 26) as i16 as i32);
+                let e: Option<WindowEvent> = match event[0] & 0x7f {
+                    1 => {
+                        let mut rest: Vec<u8>
+                            = vec![0; 4*(read_u32(&event, 4) as usize)];
+                        if reader.read_exact(&mut rest).is_err() {
+                            break;
+                        }
+                        None
+                    },
+                    2 => keysyms.get(event[1].wrapping_sub(min_keycode)
+                                     as usize)
+                        .map(|&k| WindowEvent::Key(k)),
+                    4 if event[1] == 1
+                        => Some(WindowEvent::Press(position.0, position.1)),
+                    5 if event[1] == 1 => Some(WindowEvent::Release),
+                    6 => Some(WindowEvent::Motion(position.0, position.1)),
+                    12 => Some(WindowEvent::Expose),
+                    33 if read_u32(&event, 12) == wm_delete_window
+                        => Some(WindowEvent::Close),
+                    _ => None,
+                };
+                if let Some(e) = e {
+                    if event_sender.send(e).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let panel_left: i32 = (self.width - PANEL_WIDTH) as i32;
+        let mut panel = ControlPanel::new(settings);
+        let mut charts = Charts::new(PANEL_WIDTH);
+        let mut new_samples: bool = false;
+        let mut last_charts = std::time::Instant::now();
+        let mut last: Option<Frame> = None;
+        let mut draw_rate = FrameRate::default();
+        let mut step_times = std::collections::VecDeque::<(
+            std::time::Instant, usize)>::new();
+        let mut last_title = std::time::Instant::now();
+        self.draw_panel(&panel)?;
+        self.draw_charts(&charts)?;
+        loop {
+            let mut redraw: bool = false;
+            let mut redraw_panel: bool = false;
+            while let Ok(e) = events.try_recv() {
+                // Strokes on the frame leave the panel as it is.
+                redraw_panel |= match e {
+                    WindowEvent::Key(_) => true,
+                    WindowEvent::Press(x, _) => x >= panel_left,
+                    WindowEvent::Motion(_, _) => !panel.is_dragging_frame(),
+                    _ => false,
+                };
+                let command: Option<Command> = match e {
+                    WindowEvent::Key(k) => {
+                        let show_help: bool = panel.show_help;
+                        let c: Option<Command> = panel.key(k);
+                        redraw |= panel.show_help != show_help;
+                        c
+                    },
+                    WindowEvent::Press(x, y) if x >= panel_left
+                        => panel.press(x - panel_left, y),
+                    WindowEvent::Press(x, y) => panel.press_frame(x, y),
+                    WindowEvent::Motion(x, y) if panel.is_dragging_frame()
+                        => panel.drag_frame(x, y),
+                    WindowEvent::Motion(x, y)
+                        => panel.drag(x - panel_left, y),
+                    WindowEvent::Release => {
+                        panel.release();
+                        None
+                    },
+                    WindowEvent::Expose => {
+                        redraw = true;
+                        redraw_panel = true;
+                        None
+                    },
+                    WindowEvent::Close => Some(Command::Quit),
+                };
+                if let Some(c) = command {
+                    let quit: bool = c == Command::Quit;
+                    let _ = link.commands.send(c);
+                    if quit {
+                        return Ok(());
+                    }
+                }
+            }
+            while let Ok(sample) = link.samples.try_recv() {
+                charts.push(sample);
+                new_samples = true;
+            }
+            if redraw_panel {
+                self.draw_panel(&panel)?;
+            }
+            // The charts are drawn at most ten times a second.
+            if redraw_panel || (new_samples && last_charts.elapsed()
+                                .as_secs_f64() > 0.1) {
+                self.draw_charts(&charts)?;
+                new_samples = false;
+                last_charts = std::time::Instant::now();
+            }
+            match link.frames.recv_timeout(
+                std::time::Duration::from_millis(20)) {
+                Ok(frame) => {
+                    let now = std::time::Instant::now();
+                    step_times.push_back((now, frame.step));
+                    while step_times.len() > 2 && now.duration_since(
+                        step_times[0].0).as_secs_f64() > 2.0 {
+                        step_times.pop_front();
+                    }
+                    last = Some(frame);
+                    redraw = true;
+                },
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => (),
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected)
+                    => return Ok(()),
+            }
+            if let (true, Some(f)) = (redraw, &last) {
+                self.draw(0, 0, f.width, f.height, &f.rgb)?;
+                draw_rate.tick();
+            }
+            if redraw && panel.show_help {
+                self.draw_help()?;
+            }
+            if last_title.elapsed().as_secs_f64() > 0.5 {
+                last_title = std::time::Instant::now();
+                let (t0, s0) = step_times.front().copied()
+                    .unwrap_or((last_title, 0));
+                let (t1, s1) = step_times.back().copied()
+                    .unwrap_or((last_title, 0));
+                let span: f64 = t1.duration_since(t0).as_secs_f64();
+                let steps_per_second: f64 = if span > 0.0 {
+                    ((s1 - s0) as f64)/span
+                } else {
+                    0.0
+                };
+                self.set_title(&format!(
+                    "{} - step {} - {:.1} steps/s - {:.1} fps{}", title, s1,
+                    if panel.paused {0.0} else {steps_per_second},
+                    draw_rate.rate(),
+                    if panel.paused {" (paused)"} else {""}))?;
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::constants::*;
+use crate::complex::*;
+
+/* A phase singularity found in the wave function. */
+#[derive(Clone, Copy, Debug)]
+pub struct DetectedVortex {
+    pub x: f32, pub y: f32, // centre of the plaquette, in [0, 1]
+    pub charge: i32, // winding of the phase around it, in units of 2 pi
+}
+
+/* Locate the vortices of psi by the winding of its phase around each
+plaquette of four neighbouring grid points: the phase differences
+between neighbours, each taken in (-pi, pi], sum to 2 pi times the
+charge enclosed. Plaquettes where the density at any corner is below
+min_density times the peak density are skipped, since the phase of the
+numerical noise there winds at random. The boundaries are periodic.
+
+Reference:
+
+Wikipedia - Quantum vortex
+https://en.wikipedia.org/wiki/Quantum_vortex
+
+M. Tsubota, K. Kasamatsu, M. Ueda.
+Vortex lattice formation in a rotating Bose-Einstein condensate.
+Physical Review A 65, 023603 (2002).
+*/
+pub fn find_vortices(psi: &[Complex<f32>], min_density: f32
+                     ) -> Vec<DetectedVortex> {
+    let peak: f32 = psi.iter().map(|p| p.length_squared())
+        .fold(0.0, f32::max);
+    let floor: f32 = min_density*peak;
+    let mut vortices = std::vec::Vec::<DetectedVortex>::new();
+    let phase_step = |a: Complex<f32>, b: Complex<f32>| -> f64 {
+        return (a.conj()*b).arg();
+    };
+    for i in 0..N {
+        let i1: usize = (i + 1) % N;
+        for j in 0..N {
+            let j1: usize = (j + 1) % N;
+            let corners: [Complex<f32>; 4] = [psi[i*N + j], psi[i*N + j1],
+                                              psi[i1*N + j1], psi[i1*N + j]];
+            if corners.iter().any(|c| c.length_squared() <= floor) {
+                continue;
+            }
+            let mut winding: f64 = 0.0;
+            for k in 0..4 {
+                winding += phase_step(corners[k], corners[(k + 1) % 4]);
+            }
+            let charge: i32 = f64::round(
+                winding/(2.0*std::f64::consts::PI)) as i32;
+            if charge != 0 {
+                vortices.push(DetectedVortex {
+                    x: ((j as f32) + 0.5)/(N as f32),
+                    y: ((i as f32) + 0.5)/(N as f32),
+                    charge,
+                });
+            }
+        }
+    }
+    return vortices;
+}
@@ -0,0 +1,157 @@
+use crate::constants::*;
+use crate::complex::*;
+use crate::split_op::*;
+use crate::potentials::*;
+use crate::expression::*;
+use crate::output::colormap::*;
+use crate::output::png::*;
+
+/* The simulation as a WebAssembly module for browsers, stepped and
+drawn from JavaScript through the plain C functions below, since this
+crate has no dependencies to generate bindings with. Build it with
+
+    cargo build --release --lib --target wasm32-unknown-unknown \
+        --no-default-features
+
+which leaves out the threads the browser does not have, and load the
+module with web/index.html. Frames are RGBA images of N by N pixels,
+with rows from the top down, that the page puts into a canvas with
+putImageData. Functions that read or write files compile but return
+errors in the browser. */
+struct Simulation {
+    psi: Vec<Complex<f32>>,
+    potential: Vec<Complex<f32>>,
+    expression: Option<Expression>,
+    p_squared: Vec<f32>,
+    propagators: Propagators,
+    potential_changed: bool,
+    rgba: Vec<u8>,
+    dt: Complex<f32>,
+    t: f32,
+    style: FrameStyle,
+    // Memory handed out by qm2d_alloc for passing strings in.
+    input: Vec<u8>,
+}
+
+thread_local! {
+    static SIMULATION: std::cell::RefCell<Simulation>
+        = std::cell::RefCell::new(Simulation::new());
+}
+
+impl Simulation {
+    fn new() -> Simulation {
+        let mut p_squared: Vec<f32> = vec![0.0; N*N];
+        init_momentum_squared(&mut p_squared);
+        return Simulation {
+            psi: vec![Complex {real: 0.0, imag: 0.0}; N*N],
+            potential: vec![Complex {real: 0.0, imag: 0.0}; N*N],
+            expression: None,
+            p_squared,
+            propagators: Propagators::default(),
+            potential_changed: true,
+            rgba: vec![255; 4*N*N],
+            dt: Complex {real: RE_DT, imag: IM_DT},
+            t: 0.0,
+            style: FrameStyle::Density(Colormap::Inferno),
+            input: Vec::new(),
+        };
+    }
+}
+
+/* The side length of the grid, which is also the width and height of
+the frames. */
+#[no_mangle]
+pub extern "C" fn qm2d_size() -> usize {
+    return N;
+}
+
+/* Memory in the module for len bytes, for JavaScript to write the
+expression for qm2d_set_potential into. It stays valid until the next
+call. */
+#[no_mangle]
+pub extern "C" fn qm2d_alloc(len: usize) -> *mut u8 {
+    return SIMULATION.with(|s| {
+        let mut s = s.borrow_mut();
+        s.input = vec![0; len];
+        s.input.as_mut_ptr()
+    });
+}
+
+/* Start again from a Gaussian wave packet centred at (x0, y0), with
+standard deviation sigma and wavenumbers nx and ny, all in the units of
+the initial wave packet in the configuration (x and y in [0, 1]). */
+#[no_mangle]
+pub extern "C" fn qm2d_init(x0: f32, y0: f32, sigma: f32, nx: f32, ny: f32) {
+    SIMULATION.with(|s| {
+        let mut s = s.borrow_mut();
+        for i in 0..N {
+            for j in 0..N {
+                let x: f32 = (j as f32)/(N as f32);
+                let y: f32 = (i as f32)/(N as f32);
+                let r2: f32 = (x - x0)*(x - x0) + (y - y0)*(y - y0);
+                let a: f32 = f32::exp(-0.5*r2/(sigma*sigma));
+                let phase: f32 = 2.0*std::f32::consts::PI*(nx*x + ny*y);
+                s.psi[i*N + j] = Complex {real: a*f32::cos(phase),
+                                          imag: a*f32::sin(phase)};
+            }
+        }
+        s.t = 0.0;
+    });
+}
+
+/* Set the potential to the expression of x, y and t that JavaScript
+wrote into the memory from qm2d_alloc, as for the potential key of the
+configuration. Returns 0, or 1 if it is not valid UTF-8 or does not
+parse, leaving the potential as it was. */
+#[no_mangle]
+pub extern "C" fn qm2d_set_potential() -> i32 {
+    return SIMULATION.with(|s| {
+        let s: &mut Simulation = &mut s.borrow_mut();
+        let expression: Expression = match std::str::from_utf8(&s.input)
+            .map_err(|e| e.to_string()).and_then(Expression::parse) {
+            Ok(e) => e,
+            Err(_) => return 1,
+        };
+        expression.fill_at(&mut s.potential, s.t);
+        s.expression = Some(expression);
+        s.potential_changed = true;
+        0
+    });
+}
+
+/* Advance the simulation by the given number of steps. */
+#[no_mangle]
+pub extern "C" fn qm2d_step(steps: usize) {
+    SIMULATION.with(|s| {
+        let s: &mut Simulation = &mut s.borrow_mut();
+        for _ in 0..steps {
+            if let Some(e) = &s.expression {
+                if e.is_time_dependent() {
+                    e.fill_at(&mut s.potential, s.t);
+                    s.potential_changed = true;
+                }
+            }
+            s.propagators.update(&s.potential, &s.p_squared, s.dt,
+                                 std::mem::take(&mut s.potential_changed));
+            s.propagators.propagate_spatial(&mut s.psi);
+            s.propagators.propagate_kinetic(&mut s.psi, false);
+            dampen(&mut s.psi, s.dt.real);
+            s.propagators.propagate_spatial(&mut s.psi);
+            s.t += s.dt.real;
+        }
+    });
+}
+
+/* Draw the density into the RGBA frame and return where it is in the
+module's memory, N*N*4 bytes long. */
+#[no_mangle]
+pub extern "C" fn qm2d_render() -> *const u8 {
+    return SIMULATION.with(|s| {
+        let s: &mut Simulation = &mut s.borrow_mut();
+        let rgb: Vec<u8> = s.style.render(&s.psi, 0.0);
+        for (k, c) in rgb.chunks(3).enumerate() {
+            s.rgba[4*k..4*k + 3].copy_from_slice(c);
+        }
+        s.rgba.as_ptr()
+    });
+}
@@ -0,0 +1,103 @@
+use crate::complex::*;
+use crate::log::*;
+use crate::norm::*;
+
+/* What to do when the wave function has diverged. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchdogAction {
+    Abort, // stop the run with psi as it is
+    Rollback, // stop the run with psi as it was at the last check passed
+}
+
+impl WatchdogAction {
+    pub fn parse(s: &str) -> Result<WatchdogAction, String> {
+        return match s {
+            "abort" => Ok(WatchdogAction::Abort),
+            "rollback" => Ok(WatchdogAction::Rollback),
+            _ => Err(format!(
+                "unknown watchdog action '{}', expected abort or rollback", s)),
+        };
+    }
+}
+
+/* Checks every few steps that psi has not diverged, so that a run
+that has blown up stops with an error rather than going on for hours
+writing frames of NaN. psi has diverged if any value is not finite, or
+if its norm has grown past max_growth times the norm at the start,
+which a real time step with an absorbing or real potential never does.
+
+There is no adaptive stepping to retry with a smaller time step, so on
+divergence the run stops either way; rolling back restores the last
+psi that passed, kept as a copy at each check, so that the state saved
+at the end of the run is one it can be resumed from. */
+pub struct Watchdog {
+    pub every: usize, // steps between checks
+    pub max_growth: f64, // 0 to not check the norm, as in imaginary time
+    pub action: WatchdogAction,
+    initial: f64,
+    good: Option<(usize, Vec<Complex<f32>>)>, // step and psi
+}
+
+impl Watchdog {
+    pub fn new(psi: &[Complex<f32>], every: usize, max_growth: f64,
+               action: WatchdogAction) -> Watchdog {
+        let good: Option<(usize, Vec<Complex<f32>>)>
+            = if action == WatchdogAction::Rollback {
+                Some((0, psi.to_vec()))
+            } else {
+                None
+            };
+        return Watchdog {every, max_growth, action,
+                         initial: norm_squared(psi), good};
+    }
+
+    /* Whether psi is due to be checked after the given step. */
+    pub fn is_due(&self, step: usize) -> bool {
+        return self.every > 0 && step.is_multiple_of(self.every);
+    }
+
+    /* The step psi was last found to be fine at, if it is kept. */
+    pub fn last_good_step(&self) -> Option<usize> {
+        return self.good.as_ref().map(|(step, _)| *step);
+    }
+
+    /* Check psi after the given step, returning an error saying what
+    went wrong if it has diverged, after rolling psi back if that is the
+    action. */
+    pub fn check(&mut self, psi: &mut [Complex<f32>], step: usize
+                 ) -> Result<(), String> {
+        let norm: f64 = norm_squared(psi);
+        let problem: Option<String> = if !norm.is_finite() {
+            let index: usize = psi.iter().position(
+                |z| !z.real.is_finite() || !z.imag.is_finite())
+                .unwrap_or(0);
+            Some(format!("psi is not finite at step {}, first at index {}",
+                         step, index))
+        } else if self.max_growth > 0.0 && norm > self.max_growth*self.initial {
+            Some(format!("the norm grew to {:.3} times its initial value by \
+                          step {}", norm/self.initial, step))
+        } else {
+            None
+        };
+        let message: String = match problem {
+            None => {
+                if let Some((good_step, good)) = self.good.as_mut() {
+                    *good_step = step;
+                    good.copy_from_slice(psi);
+                }
+                return Ok(());
+            },
+            Some(message) => message,
+        };
+        log_event(LogLevel::Error, "watchdog", "diverged", &[
+            ("step", step.into()), ("norm", norm.into()),
+            ("action", format!("{:?}", self.action).into())]);
+        return Err(match self.good.as_ref() {
+            Some((good_step, good)) => {
+                psi.copy_from_slice(good);
+                format!("{}; rolled back to step {}", message, good_step)
+            },
+            None => message,
+        });
+    }
+}
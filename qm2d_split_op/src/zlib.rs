@@ -0,0 +1,415 @@
+/* Compression and decompression of zlib streams, as used by the PNG
+file format.
+
+References:
+
+RFC 1950 - ZLIB Compressed Data Format Specification
+https://www.rfc-editor.org/rfc/rfc1950
+
+RFC 1951 - DEFLATE Compressed Data Format Specification
+https://www.rfc-editor.org/rfc/rfc1951
+*/
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize, // in bits
+}
+
+impl <'a> BitReader<'a> {
+    fn bit(&mut self) -> Result<u32, String> {
+        let byte: usize = self.position >> 3;
+        if byte >= self.data.len() {
+            return Err("unexpected end of compressed data".to_string());
+        }
+        let b: u32 = ((self.data[byte] >> (self.position & 7)) & 1) as u32;
+        self.position += 1;
+        return Ok(b);
+    }
+
+    /* Read n bits, least significant bit first. */
+    fn bits(&mut self, n: u32) -> Result<u32, String> {
+        let mut val: u32 = 0;
+        for i in 0..n {
+            val |= self.bit()? << i;
+        }
+        return Ok(val);
+    }
+
+    fn align_to_byte(&mut self) {
+        self.position = (self.position + 7) & !7;
+    }
+}
+
+/* Canonical Huffman code, stored as the number of codes of each
+length and the symbols sorted by code. */
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts: [u16; 16] = [0; 16];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets: [u16; 16] = [0; 16];
+        for i in 1..16 {
+            offsets[i] = offsets[i-1] + counts[i-1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbols[offsets[l as usize] as usize] = symbol as u16;
+                offsets[l as usize] += 1;
+            }
+        }
+        return Huffman {counts, symbols};
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= reader.bit()? as i32;
+            let count: i32 = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        return Err("invalid Huffman code".to_string());
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>,
+                 literals: &Huffman, distances: &Huffman
+                 ) -> Result<(), String> {
+    loop {
+        let symbol: usize = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let s: usize = symbol - 257;
+            if s >= 29 {
+                return Err("invalid length code".to_string());
+            }
+            let length: usize = LENGTH_BASE[s] as usize
+                + reader.bits(LENGTH_EXTRA[s] as u32)? as usize;
+            let d: usize = distances.decode(reader)? as usize;
+            if d >= 30 {
+                return Err("invalid distance code".to_string());
+            }
+            let distance: usize = DIST_BASE[d] as usize
+                + reader.bits(DIST_EXTRA[d] as u32)? as usize;
+            if distance > out.len() {
+                return Err("distance too far back".to_string());
+            }
+            let start: usize = out.len() - distance;
+            for k in 0..length {
+                let b: u8 = out[start + k];
+                out.push(b);
+            }
+        }
+    }
+}
+
+/* Order in which the code length code lengths are stored. */
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn read_dynamic_tables(reader: &mut BitReader
+                       ) -> Result<(Huffman, Huffman), String> {
+    let hlit: usize = reader.bits(5)? as usize + 257;
+    let hdist: usize = reader.bits(5)? as usize + 1;
+    let hclen: usize = reader.bits(4)? as usize + 4;
+    let mut code_lengths: [u8; 19] = [0; 19];
+    for i in 0..hclen {
+        code_lengths[CODE_LENGTH_ORDER[i]] = reader.bits(3)? as u8;
+    }
+    let code_length_huffman = Huffman::new(&code_lengths);
+    let mut lengths = std::vec::Vec::<u8>::with_capacity(hlit + hdist);
+    while lengths.len() < hlit + hdist {
+        let symbol: u16 = code_length_huffman.decode(reader)?;
+        let (repeat, val): (u32, u8) = match symbol {
+            0..=15 => (1, symbol as u8),
+            16 => match lengths.last() {
+                Some(&l) => (3 + reader.bits(2)?, l),
+                None => return Err("repeat with no previous length"
+                                   .to_string()),
+            },
+            17 => (3 + reader.bits(3)?, 0),
+            _ => (11 + reader.bits(7)?, 0),
+        };
+        for _ in 0..repeat {
+            lengths.push(val);
+        }
+    }
+    if lengths.len() > hlit + hdist {
+        return Err("too many code lengths".to_string());
+    }
+    return Ok((Huffman::new(&lengths[..hlit]),
+               Huffman::new(&lengths[hlit..])));
+}
+
+/* Decompress raw DEFLATE data. */
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = std::vec::Vec::<u8>::new();
+    let mut reader = BitReader {data, position: 0};
+    loop {
+        let is_final: u32 = reader.bit()?;
+        let block_type: u32 = reader.bits(2)?;
+        if block_type == 0 {
+            reader.align_to_byte();
+            let len: u32 = reader.bits(16)?;
+            let nlen: u32 = reader.bits(16)?;
+            if len != (!nlen & 0xffff) {
+                return Err("corrupt stored block length".to_string());
+            }
+            for _ in 0..len {
+                out.push(reader.bits(8)? as u8);
+            }
+        } else if block_type == 1 {
+            let mut lengths: [u8; 288] = [0; 288];
+            for (i, length) in lengths.iter_mut().enumerate() {
+                *length = if i < 144 {8} else if i < 256 {9}
+                    else if i < 280 {7} else {8};
+            }
+            let literals = Huffman::new(&lengths);
+            let distances = Huffman::new(&[5; 30]);
+            inflate_block(&mut reader, &mut out, &literals, &distances)?;
+        } else if block_type == 2 {
+            let (literals, distances) = read_dynamic_tables(&mut reader)?;
+            inflate_block(&mut reader, &mut out, &literals, &distances)?;
+        } else {
+            return Err("invalid block type".to_string());
+        }
+        if is_final == 1 {
+            return Ok(out);
+        }
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for &d in data {
+        a = (a + d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
+
+/* Decompress a zlib stream, checking its header and checksum. */
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6 {
+        return Err("zlib stream too short".to_string());
+    }
+    let cmf: u8 = data[0];
+    let flg: u8 = data[1];
+    if cmf & 0x0f != 8 || !(((cmf as u16) << 8) | flg as u16).is_multiple_of(31) {
+        return Err("invalid zlib header".to_string());
+    }
+    if flg & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".to_string());
+    }
+    let out: Vec<u8> = inflate(&data[2..])?;
+    let n: usize = data.len();
+    let expected: u32 = u32::from_be_bytes([data[n-4], data[n-3],
+                                            data[n-2], data[n-1]]);
+    if adler32(&out) != expected {
+        return Err("zlib checksum mismatch".to_string());
+    }
+    return Ok(out);
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32, // number of bits in buffer
+}
+
+impl BitWriter {
+    /* Write the n lowest bits of val, least significant bit first. */
+    fn bits(&mut self, val: u32, n: u32) {
+        self.buffer |= (val as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /* Write a Huffman code, which is stored most significant bit first. */
+    fn code(&mut self, code: u32, length: u32) {
+        let mut reversed: u32 = 0;
+        for i in 0..length {
+            reversed |= ((code >> i) & 1) << (length - 1 - i);
+        }
+        self.bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        return self.out;
+    }
+}
+
+/* Write a literal or length symbol with the fixed Huffman code. */
+fn write_fixed_literal(writer: &mut BitWriter, symbol: u32) {
+    if symbol < 144 {
+        writer.code(0x30 + symbol, 8);
+    } else if symbol < 256 {
+        writer.code(0x190 + symbol - 144, 9);
+    } else if symbol < 280 {
+        writer.code(symbol - 256, 7);
+    } else {
+        writer.code(0xc0 + symbol - 280, 8);
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let mut s: usize = 28;
+    while LENGTH_BASE[s] as usize > length {
+        s -= 1;
+    }
+    write_fixed_literal(writer, 257 + s as u32);
+    writer.bits((length - LENGTH_BASE[s] as usize) as u32,
+                LENGTH_EXTRA[s] as u32);
+    let mut d: usize = 29;
+    while DIST_BASE[d] as usize > distance {
+        d -= 1;
+    }
+    writer.code(d as u32, 5);
+    writer.bits((distance - DIST_BASE[d] as usize) as u32,
+                DIST_EXTRA[d] as u32);
+}
+
+/* Compress data as a single DEFLATE block with the fixed Huffman code,
+finding repeated strings with a hash of the next three bytes and a
+short chain of earlier positions with the same hash. This is much
+simpler than the dynamic codes zlib itself would choose, but the long
+runs of identical pixels in simulation frames compress well anyway. */
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    return deflate_level(data, 5);
+}
+
+/* Compress data as deflate does, with a level from 0 to 9 as for zlib:
+level 0 stores the data uncompressed, and each level above it follows
+chains of earlier positions twice as long, from 1 for level 1 to 256
+for level 9, which finds longer matches but takes more time. */
+pub fn deflate_level(data: &[u8], level: u32) -> Vec<u8> {
+    const WINDOW: usize = 32768;
+    const HASH_SIZE: usize = 1 << 15;
+    let mut writer = BitWriter {out: std::vec::Vec::new(), buffer: 0,
+                                count: 0};
+    if level == 0 {
+        // Stored blocks of at most 65535 bytes, or one empty block.
+        let blocks: Vec<&[u8]> = if data.is_empty() {vec![data]}
+            else {data.chunks(65535).collect()};
+        let mut out: Vec<u8> = Vec::new();
+        for (k, block) in blocks.iter().enumerate() {
+            out.push(if k + 1 == blocks.len() {1} else {0});
+            out.extend((block.len() as u16).to_le_bytes());
+            out.extend((!(block.len() as u16)).to_le_bytes());
+            out.extend(block.iter());
+        }
+        return out;
+    }
+    let max_chain: usize = 1 << (u32::min(level, 9) - 1);
+    writer.bits(1, 1); // final block
+    writer.bits(1, 2); // fixed Huffman code
+    let hash = |k: usize| -> usize {
+        let h: u32 = ((data[k] as u32) << 16) | ((data[k + 1] as u32) << 8)
+            | data[k + 2] as u32;
+        return (h.wrapping_mul(2654435761) >> 17) as usize & (HASH_SIZE - 1);
+    };
+    let mut head: Vec<usize> = vec![usize::MAX; HASH_SIZE];
+    let mut previous: Vec<usize> = vec![usize::MAX; data.len()];
+    let insert = |k: usize, head: &mut Vec<usize>, previous: &mut Vec<usize>| {
+        if k + 2 < data.len() {
+            let h: usize = hash(k);
+            previous[k] = head[h];
+            head[h] = k;
+        }
+    };
+    let mut k: usize = 0;
+    while k < data.len() {
+        let mut best_length: usize = 0;
+        let mut best_distance: usize = 0;
+        if k + 2 < data.len() {
+            let mut candidate: usize = head[hash(k)];
+            let mut chain: usize = 0;
+            let max_length: usize = usize::min(258, data.len() - k);
+            while candidate != usize::MAX && k - candidate <= WINDOW
+                && chain < max_chain {
+                let mut length: usize = 0;
+                while length < max_length
+                    && data[candidate + length] == data[k + length] {
+                    length += 1;
+                }
+                if length > best_length {
+                    best_length = length;
+                    best_distance = k - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+        if best_length >= 3 {
+            write_match(&mut writer, best_length, best_distance);
+            for m in k..k + best_length {
+                insert(m, &mut head, &mut previous);
+            }
+            k += best_length;
+        } else {
+            write_fixed_literal(&mut writer, data[k] as u32);
+            insert(k, &mut head, &mut previous);
+            k += 1;
+        }
+    }
+    write_fixed_literal(&mut writer, 256);
+    return writer.finish();
+}
+
+/* Compress data into a zlib stream. */
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    return zlib_compress_level(data, 5);
+}
+
+/* Compress data into a zlib stream with deflate_level. */
+pub fn zlib_compress_level(data: &[u8], level: u32) -> Vec<u8> {
+    // Deflate with a 32K window, and a check value making the header
+    // a multiple of 31.
+    let mut out: Vec<u8> = vec![0x78, 0x01];
+    out.extend(deflate_level(data, level));
+    out.extend(adler32(data).to_be_bytes());
+    return out;
+}
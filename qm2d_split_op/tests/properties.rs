@@ -0,0 +1,27 @@
+use qm2d_split_op::properties::*;
+
+/* cargo test [filter] checks the properties of the transforms whose
+names contain filter, or all of them, on random cases, and fails if any
+case breaks one. The seed and number of cases are those of the
+environment variables PROPERTY_SEED and PROPERTY_CASES, if set, so that
+a failure reported with its seed can be run again, or a change checked
+on more cases. The arguments cargo adds, which start with --, are
+skipped. */
+fn main() {
+    let filter: Option<String> = std::env::args().skip(1)
+        .find(|arg| !arg.starts_with("--"));
+    let seed: u64 = std::env::var("PROPERTY_SEED").ok()
+        .and_then(|s| match s.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => s.parse::<u64>().ok(),
+        })
+        .unwrap_or(DEFAULT_PROPERTY_SEED);
+    let cases: usize = std::env::var("PROPERTY_CASES").ok()
+        .and_then(|s| s.parse::<usize>().ok()).unwrap_or(32);
+    let results: Vec<PropertyResult> = run_properties(filter.as_deref(), seed,
+                                                      cases);
+    print!("{}", properties_report(&results, seed));
+    if !results.iter().all(PropertyResult::passed) {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,15 @@
+use qm2d_split_op::validation::*;
+
+/* cargo test [filter] runs the scenarios with closed form answers whose
+names contain filter, or all of them, and fails if any error is above
+its tolerance. The arguments cargo adds, which start with --, are
+skipped. */
+fn main() {
+    let filter: Option<String> = std::env::args().skip(1)
+        .find(|arg| !arg.starts_with("--"));
+    let validations: Vec<Validation> = run_validation(filter.as_deref());
+    print!("{}", validation_report(&validations));
+    if !validations.iter().all(Validation::passed) {
+        std::process::exit(1);
+    }
+}